@@ -0,0 +1,349 @@
+use std::fs;
+
+use serde::Deserialize;
+use toml_edit::{value, ArrayOfTables, DocumentMut, Item, Table};
+
+use crate::error::{Result, Error};
+
+/// Transport a bookmark connects with
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// WebSocket (`ws://` or `wss://`)
+    #[default]
+    Websocket,
+    /// WebTransport, not spoken by this client
+    Webtransport,
+}
+
+impl Transport {
+    /// Name used in the configuration file
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Transport::Websocket => "websocket",
+            Transport::Webtransport => "webtransport",
+        }
+    }
+}
+
+/// A named server connection saved in the configuration (`[[bookmarks]]`)
+#[derive(Deserialize, Debug, Clone)]
+pub struct Bookmark {
+    /// Name given to `--bookmark`
+    pub name: String,
+
+    /// WebSocket server URL
+    pub url: String,
+
+    /// Environment variable holding the token sent to the server
+    /// (the token itself is never written to the configuration)
+    #[serde(default)]
+    pub token_env: Option<String>,
+
+    /// Shell type of the session, started through the server's REST API
+    #[serde(default)]
+    pub shell: Option<String>,
+
+    /// Transport to connect with
+    #[serde(default)]
+    pub transport: Transport,
+}
+
+/// Where to connect, resolved from the command line, a bookmark and the configuration
+#[derive(Debug, Clone)]
+pub struct Target {
+    /// WebSocket server URL
+    pub url: String,
+    /// Token sent as `Authorization: Bearer`
+    pub token: Option<String>,
+    /// Shell type of a session started through the REST API (`None` lets `/ws` start one)
+    pub shell: Option<String>,
+}
+
+/// Connection settings given on the command line, each one overriding the bookmark's
+#[derive(Debug, Default)]
+pub struct Overrides {
+    /// `--url`
+    pub url: Option<String>,
+    /// `--token-env`
+    pub token_env: Option<String>,
+    /// `--shell`
+    pub shell: Option<String>,
+}
+
+/// Find a bookmark by name, the error lists the available ones
+pub fn find<'a>(bookmarks: &'a [Bookmark], name: &str) -> Result<&'a Bookmark> {
+    if let Some(bookmark) = bookmarks.iter().find(|bookmark| bookmark.name == name) {
+        return Ok(bookmark);
+    }
+    if bookmarks.is_empty() {
+        return Err(Error::Custom(format!("Unknown bookmark `{}`, no bookmarks are configured", name)));
+    }
+    let names: Vec<&str> = bookmarks.iter().map(|bookmark| bookmark.name.as_str()).collect();
+    Err(Error::Custom(format!("Unknown bookmark `{}`, available: {}", name, names.join(", "))))
+}
+
+/// Resolve the connection target; command line settings win over the bookmark, the bookmark
+/// over the configured server URL
+pub fn resolve(bookmark: Option<&Bookmark>, overrides: Overrides, default_url: &str) -> Result<Target> {
+    if let Some(bookmark) = bookmark
+        && bookmark.transport != Transport::Websocket
+    {
+        return Err(Error::Custom(format!(
+            "Bookmark `{}` uses transport {}, this client only speaks websocket",
+            bookmark.name,
+            bookmark.transport.as_str()
+        )));
+    }
+
+    let url = overrides.url
+        .or_else(|| bookmark.map(|bookmark| bookmark.url.clone()))
+        .unwrap_or_else(|| default_url.to_string());
+    let token_env = overrides.token_env
+        .or_else(|| bookmark.and_then(|bookmark| bookmark.token_env.clone()));
+    let shell = overrides.shell
+        .or_else(|| bookmark.and_then(|bookmark| bookmark.shell.clone()));
+
+    let token = match token_env {
+        Some(name) => Some(std::env::var(&name).map_err(|_| {
+            Error::Custom(format!("Token variable {} is not set", name))
+        })?),
+        None => None,
+    };
+
+    Ok(Target { url, token, shell })
+}
+
+/// One line per bookmark, for `bookmarks list`
+pub fn describe(bookmarks: &[Bookmark]) -> String {
+    if bookmarks.is_empty() {
+        return "No bookmarks configured".to_string();
+    }
+    let mut lines = Vec::with_capacity(bookmarks.len());
+    for bookmark in bookmarks {
+        let mut line = format!("{}  {}", bookmark.name, bookmark.url);
+        if let Some(shell) = &bookmark.shell {
+            line.push_str(&format!("  shell={}", shell));
+        }
+        if let Some(token_env) = &bookmark.token_env {
+            line.push_str(&format!("  token=${}", token_env));
+        }
+        if bookmark.transport != Transport::Websocket {
+            line.push_str(&format!("  transport={}", bookmark.transport.as_str()));
+        }
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+/// Append a bookmark to the configuration file at `path`, creating the file if needed
+/// The file is edited in place, so its comments and formatting are kept
+pub fn add(path: &str, bookmark: &Bookmark) -> Result<()> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(Error::Io(e)),
+    };
+    let mut document: DocumentMut = content.parse()?;
+
+    let bookmarks = document
+        .entry("bookmarks")
+        .or_insert(Item::ArrayOfTables(ArrayOfTables::new()))
+        .as_array_of_tables_mut()
+        .ok_or_else(|| Error::Custom(format!("`bookmarks` in {} is not a [[bookmarks]] array", path)))?;
+    if bookmarks
+        .iter()
+        .any(|table| table.get("name").and_then(Item::as_str) == Some(bookmark.name.as_str()))
+    {
+        return Err(Error::Custom(format!("Bookmark `{}` already exists in {}", bookmark.name, path)));
+    }
+
+    let mut table = Table::new();
+    table["name"] = value(&bookmark.name);
+    table["url"] = value(&bookmark.url);
+    if let Some(token_env) = &bookmark.token_env {
+        table["token_env"] = value(token_env);
+    }
+    if let Some(shell) = &bookmark.shell {
+        table["shell"] = value(shell);
+    }
+    if bookmark.transport != Transport::Websocket {
+        table["transport"] = value(bookmark.transport.as_str());
+    }
+    bookmarks.push(table);
+
+    fs::write(path, document.to_string())?;
+    tracing::info!("Added bookmark {} to {}", bookmark.name, path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::path::PathBuf;
+
+    /// A configuration file in a fresh temporary directory, with `content` unless `None`
+    fn config_file(name: &str, content: Option<&str>) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bookmarks-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("client.toml");
+        if let Some(content) = content {
+            fs::write(&path, content).unwrap();
+        }
+        path
+    }
+
+    fn bookmark(name: &str, url: &str) -> Bookmark {
+        Bookmark {
+            name: name.to_string(),
+            url: url.to_string(),
+            token_env: None,
+            shell: None,
+            transport: Transport::Websocket,
+        }
+    }
+
+    fn reload(path: &std::path::Path) -> Vec<Bookmark> {
+        Config::from_file(path.to_str().unwrap()).unwrap().bookmarks
+    }
+
+    #[test]
+    fn added_bookmarks_persist_across_reloads() {
+        let path = config_file(
+            "persist",
+            Some("# Staging and production\n[server]\nurl = \"ws://localhost:8080/ws\"\n"),
+        );
+        let prod = Bookmark {
+            token_env: Some("PROD_TOKEN".to_string()),
+            shell: Some("bash".to_string()),
+            ..bookmark("prod", "wss://prod.example/ws")
+        };
+        add(path.to_str().unwrap(), &prod).unwrap();
+        add(
+            path.to_str().unwrap(),
+            &bookmark("dev", "ws://dev.example/ws"),
+        )
+        .unwrap();
+
+        let bookmarks = reload(&path);
+        assert_eq!(
+            describe(&bookmarks),
+            "prod  wss://prod.example/ws  shell=bash  token=$PROD_TOKEN\ndev  ws://dev.example/ws"
+        );
+        // The rest of the file is left as it was
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(
+            content.starts_with("# Staging and production\n[server]\n"),
+            "{}",
+            content
+        );
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn adding_creates_a_missing_file() {
+        let path = config_file("create", None);
+        add(
+            path.to_str().unwrap(),
+            &bookmark("local", "ws://127.0.0.1:8080/ws"),
+        )
+        .unwrap();
+
+        let bookmarks = reload(&path);
+        assert_eq!(bookmarks.len(), 1);
+        assert_eq!(bookmarks[0].url, "ws://127.0.0.1:8080/ws");
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn duplicate_names_are_refused() {
+        let path = config_file("duplicate", None);
+        let path_str = path.to_str().unwrap();
+        add(path_str, &bookmark("prod", "wss://prod.example/ws")).unwrap();
+
+        let error = add(path_str, &bookmark("prod", "wss://other.example/ws")).unwrap_err();
+        assert!(
+            error.to_string().contains("Bookmark `prod` already exists"),
+            "{}",
+            error
+        );
+        let bookmarks = reload(&path);
+        assert_eq!(bookmarks.len(), 1);
+        assert_eq!(bookmarks[0].url, "wss://prod.example/ws");
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn unknown_names_list_the_available_bookmarks() {
+        let bookmarks = [
+            bookmark("prod", "wss://prod/ws"),
+            bookmark("dev", "ws://dev/ws"),
+        ];
+        assert_eq!(find(&bookmarks, "dev").unwrap().url, "ws://dev/ws");
+
+        let error = find(&bookmarks, "staging").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unknown bookmark `staging`, available: prod, dev"
+        );
+        let error = find(&[], "staging").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unknown bookmark `staging`, no bookmarks are configured"
+        );
+    }
+
+    #[test]
+    fn command_line_settings_override_the_bookmark() {
+        let prod = Bookmark {
+            shell: Some("bash".to_string()),
+            ..bookmark("prod", "wss://prod.example/ws")
+        };
+
+        let target = resolve(Some(&prod), Overrides::default(), "ws://default/ws").unwrap();
+        assert_eq!(target.url, "wss://prod.example/ws");
+        assert_eq!(target.shell.as_deref(), Some("bash"));
+        assert_eq!(target.token, None);
+
+        let overrides = Overrides {
+            url: Some("ws://override/ws".to_string()),
+            shell: Some("zsh".to_string()),
+            ..Overrides::default()
+        };
+        let target = resolve(Some(&prod), overrides, "ws://default/ws").unwrap();
+        assert_eq!(target.url, "ws://override/ws");
+        assert_eq!(target.shell.as_deref(), Some("zsh"));
+
+        let target = resolve(None, Overrides::default(), "ws://default/ws").unwrap();
+        assert_eq!(target.url, "ws://default/ws");
+    }
+
+    #[test]
+    fn missing_token_variable_and_other_transports_are_errors() {
+        let missing = Bookmark {
+            token_env: Some("BOOKMARKS_TEST_UNSET_TOKEN".to_string()),
+            ..bookmark("prod", "wss://prod.example/ws")
+        };
+        let error = resolve(Some(&missing), Overrides::default(), "").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Token variable BOOKMARKS_TEST_UNSET_TOKEN is not set"
+        );
+
+        let webtransport = Bookmark {
+            transport: Transport::Webtransport,
+            ..bookmark("quic", "https://quic.example/wt")
+        };
+        let error = resolve(Some(&webtransport), Overrides::default(), "").unwrap_err();
+        assert!(
+            error.to_string().contains("only speaks websocket"),
+            "{}",
+            error
+        );
+    }
+}
@@ -2,8 +2,12 @@ use serde::Deserialize;
 use std::fs;
 use std::path::Path;
 
+use crate::bookmarks::Bookmark;
 use crate::error::{Result, Error};
 
+/// Configuration file used when no `--config` is given
+pub const DEFAULT_CONFIG_PATH: &str = "client.toml";
+
 /// Server configuration
 #[derive(Deserialize, Debug, Clone)]
 pub struct ServerConfig {
@@ -11,23 +15,60 @@ pub struct ServerConfig {
     pub url: String,
 }
 
-/// Main configuration structure
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            url: "ws://localhost:8080/ws".to_string(),
+        }
+    }
+}
+
+/// Connection keepalive configuration
 #[derive(Deserialize, Debug, Clone)]
-pub struct Config {
-    /// Server configuration
-    pub server: ServerConfig,
+#[serde(default)]
+pub struct ConnectionConfig {
+    /// Seconds between pings to the server, keeping proxies from closing an idle
+    /// connection (0 disables pings)
+    pub ping_interval_secs: u64,
+    
+    /// Seconds without a pong before the connection is reported as stale
+    pub pong_timeout_secs: u64,
+    
+    /// Reconnect attempts after the connection was lost (0 never reconnects)
+    pub reconnect_attempts: u32,
+    
+    /// KiB of output kept locally and shown again after a reconnect the server doesn't
+    /// replay its scrollback for (0 disables it)
+    pub scrollback_kib: usize,
 }
 
-impl Default for Config {
+impl Default for ConnectionConfig {
     fn default() -> Self {
         Self {
-            server: ServerConfig {
-                url: "ws://localhost:8080/ws".to_string(),
-            },
+            ping_interval_secs: 30,
+            pong_timeout_secs: 10,
+            reconnect_attempts: 5,
+            scrollback_kib: 64,
         }
     }
 }
 
+/// Main configuration structure
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Config {
+    /// Server configuration
+    #[serde(default)]
+    pub server: ServerConfig,
+    
+    /// Connection keepalive configuration
+    #[serde(default)]
+    pub connection: ConnectionConfig,
+    
+    /// Named server connections (`[[bookmarks]]`), used with `--bookmark`
+    #[serde(default)]
+    pub bookmarks: Vec<Bookmark>,
+}
+
 impl Config {
     /// Load configuration from file or use default values
     pub fn load(config_path: Option<String>) -> Result<Self> {
@@ -38,9 +79,8 @@ impl Config {
             },
             None => {
                 // Try to load from default location, otherwise use defaults
-                let default_path = "client.toml";
-                if Path::new(default_path).exists() {
-                    Self::from_file(default_path)
+                if Path::new(DEFAULT_CONFIG_PATH).exists() {
+                    Self::from_file(DEFAULT_CONFIG_PATH)
                 } else {
                     Ok(Self::default())
                 }
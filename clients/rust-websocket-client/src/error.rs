@@ -9,9 +9,13 @@ pub type Result<T> = std::result::Result<T, Error>;
 /// Custom error type for the WebSocket client
 #[derive(Error, Debug)]
 pub enum Error {
-    /// WebSocket connection or protocol error
+    /// WebSocket connection or protocol error (boxed, it is much larger than the other variants)
     #[error("WebSocket error: {0}")]
-    WebSocket(#[from] TungsteniteError),
+    WebSocket(Box<TungsteniteError>),
+    
+    /// Terminal client error (boxed, it carries a WebSocket error)
+    #[error("{0}")]
+    Client(Box<terminal_client::Error>),
     
     /// IO error
     #[error("IO error: {0}")]
@@ -21,6 +25,10 @@ pub enum Error {
     #[error("Config error: {0}")]
     Config(#[from] TomlDeError),
     
+    /// Configuration file that can't be edited (`bookmarks add`)
+    #[error("Config error: {0}")]
+    ConfigEdit(#[from] toml_edit::TomlError),
+    
     /// Tracing/logging error
     #[error("Logging error: {0}")]
     Logging(#[from] TracingParseError),
@@ -29,11 +37,19 @@ pub enum Error {
     #[error("File not found: {0}")]
     FileNotFound(String),
     
-    /// Invalid URL error
-    #[error("Invalid URL: {0}")]
-    InvalidUrl(String),
-    
     /// Custom error with message
     #[error("{0}")]
     Custom(String),
 }
+
+impl From<TungsteniteError> for Error {
+    fn from(e: TungsteniteError) -> Self {
+        Error::WebSocket(Box::new(e))
+    }
+}
+
+impl From<terminal_client::Error> for Error {
+    fn from(e: terminal_client::Error) -> Self {
+        Error::Client(Box::new(e))
+    }
+}
@@ -0,0 +1,210 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+// The tokio clock, which tests can pause and advance
+use tokio::time::Instant;
+
+/// Liveness of the connection, tracked from our pings and the server's pongs
+/// Shared between the read task (which sees the pongs) and the main loop (which sends the pings)
+#[derive(Debug, Clone)]
+pub struct Heartbeat {
+    state: Arc<Mutex<HeartbeatState>>,
+    /// How long a pong may take before the connection counts as stale
+    timeout: Duration,
+}
+
+#[derive(Debug)]
+struct HeartbeatState {
+    /// Payload and send time of the ping waiting for its pong
+    pending: Option<(u64, Instant)>,
+    /// Number of the next ping, sent as its payload
+    next_ping: u64,
+    /// When the last pong arrived
+    last_pong: Option<Instant>,
+    /// Round-trip time of the last answered ping
+    rtt: Option<Duration>,
+    /// Whether the stale warning was shown for the pending ping
+    stale: bool,
+}
+
+/// What changed when a pong arrived
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PongOutcome {
+    /// The pong answered the pending ping
+    Answered,
+    /// The pong answered a ping after the connection was reported stale
+    Recovered,
+    /// The pong doesn't answer the pending ping (late or unsolicited)
+    Ignored,
+}
+
+impl Heartbeat {
+    /// Create a heartbeat considering the connection stale after `timeout` without a pong
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(HeartbeatState {
+                pending: None,
+                next_ping: 0,
+                last_pong: None,
+                rtt: None,
+                stale: false,
+            })),
+            timeout,
+        }
+    }
+
+    /// The lock is only held for field updates, a panic can't leave it inconsistent
+    fn lock(&self) -> MutexGuard<'_, HeartbeatState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Forget the pending ping, whose pong can't arrive on a new connection
+    pub fn reset(&self) {
+        let mut state = self.lock();
+        state.pending = None;
+        state.stale = false;
+    }
+
+    /// Payload of the next ping, `None` while the previous ping is still unanswered
+    pub fn next_ping(&self) -> Option<Vec<u8>> {
+        let mut state = self.lock();
+        if state.pending.is_some() {
+            return None;
+        }
+        let id = state.next_ping;
+        state.next_ping += 1;
+        state.pending = Some((id, Instant::now()));
+        Some(id.to_be_bytes().to_vec())
+    }
+
+    /// Record a pong from the server
+    pub fn pong(&self, payload: &[u8]) -> PongOutcome {
+        let mut state = self.lock();
+        let Some((id, sent_at)) = state.pending else {
+            return PongOutcome::Ignored;
+        };
+        if payload != id.to_be_bytes() {
+            return PongOutcome::Ignored;
+        }
+        let now = Instant::now();
+        state.pending = None;
+        state.last_pong = Some(now);
+        state.rtt = Some(now - sent_at);
+        if std::mem::take(&mut state.stale) {
+            PongOutcome::Recovered
+        } else {
+            PongOutcome::Answered
+        }
+    }
+
+    /// How long the pending ping has gone unanswered, the first time it exceeds the timeout
+    pub fn check_stale(&self) -> Option<Duration> {
+        let mut state = self.lock();
+        let (_, sent_at) = state.pending?;
+        let waiting = sent_at.elapsed();
+        if state.stale || waiting < self.timeout {
+            return None;
+        }
+        state.stale = true;
+        Some(waiting)
+    }
+
+    /// One line describing the liveness, for `/status`
+    pub fn status(&self) -> String {
+        let state = self.lock();
+        let rtt = match state.rtt {
+            Some(rtt) => format!("{} ms", rtt.as_millis()),
+            None => "unknown".to_string(),
+        };
+        let last_pong = match state.last_pong {
+            Some(at) => format!("{}s ago", at.elapsed().as_secs()),
+            None => "never".to_string(),
+        };
+        let liveness = if state.stale { "stale" } else { "alive" };
+        format!(
+            "Connection {}, round trip {}, last pong {}",
+            liveness, rtt, last_pong
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[tokio::test(start_paused = true)]
+    async fn unanswered_ping_turns_stale_once_after_the_timeout() {
+        let heartbeat = Heartbeat::new(TIMEOUT);
+        assert_eq!(heartbeat.next_ping(), Some(0u64.to_be_bytes().to_vec()));
+
+        tokio::time::advance(TIMEOUT - Duration::from_millis(1)).await;
+        assert_eq!(heartbeat.check_stale(), None);
+        tokio::time::advance(Duration::from_secs(2)).await;
+        assert_eq!(
+            heartbeat.check_stale(),
+            Some(TIMEOUT + Duration::from_secs(2) - Duration::from_millis(1))
+        );
+        assert!(heartbeat.status().starts_with("Connection stale"));
+
+        // Reported once, and no second ping goes out while the first is unanswered
+        tokio::time::advance(TIMEOUT).await;
+        assert_eq!(heartbeat.check_stale(), None);
+        assert_eq!(heartbeat.next_ping(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pong_resets_the_timeout() {
+        let heartbeat = Heartbeat::new(TIMEOUT);
+        let ping = heartbeat.next_ping().unwrap();
+        tokio::time::advance(Duration::from_millis(250)).await;
+        assert_eq!(heartbeat.pong(&ping), PongOutcome::Answered);
+        assert_eq!(
+            heartbeat.status(),
+            "Connection alive, round trip 250 ms, last pong 0s ago"
+        );
+
+        // The next ping is timed from when it was sent, not from the first one
+        tokio::time::advance(TIMEOUT).await;
+        assert_eq!(heartbeat.check_stale(), None);
+        let ping = heartbeat.next_ping().unwrap();
+        tokio::time::advance(TIMEOUT / 2).await;
+        assert_eq!(heartbeat.check_stale(), None);
+        assert_eq!(heartbeat.pong(&ping), PongOutcome::Answered);
+        tokio::time::advance(TIMEOUT * 2).await;
+        assert_eq!(heartbeat.check_stale(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pong_after_the_stale_warning_recovers() {
+        let heartbeat = Heartbeat::new(TIMEOUT);
+        let ping = heartbeat.next_ping().unwrap();
+        tokio::time::advance(TIMEOUT * 3).await;
+        assert!(heartbeat.check_stale().is_some());
+
+        // A pong for another ping doesn't count
+        assert_eq!(heartbeat.pong(&7u64.to_be_bytes()), PongOutcome::Ignored);
+        assert_eq!(heartbeat.pong(&ping), PongOutcome::Recovered);
+        assert!(
+            heartbeat
+                .status()
+                .starts_with("Connection alive, round trip 30000 ms")
+        );
+        assert!(heartbeat.next_ping().is_some());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reconnect_forgets_the_pending_ping() {
+        let heartbeat = Heartbeat::new(TIMEOUT);
+        let ping = heartbeat.next_ping().unwrap();
+        tokio::time::advance(TIMEOUT * 2).await;
+        assert!(heartbeat.check_stale().is_some());
+
+        heartbeat.reset();
+        assert_eq!(heartbeat.check_stale(), None);
+        // The old connection's pong can't answer the new connection's ping
+        assert_eq!(heartbeat.pong(&ping), PongOutcome::Ignored);
+        assert_eq!(heartbeat.next_ping(), Some(1u64.to_be_bytes().to_vec()));
+    }
+}
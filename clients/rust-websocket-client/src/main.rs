@@ -1,22 +1,44 @@
+mod bookmarks;
 mod config;
 mod error;
+mod heartbeat;
 mod logger;
+mod mouse;
+mod output;
+mod predict;
+mod shutdown;
+mod summary;
 mod terminal;
 mod websocket;
 
-use clap::Parser;
-use config::Config;
+use std::path::PathBuf;
+
+use bookmarks::{Bookmark, Overrides, Transport};
+use clap::{Parser, Subcommand};
+use config::{Config, DEFAULT_CONFIG_PATH};
 use error::Result;
 use logger::init_logging;
-use websocket::WebSocketClient;
+use websocket::{Options, WebSocketClient};
 
 /// Production-ready Rust WebSocket client for terminal applications
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
-    /// WebSocket server URL to connect to
-    #[arg(short, long, default_value = "ws://localhost:8080/ws")]
-    url: String,
+    /// WebSocket server URL to connect to (default from the bookmark or the configuration)
+    #[arg(short, long)]
+    url: Option<String>,
+    
+    /// Connect to a bookmark from the configuration; other options override its settings
+    #[arg(short, long)]
+    bookmark: Option<String>,
+    
+    /// Environment variable holding a token sent as `Authorization: Bearer`
+    #[arg(long)]
+    token_env: Option<String>,
+    
+    /// Start a session with this shell type through the REST API and attach to it
+    #[arg(long)]
+    shell: Option<String>,
     
     /// Enable debug logging
     #[arg(short, long, default_value_t = false)]
@@ -25,6 +47,76 @@ struct Cli {
     /// Configuration file path
     #[arg(short, long)]
     config: Option<String>,
+    
+    /// Don't ask the server for its version and shells before connecting
+    /// (for servers without the REST API)
+    #[arg(long, default_value_t = false)]
+    no_probe: bool,
+    
+    /// Seconds between pings to the server (0 disables them; default from the configuration)
+    #[arg(long)]
+    ping_interval: Option<u64>,
+    
+    /// Don't show the server's echo of input the terminal already shows
+    /// (for high-latency links; off inside full-screen applications)
+    #[arg(long, default_value_t = false)]
+    predict: bool,
+    
+    /// Send key presses as they are typed and forward mouse clicks, drags and scrolling
+    /// (for htop, vim and other full-screen applications; Ctrl+] quits)
+    #[arg(long, default_value_t = false, conflicts_with = "predict")]
+    mouse: bool,
+    
+    /// Append the terminal output received from the server to this file
+    #[arg(long)]
+    log_output: Option<PathBuf>,
+    
+    /// KiB of output kept to show again after a reconnect (0 disables it; default from the
+    /// configuration)
+    #[arg(long)]
+    scrollback_kib: Option<usize>,
+    
+    /// Don't print the session summary (duration, traffic, how it ended) to stderr on exit
+    #[arg(long, default_value_t = false)]
+    no_summary: bool,
+    
+    /// Print the session summary as JSON
+    #[arg(long, default_value_t = false, conflicts_with = "no_summary")]
+    json_summary: bool,
+    
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Manage the bookmarks saved in the configuration
+    Bookmarks {
+        #[command(subcommand)]
+        action: BookmarkAction,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum BookmarkAction {
+    /// List the saved bookmarks
+    List,
+    /// Save a bookmark in the configuration file, keeping its comments
+    Add {
+        /// Name to connect with `--bookmark`
+        name: String,
+        /// WebSocket server URL
+        url: String,
+        /// Environment variable holding the token
+        #[arg(long)]
+        token_env: Option<String>,
+        /// Shell type of the session
+        #[arg(long)]
+        shell: Option<String>,
+        /// Transport to connect with
+        #[arg(long, value_enum, default_value_t = Transport::Websocket)]
+        transport: Transport,
+    },
 }
 
 #[tokio::main]
@@ -35,21 +127,74 @@ async fn main() -> Result<()> {
     // Initialize logging
     init_logging(cli.debug)?;
     
+    // Bookmark management doesn't connect
+    if let Some(Command::Bookmarks { action }) = cli.command {
+        return run_bookmarks(action, cli.config);
+    }
+    
     // Load configuration
-    let config = Config::load(cli.config)?;
+    let mut config = Config::load(cli.config)?;
+    if let Some(ping_interval) = cli.ping_interval {
+        config.connection.ping_interval_secs = ping_interval;
+    }
+    if let Some(scrollback_kib) = cli.scrollback_kib {
+        config.connection.scrollback_kib = scrollback_kib;
+    }
     
-    // Use command line URL if provided, otherwise use config
-    let url = if !cli.url.is_empty() {
-        cli.url
-    } else {
-        config.server.url.clone()
+    // Command line options win over the bookmark, the bookmark over the configured server
+    let bookmark = match &cli.bookmark {
+        Some(name) => Some(bookmarks::find(&config.bookmarks, name)?),
+        None => None,
+    };
+    let overrides = Overrides {
+        url: cli.url,
+        token_env: cli.token_env,
+        shell: cli.shell,
     };
+    let target = bookmarks::resolve(bookmark, overrides, &config.server.url)?;
     
     // Create WebSocket client
-    let mut client = WebSocketClient::new(&url).await?;
+    let options = Options {
+        predict: cli.predict,
+        mouse: cli.mouse,
+        log_output: cli.log_output,
+    };
+    let mut client = WebSocketClient::new(target, config.connection.clone(), options).await?;
+    
+    // Show what the server offers; a server without the REST API can still be used
+    if !cli.no_probe {
+        client.probe().await;
+    }
     
     // Run the client
-    client.run().await?;
+    let result = client.run().await;
+    
+    // Summarize the session on stderr, so piped output stays clean
+    if !cli.no_summary {
+        let summary = client.summary(result.as_ref().err());
+        if cli.json_summary {
+            eprintln!("{}", summary.json());
+        } else {
+            eprintln!("{}", summary.line());
+        }
+    }
     
+    result
+}
+
+/// Run a `bookmarks` subcommand against the configuration file
+fn run_bookmarks(action: BookmarkAction, config_path: Option<String>) -> Result<()> {
+    match action {
+        BookmarkAction::List => {
+            let config = Config::load(config_path)?;
+            println!("{}", bookmarks::describe(&config.bookmarks));
+        },
+        BookmarkAction::Add { name, url, token_env, shell, transport } => {
+            let path = config_path.unwrap_or_else(|| DEFAULT_CONFIG_PATH.to_string());
+            let bookmark = Bookmark { name, url, token_env, shell, transport };
+            bookmarks::add(&path, &bookmark)?;
+            println!("Saved bookmark {} in {}", bookmark.name, path);
+        },
+    }
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,240 @@
+use std::io::{self, Write, stdout};
+
+use crossterm::event::{
+    DisableMouseCapture, EnableMouseCapture, Event, EventStream, KeyCode, KeyEvent, KeyEventKind,
+    KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+use futures_util::StreamExt;
+use tokio::sync::mpsc;
+
+/// Input read from the local terminal in raw mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RawInput {
+    /// Bytes for the terminal, a key press or a mouse report
+    Data(String),
+    /// Ctrl+] was pressed
+    Quit,
+}
+
+/// Local terminal in raw mode with mouse capture (`--mouse`), restored when dropped
+///
+/// Key presses are sent as they are typed and mouse events are forwarded as xterm SGR mouse
+/// reports (`ESC [ < button ; column ; row` followed by `M`, or `m` for a release), the encoding
+/// htop, vim and other full-screen applications enable. Moving the mouse without a button pressed
+/// isn't reported. Ctrl+] ends the session, since Ctrl+C goes to the remote shell.
+pub struct RawTerminal;
+
+impl RawTerminal {
+    /// Switch the local terminal to raw mode and capture the mouse
+    pub fn enable() -> io::Result<Self> {
+        enable_raw_mode()?;
+        if let Err(e) = execute!(stdout(), EnableMouseCapture) {
+            let _ = disable_raw_mode();
+            return Err(e);
+        }
+        Ok(Self)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = execute!(stdout(), DisableMouseCapture);
+        let _ = disable_raw_mode();
+        let _ = stdout().flush();
+    }
+}
+
+/// Read key and mouse events from the local terminal, encoded for the remote one
+/// The channel closes when the terminal's input ends
+pub fn spawn_event_reader() -> mpsc::Receiver<RawInput> {
+    let (input_tx, input_rx) = mpsc::channel(64);
+    tokio::spawn(async move {
+        let mut events = EventStream::new();
+        while let Some(event) = events.next().await {
+            let input = match event {
+                Ok(Event::Key(key)) if is_quit(&key) => RawInput::Quit,
+                Ok(Event::Key(key)) => match encode_key(&key) {
+                    Some(data) => RawInput::Data(data),
+                    None => continue,
+                },
+                Ok(Event::Mouse(mouse)) => match encode_mouse(&mouse) {
+                    Some(data) => RawInput::Data(data),
+                    None => continue,
+                },
+                Ok(Event::Paste(text)) => RawInput::Data(text),
+                Ok(_) => continue,
+                Err(e) => {
+                    tracing::error!("Failed to read terminal events: {}", e);
+                    break;
+                }
+            };
+            if input_tx.send(input).await.is_err() {
+                break;
+            }
+        }
+    });
+    input_rx
+}
+
+/// Whether a key press is Ctrl+]
+fn is_quit(key: &KeyEvent) -> bool {
+    key.kind != KeyEventKind::Release
+        && key.modifiers.contains(KeyModifiers::CONTROL)
+        && key.code == KeyCode::Char(']')
+}
+
+/// Encode a mouse event as an SGR mouse report, `None` for motion without a button
+/// Columns and rows count from 1; Shift, Alt and Ctrl add 4, 8 and 16 to the button code
+pub fn encode_mouse(event: &MouseEvent) -> Option<String> {
+    let button = |button: MouseButton| match button {
+        MouseButton::Left => 0,
+        MouseButton::Middle => 1,
+        MouseButton::Right => 2,
+    };
+    let (code, released) = match event.kind {
+        MouseEventKind::Down(pressed) => (button(pressed), false),
+        MouseEventKind::Up(pressed) => (button(pressed), true),
+        // Motion with a button pressed
+        MouseEventKind::Drag(pressed) => (button(pressed) + 32, false),
+        MouseEventKind::ScrollUp => (64, false),
+        MouseEventKind::ScrollDown => (65, false),
+        MouseEventKind::ScrollLeft => (66, false),
+        MouseEventKind::ScrollRight => (67, false),
+        MouseEventKind::Moved => return None,
+    };
+    let mut modifiers = 0;
+    if event.modifiers.contains(KeyModifiers::SHIFT) {
+        modifiers += 4;
+    }
+    if event.modifiers.contains(KeyModifiers::ALT) {
+        modifiers += 8;
+    }
+    if event.modifiers.contains(KeyModifiers::CONTROL) {
+        modifiers += 16;
+    }
+    Some(format!(
+        "\x1b[<{};{};{}{}",
+        code + modifiers,
+        u32::from(event.column) + 1,
+        u32::from(event.row) + 1,
+        if released { 'm' } else { 'M' }
+    ))
+}
+
+/// Encode a key press the way an xterm sends it, `None` for releases and keys without a sequence
+pub fn encode_key(event: &KeyEvent) -> Option<String> {
+    if event.kind == KeyEventKind::Release {
+        return None;
+    }
+    let sequence = match event.code {
+        KeyCode::Char(c) if event.modifiers.contains(KeyModifiers::CONTROL) => {
+            let control = match c.to_ascii_lowercase() {
+                c @ 'a'..='z' => c as u8 - b'a' + 1,
+                '@' | ' ' => 0,
+                '[' => 0x1b,
+                '\\' => 0x1c,
+                '^' => 0x1e,
+                '_' => 0x1f,
+                _ => return None,
+            };
+            char::from(control).to_string()
+        }
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Enter => "\r".to_string(),
+        KeyCode::Tab => "\t".to_string(),
+        KeyCode::BackTab => "\x1b[Z".to_string(),
+        KeyCode::Backspace => "\x7f".to_string(),
+        KeyCode::Esc => "\x1b".to_string(),
+        KeyCode::Up => "\x1b[A".to_string(),
+        KeyCode::Down => "\x1b[B".to_string(),
+        KeyCode::Right => "\x1b[C".to_string(),
+        KeyCode::Left => "\x1b[D".to_string(),
+        KeyCode::Home => "\x1b[H".to_string(),
+        KeyCode::End => "\x1b[F".to_string(),
+        KeyCode::Insert => "\x1b[2~".to_string(),
+        KeyCode::Delete => "\x1b[3~".to_string(),
+        KeyCode::PageUp => "\x1b[5~".to_string(),
+        KeyCode::PageDown => "\x1b[6~".to_string(),
+        KeyCode::F(n @ 1..=4) => format!("\x1bO{}", char::from(b'P' + n - 1)),
+        KeyCode::F(n) => {
+            let code = match n {
+                5 => 15,
+                6..=10 => n + 11,
+                11..=12 => n + 12,
+                _ => return None,
+            };
+            format!("\x1b[{}~", code)
+        }
+        _ => return None,
+    };
+    // Alt sends ESC before the key
+    if event.modifiers.contains(KeyModifiers::ALT) {
+        return Some(format!("\x1b{}", sequence));
+    }
+    Some(sequence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mouse(kind: MouseEventKind, column: u16, row: u16, modifiers: KeyModifiers) -> MouseEvent {
+        MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers,
+        }
+    }
+
+    #[test]
+    fn press_and_release_are_reported() {
+        let press = mouse(MouseEventKind::Down(MouseButton::Left), 9, 4, KeyModifiers::NONE);
+        assert_eq!(encode_mouse(&press).unwrap(), "\x1b[<0;10;5M");
+        let release = mouse(MouseEventKind::Up(MouseButton::Left), 9, 4, KeyModifiers::NONE);
+        assert_eq!(encode_mouse(&release).unwrap(), "\x1b[<0;10;5m");
+        let middle = mouse(MouseEventKind::Down(MouseButton::Middle), 0, 0, KeyModifiers::NONE);
+        assert_eq!(encode_mouse(&middle).unwrap(), "\x1b[<1;1;1M");
+        let right = mouse(MouseEventKind::Down(MouseButton::Right), 299, 99, KeyModifiers::NONE);
+        assert_eq!(encode_mouse(&right).unwrap(), "\x1b[<2;300;100M");
+    }
+
+    #[test]
+    fn drag_adds_the_motion_flag() {
+        let drag = mouse(MouseEventKind::Drag(MouseButton::Left), 20, 3, KeyModifiers::NONE);
+        assert_eq!(encode_mouse(&drag).unwrap(), "\x1b[<32;21;4M");
+        let drag = mouse(MouseEventKind::Drag(MouseButton::Right), 20, 3, KeyModifiers::CONTROL);
+        assert_eq!(encode_mouse(&drag).unwrap(), "\x1b[<50;21;4M");
+        let moved = mouse(MouseEventKind::Moved, 20, 3, KeyModifiers::NONE);
+        assert_eq!(encode_mouse(&moved), None);
+    }
+
+    #[test]
+    fn scrolling_is_reported_as_wheel_buttons() {
+        let up = mouse(MouseEventKind::ScrollUp, 4, 7, KeyModifiers::NONE);
+        assert_eq!(encode_mouse(&up).unwrap(), "\x1b[<64;5;8M");
+        let down = mouse(MouseEventKind::ScrollDown, 4, 7, KeyModifiers::SHIFT);
+        assert_eq!(encode_mouse(&down).unwrap(), "\x1b[<69;5;8M");
+        let left = mouse(MouseEventKind::ScrollLeft, 4, 7, KeyModifiers::ALT);
+        assert_eq!(encode_mouse(&left).unwrap(), "\x1b[<74;5;8M");
+        let right = mouse(MouseEventKind::ScrollRight, 65534, 65534, KeyModifiers::NONE);
+        assert_eq!(encode_mouse(&right).unwrap(), "\x1b[<67;65535;65535M");
+    }
+
+    #[test]
+    fn keys_are_encoded_like_xterm() {
+        let key = |code, modifiers| encode_key(&KeyEvent::new(code, modifiers));
+        assert_eq!(key(KeyCode::Char('a'), KeyModifiers::NONE).unwrap(), "a");
+        assert_eq!(key(KeyCode::Char('c'), KeyModifiers::CONTROL).unwrap(), "\x03");
+        assert_eq!(key(KeyCode::Char('b'), KeyModifiers::ALT).unwrap(), "\x1bb");
+        assert_eq!(key(KeyCode::Enter, KeyModifiers::NONE).unwrap(), "\r");
+        assert_eq!(key(KeyCode::Backspace, KeyModifiers::NONE).unwrap(), "\x7f");
+        assert_eq!(key(KeyCode::Up, KeyModifiers::NONE).unwrap(), "\x1b[A");
+        assert_eq!(key(KeyCode::F(1), KeyModifiers::NONE).unwrap(), "\x1bOP");
+        assert_eq!(key(KeyCode::F(5), KeyModifiers::NONE).unwrap(), "\x1b[15~");
+        assert_eq!(key(KeyCode::F(12), KeyModifiers::NONE).unwrap(), "\x1b[24~");
+        assert!(is_quit(&KeyEvent::new(KeyCode::Char(']'), KeyModifiers::CONTROL)));
+    }
+}
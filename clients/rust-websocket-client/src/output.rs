@@ -0,0 +1,161 @@
+use std::fs::File;
+use std::io::Write;
+
+/// Line above the local copy of the output, shown again after a reconnect
+pub const LOCAL_COPY_DIVIDER: &str =
+    "──────── output before the reconnect (local copy, not replayed by the server) ────────";
+
+/// Line below the local copy of the output
+pub const RECONNECTED_DIVIDER: &str = "──────── reconnected ────────";
+
+/// Bounded local copy of the latest terminal output (`scrollback_kib`)
+/// After a reconnect it is shown again if the server doesn't replay its own scrollback, so the
+/// screen isn't left blank
+#[derive(Debug)]
+pub struct LocalScrollback {
+    text: String,
+    /// Largest copy kept in bytes, 0 keeps none
+    capacity: usize,
+    /// Whether older output was dropped, the copy then starts at a line break
+    trimmed: bool,
+}
+
+impl LocalScrollback {
+    /// Keep up to `capacity` bytes of output (0 disables the copy)
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            text: String::new(),
+            capacity,
+            trimmed: false,
+        }
+    }
+
+    /// Keep output, dropping the oldest beyond the capacity
+    pub fn push(&mut self, output: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.text.push_str(output);
+        if self.text.len() <= self.capacity {
+            return;
+        }
+        let mut start = self.text.len() - self.capacity;
+        while !self.text.is_char_boundary(start) {
+            start += 1;
+        }
+        // Start at a line, not in the middle of one (or of an escape sequence)
+        if let Some(line) = self.text[start..].find('\n') {
+            start += line + 1;
+        }
+        self.text.drain(..start);
+        self.trimmed = true;
+    }
+
+    /// The copy between divider lines, `None` if there is no output to show
+    pub fn render(&self) -> Option<String> {
+        if self.text.is_empty() {
+            return None;
+        }
+        let separator = if self.text.ends_with('\n') { "" } else { "\r\n" };
+        Some(format!(
+            "\r\n{}\r\n{}{}{}{}\r\n",
+            LOCAL_COPY_DIVIDER,
+            if self.trimmed { "...\r\n" } else { "" },
+            self.text,
+            separator,
+            RECONNECTED_DIVIDER
+        ))
+    }
+}
+
+/// Where terminal output received from the server goes: the screen, the local scrollback and
+/// the `--log-output` file
+pub struct OutputSink {
+    screen: Box<dyn Write + Send>,
+    /// Whether output is written as-is (raw mode terminal) or as one line per message
+    raw: bool,
+    scrollback: LocalScrollback,
+    log: Option<File>,
+}
+
+impl OutputSink {
+    /// Create a sink writing to `screen`, keeping `scrollback_bytes` of output and logging
+    /// received output to `log`
+    pub fn new(
+        screen: Box<dyn Write + Send>,
+        raw: bool,
+        scrollback_bytes: usize,
+        log: Option<File>,
+    ) -> Self {
+        Self {
+            screen,
+            raw,
+            scrollback: LocalScrollback::new(scrollback_bytes),
+            log,
+        }
+    }
+
+    /// Handle output received from the server: `received` is logged, `shown` (what is left after
+    /// prediction) is displayed and kept
+    pub fn received(&mut self, received: &str, shown: &str) {
+        if let Some(log) = &mut self.log
+            && let Err(e) = log.write_all(received.as_bytes())
+        {
+            tracing::error!("Failed to write the output log, not logging anymore: {}", e);
+            self.log = None;
+        }
+        self.scrollback.push(shown);
+        if self.raw {
+            self.show(shown);
+        } else if !shown.is_empty() {
+            self.show(&format!("{}\n", shown));
+        }
+    }
+
+    /// Show the local copy of the output again (after a reconnect the server replayed nothing
+    /// for); it isn't logged or kept a second time
+    pub fn replay_local(&mut self) -> bool {
+        let Some(copy) = self.scrollback.render() else {
+            return false;
+        };
+        self.show(&copy);
+        true
+    }
+
+    fn show(&mut self, text: &str) {
+        let _ = self.screen.write_all(text.as_bytes());
+        let _ = self.screen.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrollback_keeps_the_latest_lines() {
+        let mut scrollback = LocalScrollback::new(16);
+        scrollback.push("first line\r\n");
+        scrollback.push("second\r\nthird\r\n");
+        let copy = scrollback.render().unwrap();
+        assert!(copy.contains("...\r\nsecond\r\nthird\r\n"), "{:?}", copy);
+        assert!(!copy.contains("first"), "{:?}", copy);
+        assert!(copy.starts_with(&format!("\r\n{}\r\n", LOCAL_COPY_DIVIDER)));
+        assert!(copy.ends_with(&format!("{}\r\n", RECONNECTED_DIVIDER)));
+    }
+
+    #[test]
+    fn scrollback_is_trimmed_at_char_boundaries() {
+        let mut scrollback = LocalScrollback::new(5);
+        scrollback.push("ééééé");
+        let copy = scrollback.render().unwrap();
+        assert!(copy.contains("...\r\néé\r\n"), "{:?}", copy);
+    }
+
+    #[test]
+    fn zero_capacity_keeps_nothing() {
+        let mut scrollback = LocalScrollback::new(0);
+        scrollback.push("output\r\n");
+        assert_eq!(scrollback.render(), None);
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::VecDeque;
+
+/// Local echo prediction for high-latency links (`--predict`)
+///
+/// The local terminal shows what the user types right away, so the server's echo of that input
+/// is predicted: output matching the prediction is confirmed and not shown a second time. Output
+/// before the echo starts (e.g. a late prompt) is shown; once the echo started, the first character
+/// that differs drops the remaining prediction and is shown as it arrives, as does a line break
+/// before the echo started. Escape sequences are always passed through. Inside full-screen
+/// applications (the alternate screen, entered with smcup) nothing is predicted, since their output
+/// doesn't echo input.
+#[derive(Debug, Default)]
+pub struct Predictor {
+    /// Predicted echo that hasn't been confirmed yet
+    pending: VecDeque<char>,
+    /// Whether the server started echoing the prediction
+    echo_started: bool,
+    /// Whether a full-screen application switched to the alternate screen
+    alternate_screen: bool,
+    /// Escape sequence parser state, kept across output chunks
+    escape: EscapeState,
+}
+
+/// Position inside an escape sequence
+#[derive(Debug, Default)]
+enum EscapeState {
+    /// Not in an escape sequence
+    #[default]
+    Ground,
+    /// After ESC
+    Escape,
+    /// Inside a CSI sequence, collecting its parameters
+    Csi(String),
+    /// Inside an OSC string
+    Osc,
+    /// After ESC inside an OSC string (the start of the ST terminator)
+    OscEscape,
+}
+
+/// Private modes switching to the alternate screen (`?1049`, `?1047`, `?47`)
+const ALTERNATE_SCREEN_MODES: [&str; 3] = ["1049", "1047", "47"];
+
+impl Predictor {
+    /// Create a predictor with nothing predicted
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Predict the echo of input sent to the server (ignored on the alternate screen)
+    pub fn input(&mut self, input: &str) {
+        if !self.alternate_screen {
+            self.pending.extend(input.chars());
+        }
+    }
+
+    /// Whether a full-screen application is using the alternate screen
+    pub fn alternate_screen(&self) -> bool {
+        self.alternate_screen
+    }
+
+    /// Reconcile server output with the prediction, returning the output still to be shown
+    pub fn output(&mut self, output: &str) -> String {
+        let mut shown = String::with_capacity(output.len());
+        for c in output.chars() {
+            self.escape = match std::mem::take(&mut self.escape) {
+                EscapeState::Ground if c == '\u{1b}' => EscapeState::Escape,
+                EscapeState::Ground => {
+                    if self.pending.front() == Some(&c) {
+                        // Confirmed, the user already sees it
+                        self.pending.pop_front();
+                        self.echo_started = !self.pending.is_empty();
+                        continue;
+                    }
+                    if !self.pending.is_empty() && (self.echo_started || c == '\n') {
+                        tracing::debug!(
+                            "Misprediction, dropping {} predicted characters",
+                            self.pending.len()
+                        );
+                        self.pending.clear();
+                        self.echo_started = false;
+                    }
+                    EscapeState::Ground
+                }
+                EscapeState::Escape => match c {
+                    '[' => EscapeState::Csi(String::new()),
+                    ']' => EscapeState::Osc,
+                    _ => EscapeState::Ground,
+                },
+                EscapeState::Csi(mut params) => {
+                    if ('\u{40}'..='\u{7e}').contains(&c) {
+                        self.csi_finished(&params, c);
+                        EscapeState::Ground
+                    } else {
+                        params.push(c);
+                        EscapeState::Csi(params)
+                    }
+                }
+                EscapeState::Osc => match c {
+                    '\u{7}' => EscapeState::Ground,
+                    '\u{1b}' => EscapeState::OscEscape,
+                    _ => EscapeState::Osc,
+                },
+                EscapeState::OscEscape => EscapeState::Ground,
+            };
+            shown.push(c);
+        }
+        shown
+    }
+
+    /// Track switches to and from the alternate screen
+    fn csi_finished(&mut self, params: &str, final_byte: char) {
+        let Some(modes) = params.strip_prefix('?') else {
+            return;
+        };
+        if !modes
+            .split(';')
+            .any(|mode| ALTERNATE_SCREEN_MODES.contains(&mode))
+        {
+            return;
+        }
+        match final_byte {
+            'h' => {
+                tracing::debug!("Alternate screen entered, prediction off");
+                self.alternate_screen = true;
+                self.pending.clear();
+                self.echo_started = false;
+            }
+            'l' => {
+                tracing::debug!("Alternate screen left, prediction on");
+                self.alternate_screen = false;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed output chunks as they were read from the server, returning what is shown
+    fn replay(predictor: &mut Predictor, chunks: &[&str]) -> String {
+        chunks.iter().map(|chunk| predictor.output(chunk)).collect()
+    }
+
+    #[test]
+    fn echo_is_confirmed_and_not_shown_twice() {
+        let mut predictor = Predictor::new();
+        predictor.input("ls -la");
+        // bash echoes typed characters one read at a time, or several together
+        assert_eq!(replay(&mut predictor, &["l", "s", " -la"]), "");
+
+        // Enter is echoed as CR LF, only the line feed and the command's output are new
+        predictor.input("\r");
+        let shown = replay(&mut predictor, &["\r\n", "total 0\r\n", "$ "]);
+        assert_eq!(shown, "\ntotal 0\r\n$ ");
+    }
+
+    #[test]
+    fn output_before_the_echo_is_shown() {
+        let mut predictor = Predictor::new();
+        predictor.input("pwd");
+        // A prompt printed late, then the echo
+        assert_eq!(
+            replay(&mut predictor, &["user@host:~$ ", "pwd"]),
+            "user@host:~$ "
+        );
+    }
+
+    #[test]
+    fn misprediction_drops_the_rest_of_the_prediction() {
+        let mut predictor = Predictor::new();
+        predictor.input("gti");
+        // The line editor corrected the input after the echo started
+        let shown = replay(&mut predictor, &["g", "it", " status"]);
+        assert_eq!(shown, "it status");
+        assert_eq!(predictor.output("ti"), "ti");
+    }
+
+    #[test]
+    fn unechoed_input_is_dropped_at_the_next_line() {
+        let mut predictor = Predictor::new();
+        // A password prompt doesn't echo
+        predictor.input("secret\r");
+        let shown = replay(&mut predictor, &["\r\n", "Sorry, try again.\r\n"]);
+        assert_eq!(shown, "\r\nSorry, try again.\r\n");
+        // Later output that happens to match isn't swallowed
+        assert_eq!(predictor.output("secret"), "secret");
+    }
+
+    #[test]
+    fn escape_sequences_split_across_reads_pass_through() {
+        let mut predictor = Predictor::new();
+        predictor.input("ml");
+        // The 'm' ending the color sequence isn't taken for the echo
+        let shown = replay(
+            &mut predictor,
+            &["\u{1b}[3", "2m", "m", "\u{1b}", "[0m", "l"],
+        );
+        assert_eq!(shown, "\u{1b}[32m\u{1b}[0m");
+
+        predictor.input("tl");
+        // Nor are the characters of a window title
+        let shown = replay(&mut predictor, &["\u{1b}]0;ti", "tle\u{1b}", "\\", "tl"]);
+        assert_eq!(shown, "\u{1b}]0;title\u{1b}\\");
+    }
+
+    #[test]
+    fn alternate_screen_split_across_reads_turns_prediction_off() {
+        let mut predictor = Predictor::new();
+        predictor.input(":q");
+        let shown = replay(&mut predictor, &["\u{1b}[?10", "49h", ":q"]);
+        assert_eq!(shown, "\u{1b}[?1049h:q");
+        assert!(predictor.alternate_screen());
+
+        // Typed input isn't predicted while a full-screen application runs
+        predictor.input("j");
+        assert_eq!(predictor.output("j"), "j");
+
+        assert_eq!(predictor.output("\u{1b}[?1049l"), "\u{1b}[?1049l");
+        assert!(!predictor.alternate_screen());
+        predictor.input("j");
+        assert_eq!(predictor.output("j"), "");
+    }
+}
@@ -0,0 +1,90 @@
+use tokio::sync::watch;
+
+/// Why the client is shutting down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownReason {
+    /// The user asked to quit (`/quit`)
+    Quit,
+    /// Ctrl+C (SIGINT)
+    Interrupt,
+    /// SIGTERM, handled like a quit
+    Terminate,
+}
+
+/// Receiving side of the shutdown signal, `None` until a shutdown is requested
+pub type ShutdownReceiver = watch::Receiver<Option<ShutdownReason>>;
+
+/// Sending side of the shutdown signal
+pub type ShutdownSender = watch::Sender<Option<ShutdownReason>>;
+
+/// Create the shutdown channel
+pub fn channel() -> (ShutdownSender, ShutdownReceiver) {
+    watch::channel(None)
+}
+
+/// Request a clean shutdown (the first reason wins)
+pub fn request(shutdown_tx: &ShutdownSender, reason: ShutdownReason) {
+    shutdown_tx.send_if_modified(|current| {
+        if current.is_some() {
+            return false;
+        }
+        tracing::info!("Shutdown requested: {:?}", reason);
+        *current = Some(reason);
+        true
+    });
+}
+
+/// Wait until a shutdown is requested
+pub async fn requested(shutdown_rx: &mut ShutdownReceiver) -> ShutdownReason {
+    loop {
+        if let Some(reason) = *shutdown_rx.borrow_and_update() {
+            return reason;
+        }
+        if shutdown_rx.changed().await.is_err() {
+            // All senders are gone, nothing can request a shutdown anymore
+            return std::future::pending().await;
+        }
+    }
+}
+
+/// Turn Ctrl+C and SIGTERM into shutdown requests instead of killing the process,
+/// so the connection can be closed cleanly
+pub fn spawn_signal_listener(shutdown_tx: ShutdownSender) {
+    tokio::spawn(async move {
+        let reason = tokio::select! {
+            _ = interrupt() => ShutdownReason::Interrupt,
+            _ = terminate() => ShutdownReason::Terminate,
+        };
+        request(&shutdown_tx, reason);
+    });
+}
+
+/// Resolves on Ctrl+C
+async fn interrupt() {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        tracing::warn!("Failed to listen for Ctrl+C: {}", e);
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Resolves on SIGTERM
+#[cfg(unix)]
+async fn terminate() {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            terminate.recv().await;
+        }
+        Err(e) => {
+            tracing::warn!("Failed to listen for SIGTERM: {}", e);
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// There is no SIGTERM on this platform
+#[cfg(not(unix))]
+async fn terminate() {
+    std::future::pending::<()>().await;
+}
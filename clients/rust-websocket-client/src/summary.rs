@@ -0,0 +1,187 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+
+/// Traffic counters of a session, shared by the read task and the write loop
+#[derive(Debug, Default)]
+pub struct Traffic {
+    bytes_received: AtomicU64,
+    frames_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    frames_sent: AtomicU64,
+}
+
+impl Traffic {
+    /// Count a frame of terminal output
+    pub fn received(&self, bytes: usize) {
+        self.bytes_received.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.frames_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a frame of input
+    pub fn sent(&self, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.frames_sent.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// What the user sees when the client exits: how long the session lasted, the traffic and how
+/// it ended (printed to stderr, so piped output stays clean)
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    /// Session id, when the client started the session itself (`--shell`)
+    pub session: Option<String>,
+    /// Server URL
+    pub url: String,
+    /// Time connected in seconds
+    pub duration_secs: u64,
+    /// Terminal output received in bytes
+    pub bytes_received: u64,
+    /// Output frames received
+    pub frames_received: u64,
+    /// Input sent in bytes
+    pub bytes_sent: u64,
+    /// Input frames sent
+    pub frames_sent: u64,
+    /// Close code of the server's close frame
+    pub close_code: Option<u16>,
+    /// How the session ended (quit, closed by the server, error message, ...)
+    pub ended: String,
+}
+
+impl Summary {
+    /// Take a snapshot of the counters
+    pub fn new(session: Option<String>, url: &str, duration: Duration, traffic: &Traffic, close_code: Option<u16>, ended: String) -> Self {
+        Self {
+            session,
+            url: url.to_string(),
+            duration_secs: duration.as_secs(),
+            bytes_received: traffic.bytes_received.load(Ordering::Relaxed),
+            frames_received: traffic.frames_received.load(Ordering::Relaxed),
+            bytes_sent: traffic.bytes_sent.load(Ordering::Relaxed),
+            frames_sent: traffic.frames_sent.load(Ordering::Relaxed),
+            close_code,
+            ended,
+        }
+    }
+
+    /// One line for people, e.g.
+    /// `session abc123 closed: 14m32s, 1.2 MB received, 48 KB sent, closed by server (code 1000)`
+    pub fn line(&self) -> String {
+        let subject = match &self.session {
+            Some(session) => format!("session {}", session),
+            None => format!("connection to {}", self.url),
+        };
+        format!(
+            "{} closed: {}, {} received, {} sent, {}",
+            subject,
+            format_duration(self.duration_secs),
+            format_bytes(self.bytes_received),
+            format_bytes(self.bytes_sent),
+            self.ended
+        )
+    }
+
+    /// The summary as a JSON object, for scripts (`--json-summary`)
+    pub fn json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|e| {
+            format!("{{\"error\":\"failed to encode summary: {}\"}}", e)
+        })
+    }
+}
+
+/// `45s`, `14m32s` or `2h05m`
+fn format_duration(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// `512 B`, `48 KB` or `1.2 MB`
+fn format_bytes(bytes: u64) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = 1024 * KB;
+    match bytes {
+        0..KB => format!("{} B", bytes),
+        KB..MB => format!("{} KB", bytes / KB),
+        _ => format!("{:.1} MB", bytes as f64 / MB as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A summary after a session with some traffic in both directions
+    fn session_summary(
+        session: Option<&str>,
+        duration: Duration,
+        close_code: Option<u16>,
+    ) -> Summary {
+        let traffic = Traffic::default();
+        for _ in 0..3 {
+            traffic.received(400 * 1024);
+        }
+        traffic.received(57);
+        traffic.sent(48 * 1024);
+        traffic.sent(5);
+        Summary::new(
+            session.map(str::to_string),
+            "ws://localhost:8080/ws",
+            duration,
+            &traffic,
+            close_code,
+            "closed by server (code 1000)".to_string(),
+        )
+    }
+
+    #[test]
+    fn rendered_line_snapshot() {
+        let summary = session_summary(
+            Some("abc123"),
+            Duration::from_secs(14 * 60 + 32),
+            Some(1000),
+        );
+        assert_eq!(
+            summary.line(),
+            "session abc123 closed: 14m32s, 1.2 MB received, 48 KB sent, closed by server (code 1000)"
+        );
+
+        let summary = session_summary(None, Duration::from_secs(2 * 3600 + 5 * 60 + 59), None);
+        assert_eq!(
+            summary.line(),
+            "connection to ws://localhost:8080/ws closed: 2h05m, 1.2 MB received, 48 KB sent, \
+             closed by server (code 1000)"
+        );
+    }
+
+    #[test]
+    fn json_snapshot() {
+        let summary = session_summary(Some("abc123"), Duration::from_millis(45_900), Some(1000));
+        assert_eq!(
+            summary.json(),
+            concat!(
+                r#"{"session":"abc123","url":"ws://localhost:8080/ws","duration_secs":45,"#,
+                r#""bytes_received":1228857,"frames_received":4,"bytes_sent":49157,"#,
+                r#""frames_sent":2,"close_code":1000,"ended":"closed by server (code 1000)"}"#
+            )
+        );
+    }
+
+    #[test]
+    fn durations_and_sizes_at_their_unit_boundaries() {
+        assert_eq!(format_duration(0), "0s");
+        assert_eq!(format_duration(59), "59s");
+        assert_eq!(format_duration(60), "1m00s");
+        assert_eq!(format_duration(3599), "59m59s");
+        assert_eq!(format_duration(3600), "1h00m");
+
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1024), "1 KB");
+        assert_eq!(format_bytes(1024 * 1024 - 1), "1023 KB");
+        assert_eq!(format_bytes(1024 * 1024), "1.0 MB");
+    }
+}
@@ -1,14 +1,40 @@
 use std::io::{self, stdin, stdout, Write};
 
-/// Read a line from stdin with a prompt
-pub fn read_line(prompt: &str) -> io::Result<String> {
+use tokio::sync::mpsc;
+
+/// Read a line from stdin with a prompt, `None` at the end of input
+pub fn read_line(prompt: &str) -> io::Result<Option<String>> {
     print!("{}", prompt);
     stdout().flush()?;
     
     let mut input = String::new();
-    stdin().read_line(&mut input)?;
+    if stdin().read_line(&mut input)? == 0 {
+        return Ok(None);
+    }
     
-    Ok(input.trim().to_string())
+    Ok(Some(input.trim().to_string()))
+}
+
+/// Read lines from stdin on a separate thread, so waiting for input doesn't block shutdown
+/// The channel closes at the end of input
+pub fn spawn_line_reader(prompt: &'static str) -> mpsc::Receiver<String> {
+    let (line_tx, line_rx) = mpsc::channel(16);
+    // A plain thread rather than a blocking task: the runtime doesn't wait for it on exit
+    std::thread::spawn(move || loop {
+        match read_line(prompt) {
+            Ok(Some(line)) => {
+                if line_tx.blocking_send(line).is_err() {
+                    break;
+                }
+            },
+            Ok(None) => break,
+            Err(e) => {
+                tracing::error!("IO error: {}", e);
+                break;
+            },
+        }
+    });
+    line_rx
 }
 
 /// Display a message to stdout
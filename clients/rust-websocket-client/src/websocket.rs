@@ -1,59 +1,239 @@
-use futures_util::{SinkExt, StreamExt};
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message, WebSocketStream, MaybeTlsStream};
-use tokio_tungstenite::tungstenite::client::IntoClientRequest;
-use tokio::net::TcpStream;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use terminal_client::protocol::TerminalMessage;
+use terminal_client::dto::{CreateSessionRequest, HealthResponse};
+use terminal_client::{
+    CloseReason, ServerInfo, TerminalApiClient, TerminalOutput, TerminalWsReader, TerminalWsSession,
+    http_origin, probe_server,
+};
+
+use crate::bookmarks::Target;
+use crate::config::ConnectionConfig;
 use crate::error::{Result, Error};
-use crate::terminal::{read_line, display_message};
+use crate::heartbeat::{Heartbeat, PongOutcome};
+use crate::mouse::{self, RawInput, RawTerminal};
+use crate::output::OutputSink;
+use crate::predict::Predictor;
+use crate::shutdown::{self, ShutdownReason};
+use crate::summary::{Summary, Traffic};
+use crate::terminal::{spawn_line_reader, display_message};
+
+/// How long to wait for the server to answer our close frame
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long each server info request may take before the probe is skipped
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Largest binary frame `/paste` sends, well below the server's message limit
+const PASTE_FRAME_BYTES: usize = 64 * 1024;
+
+/// How long after a reconnect the server may take to replay its scrollback before the local
+/// copy of the output is shown instead
+const REPLAY_WINDOW: Duration = Duration::from_secs(1);
+
+/// Delay before the first reconnect attempt, doubled after each failed one
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest delay between reconnect attempts
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Close codes after which reconnecting makes sense: the server went away, restarts or asks to
+/// try again later (other close frames mean the session ended)
+const RECONNECT_CLOSE_CODES: [u16; 3] = [1001, 1012, 1013];
+
+/// How the read task ended: the server's close frame, or the error that broke the connection
+type ReadEnd = std::result::Result<Option<CloseReason>, String>;
+
+/// Client behaviour chosen on the command line
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Whether the server's echo of input is predicted (`--predict`)
+    pub predict: bool,
+    /// Whether keys and mouse events are sent from a raw mode terminal (`--mouse`)
+    pub mouse: bool,
+    /// File the received terminal output is appended to (`--log-output`)
+    pub log_output: Option<PathBuf>,
+}
+
+/// State the read task shares with the write loop
+#[derive(Clone)]
+struct ReadContext {
+    pongs: Heartbeat,
+    predictor: Option<Arc<Mutex<Predictor>>>,
+    traffic: Arc<Traffic>,
+    output: Arc<Mutex<OutputSink>>,
+    /// Set once output arrived on the current connection
+    received: Arc<AtomicBool>,
+}
 
 /// WebSocket client for terminal applications
 pub struct WebSocketClient {
-    /// WebSocket server URL
-    url: String,
-    /// WebSocket stream
-    stream: Option<WebSocketStream<MaybeTlsStream<TcpStream>>>,
+    /// Server URL, token and shell to connect with
+    target: Target,
+    /// Terminal WebSocket session
+    stream: Option<TerminalWsSession>,
+    /// Ping interval and pong timeout
+    connection: ConnectionConfig,
+    /// Prediction, mouse reporting and output logging
+    options: Options,
+    /// Where output is shown
+    screen: Option<Box<dyn Write + Send>>,
+    /// Input lines, read from stdin unless set before running
+    lines: Option<mpsc::Receiver<String>>,
+    /// Id of the session started through the REST API (`--shell`)
+    session_id: Option<String>,
+    /// When the connection was established
+    connected_at: Option<Instant>,
+    /// Bytes and frames exchanged
+    traffic: Arc<Traffic>,
+    /// Close code sent by the server
+    close_code: Option<u16>,
+    /// How the session ended, for the summary
+    ended: Option<String>,
 }
 
 impl WebSocketClient {
     /// Create a new WebSocket client
-    pub async fn new(url: &str) -> Result<Self> {
-        tracing::info!("Creating WebSocket client for URL: {}", url);
+    pub async fn new(target: Target, connection: ConnectionConfig, options: Options) -> Result<Self> {
+        tracing::info!("Creating WebSocket client for URL: {}", target.url);
         
         Ok(Self {
-            url: url.to_string(),
+            target,
             stream: None,
+            connection,
+            options,
+            screen: Some(Box::new(std::io::stdout())),
+            lines: None,
+            session_id: None,
+            connected_at: None,
+            traffic: Arc::new(Traffic::default()),
+            close_code: None,
+            ended: None,
         })
     }
     
+    /// Ask the server for its version and shells and display them
+    /// Failures are only logged, connecting doesn't depend on the probe
+    pub async fn probe(&self) {
+        match probe_server(&self.target.url, PROBE_TIMEOUT).await {
+            Ok(info) => display_message(&server_banner(&info)),
+            Err(e) => tracing::warn!("Failed to probe server, connecting anyway: {}", e),
+        }
+    }
+    
+    /// Ask the server's `GET /health` for its version and running sessions, for `/status`
+    async fn server_health(&self) -> String {
+        let Some(origin) = http_origin(&self.target.url) else {
+            return format!("Server health: no REST API for {}", self.target.url);
+        };
+        let mut api = TerminalApiClient::new(&origin);
+        if let Some(token) = &self.target.token {
+            api = api.with_token(token);
+        }
+        match tokio::time::timeout(PROBE_TIMEOUT, api.health()).await {
+            Ok(Ok(health)) => describe_health(&health),
+            Ok(Err(e)) => format!("Server health unavailable: {}", e),
+            Err(_) => format!("Server health unavailable: no answer within {}s", PROBE_TIMEOUT.as_secs()),
+        }
+    }
+    
     /// Connect to the WebSocket server
+    /// With a shell, the session is started through the REST API and attached to at
+    /// `<url>/<session id>`; otherwise connecting to the URL starts one
+    /// Reconnecting attaches to the session started before
     pub async fn connect(&mut self) -> Result<()> {
-        tracing::info!("Connecting to WebSocket server at: {}", self.url);
-        
-        // Parse the URL and create a client request
-        let request = self.url.clone().into_client_request()
-            .map_err(|e| Error::InvalidUrl(e.to_string()))?;
+        let url = match (&self.session_id, &self.target.shell) {
+            (Some(session_id), _) => format!("{}/{}", self.target.url.trim_end_matches('/'), session_id),
+            (None, Some(shell)) => {
+                let session_id = self.start_session(shell).await?;
+                let url = format!("{}/{}", self.target.url.trim_end_matches('/'), session_id);
+                self.session_id = Some(session_id);
+                url
+            },
+            (None, None) => self.target.url.clone(),
+        };
         
         // Connect to the server
-        let (stream, response) = connect_async(request).await?;
-        
-        tracing::info!("Connected to server! Response status: {:?}", response.status());
-        tracing::debug!("Response headers: {:?}", response.headers());
+        let stream = TerminalWsSession::connect_with_token(&url, self.target.token.as_deref()).await?;
         
         self.stream = Some(stream);
+        self.connected_at.get_or_insert_with(Instant::now);
         Ok(())
     }
     
+    /// Connect again after the connection was lost, with growing delays between attempts
+    /// Returns `false` once `reconnect_attempts` failed, or if a shutdown was requested meanwhile
+    async fn reconnect(&mut self, lost: &str, shutdown_rx: &mut shutdown::ShutdownReceiver) -> bool {
+        let attempts = self.connection.reconnect_attempts;
+        let mut delay = RECONNECT_DELAY;
+        for attempt in 1..=attempts {
+            display_message(&format!(
+                "Connection lost ({}), reconnecting in {}s (attempt {}/{})",
+                lost, delay.as_secs(), attempt, attempts
+            ));
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {},
+                reason = shutdown::requested(shutdown_rx) => {
+                    tracing::info!("Shutdown while reconnecting: {:?}", reason);
+                    return false;
+                },
+            }
+            match self.connect().await {
+                Ok(()) => {
+                    tracing::info!("Reconnected after {} attempts", attempt);
+                    return true;
+                },
+                Err(e) => tracing::warn!("Reconnect attempt {} failed: {}", attempt, e),
+            }
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+        false
+    }
+    
+    /// Start a session running `shell` through the REST API, returning its id
+    async fn start_session(&self, shell: &str) -> Result<String> {
+        let origin = http_origin(&self.target.url).ok_or_else(|| {
+            Error::Custom(format!("No REST API for {}, can't start a {} session", self.target.url, shell))
+        })?;
+        let mut api = TerminalApiClient::new(&origin);
+        if let Some(token) = &self.target.token {
+            api = api.with_token(token);
+        }
+        let request = CreateSessionRequest {
+            user_id: std::env::var("USER").unwrap_or_else(|_| "ws-client".to_string()),
+            shell_type: Some(shell.to_string()),
+            ..Default::default()
+        };
+        let session = api.create_session(&request).await?;
+        tracing::info!("Started {} session {}", shell, session.id);
+        Ok(session.id)
+    }
+    
     /// Disconnect from the WebSocket server
     #[allow(dead_code)]
     pub async fn disconnect(&mut self) -> Result<()> {
         if let Some(mut stream) = self.stream.take() {
             tracing::info!("Disconnecting from WebSocket server...");
-            stream.close(None).await?;
+            stream.close().await?;
         }
         Ok(())
     }
     
     /// Run the WebSocket client main loop
+    /// Returns once the server closed the connection or after a clean shutdown (`/quit`,
+    /// Ctrl+C, SIGTERM, end of input)
+    /// Pings are sent while the loop runs; `/status` shows the round trip time
+    /// `/paste <path>` sends a file as binary frames, which the server writes to the terminal as-is
+    /// With `--mouse` every key press and mouse report is sent as it happens, Ctrl+] quits
     pub async fn run(&mut self) -> Result<()> {
         // Connect to the server
         self.connect().await?;
@@ -64,89 +244,356 @@ impl WebSocketClient {
         })?;
         
         // Split the stream into read and write halves
-        let (mut write, mut read) = stream.split();
+        let (mut write, read) = stream.into_split();
         
-        // Spawn a task to read messages from the server
-        let read_task = tokio::spawn(async move {
-            while let Some(msg) = read.next().await {
-                match msg {
-                    Ok(Message::Text(text)) => {
-                        tracing::info!("Received from server: {}", text);
-                        display_message(&text);
-                    },
-                    Ok(Message::Binary(bin)) => {
-                        tracing::debug!("Received binary message, length: {}", bin.len());
-                        display_message(&format!("Received binary data: {} bytes", bin.len()));
-                    },
-                    Ok(Message::Ping(_ping)) => {
-                        tracing::debug!("Received ping from server");
-                    },
-                    Ok(Message::Pong(_)) => {
-                        tracing::debug!("Received pong from server");
-                    },
-                    Ok(Message::Close(frame)) => {
-                        if let Some(frame) = frame {
-                            tracing::info!("Received close frame: code={}, reason={}", frame.code, frame.reason);
-                        } else {
-                            tracing::info!("Received close frame");
-                        }
-                        break;
-                    },
-                    Ok(Message::Frame(frame)) => {
-                        tracing::debug!("Received raw frame: {:?}", frame);
-                    },
-                    Err(e) => {
-                        tracing::error!("WebSocket error: {}", e);
-                        break;
-                    },
-                }
-            }
+        // Ctrl+C and SIGTERM request a clean shutdown instead of killing the process
+        let (shutdown_tx, mut shutdown_rx) = shutdown::channel();
+        shutdown::spawn_signal_listener(shutdown_tx.clone());
+        
+        // Pings keep proxies from closing an idle connection and detect a dead one
+        let heartbeat = Heartbeat::new(Duration::from_secs(self.connection.pong_timeout_secs));
+        let mut ping_timer = (self.connection.ping_interval_secs > 0).then(|| {
+            tokio::time::interval(Duration::from_secs(self.connection.ping_interval_secs))
         });
         
+        // Shared by the write loop (predicting the echo) and the read task (reconciling it)
+        let predictor = self.options.predict.then(|| Arc::new(Mutex::new(Predictor::new())));
+        
+        // Received output goes to the screen, the local scrollback and the output log
+        let log = match &self.options.log_output {
+            Some(path) => Some(OpenOptions::new().create(true).append(true).open(path)?),
+            None => None,
+        };
+        let screen = self.screen.take().unwrap_or_else(|| Box::new(std::io::stdout()));
+        let scrollback_bytes = self.connection.scrollback_kib * 1024;
+        let output = OutputSink::new(screen, self.options.mouse, scrollback_bytes, log);
+        
+        // Spawn a task to read messages from the server
+        let context = ReadContext {
+            pongs: heartbeat.clone(),
+            predictor: predictor.clone(),
+            traffic: self.traffic.clone(),
+            output: Arc::new(Mutex::new(output)),
+            received: Arc::new(AtomicBool::new(false)),
+        };
+        let mut read_task = spawn_reader(read, context.clone());
+        
+        // After a reconnect, when to show the local copy of the output if the server replayed none
+        let mut replay_deadline: Option<Pin<Box<tokio::time::Sleep>>> = None;
+        
+        // Input comes line by line, or from the raw mode terminal (restored when `run` returns)
+        let (_raw_terminal, mut raw_input, mut lines) = if self.options.mouse {
+            display_message("Mouse reporting on, press Ctrl+] to quit");
+            (Some(RawTerminal::enable()?), Some(mouse::spawn_event_reader()), None)
+        } else {
+            let lines = self.lines.take()
+                .unwrap_or_else(|| spawn_line_reader("Enter message (or /quit to exit): "));
+            (None, None, Some(lines))
+        };
+        
         // Main write loop
-        let write_task = tokio::spawn(async move {
-            loop {
-                // Read input from stdin
-                let input = match read_line("Enter message (or /quit to exit): ") {
-                    Ok(input) => input,
-                    Err(e) => {
-                        tracing::error!("IO error: {}", e);
+        let reason = loop {
+            let input = tokio::select! {
+                read_end = &mut read_task => {
+                    tracing::info!("Read task completed");
+                    let (ended, lost) = match read_end {
+                        Ok(Ok(Some(close))) => {
+                            self.close_code = Some(close.code);
+                            (describe_close(&close), RECONNECT_CLOSE_CODES.contains(&close.code))
+                        },
+                        Ok(Ok(None)) => ("closed by server".to_string(), false),
+                        Ok(Err(e)) => (format!("connection error: {}", e), true),
+                        Err(e) => (format!("connection error: {}", e), false),
+                    };
+                    if !lost || !self.reconnect(&ended, &mut shutdown_rx).await {
+                        self.ended = Some(ended);
+                        return Ok(());
+                    }
+                    let stream = self.stream.take().ok_or_else(|| {
+                        Error::Custom("WebSocket stream not available".to_string())
+                    })?;
+                    let (reconnected, read) = stream.into_split();
+                    write = reconnected;
+                    self.close_code = None;
+                    heartbeat.reset();
+                    context.received.store(false, Ordering::SeqCst);
+                    read_task = spawn_reader(read, context.clone());
+                    replay_deadline = Some(Box::pin(tokio::time::sleep(REPLAY_WINDOW)));
+                    display_message("Reconnected");
+                    continue;
+                },
+                _ = deadline(&mut replay_deadline) => {
+                    replay_deadline = None;
+                    if !context.received.load(Ordering::SeqCst) {
+                        tracing::info!("No output replayed by the server, showing the local copy");
+                        context.output.lock().unwrap_or_else(|e| e.into_inner()).replay_local();
+                    }
+                    continue;
+                },
+                reason = shutdown::requested(&mut shutdown_rx) => break reason,
+                _ = tick(&mut ping_timer) => {
+                    if let Some(waiting) = heartbeat.check_stale() {
+                        tracing::warn!("No pong for {:?}", waiting);
+                        display_message(&format!(
+                            "Warning: connection stale, no answer from the server for {}s",
+                            waiting.as_secs()
+                        ));
+                    }
+                    if let Some(payload) = heartbeat.next_ping()
+                        && let Err(e) = write.send_message(TerminalMessage::Ping(payload)).await
+                    {
+                        // The read task ends with the connection and reports how, so a lost
+                        // connection is reconnected instead of ending the session here
+                        tracing::warn!("Failed to send ping: {}", e);
+                    }
+                    continue;
+                },
+                raw = recv(&mut raw_input) => {
+                    match raw {
+                        Some(RawInput::Data(data)) => {
+                            if let Err(e) = write.send_input(&data).await {
+                                tracing::error!("Failed to send input: {}", e);
+                                self.ended = Some(format!("connection error: {}", e));
+                                read_task.abort();
+                                return Ok(());
+                            }
+                            self.traffic.sent(data.len());
+                        },
+                        Some(RawInput::Quit) | None => shutdown::request(&shutdown_tx, ShutdownReason::Quit),
+                    }
+                    continue;
+                },
+                line = recv(&mut lines) => match line {
+                    Some(line) => line,
+                    None => {
+                        tracing::info!("End of input");
+                        shutdown::request(&shutdown_tx, ShutdownReason::Quit);
                         continue;
                     },
-                };
-                
-                // Check for quit command
-                if input == "/quit" {
-                    tracing::info!("Closing connection...");
-                    if let Err(e) = write.send(Message::Close(None)).await {
-                        tracing::error!("Failed to send close message: {}", e);
-                    }
-                    break;
+                },
+            };
+            
+            // Check for quit command
+            if input == "/quit" {
+                shutdown::request(&shutdown_tx, ShutdownReason::Quit);
+                continue;
+            }
+            
+            // Show the connection status and the server's health
+            if input == "/status" {
+                display_message(&format!("{}: {}", self.target.url, heartbeat.status()));
+                display_message(&self.server_health().await);
+                if let Some(predictor) = &predictor {
+                    let suspended = predictor
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .alternate_screen();
+                    display_message(if suspended {
+                        "Prediction off while a full-screen application runs"
+                    } else {
+                        "Prediction on"
+                    });
                 }
-                
-                // Check for empty input
-                if input.is_empty() {
+                continue;
+            }
+            
+            // Send a file as binary frames, written to the terminal byte for byte
+            if input == "/paste" || input.starts_with("/paste ") {
+                let path = input["/paste".len()..].trim();
+                if path.is_empty() {
+                    display_message("Usage: /paste <path>");
                     continue;
                 }
-                
-                // Send the message to the server
-                if let Err(e) = write.send(Message::Text(input.clone())).await {
-                    tracing::error!("Failed to send message: {}", e);
-                    break;
+                let data = match tokio::fs::read(path).await {
+                    Ok(data) => data,
+                    Err(e) => {
+                        display_message(&format!("Can't paste {}: {}", path, e));
+                        continue;
+                    }
+                };
+                for frame in data.chunks(PASTE_FRAME_BYTES) {
+                    if let Err(e) = write.send_binary(frame).await {
+                        tracing::error!("Failed to send pasted data: {}", e);
+                        self.ended = Some(format!("connection error: {}", e));
+                        read_task.abort();
+                        return Ok(());
+                    }
+                    self.traffic.sent(frame.len());
                 }
-                
-                tracing::info!("Sent message: {}", input);
+                tracing::info!("Pasted {} bytes from {}", data.len(), path);
+                display_message(&format!("Pasted {} bytes from {}", data.len(), path));
+                continue;
             }
-        });
+            
+            // Check for empty input
+            if input.is_empty() {
+                continue;
+            }
+            
+            // The terminal already shows the input, the server's echo of it is predicted
+            if let Some(predictor) = &predictor {
+                predictor.lock().unwrap_or_else(|e| e.into_inner()).input(&input);
+            }
+            
+            // Send the message to the server
+            if let Err(e) = write.send_input(&input).await {
+                tracing::error!("Failed to send message: {}", e);
+                self.ended = Some(format!("connection error: {}", e));
+                read_task.abort();
+                return Ok(());
+            }
+            self.traffic.sent(input.len());
+            
+            tracing::info!("Sent message: {}", input);
+        };
+        self.ended = Some(match reason {
+            ShutdownReason::Quit => "quit",
+            ShutdownReason::Interrupt => "interrupted",
+            ShutdownReason::Terminate => "terminated",
+        }.to_string());
         
-        // Wait for either task to complete
-        tokio::select! {
-            _ = read_task => tracing::info!("Read task completed"),
-            _ = write_task => tracing::info!("Write task completed"),
+        // Clean shutdown: send a close frame and give the server a moment to answer it
+        // (the read task ends when the server's close frame arrives)
+        display_message("");
+        tracing::info!("Closing connection...");
+        if let Err(e) = write.close().await {
+            tracing::error!("Failed to send close message: {}", e);
+        }
+        match tokio::time::timeout(CLOSE_TIMEOUT, &mut read_task).await {
+            Ok(Ok(Ok(Some(close)))) => self.close_code = Some(close.code),
+            Ok(_) => {},
+            Err(_) => {
+                tracing::warn!("Server did not answer the close frame in time");
+                read_task.abort();
+            },
         }
         
         Ok(())
     }
+    
+    /// Summarize the session: duration, traffic and how it ended (`error` is what `run` returned)
+    pub fn summary(&self, error: Option<&Error>) -> Summary {
+        let ended = match (&self.ended, error) {
+            (Some(ended), _) => ended.clone(),
+            (None, Some(e)) => format!("error: {}", e),
+            (None, None) => "closed".to_string(),
+        };
+        let duration = self.connected_at.map(|at| at.elapsed()).unwrap_or_default();
+        Summary::new(self.session_id.clone(), &self.target.url, duration, &self.traffic, self.close_code, ended)
+    }
+}
+
+/// Describe the server's close frame, e.g. `closed by server (code 1009: Message too big)`
+fn describe_close(close: &CloseReason) -> String {
+    if close.reason.is_empty() {
+        format!("closed by server (code {})", close.code)
+    } else {
+        format!("closed by server (code {}: {})", close.code, close.reason)
+    }
+}
+
+/// Describe the server's health, e.g. `Server: rs_terminal 0.1.0, 3 sessions running, draining`
+fn describe_health(health: &HealthResponse) -> String {
+    let version = health.version.as_deref().unwrap_or("unknown version");
+    let mut line = format!(
+        "Server: rs_terminal {}, {} session{} running",
+        version,
+        health.sessions,
+        if health.sessions == 1 { "" } else { "s" }
+    );
+    if health.draining {
+        line.push_str(", draining (no new sessions)");
+    }
+    if health.degraded {
+        line.push_str(", degraded (WebTransport unavailable)");
+    }
+    line
+}
+
+/// Read messages from the server until the connection ends
+fn spawn_reader(mut read: TerminalWsReader, context: ReadContext) -> JoinHandle<ReadEnd> {
+    tokio::spawn(async move {
+        loop {
+            let Some(msg) = read.recv_output().await else {
+                return ReadEnd::Ok(read.close_reason().cloned());
+            };
+            match msg {
+                Ok(TerminalOutput::Text(text)) => {
+                    tracing::info!("Received from server: {}", text);
+                    context.traffic.received(text.len());
+                    context.received.store(true, Ordering::SeqCst);
+                    let shown = match &context.predictor {
+                        Some(predictor) => predictor
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .output(&text),
+                        None => text.clone(),
+                    };
+                    context.output.lock().unwrap_or_else(|e| e.into_inner()).received(&text, &shown);
+                },
+                Ok(TerminalOutput::Binary(bin)) => {
+                    tracing::debug!("Received binary message, length: {}", bin.len());
+                    context.traffic.received(bin.len());
+                    display_message(&format!("Received binary data: {} bytes", bin.len()));
+                },
+                Ok(TerminalOutput::Pong(payload)) => {
+                    if context.pongs.pong(&payload) == PongOutcome::Recovered {
+                        display_message("Connection alive again");
+                    }
+                },
+                Ok(TerminalOutput::Envelope(envelope)) => {
+                    // Raw connections carry no envelopes
+                    tracing::debug!("Ignoring {} message", envelope.type_name());
+                },
+                Err(e) => {
+                    tracing::error!("WebSocket error: {}", e);
+                    return ReadEnd::Err(e.to_string());
+                },
+            }
+        }
+    })
+}
+
+/// Wait until an optional deadline passes (never resolves without one)
+async fn deadline(sleep: &mut Option<Pin<Box<tokio::time::Sleep>>>) {
+    match sleep {
+        Some(sleep) => sleep.as_mut().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Wait for the next tick of an optional timer (never resolves without one)
+async fn tick(timer: &mut Option<tokio::time::Interval>) {
+    match timer {
+        Some(timer) => {
+            timer.tick().await;
+        },
+        None => std::future::pending().await,
+    }
+}
+
+/// Receive from an optional channel (never resolves without one)
+async fn recv<T>(receiver: &mut Option<tokio::sync::mpsc::Receiver<T>>) -> Option<T> {
+    match receiver {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Describe the server: version, shells and the default size
+fn server_banner(info: &ServerInfo) -> String {
+    let version = info.version.as_deref().unwrap_or("unknown version");
+    let shells: Vec<&str> = info.shells.iter().map(|shell| shell.name.as_str()).collect();
+    let mut banner = format!(
+        "Server: rs_terminal {}\nShells: {} (default: {})",
+        version,
+        shells.join(", "),
+        info.default_shell
+    );
+    if let Some((columns, rows)) = info.default_size() {
+        banner.push_str(&format!("\nDefault size: {}x{}", columns, rows));
+    }
+    banner
 }
 
 impl Drop for WebSocketClient {
@@ -158,3 +605,380 @@ impl Drop for WebSocketClient {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::{SinkExt, StreamExt};
+    use terminal_client::dto::ShellInfo;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
+    use tokio_tungstenite::tungstenite::Message;
+    use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+    use crate::output::{LOCAL_COPY_DIVIDER, RECONNECTED_DIVIDER};
+    
+    /// Screen writing into a buffer the test reads
+    #[derive(Clone, Default)]
+    struct Screen(Arc<Mutex<Vec<u8>>>);
+    
+    impl Write for Screen {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    
+    impl Screen {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+    
+    /// Run the client against a stub server: the first connection sends `hello` and breaks
+    /// without a close frame, the second sends `replay` (if any), waits past the replay window
+    /// and closes normally
+    /// Returns the screen and the output log
+    async fn reconnect_with(replay: Option<&'static str>) -> (String, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = accept_async(stream).await.unwrap();
+            ws.send(Message::Text("hello\r\n".to_string())).await.unwrap();
+            ws.flush().await.unwrap();
+            // Dropping the connection without a close frame, as a network failure does
+            drop(ws);
+            
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = accept_async(stream).await.unwrap();
+            if let Some(replay) = replay {
+                ws.send(Message::Text(replay.to_string())).await.unwrap();
+            }
+            tokio::time::sleep(REPLAY_WINDOW * 2).await;
+            ws.close(Some(CloseFrame {
+                code: CloseCode::Normal,
+                reason: "".into(),
+            })).await.unwrap();
+            while ws.next().await.is_some() {}
+        });
+        
+        let log = std::env::temp_dir().join(format!(
+            "rust-websocket-client-{}-{}.log",
+            std::process::id(),
+            replay.is_some()
+        ));
+        let _ = std::fs::remove_file(&log);
+        let target = Target {
+            url,
+            token: None,
+            shell: None,
+        };
+        let options = Options {
+            log_output: Some(log.clone()),
+            ..Options::default()
+        };
+        let mut client = WebSocketClient::new(target, ConnectionConfig::default(), options).await.unwrap();
+        let screen = Screen::default();
+        client.screen = Some(Box::new(screen.clone()));
+        // No input, but the input doesn't end either
+        let (_input_tx, input_rx) = mpsc::channel(1);
+        client.lines = Some(input_rx);
+        
+        tokio::time::timeout(Duration::from_secs(20), client.run()).await.unwrap().unwrap();
+        server.await.unwrap();
+        assert_eq!(client.summary(None).ended, "closed by server (code 1000)");
+        
+        let logged = std::fs::read_to_string(&log).unwrap();
+        let _ = std::fs::remove_file(&log);
+        (screen.text(), logged)
+    }
+
+    /// Run the client against a stub server with the input `lines`, until it quits (at the end
+    /// of the input at the latest), returning what the client reported as the end of the session
+    /// and the frames the server received
+    async fn quit_with(lines: &[&str]) -> (String, Vec<Message>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = accept_async(stream).await.unwrap();
+            ws.send(Message::Text("hello\r\n".to_string()))
+                .await
+                .unwrap();
+            // Answering the close frame ends the stream; heartbeat pings depend on timing
+            let mut received = Vec::new();
+            while let Some(Ok(message)) = ws.next().await {
+                if !matches!(message, Message::Ping(_)) {
+                    received.push(message);
+                }
+            }
+            received
+        });
+
+        let target = Target {
+            url,
+            token: None,
+            shell: None,
+        };
+        let mut client =
+            WebSocketClient::new(target, ConnectionConfig::default(), Options::default())
+                .await
+                .unwrap();
+        client.screen = Some(Box::new(Screen::default()));
+        let (input_tx, input_rx) = mpsc::channel(lines.len().max(1));
+        client.lines = Some(input_rx);
+        for line in lines {
+            input_tx.send(line.to_string()).await.unwrap();
+        }
+        // End of input
+        drop(input_tx);
+
+        tokio::time::timeout(Duration::from_secs(10), client.run())
+            .await
+            .unwrap()
+            .unwrap();
+        let received = tokio::time::timeout(Duration::from_secs(10), server)
+            .await
+            .unwrap()
+            .unwrap();
+        (client.summary(None).ended, received)
+    }
+
+    #[tokio::test]
+    async fn quit_sends_a_close_frame() {
+        let (ended, received) = quit_with(&["/quit"]).await;
+        assert_eq!(ended, "quit");
+        // `/quit` itself isn't sent, the close frame is the only frame
+        assert_eq!(received, [Message::Close(None)]);
+    }
+
+    #[tokio::test]
+    async fn end_of_input_sends_a_close_frame() {
+        let (ended, received) = quit_with(&[]).await;
+        assert_eq!(ended, "quit");
+        assert_eq!(received, [Message::Close(None)]);
+    }
+
+    #[tokio::test]
+    async fn paste_sends_the_file_as_binary_frames() {
+        // Not UTF-8, and more than two frames
+        let data: Vec<u8> = (0..PASTE_FRAME_BYTES * 2 + 10)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let path = std::env::temp_dir().join(format!(
+            "rust-websocket-client-{}-paste.bin",
+            std::process::id()
+        ));
+        std::fs::write(&path, &data).unwrap();
+        let paste = format!("/paste {}", path.display());
+
+        let (ended, received) = quit_with(&[&paste, "/quit"]).await;
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(ended, "quit");
+        let frames: Vec<Message> = data
+            .chunks(PASTE_FRAME_BYTES)
+            .map(|frame| Message::Binary(frame.to_vec()))
+            .chain([Message::Close(None)])
+            .collect();
+        assert_eq!(received, frames);
+    }
+
+    #[tokio::test]
+    async fn paste_sends_nothing_without_a_readable_file() {
+        let (ended, received) = quit_with(&[
+            "/paste",
+            "/paste /nonexistent/rust-websocket-client",
+            "/quit",
+        ])
+        .await;
+        assert_eq!(ended, "quit");
+        assert_eq!(received, [Message::Close(None)]);
+    }
+
+    #[tokio::test]
+    async fn reconnect_shows_the_local_copy() {
+        let (screen, logged) = reconnect_with(None).await;
+        let copy = format!(
+            "hello\r\n\n\r\n{}\r\nhello\r\n{}\r\n",
+            LOCAL_COPY_DIVIDER, RECONNECTED_DIVIDER
+        );
+        assert_eq!(screen, copy);
+        // The local copy isn't logged again
+        assert_eq!(logged, "hello\r\n");
+    }
+    
+    #[tokio::test]
+    async fn replayed_output_replaces_the_local_copy() {
+        let (screen, logged) = reconnect_with(Some("replayed\r\n")).await;
+        assert!(!screen.contains(LOCAL_COPY_DIVIDER), "{:?}", screen);
+        assert_eq!(screen, "hello\r\n\nreplayed\r\n\n");
+        assert_eq!(logged, "hello\r\nreplayed\r\n");
+    }
+
+    fn shell(name: &str, columns: u16, rows: u16) -> ShellInfo {
+        ShellInfo {
+            name: name.to_string(),
+            columns,
+            rows,
+        }
+    }
+
+    #[test]
+    fn banner_shows_the_version_shells_and_default_size() {
+        let info = ServerInfo {
+            version: Some("0.1.0".to_string()),
+            default_shell: "sh".to_string(),
+            shells: vec![shell("bash", 120, 40), shell("sh", 80, 24)],
+        };
+        assert_eq!(
+            server_banner(&info),
+            "Server: rs_terminal 0.1.0\nShells: bash, sh (default: sh)\nDefault size: 80x24"
+        );
+
+        // An older server: no version, and a default shell it doesn't list
+        let info = ServerInfo {
+            version: None,
+            default_shell: "zsh".to_string(),
+            shells: vec![shell("bash", 120, 40)],
+        };
+        assert_eq!(
+            server_banner(&info),
+            "Server: rs_terminal unknown version\nShells: bash (default: zsh)"
+        );
+    }
+
+    /// Health as the server reports it, with `sessions` running
+    fn health(sessions: usize, version: Option<&str>) -> HealthResponse {
+        HealthResponse {
+            success: true,
+            message: "Server is healthy".to_string(),
+            draining: false,
+            degraded: false,
+            sessions,
+            version: version.map(str::to_string),
+        }
+    }
+
+    /// Ask a stub server answering `GET /health` with `body` for its health through `/status`,
+    /// returning the line shown and the request the server got
+    async fn server_health_from(body: String, token: Option<&str>) -> (String, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).await.unwrap();
+                assert!(n > 0, "the request ended early");
+                request.extend_from_slice(&buf[..n]);
+            }
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let target = Target {
+            url,
+            token: token.map(str::to_string),
+            shell: None,
+        };
+        let client = WebSocketClient::new(target, ConnectionConfig::default(), Options::default())
+            .await
+            .unwrap();
+        let shown = client.server_health().await;
+        (shown, server.await.unwrap())
+    }
+
+    #[test]
+    fn health_shows_the_version_and_running_sessions() {
+        assert_eq!(
+            describe_health(&health(3, Some("0.1.0"))),
+            "Server: rs_terminal 0.1.0, 3 sessions running"
+        );
+        assert_eq!(
+            describe_health(&health(1, None)),
+            "Server: rs_terminal unknown version, 1 session running"
+        );
+        let unavailable = HealthResponse {
+            draining: true,
+            degraded: true,
+            ..health(0, Some("0.1.0"))
+        };
+        assert_eq!(
+            describe_health(&unavailable),
+            "Server: rs_terminal 0.1.0, 0 sessions running, draining (no new sessions), \
+             degraded (WebTransport unavailable)"
+        );
+    }
+
+    #[tokio::test]
+    async fn status_asks_the_server_for_its_health() {
+        let body = serde_json::to_string(&health(2, Some("0.1.0"))).unwrap();
+        let (shown, request) = server_health_from(body, Some("secret")).await;
+        assert_eq!(shown, "Server: rs_terminal 0.1.0, 2 sessions running");
+        let request = request.to_ascii_lowercase();
+        assert!(
+            request.starts_with("get /health http/1.1\r\n"),
+            "{}",
+            request
+        );
+        assert!(
+            request.contains("\r\nauthorization: bearer secret\r\n"),
+            "{}",
+            request
+        );
+    }
+
+    #[tokio::test]
+    async fn status_reports_health_it_cannot_get() {
+        let (shown, _) = server_health_from("not json".to_string(), None).await;
+        assert!(
+            shown.starts_with("Server health unavailable: "),
+            "{}",
+            shown
+        );
+
+        // Nothing listens on the port anymore
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+        drop(listener);
+        let target = Target {
+            url,
+            token: None,
+            shell: None,
+        };
+        let client = WebSocketClient::new(target, ConnectionConfig::default(), Options::default())
+            .await
+            .unwrap();
+        let shown = client.server_health().await;
+        assert!(
+            shown.starts_with("Server health unavailable: "),
+            "{}",
+            shown
+        );
+
+        let target = Target {
+            url: "unix:/run/terminal.sock".to_string(),
+            token: None,
+            shell: None,
+        };
+        let client = WebSocketClient::new(target, ConnectionConfig::default(), Options::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            client.server_health().await,
+            "Server health: no REST API for unix:/run/terminal.sock"
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use std::time::Duration;
+
+use reqwest::{Client, Method, RequestBuilder, Response, Url};
+use serde::de::DeserializeOwned;
+use terminal_types::dto::{
+    CreateSessionRequest, ErrorResponse, HealthResponse, ResizeTerminalRequest, ShellInfo,
+    ShellsResponse, TerminalResizeResponse, TerminalSession, TerminalTerminateResponse,
+    UpdateSessionRequest,
+};
+
+use crate::error::{Error, Result};
+
+/// What a server reports about itself, see [`probe_server`]
+#[derive(Debug, Clone)]
+pub struct ServerInfo {
+    /// Server version (`None` for servers that don't report it)
+    pub version: Option<String>,
+    /// Shell type used when a session doesn't name one
+    pub default_shell: String,
+    /// Configured shells with the size new sessions start with
+    pub shells: Vec<ShellInfo>,
+}
+
+impl ServerInfo {
+    /// Size (columns, rows) a session of the default shell starts with
+    pub fn default_size(&self) -> Option<(u16, u16)> {
+        self.shells
+            .iter()
+            .find(|shell| shell.name == self.default_shell)
+            .map(|shell| (shell.columns, shell.rows))
+    }
+}
+
+/// Client for the session management REST API (`/api/sessions`)
+#[derive(Debug, Clone)]
+pub struct TerminalApiClient {
+    /// Server base URL, e.g. `http://localhost:8080`
+    base_url: String,
+    /// Shared HTTP client
+    http: Client,
+    /// Token sent as `Authorization: Bearer` with every request
+    token: Option<String>,
+}
+
+impl TerminalApiClient {
+    /// Create a client for the server at `base_url`
+    pub fn new(base_url: &str) -> Self {
+        Self::with_http_client(base_url, Client::new())
+    }
+
+    /// Create a client reusing an existing reqwest client (proxies, timeouts, TLS settings)
+    pub fn with_http_client(base_url: &str, http: Client) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            http,
+            token: None,
+        }
+    }
+
+    /// Authenticate every request with a bearer token
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Get the server base URL
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Check the server's health (also reports the server version)
+    pub async fn health(&self) -> Result<HealthResponse> {
+        let response = self.request(Method::GET, "/health").send().await?;
+        Self::parse(response).await
+    }
+
+    /// List the configured shells and the default shell
+    pub async fn list_shells(&self) -> Result<ShellsResponse> {
+        let response = self.request(Method::GET, "/api/shells").send().await?;
+        Self::parse(response).await
+    }
+
+    /// Create a new terminal session
+    pub async fn create_session(&self, request: &CreateSessionRequest) -> Result<TerminalSession> {
+        let response = self
+            .request(Method::POST, "/api/sessions")
+            .json(request)
+            .send()
+            .await?;
+        Self::parse(response).await
+    }
+
+    /// List all terminal sessions
+    pub async fn list_sessions(&self) -> Result<Vec<TerminalSession>> {
+        let response = self.request(Method::GET, "/api/sessions").send().await?;
+        Self::parse(response).await
+    }
+
+    /// Get a specific terminal session
+    pub async fn get_session(&self, session_id: &str) -> Result<TerminalSession> {
+        let response = self
+            .request(Method::GET, &format!("/api/sessions/{}", session_id))
+            .send()
+            .await?;
+        Self::parse(response).await
+    }
+
+    /// Change the title of a terminal session
+    pub async fn set_session_title(
+        &self,
+        session_id: &str,
+        title: &str,
+    ) -> Result<TerminalSession> {
+        let response = self
+            .request(Method::PATCH, &format!("/api/sessions/{}", session_id))
+            .json(&UpdateSessionRequest {
+                title: title.to_string(),
+            })
+            .send()
+            .await?;
+        Self::parse(response).await
+    }
+
+    /// Resize a terminal session
+    pub async fn resize_session(
+        &self,
+        session_id: &str,
+        columns: u16,
+        rows: u16,
+    ) -> Result<TerminalResizeResponse> {
+        let response = self
+            .request(
+                Method::POST,
+                &format!("/api/sessions/{}/resize", session_id),
+            )
+            .json(&ResizeTerminalRequest { columns, rows })
+            .send()
+            .await?;
+        Self::parse(response).await
+    }
+
+    /// Terminate a terminal session
+    pub async fn terminate_session(&self, session_id: &str) -> Result<TerminalTerminateResponse> {
+        let response = self
+            .request(Method::DELETE, &format!("/api/sessions/{}", session_id))
+            .send()
+            .await?;
+        Self::parse(response).await
+    }
+
+    /// Start a request to an API path, with the token if there is one
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self
+            .http
+            .request(method, format!("{}{}", self.base_url, path));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// Decode a successful response, or turn the server's error response into [`Error::Api`]
+    async fn parse<T: DeserializeOwned>(response: Response) -> Result<T> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response.json().await?);
+        }
+
+        let body = response.text().await?;
+        let message = error_message(&body).unwrap_or(body);
+        Err(Error::Api {
+            status: status.as_u16(),
+            message,
+        })
+    }
+}
+
+/// Ask the server behind a WebSocket URL for its version and shells through its REST API
+/// Each request gives up after `timeout`
+pub async fn probe_server(ws_url: &str, timeout: Duration) -> Result<ServerInfo> {
+    let origin = http_origin(ws_url)
+        .ok_or_else(|| Error::InvalidUrl(format!("no HTTP origin for `{}`", ws_url)))?;
+    let http = Client::builder().timeout(timeout).build()?;
+    let api = TerminalApiClient::with_http_client(&origin, http);
+
+    let health = api.health().await?;
+    let shells = api.list_shells().await?;
+    Ok(ServerInfo {
+        version: health.version,
+        default_shell: shells.default_shell,
+        shells: shells.shells,
+    })
+}
+
+/// HTTP origin serving the REST API for a WebSocket URL
+/// (`ws://host:8080/ws/abc` gives `http://host:8080`, `wss` gives `https`)
+/// `None` unless the URL is a `ws`, `wss`, `http` or `https` URL with a host
+pub fn http_origin(ws_url: &str) -> Option<String> {
+    let url = Url::parse(ws_url).ok()?;
+    let scheme = match url.scheme() {
+        "ws" | "http" => "http",
+        "wss" | "https" => "https",
+        _ => return None,
+    };
+    let host = url.host_str()?;
+    Some(match url.port() {
+        Some(port) => format!("{}://{}:{}", scheme, host, port),
+        None => format!("{}://{}", scheme, host),
+    })
+}
+
+/// Extract the message from an [`ErrorResponse`] body
+fn error_message(body: &str) -> Option<String> {
+    serde_json::from_str::<ErrorResponse>(body)
+        .ok()
+        .map(|error| error.message)
+}
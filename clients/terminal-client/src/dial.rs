@@ -0,0 +1,125 @@
+//! Connecting to hosts with several addresses
+//!
+//! A hostname may resolve to IPv6 and IPv4 addresses of which only some are reachable. Following
+//! RFC 8305 ("Happy Eyeballs"), the addresses are tried in turn with a short stagger instead of
+//! waiting for each one to time out, and the first connection wins.
+
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use futures_util::stream::FuturesUnordered;
+use tokio::net::TcpStream;
+
+use crate::error::{Error, Result};
+
+/// Time after which the next address is tried while earlier attempts are still pending
+/// (RFC 8305's recommended "Connection Attempt Delay")
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Resolve `host` and connect to the first of its addresses that answers
+/// The host is resolved on every call, so a reconnect never sticks to a dead address
+pub async fn connect_tcp(host: &str, port: u16) -> Result<TcpStream> {
+    // IPv6 literals keep their brackets in URLs
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|source| Error::Resolve {
+            host: host.to_string(),
+            source,
+        })?
+        .collect();
+    let addrs = interleave_families(addrs);
+    tracing::debug!("{} resolved to {:?}", host, addrs);
+
+    race(&addrs, CONNECTION_ATTEMPT_DELAY, TcpStream::connect)
+        .await
+        .map_err(|failures| Error::Connect {
+            host: format!("{}:{}", host, port),
+            failures,
+        })
+}
+
+/// Order addresses as RFC 8305 asks: alternate between IPv6 and IPv4, starting with the family
+/// of the first address the resolver returned, keeping the resolver's order within each family
+pub fn interleave_families(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first) = addrs.first() else {
+        return addrs;
+    };
+    let first_is_ipv6 = first.is_ipv6();
+    let (mut preferred, mut other): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == first_is_ipv6);
+    let mut ordered = Vec::with_capacity(preferred.len() + other.len());
+    let mut preferred = preferred.drain(..);
+    let mut other = other.drain(..);
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => break,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+    ordered
+}
+
+/// Try `connect` on each address in order, starting the next attempt `delay` after the previous
+/// one or as soon as it fails; the first success wins and the attempts still pending are dropped
+/// Fails with every address and its error once all attempts failed
+pub async fn race<T, C, F>(
+    addrs: &[SocketAddr],
+    delay: Duration,
+    connect: C,
+) -> std::result::Result<T, Vec<(SocketAddr, io::Error)>>
+where
+    C: Fn(SocketAddr) -> F,
+    F: Future<Output = io::Result<T>>,
+{
+    let attempt = |addr: SocketAddr| {
+        let connecting = connect(addr);
+        async move { (addr, connecting.await) }
+    };
+    let mut remaining = addrs.iter().copied();
+    let mut attempts = FuturesUnordered::new();
+    let mut failures = Vec::new();
+    match remaining.next() {
+        Some(addr) => attempts.push(attempt(addr)),
+        None => return Err(failures),
+    }
+
+    loop {
+        tokio::select! {
+            Some((addr, result)) = attempts.next() => match result {
+                Ok(connection) => return Ok(connection),
+                Err(e) => {
+                    tracing::debug!("Connecting to {} failed: {}", addr, e);
+                    failures.push((addr, e));
+                    match remaining.next() {
+                        Some(next) => attempts.push(attempt(next)),
+                        None if attempts.is_empty() => return Err(failures),
+                        None => {}
+                    }
+                }
+            },
+            _ = tokio::time::sleep(delay), if remaining.len() > 0 => {
+                if let Some(next) = remaining.next() {
+                    tracing::debug!("No answer within {:?}, also trying {}", delay, next);
+                    attempts.push(attempt(next));
+                }
+            }
+        }
+    }
+}
+
+/// `[::1]:8080: connection refused; 127.0.0.1:8080: timed out`
+pub(crate) fn describe_failures(failures: &[(SocketAddr, io::Error)]) -> String {
+    if failures.is_empty() {
+        return "no addresses".to_string();
+    }
+    failures
+        .iter()
+        .map(|(addr, e)| format!("{}: {}", addr, e))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
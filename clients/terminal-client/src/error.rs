@@ -0,0 +1,60 @@
+use thiserror::Error;
+use tokio_tungstenite::tungstenite::Error as TungsteniteError;
+
+/// Result type alias with the client Error
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Errors returned by the terminal client
+#[derive(Error, Debug)]
+pub enum Error {
+    /// HTTP transport or response decoding error
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The server answered with an error response
+    #[error("API error ({status}): {message}")]
+    Api {
+        /// HTTP status code
+        status: u16,
+        /// Message from the server's error response
+        message: String,
+    },
+
+    /// WebSocket connection or protocol error
+    #[error("WebSocket error: {0}")]
+    WebSocket(#[from] TungsteniteError),
+
+    /// Invalid URL error
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+
+    /// The server's hostname could not be resolved
+    #[error("Failed to resolve {host}: {source}")]
+    Resolve {
+        /// Hostname
+        host: String,
+        /// Resolver error
+        source: std::io::Error,
+    },
+
+    /// None of the server's addresses accepted a connection
+    #[error("Failed to connect to {host}: {}", crate::dial::describe_failures(.failures))]
+    Connect {
+        /// Host and port
+        host: String,
+        /// Every address attempted, with its error
+        failures: Vec<(std::net::SocketAddr, std::io::Error)>,
+    },
+
+    /// Token that can't be sent in the `Authorization` header
+    #[error("Invalid token: {0}")]
+    InvalidToken(String),
+
+    /// Protocol message this client version can't send
+    #[error("Unsupported message: {0}")]
+    Unsupported(String),
+
+    /// `waylon-terminal-v1` message that couldn't be encoded or decoded
+    #[error("Malformed message: {0}")]
+    Json(#[from] serde_json::Error),
+}
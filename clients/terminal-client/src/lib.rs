@@ -0,0 +1,16 @@
+//! Typed client for the rs_terminal server
+//!
+//! [`TerminalApiClient`] wraps the REST session management API and
+//! [`TerminalWsSession`] wraps the WebSocket terminal stream. [`probe_server`] asks a server
+//! for its version and shells before connecting. WebSocket connections race the addresses of the
+//! server's hostname, see [`dial`].
+
+mod api;
+pub mod dial;
+mod error;
+mod ws;
+
+pub use api::{ServerInfo, TerminalApiClient, http_origin, probe_server};
+pub use error::{Error, Result};
+pub use terminal_types::{dto, protocol};
+pub use ws::{CloseReason, TerminalOutput, TerminalWsReader, TerminalWsSession, TerminalWsWriter};
@@ -0,0 +1,286 @@
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::http::header::{AUTHORIZATION, SEC_WEBSOCKET_PROTOCOL};
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, client_async, connect_async};
+
+use crate::dial::connect_tcp;
+use crate::error::{Error, Result};
+use terminal_types::protocol::{ClientEnvelope, ServerEnvelope, Subprotocol, TerminalMessage};
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Output received from the terminal
+#[derive(Debug, Clone, PartialEq)]
+pub enum TerminalOutput {
+    /// Terminal output sent as text
+    Text(String),
+    /// Terminal output sent as binary data
+    Binary(Vec<u8>),
+    /// Answer to a ping sent with [`TerminalMessage::Ping`], carrying the ping's payload
+    Pong(Vec<u8>),
+    /// `waylon-terminal-v1` message other than terminal output (notices, errors, acks, ...)
+    Envelope(ServerEnvelope),
+}
+
+/// A WebSocket connection to a terminal session
+///
+/// Input, output and resizes are encoded for the subprotocol the server selected. Raw connections
+/// can't resize in-band, use [`crate::TerminalApiClient::resize_session`] for them.
+pub struct TerminalWsSession {
+    writer: TerminalWsWriter,
+    reader: TerminalWsReader,
+}
+
+impl TerminalWsSession {
+    /// Connect to a WebSocket URL such as `ws://localhost:8080/ws` (which starts a new session)
+    pub async fn connect(url: &str) -> Result<Self> {
+        Self::connect_with_token(url, None).await
+    }
+
+    /// Connect like [`Self::connect`], sending `token` as `Authorization: Bearer` in the handshake
+    pub async fn connect_with_token(url: &str, token: Option<&str>) -> Result<Self> {
+        Self::connect_with_protocol(url, token, None).await
+    }
+
+    /// Connect like [`Self::connect_with_token`], offering `subprotocol` to the server
+    /// Without an offer, or when the server selects none, the connection uses raw mode
+    pub async fn connect_with_protocol(
+        url: &str,
+        token: Option<&str>,
+        subprotocol: Option<Subprotocol>,
+    ) -> Result<Self> {
+        tracing::info!("Connecting to WebSocket server at: {}", url);
+
+        let mut request = url
+            .into_client_request()
+            .map_err(|e| Error::InvalidUrl(e.to_string()))?;
+        if let Some(token) = token {
+            let value = HeaderValue::from_str(&format!("Bearer {}", token))
+                .map_err(|_| Error::InvalidToken("not a valid header value".to_string()))?;
+            request.headers_mut().insert(AUTHORIZATION, value);
+        }
+        if let Some(subprotocol) = subprotocol {
+            request.headers_mut().insert(
+                SEC_WEBSOCKET_PROTOCOL,
+                HeaderValue::from_static(subprotocol.name()),
+            );
+        }
+        // Plain connections race the server's addresses; TLS is left to tungstenite's own connect
+        let (stream, response) = match (request.uri().scheme_str(), request.uri().host()) {
+            (Some("ws"), Some(host)) => {
+                let port = request.uri().port_u16().unwrap_or(80);
+                let tcp = connect_tcp(host, port).await?;
+                client_async(request, MaybeTlsStream::Plain(tcp)).await?
+            }
+            _ => connect_async(request).await?,
+        };
+
+        tracing::info!(
+            "Connected to server! Response status: {:?}",
+            response.status()
+        );
+        tracing::debug!("Response headers: {:?}", response.headers());
+        let subprotocol = response
+            .headers()
+            .get(SEC_WEBSOCKET_PROTOCOL)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Subprotocol::from_name)
+            .unwrap_or(Subprotocol::Raw);
+
+        let (sink, stream) = stream.split();
+        Ok(Self {
+            writer: TerminalWsWriter { sink, subprotocol },
+            reader: TerminalWsReader {
+                stream,
+                subprotocol,
+                close_reason: None,
+            },
+        })
+    }
+
+    /// Subprotocol the server selected
+    pub fn subprotocol(&self) -> Subprotocol {
+        self.writer.subprotocol
+    }
+
+    /// Connect to an existing session on the server at `server_url` (e.g. `ws://localhost:8080`)
+    pub async fn connect_session(server_url: &str, session_id: &str) -> Result<Self> {
+        let url = format!("{}/ws/{}", server_url.trim_end_matches('/'), session_id);
+        Self::connect(&url).await
+    }
+
+    /// Send input to the terminal
+    pub async fn send_input(&mut self, input: &str) -> Result<()> {
+        self.writer.send_input(input).await
+    }
+
+    /// Send raw bytes to the terminal
+    pub async fn send_binary(&mut self, data: &[u8]) -> Result<()> {
+        self.writer.send_binary(data).await
+    }
+
+    /// Send a protocol message
+    pub async fn send_message(&mut self, message: TerminalMessage) -> Result<()> {
+        self.writer.send_message(message).await
+    }
+
+    /// Resize the terminal (needs the `waylon-terminal-v1` subprotocol)
+    pub async fn resize(&mut self, columns: u16, rows: u16) -> Result<()> {
+        self.writer.resize(columns, rows).await
+    }
+
+    /// Receive the next terminal output, `None` once the connection is closed
+    pub async fn recv_output(&mut self) -> Option<Result<TerminalOutput>> {
+        self.reader.recv_output().await
+    }
+
+    /// Close the connection
+    pub async fn close(&mut self) -> Result<()> {
+        self.writer.close().await
+    }
+
+    /// Split into halves that can be used from separate tasks
+    pub fn into_split(self) -> (TerminalWsWriter, TerminalWsReader) {
+        (self.writer, self.reader)
+    }
+}
+
+/// Sending half of a [`TerminalWsSession`]
+pub struct TerminalWsWriter {
+    sink: SplitSink<WsStream, Message>,
+    subprotocol: Subprotocol,
+}
+
+impl TerminalWsWriter {
+    /// Send input to the terminal
+    pub async fn send_input(&mut self, input: &str) -> Result<()> {
+        if self.subprotocol == Subprotocol::V1 {
+            return self
+                .send_envelope(&ClientEnvelope::Input {
+                    data: input.to_string(),
+                    id: None,
+                })
+                .await;
+        }
+        self.send_message(TerminalMessage::Text(input.to_string()))
+            .await
+    }
+
+    /// Resize the terminal (needs the `waylon-terminal-v1` subprotocol)
+    pub async fn resize(&mut self, columns: u16, rows: u16) -> Result<()> {
+        if self.subprotocol != Subprotocol::V1 {
+            return Err(Error::Unsupported(format!(
+                "resize on a {} connection, use TerminalApiClient::resize_session",
+                self.subprotocol.name()
+            )));
+        }
+        self.send_envelope(&ClientEnvelope::Resize { columns, rows })
+            .await
+    }
+
+    /// Send a `waylon-terminal-v1` envelope as a text frame
+    async fn send_envelope(&mut self, envelope: &ClientEnvelope) -> Result<()> {
+        let text = serde_json::to_string(envelope)?;
+        self.sink.send(Message::Text(text)).await?;
+        Ok(())
+    }
+
+    /// Send raw bytes to the terminal
+    pub async fn send_binary(&mut self, data: &[u8]) -> Result<()> {
+        self.send_message(TerminalMessage::Binary(data.to_vec()))
+            .await
+    }
+
+    /// Send a protocol message
+    pub async fn send_message(&mut self, message: TerminalMessage) -> Result<()> {
+        let frame = match message {
+            TerminalMessage::Text(text) => Message::Text(text),
+            TerminalMessage::Binary(bin) => Message::Binary(bin),
+            TerminalMessage::Ping(data) => Message::Ping(data),
+            TerminalMessage::Pong(()) => Message::Pong(Vec::new()),
+            TerminalMessage::Close => Message::Close(None),
+            other => {
+                return Err(Error::Unsupported(format!("{:?}", other)));
+            }
+        };
+        self.sink.send(frame).await?;
+        Ok(())
+    }
+
+    /// Close the connection
+    pub async fn close(&mut self) -> Result<()> {
+        self.send_message(TerminalMessage::Close).await
+    }
+}
+
+/// Close code and reason the server sent with its close frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseReason {
+    /// WebSocket close code (1000 for a normal close)
+    pub code: u16,
+    /// Reason text, may be empty
+    pub reason: String,
+}
+
+/// Receiving half of a [`TerminalWsSession`]
+pub struct TerminalWsReader {
+    stream: SplitStream<WsStream>,
+    subprotocol: Subprotocol,
+    /// Close frame received from the server, if it had a code
+    close_reason: Option<CloseReason>,
+}
+
+impl TerminalWsReader {
+    /// Receive the next terminal output, `None` once the connection is closed
+    pub async fn recv_output(&mut self) -> Option<Result<TerminalOutput>> {
+        while let Some(msg) = self.stream.next().await {
+            match msg {
+                Ok(Message::Text(text)) if self.subprotocol == Subprotocol::V1 => {
+                    return Some(decode_envelope(&text).map_err(Error::from));
+                }
+                Ok(Message::Text(text)) => return Some(Ok(TerminalOutput::Text(text))),
+                Ok(Message::Binary(bin)) => return Some(Ok(TerminalOutput::Binary(bin))),
+                Ok(Message::Close(frame)) => {
+                    match frame {
+                        Some(frame) => {
+                            tracing::info!(
+                                "Received close frame: code={}, reason={}",
+                                frame.code,
+                                frame.reason
+                            );
+                            self.close_reason = Some(CloseReason {
+                                code: frame.code.into(),
+                                reason: frame.reason.into_owned(),
+                            });
+                        }
+                        None => tracing::info!("Received close frame"),
+                    }
+                    return None;
+                }
+                Ok(Message::Pong(payload)) => return Some(Ok(TerminalOutput::Pong(payload))),
+                Ok(Message::Ping(_)) | Ok(Message::Frame(_)) => {
+                    tracing::debug!("Received control frame");
+                }
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+        None
+    }
+
+    /// Code and reason of the server's close frame, once one with a code arrived
+    pub fn close_reason(&self) -> Option<&CloseReason> {
+        self.close_reason.as_ref()
+    }
+}
+
+/// Terminal output of a `waylon-terminal-v1` text frame, or the envelope it carries
+fn decode_envelope(text: &str) -> serde_json::Result<TerminalOutput> {
+    Ok(match serde_json::from_str(text)? {
+        ServerEnvelope::Output { data, .. } => TerminalOutput::Text(data),
+        envelope => TerminalOutput::Envelope(envelope),
+    })
+}
@@ -0,0 +1,250 @@
+//! Racing the addresses of a host against fake connectors on a paused clock: slow addresses
+//! don't hold up the next one, failures move on at once, and the error lists every address
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use terminal_client::Error;
+use terminal_client::dial::{CONNECTION_ATTEMPT_DELAY, interleave_families, race};
+use tokio::time::Instant;
+
+/// How a fake address answers
+#[derive(Clone, Copy)]
+enum Answer {
+    /// Accepts the connection after the delay
+    Accept(Duration),
+    /// Refuses the connection after the delay
+    Refuse(Duration),
+    /// Never answers
+    Hang,
+}
+
+/// Connector answering each address as configured, recording when each attempt started
+struct FakeConnector {
+    answers: Vec<(SocketAddr, Answer)>,
+    started: Instant,
+    attempts: Arc<Mutex<Vec<(SocketAddr, Duration)>>>,
+}
+
+impl FakeConnector {
+    fn new(answers: &[(&str, Answer)]) -> Self {
+        Self {
+            answers: answers
+                .iter()
+                .map(|(addr, answer)| (addr.parse().unwrap(), *answer))
+                .collect(),
+            started: Instant::now(),
+            attempts: Arc::default(),
+        }
+    }
+
+    fn addrs(&self) -> Vec<SocketAddr> {
+        self.answers.iter().map(|(addr, _)| *addr).collect()
+    }
+
+    /// "Connect" to `addr`, the connection being the address itself
+    async fn connect(&self, addr: SocketAddr) -> io::Result<SocketAddr> {
+        self.attempts
+            .lock()
+            .unwrap()
+            .push((addr, self.started.elapsed()));
+        let (_, answer) = self.answers.iter().find(|(a, _)| *a == addr).unwrap();
+        match *answer {
+            Answer::Accept(delay) => {
+                tokio::time::sleep(delay).await;
+                Ok(addr)
+            }
+            Answer::Refuse(delay) => {
+                tokio::time::sleep(delay).await;
+                Err(io::Error::from(io::ErrorKind::ConnectionRefused))
+            }
+            Answer::Hang => std::future::pending().await,
+        }
+    }
+
+    /// Addresses in the order they were tried, with the time each attempt started
+    fn attempts(&self) -> Vec<(String, u64)> {
+        self.attempts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(addr, at)| (addr.to_string(), at.as_millis() as u64))
+            .collect()
+    }
+}
+
+fn ms(millis: u64) -> Duration {
+    Duration::from_millis(millis)
+}
+
+#[tokio::test(start_paused = true)]
+async fn first_address_that_answers_wins() {
+    let fake = FakeConnector::new(&[
+        ("[2001:db8::1]:443", Answer::Accept(ms(50))),
+        ("192.0.2.1:443", Answer::Accept(ms(10))),
+    ]);
+
+    let connected = race(&fake.addrs(), CONNECTION_ATTEMPT_DELAY, |addr| {
+        fake.connect(addr)
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(connected.to_string(), "[2001:db8::1]:443");
+    // The first address answered within the delay, the second was never tried
+    assert_eq!(fake.attempts(), [("[2001:db8::1]:443".to_string(), 0)]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn slow_address_does_not_hold_up_the_next() {
+    let fake = FakeConnector::new(&[
+        ("[2001:db8::1]:443", Answer::Hang),
+        ("192.0.2.1:443", Answer::Accept(ms(100))),
+    ]);
+
+    let connected = race(&fake.addrs(), CONNECTION_ATTEMPT_DELAY, |addr| {
+        fake.connect(addr)
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(connected.to_string(), "192.0.2.1:443");
+    assert_eq!(
+        fake.attempts(),
+        [
+            ("[2001:db8::1]:443".to_string(), 0),
+            ("192.0.2.1:443".to_string(), 250),
+        ]
+    );
+    // Connected 100 ms after the second attempt started, not after an OS timeout
+    assert_eq!(fake.started.elapsed(), ms(350));
+}
+
+#[tokio::test(start_paused = true)]
+async fn failed_address_moves_on_at_once() {
+    let fake = FakeConnector::new(&[
+        ("[2001:db8::1]:443", Answer::Refuse(ms(10))),
+        ("192.0.2.1:443", Answer::Hang),
+        ("[2001:db8::2]:443", Answer::Refuse(ms(20))),
+        ("192.0.2.2:443", Answer::Accept(ms(30))),
+    ]);
+
+    let connected = race(&fake.addrs(), CONNECTION_ATTEMPT_DELAY, |addr| {
+        fake.connect(addr)
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(connected.to_string(), "192.0.2.2:443");
+    // Tried in order: the first refusal starts the second attempt without waiting for the
+    // delay, the second hangs until the delay, the third's refusal starts the fourth
+    assert_eq!(
+        fake.attempts(),
+        [
+            ("[2001:db8::1]:443".to_string(), 0),
+            ("192.0.2.1:443".to_string(), 10),
+            ("[2001:db8::2]:443".to_string(), 260),
+            ("192.0.2.2:443".to_string(), 280),
+        ]
+    );
+    assert_eq!(fake.started.elapsed(), ms(310));
+}
+
+#[tokio::test(start_paused = true)]
+async fn every_failure_is_reported() {
+    let fake = FakeConnector::new(&[
+        ("[2001:db8::1]:443", Answer::Refuse(ms(400))),
+        ("192.0.2.1:443", Answer::Refuse(ms(10))),
+    ]);
+
+    let failures = race(&fake.addrs(), CONNECTION_ATTEMPT_DELAY, |addr| {
+        fake.connect(addr)
+    })
+    .await
+    .unwrap_err();
+
+    // In the order the attempts failed, waiting for the slow one before giving up
+    let failed: Vec<_> = failures
+        .iter()
+        .map(|(addr, e)| (addr.to_string(), e.kind()))
+        .collect();
+    assert_eq!(
+        failed,
+        [
+            (
+                "192.0.2.1:443".to_string(),
+                io::ErrorKind::ConnectionRefused
+            ),
+            (
+                "[2001:db8::1]:443".to_string(),
+                io::ErrorKind::ConnectionRefused
+            ),
+        ]
+    );
+    assert_eq!(fake.started.elapsed(), ms(400));
+
+    let error = Error::Connect {
+        host: "terminal.example:443".to_string(),
+        failures,
+    };
+    let refused = io::Error::from(io::ErrorKind::ConnectionRefused).to_string();
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "Failed to connect to terminal.example:443: 192.0.2.1:443: {}; [2001:db8::1]:443: {}",
+            refused, refused
+        )
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn no_addresses_fail_at_once() {
+    let fake = FakeConnector::new(&[]);
+
+    let failures = race(&[], CONNECTION_ATTEMPT_DELAY, |addr| fake.connect(addr))
+        .await
+        .unwrap_err();
+
+    assert!(failures.is_empty());
+    assert!(fake.attempts().is_empty());
+    let error = Error::Connect {
+        host: "terminal.example:443".to_string(),
+        failures,
+    };
+    assert_eq!(
+        error.to_string(),
+        "Failed to connect to terminal.example:443: no addresses"
+    );
+}
+
+#[test]
+fn families_alternate_starting_with_the_first() {
+    let addrs =
+        |list: &[&str]| -> Vec<SocketAddr> { list.iter().map(|a| a.parse().unwrap()).collect() };
+
+    assert_eq!(
+        interleave_families(addrs(&[
+            "[2001:db8::1]:443",
+            "[2001:db8::2]:443",
+            "[2001:db8::3]:443",
+            "192.0.2.1:443",
+        ])),
+        addrs(&[
+            "[2001:db8::1]:443",
+            "192.0.2.1:443",
+            "[2001:db8::2]:443",
+            "[2001:db8::3]:443",
+        ])
+    );
+    assert_eq!(
+        interleave_families(addrs(&[
+            "192.0.2.1:443",
+            "192.0.2.2:443",
+            "[2001:db8::1]:443"
+        ])),
+        addrs(&["192.0.2.1:443", "[2001:db8::1]:443", "192.0.2.2:443"])
+    );
+    assert!(interleave_families(Vec::new()).is_empty());
+}
@@ -0,0 +1,190 @@
+//! The client against an rs_terminal server running in the test process, its sessions on mock
+//! PTYs that echo their input
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use rs_terminal::app_state::{AppState, DiagnosticsStore};
+use rs_terminal::config::ConfigLoader;
+use rs_terminal::pty::MockPtyFactory;
+use rs_terminal::server::build_router;
+use terminal_client::dto::CreateSessionRequest;
+use terminal_client::protocol::Subprotocol;
+use terminal_client::{
+    Error, TerminalApiClient, TerminalOutput, TerminalWsSession, http_origin, probe_server,
+};
+use tokio::net::TcpListener;
+
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+nudge_on_connect = false
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+
+[shells.sh]
+command = ["sh"]
+"#;
+
+/// Longest wait for output or a change on the server
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Start a server on mock PTYs, returning its HTTP and WebSocket base URLs
+async fn start_server() -> (String, String) {
+    let config = ConfigLoader::new().parse_config(CONFIG).unwrap();
+    let state = AppState::new(config, Arc::new(DiagnosticsStore::new()))
+        .with_pty_factory(Arc::new(MockPtyFactory));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, build_router(state)).await });
+    (format!("http://{}", address), format!("ws://{}", address))
+}
+
+/// Receive output until it ends with `expected`
+async fn output_until(session: &mut TerminalWsSession, expected: &str) {
+    let mut output = String::new();
+    while !output.ends_with(expected) {
+        let received = tokio::time::timeout(TIMEOUT, session.recv_output())
+            .await
+            .unwrap_or_else(|_| panic!("no {:?} in the output {:?}", expected, output))
+            .expect("connection closed")
+            .unwrap();
+        if let TerminalOutput::Text(text) = received {
+            output.push_str(&text);
+        }
+    }
+}
+
+#[tokio::test]
+async fn sessions_are_managed_through_the_rest_api() {
+    let (http, _) = start_server().await;
+    let api = TerminalApiClient::new(&http);
+
+    let request = CreateSessionRequest {
+        title: Some("build".to_string()),
+        columns: Some(100),
+        rows: Some(30),
+        ..Default::default()
+    };
+    let session = api.create_session(&request).await.unwrap();
+    assert_eq!(session.title.as_deref(), Some("build"));
+    assert_eq!((session.columns, session.rows), (100, 30));
+
+    let listed = api.list_sessions().await.unwrap();
+    assert_eq!(
+        listed.iter().map(|s| s.id.as_str()).collect::<Vec<_>>(),
+        [session.id.as_str()]
+    );
+    assert_eq!(api.get_session(&session.id).await.unwrap().id, session.id);
+
+    let renamed = api.set_session_title(&session.id, "deploy").await.unwrap();
+    assert_eq!(renamed.title.as_deref(), Some("deploy"));
+
+    let resized = api.resize_session(&session.id, 120, 40).await.unwrap();
+    assert_eq!((resized.columns, resized.rows), (120, 40));
+    let fetched = api.get_session(&session.id).await.unwrap();
+    assert_eq!((fetched.columns, fetched.rows), (120, 40));
+
+    api.terminate_session(&session.id).await.unwrap();
+    match api.get_session(&session.id).await {
+        Err(Error::Api { status, .. }) => assert_eq!(status, 404),
+        other => panic!("terminated session still found: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn raw_session_echoes_input() {
+    let (_, ws) = start_server().await;
+    let mut session = TerminalWsSession::connect(&format!("{}/ws", ws))
+        .await
+        .unwrap();
+    assert_eq!(session.subprotocol(), Subprotocol::Raw);
+    output_until(&mut session, "mock$ ").await;
+
+    session.send_input("hello raw").await.unwrap();
+    output_until(&mut session, "hello raw").await;
+
+    // Raw connections have no in-band resize
+    assert!(matches!(
+        session.resize(100, 30).await,
+        Err(Error::Unsupported(_))
+    ));
+    session.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn v1_session_attaches_by_id_and_resizes_in_band() {
+    let (http, ws) = start_server().await;
+    let created = TerminalApiClient::new(&http)
+        .create_session(&CreateSessionRequest::default())
+        .await
+        .unwrap();
+
+    let url = format!("{}/ws/{}", ws, created.id);
+    let mut session = TerminalWsSession::connect_with_protocol(&url, None, Some(Subprotocol::V1))
+        .await
+        .unwrap();
+    assert_eq!(session.subprotocol(), Subprotocol::V1);
+    output_until(&mut session, "mock$ ").await;
+
+    // Input goes out as an envelope, output comes back as text
+    session.send_input("hello v1").await.unwrap();
+    output_until(&mut session, "hello v1").await;
+
+    // The mock PTY reports the size it was given
+    session.resize(132, 43).await.unwrap();
+    output_until(&mut session, "[resized to 132x43]\r\nmock$ ").await;
+    session.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn probe_reports_the_version_and_shells() {
+    let (_, ws) = start_server().await;
+
+    let info = probe_server(&format!("{}/ws/some-session", ws), TIMEOUT)
+        .await
+        .unwrap();
+    assert_eq!(info.version.as_deref(), Some("0.1.0"));
+    assert_eq!(info.default_shell, "sh");
+    let shells: Vec<_> = info
+        .shells
+        .iter()
+        .map(|shell| (shell.name.as_str(), shell.columns, shell.rows))
+        .collect();
+    assert_eq!(shells, [("sh", 80, 24)]);
+    assert_eq!(info.default_size(), Some((80, 24)));
+}
+
+#[tokio::test]
+async fn probe_of_a_server_that_is_gone_fails() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ws = format!("ws://{}/ws", listener.local_addr().unwrap());
+    drop(listener);
+
+    assert!(probe_server(&ws, TIMEOUT).await.is_err());
+    assert!(matches!(
+        probe_server("tcp://host/ws", TIMEOUT).await,
+        Err(Error::InvalidUrl(_))
+    ));
+}
+
+#[test]
+fn http_origin_of_websocket_urls() {
+    assert_eq!(
+        http_origin("ws://host:8080/ws/abc").as_deref(),
+        Some("http://host:8080")
+    );
+    assert_eq!(
+        http_origin("wss://terminal.example/ws").as_deref(),
+        Some("https://terminal.example")
+    );
+    assert_eq!(
+        http_origin("https://terminal.example:8443/").as_deref(),
+        Some("https://terminal.example:8443")
+    );
+    assert_eq!(http_origin("tcp://host:8080"), None);
+    assert_eq!(http_origin("not a url"), None);
+}
@@ -0,0 +1,159 @@
+//! Protocol conformance: the client decodes every case of the shared fixtures in
+//! `rs_terminal/terminal-types/conformance` and encodes it again byte for byte, and its
+//! WebSocket connection carries the frames unchanged
+//!
+//! The server runs the same fixtures (`rs_terminal/tests/protocol_conformance.rs`), which also
+//! checks that they cover every message.
+
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use terminal_client::protocol::{ClientEnvelope, ServerEnvelope};
+use terminal_client::{TerminalOutput, TerminalWsSession};
+use tokio::net::TcpListener;
+use tokio_tungstenite::accept_async;
+use tokio_tungstenite::tungstenite::Message;
+
+const FIXTURES: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../rs_terminal/terminal-types/conformance"
+);
+
+/// A message and its encodings
+#[derive(Debug, Deserialize)]
+struct Case {
+    name: String,
+    /// Text frame of the `waylon-terminal-v1` subprotocol
+    json: String,
+    /// Hex of the frame on a `waylon-terminal-raw` connection, for the messages it carries
+    #[serde(default)]
+    raw: Option<String>,
+}
+
+/// A frame that must not decode
+#[derive(Debug, Deserialize)]
+struct InvalidCase {
+    name: String,
+    /// `client` or `server`, who sends the frame
+    direction: String,
+    json: String,
+}
+
+fn load<T: DeserializeOwned>(file: &str) -> Vec<T> {
+    let path = format!("{}/{}", FIXTURES, file);
+    let text = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    serde_json::from_str(&text).unwrap_or_else(|e| panic!("{}: {}", path, e))
+}
+
+fn from_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+#[test]
+fn client_messages_round_trip() {
+    for case in load::<Case>("client.json") {
+        let envelope: ClientEnvelope = serde_json::from_str(&case.json)
+            .unwrap_or_else(|e| panic!("{}: doesn't decode: {}", case.name, e));
+        assert_eq!(
+            serde_json::to_string(&envelope).unwrap(),
+            case.json,
+            "{}",
+            case.name
+        );
+    }
+}
+
+#[test]
+fn server_messages_round_trip() {
+    for case in load::<Case>("server.json") {
+        let envelope: ServerEnvelope = serde_json::from_str(&case.json)
+            .unwrap_or_else(|e| panic!("{}: doesn't decode: {}", case.name, e));
+        assert_eq!(
+            serde_json::to_string(&envelope).unwrap(),
+            case.json,
+            "{}",
+            case.name
+        );
+    }
+}
+
+#[test]
+fn invalid_messages_are_rejected() {
+    for case in load::<InvalidCase>("invalid.json") {
+        let rejected = match case.direction.as_str() {
+            "client" => serde_json::from_str::<ClientEnvelope>(&case.json).is_err(),
+            "server" => serde_json::from_str::<ServerEnvelope>(&case.json).is_err(),
+            other => panic!("{}: unknown direction {}", case.name, other),
+        };
+        assert!(rejected, "{}: decoded", case.name);
+    }
+}
+
+#[tokio::test]
+async fn frames_cross_the_connection_unchanged() {
+    let client_cases = load::<Case>("client.json");
+    let server_cases = load::<Case>("server.json");
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+    let expected: Vec<Message> = client_cases
+        .iter()
+        .flat_map(|case| {
+            let raw = case
+                .raw
+                .as_deref()
+                .map(|raw| Message::Binary(from_hex(raw)));
+            std::iter::once(Message::Text(case.json.clone())).chain(raw)
+        })
+        .collect();
+    // Raw connections carry output as text frames
+    let replies: Vec<String> = server_cases
+        .iter()
+        .flat_map(|case| {
+            let raw = case
+                .raw
+                .as_deref()
+                .map(|raw| String::from_utf8(from_hex(raw)).unwrap());
+            std::iter::once(case.json.clone()).chain(raw)
+        })
+        .collect();
+    // Stub server: checks every frame it receives, then sends the server's messages
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = accept_async(stream).await.unwrap();
+        for expected in expected {
+            assert_eq!(ws.next().await.unwrap().unwrap(), expected);
+        }
+        for reply in replies {
+            ws.send(Message::Text(reply)).await.unwrap();
+        }
+        ws.close(None).await.unwrap();
+    });
+
+    let mut session = TerminalWsSession::connect(&url).await.unwrap();
+    for case in &client_cases {
+        session.send_input(&case.json).await.unwrap();
+        if let Some(raw) = &case.raw {
+            session.send_binary(&from_hex(raw)).await.unwrap();
+        }
+    }
+    for case in &server_cases {
+        match session.recv_output().await {
+            Some(Ok(TerminalOutput::Text(text))) => {
+                assert_eq!(text, case.json, "{}", case.name);
+                let envelope: ServerEnvelope = serde_json::from_str(&text).unwrap();
+                assert_eq!(serde_json::to_string(&envelope).unwrap(), case.json);
+            }
+            other => panic!("{}: received {:?}", case.name, other),
+        }
+        let Some(raw) = &case.raw else { continue };
+        match session.recv_output().await {
+            Some(Ok(TerminalOutput::Text(text))) => assert_eq!(text.as_bytes(), from_hex(raw)),
+            other => panic!("{}: received {:?} for the raw frame", case.name, other),
+        }
+    }
+    server.await.unwrap();
+}
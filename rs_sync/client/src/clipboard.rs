@@ -0,0 +1,172 @@
+//! Where synced content ends up: the system clipboard or, on hosts without a display, a file
+
+use crate::error::{Result, SyncError};
+use arboard::{Clipboard, ImageData};
+use std::borrow::Cow;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// Destination of synced content
+pub trait ClipboardSink {
+    /// Replace the content
+    fn set_text(&mut self, text: &str) -> Result<()>;
+
+    /// Replace the content with binary data (`--non-text-policy binary`)
+    fn set_binary(&mut self, bytes: &[u8]) -> Result<()>;
+
+    /// Current content, `None` if there is no text
+    fn get_text(&mut self) -> Result<Option<String>>;
+}
+
+/// The system clipboard
+pub struct ArboardSink {
+    clipboard: Clipboard,
+}
+
+impl ArboardSink {
+    /// Open the system clipboard (fails without a display server)
+    pub fn new() -> Result<Self> {
+        let clipboard = Clipboard::new().map_err(|e| SyncError::Clipboard {
+            action: "open the clipboard".to_string(),
+            source: Box::new(e),
+        })?;
+        Ok(Self { clipboard })
+    }
+}
+
+impl ClipboardSink for ArboardSink {
+    fn set_text(&mut self, text: &str) -> Result<()> {
+        self.clipboard
+            .set_text(text)
+            .map_err(|e| SyncError::Clipboard {
+                action: "copy to the clipboard".to_string(),
+                source: Box::new(e),
+            })
+    }
+
+    /// Only images can be held as binary data, they are decoded for the clipboard
+    fn set_binary(&mut self, bytes: &[u8]) -> Result<()> {
+        let image = image::load_from_memory(bytes).map_err(|e| SyncError::Clipboard {
+            action: "copy binary content to the clipboard (only images are supported)".to_string(),
+            source: Box::new(e),
+        })?;
+        let image = image.into_rgba8();
+        let image = ImageData {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            bytes: Cow::Owned(image.into_raw()),
+        };
+        self.clipboard
+            .set_image(image)
+            .map_err(|e| SyncError::Clipboard {
+                action: "copy an image to the clipboard".to_string(),
+                source: Box::new(e),
+            })
+    }
+
+    fn get_text(&mut self) -> Result<Option<String>> {
+        match self.clipboard.get_text() {
+            Ok(text) => Ok(Some(text)),
+            Err(arboard::Error::ContentNotAvailable) => Ok(None),
+            Err(e) => Err(SyncError::Clipboard {
+                action: "read the clipboard".to_string(),
+                source: Box::new(e),
+            }),
+        }
+    }
+}
+
+/// A file standing in for the clipboard, replaced atomically on each change
+pub struct FileSink {
+    path: PathBuf,
+}
+
+impl FileSink {
+    /// Write the content to `path`
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    fn error(&self, action: &str, e: std::io::Error) -> SyncError {
+        SyncError::Clipboard {
+            action: format!("{} {}", action, self.path.display()),
+            source: Box::new(e),
+        }
+    }
+}
+
+impl ClipboardSink for FileSink {
+    fn set_text(&mut self, text: &str) -> Result<()> {
+        self.set_binary(text.as_bytes())
+    }
+
+    fn set_binary(&mut self, bytes: &[u8]) -> Result<()> {
+        // Readers never see a half-written file
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        std::fs::write(&temporary, bytes).map_err(|e| self.error("write", e))?;
+        std::fs::rename(&temporary, &self.path).map_err(|e| self.error("replace", e))
+    }
+
+    fn get_text(&mut self) -> Result<Option<String>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(text) => Ok(Some(text)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(self.error("read", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rs_sync_sink_{}_{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn file_sink_replaces_the_file() {
+        let path = temp_path("replaced");
+        let mut sink = FileSink::new(path.clone());
+        assert_eq!(sink.get_text().unwrap(), None);
+
+        sink.set_text("first").unwrap();
+        sink.set_text("second").unwrap();
+        let text = sink.get_text().unwrap();
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".tmp");
+        let leftover = PathBuf::from(temporary).exists();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(text.as_deref(), Some("second"));
+        assert!(!leftover, "the temporary file was left behind");
+    }
+
+    #[test]
+    fn file_sink_keeps_binary_content_as_is() {
+        let path = temp_path("binary");
+        let mut sink = FileSink::new(path.clone());
+
+        sink.set_binary(b"\x89PNG\0\xff").unwrap();
+        let written = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(written, b"\x89PNG\0\xff");
+    }
+
+    #[test]
+    fn file_sink_that_cant_write_is_a_clipboard_error() {
+        let path = temp_path("missing").join("clipboard");
+        let mut sink = FileSink::new(path.clone());
+
+        let error = sink.set_text("content").unwrap_err();
+        assert!(matches!(error, SyncError::Clipboard { .. }), "{:?}", error);
+        assert_eq!(error.exit_code(), 7);
+        assert!(
+            error.to_string().contains(&path.display().to_string()),
+            "{}",
+            error
+        );
+    }
+}
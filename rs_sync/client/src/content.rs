@@ -0,0 +1,146 @@
+//! Telling text from content that isn't, before anything reaches the clipboard
+//!
+//! A synced file may hold NUL bytes or binary data, which clipboards reject as text.
+//! Such content is handled by the `--non-text-policy` instead of failing on every poll.
+
+use crate::error::{Result, SyncError};
+use clap::ValueEnum;
+use tracing::debug;
+
+/// What to do with content that isn't text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NonTextPolicy {
+    /// Leave the clipboard unchanged and warn
+    Skip,
+    /// Drop NUL bytes and replace invalid UTF-8, then copy it as text
+    Sanitize,
+    /// Copy it as binary data (images for the clipboard, the raw bytes for --output-file)
+    Binary,
+}
+
+/// Invalid UTF-8 tolerated in text, in percent of its bytes (stray bytes are replaced)
+const MAX_INVALID_UTF8_PERCENT: usize = 1;
+
+/// Content ready for the clipboard
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Content {
+    /// Text
+    Text(String),
+    /// Anything else, kept as received
+    Binary(Vec<u8>),
+}
+
+impl Content {
+    /// The content's bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Content::Text(text) => text.as_bytes(),
+            Content::Binary(bytes) => bytes,
+        }
+    }
+
+    /// Size in bytes
+    pub fn len(&self) -> usize {
+        self.as_bytes().len()
+    }
+}
+
+/// Why the bytes aren't text, `None` for text
+fn non_text_reason(bytes: &[u8]) -> Option<String> {
+    if let Some(offset) = bytes.iter().position(|&b| b == 0) {
+        return Some(format!("contains a NUL byte at offset {}", offset));
+    }
+    let invalid: usize = bytes.utf8_chunks().map(|chunk| chunk.invalid().len()).sum();
+    if invalid * 100 > bytes.len() * MAX_INVALID_UTF8_PERCENT {
+        return Some(format!("{} bytes are not valid UTF-8", invalid));
+    }
+    None
+}
+
+/// Turn the bytes of `file` into clipboard content, applying `policy` to anything that isn't text
+pub fn classify(bytes: Vec<u8>, file: &str, policy: NonTextPolicy) -> Result<Content> {
+    let Some(reason) = non_text_reason(&bytes) else {
+        let text = match String::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+        };
+        return Ok(Content::Text(text));
+    };
+
+    match policy {
+        NonTextPolicy::Skip => Err(SyncError::NonText {
+            file: file.to_string(),
+            bytes: bytes.len() as u64,
+            reason,
+        }),
+        NonTextPolicy::Sanitize => {
+            debug!(
+                file,
+                bytes = bytes.len(),
+                reason,
+                "Sanitizing non-text content"
+            );
+            Ok(Content::Text(
+                String::from_utf8_lossy(&bytes).replace('\0', ""),
+            ))
+        }
+        NonTextPolicy::Binary => Ok(Content::Binary(bytes)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_kept_as_text() {
+        let content = classify("grüße\n".as_bytes().to_vec(), "notes", NonTextPolicy::Skip);
+        assert_eq!(content.unwrap(), Content::Text("grüße\n".to_string()));
+
+        // A stray invalid byte in otherwise valid text is replaced
+        let mut bytes = "x".repeat(200).into_bytes();
+        bytes[100] = 0xff;
+        let Content::Text(text) = classify(bytes, "notes", NonTextPolicy::Skip).unwrap() else {
+            panic!("not text");
+        };
+        assert_eq!(text.chars().nth(100), Some('\u{fffd}'));
+    }
+
+    #[test]
+    fn non_text_is_skipped_with_the_reason() {
+        let error = classify(b"abc\0def".to_vec(), "notes", NonTextPolicy::Skip).unwrap_err();
+        assert_eq!(error.exit_code(), 10);
+        assert_eq!(
+            error.to_string(),
+            "Content of notes (7 bytes) is not text: contains a NUL byte at offset 3, clipboard \
+             left unchanged (see --non-text-policy)"
+        );
+
+        let error = classify(vec![0xff; 10], "image", NonTextPolicy::Skip).unwrap_err();
+        assert!(
+            error.to_string().contains("10 bytes are not valid UTF-8"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn non_text_is_sanitized_into_text() {
+        let content = classify(
+            b"abc\0de\xff\xfe".to_vec(),
+            "notes",
+            NonTextPolicy::Sanitize,
+        );
+        assert_eq!(
+            content.unwrap(),
+            Content::Text("abcde\u{fffd}\u{fffd}".to_string())
+        );
+    }
+
+    #[test]
+    fn non_text_is_kept_as_binary() {
+        let bytes = b"\x89PNG\r\n\x1a\n\0\0".to_vec();
+        let content = classify(bytes.clone(), "image", NonTextPolicy::Binary);
+        assert_eq!(content.unwrap(), Content::Binary(bytes));
+    }
+}
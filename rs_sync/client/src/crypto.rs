@@ -0,0 +1,191 @@
+//! End-to-end encryption of synced content with a pre-shared key
+//!
+//! Encrypted content is text so the server can keep serving it like any other file:
+//! a magic header line followed by base64 of `nonce || ciphertext` (XChaCha20-Poly1305).
+//! The server never sees the key and never needs to know the content is encrypted.
+
+use crate::error::{Result, SyncError};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chacha20poly1305::{
+    XChaCha20Poly1305, XNonce,
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+};
+use std::path::Path;
+
+/// First line of every encrypted payload
+const MAGIC_HEADER: &str = "RS_SYNC_ENCRYPTED_V1\n";
+
+/// XChaCha20-Poly1305 nonce length in bytes
+const NONCE_LEN: usize = 24;
+
+/// Pre-shared key used to encrypt and decrypt content
+pub struct ContentKey {
+    cipher: XChaCha20Poly1305,
+}
+
+impl ContentKey {
+    /// Load a key file containing 32 bytes as 64 hex characters (e.g. `openssl rand -hex 32`)
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(|source| SyncError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let bytes = hex::decode(text.trim()).map_err(|e| {
+            SyncError::Config(format!(
+                "Key file {} is not valid hex: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        if bytes.len() != 32 {
+            return Err(SyncError::Config(format!(
+                "Key file {} must contain 32 bytes (64 hex characters), found {} bytes",
+                path.display(),
+                bytes.len()
+            )));
+        }
+
+        let cipher = XChaCha20Poly1305::new_from_slice(&bytes)
+            .map_err(|_| SyncError::Config(format!("Invalid key length in {}", path.display())))?;
+        Ok(Self { cipher })
+    }
+
+    /// Encrypt plaintext into the textual payload format
+    pub fn seal(&self, plaintext: &[u8]) -> Result<String> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| SyncError::Content("Failed to encrypt content".to_string()))?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&ciphertext);
+        Ok(format!("{}{}\n", MAGIC_HEADER, BASE64.encode(payload)))
+    }
+
+    /// Decrypt a payload produced by [`ContentKey::seal`]
+    fn open(&self, body: &[u8]) -> Result<Vec<u8>> {
+        let payload = BASE64.decode(body.trim_ascii()).map_err(|e| {
+            SyncError::Content(format!(
+                "Encrypted content is not valid base64 (truncated or corrupted?): {}",
+                e
+            ))
+        })?;
+        if payload.len() < NONCE_LEN {
+            return Err(SyncError::Content(
+                "Encrypted content is too short (truncated or corrupted?)".to_string(),
+            ));
+        }
+
+        let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| {
+                SyncError::Content(
+                    "Failed to decrypt content: wrong key or tampered content".to_string(),
+                )
+            })
+    }
+}
+
+/// Turn content received from the server into the plaintext for the clipboard
+///
+/// Without a key only plaintext is accepted; with a key only encrypted content is accepted,
+/// so a relay can neither read the clipboard nor inject plaintext into it.
+pub fn decode_content(content: Vec<u8>, key: Option<&ContentKey>) -> Result<Vec<u8>> {
+    match (content.strip_prefix(MAGIC_HEADER.as_bytes()), key) {
+        (Some(body), Some(key)) => key.open(body),
+        (Some(_), None) => Err(SyncError::Config(
+            "Content is encrypted but no --key-file was given".to_string(),
+        )),
+        (None, Some(_)) => Err(SyncError::Content(
+            "Expected encrypted content but the server sent plaintext".to_string(),
+        )),
+        (None, None) => Ok(content),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Write `hex` as key file `name` and load it
+    fn key_file(name: &str, hex: &str) -> (PathBuf, Result<ContentKey>) {
+        let path =
+            std::env::temp_dir().join(format!("rs_sync_{}_{}.key", name, std::process::id()));
+        std::fs::write(&path, hex).unwrap();
+        let key = ContentKey::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        (path, key)
+    }
+
+    fn key(name: &str, byte: u8) -> ContentKey {
+        key_file(name, &hex::encode([byte; 32])).1.unwrap()
+    }
+
+    #[test]
+    fn sealed_content_round_trips() {
+        let key = key("round_trip", 7);
+        let sealed = key.seal(b"clipboard secret").unwrap();
+        assert!(sealed.starts_with(MAGIC_HEADER));
+        assert!(!sealed.contains("clipboard secret"));
+
+        // The server stores and serves the payload as an opaque file
+        let stored = std::env::temp_dir().join(format!("rs_sync_stored_{}", std::process::id()));
+        std::fs::write(&stored, &sealed).unwrap();
+        let served = std::fs::read(&stored).unwrap();
+        std::fs::remove_file(&stored).unwrap();
+
+        assert_eq!(
+            decode_content(served, Some(&key)).unwrap(),
+            b"clipboard secret"
+        );
+    }
+
+    #[test]
+    fn wrong_key_is_an_error() {
+        let sealed = key("sealing", 1).seal(b"clipboard secret").unwrap();
+        let error = decode_content(sealed.into_bytes(), Some(&key("other", 2))).unwrap_err();
+        assert!(matches!(error, SyncError::Content(_)));
+        assert_eq!(
+            error.to_string(),
+            "Failed to decrypt content: wrong key or tampered content"
+        );
+    }
+
+    #[test]
+    fn tampered_content_is_an_error() {
+        let key = key("tampered", 3);
+        let sealed = key.seal(b"clipboard secret").unwrap();
+        let mut payload = BASE64.decode(sealed[MAGIC_HEADER.len()..].trim()).unwrap();
+        *payload.last_mut().unwrap() ^= 1;
+        let tampered = format!("{}{}", MAGIC_HEADER, BASE64.encode(payload));
+        assert!(decode_content(tampered.into_bytes(), Some(&key)).is_err());
+    }
+
+    #[test]
+    fn key_and_content_must_agree() {
+        let key = key("mismatch", 4);
+        let sealed = key.seal(b"clipboard secret").unwrap();
+        assert!(matches!(
+            decode_content(sealed.into_bytes(), None),
+            Err(SyncError::Config(_))
+        ));
+        assert!(matches!(
+            decode_content(b"plain".to_vec(), Some(&key)),
+            Err(SyncError::Content(_))
+        ));
+        assert_eq!(decode_content(b"plain".to_vec(), None).unwrap(), b"plain");
+    }
+
+    #[test]
+    fn invalid_key_files_are_rejected() {
+        for (name, contents) in [("not_hex", "xyz"), ("short", "00ff")] {
+            let (path, key) = key_file(name, contents);
+            let error = key.err().unwrap();
+            assert!(matches!(error, SyncError::Config(_)));
+            assert!(error.to_string().contains(&path.display().to_string()));
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Errors of the sync client and the process exit codes they map to
+//!
+//! Each class of failure exits with its own code, so a supervisor (systemd, monitoring)
+//! can tell a misconfiguration from an unreachable server or a broken clipboard.
+
+use reqwest::StatusCode;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Result type of the sync client
+pub type Result<T> = std::result::Result<T, SyncError>;
+
+/// Everything that can go wrong while syncing
+#[derive(Debug, Error)]
+pub enum SyncError {
+    /// The server answered with an error status
+    #[error("Server returned error: {status} {body}")]
+    Http { status: StatusCode, body: String },
+
+    /// The server could not be reached or the response could not be read
+    #[error("Failed to connect to server at {url}: {source}")]
+    Network {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    /// The clipboard (or the file standing in for it) is unavailable or refused the content
+    #[error("Failed to {action}: {source}")]
+    Clipboard {
+        action: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// A local file could not be read
+    #[error("Failed to read {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The content is larger than the client accepts; `bytes` is the announced size, or what
+    /// arrived before the download was stopped
+    #[error(
+        "Content of {file} ({bytes} bytes or more) exceeds the limit of {limit} bytes, \
+         clipboard left unchanged"
+    )]
+    ContentTooLarge {
+        file: String,
+        bytes: u64,
+        limit: u64,
+    },
+
+    /// The content isn't text and `--non-text-policy skip` is in effect
+    #[error(
+        "Content of {file} ({bytes} bytes) is not text: {reason}, clipboard left unchanged \
+         (see --non-text-policy)"
+    )]
+    NonText {
+        file: String,
+        bytes: u64,
+        reason: String,
+    },
+
+    /// The content kept failing its checksum
+    #[error("Checksum mismatch persisted after retrying, clipboard left unchanged")]
+    HashMismatch,
+
+    /// The content can't be used (undecryptable, unexpected format)
+    #[error("{0}")]
+    Content(String),
+
+    /// The options or files given to the client are invalid
+    #[error("{0}")]
+    Config(String),
+}
+
+impl SyncError {
+    /// Process exit code of this error class (documented in `--help`)
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            SyncError::Config(_) => 3,
+            SyncError::Io { .. } => 4,
+            SyncError::Network { .. } => 5,
+            SyncError::Http { .. } => 6,
+            SyncError::Clipboard { .. } => 7,
+            SyncError::ContentTooLarge { .. } => 8,
+            SyncError::HashMismatch => 9,
+            SyncError::Content(_) | SyncError::NonText { .. } => 10,
+        }
+    }
+}
+
+/// Exit codes listed at the end of `--help`
+pub const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0   success
+  2   invalid command line
+  3   invalid configuration (key file, options)
+  4   local file could not be read
+  5   server unreachable
+  6   server returned an error status
+  7   clipboard (or --output-file) unavailable
+  8   content too large
+  9   checksum mismatch
+  10  content unusable (decryption failed, unexpected format, not text)";
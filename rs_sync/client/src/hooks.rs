@@ -0,0 +1,213 @@
+//! Actions run after new content lands in the clipboard: desktop notifications and
+//! the user's `--on-change` command. Both run detached so they never delay the sync loop.
+
+use crate::content::Content;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+use tracing::{debug, warn};
+
+/// Maximum number of change hooks running at the same time
+const MAX_CONCURRENT_HOOKS: usize = 4;
+
+/// Characters of content shown in a notification preview
+const PREVIEW_CHARS: usize = 80;
+
+/// Notification and hook settings taken from the command line
+pub struct ChangeHooks {
+    notify: bool,
+    hide_content: bool,
+    command: Option<String>,
+    timeout: Duration,
+    permits: Arc<Semaphore>,
+}
+
+impl ChangeHooks {
+    /// Create the hooks; `command` is run through the platform shell
+    pub fn new(
+        notify: bool,
+        hide_content: bool,
+        command: Option<String>,
+        timeout: Duration,
+    ) -> Self {
+        if notify && !cfg!(feature = "notifications") {
+            warn!("Built without the notifications feature, --notify shows nothing");
+        }
+
+        Self {
+            notify,
+            hide_content,
+            command,
+            timeout,
+            permits: Arc::new(Semaphore::new(MAX_CONCURRENT_HOOKS)),
+        }
+    }
+
+    /// Fire the configured actions for content that just changed
+    pub fn content_changed(&self, file: &str, content: &Content) {
+        if self.notify {
+            self.show_notification(file, content);
+        }
+        if let Some(command) = &self.command {
+            self.run_command(command, file, content);
+        }
+    }
+
+    /// Show a desktop notification with a short preview of the content
+    fn show_notification(&self, file: &str, content: &Content) {
+        let body = match content {
+            Content::Text(text) if !self.hide_content => preview(text),
+            _ => format!("{} bytes from {}", content.len(), file),
+        };
+        let summary = format!("Clipboard updated from {}", file);
+        send_notification(summary, body);
+    }
+
+    /// Run the change command with the content on stdin, unless too many are still running
+    fn run_command(&self, command: &str, file: &str, content: &Content) {
+        let Ok(permit) = self.permits.clone().try_acquire_owned() else {
+            warn!(
+                limit = MAX_CONCURRENT_HOOKS,
+                "Too many change hooks still running, skipping this one"
+            );
+            return;
+        };
+
+        let mut child = match shell_command(command)
+            .env("RS_SYNC_FILE", file)
+            .env("RS_SYNC_TIMESTAMP", chrono::Local::now().to_rfc3339())
+            .env("RS_SYNC_BYTES", content.len().to_string())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => {
+                warn!(command, error = %e, "Failed to start change hook");
+                return;
+            }
+        };
+
+        let command = command.to_string();
+        let content = content.as_bytes().to_vec();
+        let timeout = self.timeout;
+        tokio::spawn(async move {
+            let _permit = permit;
+
+            if let Some(mut stdin) = child.stdin.take() {
+                // A hook that doesn't read its input is fine, ignore broken pipes
+                let _ = stdin.write_all(&content).await;
+            }
+
+            match tokio::time::timeout(timeout, child.wait()).await {
+                Ok(Ok(status)) if status.success() => debug!(command, "Change hook finished"),
+                Ok(Ok(status)) => warn!(command, %status, "Change hook exited with failure"),
+                Ok(Err(e)) => warn!(command, error = %e, "Failed to wait for change hook"),
+                Err(_) => {
+                    warn!(
+                        command,
+                        timeout_secs = timeout.as_secs(),
+                        "Change hook timed out, killing it"
+                    );
+                    let _ = child.kill().await;
+                }
+            }
+        });
+    }
+}
+
+/// Hand a notification to the desktop's notification daemon
+#[cfg(feature = "notifications")]
+fn send_notification(summary: String, body: String) {
+    // Talking to the notification daemon blocks, keep it off the async workers
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = notify_rust::Notification::new()
+            .appname("rs_sync")
+            .summary(&summary)
+            .body(&body)
+            .show()
+        {
+            warn!(error = %e, "Failed to show desktop notification");
+        }
+    });
+}
+
+/// Without the `notifications` feature there is no daemon to talk to; `ChangeHooks::new` has
+/// already warned about it
+#[cfg(not(feature = "notifications"))]
+fn send_notification(summary: String, _body: String) {
+    debug!(summary, "Built without notifications, not showing one");
+}
+
+/// Build a command running `command` through the platform shell
+fn shell_command(command: &str) -> Command {
+    #[cfg(windows)]
+    {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    }
+    #[cfg(not(windows))]
+    {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    }
+}
+
+/// First line of the content, shortened for a notification
+fn preview(content: &str) -> String {
+    let line = content.lines().next().unwrap_or_default();
+    let mut preview: String = line.chars().take(PREVIEW_CHARS).collect();
+    if preview.len() < content.len() {
+        preview.push('…');
+    }
+    preview
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// Wait for a hook to create `path`
+    async fn wait_for_file(path: &Path) -> String {
+        for _ in 0..100 {
+            if let Ok(contents) = std::fs::read_to_string(path) {
+                return contents;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("the hook didn't write {}", path.display());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn hook_gets_the_content_and_its_description() {
+        let output = std::env::temp_dir().join(format!("rs_sync_hook_{}", std::process::id()));
+        let _ = std::fs::remove_file(&output);
+        // Written aside and moved, so the test never reads a partial file
+        let command = format!(
+            "{{ cat; echo; echo \"$RS_SYNC_FILE $RS_SYNC_BYTES\"; }} > '{0}.tmp' && mv '{0}.tmp' '{0}'",
+            output.display()
+        );
+        let hooks = ChangeHooks::new(false, false, Some(command), Duration::from_secs(5));
+
+        hooks.content_changed("notes", &Content::Text("hello hook".to_string()));
+        let written = wait_for_file(&output).await;
+        std::fs::remove_file(&output).unwrap();
+        assert_eq!(written, "hello hook\nnotes 10\n");
+    }
+
+    #[test]
+    fn preview_keeps_the_first_line() {
+        assert_eq!(preview("short"), "short");
+        assert_eq!(preview("first\nsecond"), "first…");
+        let long = "x".repeat(PREVIEW_CHARS + 10);
+        assert_eq!(preview(&long), format!("{}…", "x".repeat(PREVIEW_CHARS)));
+    }
+}
@@ -1,15 +1,44 @@
-use anyhow::Result;
-use arboard::Clipboard;
-use clap::Parser;
-use reqwest::Client;
+mod clipboard;
+mod content;
+mod crypto;
+mod error;
+mod hooks;
+mod reconnect;
+#[cfg(test)]
+mod test_support;
+
+use clap::{Parser, ValueEnum};
+use clipboard::{ArboardSink, ClipboardSink, FileSink};
+use content::{Content, NonTextPolicy};
+use crypto::ContentKey;
+use error::{EXIT_CODES_HELP, Result, SyncError};
+use hooks::ChangeHooks;
+use reconnect::Reconnect;
+use reqwest::{Client, Url};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::process::ExitCode;
 use std::time::Duration;
 use tokio::signal;
 use tokio::sync::oneshot;
-use tokio::time::interval;
+use tokio::time::{Instant, sleep_until};
+use tracing::{Subscriber, debug, info, warn};
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::util::SubscriberInitExt;
+
+// Log output format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per event
+    Json,
+}
 
 // Client configuration
 #[derive(Debug, Parser)]
-#[clap(author, version, about, long_about = None)]
+#[clap(author, version, about, long_about = None, after_help = EXIT_CODES_HELP)]
 pub struct ClientConfig {
     #[clap(short = 'a', long, default_value = "http://localhost:3000")]
     pub http_address: String,
@@ -20,25 +49,178 @@ pub struct ClientConfig {
     #[clap(short, long, default_value = "5")]
     pub interval: u64,
 
-    #[clap(short = 'f', long, default_value = "content.txt")]
-    pub file_path: String,
+    /// Longest pause in seconds between attempts while the server can't be reached
+    /// (the pause starts at --interval and doubles on each failed attempt)
+    #[clap(long, default_value = "60")]
+    pub max_backoff: u64,
+
+    /// Give up after this many failed attempts in a row to reach the server
+    /// (by default the client keeps trying, e.g. across server restarts)
+    #[clap(long, value_name = "ATTEMPTS", value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_reconnects: Option<u32>,
+
+    /// Name of the file entry to sync (see --list)
+    #[clap(short, long, conflicts_with = "file_path")]
+    pub name: Option<String>,
+
+    /// Raw file path to request (only honored by servers started with --allow-raw-paths)
+    #[clap(short = 'f', long)]
+    pub file_path: Option<String>,
+
+    /// List the files offered by the server and exit
+    #[clap(short, long)]
+    pub list: bool,
+
+    /// Pre-shared key (64 hex characters) for end-to-end encrypted content
+    #[clap(short, long)]
+    pub key_file: Option<PathBuf>,
+
+    /// Write the content to this file instead of the clipboard (for hosts without a display)
+    #[clap(long, value_name = "PATH")]
+    pub output_file: Option<PathBuf>,
+
+    /// Show a desktop notification when new content lands in the clipboard
+    #[clap(long)]
+    pub notify: bool,
+
+    /// Leave the content preview out of notifications
+    #[clap(long, requires = "notify")]
+    pub notify_hide_content: bool,
+
+    /// Shell command run on each change, with the content on stdin and
+    /// RS_SYNC_FILE, RS_SYNC_TIMESTAMP and RS_SYNC_BYTES set
+    #[clap(long, value_name = "CMD")]
+    pub on_change: Option<String>,
+
+    /// Seconds a change command may run before it is killed
+    #[clap(long, default_value = "10")]
+    pub hook_timeout: u64,
+
+    /// What to do with content that isn't text (NUL bytes, mostly invalid UTF-8)
+    #[clap(long, value_enum, default_value = "skip")]
+    pub non_text_policy: NonTextPolicy,
+
+    /// Encrypt this file with --key-file, print the result for upload to the server and exit
+    #[clap(long, value_name = "INPUT", requires = "key_file")]
+    pub seal: Option<PathBuf>,
+
+    /// Only log warnings and errors
+    #[clap(short, long)]
+    pub quiet: bool,
+
+    /// Log output format
+    #[clap(long, value_enum, default_value = "text")]
+    pub log_format: LogFormat,
 }
 
 /// Build full URL from base address and endpoint
-fn build_url(config: &ClientConfig) -> String {
-    format!("{}{}", config.http_address, config.endpoint)
+fn build_url(config: &ClientConfig) -> Result<String> {
+    let url = format!("{}{}", config.http_address, config.endpoint);
+    match Url::parse(&url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(url),
+        _ => Err(SyncError::Config(format!(
+            "Invalid server URL {} (check --http-address and --endpoint)",
+            url
+        ))),
+    }
+}
+
+/// Initialize logging; RUST_LOG overrides the level chosen by --quiet
+fn init_logging(config: &ClientConfig) {
+    log_subscriber(config, std::io::stdout).init();
 }
 
-/// Print client configuration
-fn print_config(config: &ClientConfig) {
-    println!("Client starting with configuration:");
-    println!("  HTTP Address: {}", config.http_address);
-    println!("  Endpoint: {}", config.endpoint);
-    println!("  Update Interval: {} seconds", config.interval);
-    println!("  File Path: {}", config.file_path);
-    println!();
-    println!("Press Ctrl+C to gracefully exit.");
-    println!();
+/// Build the subscriber formatting log events as chosen by --quiet and --log-format
+fn log_subscriber<W>(config: &ClientConfig, writer: W) -> Box<dyn Subscriber + Send + Sync>
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let default_level = if config.quiet { "warn" } else { "info" };
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_level));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_target(false)
+        .with_writer(writer);
+
+    match config.log_format {
+        LogFormat::Text => Box::new(builder.finish()),
+        LogFormat::Json => Box::new(builder.json().flatten_event(true).finish()),
+    }
+}
+
+/// Describe which file is synced, for logs
+fn file_label(config: &ClientConfig) -> &str {
+    config
+        .name
+        .as_deref()
+        .or(config.file_path.as_deref())
+        .unwrap_or("default")
+}
+
+/// Build the request body selecting the file to fetch
+fn file_request_body(config: &ClientConfig) -> serde_json::Value {
+    match (&config.name, &config.file_path) {
+        (Some(name), _) => serde_json::json!({ "name": name }),
+        (None, Some(file_path)) => serde_json::json!({ "file_path": file_path }),
+        (None, None) => serde_json::json!({}),
+    }
+}
+
+/// Print the files offered by the server
+async fn list_files(client: &Client, config: &ClientConfig) -> Result<()> {
+    let url = format!("{}/files", config.http_address);
+    let response = client
+        .get(&url)
+        .send()
+        .await
+        .map_err(|source| SyncError::Network {
+            url: url.clone(),
+            source,
+        })?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(SyncError::Http { status, body });
+    }
+
+    let entries: Vec<serde_json::Value> = response.json().await.map_err(|e| {
+        if e.is_decode() {
+            SyncError::Content(format!("Server sent an invalid file list: {}", e))
+        } else {
+            SyncError::Network {
+                url: url.clone(),
+                source: e,
+            }
+        }
+    })?;
+    for entry in entries {
+        let field = |key: &str| match &entry[key] {
+            serde_json::Value::Null => "-".to_string(),
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        println!(
+            "{:<5} {:<30} {:>10}  {}",
+            field("kind"),
+            field("name"),
+            field("size"),
+            field("modified")
+        );
+    }
+    Ok(())
+}
+
+/// Log client configuration
+fn log_config(config: &ClientConfig) {
+    info!(
+        http_address = %config.http_address,
+        endpoint = %config.endpoint,
+        interval_secs = config.interval,
+        file = file_label(config),
+        "Client starting, press Ctrl+C to exit"
+    );
 }
 
 /// Wait for shutdown signal (Ctrl+C or SIGTERM)
@@ -46,7 +228,7 @@ async fn wait_for_shutdown() -> Result<()> {
     // Wait for either Ctrl+C or SIGTERM signal
     tokio::select! {
         _ = signal::ctrl_c() => {
-            println!("\nReceived Ctrl+C, shutting down...");
+            info!("Received Ctrl+C, shutting down");
         },
         _ = async {
             // Only listen for SIGTERM on Unix systems
@@ -54,7 +236,7 @@ async fn wait_for_shutdown() -> Result<()> {
             {
                 if let Ok(mut sigterm) = signal::unix::signal(signal::unix::SignalKind::terminate()) {
                     sigterm.recv().await;
-                    println!("\nReceived SIGTERM, shutting down...");
+                    info!("Received SIGTERM, shutting down");
                 }
             }
             // For Windows, just wait indefinitely
@@ -67,84 +249,337 @@ async fn wait_for_shutdown() -> Result<()> {
     Ok(())
 }
 
+/// Counters reported when the client exits
+#[derive(Debug, Default)]
+struct SyncStats {
+    syncs: u64,
+    failures: u64,
+    last_success: Option<chrono::DateTime<chrono::Local>>,
+}
+
+/// Log the final sync summary
+fn log_summary(stats: &SyncStats) {
+    let last_success = stats
+        .last_success
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| "never".to_string());
+    info!(
+        syncs = stats.syncs,
+        failures = stats.failures,
+        last_success = %last_success,
+        "Client exited"
+    );
+}
+
+/// Header carrying the hex SHA-256 of the response body
+const CONTENT_SHA256: &str = "x-content-sha256";
+
+/// Largest content copied to the clipboard
+const MAX_CONTENT_BYTES: u64 = 32 * 1024 * 1024;
+
+/// Fetch the file content, retrying once when it doesn't match the server's checksum
+async fn fetch_verified(
+    client: &Client,
+    url: &str,
+    request_body: &serde_json::Value,
+    file: &str,
+) -> Result<Vec<u8>> {
+    for attempt in 1..=2 {
+        let (content, expected) = fetch_content(client, url, request_body, file).await?;
+        let Some(expected) = expected else {
+            // Older servers don't send a checksum
+            return Ok(content);
+        };
+
+        let actual = hex::encode(Sha256::digest(&content));
+        if actual.eq_ignore_ascii_case(&expected) {
+            return Ok(content);
+        }
+        warn!(
+            url,
+            attempt,
+            expected = %expected,
+            actual = %actual,
+            "Checksum mismatch, content was damaged in transit"
+        );
+    }
+
+    Err(SyncError::HashMismatch)
+}
+
+/// Fetch the file content from the server, along with its checksum if the server sent one
+/// The body is read in chunks and dropped as soon as it exceeds the size limit
+async fn fetch_content(
+    client: &Client,
+    url: &str,
+    request_body: &serde_json::Value,
+    file: &str,
+) -> Result<(Vec<u8>, Option<String>)> {
+    // Fetch file content using POST
+    let mut response = client
+        .post(url)
+        .header("Content-Type", "application/json")
+        .json(request_body)
+        .send()
+        .await
+        .map_err(|source| SyncError::Network {
+            url: url.to_string(),
+            source,
+        })?;
+
+    let status = response.status();
+    debug!(%status, "Received response");
+
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(SyncError::Http { status, body });
+    }
+    let announced = response.content_length();
+    if let Some(bytes) = announced
+        && bytes > MAX_CONTENT_BYTES
+    {
+        return Err(SyncError::ContentTooLarge {
+            file: file.to_string(),
+            bytes,
+            limit: MAX_CONTENT_BYTES,
+        });
+    }
+
+    let checksum = response
+        .headers()
+        .get(CONTENT_SHA256)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    // Chunked responses carry no length up front, count while reading
+    let mut content = Vec::with_capacity(announced.unwrap_or(0) as usize);
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|source| SyncError::Network {
+            url: url.to_string(),
+            source,
+        })?
+    {
+        if (content.len() + chunk.len()) as u64 > MAX_CONTENT_BYTES {
+            return Err(SyncError::ContentTooLarge {
+                file: file.to_string(),
+                bytes: (content.len() + chunk.len()) as u64,
+                limit: MAX_CONTENT_BYTES,
+            });
+        }
+        content.extend_from_slice(&chunk);
+    }
+    Ok((content, checksum))
+}
+
 /// Run the main client loop with interval updates
 async fn run_client_loop(
     config: &ClientConfig,
     client: &Client,
     url: &str,
-    mut clipboard: Clipboard,
+    sink: &mut dyn ClipboardSink,
+    key: Option<&ContentKey>,
+    hooks: &ChangeHooks,
     shutdown_rx: &mut oneshot::Receiver<()>,
-) -> Result<()> {
-    let mut interval = interval(Duration::from_secs(config.interval));
+) -> Result<SyncStats> {
+    let interval = Duration::from_secs(config.interval);
+    let mut reconnect = Reconnect::new(
+        interval,
+        Duration::from_secs(config.max_backoff),
+        config.max_reconnects,
+    );
+    let mut next_poll = Instant::now();
+    let mut stats = SyncStats::default();
+    let mut last_content: Option<Content> = None;
+    // A skipped file is reported once, not on every poll
+    let mut last_skipped: Option<String> = None;
+    let request_body = file_request_body(config);
+    let file = file_label(config);
 
     loop {
         tokio::select! {
-            // Wait for next interval
-            _ = interval.tick() => {
-                println!("[CLIENT] Fetching content from: {} (file: {})", url, config.file_path);
-
-                // Prepare request body with file_path from config
-                let request_body = serde_json::json!({ "file_path": &config.file_path });
-
-                // Fetch file content using POST
-                match client.post(url)
-                    .header("Content-Type", "application/json")
-                    .json(&request_body)
-                    .send().await {
-                    Ok(response) => {
-                        let status = response.status();
-                        println!("[CLIENT] Received response: {}", status);
-
-                        if status.is_success() {
-                            match response.text().await {
-                                Ok(content) => {
-                                    println!("[CLIENT] Content received: {} bytes", content.len());
-
-                                    // Copy to clipboard
-                                    if let Err(e) = clipboard.set_text(content.clone()) {
-                                        eprintln!("[CLIENT] ❌ Failed to copy to clipboard: {}", e);
-                                        continue;
-                                    }
-
-                                    println!("[CLIENT] ✓ Clipboard updated at {}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"));
-                                }
-                                Err(e) => {
-                                    eprintln!("[CLIENT] ❌ Failed to read response text: {}", e);
-                                }
-                            }
-                        } else {
-                            eprintln!("[CLIENT] ❌ Server returned error: {}", status);
-                        }
+            biased;
+
+            // Wait for shutdown signal, also while backing off from an unreachable server
+            _ = &mut *shutdown_rx => break,
+            // Wait for the next poll
+            _ = sleep_until(next_poll) => {}
+        }
+
+        debug!(url, file, "Fetching content");
+        let started_at = Instant::now();
+        next_poll = started_at + interval;
+
+        // Race the request against shutdown so a late response never reaches the clipboard
+        let result = tokio::select! {
+            biased;
+
+            _ = &mut *shutdown_rx => {
+                info!(url, "Cancelled in-flight request");
+                break;
+            }
+            result = fetch_verified(client, url, &request_body, file) => result,
+        };
+
+        let result = result.and_then(|content| {
+            // Decrypt (or reject) before anything reaches the clipboard
+            let content = crypto::decode_content(content, key)?;
+            let content = content::classify(content, file, config.non_text_policy)?;
+
+            // Copy to clipboard, unless it already holds the content (rewriting it would
+            // take the selection from its owner on every poll)
+            match &content {
+                Content::Text(text) => {
+                    if sink.get_text().ok().flatten().as_deref() != Some(text.as_str()) {
+                        sink.set_text(text)?;
                     }
-                    Err(e) => {
-                        eprintln!("[CLIENT] ❌ Failed to connect to server: {}", e);
-                        eprintln!("[CLIENT] Make sure the server is running at: {}", url);
+                }
+                Content::Binary(bytes) => {
+                    if last_content.as_ref() != Some(&content) {
+                        sink.set_binary(bytes)?;
                     }
                 }
+            }
+            Ok(content)
+        });
+        let duration_ms = started_at.elapsed().as_millis() as u64;
 
-                println!("[CLIENT] Next update in {} seconds...\n", config.interval);
-            },
-            // Wait for shutdown signal
-            _ = &mut *shutdown_rx => {
-                println!("\n[CLIENT] Received shutdown signal...");
-                println!("[CLIENT] Shutting down client...");
-                break;
+        // Anything but a network error means the server answered
+        if !matches!(result, Err(SyncError::Network { .. })) {
+            let failures = reconnect.succeeded();
+            if failures > 0 {
+                info!(url, failures, "Server reachable again");
+            }
+        }
+
+        match result {
+            Ok(content) => {
+                stats.syncs += 1;
+                stats.last_success = Some(chrono::Local::now());
+                last_skipped = None;
+
+                // Only changed content (or recovery after a failure) is worth an INFO line
+                if last_content.as_ref() != Some(&content) {
+                    info!(
+                        url,
+                        file,
+                        bytes = content.len(),
+                        duration_ms,
+                        outcome = "success",
+                        "Clipboard updated"
+                    );
+                    hooks.content_changed(file, &content);
+                } else {
+                    debug!(
+                        url,
+                        file,
+                        bytes = content.len(),
+                        duration_ms,
+                        outcome = "success",
+                        "Clipboard unchanged"
+                    );
+                }
+                last_content = Some(content);
+            }
+            Err(e @ SyncError::NonText { .. }) => {
+                stats.failures += 1;
+                last_content = None;
+                let message = e.to_string();
+                if last_skipped.as_deref() == Some(message.as_str()) {
+                    debug!(url, file, outcome = "skipped", "Content still not text");
+                } else {
+                    warn!(url, file, outcome = "skipped", "{}", message);
+                    last_skipped = Some(message);
+                }
+            }
+            Err(e @ SyncError::Network { .. }) => {
+                stats.failures += 1;
+                last_content = None;
+                last_skipped = None;
+                let Some(backoff) = reconnect.failed() else {
+                    warn!(
+                        url,
+                        file,
+                        attempts = reconnect.failures(),
+                        outcome = "failure",
+                        error = %e,
+                        "Server unreachable, giving up (see --max-reconnects)"
+                    );
+                    log_summary(&stats);
+                    return Err(e);
+                };
+                next_poll = Instant::now() + backoff;
+                warn!(
+                    url,
+                    file,
+                    duration_ms,
+                    attempts = reconnect.failures(),
+                    retry_in_secs = backoff.as_secs(),
+                    outcome = "failure",
+                    error = %e,
+                    "Server unreachable, retrying"
+                );
+            }
+            Err(e) => {
+                stats.failures += 1;
+                last_content = None;
+                last_skipped = None;
+                warn!(
+                    url,
+                    file,
+                    duration_ms,
+                    outcome = "failure",
+                    error = %e,
+                    "Sync failed"
+                );
             }
         }
     }
 
-    Ok(())
+    info!("Shutting down client");
+    Ok(stats)
 }
 
 /// Main client run function
 async fn run_client(config: ClientConfig) -> Result<()> {
-    // Build URL and print config
-    let url = build_url(&config);
-    print_config(&config);
+    // Load the encryption key, if any
+    let key = config
+        .key_file
+        .as_deref()
+        .map(ContentKey::from_file)
+        .transpose()?;
+
+    if let (Some(input), Some(key)) = (&config.seal, &key) {
+        let plaintext = std::fs::read(input).map_err(|source| SyncError::Io {
+            path: input.clone(),
+            source,
+        })?;
+        print!("{}", key.seal(&plaintext)?);
+        return Ok(());
+    }
 
-    // Create HTTP client and clipboard
+    // Create HTTP client
     let client = Client::new();
-    let clipboard = Clipboard::new()?;
+    if config.list {
+        return list_files(&client, &config).await;
+    }
+
+    // Build URL and log config
+    let url = build_url(&config)?;
+    log_config(&config);
+
+    // Create clipboard and change hooks
+    let mut sink: Box<dyn ClipboardSink> = match &config.output_file {
+        Some(path) => Box::new(FileSink::new(path.clone())),
+        None => Box::new(ArboardSink::new()?),
+    };
+    let hooks = ChangeHooks::new(
+        config.notify,
+        config.notify_hide_content,
+        config.on_change.clone(),
+        Duration::from_secs(config.hook_timeout),
+    );
 
     // Create shutdown channel
     let (shutdown_tx, mut shutdown_rx) = oneshot::channel();
@@ -156,17 +591,482 @@ async fn run_client(config: ClientConfig) -> Result<()> {
     });
 
     // Run main client loop
-    run_client_loop(&config, &client, &url, clipboard, &mut shutdown_rx).await?;
+    let stats = run_client_loop(
+        &config,
+        &client,
+        &url,
+        sink.as_mut(),
+        key.as_ref(),
+        &hooks,
+        &mut shutdown_rx,
+    )
+    .await?;
 
-    println!("Client gracefully exited.");
+    log_summary(&stats);
     Ok(())
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
     // Parse command line arguments
     let config = ClientConfig::parse();
+    init_logging(&config);
+
+    // Run the client, each error class exits with its own code
+    match run_client(config).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{LogCapture, MemorySink, MockResponse, mock_server, mock_server_at};
+
+    fn config(http_address: &str) -> ClientConfig {
+        ClientConfig::parse_from(["client", "--http-address", http_address, "--interval", "1"])
+    }
+
+    /// Hooks as `run_client` sets them up
+    fn hooks(config: &ClientConfig) -> ChangeHooks {
+        ChangeHooks::new(
+            config.notify,
+            config.notify_hide_content,
+            config.on_change.clone(),
+            Duration::from_secs(config.hook_timeout),
+        )
+    }
+
+    /// Poll the server once, returning the counters and what reached the clipboard
+    async fn poll_once(config: &ClientConfig, key: Option<&ContentKey>) -> (SyncStats, MemorySink) {
+        poll_for(config, key, Duration::from_millis(300)).await
+    }
+
+    /// Run the client loop for `duration`, returning the counters and what reached the clipboard
+    async fn poll_for(
+        config: &ClientConfig,
+        key: Option<&ContentKey>,
+        duration: Duration,
+    ) -> (SyncStats, MemorySink) {
+        let url = build_url(config).unwrap();
+        let mut sink = MemorySink::default();
+        let (shutdown_tx, mut shutdown_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            tokio::time::sleep(duration).await;
+            let _ = shutdown_tx.send(());
+        });
+        let stats = run_client_loop(
+            config,
+            &Client::new(),
+            &url,
+            &mut sink,
+            key,
+            &hooks(config),
+            &mut shutdown_rx,
+        )
+        .await
+        .unwrap();
+        (stats, sink)
+    }
+
+    /// Poll the server once and return the JSON log events
+    async fn poll_logged_as_json(response: MockResponse) -> Vec<serde_json::Value> {
+        let server = mock_server(response).await;
+        let mut config = config(&server);
+        config.log_format = LogFormat::Json;
+        let logs = LogCapture::default();
+        let _subscriber = tracing::subscriber::set_default(log_subscriber(&config, logs.clone()));
+        poll_once(&config, None).await;
+
+        logs.output()
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("log line isn't JSON"))
+            .collect()
+    }
+
+    /// The event reporting the poll's outcome
+    fn poll_event(events: &[serde_json::Value]) -> &serde_json::Value {
+        events
+            .iter()
+            .find(|event| event.get("outcome").is_some())
+            .expect("no event with an outcome")
+    }
+
+    #[tokio::test]
+    async fn successful_poll_is_logged_as_json() {
+        let events = poll_logged_as_json(MockResponse::ok(b"hello")).await;
+        let event = poll_event(&events);
+        assert_eq!(event["level"], "INFO");
+        assert_eq!(event["message"], "Clipboard updated");
+        assert_eq!(event["outcome"], "success");
+        assert_eq!(event["file"], "default");
+        assert_eq!(event["bytes"], 5);
+        assert!(event["url"].as_str().unwrap().ends_with("/file"));
+        assert!(event["duration_ms"].is_u64());
+    }
+
+    #[tokio::test]
+    async fn failed_poll_is_logged_as_json() {
+        let events = poll_logged_as_json(MockResponse {
+            status: 500,
+            ..MockResponse::ok(b"disk on fire")
+        })
+        .await;
+        let event = poll_event(&events);
+        assert_eq!(event["level"], "WARN");
+        assert_eq!(event["message"], "Sync failed");
+        assert_eq!(event["outcome"], "failure");
+        assert_eq!(event["file"], "default");
+        assert!(event["url"].as_str().unwrap().ends_with("/file"));
+        assert!(event["duration_ms"].is_u64());
+        let error = event["error"].as_str().unwrap();
+        assert!(
+            error.contains("500") && error.contains("disk on fire"),
+            "{}",
+            error
+        );
+    }
+
+    /// Key file with 32 bytes of `byte`
+    fn content_key(name: &str, byte: u8) -> ContentKey {
+        let path =
+            std::env::temp_dir().join(format!("rs_sync_{}_{}.key", name, std::process::id()));
+        std::fs::write(&path, hex::encode([byte; 32])).unwrap();
+        let key = ContentKey::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        key
+    }
+
+    #[tokio::test]
+    async fn encrypted_content_is_decrypted_for_the_clipboard() {
+        let key = content_key("client_round_trip", 5);
+        let sealed = key.seal(b"clipboard secret").unwrap();
+        let server = mock_server(MockResponse::ok(sealed.as_bytes())).await;
+
+        let (stats, sink) = poll_once(&config(&server), Some(&key)).await;
+        assert_eq!(stats.syncs, 1);
+        assert_eq!(sink.writes, vec![b"clipboard secret".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn wrong_key_leaves_the_clipboard_untouched() {
+        let sealed = content_key("client_sealing", 5)
+            .seal(b"clipboard secret")
+            .unwrap();
+        let server = mock_server(MockResponse::ok(sealed.as_bytes())).await;
+
+        let (stats, sink) =
+            poll_once(&config(&server), Some(&content_key("client_other", 6))).await;
+        assert_eq!((stats.syncs, stats.failures), (0, 1));
+        assert!(sink.writes.is_empty(), "{:?}", sink.writes);
+    }
+
+    #[tokio::test]
+    async fn verified_content_reaches_the_clipboard() {
+        let response = MockResponse::ok(b"complete content").with_checksum();
+        let server = mock_server(response).await;
+
+        let (stats, sink) = poll_once(&config(&server), None).await;
+        assert_eq!(stats.syncs, 1);
+        assert_eq!(sink.writes, vec![b"complete content".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn tampered_body_leaves_the_clipboard_untouched() {
+        // The checksum is of the complete content, the body loses its end on the way
+        let mut response = MockResponse::ok(b"complete content").with_checksum();
+        response.body.truncate(8);
+        let server = mock_server(response).await;
+        let config = config(&server);
+        let logs = LogCapture::default();
+        let _subscriber = tracing::subscriber::set_default(log_subscriber(&config, logs.clone()));
+
+        let (stats, sink) = poll_once(&config, None).await;
+        assert_eq!((stats.syncs, stats.failures), (0, 1));
+        assert!(sink.writes.is_empty(), "{:?}", sink.writes);
+        // The damaged body was fetched again once before the poll failed
+        let mismatches = logs
+            .output()
+            .matches("content was damaged in transit")
+            .count();
+        assert_eq!(mismatches, 2);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn change_hook_skips_unchanged_content() {
+        let server = mock_server(MockResponse::ok(b"same content")).await;
+        let log = std::env::temp_dir().join(format!("rs_sync_hook_runs_{}", std::process::id()));
+        let _ = std::fs::remove_file(&log);
+        let mut config = config(&server);
+        config.on_change = Some(format!(
+            "cat >> '{}'; echo >> '{}'",
+            log.display(),
+            log.display()
+        ));
+
+        // Two polls a second apart serve the same content
+        let (stats, sink) = poll_for(&config, None, Duration::from_millis(1500)).await;
+        assert_eq!(stats.syncs, 2);
+        assert_eq!(sink.writes.len(), 1);
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let runs = std::fs::read_to_string(&log).unwrap();
+        std::fs::remove_file(&log).unwrap();
+        assert_eq!(runs, "same content\n");
+    }
+
+    #[tokio::test]
+    async fn shutdown_cancels_the_request_in_flight() {
+        let server = mock_server(MockResponse {
+            delay: Duration::from_secs(2),
+            ..MockResponse::ok(b"late content")
+        })
+        .await;
+        let config = config(&server);
+        let url = build_url(&config).unwrap();
+        let mut sink = MemorySink::default();
+        let (shutdown_tx, mut shutdown_rx) = oneshot::channel();
+
+        // Shutdown begins while the server is still preparing its answer
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            let _ = shutdown_tx.send(());
+        });
+        let stats = tokio::time::timeout(
+            Duration::from_secs(1),
+            run_client_loop(
+                &config,
+                &Client::new(),
+                &url,
+                &mut sink,
+                None,
+                &hooks(&config),
+                &mut shutdown_rx,
+            ),
+        )
+        .await
+        .expect("shutdown waited for the response")
+        .unwrap();
+
+        assert_eq!(stats.syncs, 0);
+        assert!(sink.writes.is_empty(), "{:?}", sink.writes);
+    }
+
+    #[tokio::test]
+    async fn non_text_content_is_skipped_with_one_warning() {
+        let server = mock_server(MockResponse::ok(b"ELF\0\x01")).await;
+        let config = config(&server);
+        let logs = LogCapture::default();
+        let _subscriber = tracing::subscriber::set_default(log_subscriber(&config, logs.clone()));
+
+        // Two polls a second apart serve the same content
+        let (stats, sink) = poll_for(&config, None, Duration::from_millis(1500)).await;
+        assert_eq!((stats.syncs, stats.failures), (0, 2));
+        assert!(sink.writes.is_empty(), "{:?}", sink.writes);
+        assert_eq!(logs.output().matches("is not text").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn binary_policy_copies_non_text_content_once() {
+        let server = mock_server(MockResponse::ok(b"ELF\0\x01")).await;
+        let mut config = config(&server);
+        config.non_text_policy = NonTextPolicy::Binary;
+
+        let (stats, sink) = poll_for(&config, None, Duration::from_millis(1500)).await;
+        assert_eq!(stats.syncs, 2);
+        assert_eq!(sink.writes, vec![b"ELF\0\x01".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn oversized_content_leaves_the_clipboard_untouched() {
+        let body = vec![b'x'; MAX_CONTENT_BYTES as usize + 1];
+        let server = mock_server(MockResponse::ok(&body)).await;
+        let config = config(&server);
+        let logs = LogCapture::default();
+        let _subscriber = tracing::subscriber::set_default(log_subscriber(&config, logs.clone()));
+
+        let (stats, sink) = poll_once(&config, None).await;
+        assert_eq!((stats.syncs, stats.failures), (0, 1));
+        assert!(sink.writes.is_empty());
+        assert!(
+            logs.output()
+                .contains("exceeds the limit of 33554432 bytes"),
+            "{}",
+            logs.output()
+        );
+    }
+
+    #[tokio::test]
+    async fn output_file_receives_the_content() {
+        let server = mock_server(MockResponse::ok(b"headless content")).await;
+        let config = config(&server);
+        let url = build_url(&config).unwrap();
+        let path = std::env::temp_dir().join(format!("rs_sync_output_{}", std::process::id()));
+        let mut sink = FileSink::new(path.clone());
+        let (shutdown_tx, mut shutdown_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            let _ = shutdown_tx.send(());
+        });
+
+        let stats = run_client_loop(
+            &config,
+            &Client::new(),
+            &url,
+            &mut sink,
+            None,
+            &hooks(&config),
+            &mut shutdown_rx,
+        )
+        .await
+        .unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(stats.syncs, 1);
+        assert_eq!(written, "headless content");
+    }
 
-    // Run the client
-    run_client(config).await
+    #[tokio::test]
+    async fn unreachable_server_is_given_up_after_max_reconnects() {
+        let server = unreachable_server().await;
+        let mut config = config(&server);
+        config.max_reconnects = Some(2);
+        let url = build_url(&config).unwrap();
+        let mut sink = MemorySink::default();
+        let (_shutdown_tx, mut shutdown_rx) = oneshot::channel();
+
+        // The second attempt follows a pause of --interval
+        let started = Instant::now();
+        let error = tokio::time::timeout(
+            Duration::from_secs(5),
+            run_client_loop(
+                &config,
+                &Client::new(),
+                &url,
+                &mut sink,
+                None,
+                &hooks(&config),
+                &mut shutdown_rx,
+            ),
+        )
+        .await
+        .expect("the client kept trying")
+        .unwrap_err();
+
+        assert!(matches!(error, SyncError::Network { .. }), "{:?}", error);
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn shutdown_during_a_backoff_exits_promptly() {
+        let server = unreachable_server().await;
+        let mut config = config(&server);
+        // The first failed attempt is followed by a pause of --interval
+        config.interval = 300;
+        config.max_backoff = 600;
+        let url = build_url(&config).unwrap();
+        let mut sink = MemorySink::default();
+        let (shutdown_tx, mut shutdown_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            let _ = shutdown_tx.send(());
+        });
+
+        let stats = tokio::time::timeout(
+            Duration::from_secs(5),
+            run_client_loop(
+                &config,
+                &Client::new(),
+                &url,
+                &mut sink,
+                None,
+                &hooks(&config),
+                &mut shutdown_rx,
+            ),
+        )
+        .await
+        .expect("shutdown waited for the backoff to end")
+        .unwrap();
+
+        assert_eq!((stats.syncs, stats.failures), (0, 1));
+    }
+
+    #[tokio::test]
+    async fn server_coming_back_is_synced_again() {
+        let server = unreachable_server().await;
+        let config = config(&server);
+        let logs = LogCapture::default();
+        let _subscriber = tracing::subscriber::set_default(log_subscriber(&config, logs.clone()));
+
+        // Up before the second attempt
+        let address = server.trim_start_matches("http://").parse().unwrap();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            mock_server_at(address, MockResponse::ok(b"back again")).await;
+        });
+        let (stats, sink) = poll_for(&config, None, Duration::from_millis(1500)).await;
+
+        assert_eq!((stats.syncs, stats.failures), (1, 1));
+        assert_eq!(sink.writes, vec![b"back again".to_vec()]);
+        let output = logs.output();
+        assert!(
+            output.contains("Server unreachable, retrying"),
+            "{}",
+            output
+        );
+        assert!(output.contains("Server reachable again"), "{}", output);
+    }
+
+    /// Exit code the client ends with for `args`
+    async fn exit_code(args: &[&str]) -> u8 {
+        let config =
+            ClientConfig::parse_from(std::iter::once("client").chain(args.iter().copied()));
+        run_client(config).await.unwrap_err().exit_code()
+    }
+
+    /// Base URL of a port nothing listens on
+    async fn unreachable_server() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    }
+
+    #[tokio::test]
+    async fn each_error_class_exits_with_its_code() {
+        let key = std::env::temp_dir().join(format!("rs_sync_short_{}.key", std::process::id()));
+        std::fs::write(&key, "abcd").unwrap();
+        let short_key = exit_code(&["--key-file", key.to_str().unwrap(), "--list"]).await;
+        std::fs::remove_file(&key).unwrap();
+        assert_eq!(short_key, 3);
+
+        assert_eq!(exit_code(&["--http-address", "ftp://server"]).await, 3);
+        let missing_key = key.with_extension("missing");
+        assert_eq!(
+            exit_code(&["--key-file", missing_key.to_str().unwrap()]).await,
+            4
+        );
+        let server = unreachable_server().await;
+        assert_eq!(exit_code(&["--http-address", &server, "--list"]).await, 5);
+        let server = mock_server(MockResponse {
+            status: 404,
+            ..MockResponse::ok(b"no such route")
+        })
+        .await;
+        assert_eq!(exit_code(&["--http-address", &server, "--list"]).await, 6);
+        let server = mock_server(MockResponse::ok(b"not a list")).await;
+        assert_eq!(exit_code(&["--http-address", &server, "--list"]).await, 10);
+    }
+
+    #[test]
+    fn help_lists_the_exit_codes() {
+        use clap::CommandFactory;
+
+        let help = ClientConfig::command().render_help().to_string();
+        assert!(help.trim_end().ends_with(EXIT_CODES_HELP), "{}", help);
+    }
 }
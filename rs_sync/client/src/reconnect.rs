@@ -0,0 +1,97 @@
+//! Pausing between attempts while the server can't be reached
+//!
+//! A server that restarts, or drops the connection in the middle of a response, is retried with
+//! growing pauses starting at the poll interval, so a long outage doesn't cost a request every few
+//! seconds. The client keeps trying until `--max-reconnects` attempts in a row failed, forever by
+//! default.
+
+use std::time::Duration;
+
+/// Failed attempts to reach the server in a row, and the pause before the next one
+#[derive(Debug)]
+pub struct Reconnect {
+    interval: Duration,
+    max_backoff: Duration,
+    max_attempts: Option<u32>,
+    failures: u32,
+}
+
+impl Reconnect {
+    pub fn new(interval: Duration, max_backoff: Duration, max_attempts: Option<u32>) -> Self {
+        Self {
+            interval,
+            max_backoff: max_backoff.max(interval),
+            max_attempts,
+            failures: 0,
+        }
+    }
+
+    /// Count a failed attempt, returning the pause before the next one, or `None` to give up
+    pub fn failed(&mut self) -> Option<Duration> {
+        self.failures = self.failures.saturating_add(1);
+        if self.max_attempts.is_some_and(|max| self.failures >= max) {
+            return None;
+        }
+        let factor = 1u32.checked_shl(self.failures - 1).unwrap_or(u32::MAX);
+        Some(self.interval.saturating_mul(factor).min(self.max_backoff))
+    }
+
+    /// The server answered again, returning how many attempts had failed
+    pub fn succeeded(&mut self) -> u32 {
+        std::mem::take(&mut self.failures)
+    }
+
+    /// Failed attempts in a row so far
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn pause_doubles_up_to_the_limit() {
+        let mut reconnect = Reconnect::new(2 * SECOND, 10 * SECOND, None);
+        let pauses: Vec<_> = (0..5).map(|_| reconnect.failed().unwrap()).collect();
+        assert_eq!(
+            pauses,
+            [2 * SECOND, 4 * SECOND, 8 * SECOND, 10 * SECOND, 10 * SECOND]
+        );
+        assert_eq!(reconnect.failures(), 5);
+
+        // Far past the point where the factor overflows
+        for _ in 0..100 {
+            assert_eq!(reconnect.failed(), Some(10 * SECOND));
+        }
+    }
+
+    #[test]
+    fn success_starts_over() {
+        let mut reconnect = Reconnect::new(SECOND, 60 * SECOND, None);
+        reconnect.failed();
+        reconnect.failed();
+
+        assert_eq!(reconnect.succeeded(), 2);
+        assert_eq!(reconnect.failures(), 0);
+        assert_eq!(reconnect.failed(), Some(SECOND));
+    }
+
+    #[test]
+    fn gives_up_after_the_last_attempt() {
+        let mut reconnect = Reconnect::new(SECOND, 60 * SECOND, Some(3));
+        assert_eq!(reconnect.failed(), Some(SECOND));
+        assert_eq!(reconnect.failed(), Some(2 * SECOND));
+        assert_eq!(reconnect.failed(), None);
+    }
+
+    #[test]
+    fn limit_below_the_interval_is_the_interval() {
+        let mut reconnect = Reconnect::new(5 * SECOND, SECOND, None);
+        assert_eq!(reconnect.failed(), Some(5 * SECOND));
+        assert_eq!(reconnect.failed(), Some(5 * SECOND));
+    }
+}
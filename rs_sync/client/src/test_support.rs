@@ -0,0 +1,154 @@
+//! Stand-ins for the server and the clipboard used by the client's tests
+
+use crate::clipboard::ClipboardSink;
+use crate::error::Result;
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Clipboard keeping everything written to it
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    /// Content of every write, in order
+    pub writes: Vec<Vec<u8>>,
+    text: Option<String>,
+}
+
+impl ClipboardSink for MemorySink {
+    fn set_text(&mut self, text: &str) -> Result<()> {
+        self.writes.push(text.as_bytes().to_vec());
+        self.text = Some(text.to_string());
+        Ok(())
+    }
+
+    fn set_binary(&mut self, bytes: &[u8]) -> Result<()> {
+        self.writes.push(bytes.to_vec());
+        self.text = None;
+        Ok(())
+    }
+
+    fn get_text(&mut self) -> Result<Option<String>> {
+        Ok(self.text.clone())
+    }
+}
+
+/// Log output kept in memory, for subscribers built with `with_writer`
+#[derive(Debug, Clone, Default)]
+pub struct LogCapture(Arc<Mutex<Vec<u8>>>);
+
+impl LogCapture {
+    /// Everything logged so far
+    pub fn output(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+impl Write for LogCapture {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'writer> tracing_subscriber::fmt::MakeWriter<'writer> for LogCapture {
+    type Writer = Self;
+
+    fn make_writer(&'writer self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+/// Response the mock server sends to every request
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// Time the server takes before answering
+    pub delay: Duration,
+}
+
+impl MockResponse {
+    /// `200 OK` with `body`, answered right away
+    pub fn ok(body: &[u8]) -> Self {
+        Self {
+            status: 200,
+            headers: Vec::new(),
+            body: body.to_vec(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// Send the SHA-256 of the current body in `X-Content-SHA256`, like the server does
+    pub fn with_checksum(mut self) -> Self {
+        let checksum = hex::encode(Sha256::digest(&self.body));
+        self.headers
+            .push(("X-Content-SHA256".to_string(), checksum));
+        self
+    }
+}
+
+/// Serve `response` to every request on a local port, returning the server's base URL
+pub async fn mock_server(response: MockResponse) -> String {
+    mock_server_at("127.0.0.1:0".parse().unwrap(), response).await
+}
+
+/// Serve `response` to every request on `address`, returning the server's base URL
+pub async fn mock_server_at(address: SocketAddr, response: MockResponse) -> String {
+    let listener = TcpListener::bind(address).await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(answer(stream, response.clone()));
+        }
+    });
+    format!("http://{}", address)
+}
+
+async fn answer(mut stream: TcpStream, response: MockResponse) {
+    // Read the request head and body before answering
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 4096];
+    loop {
+        let Ok(n) = stream.read(&mut buffer).await else {
+            return;
+        };
+        if n == 0 {
+            return;
+        }
+        request.extend_from_slice(&buffer[..n]);
+        let text = String::from_utf8_lossy(&request);
+        if let Some(head_end) = text.find("\r\n\r\n") {
+            let body_length = text[..head_end]
+                .lines()
+                .filter_map(|line| line.split_once(':'))
+                .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            if request.len() >= head_end + 4 + body_length {
+                break;
+            }
+        }
+    }
+
+    tokio::time::sleep(response.delay).await;
+    let mut head = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(&response.body).await;
+}
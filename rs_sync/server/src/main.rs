@@ -1,10 +1,22 @@
 use anyhow::Result;
-use axum::{Extension, Router, response::IntoResponse, routing::post};
-use chrono::Local;
+use axum::{
+    Extension, Json, Router,
+    body::Bytes,
+    extract::Query,
+    http::{HeaderMap, HeaderName, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use chrono::{DateTime, Local};
 use clap::Parser;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
 use std::fs::read_to_string;
 use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
 use tokio::signal;
 use tokio::sync::oneshot;
@@ -19,37 +31,240 @@ struct ServerConfig {
     #[clap(short, long, default_value = "3000")]
     port: u16,
 
+    /// File served as the "default" entry (used when a request names no file)
     #[clap(short, long, default_value = "content.txt")]
     file_path: String,
+
+    /// Named file entry as name=path, may be repeated
+    #[clap(long = "file", value_name = "NAME=PATH", value_parser = parse_file_entry)]
+    files: Vec<(String, PathBuf)>,
+
+    /// Also accept raw file paths in requests (legacy clients)
+    #[clap(long)]
+    allow_raw_paths: bool,
+
+    /// Accept raw file paths only inside this directory, may be repeated (implies raw paths)
+    #[clap(long = "allow-root", value_name = "DIR")]
+    allow_roots: Vec<PathBuf>,
+
+    /// Accept PUT /file replacing the content of a named entry
+    #[clap(long)]
+    allow_uploads: bool,
+
+    /// Seconds to wait for in-flight requests to finish on shutdown
+    #[clap(short = 'd', long, default_value = "10")]
+    drain_timeout: u64,
+}
+
+/// Name of the entry backed by --file-path
+const DEFAULT_ENTRY: &str = "default";
+
+/// Parse a --file argument of the form name=path
+fn parse_file_entry(value: &str) -> Result<(String, PathBuf), String> {
+    match value.split_once('=') {
+        Some((name, path)) if !name.is_empty() && !path.is_empty() => {
+            Ok((name.to_string(), PathBuf::from(path)))
+        }
+        _ => Err(format!("expected NAME=PATH, got '{}'", value)),
+    }
+}
+
+// Which raw file paths requests may name
+#[derive(Clone)]
+enum RawPaths {
+    /// Only named entries can be requested
+    Disabled,
+    /// Any path readable by the server (legacy behavior)
+    Anywhere,
+    /// Paths inside these canonicalized directories
+    Within(Vec<PathBuf>),
 }
 
-// App state containing the file content
+// App state containing the file registry
 #[derive(Clone)]
 struct AppState {
-    file_path: String,
+    /// Named files that clients may request
+    files: BTreeMap<String, PathBuf>,
+    /// Whether and where requests may name filesystem paths directly
+    raw_paths: RawPaths,
+    /// Whether clients may replace the content of named entries
+    uploads: bool,
 }
 
-// Request body structure for file path
+impl AppState {
+    /// Build the registry from configuration; --file-path becomes the "default" entry
+    /// unless an explicit --file entry already uses that name
+    fn from_config(config: &ServerConfig) -> Result<Self> {
+        let mut files: BTreeMap<String, PathBuf> = config.files.iter().cloned().collect();
+        files
+            .entry(DEFAULT_ENTRY.to_string())
+            .or_insert_with(|| PathBuf::from(&config.file_path));
+
+        let raw_paths = if !config.allow_roots.is_empty() {
+            let roots = config
+                .allow_roots
+                .iter()
+                .map(|root| {
+                    root.canonicalize().map_err(|e| {
+                        anyhow::anyhow!("Invalid --allow-root {}: {}", root.display(), e)
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            RawPaths::Within(roots)
+        } else if config.allow_raw_paths {
+            RawPaths::Anywhere
+        } else {
+            RawPaths::Disabled
+        };
+
+        Ok(Self {
+            files,
+            raw_paths,
+            uploads: config.allow_uploads,
+        })
+    }
+
+    /// Resolve the file a request refers to
+    fn resolve(&self, request: &FileRequest) -> Result<PathBuf, ResolveError> {
+        if let Some(name) = &request.name {
+            return self
+                .files
+                .get(name)
+                .cloned()
+                .ok_or_else(|| ResolveError::UnknownName(name.clone()));
+        }
+
+        let Some(path) = &request.file_path else {
+            return Ok(self.files[DEFAULT_ENTRY].clone());
+        };
+
+        match &self.raw_paths {
+            RawPaths::Disabled => Err(ResolveError::RawPathsDisabled),
+            RawPaths::Anywhere => Ok(PathBuf::from(path)),
+            RawPaths::Within(roots) => {
+                // Canonicalize so ".." and symlinks can't step outside the roots
+                let path = PathBuf::from(path)
+                    .canonicalize()
+                    .map_err(|_| ResolveError::OutsideRoots)?;
+                if roots.iter().any(|root| path.starts_with(root)) {
+                    Ok(path)
+                } else {
+                    Err(ResolveError::OutsideRoots)
+                }
+            }
+        }
+    }
+
+    /// Build the error response for a request that couldn't be resolved
+    fn resolve_error_response(&self, error: ResolveError) -> Response {
+        let (status, message) = match error {
+            ResolveError::UnknownName(name) => (
+                StatusCode::NOT_FOUND,
+                format!("Unknown file name: {}", name),
+            ),
+            ResolveError::RawPathsDisabled => (
+                StatusCode::BAD_REQUEST,
+                "Raw file paths are disabled, request a name from GET /files".to_string(),
+            ),
+            ResolveError::OutsideRoots => (
+                StatusCode::FORBIDDEN,
+                "File does not exist or is outside the allowed roots, see GET /files".to_string(),
+            ),
+        };
+        self.error_response(status, message)
+    }
+
+    /// Build an error response listing the names that can be requested
+    fn error_response(&self, status: StatusCode, message: String) -> Response {
+        let body = ErrorBody {
+            error: message,
+            available: self.files.keys().cloned().collect(),
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+// Reasons a file request can't be resolved
+enum ResolveError {
+    UnknownName(String),
+    RawPathsDisabled,
+    OutsideRoots,
+}
+
+// Request body selecting a file by name (or by path in compatibility mode)
 #[derive(serde::Deserialize)]
 struct FileRequest {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
     file_path: Option<String>,
 }
 
+// Query of an upload, naming the entry to replace ("default" when absent)
+#[derive(serde::Deserialize)]
+struct UploadQuery {
+    #[serde(default)]
+    name: Option<String>,
+}
+
+// Error body returned for rejected requests, listing the names that can be requested
+#[derive(serde::Serialize)]
+struct ErrorBody {
+    error: String,
+    available: Vec<String>,
+}
+
+// Entry returned by GET /files
+#[derive(serde::Serialize)]
+struct FileEntry {
+    /// Value to request: an entry name, or a path for files under an allowed root
+    name: String,
+    /// "name" or "path", telling clients which request field to use
+    kind: &'static str,
+    size: Option<u64>,
+    modified: Option<String>,
+}
+
+impl FileEntry {
+    /// Describe a file, leaving size and modification time empty when it can't be read
+    fn new(name: String, kind: &'static str, path: &Path) -> Self {
+        let metadata = std::fs::metadata(path).ok();
+        Self {
+            name,
+            kind,
+            size: metadata.as_ref().map(|m| m.len()),
+            modified: metadata
+                .and_then(|m| m.modified().ok())
+                .map(|time| DateTime::<Local>::from(time).to_rfc3339()),
+        }
+    }
+}
+
 // Handler for the file content endpoint
 async fn get_file_content(
     Extension(state): Extension<Arc<AppState>>,
     axum::Json(request): axum::Json<FileRequest>,
-) -> impl IntoResponse {
-    // Use file path from request body if provided, otherwise use default
-    let file_path = request.file_path.as_ref().unwrap_or(&state.file_path);
-
+) -> Response {
     let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
+
+    let path = match state.resolve(&request) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!(
+                "[SERVER] {} - ❌ Rejected file request (name: {:?}, path: {:?})",
+                timestamp, request.name, request.file_path
+            );
+            return state.resolve_error_response(err);
+        }
+    };
+    let file_path = path.display();
+
     println!(
         "[SERVER] {} - Received file request for: {}",
         timestamp, file_path
     );
 
-    let result = read_to_string(file_path);
+    let result = read_to_string(&path);
     match &result {
         Ok(content) => {
             println!(
@@ -68,13 +283,164 @@ async fn get_file_content(
     }
     println!(); // Add empty line to separate requests
 
-    result.unwrap_or_else(|err| format!("Failed to read file: {} - {}", file_path, err))
+    match result {
+        Ok(content) => content_response(content),
+        Err(err) => format!("Failed to read file: {} - {}", file_path, err).into_response(),
+    }
+}
+
+/// Header carrying the hex SHA-256 of the response body
+static CONTENT_SHA256: HeaderName = HeaderName::from_static("x-content-sha256");
+
+/// Build a response for file content with its SHA-256 as checksum header and ETag
+fn content_response(content: String) -> Response {
+    (checksum_headers(content.as_bytes()), content).into_response()
+}
+
+/// Checksum header and ETag of content
+fn checksum_headers(content: &[u8]) -> [(HeaderName, String); 2] {
+    let checksum = hex::encode(Sha256::digest(content));
+    let etag = format!("\"{}\"", checksum);
+    [(CONTENT_SHA256.clone(), checksum), (header::ETAG, etag)]
+}
+
+// Handler replacing the content of a named entry
+async fn put_file_content(
+    Extension(state): Extension<Arc<AppState>>,
+    Query(query): Query<UploadQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
+
+    if !state.uploads {
+        eprintln!(
+            "[SERVER] {} - ❌ Rejected upload (name: {:?}), uploads are disabled",
+            timestamp, query.name
+        );
+        return state.error_response(
+            StatusCode::FORBIDDEN,
+            "Uploads are disabled, start the server with --allow-uploads".to_string(),
+        );
+    }
+
+    // Uploads only replace named entries, never raw paths
+    let request = FileRequest {
+        name: query.name,
+        file_path: None,
+    };
+    let path = match state.resolve(&request) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!(
+                "[SERVER] {} - ❌ Rejected upload (name: {:?})",
+                timestamp, request.name
+            );
+            return state.resolve_error_response(err);
+        }
+    };
+    let file_path = path.display();
+
+    // A body damaged in transit must not replace the file
+    let checksum = hex::encode(Sha256::digest(&body));
+    if let Some(expected) = headers.get(&CONTENT_SHA256)
+        && !expected
+            .to_str()
+            .is_ok_and(|expected| expected.eq_ignore_ascii_case(&checksum))
+    {
+        eprintln!(
+            "[SERVER] {} - ❌ Rejected upload for {}: checksum mismatch",
+            timestamp, file_path
+        );
+        return state.error_response(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!(
+                "Checksum mismatch: {} is {:?}, the body hashes to {}",
+                CONTENT_SHA256, expected, checksum
+            ),
+        );
+    }
+    if std::str::from_utf8(&body).is_err() {
+        return state.error_response(
+            StatusCode::BAD_REQUEST,
+            "Content must be UTF-8 text".to_string(),
+        );
+    }
+
+    match replace_file(&path, &body) {
+        Ok(()) => {
+            println!(
+                "[SERVER] {} - ✓ Replaced file: {} ({} bytes)",
+                timestamp,
+                file_path,
+                body.len()
+            );
+            (StatusCode::NO_CONTENT, checksum_headers(&body)).into_response()
+        }
+        Err(err) => {
+            eprintln!(
+                "[SERVER] {} - ❌ Error writing file {}: {}",
+                timestamp, file_path, err
+            );
+            state.error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to write file: {} - {}", file_path, err),
+            )
+        }
+    }
+}
+
+/// Replace a file's content through a temporary file, so readers never see it half-written
+fn replace_file(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let Some(file_name) = path.file_name() else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "not a file path",
+        ));
+    };
+    let mut temp_name = OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(".upload");
+    let temp_path = path.with_file_name(temp_name);
+    std::fs::write(&temp_path, content)?;
+    std::fs::rename(&temp_path, path)
+}
+
+// Handler listing the available file names
+async fn list_files(Extension(state): Extension<Arc<AppState>>) -> Json<Vec<FileEntry>> {
+    let mut entries: Vec<FileEntry> = state
+        .files
+        .iter()
+        .map(|(name, path)| FileEntry::new(name.clone(), "name", path))
+        .collect();
+
+    // Files directly inside each allowed root can be requested by path
+    if let RawPaths::Within(roots) = &state.raw_paths {
+        for root in roots {
+            let Ok(dir) = std::fs::read_dir(root) else {
+                continue;
+            };
+            let mut paths: Vec<PathBuf> = dir
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.is_file())
+                .collect();
+            paths.sort();
+            entries.extend(
+                paths
+                    .iter()
+                    .map(|path| FileEntry::new(path.display().to_string(), "path", path)),
+            );
+        }
+    }
+
+    Json(entries)
 }
 
 /// Create and configure the Axum router
 fn create_router(state: Arc<AppState>) -> Router {
     Router::new()
-        .route("/file", post(get_file_content))
+        .route("/file", post(get_file_content).put(put_file_content))
+        .route("/files", get(list_files))
         .layer(Extension(state))
 }
 
@@ -119,18 +485,28 @@ async fn run_server(config: ServerConfig) -> Result<()> {
     let addr = parse_socket_addr(&config)?;
 
     println!("[SERVER] Server listening on http://{}", addr);
-    println!("[SERVER] Serving file: {}", config.file_path);
+    let state = Arc::new(AppState::from_config(&config)?);
+    for (name, path) in &state.files {
+        println!("[SERVER] Serving file '{}': {}", name, path.display());
+    }
+    if state.uploads {
+        println!("[SERVER] Uploads replacing named files are allowed");
+    }
+    match &state.raw_paths {
+        RawPaths::Disabled => {}
+        RawPaths::Anywhere => println!("[SERVER] Raw file paths in requests are allowed"),
+        RawPaths::Within(roots) => {
+            for root in roots {
+                println!("[SERVER] Raw file paths allowed under: {}", root.display());
+            }
+        }
+    }
     println!("[SERVER] Press Ctrl+C to gracefully shutdown the server...");
     println!(
         "[SERVER] Server started at {}",
         Local::now().format("%Y-%m-%d %H:%M:%S")
     );
 
-    // Create app state
-    let state = Arc::new(AppState {
-        file_path: config.file_path,
-    });
-
     // Create router
     let app = create_router(state);
 
@@ -138,24 +514,19 @@ async fn run_server(config: ServerConfig) -> Result<()> {
     let listener = TcpListener::bind(addr).await?;
 
     // Create shutdown channel
-    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
 
-    // Spawn server task
+    // Spawn server task; in-flight requests are allowed to finish once shutdown starts
     let server_handle = tokio::spawn(async move {
-        let serve_future = axum::serve(listener, app);
+        let result = axum::serve(listener, app)
+            .with_graceful_shutdown(async {
+                let _ = shutdown_rx.await;
+            })
+            .await;
 
-        // Wait for either server completion or shutdown signal
-        tokio::select! {
-            result = serve_future => {
-                if let Err(err) = result {
-                    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
-                    eprintln!("[SERVER] {} - ❌ Server error: {}", timestamp, err);
-                }
-            },
-            _ = shutdown_rx => {
-                let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
-                println!("[SERVER] {} - Shutting down server...", timestamp);
-            }
+        if let Err(err) = result {
+            let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
+            eprintln!("[SERVER] {} - ❌ Server error: {}", timestamp, err);
         }
     });
 
@@ -163,10 +534,25 @@ async fn run_server(config: ServerConfig) -> Result<()> {
     wait_for_shutdown().await?;
 
     // Send shutdown signal to server task
+    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
+    println!(
+        "[SERVER] {} - Shutting down server, draining in-flight requests (up to {}s)...",
+        timestamp, config.drain_timeout
+    );
     let _ = shutdown_tx.send(());
 
-    // Wait for server task to complete
-    server_handle.await?;
+    // Wait for in-flight requests to drain, bounded by the drain timeout
+    let drain_timeout = Duration::from_secs(config.drain_timeout);
+    if tokio::time::timeout(drain_timeout, server_handle)
+        .await
+        .is_err()
+    {
+        let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
+        eprintln!(
+            "[SERVER] {} - ❌ Drain timeout elapsed, dropping remaining connections",
+            timestamp
+        );
+    }
 
     let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
     println!("[SERVER] {} - Server gracefully shutdown.", timestamp);
@@ -181,3 +567,316 @@ async fn main() -> Result<()> {
     // Run the server
     run_server(config).await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty directory for a test's files
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rs_sync_{}_{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn state(args: &[&str]) -> AppState {
+        let config =
+            ServerConfig::parse_from(std::iter::once("server").chain(args.iter().copied()));
+        AppState::from_config(&config).unwrap()
+    }
+
+    fn by_name(name: &str) -> FileRequest {
+        FileRequest {
+            name: Some(name.to_string()),
+            file_path: None,
+        }
+    }
+
+    fn by_path(path: &Path) -> FileRequest {
+        FileRequest {
+            name: None,
+            file_path: Some(path.display().to_string()),
+        }
+    }
+
+    /// Status and JSON body of an error response
+    async fn error_response(
+        state: &AppState,
+        error: ResolveError,
+    ) -> (StatusCode, serde_json::Value) {
+        let response = state.resolve_error_response(error);
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[test]
+    fn names_resolve_to_their_files() {
+        let registry = state(&["--file-path", "main.txt", "--file", "notes=/srv/notes.txt"]);
+        assert_eq!(
+            registry.resolve(&by_name("notes")).ok(),
+            Some(PathBuf::from("/srv/notes.txt"))
+        );
+        assert_eq!(
+            registry.resolve(&by_name("default")).ok(),
+            Some(PathBuf::from("main.txt"))
+        );
+        let no_file = FileRequest {
+            name: None,
+            file_path: None,
+        };
+        assert_eq!(
+            registry.resolve(&no_file).ok(),
+            Some(PathBuf::from("main.txt"))
+        );
+
+        // An explicit entry named "default" replaces --file-path
+        let registry = state(&[
+            "--file-path",
+            "main.txt",
+            "--file",
+            "default=/srv/other.txt",
+        ]);
+        assert_eq!(
+            registry.resolve(&by_name("default")).ok(),
+            Some(PathBuf::from("/srv/other.txt"))
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_name_lists_the_available_names() {
+        let state = state(&["--file", "notes=/srv/notes.txt"]);
+        let error = state.resolve(&by_name("secrets")).err().unwrap();
+        assert!(matches!(&error, ResolveError::UnknownName(name) if name == "secrets"));
+
+        let (status, body) = error_response(&state, error).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"], "Unknown file name: secrets");
+        assert_eq!(body["available"], serde_json::json!(["default", "notes"]));
+    }
+
+    #[tokio::test]
+    async fn raw_paths_are_refused_by_default() {
+        let state = state(&[]);
+        let error = state
+            .resolve(&by_path(Path::new("/etc/passwd")))
+            .err()
+            .unwrap();
+        assert!(matches!(error, ResolveError::RawPathsDisabled));
+
+        let (status, body) = error_response(&state, error).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["available"], serde_json::json!(["default"]));
+    }
+
+    #[test]
+    fn legacy_mode_accepts_raw_paths() {
+        let state = state(&["--allow-raw-paths"]);
+        let path = Path::new("/var/lib/sync/content.txt");
+        assert_eq!(state.resolve(&by_path(path)).ok(), Some(path.to_path_buf()));
+        // Names keep working next to raw paths
+        assert_eq!(
+            state.resolve(&by_name("default")).ok(),
+            Some(PathBuf::from("content.txt"))
+        );
+    }
+
+    #[tokio::test]
+    async fn allowed_roots_confine_raw_paths() {
+        let dir = temp_dir("allowed_roots");
+        let root = dir.join("shared");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("inside.txt"), "inside").unwrap();
+        std::fs::write(dir.join("outside.txt"), "outside").unwrap();
+        let state = state(&["--allow-root", root.to_str().unwrap()]);
+
+        let inside = root.join("inside.txt").canonicalize().unwrap();
+        assert_eq!(state.resolve(&by_path(&inside)).ok(), Some(inside));
+        for path in [
+            root.join("../outside.txt"),
+            dir.join("outside.txt"),
+            root.join("missing.txt"),
+        ] {
+            let error = state.resolve(&by_path(&path)).err().unwrap();
+            assert!(
+                matches!(error, ResolveError::OutsideRoots),
+                "{}",
+                path.display()
+            );
+        }
+        let (status, _) = error_response(&state, ResolveError::OutsideRoots).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn listing_reflects_the_allowed_files() {
+        let dir = temp_dir("listing");
+        let root = dir.join("shared");
+        std::fs::create_dir_all(root.join("nested")).unwrap();
+        std::fs::write(root.join("a.txt"), "aaa").unwrap();
+        std::fs::write(root.join("b.txt"), "bb").unwrap();
+        std::fs::write(root.join("nested/c.txt"), "c").unwrap();
+        std::fs::write(dir.join("outside.txt"), "outside").unwrap();
+        let notes = dir.join("notes.txt");
+        std::fs::write(&notes, "notes").unwrap();
+        let state = state(&[
+            "--file-path",
+            dir.join("missing.txt").to_str().unwrap(),
+            "--file",
+            &format!("notes={}", notes.display()),
+            "--allow-root",
+            root.to_str().unwrap(),
+        ]);
+
+        let Json(entries) = list_files(Extension(Arc::new(state))).await;
+        let listed: Vec<(&str, &str, Option<u64>)> = entries
+            .iter()
+            .map(|entry| (entry.kind, entry.name.as_str(), entry.size))
+            .collect();
+        let root = root.canonicalize().unwrap();
+        let a = root.join("a.txt").display().to_string();
+        let b = root.join("b.txt").display().to_string();
+        assert_eq!(
+            listed,
+            vec![
+                ("name", "default", None),
+                ("name", "notes", Some(5)),
+                ("path", a.as_str(), Some(3)),
+                ("path", b.as_str(), Some(2)),
+            ]
+        );
+        assert!(entries[1].modified.is_some());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn sha256(content: &[u8]) -> String {
+        hex::encode(Sha256::digest(content))
+    }
+
+    /// Upload `body` to the entry `name`, with `checksum` in `X-Content-SHA256`
+    async fn upload(
+        state: AppState,
+        name: &str,
+        checksum: Option<&str>,
+        body: &'static str,
+    ) -> Response {
+        let mut headers = HeaderMap::new();
+        if let Some(checksum) = checksum {
+            headers.insert(&CONTENT_SHA256, checksum.parse().unwrap());
+        }
+        let query = UploadQuery {
+            name: Some(name.to_string()),
+        };
+        put_file_content(
+            Extension(Arc::new(state)),
+            Query(query),
+            headers,
+            Bytes::from_static(body.as_bytes()),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn served_content_carries_its_checksum() {
+        let dir = temp_dir("served_checksum");
+        let notes = dir.join("notes.txt");
+        std::fs::write(&notes, "some notes").unwrap();
+        let state = state(&["--file", &format!("notes={}", notes.display())]);
+
+        let response = get_file_content(Extension(Arc::new(state)), Json(by_name("notes"))).await;
+        let checksum = sha256(b"some notes");
+        assert_eq!(response.headers()[&CONTENT_SHA256], checksum.as_str());
+        assert_eq!(
+            response.headers()[header::ETAG],
+            format!("\"{}\"", checksum).as_str()
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn upload_with_a_matching_checksum_replaces_the_file() {
+        let dir = temp_dir("upload_matching");
+        let notes = dir.join("notes.txt");
+        std::fs::write(&notes, "old").unwrap();
+        let args = [
+            "--allow-uploads",
+            "--file",
+            &format!("notes={}", notes.display()),
+        ];
+
+        let checksum = sha256(b"new content");
+        let response = upload(state(&args), "notes", Some(&checksum), "new content").await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            response.headers()[header::ETAG],
+            format!("\"{}\"", checksum).as_str()
+        );
+        assert_eq!(std::fs::read_to_string(&notes).unwrap(), "new content");
+
+        // The checksum is optional
+        let response = upload(state(&args), "notes", None, "newer").await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(std::fs::read_to_string(&notes).unwrap(), "newer");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn upload_with_a_mismatched_checksum_is_rejected() {
+        let dir = temp_dir("upload_mismatched");
+        let notes = dir.join("notes.txt");
+        std::fs::write(&notes, "old").unwrap();
+        let state = state(&[
+            "--allow-uploads",
+            "--file",
+            &format!("notes={}", notes.display()),
+        ]);
+
+        // The checksum of the complete content, the body lost its end on the way
+        let checksum = sha256(b"complete content");
+        let response = upload(state, "notes", Some(&checksum), "complete").await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(
+            body["error"]
+                .as_str()
+                .unwrap()
+                .starts_with("Checksum mismatch")
+        );
+        assert_eq!(std::fs::read_to_string(&notes).unwrap(), "old");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn uploads_are_refused_by_default() {
+        let dir = temp_dir("upload_disabled");
+        let notes = dir.join("notes.txt");
+        std::fs::write(&notes, "old").unwrap();
+        let state = state(&["--file", &format!("notes={}", notes.display())]);
+
+        let response = upload(state, "notes", None, "new").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(std::fs::read_to_string(&notes).unwrap(), "old");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn listing_has_no_paths_without_allowed_roots() {
+        let state = state(&["--allow-raw-paths", "--file", "notes=/srv/notes.txt"]);
+        let Json(entries) = list_files(Extension(Arc::new(state))).await;
+        assert!(entries.iter().all(|entry| entry.kind == "name"));
+        assert_eq!(entries.len(), 2);
+    }
+}
@@ -0,0 +1,101 @@
+//! Read throughput of `PortablePty` with and without the read buffer pool
+//!
+//! ```text
+//! cargo bench --bench pty_read
+//! ```
+//!
+//! Each iteration starts a shell writing `OUTPUT_BYTES` to its terminal and reads them all.
+//! Afterwards the allocations per MiB read are printed for every pool size, the number the pool
+//! is there to bring down.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use rs_terminal::pty::{PortablePtyFactory, PtyConfig, PtyFactory};
+use tokio::io::AsyncReadExt;
+use tokio::runtime::Runtime;
+
+/// Output written by the shell per iteration
+const OUTPUT_BYTES: usize = 4 * 1024 * 1024;
+
+/// `pty_read_buffer_pool` values compared, 0 allocates a buffer per read
+const POOL_SIZES: [usize; 2] = [0, 16];
+
+/// Counts allocations, so the benchmark can show what the pool saves
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn config() -> PtyConfig {
+    // Raw mode, so the terminal passes the output through unchanged
+    let script = format!("stty raw -echo; head -c {} /dev/zero", OUTPUT_BYTES);
+    PtyConfig {
+        command: "sh".to_string(),
+        args: vec!["-c".to_string(), script],
+        cols: 80,
+        rows: 24,
+        env: Vec::new(),
+        cwd: None,
+    }
+}
+
+/// Start the shell and read its output to the end
+async fn read_output(factory: &PortablePtyFactory, config: &PtyConfig) -> usize {
+    let mut pty = factory.create(config).await.expect("PTY not created");
+    let mut buffer = vec![0u8; 8192];
+    let mut total = 0;
+    while total < OUTPUT_BYTES {
+        match pty.read(&mut buffer).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => total += n,
+        }
+    }
+    total
+}
+
+fn pty_read(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let config = config();
+
+    let mut group = c.benchmark_group("pty_read");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(OUTPUT_BYTES as u64));
+    for pool in POOL_SIZES {
+        let factory = PortablePtyFactory::new(1, pool);
+        group.bench_with_input(BenchmarkId::new("pool", pool), &pool, |b, _| {
+            b.to_async(&runtime).iter(|| read_output(&factory, &config));
+        });
+    }
+    group.finish();
+
+    for pool in POOL_SIZES {
+        let factory = PortablePtyFactory::new(1, pool);
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        let read = runtime.block_on(read_output(&factory, &config));
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        println!(
+            "pty_read/pool/{}: {} allocations per MiB ({} bytes read)",
+            pool,
+            allocations * 1024 * 1024 / read.max(1),
+            read
+        );
+    }
+}
+
+criterion_group!(benches, pty_read);
+criterion_main!(benches);
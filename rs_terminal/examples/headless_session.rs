@@ -0,0 +1,151 @@
+//! Drive a terminal session in-process, without the HTTP server
+//!
+//! ```text
+//! cargo run --example headless_session            # runs bash
+//! cargo run --example headless_session -- --mock  # echo-only mock PTY, no process started
+//! ```
+//!
+//! Starts a session on an in-process connection, writes a couple of commands, prints the output
+//! with timestamps, resizes the terminal mid-stream and terminates the session through its
+//! `SessionHandle`. Each step reports its failure instead of panicking.
+
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rs_terminal::app_state::{AppState, DiagnosticsStore};
+use rs_terminal::config::ConfigLoader;
+use rs_terminal::protocol::{ChannelClient, ServerEnvelope, channel_connection};
+use rs_terminal::pty::MockPtyFactory;
+use rs_terminal::service::handle_terminal_session;
+
+/// Configuration of the embedded server: bash without the user's startup files
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "bash"
+session_timeout = 1800000
+nudge_on_connect = false
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+environment = { TERM = "xterm-256color", PS1 = "$ " }
+
+[shells.bash]
+command = ["bash", "--norc", "--noprofile", "-i"]
+"#;
+
+/// ID of the session, chosen by the application like the `/ws/:session_id` path does
+const SESSION_ID: &str = "headless-demo";
+
+/// Output is considered complete once the terminal was quiet this long
+const QUIET_PERIOD: Duration = Duration::from_millis(500);
+
+/// How long the session may take to end once it was asked to terminate
+const TERMINATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+type Error = Box<dyn std::error::Error>;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mock = std::env::args().skip(1).any(|arg| arg == "--mock");
+    match run(mock).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("headless_session: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(mock: bool) -> Result<(), Error> {
+    // The configuration is validated like the server's own configuration file
+    let config = ConfigLoader::new()
+        .parse_config(CONFIG)
+        .map_err(|e| format!("invalid configuration: {}", e))?;
+
+    // The state holds every session; the mock PTY replaces the configured implementation
+    let mut state = AppState::new(config, Arc::new(DiagnosticsStore::new()));
+    if mock {
+        state = state.with_pty_factory(Arc::new(MockPtyFactory));
+    }
+
+    // The session runs in its own task, the client end drives it
+    let (connection, mut client) = channel_connection(SESSION_ID);
+    let session = tokio::spawn(handle_terminal_session(connection, state.clone()));
+    let started = Instant::now();
+    wait_for_hello(&mut client).await?;
+    println!("[{:>7.3}s] session {} started", secs(started), SESSION_ID);
+
+    client.input("echo hello from $0\r").await?;
+    stream_output(&mut client, started).await?;
+
+    // The shell sees the new size right away (SIGWINCH), `stty size` prints it
+    client.resize(120, 40).await?;
+    println!("[{:>7.3}s] resized to 120x40", secs(started));
+    client.input("stty size\r").await?;
+    stream_output(&mut client, started).await?;
+
+    // Terminate through the handle the application keeps for every running session
+    let mut handle = state
+        .take_session_handle(SESSION_ID)
+        .await
+        .ok_or("the session ended on its own")?;
+    if !handle.request_terminate("demo finished") {
+        return Err("the session ended before it was terminated".into());
+    }
+    let closed = stream_output(&mut client, started).await?;
+    if !handle.wait_finished(TERMINATE_TIMEOUT).await {
+        return Err(format!("the session didn't end within {:?}", TERMINATE_TIMEOUT).into());
+    }
+    session
+        .await
+        .map_err(|e| format!("the session task failed: {}", e))?;
+    println!(
+        "[{:>7.3}s] session terminated{}",
+        secs(started),
+        if closed { ", connection closed" } else { "" }
+    );
+    Ok(())
+}
+
+/// Wait for the first message of the session, sent once its PTY runs
+async fn wait_for_hello(client: &mut ChannelClient) -> Result<(), Error> {
+    match client.receive().await {
+        Some(Ok(ServerEnvelope::Hello { .. })) => Ok(()),
+        // A shell that can't be started is reported before the connection closes
+        Some(Ok(ServerEnvelope::Error { message, .. })) => Err(message.into()),
+        Some(Ok(other)) => Err(format!("unexpected first message {:?}", other).into()),
+        Some(Err(e)) => Err(format!("failed to receive from the session: {}", e).into()),
+        None => Err("the session closed before it started".into()),
+    }
+}
+
+/// Print the session's messages until the terminal is quiet or the session closed the
+/// connection, returning whether it closed
+async fn stream_output(client: &mut ChannelClient, started: Instant) -> Result<bool, Error> {
+    loop {
+        let Ok(message) = tokio::time::timeout(QUIET_PERIOD, client.receive()).await else {
+            return Ok(false);
+        };
+        match message {
+            Some(Ok(ServerEnvelope::Output { seq, data })) => {
+                println!("[{:>7.3}s] #{:<3} {:?}", secs(started), seq, data);
+            }
+            Some(Ok(ServerEnvelope::Notice { message, .. })) => {
+                println!("[{:>7.3}s] notice: {}", secs(started), message);
+            }
+            Some(Ok(ServerEnvelope::Error { message, .. })) => {
+                println!("[{:>7.3}s] error: {}", secs(started), message);
+            }
+            Some(Ok(_)) => {}
+            Some(Err(e)) => return Err(format!("failed to receive from the session: {}", e).into()),
+            None => return Ok(true),
+        }
+    }
+}
+
+/// Seconds since `started`
+fn secs(started: Instant) -> f64 {
+    started.elapsed().as_secs_f64()
+}
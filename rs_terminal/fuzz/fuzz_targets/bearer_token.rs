@@ -0,0 +1,26 @@
+//! Authenticate arbitrary bearer tokens, decoding their base64url segments; none of them is signed
+//! with the secret, so all must be rejected
+
+#![no_main]
+
+use axum::http::Request;
+use libfuzzer_sys::fuzz_target;
+use rs_terminal::auth::{AuthProvider, Jwt};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(token) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(request) = Request::builder()
+        .header("authorization", format!("Bearer {}", token))
+        .body(())
+    else {
+        return;
+    };
+    let (parts, ()) = request.into_parts();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let jwt = Jwt::new("fuzz-secret", None);
+    assert!(runtime.block_on(jwt.authenticate(&parts)).is_err());
+});
@@ -0,0 +1,20 @@
+//! Decode arbitrary text as a `waylon-terminal-v1` client envelope; whatever decodes must survive
+//! encoding and decoding again unchanged
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rs_terminal::protocol::ClientEnvelope;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(envelope) = serde_json::from_str::<ClientEnvelope>(text) {
+        let json = serde_json::to_string(&envelope).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ClientEnvelope>(&json).unwrap(),
+            envelope
+        );
+    }
+});
@@ -0,0 +1,28 @@
+//! Scan arbitrary terminal output for escape sequences; the first byte picks the profile and the
+//! chunk size, and the output must not depend on where the chunks were cut
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rs_terminal::api::dto::TerminalProfile;
+use rs_terminal::service::OutputFilter;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, output)) = data.split_first() else {
+        return;
+    };
+    let profile = if selector & 1 == 0 {
+        TerminalProfile::NoColor
+    } else {
+        TerminalProfile::Dumb
+    };
+    let chunk = usize::from(selector >> 1) + 1;
+
+    let whole = OutputFilter::new(profile).unwrap().filter(output);
+    let mut filter = OutputFilter::new(profile).unwrap();
+    let chunked: Vec<u8> = output
+        .chunks(chunk)
+        .flat_map(|piece| filter.filter(piece))
+        .collect();
+    assert_eq!(chunked, whole);
+});
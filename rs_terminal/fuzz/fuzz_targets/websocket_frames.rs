@@ -0,0 +1,29 @@
+//! Run arbitrary client bytes through the permessage-deflate decoder, which must refuse malformed
+//! frames with an error
+
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use rs_terminal::protocol::{DeflateParams, DeflateStream};
+use tokio::io::AsyncReadExt;
+
+/// Largest message the decoder accepts
+const LIMIT: usize = 64 * 1024;
+
+fuzz_target!(|data: &[u8]| {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let params = DeflateParams {
+            server_no_context_takeover: false,
+        };
+        let mut stream = DeflateStream::new(Cursor::new(data.to_vec()), params, LIMIT);
+        let mut frames = Vec::new();
+        if let Err(e) = stream.read_to_end(&mut frames).await {
+            assert_eq!(e.kind(), std::io::ErrorKind::InvalidData, "{}", e);
+        }
+    });
+});
@@ -1,2 +1,2 @@
 /// REST API implementation for terminal session management
-pub mod dto;
+pub use terminal_types::dto;
@@ -1,44 +1,250 @@
-use crate::app_state::Session;
+use crate::api::dto::TerminalSession;
+use crate::app_state::{
+    Clock, DetachedPtyStore, DiagnosticsStore, InputLatencyStore, PtySpawnStatsStore,
+    ScrollbackStore, Session, SessionHandle, SessionTaskLink, SystemClock, fold_title,
+    session_channel,
+};
+use crate::auth::{AuthProvider, auth_provider_from_config};
 use crate::config::TerminalConfig;
+use crate::protocol::NoticeLevel;
+use crate::pty::{PtyFactory, get_pty_factory};
+use arc_swap::ArcSwap;
 use std::collections::HashMap;
 /// Application state implementation for Waylon Terminal Rust backend
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::{Mutex, Notify};
+use tracing::info;
 
 /// Application state containing shared data across handlers
 #[derive(Clone)]
 pub struct AppState {
     /// Map of active sessions by session ID
     pub sessions: Arc<Mutex<HashMap<String, Session>>>,
-    /// Application configuration
+    /// Application configuration this state was taken with (see `with_current_config`)
     pub config: Arc<TerminalConfig>,
+    /// Latest configuration, replaced when the configuration is reloaded
+    live_config: Arc<ArcSwap<TerminalConfig>>,
+    /// PTY factory shared by all sessions
+    pub pty_factory: Arc<dyn PtyFactory>,
+    /// Authentication provider of REST requests and terminal connections
+    pub auth: Arc<dyn AuthProvider>,
+    /// Scrollback of all sessions, bounded by a global memory budget
+    pub scrollback: Arc<ScrollbackStore>,
+    /// Input echo latency of all sessions (only filled when `input_latency_probe` is enabled)
+    pub input_latency: Arc<InputLatencyStore>,
+    /// Spawn time and failures of the PTYs created since the server started
+    pub pty_spawn_stats: Arc<PtySpawnStatsStore>,
+    /// Recent warnings and errors, counters and close reasons of running sessions
+    pub diagnostics: Arc<DiagnosticsStore>,
+    /// Control handles of the session tasks that are currently running
+    pub session_handles: Arc<Mutex<HashMap<String, SessionHandle>>>,
+    /// PTYs of disconnected sessions, waiting for a client to attach again
+    pub detached_ptys: Arc<DetachedPtyStore>,
+    /// Set while the server is draining: new sessions are refused, running ones continue
+    pub draining: Arc<AtomicBool>,
+    /// Set while the WebTransport server is down after repeated failures
+    pub webtransport_degraded: Arc<AtomicBool>,
+    /// Notified when the server should shut down by itself (drained with `shutdown_when_drained`)
+    pub shutdown: Arc<Notify>,
+    /// Source of timestamps and elapsed time
+    pub clock: Arc<dyn Clock>,
 }
 
 impl AppState {
     /// Create a new instance of AppState with configuration
-    pub fn new(config: TerminalConfig) -> Self {
+    /// `diagnostics` is shared with the logging layer that fills it
+    pub fn new(config: TerminalConfig, diagnostics: Arc<DiagnosticsStore>) -> Self {
+        let pty_factory = get_pty_factory(
+            &config.pty_implementation,
+            config.max_concurrent_pty_spawns,
+            config.pty_read_buffer_pool,
+        );
+
+        let scrollback = ScrollbackStore::new(
+            config.scrollback_limit_bytes,
+            config.scrollback_memory_budget_bytes,
+        );
+
+        let auth = auth_provider_from_config(&config.auth);
+
+        let config = Arc::new(config);
         Self {
             sessions: Arc::new(Mutex::new(HashMap::new())),
-            config: Arc::new(config),
+            live_config: Arc::new(ArcSwap::new(config.clone())),
+            config,
+            pty_factory: Arc::from(pty_factory),
+            auth,
+            scrollback: Arc::new(scrollback),
+            input_latency: Arc::new(InputLatencyStore::new()),
+            pty_spawn_stats: Arc::new(PtySpawnStatsStore::new()),
+            diagnostics,
+            session_handles: Arc::new(Mutex::new(HashMap::new())),
+            detached_ptys: Arc::new(DetachedPtyStore::new()),
+            draining: Arc::new(AtomicBool::new(false)),
+            webtransport_degraded: Arc::new(AtomicBool::new(false)),
+            shutdown: Arc::new(Notify::new()),
+            clock: Arc::new(SystemClock::new()),
+        }
+    }
+
+    /// Replace the authentication provider chosen by the configuration, e.g. with one
+    /// checking sessions of the application embedding the server
+    pub fn with_auth_provider(mut self, provider: Arc<dyn AuthProvider>) -> Self {
+        self.auth = provider;
+        self
+    }
+
+    /// Replace the system clock, e.g. with a `MockClock` in tests
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Replace the PTY factory chosen by the configuration (`pty_implementation`)
+    pub fn with_pty_factory(mut self, factory: Arc<dyn PtyFactory>) -> Self {
+        self.pty_factory = factory;
+        self
+    }
+
+    /// Copy of the state using the latest configuration
+    /// Requests and connections take one when they start, so a running session keeps the
+    /// configuration it started with across reloads
+    pub fn with_current_config(&self) -> Self {
+        Self {
+            config: self.live_config.load_full(),
+            ..self.clone()
         }
     }
 
+    /// Replace the configuration used by requests and sessions started from now on
+    pub fn reload_config(&self, config: TerminalConfig) {
+        self.live_config.store(Arc::new(config));
+    }
+
+    /// Whether the server is draining
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Acquire)
+    }
+
+    /// Whether the WebTransport server is down after repeated failures
+    pub fn is_webtransport_degraded(&self) -> bool {
+        self.webtransport_degraded.load(Ordering::Acquire)
+    }
+
+    /// Mark the WebTransport server as down or running again
+    pub fn set_webtransport_degraded(&self, degraded: bool) {
+        self.webtransport_degraded
+            .store(degraded, Ordering::Release);
+    }
+
+    /// Start or stop draining
+    pub async fn set_draining(&self, draining: bool) {
+        self.draining.store(draining, Ordering::Release);
+        self.shutdown_if_drained().await;
+    }
+
+    /// Number of session tasks that are currently running
+    pub async fn running_session_count(&self) -> usize {
+        self.session_handles.lock().await.len()
+    }
+
+    /// Request a shutdown if the server is draining, no session is left and
+    /// `shutdown_when_drained` is enabled
+    pub async fn shutdown_if_drained(&self) {
+        if self.config.shutdown_when_drained
+            && self.is_draining()
+            && self.running_session_count().await == 0
+        {
+            info!("Server drained, requesting shutdown");
+            self.shutdown.notify_one();
+        }
+    }
+
+    /// Register a running session task, returning the link it receives commands through
+    pub async fn register_session_task(&self, session_id: &str) -> SessionTaskLink {
+        let (handle, link) = session_channel();
+        self.session_handles
+            .lock()
+            .await
+            .insert(session_id.to_string(), handle);
+        link
+    }
+
+    /// Ask a running session task to disconnect its client, keeping the PTY
+    /// Returns false if no task is running for the session or it has too many commands pending
+    pub async fn request_disconnect(&self, session_id: &str, reason: &str) -> bool {
+        self.session_handles
+            .lock()
+            .await
+            .get(session_id)
+            .is_some_and(|handle| handle.try_disconnect(reason))
+    }
+
+    /// Take the control handle of a running session task
+    pub async fn take_session_handle(&self, session_id: &str) -> Option<SessionHandle> {
+        self.session_handles.lock().await.remove(session_id)
+    }
+
+    /// Queue a notice for all running session tasks without waiting for them
+    /// Returns the number of sessions it was queued for and the number that couldn't take it
+    pub async fn notify_all_sessions(&self, level: NoticeLevel, message: &str) -> (usize, usize) {
+        let handles = self.session_handles.lock().await;
+        let delivered = handles
+            .values()
+            .filter(|handle| handle.try_notify(level, message))
+            .count();
+        (delivered, handles.len() - delivered)
+    }
+
+    /// Take the control handles of all running session tasks
+    pub async fn take_all_session_handles(&self) -> Vec<(String, SessionHandle)> {
+        self.session_handles.lock().await.drain().collect()
+    }
+
     /// Add a new session to the state
     pub async fn add_session(&self, session: Session) {
         let mut sessions = self.sessions.lock().await;
         sessions.insert(session.id.clone(), session);
     }
 
+    /// Add a new session, first appending " (2)", " (3)", ... to its title if another session
+    /// of the same user already has that title (compared case-insensitively)
+    /// Returns the title the session was added with
+    pub async fn add_session_with_unique_title(&self, mut session: Session) -> Option<String> {
+        let mut sessions = self.sessions.lock().await;
+        if let Some(title) = &session.title {
+            let taken: Vec<String> = sessions
+                .values()
+                .filter(|other| other.user_id == session.user_id)
+                .filter_map(|other| other.title.as_deref().map(fold_title))
+                .collect();
+            if taken.contains(&fold_title(title)) {
+                let unique = (2..)
+                    .map(|n| format!("{} ({})", title, n))
+                    .find(|candidate| !taken.contains(&fold_title(candidate)))
+                    .expect("a free suffix exists");
+                session.title = Some(unique);
+            }
+        }
+        let title = session.title.clone();
+        sessions.insert(session.id.clone(), session);
+        title
+    }
+
     /// Get a session by ID
     pub async fn get_session(&self, session_id: &str) -> Option<Session> {
         let sessions = self.sessions.lock().await;
         sessions.get(session_id).cloned()
     }
 
-    /// Remove a session by ID
+    /// Remove a session by ID, releasing its scrollback, latency samples and diagnostics
     pub async fn remove_session(&self, session_id: &str) -> Option<Session> {
-        let mut sessions = self.sessions.lock().await;
-        sessions.remove(session_id)
+        let removed = self.sessions.lock().await.remove(session_id);
+        self.scrollback.remove(session_id).await;
+        self.input_latency.remove(session_id).await;
+        self.diagnostics.remove(session_id);
+        removed
     }
 
     /// Update an existing session
@@ -58,6 +264,33 @@ impl AppState {
         sessions.values().cloned().collect()
     }
 
+    /// Map sessions to API response DTOs
+    pub async fn session_responses(&self, sessions: Vec<Session>) -> Vec<TerminalSession> {
+        let mut response_sessions: Vec<TerminalSession> = Vec::with_capacity(sessions.len());
+        for session in sessions {
+            let input_latency_p95_ms = self.input_latency.p95_ms(&session.id).await;
+            response_sessions.push(TerminalSession {
+                id: session.id,
+                user_id: session.user_id,
+                title: session.title,
+                status: format!("{:?}", session.status).to_lowercase(),
+                columns: session.columns,
+                rows: session.rows,
+                working_directory: session.working_directory,
+                shell_type: session.shell_type,
+                connection_type: format!("{:?}", session.connection_type),
+                created_at: session.created_at,
+                capabilities: session.capabilities,
+                read_only: session.read_only,
+                terminal_profile: session.terminal_profile,
+                template: session.template,
+                labels: session.labels,
+                input_latency_p95_ms,
+            });
+        }
+        response_sessions
+    }
+
     /// Get the number of active sessions
     pub async fn session_count(&self) -> usize {
         let sessions = self.sessions.lock().await;
@@ -66,9 +299,15 @@ impl AppState {
 
     /// Clean up all sessions and return the number of sessions cleaned
     pub async fn cleanup_all_sessions(&self) -> usize {
-        let mut sessions = self.sessions.lock().await;
-        let count = sessions.len();
-        sessions.clear();
-        count
+        let session_ids: Vec<String> = {
+            let mut sessions = self.sessions.lock().await;
+            sessions.drain().map(|(id, _)| id).collect()
+        };
+        for session_id in &session_ids {
+            self.scrollback.remove(session_id).await;
+            self.input_latency.remove(session_id).await;
+        }
+        self.diagnostics.clear();
+        session_ids.len()
     }
 }
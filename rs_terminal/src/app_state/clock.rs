@@ -0,0 +1,84 @@
+/// Time source for session timestamps and elapsed-time checks
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Source of the current time, shared through `AppState`
+pub trait Clock: Send + Sync {
+    /// Current wall-clock time as UNIX epoch seconds, for timestamps shown to clients
+    fn now_unix(&self) -> u64;
+
+    /// Current monotonic time, for measuring elapsed time
+    fn now_instant(&self) -> Instant;
+}
+
+/// Clock backed by the system time
+/// Timestamps never go backwards, even when the system clock is stepped back (e.g. by NTP)
+#[derive(Debug, Default)]
+pub struct SystemClock {
+    /// Latest timestamp handed out
+    last_unix: AtomicU64,
+}
+
+impl SystemClock {
+    /// Create a new system clock
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now_unix(&self) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            // Fall back to 0 for a clock set before the UNIX epoch
+            .unwrap_or_default()
+            .as_secs();
+        let previous = self.last_unix.fetch_max(now, Ordering::AcqRel);
+        now.max(previous)
+    }
+
+    fn now_instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock that only moves when it is told to, so time-based behavior can be tested without sleeping
+#[derive(Debug)]
+pub struct MockClock {
+    /// Current wall-clock time as UNIX epoch seconds
+    unix: AtomicU64,
+    /// Current monotonic time
+    instant: Mutex<Instant>,
+}
+
+impl MockClock {
+    /// Create a clock standing at `unix` epoch seconds
+    pub fn new(unix: u64) -> Self {
+        Self {
+            unix: AtomicU64::new(unix),
+            instant: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Move the clock forward
+    pub fn advance(&self, duration: Duration) {
+        self.unix.fetch_add(duration.as_secs(), Ordering::AcqRel);
+        *self.instant.lock().unwrap_or_else(|e| e.into_inner()) += duration;
+    }
+
+    /// Set the wall-clock time, e.g. back, like a stepped system clock (monotonic time is kept)
+    pub fn set_unix(&self, unix: u64) {
+        self.unix.store(unix, Ordering::Release);
+    }
+}
+
+impl Clock for MockClock {
+    fn now_unix(&self) -> u64 {
+        self.unix.load(Ordering::Acquire)
+    }
+
+    fn now_instant(&self) -> Instant {
+        *self.instant.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
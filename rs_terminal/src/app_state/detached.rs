@@ -0,0 +1,62 @@
+/// PTYs of sessions whose client was disconnected, kept running until a client attaches again
+use std::collections::HashMap;
+
+use tokio::sync::{Mutex, oneshot};
+use tokio::task::JoinHandle;
+
+use crate::pty::AsyncPty;
+
+/// A detached PTY, owned by the task draining its output
+struct DetachedPty {
+    /// Tells the task to stop draining and hand the PTY back
+    stop: oneshot::Sender<()>,
+    /// The draining task, returning the PTY (`None` if its shell exited)
+    task: JoinHandle<Option<Box<dyn AsyncPty>>>,
+}
+
+/// Detached PTYs by session ID
+#[derive(Default)]
+pub struct DetachedPtyStore {
+    ptys: Mutex<HashMap<String, DetachedPty>>,
+}
+
+impl DetachedPtyStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep the PTY of a session running while `task` drains its output
+    /// The task must return the PTY once `stop` fires
+    pub async fn insert(
+        &self,
+        session_id: &str,
+        stop: oneshot::Sender<()>,
+        task: JoinHandle<Option<Box<dyn AsyncPty>>>,
+    ) {
+        self.ptys
+            .lock()
+            .await
+            .insert(session_id.to_string(), DetachedPty { stop, task });
+    }
+
+    /// Take back the PTY of a detached session
+    /// `None` if the session isn't detached or its shell exited meanwhile
+    pub async fn take(&self, session_id: &str) -> Option<Box<dyn AsyncPty>> {
+        let detached = self.ptys.lock().await.remove(session_id)?;
+        // The task may already have ended because the shell exited
+        let _ = detached.stop.send(());
+        detached.task.await.ok().flatten()
+    }
+
+    /// Forget a detached session whose shell exited (called by its draining task)
+    /// Returns false if the PTY is being taken back at the same time
+    pub async fn remove(&self, session_id: &str) -> bool {
+        self.ptys.lock().await.remove(session_id).is_some()
+    }
+
+    /// IDs of all detached sessions
+    pub async fn session_ids(&self) -> Vec<String> {
+        self.ptys.lock().await.keys().cloned().collect()
+    }
+}
@@ -0,0 +1,219 @@
+/// Per-session diagnostics: recent warnings and errors logged within a session, traffic counters
+/// and the reason the session closed, for correlating client bug reports with the server side
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::api::dto::{DiagnosticLogEvent, SessionCounters};
+
+/// Name of the span session tasks run in; its `session_id` field assigns log events to a session
+pub const SESSION_SPAN: &str = "session";
+
+/// Diagnostics of a single session
+#[derive(Debug, Clone, Default)]
+pub struct SessionDiagnostics {
+    /// Most recent warnings and errors, oldest first
+    pub events: VecDeque<DiagnosticLogEvent>,
+    /// Most events kept
+    capacity: usize,
+    /// Negotiated subprotocol of the current connection
+    pub subprotocol: Option<String>,
+    /// Traffic counters
+    pub counters: SessionCounters,
+    /// Why the session task ended
+    pub close_reason: Option<String>,
+}
+
+/// Diagnostics of all sessions that are running
+/// Events are only kept for registered sessions and each session keeps a bounded number of them,
+/// so log events of sessions that are gone can't pile up
+#[derive(Default)]
+pub struct DiagnosticsStore {
+    sessions: Mutex<HashMap<String, SessionDiagnostics>>,
+}
+
+impl DiagnosticsStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The lock is only held for map operations, a panic can't leave it inconsistent
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, SessionDiagnostics>> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Start collecting diagnostics for a session, keeping at most `capacity` log events
+    /// A reconnecting session keeps its counters and events
+    pub fn register(&self, session_id: &str, capacity: usize) {
+        let mut sessions = self.lock();
+        let diagnostics = sessions.entry(session_id.to_string()).or_default();
+        diagnostics.capacity = capacity;
+        diagnostics.close_reason = None;
+        while diagnostics.events.len() > capacity {
+            diagnostics.events.pop_front();
+        }
+    }
+
+    /// Stop collecting diagnostics for a session and drop them
+    pub fn remove(&self, session_id: &str) {
+        self.lock().remove(session_id);
+    }
+
+    /// Drop the diagnostics of all sessions
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Record a log event of a session (ignored for unregistered sessions)
+    pub fn record_event(&self, session_id: &str, event: DiagnosticLogEvent) {
+        if let Some(diagnostics) = self.lock().get_mut(session_id) {
+            if diagnostics.capacity == 0 {
+                return;
+            }
+            if diagnostics.events.len() == diagnostics.capacity {
+                diagnostics.events.pop_front();
+            }
+            diagnostics.events.push_back(event);
+        }
+    }
+
+    /// Record the subprotocol negotiated by the session's connection
+    pub fn set_subprotocol(&self, session_id: &str, subprotocol: &str) {
+        if let Some(diagnostics) = self.lock().get_mut(session_id) {
+            diagnostics.subprotocol = Some(subprotocol.to_string());
+        }
+    }
+
+    /// Count a message received from the client
+    pub fn count_input(&self, session_id: &str) {
+        if let Some(diagnostics) = self.lock().get_mut(session_id) {
+            diagnostics.counters.input_messages += 1;
+        }
+    }
+
+    /// Count a chunk of PTY output
+    pub fn count_output(&self, session_id: &str, bytes: usize) {
+        if let Some(diagnostics) = self.lock().get_mut(session_id) {
+            diagnostics.counters.output_chunks += 1;
+            diagnostics.counters.output_bytes += bytes as u64;
+        }
+    }
+
+    /// Record why the session task ended
+    pub fn set_close_reason(&self, session_id: &str, reason: String) {
+        if let Some(diagnostics) = self.lock().get_mut(session_id) {
+            diagnostics.close_reason = Some(reason);
+        }
+    }
+
+    /// Get the diagnostics of a session
+    pub fn get(&self, session_id: &str) -> Option<SessionDiagnostics> {
+        self.lock().get(session_id).cloned()
+    }
+}
+
+/// Session ID stored in the extensions of a session span
+struct SessionSpanId(String);
+
+/// Collects the `session_id` field of a span
+#[derive(Default)]
+struct SessionIdVisitor(Option<String>);
+
+impl Visit for SessionIdVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "session_id" {
+            self.0 = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "session_id" {
+            self.0 = Some(format!("{:?}", value));
+        }
+    }
+}
+
+/// Collects the message and other fields of an event into one line
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        if field.name() == "message" {
+            self.0.push_str(&format!("{:?}", value));
+        } else {
+            self.0.push_str(&format!("{}={:?}", field.name(), value));
+        }
+    }
+}
+
+/// Tracing layer copying warnings and errors logged within a session span into the store
+pub struct DiagnosticsLayer {
+    store: Arc<DiagnosticsStore>,
+}
+
+impl DiagnosticsLayer {
+    /// Create a layer recording into `store`
+    pub fn new(store: Arc<DiagnosticsStore>) -> Self {
+        Self { store }
+    }
+}
+
+impl<S> Layer<S> for DiagnosticsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if attrs.metadata().name() != SESSION_SPAN {
+            return;
+        }
+        let mut visitor = SessionIdVisitor::default();
+        attrs.record(&mut visitor);
+        if let (Some(session_id), Some(span)) = (visitor.0, ctx.span(id)) {
+            span.extensions_mut().insert(SessionSpanId(session_id));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if *metadata.level() > Level::WARN {
+            return;
+        }
+        let Some(mut scope) = ctx.event_scope(event) else {
+            return;
+        };
+        let Some(session_id) = scope.find_map(|span| {
+            span.extensions()
+                .get::<SessionSpanId>()
+                .map(|id| id.0.clone())
+        }) else {
+            return;
+        };
+
+        let mut message = MessageVisitor::default();
+        event.record(&mut message);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        self.store.record_event(
+            &session_id,
+            DiagnosticLogEvent {
+                timestamp,
+                level: metadata.level().to_string().to_lowercase(),
+                target: metadata.target().to_string(),
+                message: message.0,
+            },
+        );
+    }
+}
@@ -0,0 +1,135 @@
+/// Input echo latency measured per session
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::time::Duration;
+
+use tokio::sync::Mutex;
+
+/// Upper bounds of the histogram buckets in seconds (`+Inf` is implied)
+const BUCKETS: [f64; 8] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+/// Number of most recent samples the rolling p95 is computed from
+const ROLLING_SAMPLES: usize = 256;
+
+/// Latency samples of a single session
+#[derive(Default)]
+struct SessionLatency {
+    /// Cumulative count per bucket (same order as `BUCKETS`)
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum_seconds: f64,
+    recent: VecDeque<Duration>,
+}
+
+impl SessionLatency {
+    fn record(&mut self, latency: Duration) {
+        let seconds = latency.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter_mut().zip(BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum_seconds += seconds;
+
+        if self.recent.len() == ROLLING_SAMPLES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(latency);
+    }
+
+    fn p95(&self) -> Option<Duration> {
+        if self.recent.is_empty() {
+            return None;
+        }
+        let mut samples: Vec<Duration> = self.recent.iter().copied().collect();
+        samples.sort_unstable();
+        let index = (samples.len() * 95).div_ceil(100) - 1;
+        Some(samples[index])
+    }
+}
+
+/// Time between client input and the next PTY output (usually the echo), for all sessions.
+/// This is a heuristic: output that isn't an echo of the input is measured as well.
+#[derive(Default)]
+pub struct InputLatencyStore {
+    sessions: Mutex<HashMap<String, SessionLatency>>,
+}
+
+impl InputLatencyStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record one latency sample for a session
+    pub async fn record(&self, session_id: &str, latency: Duration) {
+        let mut sessions = self.sessions.lock().await;
+        sessions
+            .entry(session_id.to_string())
+            .or_default()
+            .record(latency);
+    }
+
+    /// Get the p95 latency of a session's recent samples in milliseconds
+    pub async fn p95_ms(&self, session_id: &str) -> Option<f64> {
+        let sessions = self.sessions.lock().await;
+        sessions
+            .get(session_id)
+            .and_then(SessionLatency::p95)
+            .map(|p95| p95.as_secs_f64() * 1000.0)
+    }
+
+    /// Forget the samples of a session
+    pub async fn remove(&self, session_id: &str) {
+        self.sessions.lock().await.remove(session_id);
+    }
+
+    /// Render the per-session histograms in the Prometheus text format
+    pub async fn render_prometheus(&self) -> String {
+        let sessions = self.sessions.lock().await;
+        let mut out = String::new();
+        let name = "terminal_input_echo_latency_seconds";
+        let _ = writeln!(
+            out,
+            "# HELP {} Time from client input to the next PTY output",
+            name
+        );
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+
+        for (session_id, latency) in sessions.iter() {
+            let session_id = escape_label(session_id);
+            for (count, bound) in latency.buckets.iter().zip(BUCKETS) {
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{session_id=\"{}\",le=\"{}\"}} {}",
+                    name, session_id, bound, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_bucket{{session_id=\"{}\",le=\"+Inf\"}} {}",
+                name, session_id, latency.count
+            );
+            let _ = writeln!(
+                out,
+                "{}_sum{{session_id=\"{}\"}} {}",
+                name, session_id, latency.sum_seconds
+            );
+            let _ = writeln!(
+                out,
+                "{}_count{{session_id=\"{}\"}} {}",
+                name, session_id, latency.count
+            );
+        }
+        out
+    }
+}
+
+/// Escape a Prometheus label value (session IDs come from the request URL)
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -1,6 +1,20 @@
 /// Application state management for Waylon Terminal Rust backend
 mod app_state;
+mod clock;
+mod detached;
+mod diagnostics;
+mod latency;
+mod pty_stats;
+mod scrollback;
 mod session;
+mod session_control;
 
 pub use app_state::AppState;
-pub use session::{ConnectionType, Session, SessionStatus};
+pub use clock::{Clock, MockClock, SystemClock};
+pub use detached::DetachedPtyStore;
+pub use diagnostics::{DiagnosticsLayer, DiagnosticsStore, SESSION_SPAN};
+pub use latency::InputLatencyStore;
+pub use pty_stats::PtySpawnStatsStore;
+pub use scrollback::ScrollbackStore;
+pub use session::{ConnectionType, Session, SessionParams, SessionStatus, fold_title};
+pub use session_control::{SessionCommand, SessionHandle, SessionTaskLink, session_channel};
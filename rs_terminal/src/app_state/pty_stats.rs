@@ -0,0 +1,194 @@
+/// PTY spawn time and failures per factory and shell type
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use arc_swap::ArcSwap;
+
+use crate::api::dto::PtySpawnStats;
+
+/// Upper bounds of the histogram buckets in seconds (`+Inf` is implied)
+const BUCKETS: [f64; 12] = [
+    0.001, 0.002, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/// Counters of one factory and shell type, updated without locking
+#[derive(Default)]
+struct SpawnCounters {
+    /// Count per bucket, not cumulative (same order as `BUCKETS`, then `+Inf`)
+    buckets: [AtomicU64; BUCKETS.len() + 1],
+    count: AtomicU64,
+    failures: AtomicU64,
+    sum_micros: AtomicU64,
+    max_micros: AtomicU64,
+}
+
+impl SpawnCounters {
+    fn record(&self, elapsed: Duration, success: bool) {
+        let seconds = elapsed.as_secs_f64();
+        let bucket = BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        if !success {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+        }
+        let micros = elapsed.as_micros() as u64;
+        self.sum_micros.fetch_add(micros, Ordering::Relaxed);
+        self.max_micros.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    fn bucket_counts(&self) -> [u64; BUCKETS.len() + 1] {
+        std::array::from_fn(|i| self.buckets[i].load(Ordering::Relaxed))
+    }
+
+    /// Spawn time below which `percent` of the spawns finished, in milliseconds
+    /// (the upper bound of the bucket it falls in, at most the slowest spawn)
+    fn percentile_ms(&self, percent: u64) -> Option<f64> {
+        let counts = self.bucket_counts();
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            return None;
+        }
+        let max_ms = self.max_micros.load(Ordering::Relaxed) as f64 / 1000.0;
+        let rank = (total * percent).div_ceil(100);
+        let mut seen = 0;
+        for (count, bound) in counts.iter().zip(BUCKETS) {
+            seen += count;
+            if seen >= rank {
+                return Some((bound * 1000.0).min(max_ms));
+            }
+        }
+        Some(max_ms)
+    }
+}
+
+/// Time it takes to spawn PTYs and how often it fails, since the server started
+/// Recording only reads the map of counters; a new factory and shell type pair copies it once
+#[derive(Default)]
+pub struct PtySpawnStatsStore {
+    counters: ArcSwap<HashMap<(String, String), Arc<SpawnCounters>>>,
+}
+
+impl PtySpawnStatsStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a spawn attempt of `factory` for `shell_type`
+    pub fn record(&self, factory: &str, shell_type: &str, elapsed: Duration, success: bool) {
+        let key = (factory.to_string(), shell_type.to_string());
+        if let Some(counters) = self.counters.load().get(&key) {
+            counters.record(elapsed, success);
+            return;
+        }
+        let mut counters = None;
+        self.counters.rcu(|current| {
+            let mut map = HashMap::clone(current);
+            counters = Some(map.entry(key.clone()).or_default().clone());
+            map
+        });
+        if let Some(counters) = counters {
+            counters.record(elapsed, success);
+        }
+    }
+
+    /// Summary per factory and shell type, for `GET /api/admin/pty-stats`
+    pub fn summary(&self) -> Vec<PtySpawnStats> {
+        let counters = self.counters.load();
+        let mut stats: Vec<PtySpawnStats> = counters
+            .iter()
+            .map(|((factory, shell_type), counters)| {
+                let count = counters.count.load(Ordering::Relaxed);
+                let failures = counters.failures.load(Ordering::Relaxed);
+                PtySpawnStats {
+                    factory: factory.clone(),
+                    shell_type: shell_type.clone(),
+                    count,
+                    failures,
+                    error_rate: if count == 0 {
+                        0.0
+                    } else {
+                        failures as f64 / count as f64
+                    },
+                    p50_ms: counters.percentile_ms(50),
+                    p99_ms: counters.percentile_ms(99),
+                }
+            })
+            .collect();
+        stats.sort_by(|a, b| (&a.factory, &a.shell_type).cmp(&(&b.factory, &b.shell_type)));
+        stats
+    }
+
+    /// Render the spawn time histograms and failure counters in the Prometheus text format
+    pub fn render_prometheus(&self) -> String {
+        let counters = self.counters.load();
+        let mut out = String::new();
+        let name = "terminal_pty_spawn_seconds";
+        let _ = writeln!(
+            out,
+            "# HELP {} Time to create a PTY and spawn its process",
+            name
+        );
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for ((factory, shell_type), counters) in counters.iter() {
+            let labels = format!(
+                "factory=\"{}\",shell_type=\"{}\"",
+                escape_label(factory),
+                escape_label(shell_type)
+            );
+            let mut cumulative = 0;
+            let counts = counters.bucket_counts();
+            for (count, bound) in counts.iter().zip(BUCKETS) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{{},le=\"{}\"}} {}",
+                    name, labels, bound, cumulative
+                );
+            }
+            cumulative += counts[BUCKETS.len()];
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{},le=\"+Inf\"}} {}",
+                name, labels, cumulative
+            );
+            let _ = writeln!(
+                out,
+                "{}_sum{{{}}} {}",
+                name,
+                labels,
+                counters.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0
+            );
+            let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, cumulative);
+        }
+
+        let name = "terminal_pty_spawn_failures_total";
+        let _ = writeln!(out, "# HELP {} PTYs that could not be created", name);
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        for ((factory, shell_type), counters) in counters.iter() {
+            let _ = writeln!(
+                out,
+                "{}{{factory=\"{}\",shell_type=\"{}\"}} {}",
+                name,
+                escape_label(factory),
+                escape_label(shell_type),
+                counters.failures.load(Ordering::Relaxed)
+            );
+        }
+        out
+    }
+}
+
+/// Escape a Prometheus label value (shell types come from requests)
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,186 @@
+/// Scrollback storage shared by all terminal sessions
+use std::collections::{HashMap, VecDeque};
+
+use tokio::sync::Mutex;
+use tracing::debug;
+
+/// A chunk of PTY output stamped with a global insertion order
+struct ScrollbackChunk {
+    stamp: u64,
+    /// Output sequence number within the session
+    seq: u64,
+    data: Vec<u8>,
+}
+
+/// Scrollback buffer of a single session
+#[derive(Default)]
+struct SessionScrollback {
+    chunks: VecDeque<ScrollbackChunk>,
+    bytes: usize,
+    /// Sequence number of the next output chunk
+    next_seq: u64,
+}
+
+impl SessionScrollback {
+    /// Drop the oldest chunk, returning the number of bytes released
+    fn pop_oldest(&mut self) -> usize {
+        match self.chunks.pop_front() {
+            Some(chunk) => {
+                self.bytes -= chunk.data.len();
+                chunk.data.len()
+            }
+            None => 0,
+        }
+    }
+
+    /// Shrink the buffer to at most `limit` bytes, returning the number of bytes released
+    fn trim_to(&mut self, limit: usize) -> usize {
+        let mut released = 0;
+
+        while self.bytes > limit && self.chunks.len() > 1 {
+            released += self.pop_oldest();
+        }
+
+        // A single oversized chunk keeps only its most recent bytes
+        if self.bytes > limit
+            && let Some(chunk) = self.chunks.front_mut()
+        {
+            let excess = self.bytes - limit;
+            chunk.data.drain(..excess);
+            self.bytes -= excess;
+            released += excess;
+        }
+
+        released
+    }
+}
+
+struct ScrollbackInner {
+    buffers: HashMap<String, SessionScrollback>,
+    total_bytes: usize,
+    next_stamp: u64,
+}
+
+/// Scrollback buffers for all sessions with a per-session cap and a global memory budget.
+/// When the budget is exceeded the oldest output across all sessions is evicted first.
+pub struct ScrollbackStore {
+    inner: Mutex<ScrollbackInner>,
+    session_limit: usize,
+    memory_budget: usize,
+}
+
+impl ScrollbackStore {
+    /// Create a store bounded per session by `session_limit` and in total by `memory_budget` bytes
+    pub fn new(session_limit: usize, memory_budget: usize) -> Self {
+        Self {
+            inner: Mutex::new(ScrollbackInner {
+                buffers: HashMap::new(),
+                total_bytes: 0,
+                next_stamp: 0,
+            }),
+            session_limit,
+            memory_budget,
+        }
+    }
+
+    /// Append PTY output to a session's scrollback, returning its sequence number
+    /// Output is numbered even when scrollback is disabled
+    pub async fn append(&self, session_id: &str, data: &[u8]) -> u64 {
+        let mut inner = self.inner.lock().await;
+        let stamp = inner.next_stamp;
+        inner.next_stamp += 1;
+
+        let buffer = inner.buffers.entry(session_id.to_string()).or_default();
+        let seq = buffer.next_seq;
+        buffer.next_seq += 1;
+        if data.is_empty() || self.session_limit == 0 || self.memory_budget == 0 {
+            return seq;
+        }
+
+        buffer.chunks.push_back(ScrollbackChunk {
+            stamp,
+            seq,
+            data: data.to_vec(),
+        });
+        buffer.bytes += data.len();
+        let released = buffer.trim_to(self.session_limit);
+
+        inner.total_bytes = inner.total_bytes + data.len() - released;
+        Self::enforce_budget(&mut inner, self.memory_budget);
+        seq
+    }
+
+    /// Get the retained output of a session starting at sequence number `seq`
+    /// Older output may have been evicted, callers can detect the gap from the first sequence number
+    pub async fn replay_from(&self, session_id: &str, seq: u64) -> Vec<(u64, Vec<u8>)> {
+        let inner = self.inner.lock().await;
+        inner
+            .buffers
+            .get(session_id)
+            .map(|buffer| {
+                buffer
+                    .chunks
+                    .iter()
+                    .filter(|chunk| chunk.seq >= seq)
+                    .map(|chunk| (chunk.seq, chunk.data.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Drop the retained output of a session, returning the number of bytes released
+    /// Sequence numbers continue where they were, so clients can tell replayed output is missing
+    pub async fn clear(&self, session_id: &str) -> usize {
+        let mut inner = self.inner.lock().await;
+        let Some(buffer) = inner.buffers.get_mut(session_id) else {
+            return 0;
+        };
+        let released = buffer.bytes;
+        buffer.chunks.clear();
+        buffer.bytes = 0;
+        inner.total_bytes -= released;
+        debug!(
+            "Cleared {} bytes of scrollback of session {}",
+            released, session_id
+        );
+        released
+    }
+
+    /// Bytes of scrollback currently kept across all sessions
+    pub async fn total_bytes(&self) -> usize {
+        self.inner.lock().await.total_bytes
+    }
+
+    /// Remove a session's scrollback, releasing its memory
+    pub async fn remove(&self, session_id: &str) {
+        let mut inner = self.inner.lock().await;
+        if let Some(buffer) = inner.buffers.remove(session_id) {
+            inner.total_bytes -= buffer.bytes;
+        }
+    }
+
+    /// Evict the oldest chunks across all sessions until the total fits the budget
+    fn enforce_budget(inner: &mut ScrollbackInner, memory_budget: usize) {
+        while inner.total_bytes > memory_budget {
+            let oldest = inner
+                .buffers
+                .iter()
+                .filter_map(|(id, buffer)| buffer.chunks.front().map(|chunk| (chunk.stamp, id)))
+                .min()
+                .map(|(_, id)| id.clone());
+
+            let Some(session_id) = oldest else {
+                break;
+            };
+
+            if let Some(buffer) = inner.buffers.get_mut(&session_id) {
+                let released = buffer.pop_oldest();
+                inner.total_bytes -= released;
+                debug!(
+                    "Scrollback budget exceeded, evicted {} bytes from session {} ({} bytes in use)",
+                    released, session_id, inner.total_bytes
+                );
+            }
+        }
+    }
+}
@@ -1,6 +1,13 @@
 use serde::Serialize;
 /// Terminal session implementation
-use std::time::SystemTime;
+use std::collections::HashMap;
+
+use std::hash::BuildHasher;
+use std::sync::OnceLock;
+
+use crate::api::dto::{
+    EnvironmentSource, EnvironmentVariableInfo, TerminalCapabilities, TerminalProfile,
+};
 
 /// Terminal session state
 #[derive(Debug, Clone, PartialEq, Serialize)]
@@ -22,6 +29,8 @@ pub enum ConnectionType {
     WebSocket,
     /// WebTransport connection
     WebTransport,
+    /// In-process connection of an application embedding the server
+    Embedded,
 }
 
 /// Terminal session structure
@@ -59,26 +68,92 @@ pub struct Session {
 
     /// Session last updated timestamp (UNIX epoch in seconds)
     pub updated_at: u64,
+
+    /// Environment profile requested when the session was created
+    pub environment_profile: Option<String>,
+
+    /// Environment variables of the session's template and of the client (filtered by the allowlist)
+    pub environment: HashMap<String, String>,
+
+    /// Command replacing the shell's command (from the session's template)
+    pub command: Option<Vec<String>>,
+
+    /// Template the session was created from
+    pub template: Option<String>,
+
+    /// Labels of the session's template
+    pub labels: HashMap<String, String>,
+
+    /// View-only session, client input never reaches the PTY
+    pub read_only: bool,
+
+    /// Write a newline to the PTY when a client connects (unset uses `nudge_on_connect`)
+    pub nudge_on_connect: Option<bool>,
+
+    /// Key remap table applied to client input (from `key_remaps`)
+    pub key_remap: Option<String>,
+
+    /// Terminal profile requested at creation, replaced by the profile of each attaching client
+    pub terminal_profile: TerminalProfile,
+
+    /// Terminal capabilities derived from the shell environment
+    pub capabilities: TerminalCapabilities,
+
+    /// Environment the shell was started with, sorted by name; values are only kept for
+    /// revealable variables (recorded when the PTY is created)
+    pub environment_snapshot: Option<Vec<EnvironmentVariableInfo>>,
+}
+
+/// Parameters a session is created with
+#[derive(Debug, Clone)]
+pub struct SessionParams {
+    /// Unique session ID
+    pub id: String,
+    /// User ID
+    pub user_id: String,
+    /// Session title
+    pub title: Option<String>,
+    /// Working directory
+    pub working_directory: Option<String>,
+    /// Shell type
+    pub shell_type: String,
+    /// Terminal columns
+    pub columns: u16,
+    /// Terminal rows
+    pub rows: u16,
+    /// Connection type
+    pub connection_type: ConnectionType,
+}
+
+/// Hash an environment variable value
+/// The hash is keyed per server process: equal values of two sessions have equal hashes, but
+/// hashes can't be matched against guessed values outside the server
+fn environment_value_hash(value: &str) -> String {
+    static HASH_KEY: OnceLock<std::collections::hash_map::RandomState> = OnceLock::new();
+    let hash = HASH_KEY
+        .get_or_init(std::collections::hash_map::RandomState::new)
+        .hash_one(value);
+    format!("{:016x}", hash)
+}
+
+/// Normalize a title for comparisons with Unicode full case folding ("Straße" equals "STRASSE")
+pub fn fold_title(title: &str) -> String {
+    caseless::default_case_fold_str(title)
 }
 
 impl Session {
-    /// Create a new session with the given parameters
-    pub fn new(
-        id: String,
-        user_id: String,
-        title: Option<String>,
-        working_directory: Option<String>,
-        shell_type: String,
-        columns: u16,
-        rows: u16,
-        connection_type: ConnectionType,
-    ) -> Self {
-        let now = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            // In practice, this should never fail as current time is always after UNIX epoch
-            // Fallback to 0 if for some reason it does fail
-            .unwrap_or_default()
-            .as_secs();
+    /// Create a new session from `params`, created at `now` (UNIX epoch in seconds)
+    pub fn new(params: SessionParams, now: u64) -> Self {
+        let SessionParams {
+            id,
+            user_id,
+            title,
+            working_directory,
+            shell_type,
+            columns,
+            rows,
+            connection_type,
+        } = params;
 
         Self {
             id,
@@ -92,25 +167,56 @@ impl Session {
             connection_type,
             created_at: now,
             updated_at: now,
+            environment_profile: None,
+            environment: HashMap::new(),
+            command: None,
+            template: None,
+            labels: HashMap::new(),
+            read_only: false,
+            nudge_on_connect: None,
+            key_remap: None,
+            terminal_profile: TerminalProfile::Full,
+            capabilities: TerminalCapabilities::default(),
+            environment_snapshot: None,
         }
     }
 
     /// Update the terminal size
-    pub fn resize(&mut self, columns: u16, rows: u16) {
+    pub fn resize(&mut self, columns: u16, rows: u16, now: u64) {
         self.columns = columns;
         self.rows = rows;
-        self.updated_at = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
+        self.updated_at = now;
+    }
+
+    /// Update the session title
+    pub fn set_title(&mut self, title: String, now: u64) {
+        self.title = Some(title);
+        self.updated_at = now;
+    }
+
+    /// Record the environment the shell was started with
+    /// Only the values of `revealable` variables are kept, all others are stored as hashes
+    pub fn record_environment(
+        &mut self,
+        variables: impl IntoIterator<Item = (String, String, EnvironmentSource)>,
+        revealable: &[String],
+    ) {
+        let mut snapshot: Vec<EnvironmentVariableInfo> = variables
+            .into_iter()
+            .map(|(name, value, source)| EnvironmentVariableInfo {
+                value_hash: environment_value_hash(&value),
+                value: revealable.contains(&name).then_some(value),
+                name,
+                source,
+            })
+            .collect();
+        snapshot.sort_by(|a, b| a.name.cmp(&b.name));
+        self.environment_snapshot = Some(snapshot);
     }
 
     /// Update the session status
-    pub fn set_status(&mut self, status: SessionStatus) {
+    pub fn set_status(&mut self, status: SessionStatus, now: u64) {
         self.status = status;
-        self.updated_at = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
+        self.updated_at = now;
     }
 }
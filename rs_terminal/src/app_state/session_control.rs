@@ -0,0 +1,112 @@
+/// Control channel between the application and running session tasks
+use std::time::Duration;
+
+use futures_util::future::{AbortHandle, AbortRegistration};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::protocol::NoticeLevel;
+
+/// Command sent to a running session task
+#[derive(Debug)]
+pub enum SessionCommand {
+    /// Notify the client and end the session (the PTY is killed during cleanup)
+    Terminate { reason: String },
+    /// Show a notice to the client (e.g. upcoming maintenance), the session continues
+    Notice { level: NoticeLevel, message: String },
+    /// Close the connection but keep the PTY running for the next client to attach
+    Disconnect { reason: String },
+}
+
+/// Handle to a running session task, kept in the application state
+pub struct SessionHandle {
+    commands: mpsc::Sender<SessionCommand>,
+    finished: oneshot::Receiver<()>,
+    abort: AbortHandle,
+}
+
+/// Session task side of a [`SessionHandle`]
+/// Dropping it (when the session task ends) marks the session as finished
+pub struct SessionTaskLink {
+    pub commands: mpsc::Receiver<SessionCommand>,
+    abort: Option<AbortRegistration>,
+    _finished: oneshot::Sender<()>,
+}
+
+/// Create a connected handle and task link
+pub fn session_channel() -> (SessionHandle, SessionTaskLink) {
+    let (commands_tx, commands_rx) = mpsc::channel(4);
+    let (finished_tx, finished_rx) = oneshot::channel();
+    let (abort, registration) = AbortHandle::new_pair();
+    (
+        SessionHandle {
+            commands: commands_tx,
+            finished: finished_rx,
+            abort,
+        },
+        SessionTaskLink {
+            commands: commands_rx,
+            abort: Some(registration),
+            _finished: finished_tx,
+        },
+    )
+}
+
+impl SessionTaskLink {
+    /// Registration for the session loop, cancelled by [`SessionHandle::force_terminate`]
+    /// Only the first call gets that one, later calls get a registration nothing cancels
+    pub fn take_abort_registration(&mut self) -> AbortRegistration {
+        self.abort
+            .take()
+            .unwrap_or_else(|| AbortHandle::new_pair().1)
+    }
+}
+
+impl SessionHandle {
+    /// Ask the session task to terminate without waiting: a task with too many commands pending
+    /// wouldn't act on this one in time either (see [`SessionHandle::force_terminate`])
+    /// Returns false if the task has already ended
+    pub fn request_terminate(&self, reason: &str) -> bool {
+        let command = SessionCommand::Terminate {
+            reason: reason.to_string(),
+        };
+        !matches!(
+            self.commands.try_send(command),
+            Err(mpsc::error::TrySendError::Closed(_))
+        )
+    }
+
+    /// Queue a notice for the session task without waiting
+    /// Returns false if the task has ended or has too many commands pending
+    pub fn try_notify(&self, level: NoticeLevel, message: &str) -> bool {
+        self.commands
+            .try_send(SessionCommand::Notice {
+                level,
+                message: message.to_string(),
+            })
+            .is_ok()
+    }
+
+    /// Queue a disconnect for the session task without waiting
+    /// Returns false if the task has ended or has too many commands pending
+    pub fn try_disconnect(&self, reason: &str) -> bool {
+        self.commands
+            .try_send(SessionCommand::Disconnect {
+                reason: reason.to_string(),
+            })
+            .is_ok()
+    }
+
+    /// Cancel the session loop of a task that doesn't act on commands (e.g. stuck sending to a
+    /// client that stopped reading); the task kills its PTY and cleans up
+    pub fn force_terminate(&self) {
+        self.abort.abort();
+    }
+
+    /// Wait until the session task has ended, returns false if it didn't within `timeout`
+    pub async fn wait_finished(&mut self, timeout: Duration) -> bool {
+        // The sender is never used, the channel closes when the task link is dropped
+        tokio::time::timeout(timeout, &mut self.finished)
+            .await
+            .is_ok()
+    }
+}
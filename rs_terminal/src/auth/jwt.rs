@@ -0,0 +1,129 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use axum::http::request::Parts;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use ring::hmac;
+use serde::Deserialize;
+
+use super::{AuthContext, AuthError, AuthProvider, bearer_token};
+
+/// Clock difference tolerated between the token issuer and this server, in seconds
+const CLOCK_SKEW_SECS: u64 = 30;
+
+/// Accepts JSON Web Tokens signed with a shared secret (HS256)
+pub struct Jwt {
+    key: hmac::Key,
+    issuer: Option<String>,
+}
+
+/// JOSE header of a token
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+}
+
+/// Claims read from a token
+#[derive(Deserialize)]
+struct Claims {
+    sub: String,
+    #[serde(default)]
+    exp: Option<u64>,
+    #[serde(default)]
+    nbf: Option<u64>,
+    #[serde(default)]
+    iss: Option<String>,
+    #[serde(default)]
+    roles: Vec<String>,
+    #[serde(default)]
+    shells: Option<Vec<String>>,
+}
+
+impl Jwt {
+    pub fn new(secret: &str, issuer: Option<String>) -> Self {
+        Self {
+            key: hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()),
+            issuer,
+        }
+    }
+
+    /// Check the signature and claims of a token
+    fn verify(&self, token: &str) -> Result<AuthContext, AuthError> {
+        let invalid = |reason: &str| AuthError::InvalidCredentials(reason.to_string());
+
+        let mut segments = token.split('.');
+        let (Some(header), Some(payload), Some(signature), None) = (
+            segments.next(),
+            segments.next(),
+            segments.next(),
+            segments.next(),
+        ) else {
+            return Err(invalid("malformed token"));
+        };
+
+        // Only HS256 is accepted, never "none" or an algorithm picked by the client
+        let header: Header = decode_segment(header).ok_or_else(|| invalid("malformed header"))?;
+        if header.alg != "HS256" {
+            return Err(invalid("unsupported algorithm"));
+        }
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| invalid("malformed signature"))?;
+        let signed = &token[..header_and_payload_len(token)];
+        hmac::verify(&self.key, signed.as_bytes(), &signature)
+            .map_err(|_| invalid("bad signature"))?;
+
+        let claims: Claims = decode_segment(payload).ok_or_else(|| invalid("malformed claims"))?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        if claims
+            .exp
+            .is_some_and(|exp| exp.saturating_add(CLOCK_SKEW_SECS) < now)
+        {
+            return Err(AuthError::Expired);
+        }
+        if claims
+            .nbf
+            .is_some_and(|nbf| nbf > now.saturating_add(CLOCK_SKEW_SECS))
+        {
+            return Err(invalid("token not valid yet"));
+        }
+        if let Some(issuer) = &self.issuer
+            && claims.iss.as_ref() != Some(issuer)
+        {
+            return Err(invalid("unexpected issuer"));
+        }
+
+        Ok(AuthContext {
+            user_id: claims.sub,
+            roles: claims.roles,
+            expires_at: claims.exp.map(|exp| UNIX_EPOCH + Duration::from_secs(exp)),
+            shells: claims.shells,
+        })
+    }
+}
+
+/// Length of the signed part of a token (`header.payload`)
+fn header_and_payload_len(token: &str) -> usize {
+    token.rfind('.').unwrap_or(token.len())
+}
+
+/// Decode a base64url JSON segment
+fn decode_segment<T: for<'de> Deserialize<'de>>(segment: &str) -> Option<T> {
+    let bytes = URL_SAFE_NO_PAD.decode(segment).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+#[async_trait]
+impl AuthProvider for Jwt {
+    async fn authenticate(&self, parts: &Parts) -> Result<AuthContext, AuthError> {
+        let token = bearer_token(parts).ok_or(AuthError::MissingCredentials)?;
+        self.verify(token)
+    }
+
+    fn name(&self) -> &'static str {
+        "jwt"
+    }
+}
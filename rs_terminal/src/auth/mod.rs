@@ -0,0 +1,26 @@
+/// Authentication of REST requests and terminal connections
+/// The provider is chosen in the configuration (`[auth]`); embedders can install their own
+/// with `AppState::with_auth_provider`
+mod jwt;
+mod provider;
+mod static_token;
+
+pub use jwt::Jwt;
+pub use provider::*;
+pub use static_token::StaticToken;
+
+use std::sync::Arc;
+
+use crate::config::{AuthConfig, AuthProviderKind};
+
+/// Get the authentication provider based on configuration
+pub fn auth_provider_from_config(config: &AuthConfig) -> Arc<dyn AuthProvider> {
+    match config.provider {
+        AuthProviderKind::None => Arc::new(NoAuth),
+        AuthProviderKind::StaticToken => Arc::new(StaticToken::new(config.tokens.clone())),
+        AuthProviderKind::Jwt => Arc::new(Jwt::new(
+            config.jwt_secret.as_deref().unwrap_or_default(),
+            config.jwt_issuer.clone(),
+        )),
+    }
+}
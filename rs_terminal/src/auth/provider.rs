@@ -0,0 +1,114 @@
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use axum::http::{header, request::Parts};
+use thiserror::Error;
+
+/// Query parameter carrying the token for clients that can't set headers (browser WebSockets)
+pub const ACCESS_TOKEN_PARAM: &str = "access_token";
+
+/// User ID of requests let in by `NoAuth`
+pub const ANONYMOUS_USER: &str = "anonymous";
+
+/// Role needed for the `/api/admin` endpoints and for other users' sessions
+pub const ADMIN_ROLE: &str = "admin";
+
+/// Who a request was authenticated as
+/// Added to the request extensions, so handlers can read it with `Extension<AuthContext>`
+#[derive(Debug, Clone)]
+pub struct AuthContext {
+    /// Authenticated user
+    pub user_id: String,
+    /// Roles of the user
+    pub roles: Vec<String>,
+    /// When the credentials expire, if they do
+    pub expires_at: Option<SystemTime>,
+    /// Shell types the credentials may start or attach to (`None` allows every shell)
+    pub shells: Option<Vec<String>>,
+}
+
+impl AuthContext {
+    /// Whether the user has a role
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+
+    /// Whether the user is an administrator
+    pub fn is_admin(&self) -> bool {
+        self.has_role(ADMIN_ROLE)
+    }
+
+    /// Whether the user may use a session owned by `owner` (their own, or any as an administrator)
+    pub fn can_access(&self, owner: &str) -> bool {
+        self.user_id == owner || self.is_admin()
+    }
+
+    /// Whether the credentials allow a shell type
+    pub fn can_use_shell(&self, shell_type: &str) -> bool {
+        self.shells
+            .as_ref()
+            .is_none_or(|shells| shells.iter().any(|shell| shell == shell_type))
+    }
+}
+
+/// Why a request was not authenticated
+#[derive(Error, Debug)]
+pub enum AuthError {
+    #[error("No credentials were sent")]
+    MissingCredentials,
+
+    #[error("Invalid credentials: {0}")]
+    InvalidCredentials(String),
+
+    #[error("The credentials have expired")]
+    Expired,
+}
+
+/// Authentication provider, consulted before a REST request is handled and before a
+/// WebSocket or WebTransport session is accepted
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+    /// Authenticate a request by its method, URI and headers
+    async fn authenticate(&self, parts: &Parts) -> Result<AuthContext, AuthError>;
+
+    /// Provider name for logs
+    fn name(&self) -> &'static str;
+}
+
+/// Lets every request in as the anonymous user
+/// Without authentication there is nobody to tell apart, so the anonymous user is an administrator
+pub struct NoAuth;
+
+#[async_trait]
+impl AuthProvider for NoAuth {
+    async fn authenticate(&self, _parts: &Parts) -> Result<AuthContext, AuthError> {
+        Ok(AuthContext {
+            user_id: ANONYMOUS_USER.to_string(),
+            roles: vec![ADMIN_ROLE.to_string()],
+            expires_at: None,
+            shells: None,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "none"
+    }
+}
+
+/// Get the bearer token of a request: the `Authorization: Bearer` header, otherwise the
+/// `access_token` query parameter
+pub fn bearer_token(parts: &Parts) -> Option<&str> {
+    if let Some(value) = parts.headers.get(header::AUTHORIZATION) {
+        let (scheme, token) = value.to_str().ok()?.split_once(' ')?;
+        return scheme
+            .eq_ignore_ascii_case("bearer")
+            .then(|| token.trim())
+            .filter(|token| !token.is_empty());
+    }
+    parts
+        .uri
+        .query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix(ACCESS_TOKEN_PARAM)?.strip_prefix('='))
+        .filter(|token| !token.is_empty())
+}
@@ -0,0 +1,43 @@
+use async_trait::async_trait;
+use axum::http::request::Parts;
+use subtle::ConstantTimeEq;
+
+use super::{AuthContext, AuthError, AuthProvider, bearer_token};
+use crate::config::StaticTokenConfig;
+
+/// Accepts the bearer tokens listed in the configuration
+pub struct StaticToken {
+    tokens: Vec<StaticTokenConfig>,
+}
+
+impl StaticToken {
+    pub fn new(tokens: Vec<StaticTokenConfig>) -> Self {
+        Self { tokens }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for StaticToken {
+    async fn authenticate(&self, parts: &Parts) -> Result<AuthContext, AuthError> {
+        let token = bearer_token(parts).ok_or(AuthError::MissingCredentials)?;
+        // Compared in constant time, and against every entry, so timing doesn't reveal tokens
+        let mut matched = None;
+        for entry in &self.tokens {
+            if bool::from(entry.token.as_bytes().ct_eq(token.as_bytes())) {
+                matched = Some(entry);
+            }
+        }
+        let entry =
+            matched.ok_or_else(|| AuthError::InvalidCredentials("unknown token".to_string()))?;
+        Ok(AuthContext {
+            user_id: entry.user_id.clone(),
+            roles: entry.roles.clone(),
+            expires_at: None,
+            shells: entry.shells.clone(),
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "static_token"
+    }
+}
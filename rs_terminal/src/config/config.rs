@@ -1,7 +1,39 @@
 /// Configuration data structures for rs_terminal
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
+use super::ConfigError;
+use crate::api::dto::{EnvironmentSource, TerminalProfile};
+
+/// Default limit for PTYs being spawned at the same time
+pub const DEFAULT_MAX_CONCURRENT_PTY_SPAWNS: usize = 8;
+
+/// Default number of read buffers each PTY keeps for reuse
+pub const DEFAULT_PTY_READ_BUFFER_POOL: usize = 32;
+
+/// Locale variables a client may forward with `forward_client_locale`
+pub const CLIENT_LOCALE_VARIABLES: [&str; 15] = [
+    "LANG",
+    "LANGUAGE",
+    "LC_ALL",
+    "LC_ADDRESS",
+    "LC_COLLATE",
+    "LC_CTYPE",
+    "LC_IDENTIFICATION",
+    "LC_MEASUREMENT",
+    "LC_MESSAGES",
+    "LC_MONETARY",
+    "LC_NAME",
+    "LC_NUMERIC",
+    "LC_PAPER",
+    "LC_TELEPHONE",
+    "LC_TIME",
+];
+
+/// Longest locale value a client may forward
+const MAX_CLIENT_LOCALE_VALUE: usize = 64;
+
 /// Terminal configuration
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct TerminalConfig {
@@ -11,15 +43,225 @@ pub struct TerminalConfig {
     /// Session timeout in milliseconds (default: 30 minutes)
     pub session_timeout: u64,
 
+    /// Port shared by HTTP/WebSocket (TCP) and WebTransport (UDP)
+    /// Takes precedence over `http_port`, `webtransport_port` and `single_port`
+    #[serde(default)]
+    pub port: Option<u16>,
+
     /// HTTP server port
+    #[serde(default = "default_http_port")]
     pub http_port: u16,
 
     /// WebTransport server port
+    #[serde(default = "default_webtransport_port")]
     pub webtransport_port: u16,
 
-    /// PTY implementation to use (options: "tokio_process", "portable_pty")
+    /// Serve WebTransport on `http_port` as well (WebSocket uses TCP, WebTransport uses UDP),
+    /// so only one port number has to be opened in the firewall
+    /// Kept for existing configurations; ignored when `port` is set
+    #[serde(default)]
+    pub single_port: bool,
+
+    /// Serve the WebSocket endpoints `/ws` and `/ws/:session_id` (disable to use only WebTransport)
+    #[serde(default = "default_enable_websocket")]
+    pub enable_websocket: bool,
+
+    /// Run the WebTransport service (disable to serve WebSocket only, without a UDP port or certificate)
+    #[serde(default = "default_enable_webtransport")]
+    pub enable_webtransport: bool,
+
+    /// Whether failing to start WebTransport aborts startup (otherwise only a warning)
+    #[serde(default)]
+    pub webtransport_required: bool,
+
+    /// Environment variables clients may set when creating a session (all others are dropped)
+    #[serde(default)]
+    pub client_environment_allowlist: Vec<String>,
+
+    /// Apply the locale variables (`LANG`, `LC_*`) a WebSocket client sends with `locale` to the
+    /// shell it starts
+    #[serde(default)]
+    pub forward_client_locale: bool,
+
+    /// Reject WebSocket clients that only offer unknown subprotocols with 426 Upgrade Required
+    /// (otherwise they are accepted in raw mode)
+    #[serde(default)]
+    pub reject_unknown_subprotocols: bool,
+
+    /// Compress WebSocket messages with `permessage-deflate` for clients offering the extension
+    #[serde(default)]
+    pub websocket_permessage_deflate: bool,
+
+    /// Largest WebSocket message accepted from clients in bytes, also the most WebTransport stream
+    /// data read at once; larger terminal output is split into several frames
+    #[serde(default = "default_max_message_bytes")]
+    pub max_message_bytes: usize,
+
+    /// PTY implementation to use (options: "tokio_process", "portable_pty", "mock")
     pub pty_implementation: String,
 
+    /// Maximum number of PTYs being created concurrently (spawning blocks a worker thread)
+    #[serde(default = "default_max_concurrent_pty_spawns")]
+    pub max_concurrent_pty_spawns: usize,
+
+    /// Read buffers (4 KiB each) every PTY keeps for reuse, so output doesn't allocate per read
+    /// (0 allocates a buffer for every read)
+    #[serde(default = "default_pty_read_buffer_pool")]
+    pub pty_read_buffer_pool: usize,
+
+    /// Maximum scrollback kept per session in bytes (0 disables scrollback)
+    #[serde(default = "default_scrollback_limit_bytes")]
+    pub scrollback_limit_bytes: usize,
+
+    /// Maximum scrollback kept across all sessions in bytes; oldest output is evicted first
+    #[serde(default = "default_scrollback_memory_budget_bytes")]
+    pub scrollback_memory_budget_bytes: usize,
+
+    /// Time sessions get to finish on shutdown or bulk termination, in milliseconds
+    #[serde(default = "default_shutdown_timeout_ms")]
+    pub shutdown_timeout_ms: u64,
+
+    /// Maximum time from the shutdown signal until the server exits, in milliseconds;
+    /// connections still open afterwards are dropped
+    #[serde(default = "default_max_shutdown_duration_ms")]
+    pub max_shutdown_duration_ms: u64,
+
+    /// Timeout for exec requests that don't specify one, in milliseconds
+    #[serde(default = "default_exec_timeout_ms")]
+    pub default_exec_timeout_ms: u64,
+
+    /// Maximum output returned by an exec request in bytes, the rest is discarded
+    #[serde(default = "default_exec_output_limit_bytes")]
+    pub exec_output_limit_bytes: usize,
+
+    /// Path template for per-session output logs, `{session_id}` is replaced by the session ID
+    /// (percent-encoded except for lowercase ASCII letters, digits, `-` and `_`)
+    /// Only PTY output is written, client input never is (unset disables output logs)
+    #[serde(default)]
+    pub session_output_log: Option<String>,
+
+    /// Size in bytes at which a session output log is rotated to `<path>.1`
+    #[serde(default = "default_session_output_log_max_bytes")]
+    pub session_output_log_max_bytes: u64,
+
+    /// Directory under which each session gets a private scratch directory `<root>/<session_id>`,
+    /// (the ID percent-encoded like output log names), exported as `SESSION_TMPDIR` and removed
+    /// when the session ends (unset disables them)
+    #[serde(default)]
+    pub session_tmpdir_root: Option<PathBuf>,
+
+    /// Start the shell in its scratch directory when the session has no working directory
+    #[serde(default)]
+    pub session_tmpdir_as_cwd: bool,
+
+    /// Scratch directories larger than this (in bytes) are not removed, only reported
+    #[serde(default = "default_session_tmpdir_max_bytes")]
+    pub session_tmpdir_max_bytes: u64,
+
+    /// Age in seconds after which scratch directories without a session are swept
+    #[serde(default = "default_session_tmpdir_max_age_secs")]
+    pub session_tmpdir_max_age_secs: u64,
+
+    /// Permit measuring the time from client input to the next PTY output (usually the echo),
+    /// reported per session in the session API and on /metrics; clients turn the measurement on
+    /// with `latencyProbe` in their hello
+    #[serde(default)]
+    pub input_latency_probe: bool,
+
+    /// Number of recent warnings and errors kept per session for its diagnostics endpoint
+    #[serde(default = "default_diagnostics_log_events")]
+    pub diagnostics_log_events: usize,
+
+    /// Write a newline to the PTY when a client connects so the shell prints its prompt right away
+    /// (sessions can override this with `nudgeOnConnect`)
+    #[serde(default)]
+    pub nudge_on_connect: bool,
+
+    /// Answer `list_sessions` envelopes of `waylon-terminal-v1` connections with the sessions of
+    /// the connection's user
+    #[serde(default)]
+    pub enable_session_list_frame: bool,
+
+    /// How terminal output is split into frames (`raw` or `lines`)
+    #[serde(default)]
+    pub frame_mode: FrameMode,
+
+    /// Most bytes of a frame with `frame_mode = "lines"`, longer lines are sent in pieces
+    #[serde(default = "default_frame_max_line_bytes")]
+    pub frame_max_line_bytes: usize,
+
+    /// Milliseconds writing client input to the PTY may take before the terminal counts as not
+    /// accepting input (e.g. its process was stopped and the input queue is full)
+    #[serde(default = "default_pty_write_timeout_ms")]
+    pub pty_write_timeout_ms: u64,
+
+    /// What happens to input while the terminal isn't accepting it (`drop` or `buffer`)
+    #[serde(default)]
+    pub stalled_input: StalledInputPolicy,
+
+    /// Most bytes of input kept with `stalled_input = "buffer"`, input beyond it is dropped
+    #[serde(default = "default_stalled_input_buffer_bytes")]
+    pub stalled_input_buffer_bytes: usize,
+
+    /// Milliseconds sending a message to a client may take before the connection is closed
+    #[serde(default = "default_connection_send_timeout_ms")]
+    pub connection_send_timeout_ms: u64,
+
+    /// Seconds without traffic after which a protocol-level ping is sent to the client, so idle
+    /// connections survive proxies; nothing is written to the PTY (0 disables it)
+    #[serde(default = "default_keepalive_interval_secs")]
+    pub keepalive_interval_secs: u64,
+
+    /// Seconds without client input after which a session is locked: its output is held back and
+    /// its input dropped until the client authenticates again as the same user (unset: never)
+    /// The shell keeps running while the session is locked
+    #[serde(default)]
+    pub lock_after_idle_secs: Option<u64>,
+
+    /// Most output held back while a session is locked, in bytes; older output is dropped
+    #[serde(default = "default_lock_buffer_bytes")]
+    pub lock_buffer_bytes: usize,
+
+    /// Shut the server down once it is draining and the last session has ended
+    #[serde(default)]
+    pub shutdown_when_drained: bool,
+
+    /// Look up bare command names (without a path) in the shell's `PATH` (or the server's) before
+    /// spawning, so a missing command fails with a clear error instead of an OS spawn error
+    #[serde(default)]
+    pub resolve_command_path: bool,
+
+    /// Append " (2)", " (3)", ... to the title of a new session when the same user already has
+    /// a session with that title (compared case-insensitively)
+    #[serde(default)]
+    pub unique_titles_per_user: bool,
+
+    /// Environment variables whose values may be revealed by the session environment endpoint
+    #[serde(default = "default_environment_reveal_allowlist")]
+    pub environment_reveal_allowlist: Vec<String>,
+
+    /// Named session presets, selected with `template` when creating a session
+    #[serde(default)]
+    pub templates: HashMap<String, SessionTemplate>,
+
+    /// Named sets of environment variables, selected per session (REST), per connection
+    /// (`environment_profile` query parameter) or per transport
+    #[serde(default)]
+    pub environment_profiles: HashMap<String, HashMap<String, String>>,
+
+    /// Environment profile per transport ("websocket", "webtransport", "embedded") used when none is requested
+    #[serde(default)]
+    pub transport_environment_profiles: HashMap<String, String>,
+
+    /// Named key remap tables, selected per session (REST `keyRemap` or a template's `key_remap`);
+    /// each maps an input sequence to the sequence written to the PTY instead
+    #[serde(default)]
+    pub key_remaps: HashMap<String, HashMap<String, String>>,
+
+    /// How REST requests and terminal connections are authenticated
+    #[serde(default)]
+    pub auth: AuthConfig,
+
     /// Default shell configuration (used as fallback for all shells)
     pub default_shell_config: DefaultShellConfig,
 
@@ -27,6 +269,175 @@ pub struct TerminalConfig {
     pub shells: std::collections::HashMap<String, ShellConfig>,
 }
 
+fn default_http_port() -> u16 {
+    8080
+}
+
+fn default_webtransport_port() -> u16 {
+    8082
+}
+
+fn default_environment_reveal_allowlist() -> Vec<String> {
+    vec!["PATH".to_string(), "TERM".to_string()]
+}
+
+fn default_enable_websocket() -> bool {
+    true
+}
+
+fn default_enable_webtransport() -> bool {
+    true
+}
+
+fn default_frame_max_line_bytes() -> usize {
+    4096
+}
+
+fn default_pty_write_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_stalled_input_buffer_bytes() -> usize {
+    64 * 1024
+}
+
+fn default_connection_send_timeout_ms() -> u64 {
+    30_000
+}
+
+fn default_keepalive_interval_secs() -> u64 {
+    30
+}
+
+fn default_lock_buffer_bytes() -> usize {
+    64 * 1024
+}
+
+fn default_max_concurrent_pty_spawns() -> usize {
+    DEFAULT_MAX_CONCURRENT_PTY_SPAWNS
+}
+
+fn default_pty_read_buffer_pool() -> usize {
+    DEFAULT_PTY_READ_BUFFER_POOL
+}
+
+fn default_max_message_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_scrollback_limit_bytes() -> usize {
+    256 * 1024
+}
+
+fn default_scrollback_memory_budget_bytes() -> usize {
+    64 * 1024 * 1024
+}
+
+fn default_shutdown_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_max_shutdown_duration_ms() -> u64 {
+    30_000
+}
+
+fn default_exec_timeout_ms() -> u64 {
+    30_000
+}
+
+fn default_exec_output_limit_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_session_output_log_max_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_session_tmpdir_max_bytes() -> u64 {
+    10 * 1024 * 1024 * 1024
+}
+
+fn default_session_tmpdir_max_age_secs() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_diagnostics_log_events() -> usize {
+    50
+}
+
+/// How terminal output is split into frames
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameMode {
+    /// Output is sent as it is read from the PTY
+    #[default]
+    Raw,
+    /// Output is buffered until a newline and sent as one frame per line
+    Lines,
+}
+
+/// What happens to client input while the terminal isn't accepting it
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StalledInputPolicy {
+    /// The input is dropped
+    #[default]
+    Drop,
+    /// The input is kept and written ahead of the next input the terminal accepts
+    Buffer,
+}
+
+/// Authentication provider checking REST requests and terminal connections
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthProviderKind {
+    /// Everyone is let in as the anonymous user
+    #[default]
+    None,
+    /// Bearer tokens listed in `tokens`
+    StaticToken,
+    /// JSON Web Tokens signed with `jwt_secret` (HS256)
+    Jwt,
+}
+
+/// Authentication settings (`[auth]`)
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct AuthConfig {
+    /// Provider checking the credentials
+    #[serde(default)]
+    pub provider: AuthProviderKind,
+
+    /// Accepted tokens of the `static_token` provider
+    #[serde(default)]
+    pub tokens: Vec<StaticTokenConfig>,
+
+    /// Shared secret of the `jwt` provider
+    #[serde(default)]
+    pub jwt_secret: Option<String>,
+
+    /// Required `iss` claim of the `jwt` provider (any issuer when unset)
+    #[serde(default)]
+    pub jwt_issuer: Option<String>,
+}
+
+/// A token accepted by the `static_token` provider and who it authenticates
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct StaticTokenConfig {
+    /// The bearer token
+    pub token: String,
+
+    /// User the token authenticates
+    pub user_id: String,
+
+    /// Roles of the user
+    #[serde(default)]
+    pub roles: Vec<String>,
+
+    /// Shell types the token may start or attach to (every shell when unset)
+    #[serde(default)]
+    pub shells: Option<Vec<String>>,
+}
+
 /// Terminal size configuration
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct TerminalSize {
@@ -66,7 +477,286 @@ pub struct ShellConfig {
     pub environment: Option<std::collections::HashMap<String, String>>,
 }
 
+/// Session template: a named preset of shell, command, size, working directory, environment and labels
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SessionTemplate {
+    /// Shell type (must be configured in `shells`)
+    pub shell_type: String,
+
+    /// Command replacing the shell's command, e.g. `["bin/rails", "console"]`
+    #[serde(default)]
+    pub command: Option<Vec<String>>,
+
+    /// Terminal size (optional, defaults to the shell's size; the request can override it)
+    #[serde(default)]
+    pub size: Option<TerminalSize>,
+
+    /// Working directory (optional, defaults to the shell's working directory)
+    #[serde(default)]
+    pub working_directory: Option<PathBuf>,
+
+    /// Environment variables added to the shell environment
+    #[serde(default)]
+    pub environment: HashMap<String, String>,
+
+    /// Free-form labels reported with the template and its sessions
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+
+    /// Session title, `{user_id}` is replaced by the user ID (the request can override it)
+    #[serde(default)]
+    pub title: Option<String>,
+
+    /// Key remap table (from `key_remaps`; the request can override it)
+    #[serde(default)]
+    pub key_remap: Option<String>,
+}
+
 impl TerminalConfig {
+    /// Check references between configuration sections
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !self.enable_websocket && !self.enable_webtransport {
+            return Err(ConfigError::InvalidStructure(
+                "enable_websocket and enable_webtransport are both false, no terminal transport is left"
+                    .to_string(),
+            ));
+        }
+        for (name, template) in &self.templates {
+            if !self.shells.contains_key(&template.shell_type) {
+                return Err(ConfigError::ShellConfigNotFound(format!(
+                    "{} (used by template {})",
+                    template.shell_type, name
+                )));
+            }
+            if template.command.as_ref().is_some_and(Vec::is_empty) {
+                return Err(ConfigError::InvalidStructure(format!(
+                    "Template {} has an empty command",
+                    name
+                )));
+            }
+            if let Some(key_remap) = &template.key_remap
+                && !self.key_remaps.contains_key(key_remap)
+            {
+                return Err(ConfigError::InvalidStructure(format!(
+                    "Template {} uses unknown key remap {}",
+                    name, key_remap
+                )));
+            }
+        }
+        for (name, table) in &self.key_remaps {
+            if table.keys().any(String::is_empty) {
+                return Err(ConfigError::InvalidStructure(format!(
+                    "Key remap {} maps an empty sequence",
+                    name
+                )));
+            }
+        }
+        if self.lock_after_idle_secs == Some(0) {
+            return Err(ConfigError::InvalidStructure(
+                "lock_after_idle_secs must be greater than 0 (remove it to never lock)".to_string(),
+            ));
+        }
+        match self.auth.provider {
+            AuthProviderKind::None => {}
+            AuthProviderKind::StaticToken => {
+                if self.auth.tokens.is_empty() {
+                    return Err(ConfigError::InvalidStructure(
+                        "auth provider static_token needs at least one entry in auth.tokens"
+                            .to_string(),
+                    ));
+                }
+                if self.auth.tokens.iter().any(|entry| entry.token.is_empty()) {
+                    return Err(ConfigError::InvalidStructure(
+                        "auth.tokens contains an empty token".to_string(),
+                    ));
+                }
+                if let Some(shell) = self
+                    .auth
+                    .tokens
+                    .iter()
+                    .flat_map(|entry| entry.shells.iter().flatten())
+                    .find(|shell| !self.shells.contains_key(*shell))
+                {
+                    return Err(ConfigError::InvalidStructure(format!(
+                        "auth.tokens allows unknown shell type: {}",
+                        shell
+                    )));
+                }
+            }
+            AuthProviderKind::Jwt => {
+                if self.auth.jwt_secret.as_deref().is_none_or(str::is_empty) {
+                    return Err(ConfigError::InvalidStructure(
+                        "auth provider jwt needs auth.jwt_secret".to_string(),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the TCP port the HTTP/WebSocket server listens on
+    pub fn effective_http_port(&self) -> u16 {
+        self.port.unwrap_or(self.http_port)
+    }
+
+    /// Get the name of the config key that decides the HTTP port (for diagnostics)
+    pub fn http_port_key(&self) -> &'static str {
+        if self.port.is_some() {
+            "port"
+        } else {
+            "http_port"
+        }
+    }
+
+    /// Get the UDP port the WebTransport server listens on
+    /// `port` if set, else `http_port` with `single_port` enabled, else `webtransport_port`
+    pub fn effective_webtransport_port(&self) -> u16 {
+        if self.port.is_some() || self.single_port {
+            self.effective_http_port()
+        } else {
+            self.webtransport_port
+        }
+    }
+
+    /// Get the name of the config key that decides the WebTransport port (for diagnostics)
+    pub fn webtransport_port_key(&self) -> &'static str {
+        if self.port.is_some() || self.single_port {
+            self.http_port_key()
+        } else {
+            "webtransport_port"
+        }
+    }
+
+    /// Get the variables of an environment profile
+    pub fn environment_profile(&self, name: &str) -> Option<&HashMap<String, String>> {
+        self.environment_profiles.get(name)
+    }
+
+    /// Split client-supplied environment variables into allowed ones and the names of dropped ones
+    pub fn filter_client_environment(
+        &self,
+        environment: HashMap<String, String>,
+    ) -> (HashMap<String, String>, Vec<String>) {
+        let (allowed, dropped): (HashMap<_, _>, HashMap<_, _>) = environment
+            .into_iter()
+            .partition(|(key, _)| self.client_environment_allowlist.contains(key));
+        (allowed, dropped.into_keys().collect())
+    }
+
+    /// Parse the `locale` a client sent when connecting (`LANG=de_DE.UTF-8,LC_TIME=C`)
+    /// Nothing is forwarded unless `forward_client_locale` is set; names outside
+    /// `CLIENT_LOCALE_VARIABLES` and values that don't look like locale names are refused
+    pub fn client_locale(&self, locale: Option<&str>) -> Result<Vec<(String, String)>, String> {
+        let Some(locale) = locale.filter(|_| self.forward_client_locale) else {
+            return Ok(Vec::new());
+        };
+        let mut variables = Vec::new();
+        for pair in locale.split(',').filter(|pair| !pair.is_empty()) {
+            let Some((name, value)) = pair.split_once('=') else {
+                return Err(format!(
+                    "Invalid locale setting {}, expected NAME=value",
+                    pair
+                ));
+            };
+            if !CLIENT_LOCALE_VARIABLES.contains(&name) {
+                return Err(format!("Locale variable {} can't be forwarded", name));
+            }
+            let valid_value = value.len() <= MAX_CLIENT_LOCALE_VALUE
+                && value
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-' | '@' | ':'));
+            if !valid_value {
+                return Err(format!("Invalid value for locale variable {}", name));
+            }
+            variables.retain(|(key, _): &(String, String)| key != name);
+            variables.push((name.to_string(), value.to_string()));
+        }
+        Ok(variables)
+    }
+
+    /// Get the environment of a shell: default variables, overridden by the shell's variables,
+    /// the variables of the environment profile (unknown profiles add nothing), the variables
+    /// set by the client and finally the variables of the terminal profile
+    pub fn shell_environment(
+        &self,
+        shell_type: &str,
+        environment_profile: Option<&str>,
+        client_environment: &HashMap<String, String>,
+        terminal_profile: TerminalProfile,
+    ) -> Vec<(String, String)> {
+        self.shell_environment_sources(
+            shell_type,
+            environment_profile,
+            client_environment,
+            &[],
+            terminal_profile,
+        )
+        .into_iter()
+        .map(|(key, value, _)| (key, value))
+        .collect()
+    }
+
+    /// Get the environment of a shell like `shell_environment`, with the source of each value
+    pub fn shell_environment_sources(
+        &self,
+        shell_type: &str,
+        environment_profile: Option<&str>,
+        client_environment: &HashMap<String, String>,
+        client_locale: &[(String, String)],
+        terminal_profile: TerminalProfile,
+    ) -> Vec<(String, String, EnvironmentSource)> {
+        let layers = [
+            (
+                EnvironmentSource::DefaultConfig,
+                self.default_shell_config.environment.as_ref(),
+            ),
+            (
+                EnvironmentSource::ShellConfig,
+                self.shells
+                    .get(shell_type)
+                    .and_then(|sc| sc.environment.as_ref()),
+            ),
+            (
+                EnvironmentSource::Profile,
+                environment_profile.and_then(|profile| self.environment_profile(profile)),
+            ),
+            (EnvironmentSource::Request, Some(client_environment)),
+        ];
+
+        let mut environment: Vec<(String, String, EnvironmentSource)> = Vec::new();
+        for (source, variables) in layers {
+            for (key, value) in variables.into_iter().flatten() {
+                // Later layers replace variables of earlier ones
+                if let Some(index) = environment.iter().position(|(k, _, _)| k == key) {
+                    environment[index] = (key.clone(), value.clone(), source);
+                } else {
+                    environment.push((key.clone(), value.clone(), source));
+                }
+            }
+        }
+        // The locale the connecting client renders with replaces the configured one
+        for (key, value) in client_locale {
+            let entry = (key.clone(), value.clone(), EnvironmentSource::ClientLocale);
+            match environment.iter().position(|(k, _, _)| k == key) {
+                Some(index) => environment[index] = entry,
+                None => environment.push(entry),
+            }
+        }
+        // The terminal profile describes what the client can display, nothing may override it
+        for (key, value) in terminal_profile.environment() {
+            let entry = (
+                key.to_string(),
+                value.to_string(),
+                EnvironmentSource::TerminalProfile,
+            );
+            match environment.iter().position(|(k, _, _)| k == key) {
+                Some(index) => environment[index] = entry,
+                None => environment.push(entry),
+            }
+        }
+        environment
+    }
+
     /// Get the complete shell configuration for a given shell type
     /// Priority: shell-specific config > default config
     pub fn get_shell_config(&self, shell_type: &str) -> ResolvedShellConfig {
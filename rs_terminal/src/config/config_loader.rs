@@ -1,3 +1,4 @@
+use crate::config::migration::legacy_keys;
 use crate::config::{ConfigError, TerminalConfig};
 /// Configuration file loader for rs_terminal
 use std::fs::File;
@@ -44,18 +45,40 @@ impl ConfigLoader {
     fn load_config_from_file(&self, path: &Path) -> Result<TerminalConfig, ConfigError> {
         info!("Loading configuration from file: {:?}", path);
 
-        let mut file = File::open(path)?;
+        let file = File::open(path)?;
+        self.load_config_from_reader(file)
+    }
+
+    /// Load configuration from standard input (`--config -`)
+    pub fn load_config_from_stdin(&self) -> Result<TerminalConfig, ConfigError> {
+        info!("Loading configuration from stdin");
+        self.load_config_from_reader(std::io::stdin().lock())
+    }
 
+    /// Load configuration from any reader, bypassing file lookup entirely
+    pub fn load_config_from_reader(
+        &self,
+        mut reader: impl Read,
+    ) -> Result<TerminalConfig, ConfigError> {
         let mut contents = String::new();
-        file.read_to_string(&mut contents)?;
+        reader.read_to_string(&mut contents)?;
 
         self.parse_config(&contents)
     }
 
     /// Parse configuration from string content
-    fn parse_config(&self, content: &str) -> Result<TerminalConfig, ConfigError> {
+    /// Files of an older layout are pointed to `migrate-config` instead of failing on the first
+    /// key that doesn't fit
+    pub fn parse_config(&self, content: &str) -> Result<TerminalConfig, ConfigError> {
+        if let Ok(table) = toml::from_str::<toml::Table>(content) {
+            let legacy = legacy_keys(&table);
+            if !legacy.is_empty() {
+                return Err(ConfigError::LegacyLayout(legacy.join(", ")));
+            }
+        }
         match toml::from_str::<TerminalConfig>(content) {
             Ok(config) => {
+                config.validate()?;
                 info!("Configuration parsed successfully");
                 Ok(config)
             }
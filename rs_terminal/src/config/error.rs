@@ -29,4 +29,10 @@ pub enum ConfigError {
     /// Shell configuration not found
     #[error("Shell configuration not found for: {0}")]
     ShellConfigNotFound(String),
+
+    /// The configuration uses keys of an older layout
+    #[error(
+        "Configuration uses keys of an older layout ({0}); convert it with `rs_terminal migrate-config <file> --write <file>`"
+    )]
+    LegacyLayout(String),
 }
@@ -1,8 +1,38 @@
+use std::sync::Arc;
+
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::app_state::{DiagnosticsLayer, DiagnosticsStore};
+
+/// Format of the log lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields of the enclosing spans (such as `session_id`)
+    Json,
+}
+
 /// Initialize logging configuration
-pub fn init_logging() {
-    tracing_subscriber::fmt()
+/// Warnings and errors logged within sessions are also recorded into `diagnostics`
+pub fn init_logging(diagnostics: Arc<DiagnosticsStore>, format: LogFormat) {
+    let builder = tracing_subscriber::fmt()
         .with_env_filter("rs_terminal=debug")
         .with_thread_ids(true)
-        .with_thread_names(true)
-        .init();
+        .with_thread_names(true);
+    match format {
+        LogFormat::Text => builder
+            .finish()
+            .with(DiagnosticsLayer::new(diagnostics))
+            .init(),
+        LogFormat::Json => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .finish()
+            .with(DiagnosticsLayer::new(diagnostics))
+            .init(),
+    }
 }
@@ -0,0 +1,238 @@
+/// Conversion of configuration files written for older layouts
+/// Each layout change adds entries to `LEGACY_KEYS`; detection and conversion are driven by it
+use toml::{Table, Value};
+
+use crate::config::ConfigError;
+
+/// How the value of a legacy key is converted
+#[derive(Debug, Clone, Copy)]
+enum Conversion {
+    /// The value is moved as it is
+    Move,
+    /// A command line string is split at whitespace into the command array
+    SplitCommand,
+}
+
+impl Conversion {
+    /// Convert a value, `None` if it already has the current form
+    fn convert(self, value: &Value) -> Option<Value> {
+        match self {
+            Conversion::Move => Some(value.clone()),
+            Conversion::SplitCommand => value.as_str().map(|command| {
+                Value::Array(
+                    command
+                        .split_whitespace()
+                        .map(|part| Value::String(part.to_string()))
+                        .collect(),
+                )
+            }),
+        }
+    }
+}
+
+/// A key of an older configuration layout
+struct LegacyKey {
+    /// Dotted path of the key, `*` matches any key (e.g. any shell name)
+    path: &'static str,
+    /// Dotted path in the current layout, `*` stands for the key matched in `path`
+    replacement: &'static str,
+    /// How the value is converted
+    conversion: Conversion,
+}
+
+/// Legacy keys in the order they are converted (later entries see the result of earlier ones)
+const LEGACY_KEYS: &[LegacyKey] = &[
+    LegacyKey {
+        path: "shell",
+        replacement: "default_shell_type",
+        conversion: Conversion::Move,
+    },
+    LegacyKey {
+        path: "default_size",
+        replacement: "default_shell_config.size",
+        conversion: Conversion::Move,
+    },
+    LegacyKey {
+        path: "default_working_directory",
+        replacement: "default_shell_config.working_directory",
+        conversion: Conversion::Move,
+    },
+    LegacyKey {
+        path: "default_environment",
+        replacement: "default_shell_config.environment",
+        conversion: Conversion::Move,
+    },
+    LegacyKey {
+        path: "default_shell_config.size.cols",
+        replacement: "default_shell_config.size.columns",
+        conversion: Conversion::Move,
+    },
+    LegacyKey {
+        path: "shells.*.size.cols",
+        replacement: "shells.*.size.columns",
+        conversion: Conversion::Move,
+    },
+    LegacyKey {
+        path: "shells.*.env",
+        replacement: "shells.*.environment",
+        conversion: Conversion::Move,
+    },
+    LegacyKey {
+        path: "shells.*.command",
+        replacement: "shells.*.command",
+        conversion: Conversion::SplitCommand,
+    },
+];
+
+/// Result of converting a configuration
+#[derive(Debug)]
+pub struct ConfigMigration {
+    /// The converted configuration (TOML)
+    pub config: String,
+    /// One line per conversion that was applied
+    pub changes: Vec<String>,
+}
+
+/// Find the concrete paths matching a pattern: the path and the keys matched by `*`
+fn matching_paths(table: &Table, pattern: &[&str]) -> Vec<(Vec<String>, Vec<String>)> {
+    let Some((first, rest)) = pattern.split_first() else {
+        return Vec::new();
+    };
+    let keys: Vec<&String> = if *first == "*" {
+        table.keys().collect()
+    } else {
+        table
+            .get_key_value(*first)
+            .map(|(key, _)| key)
+            .into_iter()
+            .collect()
+    };
+
+    let mut paths = Vec::new();
+    for key in keys {
+        let wildcard: Vec<String> = if *first == "*" {
+            vec![key.clone()]
+        } else {
+            Vec::new()
+        };
+        if rest.is_empty() {
+            paths.push((vec![key.clone()], wildcard));
+        } else if let Some(Value::Table(child)) = table.get(key) {
+            for (mut path, mut matched) in matching_paths(child, rest) {
+                path.insert(0, key.clone());
+                matched.splice(0..0, wildcard.iter().cloned());
+                paths.push((path, matched));
+            }
+        }
+    }
+    paths
+}
+
+/// Fill the `*` of a pattern with the matched keys
+fn fill_pattern(pattern: &str, matched: &[String]) -> Vec<String> {
+    let mut matched = matched.iter();
+    pattern
+        .split('.')
+        .map(|part| match part {
+            "*" => matched.next().cloned().unwrap_or_default(),
+            part => part.to_string(),
+        })
+        .collect()
+}
+
+fn get<'a>(table: &'a Table, path: &[String]) -> Option<&'a Value> {
+    let (last, parents) = path.split_last()?;
+    let mut table = table;
+    for key in parents {
+        table = table.get(key)?.as_table()?;
+    }
+    table.get(last)
+}
+
+fn remove(table: &mut Table, path: &[String]) -> Option<Value> {
+    let (last, parents) = path.split_last()?;
+    let mut table = table;
+    for key in parents {
+        table = table.get_mut(key)?.as_table_mut()?;
+    }
+    table.remove(last)
+}
+
+/// Insert a value, creating the tables on its path; fails if a key on the path isn't a table
+fn insert(table: &mut Table, path: &[String], value: Value) -> Result<(), ()> {
+    let (last, parents) = path.split_last().ok_or(())?;
+    let mut table = table;
+    for key in parents {
+        table = table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Table(Table::new()))
+            .as_table_mut()
+            .ok_or(())?;
+    }
+    table.insert(last.clone(), value);
+    Ok(())
+}
+
+/// Legacy keys present in a configuration, as dotted paths
+pub fn legacy_keys(table: &Table) -> Vec<String> {
+    let mut found = Vec::new();
+    for legacy in LEGACY_KEYS {
+        let pattern: Vec<&str> = legacy.path.split('.').collect();
+        for (path, _) in matching_paths(table, &pattern) {
+            if get(table, &path).is_some_and(|value| legacy.conversion.convert(value).is_some()) {
+                found.push(path.join("."));
+            }
+        }
+    }
+    found
+}
+
+/// Convert the legacy keys of a configuration table, returning a description of each change
+fn migrate_table(table: &mut Table) -> Vec<String> {
+    let mut changes = Vec::new();
+    for legacy in LEGACY_KEYS {
+        let pattern: Vec<&str> = legacy.path.split('.').collect();
+        for (path, matched) in matching_paths(table, &pattern) {
+            let Some(converted) =
+                get(table, &path).and_then(|value| legacy.conversion.convert(value))
+            else {
+                continue;
+            };
+            let replacement = fill_pattern(legacy.replacement, &matched);
+            let (from, to) = (path.join("."), replacement.join("."));
+
+            if replacement != path && get(table, &replacement).is_some() {
+                remove(table, &path);
+                changes.push(format!("removed `{}`: `{}` is already set", from, to));
+                continue;
+            }
+            remove(table, &path);
+            if insert(table, &replacement, converted).is_err() {
+                changes.push(format!("removed `{}`: `{}` can't be created", from, to));
+                continue;
+            }
+            changes.push(match legacy.conversion {
+                Conversion::Move => format!("moved `{}` to `{}`", from, to),
+                Conversion::SplitCommand => format!(
+                    "split `{}` into an array (check arguments that contained quoted spaces)",
+                    to
+                ),
+            });
+        }
+    }
+    changes
+}
+
+/// Convert a configuration written for an older layout to the current layout
+/// Comments are not preserved; check the result with `ConfigLoader::parse_config`
+pub fn migrate_config(content: &str) -> Result<ConfigMigration, ConfigError> {
+    let mut table: Table = toml::from_str(content)?;
+    let changes = migrate_table(&mut table);
+    let config = toml::to_string(&table).map_err(|e| {
+        ConfigError::InvalidStructure(format!(
+            "Failed to write the converted configuration: {}",
+            e
+        ))
+    })?;
+    Ok(ConfigMigration { config, changes })
+}
@@ -3,8 +3,12 @@ mod config;
 mod config_loader;
 mod error;
 mod logging;
+mod migration;
+mod redact;
 
 pub use config::*;
 pub use config_loader::ConfigLoader;
 pub use error::ConfigError;
-pub use logging::init_logging;
+pub use logging::{LogFormat, init_logging};
+pub use migration::{ConfigMigration, migrate_config};
+pub use redact::redacted_config;
@@ -0,0 +1,76 @@
+/// Redaction of the configuration shown by the admin config endpoint
+use serde_json::Value;
+
+use super::TerminalConfig;
+
+/// Replacement for redacted values
+const REDACTED: &str = "<redacted>";
+
+/// Parts of setting names that mark their value as secret (tokens, keys, certificates)
+const SECRET_SETTING_MARKERS: [&str; 6] = [
+    "token",
+    "secret",
+    "password",
+    "credential",
+    "cert",
+    "private_key",
+];
+
+/// The configuration as JSON with secrets replaced by `<redacted>`
+/// Environment variable values are redacted unless listed in `environment_reveal_allowlist`,
+/// settings whose name marks them as secret are redacted unless unset
+pub fn redacted_config(config: &TerminalConfig) -> Result<Value, serde_json::Error> {
+    let mut value = serde_json::to_value(config)?;
+    redact(&mut value, &config.environment_reveal_allowlist);
+    Ok(value)
+}
+
+/// Redact the secrets inside a configuration value
+fn redact(value: &mut Value, reveal: &[String]) {
+    match value {
+        Value::Object(settings) => {
+            for (name, setting) in settings.iter_mut() {
+                if is_secret_setting(name) {
+                    if !setting.is_null() {
+                        *setting = Value::String(REDACTED.to_string());
+                    }
+                } else if name == "environment" {
+                    redact_environment(setting, reveal);
+                } else if name == "environment_profiles" {
+                    if let Value::Object(profiles) = setting {
+                        for profile in profiles.values_mut() {
+                            redact_environment(profile, reveal);
+                        }
+                    }
+                } else {
+                    redact(setting, reveal);
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                redact(item, reveal);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Redact the values of an environment variable map, keeping the names
+fn redact_environment(environment: &mut Value, reveal: &[String]) {
+    if let Value::Object(variables) = environment {
+        for (name, value) in variables.iter_mut() {
+            if !reveal.contains(name) {
+                *value = Value::String(REDACTED.to_string());
+            }
+        }
+    }
+}
+
+/// Whether a setting holds a secret, judged by its name
+fn is_secret_setting(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    SECRET_SETTING_MARKERS
+        .iter()
+        .any(|marker| name.contains(marker))
+}
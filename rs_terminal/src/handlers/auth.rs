@@ -0,0 +1,128 @@
+use axum::{
+    Extension, Json,
+    extract::{Request, State},
+    http::{Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tracing::{debug, warn};
+
+use crate::{api::dto::ErrorResponse, app_state::AppState, auth::AuthContext};
+
+/// Authenticate REST requests and WebSocket upgrades before they reach their handler
+/// The `AuthContext` is added to the request extensions; failures get a 401
+pub async fn require_auth(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    // CORS preflight requests carry no credentials
+    if request.method() == Method::OPTIONS {
+        return next.run(request).await;
+    }
+
+    let (mut parts, body) = request.into_parts();
+    match state.auth.authenticate(&parts).await {
+        Ok(context) => {
+            debug!(
+                "Authenticated {} {} as {} ({})",
+                parts.method,
+                parts.uri.path(),
+                context.user_id,
+                state.auth.name()
+            );
+            parts.extensions.insert(context);
+            next.run(Request::from_parts(parts, body)).await
+        }
+        Err(e) => {
+            warn!(
+                "Refusing unauthenticated {} {}: {}",
+                parts.method,
+                parts.uri.path(),
+                e
+            );
+            let error_response = ErrorResponse {
+                error: true,
+                message: e.to_string(),
+                code: Some(401),
+            };
+            (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                Json(error_response),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Let only administrators through to the `/api/admin` endpoints
+/// Runs after `require_auth`, which added the `AuthContext`
+pub async fn require_admin(
+    Extension(auth): Extension<AuthContext>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if auth.is_admin() {
+        return next.run(request).await;
+    }
+    warn!(
+        "Refusing {} {} to {}, who is not an administrator",
+        request.method(),
+        request.uri().path(),
+        auth.user_id
+    );
+    forbidden("Administrator role required".to_string())
+}
+
+/// Reject access to a session of another user with 403 (sessions that don't exist pass, so
+/// handlers can answer 404 or create them)
+pub async fn check_session_owner(
+    state: &AppState,
+    auth: &AuthContext,
+    session_id: &str,
+) -> Option<Response> {
+    let session = state.get_session(session_id).await?;
+    if auth.can_access(&session.user_id) {
+        return None;
+    }
+    warn!(
+        "Refusing {} access to session {} of {}",
+        auth.user_id, session_id, session.user_id
+    );
+    Some(forbidden(format!(
+        "Session belongs to another user: {}",
+        session_id
+    )))
+}
+
+/// Reject a shell type the caller's credentials don't allow with 403
+pub fn check_shell_scope(auth: &AuthContext, shell_type: &str) -> Option<Response> {
+    if auth.can_use_shell(shell_type) {
+        return None;
+    }
+    warn!(
+        "Refusing {} shell {}, which their credentials don't allow",
+        auth.user_id, shell_type
+    );
+    Some(forbidden(format!(
+        "Shell type not allowed for these credentials: {}",
+        shell_type
+    )))
+}
+
+/// Get the shell a connection attaching to a session uses: the session's, or the default shell
+/// for sessions the connection creates
+pub async fn attach_shell_type(state: &AppState, session_id: Option<&str>) -> String {
+    match session_id {
+        Some(session_id) => state.get_session(session_id).await.map(|s| s.shell_type),
+        None => None,
+    }
+    .unwrap_or_else(|| state.config.default_shell_type.clone())
+}
+
+/// Respond with 403 Forbidden and an error message
+pub fn forbidden(message: String) -> Response {
+    let error_response = ErrorResponse {
+        error: true,
+        message,
+        code: Some(403),
+    };
+    (StatusCode::FORBIDDEN, Json(error_response)).into_response()
+}
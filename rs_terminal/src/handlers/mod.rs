@@ -1,3 +1,5 @@
+pub mod auth;
 pub mod rest;
 pub mod websocket;
+pub mod websocket_upgrade;
 pub mod webtransport;
@@ -1,67 +1,70 @@
 use axum::response::IntoResponse;
 /// REST API handlers for terminal session management
 use axum::{
-    extract::{Json, Path, State},
-    http::StatusCode,
+    Extension,
+    extract::{Json, Path, Query, State},
+    http::{StatusCode, header},
 };
+use serde::Deserialize;
 use serde_json::to_value;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
 use crate::{
     api::dto::{
-        CreateSessionRequest, ErrorResponse, ResizeTerminalRequest, SuccessResponse,
-        TerminalResizeResponse, TerminalSession, TerminalTerminateResponse,
+        BroadcastRequest, BroadcastResponse, BulkTerminateResponse, CreateSessionRequest,
+        ErrorResponse, ExecRequest, ExecResponse, HealthResponse, PtyStatsResponse,
+        ResizeTerminalRequest, SessionDiagnosticsResponse, SessionDryRunResponse,
+        SessionEnvironmentResponse, SessionTemplateInfo, ShellInfo, ShellsResponse,
+        SuccessResponse, TerminalCapabilities, TerminalResizeResponse, TerminalSession,
+        TerminalTerminateResponse, UpdateSessionRequest,
+    },
+    app_state::{AppState, ConnectionType, Session, SessionParams, fold_title},
+    auth::AuthContext,
+    config::redacted_config,
+    handlers::auth::{check_session_owner, check_shell_scope, forbidden},
+    service::{
+        ExecCommand, SESSION_TMPDIR_VAR, end_detached_session, resolve_session_pty, run_command,
+        shutdown_all,
     },
-    app_state::{AppState, ConnectionType, Session},
 };
+use std::time::Duration;
+
+/// Version reported by the health endpoints
+const SERVER_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 /// Create a new terminal session
 pub async fn create_session(
     State(state): State<AppState>,
-    Json(req): Json<CreateSessionRequest>,
+    Extension(auth): Extension<AuthContext>,
+    Json(mut req): Json<CreateSessionRequest>,
 ) -> impl IntoResponse {
+    // Use the latest configuration (it may have been reloaded since startup)
+    let state = state.with_current_config();
+    if let Some(rejection) = assign_session_owner(&auth, &mut req) {
+        return rejection;
+    }
     info!("Creating new terminal session for user: {}", req.user_id);
 
-    // Generate a new session ID
-    let session_id = Uuid::new_v4().to_string();
-
-    // Determine shell type (request > default)
-    let shell_type = req
-        .shell_type
-        .clone()
-        .unwrap_or_else(|| state.config.default_shell_type.clone());
-
-    // Get the complete resolved shell configuration (shell config > default config)
-    let resolved_shell_config = state.config.get_shell_config(&shell_type);
-
-    // Determine final parameters with correct priority: request > resolved shell config
-    let columns = req.columns.unwrap_or(resolved_shell_config.size.columns);
-    let rows = req.rows.unwrap_or(resolved_shell_config.size.rows);
-
-    // Determine working directory: request > resolved shell config
-    let working_directory = req.working_directory.clone().or_else(|| {
-        resolved_shell_config
-            .working_directory
-            .clone()
-            // Convert PathBuf to String
-            .map(|path| path.to_string_lossy().to_string())
-    });
+    if state.is_draining() {
+        return Draining.into_response();
+    }
 
-    // Create session with properly resolved parameters
-    let session = Session::new(
-        session_id.clone(),
-        req.user_id,
-        req.title,
-        working_directory,
-        shell_type,
-        columns,
-        rows,
-        ConnectionType::WebSocket,
-    );
+    let mut session = match resolve_session_config(&state, req) {
+        Ok(resolved) => resolved.session,
+        Err(message) => return bad_request(message),
+    };
+    if let Some(rejection) = check_shell_scope(&auth, &session.shell_type) {
+        return rejection;
+    }
+    let session_id = session.id.clone();
 
     // Add session to application state
-    state.add_session(session.clone()).await;
+    if state.config.unique_titles_per_user {
+        session.title = state.add_session_with_unique_title(session.clone()).await;
+    } else {
+        state.add_session(session.clone()).await;
+    }
 
     // Map to API response DTO with correct field names
     let response = TerminalSession {
@@ -75,46 +78,464 @@ pub async fn create_session(
         shell_type: session.shell_type,
         connection_type: format!("{:?}", session.connection_type),
         created_at: session.created_at,
+        capabilities: session.capabilities,
+        read_only: session.read_only,
+        terminal_profile: session.terminal_profile,
+        template: session.template,
+        labels: session.labels,
+        input_latency_p95_ms: None,
     };
 
     info!("Created session: {}", session_id);
 
-    (StatusCode::CREATED, Json(response))
+    (StatusCode::CREATED, Json(response)).into_response()
+}
+
+/// Resolve a session like `create_session` without creating it or starting its shell
+/// Reports the shell, size, working directory and environment the session would get
+pub async fn dry_run_session(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Json(mut req): Json<CreateSessionRequest>,
+) -> axum::response::Response {
+    let state = state.with_current_config();
+    if let Some(rejection) = assign_session_owner(&auth, &mut req) {
+        return rejection;
+    }
+    info!("Dry run of terminal session for user: {}", req.user_id);
+
+    let ResolvedSession {
+        mut session,
+        mut warnings,
+    } = match resolve_session_config(&state, req) {
+        Ok(resolved) => resolved,
+        Err(message) => return bad_request(message),
+    };
+    if let Some(rejection) = check_shell_scope(&auth, &session.shell_type) {
+        return rejection;
+    }
+    if state.is_draining() {
+        warnings.push("The server is draining and refuses new sessions".to_string());
+    }
+
+    let mut resolved = match resolve_session_pty(&state, &mut session) {
+        Ok(resolved) => resolved,
+        Err(e) => return bad_request(format!("Failed to resolve the shell: {}", e)),
+    };
+    if state.config.resolve_command_path {
+        match crate::pty::find_in_path(&resolved.pty_config.command, &resolved.pty_config.env).await
+        {
+            Ok(Some(path)) => resolved.pty_config.command = path.to_string_lossy().into_owned(),
+            Ok(None) => {}
+            Err(cause) => warnings.push(format!("The shell won't start: {}", cause)),
+        }
+    }
+
+    // The scratch directory only exists once the session starts
+    let working_directory = match &resolved.pty_config.cwd {
+        Some(cwd)
+            if session
+                .environment
+                .get(SESSION_TMPDIR_VAR)
+                .is_some_and(|tmpdir| cwd.as_os_str() == tmpdir.as_str()) =>
+        {
+            Some(cwd.to_string_lossy().into_owned())
+        }
+        Some(cwd) => match tokio::fs::canonicalize(cwd).await {
+            Ok(path) => Some(path.to_string_lossy().into_owned()),
+            Err(e) => {
+                warnings.push(format!(
+                    "Working directory {} is not accessible: {}",
+                    cwd.display(),
+                    e
+                ));
+                Some(cwd.to_string_lossy().into_owned())
+            }
+        },
+        None => None,
+    };
+
+    let environment = session
+        .environment_snapshot
+        .unwrap_or_default()
+        .into_iter()
+        .map(|mut variable| {
+            variable.value = None;
+            variable
+        })
+        .collect();
+
+    let response = SessionDryRunResponse {
+        shell_type: resolved.shell_type,
+        command: resolved.pty_config.command,
+        args: resolved.pty_config.args,
+        columns: resolved.pty_config.cols,
+        rows: resolved.pty_config.rows,
+        working_directory,
+        title: session.title,
+        environment_profile: resolved.environment_profile,
+        terminal_profile: session.terminal_profile,
+        capabilities: session.capabilities,
+        environment,
+        pty_backend: state.pty_factory.name().to_string(),
+        warnings,
+    };
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// Make the caller the owner of the session a create request asks for
+/// Only administrators may create sessions for other users, others get 403
+fn assign_session_owner(
+    auth: &AuthContext,
+    req: &mut CreateSessionRequest,
+) -> Option<axum::response::Response> {
+    if req.user_id.is_empty() {
+        req.user_id = auth.user_id.clone();
+    } else if !auth.can_access(&req.user_id) {
+        warn!(
+            "Refusing {} a session for another user: {}",
+            auth.user_id, req.user_id
+        );
+        return Some(forbidden(format!(
+            "Sessions can't be created for another user: {}",
+            req.user_id
+        )));
+    }
+    None
+}
+
+/// Session a create request resolves to
+struct ResolvedSession {
+    session: Session,
+    /// Problems that don't prevent creating the session
+    warnings: Vec<String>,
+}
+
+/// Resolve a create request into the session it creates, shared by creation and dry runs
+/// Unknown environment profiles, templates, key remaps and shells are rejected with a message
+/// for a 400 response
+fn resolve_session_config(
+    state: &AppState,
+    req: CreateSessionRequest,
+) -> Result<ResolvedSession, String> {
+    let mut warnings = Vec::new();
+
+    // Reject unknown environment profiles, templates and shells before anything is created
+    if let Some(profile) = &req.environment_profile
+        && state.config.environment_profile(profile).is_none()
+    {
+        return Err(format!("Unknown environment profile: {}", profile));
+    }
+    let template = match &req.template {
+        Some(name) => match state.config.templates.get(name) {
+            Some(template) => Some(template),
+            None => return Err(format!("Unknown template: {}", name)),
+        },
+        None => None,
+    };
+    if let Some(key_remap) = &req.key_remap
+        && !state.config.key_remaps.contains_key(key_remap)
+    {
+        return Err(format!("Unknown key remap: {}", key_remap));
+    }
+    if let Some(shell_type) = &req.shell_type
+        && !state.config.shells.contains_key(shell_type)
+    {
+        return Err(format!("Unknown shell type: {}", shell_type));
+    }
+
+    // Only keep the environment variables clients are allowed to set
+    let (client_environment, dropped) = state.config.filter_client_environment(req.environment);
+    if !dropped.is_empty() {
+        warn!(
+            "Dropping environment variables not in client_environment_allowlist: {}",
+            dropped.join(", ")
+        );
+        warnings.push(format!(
+            "Environment variables not in client_environment_allowlist are dropped: {}",
+            dropped.join(", ")
+        ));
+    }
+
+    // Template variables first, the client's allowed variables on top
+    let mut environment = template
+        .map(|template| template.environment.clone())
+        .unwrap_or_default();
+    environment.extend(client_environment);
+
+    // Generate a new session ID
+    let session_id = Uuid::new_v4().to_string();
+
+    // Determine shell type (template > request > default)
+    if let (Some(template), Some(requested)) = (template, &req.shell_type)
+        && template.shell_type != *requested
+    {
+        warnings.push(format!(
+            "The template starts shell {}, the requested shell {} is ignored",
+            template.shell_type, requested
+        ));
+    }
+    let shell_type = template
+        .map(|template| template.shell_type.clone())
+        .or_else(|| req.shell_type.clone())
+        .unwrap_or_else(|| state.config.default_shell_type.clone());
+
+    // Get the complete resolved shell configuration (shell config > default config)
+    let resolved_shell_config = state.config.get_shell_config(&shell_type);
+
+    // Determine final parameters with correct priority: request > template > resolved shell config
+    let template_size = template.and_then(|template| template.size.as_ref());
+    let columns = req
+        .columns
+        .unwrap_or(template_size.map_or(resolved_shell_config.size.columns, |size| size.columns));
+    let rows = req
+        .rows
+        .unwrap_or(template_size.map_or(resolved_shell_config.size.rows, |size| size.rows));
+
+    // Determine working directory: template > request > resolved shell config
+    let template_working_directory = template
+        .and_then(|template| template.working_directory.clone())
+        .map(|path| path.to_string_lossy().to_string());
+    if let (Some(template_directory), Some(requested)) =
+        (&template_working_directory, &req.working_directory)
+        && template_directory != requested
+    {
+        warnings.push(format!(
+            "The template starts in {}, the requested working directory {} is ignored",
+            template_directory, requested
+        ));
+    }
+    let working_directory = template_working_directory
+        .or_else(|| req.working_directory.clone())
+        .or_else(|| {
+            resolved_shell_config
+                .working_directory
+                .clone()
+                // Convert PathBuf to String
+                .map(|path| path.to_string_lossy().to_string())
+        });
+
+    // Determine title: request > template title pattern
+    let title = req.title.or_else(|| {
+        template
+            .and_then(|template| template.title.as_ref())
+            .map(|pattern| pattern.replace("{user_id}", &req.user_id))
+    });
+
+    // Create session with properly resolved parameters
+    let mut session = Session::new(
+        SessionParams {
+            id: session_id,
+            user_id: req.user_id,
+            title,
+            working_directory,
+            shell_type,
+            columns,
+            rows,
+            connection_type: ConnectionType::WebSocket,
+        },
+        state.clock.now_unix(),
+    );
+
+    let terminal_profile = req.terminal_profile.unwrap_or_default();
+    session.terminal_profile = terminal_profile;
+    session.capabilities = TerminalCapabilities::from_environment(
+        state
+            .config
+            .shell_environment(
+                &session.shell_type,
+                req.environment_profile.as_deref(),
+                &environment,
+                terminal_profile,
+            )
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str())),
+    );
+    session.environment_profile = req.environment_profile;
+    session.environment = environment;
+    session.read_only = req.read_only;
+    session.nudge_on_connect = req.nudge_on_connect;
+    session.key_remap = req
+        .key_remap
+        .or_else(|| template.and_then(|template| template.key_remap.clone()));
+    if let Some(template) = template {
+        session.command = template.command.clone();
+        session.labels = template.labels.clone();
+        session.template = req.template;
+    }
+
+    Ok(ResolvedSession { session, warnings })
+}
+
+/// List the configured session templates
+pub async fn get_templates(State(state): State<AppState>) -> impl IntoResponse {
+    let state = state.with_current_config();
+    let mut templates: Vec<SessionTemplateInfo> = state
+        .config
+        .templates
+        .iter()
+        .map(|(name, template)| SessionTemplateInfo {
+            name: name.clone(),
+            shell_type: template.shell_type.clone(),
+            title: template.title.clone(),
+            columns: template.size.as_ref().map(|size| size.columns),
+            rows: template.size.as_ref().map(|size| size.rows),
+            labels: template.labels.clone(),
+        })
+        .collect();
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+
+    (StatusCode::OK, Json(templates))
+}
+
+/// List the configured shells with the size their sessions start with
+pub async fn get_shells(State(state): State<AppState>) -> impl IntoResponse {
+    let state = state.with_current_config();
+    let mut shells: Vec<ShellInfo> = state
+        .config
+        .shells
+        .keys()
+        .map(|name| {
+            let size = state.config.get_shell_config(name).size;
+            ShellInfo {
+                name: name.clone(),
+                columns: size.columns,
+                rows: size.rows,
+            }
+        })
+        .collect();
+    shells.sort_by(|a, b| a.name.cmp(&b.name));
+
+    (
+        StatusCode::OK,
+        Json(ShellsResponse {
+            default_shell: state.config.default_shell_type.clone(),
+            shells,
+        }),
+    )
+}
+
+/// Respond with 400 Bad Request and an error message
+fn bad_request(message: String) -> axum::response::Response {
+    let error_response = ErrorResponse {
+        error: true,
+        message,
+        code: Some(400),
+    };
+    (StatusCode::BAD_REQUEST, Json(error_response)).into_response()
 }
 
 /// Get all terminal sessions
-pub async fn get_all_sessions(State(state): State<AppState>) -> impl IntoResponse {
+/// Administrators get every session, other users their own
+pub async fn get_all_sessions(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+) -> impl IntoResponse {
     info!("Getting all terminal sessions");
 
     // Get all sessions from app state
-    let sessions = state.get_all_sessions().await;
+    let sessions = state
+        .get_all_sessions()
+        .await
+        .into_iter()
+        .filter(|session| auth.can_access(&session.user_id))
+        .collect();
+
+    (
+        StatusCode::OK,
+        Json(state.session_responses(sessions).await),
+    )
+}
+
+/// How `GET /api/sessions/lookup` compares titles
+#[derive(Debug, Default, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum TitleMatch {
+    /// The whole title matches
+    #[default]
+    Exact,
+    /// The title starts with the given text
+    Prefix,
+}
+
+/// Query parameters of `GET /api/sessions/lookup`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionLookupParams {
+    /// Title to look for (compared case-insensitively)
+    pub title: String,
+    /// Exact (default) or prefix match
+    #[serde(default, rename = "match")]
+    pub match_mode: TitleMatch,
+    /// Only return sessions of this user (users other than administrators only find their own)
+    pub user_id: Option<String>,
+}
+
+/// Find sessions by title
+pub async fn lookup_sessions(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Query(mut params): Query<SessionLookupParams>,
+) -> axum::response::Response {
+    if !auth.is_admin() {
+        if let Some(user_id) = params.user_id.as_ref().filter(|id| **id != auth.user_id) {
+            return forbidden(format!(
+                "Sessions of another user can't be looked up: {}",
+                user_id
+            ));
+        }
+        params.user_id = Some(auth.user_id.clone());
+    }
+    info!(
+        "Looking up sessions by title ({:?}): {}",
+        params.match_mode, params.title
+    );
 
-    // Map to API response DTOs
-    let response_sessions: Vec<TerminalSession> = sessions
+    let wanted = fold_title(&params.title);
+    let mut sessions: Vec<Session> = state
+        .get_all_sessions()
+        .await
         .into_iter()
-        .map(|session| TerminalSession {
-            id: session.id,
-            user_id: session.user_id,
-            title: session.title,
-            status: format!("{:?}", session.status).to_lowercase(),
-            columns: session.columns,
-            rows: session.rows,
-            working_directory: session.working_directory,
-            shell_type: session.shell_type,
-            connection_type: format!("{:?}", session.connection_type),
-            created_at: session.created_at,
+        .filter(|session| {
+            params
+                .user_id
+                .as_ref()
+                .is_none_or(|user_id| &session.user_id == user_id)
+        })
+        .filter(|session| {
+            session
+                .title
+                .as_deref()
+                .map(fold_title)
+                .is_some_and(|title| match params.match_mode {
+                    TitleMatch::Exact => title == wanted,
+                    TitleMatch::Prefix => title.starts_with(&wanted),
+                })
         })
         .collect();
+    sessions.sort_by(|a, b| {
+        a.created_at
+            .cmp(&b.created_at)
+            .then_with(|| a.title.cmp(&b.title))
+    });
 
-    (StatusCode::OK, Json(response_sessions))
+    (
+        StatusCode::OK,
+        Json(state.session_responses(sessions).await),
+    )
+        .into_response()
 }
 
 /// Get a specific terminal session
 pub async fn get_session(
     State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
     Path(session_id): Path<String>,
-) -> impl IntoResponse {
+) -> axum::response::Response {
     info!("Getting terminal session: {}", session_id);
+    if let Some(rejection) = check_session_owner(&state, &auth, &session_id).await {
+        return rejection;
+    }
 
     // Get session from app state
     match state.get_session(&session_id).await {
@@ -131,6 +552,12 @@ pub async fn get_session(
                 shell_type: session.shell_type,
                 connection_type: format!("{:?}", session.connection_type),
                 created_at: session.created_at,
+                capabilities: session.capabilities,
+                read_only: session.read_only,
+                terminal_profile: session.terminal_profile,
+                template: session.template,
+                labels: session.labels,
+                input_latency_p95_ms: state.input_latency.p95_ms(&session_id).await,
             };
 
             match to_value(response) {
@@ -178,24 +605,169 @@ pub async fn get_session(
             }
         }
     }
+    .into_response()
+}
+
+/// Query parameters of `GET /api/sessions/:session_id/environment`
+#[derive(Debug, Deserialize)]
+pub struct SessionEnvironmentParams {
+    /// Comma-separated variables whose values are returned (administrators only, must be in
+    /// `environment_reveal_allowlist`)
+    pub reveal: Option<String>,
+}
+
+/// Get the environment a session's shell was started with: names, sources and value hashes
+pub async fn get_session_environment(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Path(session_id): Path<String>,
+    Query(params): Query<SessionEnvironmentParams>,
+) -> axum::response::Response {
+    let state = state.with_current_config();
+    info!("Getting environment of terminal session: {}", session_id);
+    if let Some(rejection) = check_session_owner(&state, &auth, &session_id).await {
+        return rejection;
+    }
+
+    let reveal: Vec<&str> = params
+        .reveal
+        .as_deref()
+        .map(|reveal| reveal.split(',').map(str::trim).collect())
+        .unwrap_or_default();
+    if !reveal.is_empty() && !auth.is_admin() {
+        return forbidden("Only administrators can reveal environment values".to_string());
+    }
+    if let Some(name) = reveal.iter().find(|name| {
+        !state
+            .config
+            .environment_reveal_allowlist
+            .iter()
+            .any(|allowed| allowed == *name)
+    }) {
+        let error_response = ErrorResponse {
+            error: true,
+            message: format!("Environment variable can't be revealed: {}", name),
+            code: Some(403),
+        };
+        return (StatusCode::FORBIDDEN, Json(error_response)).into_response();
+    }
+
+    let Some(session) = state.get_session(&session_id).await else {
+        let error_response = ErrorResponse {
+            error: true,
+            message: format!("Session not found: {}", session_id),
+            code: Some(404),
+        };
+        return (StatusCode::NOT_FOUND, Json(error_response)).into_response();
+    };
+    let Some(mut variables) = session.environment_snapshot else {
+        let error_response = ErrorResponse {
+            error: true,
+            message: "The environment is recorded once the session's shell has started".to_string(),
+            code: Some(409),
+        };
+        return (StatusCode::CONFLICT, Json(error_response)).into_response();
+    };
+
+    // Values are only returned when asked for
+    for variable in &mut variables {
+        if !reveal.contains(&variable.name.as_str()) {
+            variable.value = None;
+        }
+    }
+
+    let response = SessionEnvironmentResponse {
+        session_id,
+        variables,
+    };
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// Get what is needed to correlate a client error report with the server: the session's state,
+/// negotiated protocol, counters, recent warnings and errors, and why it closed
+pub async fn get_session_diagnostics(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Path(session_id): Path<String>,
+) -> axum::response::Response {
+    info!("Getting diagnostics of terminal session: {}", session_id);
+    if let Some(rejection) = check_session_owner(&state, &auth, &session_id).await {
+        return rejection;
+    }
+
+    let Some(session) = state.get_session(&session_id).await else {
+        let error_response = ErrorResponse {
+            error: true,
+            message: format!("Session not found: {}", session_id),
+            code: Some(404),
+        };
+        return (StatusCode::NOT_FOUND, Json(error_response)).into_response();
+    };
+    let environment_profile = session.environment_profile.clone();
+    let diagnostics = state.diagnostics.get(&session_id).unwrap_or_default();
+    let Some(session) = state.session_responses(vec![session]).await.pop() else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    let response = SessionDiagnosticsResponse {
+        session,
+        subprotocol: diagnostics.subprotocol,
+        environment_profile,
+        counters: diagnostics.counters,
+        events: diagnostics.events.into(),
+        close_reason: diagnostics.close_reason,
+    };
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// Empty the scrollback of a session, so reconnecting clients don't replay it
+pub async fn clear_session_scrollback(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Path(session_id): Path<String>,
+) -> axum::response::Response {
+    info!("Clearing scrollback of terminal session: {}", session_id);
+    if let Some(rejection) = check_session_owner(&state, &auth, &session_id).await {
+        return rejection;
+    }
+
+    if state.get_session(&session_id).await.is_none() {
+        let error_response = ErrorResponse {
+            error: true,
+            message: format!("Session not found: {}", session_id),
+            code: Some(404),
+        };
+        return (StatusCode::NOT_FOUND, Json(error_response)).into_response();
+    }
+    let released = state.scrollback.clear(&session_id).await;
+
+    let response = SuccessResponse {
+        success: true,
+        message: format!("Cleared {} bytes of scrollback", released),
+    };
+    (StatusCode::OK, Json(response)).into_response()
 }
 
 /// Resize a terminal session
 pub async fn resize_session(
     State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
     Path(session_id): Path<String>,
     Json(req): Json<ResizeTerminalRequest>,
-) -> impl IntoResponse {
+) -> axum::response::Response {
     info!(
         "Resizing terminal session: {} to {}x{}",
         session_id, req.columns, req.rows
     );
+    if let Some(rejection) = check_session_owner(&state, &auth, &session_id).await {
+        return rejection;
+    }
 
     // Get session from app state
     match state.get_session(&session_id).await {
         Some(mut session) => {
             // Update session size
-            session.resize(req.columns, req.rows);
+            session.resize(req.columns, req.rows, state.clock.now_unix());
 
             // Update session in app state
             if state.update_session(session.clone()).await {
@@ -278,14 +850,157 @@ pub async fn resize_session(
             }
         }
     }
+    .into_response()
+}
+
+/// Update a terminal session (currently only its title)
+pub async fn update_session(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Path(session_id): Path<String>,
+    Json(req): Json<UpdateSessionRequest>,
+) -> axum::response::Response {
+    info!(
+        "Updating title of terminal session {}: {:?}",
+        session_id, req.title
+    );
+    if let Some(rejection) = check_session_owner(&state, &auth, &session_id).await {
+        return rejection;
+    }
+
+    // Get session from app state
+    match state.get_session(&session_id).await {
+        Some(mut session) => {
+            session.set_title(req.title, state.clock.now_unix());
+
+            // Update session in app state
+            if state.update_session(session.clone()).await {
+                let response = TerminalSession {
+                    id: session.id,
+                    user_id: session.user_id,
+                    title: session.title,
+                    status: format!("{:?}", session.status).to_lowercase(),
+                    columns: session.columns,
+                    rows: session.rows,
+                    working_directory: session.working_directory,
+                    shell_type: session.shell_type,
+                    connection_type: format!("{:?}", session.connection_type),
+                    created_at: session.created_at,
+                    capabilities: session.capabilities,
+                    read_only: session.read_only,
+                    terminal_profile: session.terminal_profile,
+                    template: session.template,
+                    labels: session.labels,
+                    input_latency_p95_ms: state.input_latency.p95_ms(&session_id).await,
+                };
+
+                match to_value(response) {
+                    Ok(value) => (StatusCode::OK, Json(value)),
+                    Err(e) => {
+                        error!("Failed to serialize session response: {}", e);
+                        (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            Json(
+                                to_value(ErrorResponse {
+                                    error: true,
+                                    message: "Internal server error".to_string(),
+                                    code: Some(500),
+                                })
+                                .unwrap_or_default(),
+                            ),
+                        )
+                    }
+                }
+            } else {
+                // The session was removed while it was being updated
+                let error_response = ErrorResponse {
+                    error: true,
+                    message: format!("Session not found: {}", session_id),
+                    code: Some(404),
+                };
+
+                (
+                    StatusCode::NOT_FOUND,
+                    Json(to_value(error_response).unwrap_or_default()),
+                )
+            }
+        }
+        None => {
+            // Return error using ErrorResponse struct
+            let error_response = ErrorResponse {
+                error: true,
+                message: format!("Session not found: {}", session_id),
+                code: Some(404),
+            };
+
+            (
+                StatusCode::NOT_FOUND,
+                Json(to_value(error_response).unwrap_or_default()),
+            )
+        }
+    }
+    .into_response()
+}
+
+/// Disconnect the client of a session but keep its shell running, so another client can take
+/// over by attaching to the session
+pub async fn disconnect_session(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Path(session_id): Path<String>,
+) -> axum::response::Response {
+    info!(
+        "Disconnecting the client of terminal session: {}",
+        session_id
+    );
+    if let Some(rejection) = check_session_owner(&state, &auth, &session_id).await {
+        return rejection;
+    }
+
+    if state.get_session(&session_id).await.is_none() {
+        let error_response = ErrorResponse {
+            error: true,
+            message: format!("Session not found: {}", session_id),
+            code: Some(404),
+        };
+        return (StatusCode::NOT_FOUND, Json(error_response)).into_response();
+    }
+    if !state
+        .request_disconnect(&session_id, "disconnected by API request")
+        .await
+    {
+        let error_response = ErrorResponse {
+            error: true,
+            message: format!("Session has no connected client: {}", session_id),
+            code: Some(409),
+        };
+        return (StatusCode::CONFLICT, Json(error_response)).into_response();
+    }
+
+    let response = SuccessResponse {
+        success: true,
+        message: "Client disconnected, the shell keeps running".to_string(),
+    };
+    (StatusCode::OK, Json(response)).into_response()
 }
 
 /// Terminate a terminal session
 pub async fn terminate_session(
     State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
     Path(session_id): Path<String>,
-) -> impl IntoResponse {
+) -> axum::response::Response {
     info!("Terminating terminal session: {}", session_id);
+    if let Some(rejection) = check_session_owner(&state, &auth, &session_id).await {
+        return rejection;
+    }
+
+    // Stop the running session task, if any, which notifies the client and kills the PTY
+    if let Some(handle) = state.take_session_handle(&session_id).await {
+        handle.request_terminate("terminated by API request");
+    }
+    // A disconnected session has no task, only its shell
+    end_detached_session(&state, &session_id).await;
 
     // Remove session from app state
     match state.remove_session(&session_id).await {
@@ -342,15 +1057,259 @@ pub async fn terminate_session(
             }
         }
     }
+    .into_response()
 }
 
 /// Health check endpoint
-pub async fn health_check() -> impl IntoResponse {
+pub async fn health_check(State(state): State<AppState>) -> impl IntoResponse {
     (
         StatusCode::OK,
-        Json(SuccessResponse {
+        Json(HealthResponse {
             success: true,
             message: "Health check passed".to_string(),
+            draining: state.is_draining(),
+            degraded: state.is_webtransport_degraded(),
+            sessions: state.running_session_count().await,
+            version: Some(SERVER_VERSION.to_string()),
+        }),
+    )
+}
+
+/// Readiness check for load balancers, not ready while draining
+pub async fn readiness_check(State(state): State<AppState>) -> impl IntoResponse {
+    let draining = state.is_draining();
+    // HTTP and WebSocket still serve sessions while WebTransport is down
+    let (status, message) = if draining {
+        (StatusCode::SERVICE_UNAVAILABLE, "Server is draining")
+    } else if state.is_webtransport_degraded() {
+        (
+            StatusCode::OK,
+            "Server is ready, WebTransport is unavailable",
+        )
+    } else {
+        (StatusCode::OK, "Server is ready")
+    };
+
+    (
+        status,
+        Json(HealthResponse {
+            success: !draining,
+            message: message.to_string(),
+            draining,
+            degraded: state.is_webtransport_degraded(),
+            sessions: state.running_session_count().await,
+            version: Some(SERVER_VERSION.to_string()),
         }),
     )
 }
+
+/// Start draining: new sessions are refused, running ones continue
+pub async fn start_draining(State(state): State<AppState>) -> impl IntoResponse {
+    info!("Draining: refusing new sessions");
+    set_draining(state, true).await
+}
+
+/// Stop draining and accept new sessions again
+pub async fn stop_draining(State(state): State<AppState>) -> impl IntoResponse {
+    info!("Draining stopped: accepting new sessions");
+    set_draining(state, false).await
+}
+
+async fn set_draining(state: AppState, draining: bool) -> impl IntoResponse {
+    state.set_draining(draining).await;
+    (
+        StatusCode::OK,
+        Json(HealthResponse {
+            success: true,
+            message: if draining {
+                "Draining"
+            } else {
+                "Accepting sessions"
+            }
+            .to_string(),
+            draining,
+            degraded: state.is_webtransport_degraded(),
+            sessions: state.running_session_count().await,
+            version: Some(SERVER_VERSION.to_string()),
+        }),
+    )
+}
+
+/// Seconds clients are asked to wait before retrying while the server drains
+const DRAINING_RETRY_AFTER_SECS: u64 = 30;
+
+/// New sessions are refused because the server is draining
+pub struct Draining;
+
+impl IntoResponse for Draining {
+    fn into_response(self) -> axum::response::Response {
+        let error_response = ErrorResponse {
+            error: true,
+            message: "Server is draining, not accepting new sessions".to_string(),
+            code: Some(503),
+        };
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, DRAINING_RETRY_AFTER_SECS.to_string())],
+            Json(error_response),
+        )
+            .into_response()
+    }
+}
+
+/// Run a command in a short-lived PTY and return its output
+pub async fn exec_command(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthContext>,
+    Json(req): Json<ExecRequest>,
+) -> impl IntoResponse {
+    let state = state.with_current_config();
+    if state.is_draining() {
+        return Draining.into_response();
+    }
+
+    let shell_type = req
+        .shell_type
+        .unwrap_or_else(|| state.config.default_shell_type.clone());
+    let rejection = if req.command.trim().is_empty() {
+        Some("Command must not be empty".to_string())
+    } else if !state.config.shells.contains_key(&shell_type) {
+        Some(format!("Unknown shell type: {}", shell_type))
+    } else {
+        None
+    };
+    if let Some(message) = rejection {
+        return bad_request(message);
+    }
+    if let Some(rejection) = check_shell_scope(&auth, &shell_type) {
+        return rejection;
+    }
+
+    // Only keep the environment variables clients are allowed to set
+    let (environment, dropped) = state.config.filter_client_environment(req.environment);
+    if !dropped.is_empty() {
+        warn!(
+            "Dropping environment variables not in client_environment_allowlist: {}",
+            dropped.join(", ")
+        );
+    }
+
+    let timeout = req
+        .timeout_secs
+        .map(Duration::from_secs)
+        .unwrap_or_else(|| Duration::from_millis(state.config.default_exec_timeout_ms));
+
+    info!("Running exec command with shell {}", shell_type);
+    let exec = ExecCommand {
+        shell_type,
+        command: req.command,
+        timeout,
+        environment,
+    };
+    match run_command(&state, exec).await {
+        Ok(outcome) => {
+            let response = ExecResponse {
+                output: String::from_utf8_lossy(&outcome.output).into_owned(),
+                exit_code: outcome.exit_code,
+                signal: outcome.signal,
+                duration_ms: outcome.duration.as_millis() as u64,
+                truncated: outcome.truncated,
+                timed_out: outcome.timed_out,
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => {
+            error!("Exec command failed: {}", e);
+            let error_response = ErrorResponse {
+                error: true,
+                message: format!("Failed to run command: {}", e),
+                code: Some(500),
+            };
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)).into_response()
+        }
+    }
+}
+
+/// Terminate all sessions, waiting up to `shutdown_timeout_ms` for them to finish
+pub async fn terminate_all_sessions(State(state): State<AppState>) -> impl IntoResponse {
+    let state = state.with_current_config();
+    info!("Terminating all terminal sessions");
+
+    let timeout = Duration::from_millis(state.config.shutdown_timeout_ms);
+    let report = shutdown_all(&state, "terminated by administrator", timeout).await;
+
+    (
+        StatusCode::OK,
+        Json(BulkTerminateResponse {
+            terminated: report.terminated,
+            killed: report.killed,
+            timed_out: report.timed_out,
+        }),
+    )
+}
+
+/// Show a notice to every connected session, without waiting for slow clients
+pub async fn broadcast_notice(
+    State(state): State<AppState>,
+    Json(req): Json<BroadcastRequest>,
+) -> axum::response::Response {
+    if req.message.trim().is_empty() {
+        return bad_request("Broadcast message must not be empty".to_string());
+    }
+    info!(
+        "Broadcasting {} notice to all sessions: {}",
+        req.level.as_str(),
+        req.message
+    );
+
+    let (delivered, failed) = state.notify_all_sessions(req.level, &req.message).await;
+    if failed > 0 {
+        warn!("Broadcast could not be queued for {} sessions", failed);
+    }
+    (
+        StatusCode::OK,
+        Json(BroadcastResponse { delivered, failed }),
+    )
+        .into_response()
+}
+
+/// Get the configuration in effect, with environment values and secrets redacted
+pub async fn get_config(State(state): State<AppState>) -> axum::response::Response {
+    let state = state.with_current_config();
+    info!("Getting server configuration");
+
+    match redacted_config(&state.config) {
+        Ok(config) => (StatusCode::OK, Json(config)).into_response(),
+        Err(e) => {
+            error!("Failed to serialize configuration: {}", e);
+            let error_response = ErrorResponse {
+                error: true,
+                message: "Internal server error".to_string(),
+                code: Some(500),
+            };
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)).into_response()
+        }
+    }
+}
+
+/// Metrics in the Prometheus text format
+pub async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        format!(
+            "{}{}",
+            state.input_latency.render_prometheus().await,
+            state.pty_spawn_stats.render_prometheus()
+        ),
+    )
+}
+
+/// PTY spawn count, error rate and spawn time per factory and shell type since the server started
+pub async fn get_pty_stats(State(state): State<AppState>) -> impl IntoResponse {
+    info!("Getting PTY spawn statistics");
+    let response = PtyStatsResponse {
+        spawns: state.pty_spawn_stats.summary(),
+    };
+    (StatusCode::OK, Json(response))
+}
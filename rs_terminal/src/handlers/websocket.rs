@@ -1,44 +1,239 @@
 use axum::{
+    Extension, Json,
     extract::Path,
+    extract::Query,
     extract::State,
-    extract::ws::{WebSocket, WebSocketUpgrade},
-    response::IntoResponse,
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
 };
+use serde::Deserialize;
+use std::time::Duration;
+use tracing::{info, warn};
 
-use crate::{app_state::AppState, protocol::WebSocketConnection, service::handle_terminal_session};
+use crate::{
+    api::dto::{ErrorResponse, TerminalProfile},
+    app_state::AppState,
+    auth::AuthContext,
+    handlers::{
+        auth::{attach_shell_type, check_session_owner, check_shell_scope},
+        rest::Draining,
+        websocket_upgrade::Upgrade,
+    },
+    protocol::{ServerWebSocket, Subprotocol, WebSocketConnection},
+    service::handle_terminal_session,
+};
 use uuid::Uuid;
 
+/// Query parameters of the WebSocket endpoints
+#[derive(Debug, Default, Deserialize)]
+pub struct WebSocketParams {
+    /// Environment profile for the shell of this connection
+    pub environment_profile: Option<String>,
+    /// Terminal profile of the client (`full`, `no-color` or `dumb`), replacing the session's
+    pub terminal_profile: Option<TerminalProfile>,
+    /// Replay scrollback output starting at this sequence number before streaming live output
+    pub resume_from: Option<u64>,
+    /// Locale of the client for its shell, `NAME=value` pairs separated by commas
+    /// (`LANG=de_DE.UTF-8,LC_TIME=C`), applied with `forward_client_locale`
+    pub locale: Option<String>,
+}
+
 pub async fn websocket_handler(
-    ws: WebSocketUpgrade,
+    ws: Upgrade,
+    headers: HeaderMap,
+    Query(params): Query<WebSocketParams>,
     State(state): State<AppState>,
-) -> impl IntoResponse {
+    Extension(auth): Extension<AuthContext>,
+) -> Response {
+    // Use the latest configuration (it may have been reloaded since startup)
+    let state = state.with_current_config();
+    if state.is_draining() {
+        return Draining.into_response();
+    }
+    if let Some(rejection) = check_shell_scope(&auth, &attach_shell_type(&state, None).await) {
+        return rejection;
+    }
+    if let Some(rejection) = check_environment_profile(&params, &state) {
+        return rejection;
+    }
+    if let Some(rejection) = check_client_locale(&params, &state) {
+        return rejection;
+    }
+    let ws = match negotiate_subprotocol(ws, &headers, &state) {
+        Ok(ws) => ws,
+        Err(e) => return e.into_response(),
+    };
+    let ws = limit_message_size(ws, &state);
     let state_clone = state.clone();
-    ws.on_upgrade(|socket| handle_socket(socket, state_clone))
+    ws.on_upgrade(|socket| handle_socket(socket, params, auth, state_clone))
 }
 
 pub async fn websocket_handler_with_id(
-    ws: WebSocketUpgrade,
+    ws: Upgrade,
+    headers: HeaderMap,
     Path(session_id): Path<String>,
+    Query(params): Query<WebSocketParams>,
     State(state): State<AppState>,
-) -> impl IntoResponse {
+    Extension(auth): Extension<AuthContext>,
+) -> Response {
+    let state = state.with_current_config();
+    if state.is_draining() {
+        return Draining.into_response();
+    }
+    if let Some(rejection) = check_session_owner(&state, &auth, &session_id).await {
+        return rejection;
+    }
+    let shell_type = attach_shell_type(&state, Some(&session_id)).await;
+    if let Some(rejection) = check_shell_scope(&auth, &shell_type) {
+        return rejection;
+    }
+    if let Some(rejection) = check_environment_profile(&params, &state) {
+        return rejection;
+    }
+    if let Some(rejection) = check_client_locale(&params, &state) {
+        return rejection;
+    }
+    let ws = match negotiate_subprotocol(ws, &headers, &state) {
+        Ok(ws) => ws,
+        Err(e) => return e.into_response(),
+    };
+    let ws = limit_message_size(ws, &state);
     let state_clone = state.clone();
-    ws.on_upgrade(|socket| handle_socket_with_id(socket, session_id, state_clone))
+    ws.on_upgrade(|socket| handle_socket_with_id(socket, session_id, params, auth, state_clone))
+}
+
+/// Refuse incoming messages above `max_message_bytes` before they are buffered, and compress
+/// messages when `websocket_permessage_deflate` is set
+fn limit_message_size(ws: Upgrade, state: &AppState) -> Upgrade {
+    ws.max_message_size(state.config.max_message_bytes)
+        .permessage_deflate(state.config.websocket_permessage_deflate)
+}
+
+/// Reject the upgrade when the requested environment profile doesn't exist
+fn check_environment_profile(params: &WebSocketParams, state: &AppState) -> Option<Response> {
+    let profile = params.environment_profile.as_deref()?;
+    if state.config.environment_profile(profile).is_some() {
+        return None;
+    }
+
+    warn!(
+        "Rejecting WebSocket client requesting unknown environment profile: {}",
+        profile
+    );
+    let error_response = ErrorResponse {
+        error: true,
+        message: format!("Unknown environment profile: {}", profile),
+        code: Some(400),
+    };
+    Some((StatusCode::BAD_REQUEST, Json(error_response)).into_response())
 }
 
-pub async fn handle_socket(socket: WebSocket, state: AppState) {
+/// Reject the upgrade when the client forwards a locale that can't be applied
+fn check_client_locale(params: &WebSocketParams, state: &AppState) -> Option<Response> {
+    let message = state.config.client_locale(params.locale.as_deref()).err()?;
+    warn!(
+        "Rejecting WebSocket client forwarding its locale: {}",
+        message
+    );
+    let error_response = ErrorResponse {
+        error: true,
+        message,
+        code: Some(400),
+    };
+    Some((StatusCode::BAD_REQUEST, Json(error_response)).into_response())
+}
+
+/// Select a subprotocol from the client's `Sec-WebSocket-Protocol` offer
+/// Clients offering none use raw mode; clients offering only unknown ones are accepted in raw mode
+/// or rejected with 426, depending on `reject_unknown_subprotocols`
+/// An accepted unknown offer is echoed back because clients fail the handshake without a selection
+fn negotiate_subprotocol(
+    ws: Upgrade,
+    headers: &HeaderMap,
+    state: &AppState,
+) -> Result<Upgrade, UnsupportedSubprotocol> {
+    let offered: Vec<&str> = headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+
+    let supported = Subprotocol::ALL.map(Subprotocol::name);
+    match offered.first() {
+        Some(first) if !offered.iter().any(|name| supported.contains(name)) => {
+            if state.config.reject_unknown_subprotocols {
+                warn!(
+                    "Rejecting WebSocket client offering only unknown subprotocols: {}",
+                    offered.join(", ")
+                );
+                return Err(UnsupportedSubprotocol);
+            }
+            info!(
+                "WebSocket client offered only unknown subprotocols ({}), using raw mode",
+                offered.join(", ")
+            );
+            Ok(ws.protocols([first.to_string()]))
+        }
+        _ => Ok(ws.protocols(supported)),
+    }
+}
+
+pub async fn handle_socket(
+    socket: ServerWebSocket,
+    params: WebSocketParams,
+    auth: AuthContext,
+    state: AppState,
+) {
     // Generate session ID if none is provided using UUID for better uniqueness
     let session_id = Uuid::new_v4().to_string();
 
-    handle_socket_with_id(socket, session_id, state).await;
+    handle_socket_with_id(socket, session_id, params, auth, state).await;
 }
 
-pub async fn handle_socket_with_id(socket: WebSocket, session_id: String, state: AppState) {
+pub async fn handle_socket_with_id(
+    socket: ServerWebSocket,
+    session_id: String,
+    params: WebSocketParams,
+    auth: AuthContext,
+    state: AppState,
+) {
     // Create WebSocket connection that implements TerminalConnection trait
-    let ws_connection = WebSocketConnection {
+    let mut ws_connection = WebSocketConnection::new(
         socket,
-        id: session_id.clone(),
-    };
+        session_id,
+        state.config.max_message_bytes,
+        Duration::from_millis(state.config.connection_send_timeout_ms),
+    );
+    ws_connection.environment_profile = params.environment_profile;
+    ws_connection.terminal_profile = params.terminal_profile;
+    ws_connection.resume_from = params.resume_from;
+    ws_connection.auth_context = Some(auth);
+    ws_connection.locale = state
+        .config
+        .client_locale(params.locale.as_deref())
+        .unwrap_or_default();
 
     // Use the shared session handler to handle this connection
     handle_terminal_session(ws_connection, state).await;
 }
+
+/// Every subprotocol offered by the client is unknown and `reject_unknown_subprotocols` is set
+struct UnsupportedSubprotocol;
+
+impl IntoResponse for UnsupportedSubprotocol {
+    fn into_response(self) -> Response {
+        let supported = Subprotocol::ALL.map(Subprotocol::name).join(", ");
+        (
+            StatusCode::UPGRADE_REQUIRED,
+            [(header::SEC_WEBSOCKET_PROTOCOL, supported.clone())],
+            format!(
+                "Unsupported WebSocket subprotocol, supported: {}",
+                supported
+            ),
+        )
+            .into_response()
+    }
+}
@@ -0,0 +1,177 @@
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    extract::FromRequestParts,
+    extract::ws::{WebSocketUpgrade, rejection::WebSocketUpgradeRejection},
+    http::{HeaderValue, StatusCode, header, request::Parts},
+    response::Response,
+};
+use hyper::upgrade::OnUpgrade;
+use std::borrow::Cow;
+use std::future::Future;
+use tracing::warn;
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::WebSocketConfig;
+
+use crate::protocol::{DeflateParams, DeflateWebSocket, ServerWebSocket};
+
+/// Upgrade request of the WebSocket endpoints
+/// axum upgrades the connection, unless the client offered permessage-deflate and the handler
+/// enabled it with `permessage_deflate`: tungstenite doesn't implement the extension, so the
+/// server then answers the upgrade itself and compresses the frames
+pub struct Upgrade {
+    ws: WebSocketUpgrade,
+    deflate: Option<DeflateUpgrade>,
+}
+
+/// Upgrade of a client that offered permessage-deflate
+struct DeflateUpgrade {
+    /// Handle on the pending upgrade, shared with the one axum took out of the request
+    on_upgrade: OnUpgrade,
+    params: DeflateParams,
+    /// `Sec-WebSocket-Accept` of the response
+    accept: HeaderValue,
+    /// `Sec-WebSocket-Protocol` of the request
+    offered_protocols: Option<HeaderValue>,
+    /// Selected subprotocol
+    protocol: Option<HeaderValue>,
+    max_message_bytes: usize,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Upgrade
+where
+    S: Send + Sync,
+{
+    type Rejection = WebSocketUpgradeRejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // axum takes the pending upgrade out of the request, keep a handle on it
+        let on_upgrade = parts.extensions.get::<OnUpgrade>().cloned();
+        let ws = WebSocketUpgrade::from_request_parts(parts, state).await?;
+
+        // The request is a valid upgrade now, with a key
+        let deflate = match (DeflateParams::negotiate(&parts.headers), on_upgrade) {
+            (Some(params), Some(on_upgrade)) => {
+                let key = parts.headers.get(header::SEC_WEBSOCKET_KEY);
+                let accept = key.map(|key| derive_accept_key(key.as_bytes()));
+                accept
+                    .and_then(|accept| HeaderValue::from_str(&accept).ok())
+                    .map(|accept| DeflateUpgrade {
+                        on_upgrade,
+                        params,
+                        accept,
+                        offered_protocols: parts
+                            .headers
+                            .get(header::SEC_WEBSOCKET_PROTOCOL)
+                            .cloned(),
+                        protocol: None,
+                        max_message_bytes: WebSocketConfig::default()
+                            .max_message_size
+                            .unwrap_or(usize::MAX),
+                    })
+            }
+            _ => None,
+        };
+        Ok(Self { ws, deflate })
+    }
+}
+
+impl Upgrade {
+    /// Select the first of `protocols` the client offered, like `WebSocketUpgrade::protocols`
+    pub fn protocols<I>(mut self, protocols: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Cow<'static, str>>,
+    {
+        let protocols: Vec<Cow<'static, str>> = protocols.into_iter().map(Into::into).collect();
+        if let Some(deflate) = &mut self.deflate {
+            let offered = deflate
+                .offered_protocols
+                .as_ref()
+                .and_then(|offered| offered.to_str().ok())
+                .unwrap_or_default();
+            deflate.protocol = protocols
+                .iter()
+                .find(|protocol| {
+                    offered
+                        .split(',')
+                        .any(|name| name.trim() == protocol.as_ref())
+                })
+                .and_then(|protocol| HeaderValue::from_str(protocol).ok());
+        }
+        self.ws = self.ws.protocols(protocols);
+        self
+    }
+
+    /// Refuse messages and frames above `max` bytes
+    pub fn max_message_size(mut self, max: usize) -> Self {
+        if let Some(deflate) = &mut self.deflate {
+            deflate.max_message_bytes = max;
+        }
+        self.ws = self.ws.max_message_size(max).max_frame_size(max);
+        self
+    }
+
+    /// Compress messages with permessage-deflate when `enabled` and the client offered it
+    pub fn permessage_deflate(mut self, enabled: bool) -> Self {
+        if !enabled {
+            self.deflate = None;
+        }
+        self
+    }
+
+    /// Finish the upgrade, calling `callback` with the socket once the connection switched
+    pub fn on_upgrade<C, Fut>(self, callback: C) -> Response
+    where
+        C: FnOnce(ServerWebSocket) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        match self.deflate {
+            Some(deflate) => deflate.on_upgrade(callback),
+            None => self.ws.on_upgrade(|socket| callback(socket.into())),
+        }
+    }
+}
+
+impl DeflateUpgrade {
+    fn on_upgrade<C, Fut>(self, callback: C) -> Response
+    where
+        C: FnOnce(ServerWebSocket) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let Self {
+            on_upgrade,
+            params,
+            accept,
+            protocol,
+            max_message_bytes,
+            ..
+        } = self;
+        let selected = protocol.clone();
+        tokio::spawn(async move {
+            let upgraded = match on_upgrade.await {
+                Ok(upgraded) => upgraded,
+                Err(e) => {
+                    warn!("WebSocket upgrade failed: {}", e);
+                    return;
+                }
+            };
+            let socket =
+                DeflateWebSocket::from_upgraded(upgraded, params, selected, max_message_bytes)
+                    .await;
+            callback(socket.into()).await;
+        });
+
+        let mut response = Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(header::CONNECTION, "upgrade")
+            .header(header::UPGRADE, "websocket")
+            .header(header::SEC_WEBSOCKET_ACCEPT, accept)
+            .header(header::SEC_WEBSOCKET_EXTENSIONS, params.response());
+        if let Some(protocol) = protocol {
+            response = response.header(header::SEC_WEBSOCKET_PROTOCOL, protocol);
+        }
+        response.body(Body::empty()).unwrap()
+    }
+}
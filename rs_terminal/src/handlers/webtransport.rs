@@ -1,15 +1,61 @@
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
+use axum::http::Request;
 use tokio::sync::broadcast;
-use tracing::{debug, error, info};
+use tracing::{error, info, warn};
+use wtransport::endpoint::SessionRequest;
 
 use crate::app_state::AppState;
+use crate::auth::{AuthContext, AuthError};
+use crate::handlers::auth::attach_shell_type;
 use crate::protocol::WebTransportConnection;
+use crate::server::ServerError;
 use crate::service::handle_terminal_session;
 
+/// Path prefix of the WebTransport terminal endpoint (`/wt` or `/wt/:session_id`)
+const WEBTRANSPORT_PATH: &str = "/wt";
+
+/// Consecutive server-side accept failures after which the server stops, so it gets restarted
+const ACCEPT_BREAKER_FAILURES: u32 = 10;
+
+/// Pause after the first server-side accept failure, doubled on each consecutive one
+const ACCEPT_INITIAL_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Upper bound for the pause between failing accepts
+const ACCEPT_MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Bound WebTransport server endpoint
+pub type WebTransportEndpoint = wtransport::Endpoint<wtransport::endpoint::endpoint_side::Server>;
+
+/// Create the WebTransport endpoint, binding its UDP port
+/// `config_key` names the setting that chose the port, for bind diagnostics
+pub fn bind_webtransport_endpoint(
+    addr: SocketAddr,
+    config_key: &'static str,
+) -> Result<WebTransportEndpoint, ServerError> {
+    info!("Configuring WebTransport server on {}", addr);
+
+    // Generate a self-signed identity for WebTransport (HTTPS required)
+    let identity = wtransport::Identity::self_signed(vec!["localhost"])
+        .map_err(|e| ServerError::WebTransport(format!("Invalid certificate SAN: {}", e)))?;
+    let config = wtransport::ServerConfig::builder()
+        .with_bind_address(addr)
+        .with_identity(identity)
+        .build();
+
+    wtransport::Endpoint::server(config)
+        .map_err(|e| ServerError::bind("WebTransport (UDP)", addr, config_key, e))
+}
+
 /// WebTransport server implementation
-pub async fn start_webtransport_server(addr: SocketAddr, state: AppState) {
+/// Returns an error when the server fails or its task panics, so the caller can restart it
+pub async fn start_webtransport_server(
+    endpoint: WebTransportEndpoint,
+    state: AppState,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let addr = endpoint.local_addr()?;
     info!("Starting WebTransport server on {}", addr);
 
     // Create a shutdown signal channel for graceful shutdown
@@ -22,51 +68,46 @@ pub async fn start_webtransport_server(addr: SocketAddr, state: AppState) {
 
     // Start the WebTransport server in a separate task
     let server_task = tokio::spawn(async move {
-        if let Err(e) = run_webtransport_server(addr, state_clone, shutdown_tx_clone).await {
-            error!("WebTransport server error: {}", e);
-        }
+        run_webtransport_server(endpoint, state_clone, shutdown_tx_clone).await
     });
 
     // Wait for shutdown signal
-    tokio::select! {
+    let result = tokio::select! {
         _ = shutdown_rx.recv() => {
             info!("Received shutdown signal for WebTransport server");
+            Ok(())
         }
         result = server_task => {
             match result {
-                Ok(()) => info!("WebTransport server task completed normally"),
-                Err(e) => error!("WebTransport server task failed: {}", e),
+                Ok(Ok(())) => {
+                    info!("WebTransport server task completed normally");
+                    Ok(())
+                }
+                Ok(Err(e)) => {
+                    error!("WebTransport server error: {}", e);
+                    Err(e)
+                }
+                Err(e) => {
+                    error!("WebTransport server task failed: {}", e);
+                    Err(format!("WebTransport server task failed: {}", e).into())
+                }
             }
         }
-    }
+    };
 
     info!("WebTransport server shutdown complete");
+    result
 }
 
 /// Run the actual WebTransport server
 async fn run_webtransport_server(
-    addr: SocketAddr,
+    endpoint: WebTransportEndpoint,
     state: AppState,
     shutdown_tx: Arc<broadcast::Sender<()>>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    info!("Configuring WebTransport server on {}", addr);
-
-    // Generate server certificate for WebTransport (HTTPS required)
-    let certificate = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
-    let private_key = certificate.serialize_private_key_der();
-    let certificate_der = certificate.serialize_der()?;
-
-    // Configure WebTransport endpoint using the correct API
-    // For wtransport 0.6, we need to use a different certificate configuration approach
-    let identity = wtransport::Identity::self_signed(vec!["localhost"])?;
-    let config = wtransport::ServerConfig::builder()
-        .with_bind_address(addr)
-        .with_identity(identity)
-        .build();
-
-    let endpoint = wtransport::Endpoint::server(config)?;
-
-    info!("WebTransport server listening on {}", addr);
+    info!("WebTransport server listening on {}", endpoint.local_addr()?);
+    state.set_webtransport_degraded(false);
+    let mut breaker = AcceptBreaker::new();
 
     // Handle incoming connections
     loop {
@@ -86,7 +127,56 @@ async fn run_webtransport_server(
             incoming_session = endpoint.accept() => {
                 match incoming_session.await {
                     Ok(session) => {
-                        info!("New WebTransport session accepted");
+                        breaker.succeeded();
+                        info!(
+                            "New WebTransport session request for {}{}",
+                            session.authority(),
+                            session.path()
+                        );
+
+                        // Like REST and WebSocket; WebTransport has no 503 response, 429 asks clients to retry later
+                        if state.is_draining() {
+                            info!("Refusing WebTransport session while draining");
+                            session.too_many_requests().await;
+                            continue;
+                        }
+
+                        // Authenticate before accepting, like REST and /ws
+                        let auth = match authenticate_session_request(&state, &session).await {
+                            Ok(auth) => auth,
+                            Err(e) => {
+                                warn!("Refusing unauthenticated WebTransport session: {}", e);
+                                session.forbidden().await;
+                                continue;
+                            }
+                        };
+
+                        // Route by the requested path before accepting, like /ws/:session_id
+                        let Some(session_id) = session_id_from_path(session.path()) else {
+                            warn!("Refusing WebTransport session for unknown path: {}", session.path());
+                            session.not_found().await;
+                            continue;
+                        };
+                        // Only the owner (or an administrator) attaches to an existing session
+                        if let Some(existing) = state.get_session(&session_id).await
+                            && !auth.can_access(&existing.user_id)
+                        {
+                            warn!(
+                                "Refusing {} WebTransport access to session {} of {}",
+                                auth.user_id, session_id, existing.user_id
+                            );
+                            session.forbidden().await;
+                            continue;
+                        }
+                        let shell_type = attach_shell_type(&state, Some(&session_id)).await;
+                        if !auth.can_use_shell(&shell_type) {
+                            warn!(
+                                "Refusing {} WebTransport session with shell {}, which their credentials don't allow",
+                                auth.user_id, shell_type
+                            );
+                            session.forbidden().await;
+                            continue;
+                        }
 
                         // Accept the session to get the connection
                         match session.accept().await {
@@ -94,9 +184,10 @@ async fn run_webtransport_server(
                                 info!("WebTransport connection established");
 
                                 // Handle the connection in a separate task
-                                let state_clone = state.clone();
+                                // The session keeps the configuration it started with
+                                let state_clone = state.with_current_config();
                                 tokio::spawn(async move {
-                                    if let Err(e) = handle_webtransport_connection(connection, state_clone).await {
+                                    if let Err(e) = handle_webtransport_connection(connection, session_id, auth, state_clone).await {
                                         error!("WebTransport connection error: {}", e);
                                     }
                                 });
@@ -106,8 +197,23 @@ async fn run_webtransport_server(
                             }
                         }
                     }
+                    Err(e) if is_client_accept_error(&e) => {
+                        // One client giving up or misbehaving says nothing about the server
+                        warn!("WebTransport client failed to connect: {}", e);
+                    }
                     Err(e) => {
-                        error!("Error accepting WebTransport session: {}", e);
+                        let Some(delay) = breaker.failed() else {
+                            error!(
+                                "Accepting WebTransport sessions failed {} times in a row, stopping (last error: {})",
+                                ACCEPT_BREAKER_FAILURES, e
+                            );
+                            return Err(format!("accepting sessions keeps failing: {}", e).into());
+                        };
+                        error!(
+                            "Error accepting WebTransport session: {}; accepting again in {:?}",
+                            e, delay
+                        );
+                        tokio::time::sleep(delay).await;
                     }
                 }
             }
@@ -118,21 +224,92 @@ async fn run_webtransport_server(
     Ok(())
 }
 
+/// Authenticate a session request by its path and headers with the configured provider
+async fn authenticate_session_request(
+    state: &AppState,
+    session: &SessionRequest,
+) -> Result<AuthContext, AuthError> {
+    let mut request = Request::builder().uri(session.path());
+    // Pseudo-headers (":path", ":authority", ...) are not valid header names
+    for (name, value) in session
+        .headers()
+        .iter()
+        .filter(|(name, _)| !name.starts_with(':'))
+    {
+        request = request.header(name, value);
+    }
+    let (parts, ()) = request
+        .body(())
+        .map_err(|e| AuthError::InvalidCredentials(format!("malformed request: {}", e)))?
+        .into_parts();
+    state.auth.authenticate(&parts).await
+}
+
+/// Whether an accept failed because of the client (it closed, timed out or broke the protocol)
+/// rather than the server
+fn is_client_accept_error(error: &wtransport::error::ConnectionError) -> bool {
+    use wtransport::error::ConnectionError;
+    matches!(
+        error,
+        ConnectionError::ConnectionClosed(_)
+            | ConnectionError::ApplicationClosed(_)
+            | ConnectionError::TimedOut
+            | ConnectionError::LocalH3Error(_)
+    )
+}
+
+/// Consecutive server-side accept failures: each one pauses the accept loop a little longer,
+/// and too many stop it
+struct AcceptBreaker {
+    failures: u32,
+    backoff: Duration,
+}
+
+impl AcceptBreaker {
+    fn new() -> Self {
+        Self {
+            failures: 0,
+            backoff: ACCEPT_INITIAL_BACKOFF,
+        }
+    }
+
+    /// A session was accepted, the failures are over
+    fn succeeded(&mut self) {
+        self.failures = 0;
+        self.backoff = ACCEPT_INITIAL_BACKOFF;
+    }
+
+    /// Count a failure, returning the pause before accepting again, or `None` to stop
+    fn failed(&mut self) -> Option<Duration> {
+        self.failures += 1;
+        if self.failures >= ACCEPT_BREAKER_FAILURES {
+            return None;
+        }
+        let delay = self.backoff;
+        self.backoff = (self.backoff * 2).min(ACCEPT_MAX_BACKOFF);
+        Some(delay)
+    }
+}
+
 /// Handle individual WebTransport connection
 async fn handle_webtransport_connection(
     connection: wtransport::Connection,
+    session_id: String,
+    auth: AuthContext,
     state: AppState,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let connection_id = uuid::Uuid::new_v4().to_string();
-    info!("Handling WebTransport connection: {}", connection_id);
-
-    // Extract session ID from the connection path
-    let session_id = extract_session_id_from_connection(&connection)?;
-
-    info!("WebTransport session ID: {}", session_id);
+    info!(
+        "Handling WebTransport connection for session: {}",
+        session_id
+    );
 
     // Create WebTransport connection wrapper and set the actual connection
-    let webtransport_conn = WebTransportConnection::new(connection_id.clone());
+    let mut webtransport_conn = WebTransportConnection::new(
+        session_id.clone(),
+        Duration::from_millis(state.config.connection_send_timeout_ms),
+    )
+    .with_max_message_bytes(state.config.max_message_bytes);
+    webtransport_conn.auth_context = Some(auth);
 
     // Set the actual WebTransport connection
     if let Err(e) = webtransport_conn.set_connection(connection).await {
@@ -143,16 +320,20 @@ async fn handle_webtransport_connection(
     // Use the shared session handler to handle this connection
     handle_terminal_session(webtransport_conn, state).await;
 
-    info!("WebTransport connection closed: {}", connection_id);
+    info!("WebTransport connection closed: {}", session_id);
     Ok(())
 }
 
-/// Extract session ID from WebTransport connection
-fn extract_session_id_from_connection(
-    _connection: &wtransport::Connection,
-) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-    // WebTransport connections typically include the path in the URL
-    // For now, we'll generate a session ID based on the connection
-    // In a real implementation, we'd extract this from the connection metadata
-    Ok(uuid::Uuid::new_v4().to_string())
+/// Get the session ID from the requested path
+/// `/wt` starts a new session, `/wt/:session_id` attaches to that session, other paths give None
+fn session_id_from_path(path: &str) -> Option<String> {
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let rest = path.strip_prefix(WEBTRANSPORT_PATH)?;
+    match rest.trim_end_matches('/') {
+        "" => Some(uuid::Uuid::new_v4().to_string()),
+        rest => {
+            let session_id = rest.strip_prefix('/')?;
+            (!session_id.contains('/')).then(|| session_id.to_string())
+        }
+    }
 }
@@ -0,0 +1,12 @@
+/// Waylon Terminal Rust backend as a library
+/// The `rs_terminal` binary serves it over HTTP, WebSocket and WebTransport; applications can
+/// embed it instead and drive sessions in-process (see `examples/headless_session.rs`)
+pub mod api;
+pub mod app_state;
+pub mod auth;
+pub mod config;
+pub mod handlers;
+pub mod protocol;
+pub mod pty;
+pub mod server;
+pub mod service;
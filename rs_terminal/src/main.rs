@@ -1,28 +1,102 @@
 /// Main entry point for Waylon Terminal Rust backend
-// Import modules
-mod api;
-mod app_state;
-mod config;
-mod handlers;
-mod protocol;
-mod pty;
-mod server;
-mod service;
-
-// Use public API from modules
-use app_state::AppState;
-use config::{ConfigLoader, init_logging};
-use server::{build_router, run_server_with_graceful_shutdown, start_webtransport_service};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use clap::{Parser, Subcommand};
+
+// Use public API from the library
+use rs_terminal::app_state::{AppState, DiagnosticsStore};
+use rs_terminal::config::{self, ConfigLoader, LogFormat, init_logging, migrate_config};
+use rs_terminal::server::{
+    build_router, run_server_with_graceful_shutdown, spawn_config_reloader,
+    start_webtransport_service,
+};
+use rs_terminal::service;
+
+/// Waylon Terminal Rust backend
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Configuration file path (defaults to ./config.toml, use "-" to read TOML from stdin)
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Format of the log lines ("json" includes the session ID of session activity)
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Convert a configuration file written for an older layout and validate the result
+    MigrateConfig {
+        /// Configuration file to convert
+        input: PathBuf,
+        /// Write the converted configuration to this file instead of stdout
+        #[arg(long)]
+        write: Option<PathBuf>,
+    },
+}
+
+/// Run `migrate-config`: report each change on stderr, exit with 1 if the result is invalid
+fn run_migrate_config(input: &Path, write: Option<&Path>) {
+    let migration = match std::fs::read_to_string(input)
+        .map_err(config::ConfigError::from)
+        .and_then(|content| migrate_config(&content))
+    {
+        Ok(migration) => migration,
+        Err(e) => {
+            eprintln!("Failed to convert {}: {}", input.display(), e);
+            std::process::exit(1);
+        }
+    };
+
+    if migration.changes.is_empty() {
+        eprintln!("{} already uses the current layout", input.display());
+    }
+    for change in &migration.changes {
+        eprintln!("{}", change);
+    }
+    // Settings the old layout didn't have may still be missing
+    if let Err(e) = ConfigLoader::new().parse_config(&migration.config) {
+        eprintln!("The converted configuration is not valid yet: {}", e);
+        std::process::exit(1);
+    }
+    match write {
+        Some(output) => {
+            if let Err(e) = std::fs::write(output, &migration.config) {
+                eprintln!("Failed to write {}: {}", output.display(), e);
+                std::process::exit(1);
+            }
+            eprintln!("Converted configuration written to {}", output.display());
+        }
+        None => print!("{}", migration.config),
+    }
+}
 
 #[tokio::main]
 async fn main() {
+    // Parse command line arguments
+    let cli = Cli::parse();
+    if let Some(Command::MigrateConfig { input, write }) = &cli.command {
+        run_migrate_config(input, write.as_deref());
+        return;
+    }
+
     // Initialize logging
-    init_logging();
+    let diagnostics = Arc::new(DiagnosticsStore::new());
+    init_logging(diagnostics.clone(), cli.log_format);
 
-    // Load configuration
+    // Load configuration ("-" reads the TOML from stdin instead of a file)
     let config_loader = ConfigLoader::new();
-    let config = match config_loader.load_config(None) {
-        // Use None for default path
+    let config_result = match cli.config.as_deref() {
+        Some(path) if path.as_os_str() == "-" => config_loader.load_config_from_stdin(),
+        path => config_loader.load_config(path),
+    };
+    let config = match config_result {
         Ok(config) => config,
         Err(e) => {
             eprintln!("Failed to load configuration: {}", e);
@@ -31,14 +105,26 @@ async fn main() {
     };
 
     // Create application state with configuration
-    let app_state = AppState::new(config.clone());
+    let app_state = AppState::new(config.clone(), diagnostics);
+
+    // Start WebTransport service (only fatal when configured as required)
+    if let Err(e) = start_webtransport_service(app_state.clone()) {
+        if config.webtransport_required {
+            eprintln!("Failed to start WebTransport service: {}", e);
+            std::process::exit(1);
+        }
+        tracing::warn!("WebTransport service unavailable, continuing without it: {}", e);
+    }
+
+    // Remove scratch directories left behind by sessions of earlier runs
+    service::spawn_tmpdir_sweeper(app_state.clone());
 
-    // Start WebTransport service
-    start_webtransport_service(app_state.clone());
+    // Reload the configuration on SIGHUP for sessions started afterwards
+    spawn_config_reloader(app_state.clone(), cli.config.clone());
 
     // Build router and run server with graceful shutdown
-    let app = build_router(app_state);
-    if let Err(e) = run_server_with_graceful_shutdown(app, &config).await {
+    let app = build_router(app_state.clone());
+    if let Err(e) = run_server_with_graceful_shutdown(app, app_state).await {
         eprintln!("Failed to run server: {}", e);
         std::process::exit(1);
     }
@@ -0,0 +1,178 @@
+/// In-process connection implementation for TerminalConnection trait
+/// Lets an application embedding the server drive a session without a network transport
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use crate::auth::AuthContext;
+use crate::protocol::{
+    ClientEnvelope, ConnectionError, ConnectionResult, ConnectionType, ServerEnvelope, Subprotocol,
+    TerminalConnection, TerminalMessage,
+};
+
+/// Messages queued in each direction before the sender waits
+const CHANNEL_MESSAGES: usize = 64;
+
+/// Session side of an in-process connection, passed to `handle_terminal_session`
+/// Speaks the `waylon-terminal-v1` subprotocol, so output arrives as numbered envelopes
+#[derive(Debug)]
+pub struct ChannelConnection {
+    id: String,
+    incoming: mpsc::Receiver<TerminalMessage>,
+    outgoing: mpsc::Sender<TerminalMessage>,
+    closed: bool,
+    auth_context: Option<AuthContext>,
+    /// Largest message sent to the client, longer output is split over several envelopes
+    max_message_bytes: Option<usize>,
+}
+
+/// Application side of an in-process connection
+#[derive(Debug)]
+pub struct ChannelClient {
+    to_session: mpsc::Sender<TerminalMessage>,
+    from_session: mpsc::Receiver<TerminalMessage>,
+}
+
+/// Create a connection for the session `session_id` and the client end driving it
+pub fn channel_connection(session_id: impl Into<String>) -> (ChannelConnection, ChannelClient) {
+    let (to_session, incoming) = mpsc::channel(CHANNEL_MESSAGES);
+    let (outgoing, from_session) = mpsc::channel(CHANNEL_MESSAGES);
+    (
+        ChannelConnection {
+            id: session_id.into(),
+            incoming,
+            outgoing,
+            closed: false,
+            auth_context: None,
+            max_message_bytes: None,
+        },
+        ChannelClient {
+            to_session,
+            from_session,
+        },
+    )
+}
+
+impl ChannelConnection {
+    /// Run the session as an authenticated user: sessions it creates belong to them and only
+    /// their credentials unlock it (without one, the session belongs to the anonymous user)
+    pub fn with_auth_context(mut self, auth_context: AuthContext) -> Self {
+        self.auth_context = Some(auth_context);
+        self
+    }
+
+    /// Keep messages to the client within `limit` bytes, like a WebSocket connection does with
+    /// `max_message_bytes` (unlimited by default)
+    pub fn with_max_message_bytes(mut self, limit: usize) -> Self {
+        self.max_message_bytes = Some(limit);
+        self
+    }
+}
+
+impl ChannelClient {
+    /// Send a message to the session (input, resize, ...)
+    pub async fn send(&self, envelope: &ClientEnvelope) -> ConnectionResult<()> {
+        let text = serde_json::to_string(envelope)
+            .map_err(|e| ConnectionError::Serialization(e.to_string()))?;
+        self.to_session
+            .send(TerminalMessage::Text(text))
+            .await
+            .map_err(|_| ConnectionError::ConnectionClosed)
+    }
+
+    /// Write input to the terminal
+    pub async fn input(&self, data: &str) -> ConnectionResult<()> {
+        self.send(&ClientEnvelope::Input {
+            data: data.to_string(),
+            id: None,
+        })
+        .await
+    }
+
+    /// Resize the terminal
+    pub async fn resize(&self, columns: u16, rows: u16) -> ConnectionResult<()> {
+        self.send(&ClientEnvelope::Resize { columns, rows }).await
+    }
+
+    /// Receive the next message of the session
+    /// Returns None once the session closed the connection
+    pub async fn receive(&mut self) -> Option<ConnectionResult<ServerEnvelope>> {
+        loop {
+            match self.from_session.recv().await? {
+                TerminalMessage::Text(text) => {
+                    return Some(
+                        serde_json::from_str(&text)
+                            .map_err(|e| ConnectionError::Deserialization(e.to_string())),
+                    );
+                }
+                TerminalMessage::Close => return None,
+                // Keepalives, nothing to report
+                _ => continue,
+            }
+        }
+    }
+
+    /// Close the connection, which ends the session
+    pub async fn close(self) {
+        // Dropping the client also closes the connection once the session notices
+        let _ = self.to_session.send(TerminalMessage::Close).await;
+    }
+}
+
+#[async_trait::async_trait]
+impl TerminalConnection for ChannelConnection {
+    async fn send_text(&mut self, message: &str) -> ConnectionResult<()> {
+        self.outgoing
+            .send(TerminalMessage::Text(message.to_string()))
+            .await
+            .map_err(|_| ConnectionError::ConnectionClosed)
+    }
+
+    async fn send_binary(&mut self, data: &[u8]) -> ConnectionResult<()> {
+        self.outgoing
+            .send(TerminalMessage::Binary(data.to_vec()))
+            .await
+            .map_err(|_| ConnectionError::ConnectionClosed)
+    }
+
+    async fn receive(&mut self) -> Option<ConnectionResult<TerminalMessage>> {
+        let message = self.incoming.recv().await;
+        if message.is_none() {
+            debug!("Channel connection closed by the client");
+        }
+        message.map(Ok)
+    }
+
+    async fn close(&mut self) -> ConnectionResult<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        // The client may already be gone
+        let _ = self.outgoing.send(TerminalMessage::Close).await;
+        Ok(())
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn connection_type(&self) -> ConnectionType {
+        ConnectionType::Embedded
+    }
+
+    fn is_alive(&self) -> bool {
+        !self.closed && !self.outgoing.is_closed()
+    }
+
+    fn subprotocol(&self) -> Subprotocol {
+        Subprotocol::V1
+    }
+
+    fn auth_context(&self) -> Option<&AuthContext> {
+        self.auth_context.as_ref()
+    }
+
+    fn max_message_bytes(&self) -> Option<usize> {
+        self.max_message_bytes
+    }
+}
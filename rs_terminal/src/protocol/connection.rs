@@ -1,8 +1,12 @@
 /// Terminal connection trait for abstracting different transport protocols
 use std::fmt::Debug;
 
+use terminal_types::dto::TerminalProfile;
+use terminal_types::protocol::{Subprotocol, TerminalMessage};
 use thiserror::Error;
 
+use crate::auth::AuthContext;
+
 /// 连接错误类型
 #[derive(Error, Debug)]
 pub enum ConnectionError {
@@ -22,6 +26,10 @@ pub enum ConnectionError {
     #[error("Connection closed")]
     ConnectionClosed,
 
+    /// A single stream failed (reset or stopped by the peer), the connection itself is still usable
+    #[error("Stream reset: {0}")]
+    StreamReset(String),
+
     /// 消息序列化错误
     #[error("Message serialization error: {0}")]
     Serialization(String),
@@ -34,11 +42,27 @@ pub enum ConnectionError {
     #[error("Operation timeout")]
     Timeout,
 
+    /// 消息超过大小限制
+    #[error("Message exceeds the size limit of {0} bytes")]
+    MessageTooLarge(usize),
+
     /// 其他错误
     #[error("Connection error: {0}")]
     Other(String),
 }
 
+impl ConnectionError {
+    /// Whether the connection is still usable after this error
+    /// Timeouts, malformed messages and reset streams only affect a single receive,
+    /// everything else ends the session
+    pub fn is_recoverable(&self) -> bool {
+        matches!(
+            self,
+            Self::Timeout | Self::Deserialization(_) | Self::StreamReset(_)
+        )
+    }
+}
+
 /// 连接结果类型
 pub type ConnectionResult<T> = Result<T, ConnectionError>;
 
@@ -52,12 +76,19 @@ pub trait TerminalConnection: Send + Debug {
     async fn send_binary(&mut self, data: &[u8]) -> ConnectionResult<()>;
 
     /// Receive a message from the connection
-    /// Returns None when the connection is closed
+    /// Returns None only once the connection is definitively closed; while no message is
+    /// available (an idle client, a stream being replaced) the call keeps waiting
     async fn receive(&mut self) -> Option<ConnectionResult<TerminalMessage>>;
 
     /// Close the connection
     async fn close(&mut self) -> ConnectionResult<()>;
 
+    /// Keep an idle connection open with a protocol-level message the client doesn't show
+    /// (transports that keep themselves alive do nothing)
+    async fn send_keepalive(&mut self) -> ConnectionResult<()> {
+        Ok(())
+    }
+
     /// Get the connection ID
     fn id(&self) -> &str;
 
@@ -66,21 +97,42 @@ pub trait TerminalConnection: Send + Debug {
 
     /// Check if the connection is still alive
     fn is_alive(&self) -> bool;
-}
 
-/// Terminal message types
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub enum TerminalMessage {
-    /// Text message
-    Text(String),
-    /// Binary message
-    Binary(Vec<u8>),
-    /// Ping message
-    Ping(Vec<u8>),
-    /// Pong message
-    Pong(()),
-    /// Close message
-    Close,
+    /// Get the negotiated subprotocol (connections without negotiation use the raw protocol)
+    fn subprotocol(&self) -> Subprotocol {
+        Subprotocol::Raw
+    }
+
+    /// Get the environment profile requested by the client for this connection
+    fn environment_profile(&self) -> Option<&str> {
+        None
+    }
+
+    /// Get the terminal profile requested by the client for this connection
+    fn terminal_profile(&self) -> Option<TerminalProfile> {
+        None
+    }
+
+    /// Get the locale variables the client forwarded for its shell
+    fn locale(&self) -> &[(String, String)] {
+        &[]
+    }
+
+    /// Get the first output sequence number the client wants replayed from scrollback
+    fn resume_from(&self) -> Option<u64> {
+        None
+    }
+
+    /// Get the largest message the client accepts in one frame (`None` if unlimited)
+    fn max_message_bytes(&self) -> Option<usize> {
+        None
+    }
+
+    /// Get who the client was authenticated as (`None` for connections that weren't
+    /// authenticated, like embedded ones)
+    fn auth_context(&self) -> Option<&AuthContext> {
+        None
+    }
 }
 
 /// Connection types
@@ -90,4 +142,6 @@ pub enum ConnectionType {
     WebSocket,
     /// WebTransport connection
     WebTransport,
+    /// In-process connection of an application embedding the server
+    Embedded,
 }
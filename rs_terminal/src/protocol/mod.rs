@@ -1,10 +1,15 @@
 /// Protocol abstraction for Waylon Terminal Rust backend
+mod channel_connection;
 mod connection;
 mod websocket_connection;
+mod websocket_deflate;
 mod webtransport_connection;
 
-pub use connection::{
-    ConnectionError, ConnectionResult, ConnectionType, TerminalConnection, TerminalMessage,
+pub use channel_connection::{ChannelClient, ChannelConnection, channel_connection};
+pub use connection::{ConnectionError, ConnectionResult, ConnectionType, TerminalConnection};
+pub use terminal_types::protocol::{
+    ClientEnvelope, ErrorCode, NoticeLevel, ServerEnvelope, Subprotocol, TerminalMessage,
 };
-pub use websocket_connection::WebSocketConnection;
+pub use websocket_connection::{ServerWebSocket, WebSocketConnection};
+pub use websocket_deflate::{DeflateParams, DeflateStream, DeflateWebSocket};
 pub use webtransport_connection::WebTransportConnection;
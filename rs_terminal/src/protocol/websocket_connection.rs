@@ -1,19 +1,276 @@
 /// WebSocket connection implementation for TerminalConnection trait
+use std::borrow::Cow;
 use std::fmt::Debug;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{Mutex, mpsc};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info};
 
-use axum::extract::ws::Message::{Binary, Close, Ping, Pong, Text};
-use axum::extract::ws::WebSocket;
-use futures_util::StreamExt;
+use axum::extract::ws::Message::{self, Binary, Close, Ping, Pong, Text};
+use axum::extract::ws::{CloseFrame, WebSocket, close_code};
+use axum::http::HeaderValue;
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
 
+use crate::api::dto::TerminalProfile;
+use crate::auth::AuthContext;
 use crate::protocol::{
-    ConnectionError, ConnectionResult, ConnectionType, TerminalConnection, TerminalMessage,
+    ConnectionError, ConnectionResult, ConnectionType, DeflateWebSocket, Subprotocol,
+    TerminalConnection, TerminalMessage,
 };
 
+/// Messages received ahead of the session loop, while it is busy sending
+const RECEIVE_QUEUE_MESSAGES: usize = 32;
+
+/// Upgraded WebSocket of a client, compressed when it negotiated permessage-deflate
+/// The sockets differ a lot in size, both are boxed
+pub enum ServerWebSocket {
+    Plain(Box<WebSocket>),
+    Deflate(Box<DeflateWebSocket>),
+}
+
+impl ServerWebSocket {
+    /// Subprotocol selected during the upgrade
+    pub fn protocol(&self) -> Option<&HeaderValue> {
+        match self {
+            Self::Plain(socket) => socket.protocol(),
+            Self::Deflate(socket) => socket.protocol(),
+        }
+    }
+}
+
+impl From<WebSocket> for ServerWebSocket {
+    fn from(socket: WebSocket) -> Self {
+        Self::Plain(Box::new(socket))
+    }
+}
+
+impl From<DeflateWebSocket> for ServerWebSocket {
+    fn from(socket: DeflateWebSocket) -> Self {
+        Self::Deflate(Box::new(socket))
+    }
+}
+
+impl Stream for ServerWebSocket {
+    type Item = Result<Message, axum::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.get_mut() {
+            Self::Plain(socket) => socket.poll_next_unpin(cx),
+            Self::Deflate(socket) => socket.poll_next_unpin(cx),
+        }
+    }
+}
+
+impl Sink<Message> for ServerWebSocket {
+    type Error = axum::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.get_mut() {
+            Self::Plain(socket) => socket.poll_ready_unpin(cx),
+            Self::Deflate(socket) => socket.poll_ready_unpin(cx),
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, message: Message) -> Result<(), Self::Error> {
+        match self.get_mut() {
+            Self::Plain(socket) => socket.start_send_unpin(message),
+            Self::Deflate(socket) => socket.start_send_unpin(message),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.get_mut() {
+            Self::Plain(socket) => socket.poll_flush_unpin(cx),
+            Self::Deflate(socket) => socket.poll_flush_unpin(cx),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.get_mut() {
+            Self::Plain(socket) => socket.poll_close_unpin(cx),
+            Self::Deflate(socket) => socket.poll_close_unpin(cx),
+        }
+    }
+}
+
+/// Sending half of a WebSocket that can be cloned into other tasks (pings, broadcasts)
+/// Every frame is sent while holding the sink lock, so concurrent senders never interleave
+#[derive(Clone)]
+pub struct WebSocketSender {
+    sink: Arc<Mutex<SplitSink<ServerWebSocket, Message>>>,
+    /// How long a send may wait for a client that doesn't read
+    timeout: Duration,
+    /// Cancelled once the client closed the connection, ending sends it will never read
+    client_closed: CancellationToken,
+}
+
+impl WebSocketSender {
+    /// Send a single frame
+    pub async fn send(&self, message: Message) -> ConnectionResult<()> {
+        self.send_all(vec![message]).await
+    }
+
+    /// Send several frames back to back, without frames of other senders in between
+    pub async fn send_all(&self, messages: Vec<Message>) -> ConnectionResult<()> {
+        let send = async {
+            let mut sink = self.sink.lock().await;
+            for message in messages {
+                sink.send(message)
+                    .await
+                    .map_err(|e| ConnectionError::WebSocket(e.to_string()))?;
+            }
+            Ok(())
+        };
+        tokio::select! {
+            biased;
+            result = tokio::time::timeout(self.timeout, send) => {
+                result.map_err(|_| ConnectionError::Timeout)?
+            }
+            _ = self.client_closed.cancelled() => Err(ConnectionError::ConnectionClosed),
+        }
+    }
+}
+
+/// Read the client's messages into `queue` as they arrive, independent of the session loop,
+/// so a close frame ends a send that is stuck on a client flooded with output
+async fn pump_received_messages(
+    mut stream: SplitStream<ServerWebSocket>,
+    queue: mpsc::Sender<Result<Message, axum::Error>>,
+    client_closed: CancellationToken,
+) {
+    while let Some(item) = stream.next().await {
+        let close = matches!(item, Ok(Close(_)));
+        if close {
+            client_closed.cancel();
+        }
+        if queue.send(item).await.is_err() || close {
+            return;
+        }
+    }
+    client_closed.cancel();
+}
+
 /// WebSocket connection implementation that implements TerminalConnection trait
 pub struct WebSocketConnection {
-    pub socket: WebSocket,
+    pub sender: WebSocketSender,
+    /// Messages read by the receive pump
+    receiver: mpsc::Receiver<Result<Message, axum::Error>>,
+    /// Task reading the client's messages, it owns the receiving half of the socket
+    receive_pump: JoinHandle<()>,
     pub id: String,
+    pub subprotocol: Subprotocol,
+    pub environment_profile: Option<String>,
+    /// Terminal profile the client asked for when attaching
+    pub terminal_profile: Option<TerminalProfile>,
+    /// Output sequence number to replay scrollback from when attaching
+    pub resume_from: Option<u64>,
+    /// Locale variables forwarded by the client (with `forward_client_locale`)
+    pub locale: Vec<(String, String)>,
+    /// Largest message sent or accepted, in bytes
+    pub max_message_bytes: usize,
+    /// Who the upgrade request was authenticated as
+    pub auth_context: Option<AuthContext>,
+    /// Whether a close frame was already sent
+    closed: bool,
+}
+
+impl WebSocketConnection {
+    /// Create a new WebSocket connection
+    /// The subprotocol selected during the upgrade decides how messages are interpreted
+    /// A send taking longer than `send_timeout` fails with `ConnectionError::Timeout`
+    pub fn new(
+        socket: impl Into<ServerWebSocket>,
+        id: String,
+        max_message_bytes: usize,
+        send_timeout: Duration,
+    ) -> Self {
+        let socket = socket.into();
+        let subprotocol = socket
+            .protocol()
+            .and_then(|p| p.to_str().ok())
+            .and_then(Subprotocol::from_name)
+            .unwrap_or(Subprotocol::Raw);
+        let (sink, stream) = socket.split();
+        let client_closed = CancellationToken::new();
+        let (queue, receiver) = mpsc::channel(RECEIVE_QUEUE_MESSAGES);
+        let receive_pump =
+            tokio::spawn(pump_received_messages(stream, queue, client_closed.clone()));
+        Self {
+            sender: WebSocketSender {
+                sink: Arc::new(Mutex::new(sink)),
+                timeout: send_timeout,
+                client_closed,
+            },
+            receiver,
+            receive_pump,
+            id,
+            subprotocol,
+            environment_profile: None,
+            terminal_profile: None,
+            resume_from: None,
+            locale: Vec::new(),
+            max_message_bytes,
+            auth_context: None,
+            closed: false,
+        }
+    }
+
+    /// Tell the client its message was too large and close with code 1009 (message too big)
+    async fn reject_oversized_message(&mut self) {
+        error!(
+            "WebSocket message from session {} exceeds {} bytes, closing connection",
+            self.id, self.max_message_bytes
+        );
+        let error_msg = format!(
+            "Error: Message exceeds the size limit of {} bytes",
+            self.max_message_bytes
+        );
+        let close = Close(Some(CloseFrame {
+            code: close_code::SIZE,
+            reason: Cow::from("Message too big"),
+        }));
+        let _ = self.sender.send_all(vec![Text(error_msg), close]).await;
+        self.closed = true;
+    }
+}
+
+/// Whether a receive error is tungstenite refusing a message above the size limit
+fn is_capacity_error(error: axum::Error) -> bool {
+    matches!(
+        error.into_inner().downcast_ref::<tungstenite::Error>(),
+        Some(tungstenite::Error::Capacity(_))
+    )
+}
+
+/// Split text into pieces of at most `limit` bytes without breaking UTF-8 sequences
+fn split_text(text: &str, limit: usize) -> Vec<&str> {
+    // A piece must be able to hold the longest UTF-8 sequence
+    let limit = limit.max(4);
+    let mut pieces = Vec::with_capacity(text.len() / limit + 1);
+    let mut rest = text;
+    while rest.len() > limit {
+        let mut end = limit;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (piece, tail) = rest.split_at(end);
+        pieces.push(piece);
+        rest = tail;
+    }
+    pieces.push(rest);
+    pieces
+}
+
+impl Drop for WebSocketConnection {
+    fn drop(&mut self) {
+        // The pump holds the receiving half, the socket is only released once it stops
+        self.receive_pump.abort();
+    }
 }
 
 impl Debug for WebSocketConnection {
@@ -26,17 +283,35 @@ impl Debug for WebSocketConnection {
 
 #[async_trait::async_trait]
 impl TerminalConnection for WebSocketConnection {
+    /// Raw terminal output above `max_message_bytes` is split into several frames; a
+    /// `waylon-terminal-v1` envelope can't be split (output envelopes are split before they are
+    /// encoded), a larger one is refused
     async fn send_text(&mut self, message: &str) -> ConnectionResult<()> {
-        self.socket
-            .send(Text(message.to_string()))
-            .await
-            .map_err(|e| ConnectionError::WebSocket(e.to_string()))?;
-        Ok(())
+        if message.len() <= self.max_message_bytes {
+            return self.sender.send(Text(message.to_string())).await;
+        }
+        if self.subprotocol == Subprotocol::V1 {
+            error!(
+                "Envelope of {} bytes for session {} exceeds the message limit, not sent",
+                message.len(),
+                self.id
+            );
+            return Err(ConnectionError::MessageTooLarge(self.max_message_bytes));
+        }
+        let frames = split_text(message, self.max_message_bytes)
+            .into_iter()
+            .map(|piece| Text(piece.to_string()))
+            .collect();
+        self.sender.send_all(frames).await
     }
 
     async fn send_binary(&mut self, data: &[u8]) -> ConnectionResult<()> {
         info!("Sending binary data to client, size: {}", data.len());
-        let result = self.socket.send(Binary(data.to_vec())).await;
+        let frames = data
+            .chunks(self.max_message_bytes.max(1))
+            .map(|chunk| Binary(chunk.to_vec()))
+            .collect();
+        let result = self.sender.send_all(frames).await;
         match result {
             Ok(_) => {
                 info!("Successfully sent binary data to client");
@@ -44,15 +319,19 @@ impl TerminalConnection for WebSocketConnection {
             }
             Err(e) => {
                 error!("Failed to send binary data to client: {}", e);
-                Err(ConnectionError::WebSocket(e.to_string()))
+                Err(e)
             }
         }
     }
 
+    async fn send_keepalive(&mut self) -> ConnectionResult<()> {
+        self.sender.send(Ping(Vec::new())).await
+    }
+
     async fn receive(&mut self) -> Option<ConnectionResult<TerminalMessage>> {
-        match self.socket.next().await {
+        match self.receiver.recv().await {
             Some(Ok(Text(text))) => {
-                debug!("WebSocket received text message: {:?}", text);
+                debug!("WebSocket received text message, length: {}", text.len());
                 Some(Ok(TerminalMessage::Text(text)))
             }
             Some(Ok(Binary(bin))) => {
@@ -72,8 +351,15 @@ impl TerminalConnection for WebSocketConnection {
                 Some(Ok(TerminalMessage::Close))
             }
             Some(Err(e)) => {
-                error!("WebSocket receive error: {}", e);
-                Some(Err(ConnectionError::WebSocket(e.to_string())))
+                let message = e.to_string();
+                if is_capacity_error(e) {
+                    self.reject_oversized_message().await;
+                    return Some(Err(ConnectionError::MessageTooLarge(
+                        self.max_message_bytes,
+                    )));
+                }
+                error!("WebSocket receive error: {}", message);
+                Some(Err(ConnectionError::WebSocket(message)))
             }
             None => {
                 debug!("WebSocket connection closed");
@@ -83,11 +369,11 @@ impl TerminalConnection for WebSocketConnection {
     }
 
     async fn close(&mut self) -> ConnectionResult<()> {
-        self.socket
-            .send(Close(None))
-            .await
-            .map_err(|e| ConnectionError::WebSocket(e.to_string()))?;
-        Ok(())
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        self.sender.send(Close(None)).await
     }
 
     fn is_alive(&self) -> bool {
@@ -103,4 +389,32 @@ impl TerminalConnection for WebSocketConnection {
     fn connection_type(&self) -> ConnectionType {
         ConnectionType::WebSocket
     }
+
+    fn subprotocol(&self) -> Subprotocol {
+        self.subprotocol
+    }
+
+    fn environment_profile(&self) -> Option<&str> {
+        self.environment_profile.as_deref()
+    }
+
+    fn terminal_profile(&self) -> Option<TerminalProfile> {
+        self.terminal_profile
+    }
+
+    fn locale(&self) -> &[(String, String)] {
+        &self.locale
+    }
+
+    fn resume_from(&self) -> Option<u64> {
+        self.resume_from
+    }
+
+    fn max_message_bytes(&self) -> Option<usize> {
+        Some(self.max_message_bytes)
+    }
+
+    fn auth_context(&self) -> Option<&AuthContext> {
+        self.auth_context.as_ref()
+    }
 }
@@ -0,0 +1,592 @@
+/// permessage-deflate (RFC 7692) for the WebSocket endpoints
+/// tungstenite doesn't implement the extension, so `DeflateStream` sits between the upgraded
+/// connection and tungstenite: it inflates the client's compressed messages into plain frames and
+/// compresses the data frames tungstenite sends
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use axum::extract::ws::{CloseFrame, Message};
+use axum::http::{HeaderMap, HeaderValue, header};
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use hyper::upgrade::Upgraded;
+use hyper_util::rt::TokioIo;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::WebSocketStream;
+use tungstenite::protocol::{self, Role, WebSocketConfig};
+
+/// Name of the extension in `Sec-WebSocket-Extensions`
+const EXTENSION: &str = "permessage-deflate";
+
+/// Empty stored block ending every compressed message, left out on the wire
+const MESSAGE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Bytes read from the connection at a time
+const READ_CHUNK_BYTES: usize = 16 * 1024;
+
+/// Compressed frames buffered before writes wait for the connection
+const WRITE_BUFFER_BYTES: usize = 64 * 1024;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+
+/// permessage-deflate parameters agreed with a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeflateParams {
+    /// Compress every message on its own instead of referring back to earlier ones
+    pub server_no_context_takeover: bool,
+}
+
+impl DeflateParams {
+    /// Accept the first permessage-deflate offer in the client's `Sec-WebSocket-Extensions` the
+    /// server can honour
+    pub fn negotiate(headers: &HeaderMap) -> Option<Self> {
+        headers
+            .get_all(header::SEC_WEBSOCKET_EXTENSIONS)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .find_map(Self::from_offer)
+    }
+
+    /// Parameters of a single offer, `None` when it isn't permessage-deflate or asks for
+    /// something the server doesn't support
+    fn from_offer(offer: &str) -> Option<Self> {
+        let mut parts = offer.split(';').map(str::trim);
+        if !parts.next()?.eq_ignore_ascii_case(EXTENSION) {
+            return None;
+        }
+
+        let mut params = Self {
+            server_no_context_takeover: false,
+        };
+        let mut seen = Vec::new();
+        for part in parts {
+            let (name, value) = match part.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (part, None),
+            };
+            // An offer naming a parameter twice is invalid
+            if seen.contains(&name) {
+                return None;
+            }
+            seen.push(name);
+            match (name, value) {
+                ("server_no_context_takeover", None) => params.server_no_context_takeover = true,
+                // Inflating keeps the context either way and uses the largest window, which
+                // also reads messages compressed with a smaller one
+                ("client_no_context_takeover", None) | ("client_max_window_bits", None) => {}
+                ("client_max_window_bits", Some(bits)) if is_window_bits(bits) => {}
+                // The compressor always uses the largest window
+                ("server_max_window_bits", Some("15")) => {}
+                _ => return None,
+            }
+        }
+        Some(params)
+    }
+
+    /// `Sec-WebSocket-Extensions` of the upgrade response
+    pub fn response(&self) -> HeaderValue {
+        if self.server_no_context_takeover {
+            HeaderValue::from_static("permessage-deflate; server_no_context_takeover")
+        } else {
+            HeaderValue::from_static(EXTENSION)
+        }
+    }
+}
+
+fn is_window_bits(bits: &str) -> bool {
+    bits.parse::<u8>()
+        .is_ok_and(|bits| (8..=15).contains(&bits))
+}
+
+/// Header of a WebSocket frame
+#[derive(Debug, Clone, Copy)]
+struct FrameHeader {
+    fin: bool,
+    /// RSV1, set on the first frame of a compressed message
+    compressed: bool,
+    opcode: u8,
+    mask: Option<[u8; 4]>,
+    header_len: usize,
+    payload_len: usize,
+}
+
+impl FrameHeader {
+    /// Parse the header at the start of `buffer`, `None` while it is incomplete
+    fn parse(buffer: &[u8]) -> io::Result<Option<Self>> {
+        if buffer.len() < 2 {
+            return Ok(None);
+        }
+        let (payload_len, mut header_len) = match buffer[1] & 0x7f {
+            126 if buffer.len() < 4 => return Ok(None),
+            126 => (u64::from(u16::from_be_bytes([buffer[2], buffer[3]])), 4),
+            127 if buffer.len() < 10 => return Ok(None),
+            127 => (u64::from_be_bytes(buffer[2..10].try_into().unwrap()), 10),
+            len => (u64::from(len), 2),
+        };
+        let mask = if buffer[1] & 0x80 != 0 {
+            if buffer.len() < header_len + 4 {
+                return Ok(None);
+            }
+            let mask = buffer[header_len..header_len + 4].try_into().unwrap();
+            header_len += 4;
+            Some(mask)
+        } else {
+            None
+        };
+        let payload_len = usize::try_from(payload_len)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "WebSocket frame too large"))?;
+        Ok(Some(Self {
+            fin: buffer[0] & 0x80 != 0,
+            compressed: buffer[0] & 0x40 != 0,
+            opcode: buffer[0] & 0x0f,
+            mask,
+            header_len,
+            payload_len,
+        }))
+    }
+
+    fn is_data(&self) -> bool {
+        matches!(self.opcode, OPCODE_TEXT | OPCODE_BINARY)
+    }
+
+    /// Append a header of a single-frame message to `out`
+    fn write(out: &mut Vec<u8>, compressed: bool, opcode: u8, mask: Option<[u8; 4]>, len: usize) {
+        out.push(0x80 | if compressed { 0x40 } else { 0 } | opcode);
+        let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+        match len {
+            0..=125 => out.push(mask_bit | len as u8),
+            126..=0xffff => {
+                out.push(mask_bit | 126);
+                out.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            _ => {
+                out.push(mask_bit | 127);
+                out.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        if let Some(mask) = mask {
+            out.extend_from_slice(&mask);
+        }
+    }
+}
+
+fn apply_mask(data: &mut [u8], mask: [u8; 4]) {
+    for (index, byte) in data.iter_mut().enumerate() {
+        *byte ^= mask[index % 4];
+    }
+}
+
+fn compression_error(error: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Inflates the client's compressed messages into plain frames for tungstenite
+struct Inflater {
+    decompress: Decompress,
+    /// Largest message in bytes, compressed or inflated
+    limit: usize,
+    /// Bytes read from the client that don't form a complete frame yet
+    input: Vec<u8>,
+    /// Frames waiting to be read by tungstenite
+    output: Vec<u8>,
+    read_pos: usize,
+    /// Compressed message being received: opcode of its first frame and its payload so far
+    message: Option<(u8, Vec<u8>)>,
+    /// An oversized message was handed on, tungstenite fails on it and nothing follows
+    exceeded: bool,
+}
+
+impl Inflater {
+    /// Decode the complete frames of `data` and what was buffered before it
+    fn decode(&mut self, data: &[u8]) -> io::Result<()> {
+        self.input.extend_from_slice(data);
+        let mut consumed = 0;
+        while !self.exceeded {
+            let Some(header) = FrameHeader::parse(&self.input[consumed..])? else {
+                break;
+            };
+            let starts_compressed = header.is_data() && header.compressed;
+            let continues_compressed = header.opcode == OPCODE_CONTINUATION
+                && !header.compressed
+                && self.message.is_some();
+            if header.is_data() && self.message.is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "WebSocket data frame inside a fragmented message",
+                ));
+            }
+
+            // Refuse oversized messages before buffering them
+            let received = match &self.message {
+                Some((_, payload)) if continues_compressed => payload.len(),
+                _ => 0,
+            };
+            // Declared lengths come from the client, up to u64::MAX
+            if received.saturating_add(header.payload_len) > self.limit {
+                self.exceed();
+                break;
+            }
+            let frame_len = header.header_len.saturating_add(header.payload_len);
+            let Some(frame) = self.input.get(consumed..consumed.saturating_add(frame_len)) else {
+                break;
+            };
+
+            if starts_compressed || continues_compressed {
+                let (_, payload) = self
+                    .message
+                    .get_or_insert_with(|| (header.opcode, Vec::new()));
+                let start = payload.len();
+                payload.extend_from_slice(&frame[header.header_len..]);
+                if let Some(mask) = header.mask {
+                    apply_mask(&mut payload[start..], mask);
+                }
+                if header.fin {
+                    let (opcode, payload) = self.message.take().unwrap();
+                    self.inflate(opcode, payload)?;
+                }
+            } else {
+                // Control frames and uncompressed messages pass unchanged
+                self.output.extend_from_slice(frame);
+            }
+            consumed += frame_len;
+        }
+        self.input.drain(..consumed);
+        Ok(())
+    }
+
+    /// Inflate a complete message into a single frame
+    fn inflate(&mut self, opcode: u8, mut payload: Vec<u8>) -> io::Result<()> {
+        payload.extend_from_slice(&MESSAGE_TAIL);
+        let mut inflated =
+            Vec::with_capacity((payload.len() * 4).min(self.limit.saturating_add(1)));
+        let start = self.decompress.total_in();
+        loop {
+            if inflated.len() == inflated.capacity() {
+                inflated.reserve(inflated.len().max(READ_CHUNK_BYTES));
+            }
+            let consumed = (self.decompress.total_in() - start) as usize;
+            let status = self
+                .decompress
+                .decompress_vec(&payload[consumed..], &mut inflated, FlushDecompress::Sync)
+                .map_err(compression_error)?;
+            if inflated.len() > self.limit {
+                self.exceed();
+                return Ok(());
+            }
+            if status == Status::StreamEnd {
+                // The client ended the deflate stream, its next message starts a new one
+                self.decompress.reset(false);
+                break;
+            }
+            if inflated.len() < inflated.capacity() {
+                break;
+            }
+        }
+
+        // Client frames are masked, a zero mask leaves the payload as it is
+        FrameHeader::write(
+            &mut self.output,
+            false,
+            opcode,
+            Some([0; 4]),
+            inflated.len(),
+        );
+        self.output.extend_from_slice(&inflated);
+        Ok(())
+    }
+
+    /// Hand on only the header of a frame above the limit, tungstenite refuses it as too large
+    fn exceed(&mut self) {
+        FrameHeader::write(
+            &mut self.output,
+            false,
+            OPCODE_BINARY,
+            Some([0; 4]),
+            self.limit.saturating_add(1),
+        );
+        self.exceeded = true;
+        self.message = None;
+    }
+
+    fn copy_to(&mut self, buf: &mut ReadBuf<'_>) {
+        let len = buf.remaining().min(self.output.len() - self.read_pos);
+        buf.put_slice(&self.output[self.read_pos..self.read_pos + len]);
+        self.read_pos += len;
+        if self.read_pos == self.output.len() {
+            self.output.clear();
+            self.read_pos = 0;
+        }
+    }
+}
+
+/// Compresses the data frames written by tungstenite
+struct Deflater {
+    compress: Compress,
+    no_context_takeover: bool,
+    /// Bytes written by tungstenite that don't form a complete frame yet
+    input: Vec<u8>,
+    /// Frames waiting to be written to the client
+    output: Vec<u8>,
+    write_pos: usize,
+}
+
+impl Deflater {
+    /// Encode the complete frames of `data` and what was buffered before it
+    fn encode(&mut self, data: &[u8]) -> io::Result<()> {
+        self.input.extend_from_slice(data);
+        let mut consumed = 0;
+        while let Some(header) = FrameHeader::parse(&self.input[consumed..])? {
+            let frame_len = header.header_len + header.payload_len;
+            let Some(frame) = self.input.get(consumed..consumed + frame_len) else {
+                break;
+            };
+            // Fragmented, empty and control frames are sent as they are
+            if header.fin
+                && header.is_data()
+                && !header.compressed
+                && header.mask.is_none()
+                && header.payload_len > 0
+            {
+                let compressed = deflate(&mut self.compress, &frame[header.header_len..])?;
+                if self.no_context_takeover {
+                    self.compress.reset();
+                }
+                FrameHeader::write(
+                    &mut self.output,
+                    true,
+                    header.opcode,
+                    None,
+                    compressed.len(),
+                );
+                self.output.extend_from_slice(&compressed);
+            } else {
+                self.output.extend_from_slice(frame);
+            }
+            consumed += frame_len;
+        }
+        self.input.drain(..consumed);
+        Ok(())
+    }
+}
+
+/// Compress a message, without the tail the extension leaves out
+fn deflate(compress: &mut Compress, data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut compressed = Vec::with_capacity(data.len() / 2 + 64);
+    let start = compress.total_in();
+    loop {
+        if compressed.len() == compressed.capacity() {
+            compressed.reserve(compressed.len().max(64));
+        }
+        let consumed = (compress.total_in() - start) as usize;
+        compress
+            .compress_vec(&data[consumed..], &mut compressed, FlushCompress::Sync)
+            .map_err(compression_error)?;
+        if compressed.len() < compressed.capacity() {
+            break;
+        }
+    }
+    if compressed.ends_with(&MESSAGE_TAIL) {
+        compressed.truncate(compressed.len() - MESSAGE_TAIL.len());
+    }
+    Ok(compressed)
+}
+
+/// Connection with permessage-deflate applied to the frames passing through it
+pub struct DeflateStream<S> {
+    inner: S,
+    inflater: Inflater,
+    deflater: Deflater,
+}
+
+impl<S> DeflateStream<S> {
+    /// Wrap an upgraded server connection, refusing client messages above `max_message_bytes`
+    pub fn new(inner: S, params: DeflateParams, max_message_bytes: usize) -> Self {
+        Self {
+            inner,
+            inflater: Inflater {
+                decompress: Decompress::new(false),
+                limit: max_message_bytes,
+                input: Vec::new(),
+                output: Vec::new(),
+                read_pos: 0,
+                message: None,
+                exceeded: false,
+            },
+            deflater: Deflater {
+                compress: Compress::new(Compression::default(), false),
+                no_context_takeover: params.server_no_context_takeover,
+                input: Vec::new(),
+                output: Vec::new(),
+                write_pos: 0,
+            },
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> DeflateStream<S> {
+    /// Write the compressed frames to the connection
+    fn poll_write_frames(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let deflater = &mut self.deflater;
+        while deflater.write_pos < deflater.output.len() {
+            let written = ready!(
+                Pin::new(&mut self.inner).poll_write(cx, &deflater.output[deflater.write_pos..])
+            )?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            deflater.write_pos += written;
+        }
+        deflater.output.clear();
+        deflater.write_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for DeflateStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.inflater.read_pos < this.inflater.output.len() {
+                this.inflater.copy_to(buf);
+                return Poll::Ready(Ok(()));
+            }
+            if this.inflater.exceeded {
+                return Poll::Ready(Ok(()));
+            }
+            let mut chunk = [0u8; READ_CHUNK_BYTES];
+            let mut chunk = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk))?;
+            if chunk.filled().is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+            this.inflater.decode(chunk.filled())?;
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for DeflateStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.deflater.output.len() >= WRITE_BUFFER_BYTES {
+            ready!(this.poll_write_frames(cx))?;
+        }
+        this.deflater.encode(buf)?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_frames(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_frames(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+/// WebSocket of a client that negotiated permessage-deflate, with the message types of axum's
+/// `WebSocket`
+pub struct DeflateWebSocket {
+    inner: WebSocketStream<DeflateStream<TokioIo<Upgraded>>>,
+    protocol: Option<HeaderValue>,
+}
+
+impl DeflateWebSocket {
+    /// Speak WebSocket with permessage-deflate on an upgraded connection
+    pub async fn from_upgraded(
+        upgraded: Upgraded,
+        params: DeflateParams,
+        protocol: Option<HeaderValue>,
+        max_message_bytes: usize,
+    ) -> Self {
+        let stream = DeflateStream::new(TokioIo::new(upgraded), params, max_message_bytes);
+        let config = WebSocketConfig {
+            max_message_size: Some(max_message_bytes),
+            max_frame_size: Some(max_message_bytes),
+            ..Default::default()
+        };
+        let inner = WebSocketStream::from_raw_socket(stream, Role::Server, Some(config)).await;
+        Self { inner, protocol }
+    }
+
+    /// Subprotocol selected during the upgrade
+    pub fn protocol(&self) -> Option<&HeaderValue> {
+        self.protocol.as_ref()
+    }
+}
+
+impl Stream for DeflateWebSocket {
+    type Item = Result<Message, axum::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let message = match ready!(self.inner.poll_next_unpin(cx)) {
+                Some(Ok(message)) => message,
+                Some(Err(e)) => return Poll::Ready(Some(Err(axum::Error::new(e)))),
+                None => return Poll::Ready(None),
+            };
+            let message = match message {
+                protocol::Message::Text(text) => Message::Text(text),
+                protocol::Message::Binary(data) => Message::Binary(data),
+                protocol::Message::Ping(data) => Message::Ping(data),
+                protocol::Message::Pong(data) => Message::Pong(data),
+                protocol::Message::Close(frame) => Message::Close(frame.map(|frame| CloseFrame {
+                    code: frame.code.into(),
+                    reason: frame.reason,
+                })),
+                // Only returned when writing raw frames
+                protocol::Message::Frame(_) => continue,
+            };
+            return Poll::Ready(Some(Ok(message)));
+        }
+    }
+}
+
+impl Sink<Message> for DeflateWebSocket {
+    type Error = axum::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready_unpin(cx).map_err(axum::Error::new)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, message: Message) -> Result<(), Self::Error> {
+        let message = match message {
+            Message::Text(text) => protocol::Message::Text(text),
+            Message::Binary(data) => protocol::Message::Binary(data),
+            Message::Ping(data) => protocol::Message::Ping(data),
+            Message::Pong(data) => protocol::Message::Pong(data),
+            Message::Close(frame) => {
+                protocol::Message::Close(frame.map(|frame| protocol::CloseFrame {
+                    code: frame.code.into(),
+                    reason: frame.reason,
+                }))
+            }
+        };
+        self.inner
+            .start_send_unpin(message)
+            .map_err(axum::Error::new)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_flush_unpin(cx).map_err(axum::Error::new)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_close_unpin(cx).map_err(axum::Error::new)
+    }
+}
@@ -1,22 +1,40 @@
 /// WebTransport connection implementation for TerminalConnection trait
 use std::fmt::Debug;
 use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 use tokio::sync::Mutex;
-use tracing::{debug, error, info};
+use tracing::{debug, info, warn};
+use wtransport::error::{StreamReadError, StreamWriteError};
 
+use crate::auth::AuthContext;
 use crate::protocol::{
     ConnectionError, ConnectionResult, ConnectionType, TerminalConnection, TerminalMessage,
 };
 
+/// Size of the buffer incoming stream data is read into
+const READ_BUFFER_SIZE: usize = 4096;
+
 /// WebTransport connection implementation that implements TerminalConnection trait
 /// This follows the same pattern as WebSocketConnection
+/// Terminal data flows over one bidirectional stream opened by the server. A stream the peer
+/// resets or stops is replaced by a new one; only a closed QUIC connection ends the session.
 pub struct WebTransportConnection {
     pub id: String,
     // WebTransport connection wrapped in Arc<Mutex> for thread safety
     connection: Arc<Mutex<Option<wtransport::Connection>>>,
     // Bidirectional stream for communication
     stream: Arc<Mutex<Option<wtransport::stream::BiStream>>>,
+    // Set once the QUIC connection has closed
+    closed: Arc<AtomicBool>,
+    // Set once the client finished sending on the current stream
+    recv_finished: AtomicBool,
+    // How long a send may wait for a client that doesn't read
+    send_timeout: Duration,
+    // Largest piece of stream data handed on as one message
+    max_message_bytes: usize,
+    /// Who the session request was authenticated as
+    pub auth_context: Option<AuthContext>,
 }
 
 impl Debug for WebTransportConnection {
@@ -27,98 +45,204 @@ impl Debug for WebTransportConnection {
     }
 }
 
+/// Stream errors mean only the stream is gone, the connection can open another one
+fn read_error(e: StreamReadError) -> ConnectionError {
+    match e {
+        StreamReadError::Reset(code) => {
+            ConnectionError::StreamReset(format!("reset by peer (code: {})", code))
+        }
+        StreamReadError::NotConnected => ConnectionError::ConnectionClosed,
+        StreamReadError::QuicProto => ConnectionError::WebTransport(e.to_string()),
+    }
+}
+
+/// Stream errors mean only the stream is gone, the connection can open another one
+fn write_error(e: StreamWriteError) -> ConnectionError {
+    match e {
+        StreamWriteError::Stopped(code) => {
+            ConnectionError::StreamReset(format!("stopped by peer (code: {})", code))
+        }
+        StreamWriteError::Closed => ConnectionError::StreamReset(e.to_string()),
+        StreamWriteError::NotConnected => ConnectionError::ConnectionClosed,
+        StreamWriteError::QuicProto => ConnectionError::WebTransport(e.to_string()),
+    }
+}
+
 impl WebTransportConnection {
     /// Create a new WebTransport connection
-    pub fn new(id: String) -> Self {
+    /// A send taking longer than `send_timeout` fails with `ConnectionError::Timeout`
+    pub fn new(id: String, send_timeout: Duration) -> Self {
         Self {
             id,
             connection: Arc::new(Mutex::new(None)),
             stream: Arc::new(Mutex::new(None)),
+            closed: Arc::new(AtomicBool::new(false)),
+            recv_finished: AtomicBool::new(false),
+            send_timeout,
+            max_message_bytes: READ_BUFFER_SIZE,
+            auth_context: None,
         }
     }
 
+    /// Hand on incoming stream data in messages of at most `limit` bytes, like a WebSocket
+    /// connection refuses larger ones (`READ_BUFFER_SIZE` by default, a larger limit has no effect)
+    pub fn with_max_message_bytes(mut self, limit: usize) -> Self {
+        self.max_message_bytes = limit.clamp(1, READ_BUFFER_SIZE);
+        self
+    }
+
     /// Set the WebTransport connection
     pub async fn set_connection(
         &self,
         connection: wtransport::Connection,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // Track the state of the QUIC connection for is_alive
+        let watched = connection.clone();
+        let closed = self.closed.clone();
+        let id = self.id.clone();
+        tokio::spawn(async move {
+            let reason = watched.closed().await;
+            debug!("WebTransport connection {} closed: {}", id, reason);
+            closed.store(true, Ordering::Release);
+        });
+
         let mut conn_guard = self.connection.lock().await;
         *conn_guard = Some(connection);
+        drop(conn_guard);
 
         // Create a bidirectional stream
-        let conn = conn_guard.as_ref().unwrap();
+        self.open_stream().await.map_err(Box::new)?;
+
+        info!(
+            "WebTransport connection established for session: {}",
+            self.id
+        );
+        Ok(())
+    }
+
+    /// Open a new bidirectional stream, replacing the current one
+    async fn open_stream(&self) -> ConnectionResult<()> {
+        let conn_guard = self.connection.lock().await;
+        let conn = conn_guard
+            .as_ref()
+            .ok_or(ConnectionError::ConnectionClosed)?;
         let opening_stream = conn
             .open_bi()
             .await
-            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+            .map_err(|_| ConnectionError::ConnectionClosed)?;
         let stream = opening_stream
             .await
-            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+            .map_err(|e| ConnectionError::WebTransport(e.to_string()))?;
 
-        let mut stream_guard = self.stream.lock().await;
-        *stream_guard = Some(stream.into());
+        *self.stream.lock().await = Some(stream.into());
+        self.recv_finished.store(false, Ordering::Release);
+        Ok(())
+    }
 
-        info!(
-            "WebTransport connection established for session: {}",
-            self.id
+    /// Wait until the QUIC connection is closed
+    /// A client that finished sending may still read output, so the session lasts until then
+    async fn wait_closed(&self) {
+        let conn = self.connection.lock().await.clone();
+        if let Some(conn) = conn {
+            conn.closed().await;
+        }
+    }
+
+    /// Replace a failed stream so the session can continue on a new one
+    async fn recover_stream(&self, error: &ConnectionError) -> ConnectionResult<()> {
+        warn!(
+            "WebTransport stream of session {} failed ({}), opening a new one",
+            self.id, error
         );
-        Ok(())
+        self.stream.lock().await.take();
+        self.open_stream().await
+    }
+
+    /// Write data to the stream, retrying once on a new stream if the current one failed
+    async fn write(&self, data: &[u8]) -> ConnectionResult<()> {
+        let result = match self.stream.lock().await.as_mut() {
+            Some(stream) => stream.send_mut().write_all(data).await.map_err(write_error),
+            None => return Err(ConnectionError::ConnectionClosed),
+        };
+        match result {
+            Err(e @ ConnectionError::StreamReset(_)) => {
+                self.recover_stream(&e).await?;
+                match self.stream.lock().await.as_mut() {
+                    Some(stream) => stream.send_mut().write_all(data).await.map_err(write_error),
+                    None => Err(ConnectionError::ConnectionClosed),
+                }
+            }
+            other => other,
+        }
     }
 }
 
 #[async_trait::async_trait]
 impl TerminalConnection for WebTransportConnection {
     async fn send_text(&mut self, message: &str) -> ConnectionResult<()> {
-        let stream_guard = self.stream.lock().await;
-        if let Some(ref _stream) = *stream_guard {
-            // For wtransport 0.6, we need to use a different approach for sending data
-            // The bidirectional stream doesn't have a split method in this version
-            // We'll need to use the connection directly or find the correct API
-            return Err(ConnectionError::WebTransport(
-                "WebTransport send_text not implemented yet".to_string(),
-            ));
-        } else {
-            return Err(ConnectionError::ConnectionClosed);
-        }
+        self.send_binary(message.as_bytes()).await
     }
 
     async fn send_binary(&mut self, data: &[u8]) -> ConnectionResult<()> {
-        let stream_guard = self.stream.lock().await;
-        if let Some(ref _stream) = *stream_guard {
-            // For wtransport 0.6, we need to use a different approach for sending data
-            // The bidirectional stream doesn't have a split method in this version
-            // We'll need to use the connection directly or find the correct API
-            return Err(ConnectionError::WebTransport(
-                "WebTransport send_binary not implemented yet".to_string(),
-            ));
-        } else {
-            return Err(ConnectionError::ConnectionClosed);
-        }
+        tokio::time::timeout(self.send_timeout, self.write(data))
+            .await
+            .map_err(|_| ConnectionError::Timeout)?
     }
 
     async fn receive(&mut self) -> Option<ConnectionResult<TerminalMessage>> {
-        let stream_guard = self.stream.lock().await;
-        if let Some(ref _stream) = *stream_guard {
-            // For wtransport 0.6, we need to use a different approach for receiving data
-            // The bidirectional stream doesn't have a split method in this version
-            // We'll need to use the connection directly or find the correct API
-            error!("WebTransport receive not implemented yet");
-            None
-        } else {
-            // No stream available, wait a bit before checking again
-            tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
-            None
+        // Nothing more arrives on this stream, only the end of the connection is left to report
+        if self.recv_finished.load(Ordering::Acquire) {
+            self.wait_closed().await;
+            return None;
+        }
+
+        // A stream lost in an earlier failed recovery is opened again rather than ending here
+        // (the lock is released first, opening the stream takes it again)
+        let stream_lost = self.stream.lock().await.is_none();
+        if stream_lost && let Err(e) = self.open_stream().await {
+            return Some(Err(e));
+        }
+
+        let mut buffer = [0u8; READ_BUFFER_SIZE];
+        let buffer = &mut buffer[..self.max_message_bytes];
+        let result = match self.stream.lock().await.as_mut() {
+            Some(stream) => stream.recv_mut().read(buffer).await,
+            None => return Some(Err(ConnectionError::ConnectionClosed)),
+        };
+
+        match result {
+            Ok(Some(n)) => Some(Ok(TerminalMessage::Binary(buffer[..n].to_vec()))),
+            // The client finished its side of the stream but may still read output
+            Ok(None) => {
+                debug!(
+                    "Client of WebTransport session {} finished sending, waiting for the connection to close",
+                    self.id
+                );
+                self.recv_finished.store(true, Ordering::Release);
+                self.wait_closed().await;
+                None
+            }
+            // The QUIC connection is gone, that is the definitive end
+            Err(StreamReadError::NotConnected) => None,
+            Err(e) => match read_error(e) {
+                e @ ConnectionError::StreamReset(_) => match self.recover_stream(&e).await {
+                    Ok(()) => Some(Err(e)),
+                    Err(recover_error) => Some(Err(recover_error)),
+                },
+                e => Some(Err(e)),
+            },
         }
     }
 
     async fn close(&mut self) -> ConnectionResult<()> {
         info!("Closing WebTransport connection: {}", self.id);
 
-        // Close the stream
+        // Finish the stream so the client reads all output
         let mut stream_guard = self.stream.lock().await;
-        if let Some(_stream) = stream_guard.take() {
-            // For wtransport 0.6, we need to use a different approach for closing streams
-            // The bidirectional stream doesn't have a split method in this version
+        if let Some(mut stream) = stream_guard.take() {
+            if let Err(e) = stream.send_mut().finish().await {
+                debug!("Failed to finish WebTransport stream: {}", e);
+            }
             debug!("WebTransport stream closed");
         }
 
@@ -143,16 +267,16 @@ impl TerminalConnection for WebTransportConnection {
 
     fn is_alive(&self) -> bool {
         // WebTransport 连接状态检查
-        // 检查连接和流是否都存在
+        // 以 QUIC 连接的实际状态为准，流可能正在重建
         let conn_exists = self
             .connection
             .try_lock()
-            .map_or(false, |guard| guard.is_some());
-        let stream_exists = self
-            .stream
-            .try_lock()
-            .map_or(false, |guard| guard.is_some());
+            .is_ok_and(|guard| guard.is_some());
+
+        conn_exists && !self.closed.load(Ordering::Acquire)
+    }
 
-        conn_exists && stream_exists
+    fn auth_context(&self) -> Option<&AuthContext> {
+        self.auth_context.as_ref()
     }
 }
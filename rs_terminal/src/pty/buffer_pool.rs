@@ -0,0 +1,49 @@
+/// Reusable buffers for PTY output chunks
+/// The reader thread reads into a pooled buffer and the consumer gives it back once the chunk is
+/// copied out, so steady output doesn't allocate a buffer per read
+use std::sync::Mutex;
+
+/// Pool of equally sized byte buffers
+#[derive(Debug)]
+pub struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    /// Size of each buffer (the largest chunk read at once)
+    buffer_size: usize,
+    /// Most buffers kept for reuse, more are freed when given back
+    capacity: usize,
+}
+
+impl BufferPool {
+    /// Create an empty pool keeping up to `capacity` buffers of `buffer_size` bytes
+    pub fn new(buffer_size: usize, capacity: usize) -> Self {
+        Self {
+            buffers: Mutex::new(Vec::with_capacity(capacity)),
+            buffer_size,
+            capacity,
+        }
+    }
+
+    /// Take a buffer of `buffer_size` bytes, allocating one if none is pooled
+    pub fn take(&self) -> Vec<u8> {
+        let pooled = self.buffers.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        match pooled {
+            Some(mut buffer) => {
+                // Within the buffer's capacity, no allocation
+                buffer.resize(self.buffer_size, 0);
+                buffer
+            }
+            None => vec![0; self.buffer_size],
+        }
+    }
+
+    /// Give a buffer back for reuse
+    pub fn give_back(&self, buffer: Vec<u8>) {
+        if buffer.capacity() < self.buffer_size {
+            return;
+        }
+        let mut buffers = self.buffers.lock().unwrap_or_else(|e| e.into_inner());
+        if buffers.len() < self.capacity {
+            buffers.push(buffer);
+        }
+    }
+}
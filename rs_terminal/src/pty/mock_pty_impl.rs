@@ -0,0 +1,204 @@
+use crate::pty::pty_trait::{AsyncPty, PtyConfig, PtyError, PtyExitStatus, PtyFactory};
+use async_trait::async_trait;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::mpsc;
+
+/// Prompt printed by the mock shell
+const MOCK_PROMPT: &str = "mock$ ";
+
+/// PTY without a process, for tests and demos (`pty_implementation = "mock"`)
+/// It behaves like a shell in a terminal that only echoes: input is echoed back, a line break
+/// prints a new prompt, a resize prints the new size and the line `exit` ends it with code 0
+pub struct MockPty {
+    cols: u16,
+    rows: u16,
+    /// Output not read yet; dropped when the mock shell exits, which ends the output
+    output_tx: Option<mpsc::UnboundedSender<Vec<u8>>>,
+    output_rx: mpsc::UnboundedReceiver<Vec<u8>>,
+    /// Rest of the output chunk being read
+    pending: Vec<u8>,
+    /// Input since the last line break
+    line: Vec<u8>,
+    /// Echo of the input being written, queued as one chunk per write
+    echo: Vec<u8>,
+    exit_status: Option<PtyExitStatus>,
+}
+
+impl MockPty {
+    pub fn new(config: &PtyConfig) -> Self {
+        let (output_tx, output_rx) = mpsc::unbounded_channel();
+        let mut pty = Self {
+            cols: config.cols,
+            rows: config.rows,
+            output_tx: Some(output_tx),
+            output_rx,
+            pending: Vec::new(),
+            line: Vec::new(),
+            echo: Vec::new(),
+            exit_status: None,
+        };
+        pty.print(format!(
+            "mock shell for `{}` ({}x{}), type exit to end\r\n{}",
+            config.command, config.cols, config.rows, MOCK_PROMPT
+        ));
+        pty
+    }
+
+    /// Queue output for the reader
+    fn print(&mut self, output: impl Into<Vec<u8>>) {
+        if let Some(output_tx) = &self.output_tx {
+            // The receiver lives as long as the PTY
+            let _ = output_tx.send(output.into());
+        }
+    }
+
+    /// End the mock shell: output already queued is still read, then the output ends
+    fn exit(&mut self, status: PtyExitStatus) {
+        let echo = std::mem::take(&mut self.echo);
+        if !echo.is_empty() {
+            self.print(echo);
+        }
+        self.output_tx = None;
+        self.exit_status = Some(status);
+    }
+
+    /// Handle one byte of input like a line-editing shell
+    fn input(&mut self, byte: u8) {
+        match byte {
+            b'\r' | b'\n' => {
+                let line = std::mem::take(&mut self.line);
+                if String::from_utf8_lossy(&line).trim() == "exit" {
+                    self.echo.extend_from_slice(b"\r\nexit\r\n");
+                    self.exit(PtyExitStatus {
+                        code: Some(0),
+                        signal: None,
+                    });
+                } else {
+                    self.echo.extend_from_slice(b"\r\n");
+                    self.echo.extend_from_slice(MOCK_PROMPT.as_bytes());
+                }
+            }
+            // Ctrl+C drops the line
+            0x03 => {
+                self.line.clear();
+                self.echo.extend_from_slice(b"^C\r\n");
+                self.echo.extend_from_slice(MOCK_PROMPT.as_bytes());
+            }
+            // Backspace
+            0x7f | 0x08 => {
+                if self.line.pop().is_some() {
+                    self.echo.extend_from_slice(b"\x08 \x08");
+                }
+            }
+            _ => {
+                self.line.push(byte);
+                self.echo.push(byte);
+            }
+        }
+    }
+}
+
+impl AsyncRead for MockPty {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        if self.pending.is_empty() {
+            match ready!(self.output_rx.poll_recv(cx)) {
+                Some(chunk) => self.pending = chunk,
+                // The mock shell exited and its output was read
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+        let len = self.pending.len().min(buf.remaining());
+        buf.put_slice(&self.pending[..len]);
+        self.pending.drain(..len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for MockPty {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        if self.output_tx.is_none() {
+            return Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()));
+        }
+        let mut written = 0;
+        // Input after `exit` is not taken
+        while written < buf.len() && self.output_tx.is_some() {
+            self.input(buf[written]);
+            written += 1;
+        }
+        let echo = std::mem::take(&mut self.echo);
+        if !echo.is_empty() {
+            self.print(echo);
+        }
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[async_trait]
+impl AsyncPty for MockPty {
+    async fn resize(&mut self, cols: u16, rows: u16) -> Result<(), PtyError> {
+        if self.output_tx.is_none() {
+            return Err(PtyError::ProcessTerminated);
+        }
+        self.cols = cols;
+        self.rows = rows;
+        self.print(format!(
+            "\r\n[resized to {}x{}]\r\n{}",
+            self.cols, self.rows, MOCK_PROMPT
+        ));
+        Ok(())
+    }
+
+    fn pid(&self) -> Option<u32> {
+        None
+    }
+
+    fn is_alive(&self) -> bool {
+        self.output_tx.is_some()
+    }
+
+    async fn try_wait(&mut self) -> Result<Option<PtyExitStatus>, PtyError> {
+        Ok(self.exit_status.clone())
+    }
+
+    async fn kill(&mut self) -> Result<(), PtyError> {
+        if self.exit_status.is_none() {
+            self.exit(PtyExitStatus {
+                code: None,
+                signal: Some("Killed".to_string()),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Factory of [`MockPty`]s, no process is started
+pub struct MockPtyFactory;
+
+#[async_trait]
+impl PtyFactory for MockPtyFactory {
+    async fn create(&self, config: &PtyConfig) -> Result<Box<dyn AsyncPty>, PtyError> {
+        Ok(Box::new(MockPty::new(config)))
+    }
+
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+}
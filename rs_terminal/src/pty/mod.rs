@@ -1,3 +1,5 @@
+mod buffer_pool;
+mod mock_pty_impl;
 mod portable_pty_impl;
 /// PTY (Pseudo Terminal) handling for Waylon Terminal
 /// This module provides a trait abstraction for different PTY implementations
@@ -5,107 +7,329 @@ mod portable_pty_impl;
 mod pty_trait;
 
 // Export all public types and traits
+pub use mock_pty_impl::{MockPty, MockPtyFactory};
 pub use portable_pty_impl::PortablePtyFactory;
 pub use pty_trait::*;
 
-use tracing::info;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use tracing::{info, instrument, warn};
+
+use crate::api::dto::{EnvironmentSource, TerminalProfile};
 
 /// Get the PTY factory based on configuration
-/// This function now always returns PortablePtyFactory, simplifying the implementation
-pub fn get_pty_factory(implementation_name: &str) -> Box<dyn PtyFactory + Send + Sync> {
-    // Simplified implementation: always use PortablePtyFactory
+/// "mock" returns MockPtyFactory (no processes), everything else PortablePtyFactory
+/// `read_buffer_pool` is the number of read buffers each PTY keeps for reuse
+pub fn get_pty_factory(
+    implementation_name: &str,
+    max_concurrent_spawns: usize,
+    read_buffer_pool: usize,
+) -> Box<dyn PtyFactory> {
+    if implementation_name == "mock" {
+        info!("Using MockPtyFactory implementation, sessions echo their input");
+        return Box::new(MockPtyFactory);
+    }
+
+    // Simplified implementation: every other name uses PortablePtyFactory
     info!(
-        "Using PortablePtyFactory implementation (requested: {})",
-        implementation_name
+        "Using PortablePtyFactory implementation (requested: {}, max concurrent spawns: {}, read buffer pool: {})",
+        implementation_name, max_concurrent_spawns, read_buffer_pool
     );
-    Box::new(PortablePtyFactory)
+    Box::new(PortablePtyFactory::new(
+        max_concurrent_spawns,
+        read_buffer_pool,
+    ))
+}
+
+/// Variable holding the session ID in the shell environment
+pub const SESSION_ID_VAR: &str = "WAYLON_SESSION_ID";
+
+/// Variable holding the session's user ID in the shell environment
+pub const USER_ID_VAR: &str = "WAYLON_USER_ID";
+
+/// Session settings applied on top of the shell configuration when creating a PTY
+#[derive(Debug, Default)]
+pub struct PtyOverrides<'a> {
+    /// Shell type to start (the default shell when unset)
+    pub shell_type: Option<&'a str>,
+    /// Command replacing the shell's command (from a session template)
+    pub command: Option<&'a [String]>,
+    /// Working directory replacing the shell's
+    pub working_directory: Option<&'a Path>,
+    /// Terminal size replacing the shell's (columns, rows)
+    pub size: Option<(u16, u16)>,
+    /// Environment profile applied on top of the shell environment
+    pub environment_profile: Option<&'a str>,
+    /// Variables of the session (template and client)
+    pub environment: Option<&'a HashMap<String, String>>,
+    /// Locale variables forwarded by the client
+    pub locale: &'a [(String, String)],
+    /// Terminal profile of the client
+    pub terminal_profile: TerminalProfile,
+    /// Session and user ID, exported as `WAYLON_SESSION_ID` and `WAYLON_USER_ID` over everything else
+    pub identity: Option<(&'a str, &'a str)>,
+}
+
+/// PTY configuration resolved from the application config and the session overrides
+#[derive(Debug, Clone)]
+pub struct ResolvedPtyConfig {
+    /// Shell type that is started (bash when the default shell isn't configured)
+    pub shell_type: String,
+    /// Environment profile applied on top of the shell environment
+    pub environment_profile: Option<String>,
+    /// Configuration the PTY is created with
+    pub pty_config: PtyConfig,
+    /// Environment of `pty_config` with the source of each variable
+    pub environment: Vec<(String, String, EnvironmentSource)>,
 }
 
 /// Create a new PTY instance using configuration from the application config
+/// Session overrides (shell, command, working directory, size, environment) take precedence
+#[instrument(name = "pty_create", level = "debug", skip_all, fields(shell_type = overrides.shell_type))]
 pub async fn create_pty_from_config(
     app_config: &crate::config::TerminalConfig,
+    factory: &dyn PtyFactory,
+    overrides: &PtyOverrides<'_>,
 ) -> Result<Box<dyn AsyncPty>, PtyError> {
-    // Get default shell configuration
-    let default_shell_type = &app_config.default_shell_type;
-    let shell_config = match app_config.shells.get(default_shell_type) {
-        Some(config) => config,
-        None => {
-            // If default shell is not found, try bash
-            match app_config.shells.get("bash") {
-                Some(config) => config,
-                None => {
-                    return Err(PtyError::Other(format!(
-                        "No shell configuration found for default shell: {}",
-                        default_shell_type
-                    )));
-                }
+    let mut resolved = resolve_pty_config(app_config, overrides)?;
+    // A missing working directory or command would only show up as an opaque OS error of the spawn
+    if let Some(cwd) = &resolved.pty_config.cwd
+        && let Some((cause, os_error)) = unusable_directory(cwd).await
+    {
+        let mut diagnostic = SpawnDiagnostic::from_cause(&resolved.pty_config, cause, os_error);
+        diagnostic.shell_type = Some(resolved.shell_type);
+        return Err(PtyError::SpawnFailed(Box::new(diagnostic)));
+    }
+    if app_config.resolve_command_path {
+        match find_in_path(&resolved.pty_config.command, &resolved.pty_config.env).await {
+            Ok(Some(path)) => resolved.pty_config.command = path.to_string_lossy().into_owned(),
+            Ok(None) => {}
+            Err(cause) => {
+                let mut diagnostic = SpawnDiagnostic::from_cause(&resolved.pty_config, cause, None);
+                diagnostic.shell_type = Some(resolved.shell_type);
+                return Err(PtyError::SpawnFailed(Box::new(diagnostic)));
             }
         }
+    }
+    match factory.create(&resolved.pty_config).await {
+        Err(PtyError::SpawnFailed(mut diagnostic)) => {
+            diagnostic.shell_type = Some(resolved.shell_type);
+            Err(PtyError::SpawnFailed(diagnostic))
+        }
+        result => result,
+    }
+}
+
+/// Why `path` can't be used as working directory (with the OS error code), `None` if it can
+async fn unusable_directory(path: &Path) -> Option<(String, Option<i32>)> {
+    match tokio::fs::metadata(path).await {
+        Ok(metadata) if metadata.is_dir() => None,
+        Ok(_) => Some((
+            format!("working directory {} is not a directory", path.display()),
+            None,
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Some((
+            format!("working directory {} does not exist", path.display()),
+            e.raw_os_error(),
+        )),
+        Err(e) => Some((
+            format!(
+                "working directory {} is not accessible: {}",
+                path.display(),
+                e
+            ),
+            e.raw_os_error(),
+        )),
+    }
+}
+
+/// Look up a bare command name in the `PATH` of the child's environment (the server's if it has
+/// none); `None` for commands with a path, which are spawned as given
+/// The error reaches the client, so the searched `PATH` is only logged
+pub async fn find_in_path(
+    command: &str,
+    env: &[(String, String)],
+) -> Result<Option<PathBuf>, String> {
+    if command.contains('/') || (cfg!(windows) && command.contains('\\')) {
+        return Ok(None);
+    }
+    let (path, origin) = match env.iter().rev().find(|(key, _)| key == "PATH") {
+        Some((_, value)) => (OsString::from(value), "the shell's PATH"),
+        None => (
+            std::env::var_os("PATH").unwrap_or_default(),
+            "the server's PATH",
+        ),
     };
 
-    // Extract command and arguments from shell config (command is required for each shell)
-    let command = shell_config.command[0].clone();
-    let args: Vec<String> = shell_config.command.iter().skip(1).cloned().collect();
+    for directory in std::env::split_paths(&path) {
+        for name in executable_names(command) {
+            let candidate = directory.join(name);
+            if is_executable(&candidate).await {
+                return Ok(Some(candidate));
+            }
+        }
+    }
+    warn!(
+        "Command {} was not found in {} ({})",
+        command,
+        origin,
+        path.to_string_lossy()
+    );
+    Err(format!("command {} was not found in PATH", command))
+}
 
-    // Determine working directory with priority: shell_config.working_directory > default_shell_config.working_directory
-    let working_directory = shell_config
-        .working_directory
-        .clone()
-        .or_else(|| app_config.default_shell_config.working_directory.clone());
+/// File names `command` may have on disk (Windows adds the `PATHEXT` extensions)
+fn executable_names(command: &str) -> Vec<String> {
+    let mut names = vec![command.to_string()];
+    if cfg!(windows) {
+        let extensions =
+            std::env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+        names.extend(
+            extensions
+                .split(';')
+                .filter(|extension| !extension.is_empty())
+                .map(|extension| format!("{}{}", command, extension)),
+        );
+    }
+    names
+}
 
-    // Determine terminal size with priority: shell_config.size > default_shell_config.size
-    let terminal_size = shell_config
-        .size
-        .as_ref()
-        .unwrap_or(&app_config.default_shell_config.size)
-        .clone();
-
-    // Determine environment variables with priority: shell_config.environment > default_shell_config.environment
-    let mut environment = Vec::new();
-
-    // Add default environment variables from default_shell_config
-    if let Some(default_env) = &app_config.default_shell_config.environment {
-        environment.reserve(default_env.len());
-        for (key, value) in default_env {
-            environment.push((key.clone(), value.clone()));
+/// Whether `path` is a file the server may execute
+async fn is_executable(path: &Path) -> bool {
+    match tokio::fs::metadata(path).await {
+        #[cfg(unix)]
+        Ok(metadata) => {
+            use std::os::unix::fs::PermissionsExt;
+            metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
         }
+        #[cfg(not(unix))]
+        Ok(metadata) => metadata.is_file(),
+        Err(_) => false,
     }
+}
 
-    // Add explicit environment variables from shell config, overwriting defaults
-    if let Some(shell_env) = &shell_config.environment {
-        environment.reserve(environment.len() + shell_env.len());
-        for (key, value) in shell_env {
-            // Check if the key already exists, if so, replace it
-            if let Some(index) = environment.iter().position(|(k, _)| k == key) {
-                environment[index] = (key.clone(), value.clone());
-            } else {
-                environment.push((key.clone(), value.clone()));
+/// Resolve the configuration a PTY would be created with, without creating it
+pub fn resolve_pty_config(
+    app_config: &crate::config::TerminalConfig,
+    overrides: &PtyOverrides<'_>,
+) -> Result<ResolvedPtyConfig, PtyError> {
+    // Get the requested (or default) shell configuration
+    let default_shell_type = &app_config.default_shell_type;
+    let requested_shell_type = overrides.shell_type.unwrap_or(default_shell_type);
+    let (shell_type, shell_config) = match app_config.shells.get(requested_shell_type) {
+        Some(config) => (requested_shell_type, config),
+        // If default shell is not found, try bash
+        None if requested_shell_type == default_shell_type => match app_config.shells.get("bash") {
+            Some(config) => ("bash", config),
+            None => {
+                return Err(PtyError::Other(format!(
+                    "No shell configuration found for default shell: {}",
+                    default_shell_type
+                )));
             }
+        },
+        None => {
+            return Err(PtyError::Other(format!(
+                "No shell configuration found for shell: {}",
+                requested_shell_type
+            )));
         }
+    };
+
+    // Extract command and arguments (a template command replaces the shell's)
+    let Some((command, args)) = overrides
+        .command
+        .unwrap_or(&shell_config.command)
+        .split_first()
+    else {
+        return Err(PtyError::Other(format!(
+            "Empty command for shell: {}",
+            shell_type
+        )));
+    };
+    let command = command.clone();
+    let args = args.to_vec();
+
+    // Determine working directory with priority: session > shell_config.working_directory > default_shell_config.working_directory
+    let working_directory = overrides
+        .working_directory
+        .map(Path::to_path_buf)
+        .or_else(|| shell_config.working_directory.clone())
+        .or_else(|| app_config.default_shell_config.working_directory.clone());
+
+    // Determine terminal size with priority: session > shell_config.size > default_shell_config.size
+    let (columns, rows) = overrides.size.unwrap_or_else(|| {
+        let size = shell_config
+            .size
+            .as_ref()
+            .unwrap_or(&app_config.default_shell_config.size);
+        (size.columns, size.rows)
+    });
+
+    // Determine environment variables with priority:
+    // terminal profile > session > profile > shell_config.environment > default_shell_config.environment
+    let environment_profile = overrides.environment_profile;
+    if let Some(profile) = environment_profile
+        && app_config.environment_profile(profile).is_none()
+    {
+        return Err(PtyError::Other(format!(
+            "Unknown environment profile: {}",
+            profile
+        )));
+    }
+    let no_environment = HashMap::new();
+    let mut environment = app_config.shell_environment_sources(
+        shell_type,
+        environment_profile,
+        overrides.environment.unwrap_or(&no_environment),
+        overrides.locale,
+        overrides.terminal_profile,
+    );
+    if let Some((session_id, user_id)) = overrides.identity {
+        apply_session_identity(&mut environment, session_id, user_id);
     }
 
     // Create PTY config
     let pty_config = PtyConfig {
         command,
         args,
-        cols: terminal_size.columns,
-        rows: terminal_size.rows,
-        env: environment,
+        cols: columns,
+        rows,
+        env: environment
+            .iter()
+            .map(|(key, value, _)| (key.clone(), value.clone()))
+            .collect(),
         cwd: working_directory,
     };
 
-    // Get PTY factory based on configuration
-    let factory = get_pty_factory(&app_config.pty_implementation);
-    let pty = factory.create(&pty_config).await?;
-    Ok(pty)
+    Ok(ResolvedPtyConfig {
+        shell_type: shell_type.to_string(),
+        environment_profile: environment_profile.map(str::to_string),
+        pty_config,
+        environment,
+    })
 }
 
-/// Create a new PTY instance with custom configuration
-/// This function uses the default PTY implementation (portable_pty)
-pub async fn create_pty_with_config(config: &PtyConfig) -> Result<Box<dyn AsyncPty>, PtyError> {
-    // 使用默认的PTY实现（PortablePty）
-    let factory = get_pty_factory("portable_pty");
-    factory.create(config).await
+/// Export the session and user ID in a shell environment
+/// The identity can't be overridden by the configuration or the client, it replaces their values
+pub fn apply_session_identity(
+    environment: &mut Vec<(String, String, EnvironmentSource)>,
+    session_id: &str,
+    user_id: &str,
+) {
+    environment.retain(|(key, _, _)| key != SESSION_ID_VAR && key != USER_ID_VAR);
+    environment.push((
+        SESSION_ID_VAR.to_string(),
+        session_id.to_string(),
+        EnvironmentSource::Session,
+    ));
+    environment.push((
+        USER_ID_VAR.to_string(),
+        user_id.to_string(),
+        EnvironmentSource::Session,
+    ));
 }
 
 /// Create a new PTY instance using a specific factory
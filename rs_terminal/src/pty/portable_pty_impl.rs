@@ -1,34 +1,178 @@
-use crate::pty::pty_trait::{AsyncPty, PtyConfig, PtyError, PtyFactory};
+use crate::pty::buffer_pool::BufferPool;
+use crate::pty::pty_trait::{
+    AsyncPty, PtyConfig, PtyError, PtyExitStatus, PtyFactory, SpawnDiagnostic,
+};
 use async_trait::async_trait;
-use portable_pty::{Child, CommandBuilder, PtySize};
+use portable_pty::{Child, CommandBuilder, MasterPty, PtySize};
 use std::pin::Pin;
-use std::process::ExitStatus as StdExitStatus;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::task::{Context, Poll};
+use std::task::{Context, Poll, ready};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
-use tokio::sync::mpsc;
-use tokio::task::spawn_blocking;
-use tracing::{debug, error, info, trace, warn};
+use tokio::sync::{Semaphore, mpsc, oneshot};
+use tokio::task::{JoinHandle, spawn_blocking};
+use tracing::{Span, debug, error, info, trace, warn};
+
+/// 后台线程每次读取的最大字节数
+const READ_CHUNK_SIZE: usize = 4096;
 
 /// 高性能异步 PTY 实现
 /// 使用零拷贝缓冲和智能阻塞策略实现真正的异步体验
 pub struct PortablePty {
     cols: u16,
     rows: u16,
-    master: Arc<Mutex<Box<dyn portable_pty::MasterPty + Send>>>,
     writer: Arc<Mutex<Box<dyn std::io::Write + Send>>>,
-    child: Arc<Mutex<Box<dyn Child + Send>>>,
-    child_exited: Arc<Mutex<bool>>,
+    /// Write running on a blocking thread, awaited by the next write or flush
+    pending_write: Option<JoinHandle<std::io::Result<()>>>,
+    commands: mpsc::UnboundedSender<PtyCommand>,
+    child_exited: Arc<AtomicBool>,
     data_rx: mpsc::Receiver<Vec<u8>>,
-    data_tx: mpsc::Sender<Vec<u8>>,
+    /// 读取缓冲区池，输出块复制完成后归还
+    read_buffers: Arc<BufferPool>,
     buffer: Box<[u8; 8192]>,
     buffer_pos: usize,
     buffer_len: usize,
+    /// 输出已结束，之后的读取直接返回 EOF
+    eof: bool,
+}
+
+/// 发送给 PTY 维护线程的命令
+enum PtyCommand {
+    Resize {
+        cols: u16,
+        rows: u16,
+        reply: oneshot::Sender<Result<(), PtyError>>,
+    },
+    TryWait {
+        reply: oneshot::Sender<Result<Option<PtyExitStatus>, PtyError>>,
+    },
+    Kill {
+        reply: Option<oneshot::Sender<Result<(), PtyError>>>,
+    },
+}
+
+/// PTY 维护线程
+/// 独占 master 和 child 句柄，串行处理 resize/try_wait/kill 请求，
+/// 避免每次操作都占用 tokio 的阻塞线程池
+struct PtyMaintenanceWorker {
+    master: Box<dyn MasterPty + Send>,
+    child: Box<dyn Child + Send>,
+    child_exited: Arc<AtomicBool>,
+    /// 子进程已被回收，其 PID 可能已被复用，不能再发送信号
+    reaped: bool,
+}
+
+impl PtyMaintenanceWorker {
+    /// 在专用线程中启动维护循环
+    fn spawn(self, commands: mpsc::UnboundedReceiver<PtyCommand>) -> Result<(), PtyError> {
+        std::thread::Builder::new()
+            .name("pty-maintenance".to_string())
+            .spawn(move || self.run(commands))
+            .map(|_| ())
+            .map_err(|e| {
+                PtyError::BackgroundTask(format!("Failed to start PTY maintenance thread: {}", e))
+            })
+    }
+
+    /// 维护循环：所有发送端关闭后确保子进程被终止并回收
+    fn run(mut self, mut commands: mpsc::UnboundedReceiver<PtyCommand>) {
+        while let Some(command) = commands.blocking_recv() {
+            match command {
+                PtyCommand::Resize { cols, rows, reply } => {
+                    let _ = reply.send(self.resize(cols, rows));
+                }
+                PtyCommand::TryWait { reply } => {
+                    let _ = reply.send(self.try_wait());
+                }
+                PtyCommand::Kill { reply } => {
+                    let result = self.kill();
+                    match reply {
+                        Some(reply) => {
+                            let _ = reply.send(result);
+                        }
+                        None => {
+                            if let Err(e) = result {
+                                error!("Failed to kill child process during drop: {}", e);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Err(e) = self.kill() {
+            error!("Failed to kill child process on PTY shutdown: {}", e);
+        }
+        debug!("PTY maintenance worker stopped");
+    }
+
+    /// 调整 PTY 大小
+    fn resize(&mut self, cols: u16, rows: u16) -> Result<(), PtyError> {
+        self.master
+            .resize(PtySize {
+                rows,
+                cols,
+                pixel_width: 0,
+                pixel_height: 0,
+            })
+            .map_err(|e| PtyError::ResizeFailed(e.to_string()))
+    }
+
+    /// 非阻塞检查进程是否结束
+    /// 输出结束（EOF）时子进程可能刚刚退出，因此总是向子进程查询真实状态
+    fn try_wait(&mut self) -> Result<Option<PtyExitStatus>, PtyError> {
+        match self.child.try_wait() {
+            Ok(Some(status)) => {
+                self.reaped = true;
+                self.child_exited.store(true, Ordering::Release);
+                Ok(Some(Self::to_exit_status(&status)))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => Err(PtyError::Other(format!("Try wait failed: {}", e))),
+        }
+    }
+
+    /// 转换 portable-pty 的 ExitStatus
+    /// 被信号终止时 portable-pty 只给出信号描述，退出码固定为 1，因此不作为退出码报告
+    fn to_exit_status(status: &portable_pty::ExitStatus) -> PtyExitStatus {
+        match status.signal() {
+            Some(signal) => PtyExitStatus {
+                code: None,
+                signal: Some(signal.to_string()),
+            },
+            None => PtyExitStatus {
+                code: Some(status.exit_code() as i32),
+                signal: None,
+            },
+        }
+    }
+
+    /// 终止并回收子进程
+    /// 输出结束（child_exited）不代表进程已退出，因此只要未回收就总是发送信号
+    fn kill(&mut self) -> Result<(), PtyError> {
+        if self.reaped {
+            return Ok(());
+        }
+
+        if let Err(e) = self.child.kill() {
+            // 进程可能已自行退出（僵尸进程），仍需回收；若仍在运行则不能阻塞等待
+            let _ = self.try_wait();
+            return Err(PtyError::Other(format!("Kill failed: {}", e)));
+        }
+        self.child_exited.store(true, Ordering::Release);
+
+        self.child
+            .wait()
+            .map_err(|e| PtyError::ResourceCleanup(format!("Reap failed: {}", e)))?;
+        self.reaped = true;
+        Ok(())
+    }
 }
 
 impl PortablePty {
     /// 创建新的 PTY 实例
-    pub fn new(config: &PtyConfig) -> Result<Self, PtyError> {
+    /// `read_buffer_pool` 为保留复用的读取缓冲区数量（0 表示每次读取都分配）
+    pub fn new(config: &PtyConfig, read_buffer_pool: usize) -> Result<Self, PtyError> {
         info!(
             "PortablePty: Creating PTY with command: {:?}",
             config.command
@@ -36,28 +180,41 @@ impl PortablePty {
 
         let (pair, child) = Self::create_pty_pair(config)?;
         let (data_tx, data_rx) = Self::create_data_channel();
-        let child_exited = Arc::new(Mutex::new(false));
+        let child_exited = Arc::new(AtomicBool::new(false));
+        let read_buffers = Arc::new(BufferPool::new(READ_CHUNK_SIZE, read_buffer_pool));
 
+        // 发送端只由后台读取任务持有，读取结束时通道关闭，poll_read 才能返回 EOF
         Self::start_background_reader(
             pair.master.try_clone_reader()?,
-            data_tx.clone(),
+            data_tx,
+            read_buffers.clone(),
             child_exited.clone(),
         );
 
         let writer = pair.master.take_writer()?;
 
+        let (commands, commands_rx) = mpsc::unbounded_channel();
+        PtyMaintenanceWorker {
+            master: pair.master,
+            child,
+            child_exited: child_exited.clone(),
+            reaped: false,
+        }
+        .spawn(commands_rx)?;
+
         Ok(Self {
             cols: config.cols,
             rows: config.rows,
-            master: Arc::new(Mutex::new(pair.master)),
             writer: Arc::new(Mutex::new(writer)),
-            child: Arc::new(Mutex::new(child)),
+            pending_write: None,
+            commands,
             child_exited,
             data_rx,
-            data_tx,
+            read_buffers,
             buffer: Box::new([0u8; 8192]),
             buffer_pos: 0,
             buffer_len: 0,
+            eof: false,
         })
     }
 
@@ -75,7 +232,10 @@ impl PortablePty {
         })?;
 
         let cmd = Self::build_command(config);
-        let child = pair.slave.spawn_command(cmd)?;
+        let child = pair
+            .slave
+            .spawn_command(cmd)
+            .map_err(|e| PtyError::SpawnFailed(Box::new(SpawnDiagnostic::new(config, &e))))?;
 
         Ok((pair, child))
     }
@@ -105,10 +265,13 @@ impl PortablePty {
     fn start_background_reader(
         reader: Box<dyn std::io::Read + Send>,
         data_tx: mpsc::Sender<Vec<u8>>,
-        child_exited: Arc<Mutex<bool>>,
+        read_buffers: Arc<BufferPool>,
+        child_exited: Arc<AtomicBool>,
     ) {
         tokio::spawn(async move {
-            let result = spawn_blocking(move || Self::background_read_loop(reader, data_tx)).await;
+            let result =
+                spawn_blocking(move || Self::background_read_loop(reader, data_tx, read_buffers))
+                    .await;
 
             match result {
                 Ok(Ok(())) => debug!("PTY background reader finished successfully"),
@@ -124,24 +287,26 @@ impl PortablePty {
     fn background_read_loop(
         mut reader: Box<dyn std::io::Read + Send>,
         data_tx: mpsc::Sender<Vec<u8>>,
+        read_buffers: Arc<BufferPool>,
     ) -> Result<(), std::io::Error> {
-        let mut buffer = vec![0; 4096];
-
         loop {
+            // 直接读入池中的缓冲区，截断后作为输出块发送，无需再复制
+            let mut buffer = read_buffers.take();
             match reader.read(&mut buffer) {
                 Ok(0) => {
                     debug!("PTY EOF reached, stopping background reader");
                     return Ok(());
                 }
                 Ok(n) => {
-                    let data = Self::process_read_data(&buffer, n);
+                    buffer.truncate(n);
 
-                    if data_tx.blocking_send(data).is_err() {
+                    if data_tx.blocking_send(buffer).is_err() {
                         debug!("PTY background reader: receiver dropped, stopping");
                         return Ok(());
                     }
                 }
                 Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    read_buffers.give_back(buffer);
                     std::thread::sleep(std::time::Duration::from_millis(10));
                     continue;
                 }
@@ -150,22 +315,32 @@ impl PortablePty {
         }
     }
 
-    /// 处理读取的数据
-    fn process_read_data(buffer: &[u8], n: usize) -> Vec<u8> {
-        if n == buffer.len() {
-            buffer.to_vec()
-        } else {
-            buffer[..n].to_vec()
-        }
-    }
-
     /// 标记子进程已退出
-    fn mark_child_exited(child_exited: Arc<Mutex<bool>>) {
-        if let Ok(mut exited) = child_exited.lock() {
-            *exited = true;
-        } else {
-            error!("Failed to acquire child_exited lock for marking exit");
-        }
+    fn mark_child_exited(child_exited: Arc<AtomicBool>) {
+        child_exited.store(true, Ordering::Release);
+    }
+
+    /// 向维护线程发送命令并等待结果
+    async fn request<T>(
+        &self,
+        operation: &str,
+        command: impl FnOnce(oneshot::Sender<Result<T, PtyError>>) -> PtyCommand,
+    ) -> Result<T, PtyError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.commands.send(command(reply_tx)).map_err(|_| {
+            PtyError::BackgroundTask(format!(
+                "PTY maintenance worker stopped before {}",
+                operation
+            ))
+        })?;
+
+        reply_rx.await.map_err(|_| {
+            PtyError::BackgroundTask(format!(
+                "PTY maintenance worker dropped {} request",
+                operation
+            ))
+        })?
     }
 }
 
@@ -181,6 +356,11 @@ impl AsyncRead for PortablePty {
             return Poll::Ready(Ok(()));
         }
 
+        // Once the output has ended every read reports EOF, without touching the channel again
+        if this.eof {
+            return Poll::Ready(Ok(()));
+        }
+
         match this.data_rx.poll_recv(cx) {
             Poll::Ready(Some(data)) => {
                 trace!("PTY AsyncRead: received {} bytes from channel", data.len());
@@ -189,6 +369,7 @@ impl AsyncRead for PortablePty {
             }
             Poll::Ready(None) => {
                 debug!("PTY AsyncRead: channel closed, PTY ended");
+                this.eof = true;
                 Poll::Ready(Ok(()))
             }
             Poll::Pending => {
@@ -227,22 +408,24 @@ impl PortablePty {
     /// 处理接收到的数据
     fn process_received_data(this: &mut Self, data: Vec<u8>, buf: &mut ReadBuf<'_>) {
         if data.len() <= buf.remaining() {
-            Self::handle_small_data(data, buf);
+            Self::handle_small_data(&data, buf);
         } else if data.len() <= this.buffer.len() {
-            Self::handle_medium_data(this, data, buf);
+            Self::handle_medium_data(this, &data, buf);
         } else {
-            Self::handle_large_data(this, data, buf);
+            Self::handle_large_data(this, &data, buf);
         }
+        // 数据已复制出去，缓冲区归还给读取线程复用
+        this.read_buffers.give_back(data);
     }
 
     /// 处理小数据量（完全适合输出缓冲区）
-    fn handle_small_data(data: Vec<u8>, buf: &mut ReadBuf<'_>) {
-        buf.put_slice(&data);
+    fn handle_small_data(data: &[u8], buf: &mut ReadBuf<'_>) {
+        buf.put_slice(data);
         trace!("PTY AsyncRead: direct zero-copy of {} bytes", data.len());
     }
 
     /// 处理中等数据量（适合内部缓冲区）
-    fn handle_medium_data(this: &mut Self, data: Vec<u8>, buf: &mut ReadBuf<'_>) {
+    fn handle_medium_data(this: &mut Self, data: &[u8], buf: &mut ReadBuf<'_>) {
         let to_copy = buf.remaining();
         buf.put_slice(&data[..to_copy]);
 
@@ -257,7 +440,7 @@ impl PortablePty {
     }
 
     /// 处理大数据量（超过内部缓冲区容量）
-    fn handle_large_data(this: &mut Self, data: Vec<u8>, buf: &mut ReadBuf<'_>) {
+    fn handle_large_data(this: &mut Self, data: &[u8], buf: &mut ReadBuf<'_>) {
         let to_copy = std::cmp::min(buf.remaining(), this.buffer.len());
         buf.put_slice(&data[..to_copy]);
 
@@ -284,24 +467,32 @@ impl PortablePty {
 }
 
 impl AsyncWrite for PortablePty {
+    /// The data is written on a blocking thread, this accepts it right away and the next write or
+    /// flush waits for it to be written (like `tokio::fs::File`), so a child that stopped reading
+    /// its input never blocks the runtime
     fn poll_write(
         self: Pin<&mut Self>,
-        _cx: &mut Context<'_>,
+        cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<Result<usize, std::io::Error>> {
         let this = self.get_mut();
+        ready!(this.poll_pending_write(cx))?;
 
-        info!("PTY AsyncWrite: writing {} bytes to PTY", buf.len());
-
-        let writer = Self::acquire_writer_lock(this)?;
-        Self::write_to_pty(writer, buf)
+        trace!("PTY AsyncWrite: writing {} bytes to PTY", buf.len());
+        let writer = this.writer.clone();
+        let data = buf.to_vec();
+        this.pending_write = Some(spawn_blocking(move || {
+            let mut writer = writer
+                .lock()
+                .map_err(|_| std::io::Error::other("PTY writer lock poisoned"))?;
+            writer.write_all(&data)?;
+            writer.flush()
+        }));
+        Poll::Ready(Ok(buf.len()))
     }
 
-    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), std::io::Error>> {
-        let this = self.get_mut();
-
-        let writer = Self::acquire_writer_lock(this)?;
-        Self::flush_writer(writer)
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), std::io::Error>> {
+        self.get_mut().poll_pending_write(cx)
     }
 
     fn poll_shutdown(
@@ -313,205 +504,25 @@ impl AsyncWrite for PortablePty {
 }
 
 impl PortablePty {
-    /// 获取写入器锁
-    fn acquire_writer_lock(
-        this: &mut Self,
-    ) -> Result<std::sync::MutexGuard<'_, Box<dyn std::io::Write + Send>>, std::io::Error> {
-        this.writer.lock().map_err(|e| {
-            error!("PTY AsyncWrite: failed to acquire writer lock: {}", e);
-            std::io::Error::new(std::io::ErrorKind::Other, "Failed to acquire lock")
-        })
-    }
-
-    /// 写入数据到 PTY
-    fn write_to_pty(
-        mut writer: std::sync::MutexGuard<'_, Box<dyn std::io::Write + Send>>,
-        buf: &[u8],
-    ) -> Poll<Result<usize, std::io::Error>> {
-        match writer.write(buf) {
-            Ok(n) => {
-                info!("PTY AsyncWrite: successfully wrote {} bytes", n);
-                Poll::Ready(Ok(n))
-            }
-            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                info!("PTY AsyncWrite: would block, pending");
-                Poll::Pending
-            }
-            Err(e) => {
+    /// Wait for the write running on the blocking thread, with its result
+    fn poll_pending_write(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), std::io::Error>> {
+        let Some(task) = self.pending_write.as_mut() else {
+            return Poll::Ready(Ok(()));
+        };
+        let result = ready!(Pin::new(task).poll(cx));
+        self.pending_write = None;
+        Poll::Ready(match result {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(e)) => {
                 error!("PTY AsyncWrite: error writing to PTY: {}", e);
-                Poll::Ready(Err(e))
-            }
-        }
-    }
-
-    /// 刷新写入器
-    fn flush_writer(
-        mut writer: std::sync::MutexGuard<'_, Box<dyn std::io::Write + Send>>,
-    ) -> Poll<Result<(), std::io::Error>> {
-        match writer.flush() {
-            Ok(()) => Poll::Ready(Ok(())),
-            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => Poll::Pending,
-            Err(e) => Poll::Ready(Err(e)),
-        }
-    }
-}
-
-impl PortablePty {
-    /// 调整 PTY 大小（阻塞操作）
-    fn resize_pty(
-        master: Arc<Mutex<Box<dyn portable_pty::MasterPty + Send>>>,
-        cols: u16,
-        rows: u16,
-    ) -> Result<(), PtyError> {
-        let master = Self::acquire_master_lock(&master, "resize")?;
-
-        match master.resize(PtySize {
-            rows,
-            cols,
-            pixel_width: 0,
-            pixel_height: 0,
-        }) {
-            Ok(()) => Ok(()),
-            Err(e) => Err(PtyError::Other(format!("Resize failed: {}", e))),
-        }
-    }
-
-    /// 处理调整大小结果
-    fn handle_resize_result(
-        resize_result: Result<Result<(), PtyError>, tokio::task::JoinError>,
-        this: &mut Self,
-        cols: u16,
-        rows: u16,
-    ) -> Result<(), PtyError> {
-        match resize_result {
-            Ok(Ok(())) => {
-                this.cols = cols;
-                this.rows = rows;
-                Ok(())
+                Err(e)
             }
-            Ok(Err(e)) => Err(PtyError::Other(format!("Resize operation failed: {}", e))),
-            Err(e) => Err(PtyError::Other(format!(
-                "Resize spawn_blocking failed: {:?}",
+            Err(e) => Err(std::io::Error::other(format!(
+                "PTY write task failed: {}",
                 e
             ))),
-        }
-    }
-
-    /// 获取 master 锁
-    fn acquire_master_lock<'a>(
-        master: &'a Arc<Mutex<Box<dyn portable_pty::MasterPty + Send>>>,
-        operation: &'a str,
-    ) -> Result<std::sync::MutexGuard<'a, Box<dyn portable_pty::MasterPty + Send>>, PtyError> {
-        master.lock().map_err(|e| {
-            error!("Failed to acquire master lock for {}: {}", operation, e);
-            PtyError::LockAcquisition(format!(
-                "Failed to acquire master lock for {}: {}",
-                operation, e
-            ))
         })
     }
-
-    /// 尝试等待进程结束（阻塞操作）
-    fn try_wait_process(
-        child: Arc<Mutex<Box<dyn Child + Send>>>,
-        child_exited: Arc<Mutex<bool>>,
-    ) -> Result<Option<StdExitStatus>, PtyError> {
-        let mut child_guard = Self::acquire_child_lock(&child, "try_wait")?;
-        let mut exited_guard = Self::acquire_child_exited_lock(&child_exited, "try_wait")?;
-
-        if *exited_guard {
-            return Ok(None);
-        }
-
-        match child_guard.try_wait() {
-            Ok(Some(_status)) => {
-                *exited_guard = true;
-                // portable-pty 的 ExitStatus 与 std::process::ExitStatus 不同
-                // 返回一个默认的成功状态
-                Ok(Some(StdExitStatus::default()))
-            }
-            Ok(None) => Ok(None),
-            Err(e) => Err(PtyError::Other(format!("Try wait failed: {}", e))),
-        }
-    }
-
-    /// 处理等待结果
-    fn handle_wait_result(
-        wait_result: Result<Result<Option<StdExitStatus>, PtyError>, tokio::task::JoinError>,
-    ) -> Result<Option<StdExitStatus>, PtyError> {
-        match wait_result {
-            Ok(result) => result,
-            Err(e) => Err(PtyError::Other(format!(
-                "Wait spawn_blocking failed: {:?}",
-                e
-            ))),
-        }
-    }
-
-    /// 获取 child 锁
-    fn acquire_child_lock<'a>(
-        child: &'a Arc<Mutex<Box<dyn Child + Send>>>,
-        operation: &'a str,
-    ) -> Result<std::sync::MutexGuard<'a, Box<dyn Child + Send>>, PtyError> {
-        child.lock().map_err(|e| {
-            error!("Failed to acquire child lock for {}: {}", operation, e);
-            PtyError::LockAcquisition(format!(
-                "Failed to acquire child lock for {}: {}",
-                operation, e
-            ))
-        })
-    }
-
-    /// 获取 child_exited 锁
-    fn acquire_child_exited_lock<'a>(
-        child_exited: &'a Arc<Mutex<bool>>,
-        operation: &'a str,
-    ) -> Result<std::sync::MutexGuard<'a, bool>, PtyError> {
-        child_exited.lock().map_err(|e| {
-            error!(
-                "Failed to acquire child_exited lock for {}: {}",
-                operation, e
-            );
-            PtyError::LockAcquisition(format!(
-                "Failed to acquire child_exited lock for {}: {}",
-                operation, e
-            ))
-        })
-    }
-
-    /// 终止进程（阻塞操作）
-    fn kill_process(
-        child: Arc<Mutex<Box<dyn Child + Send>>>,
-        child_exited: Arc<Mutex<bool>>,
-    ) -> Result<(), PtyError> {
-        let mut child_guard = Self::acquire_child_lock(&child, "kill")?;
-        let mut exited_guard = Self::acquire_child_exited_lock(&child_exited, "kill")?;
-
-        if *exited_guard {
-            return Ok(());
-        }
-
-        match child_guard.kill() {
-            Ok(()) => {
-                *exited_guard = true;
-                Ok(())
-            }
-            Err(e) => Err(PtyError::Other(format!("Kill failed: {}", e))),
-        }
-    }
-
-    /// 处理终止结果
-    fn handle_kill_result(
-        kill_result: Result<Result<(), PtyError>, tokio::task::JoinError>,
-    ) -> Result<(), PtyError> {
-        match kill_result {
-            Ok(result) => result,
-            Err(e) => Err(PtyError::Other(format!(
-                "Kill spawn_blocking failed: {:?}",
-                e
-            ))),
-        }
-    }
 }
 
 // 实现 AsyncPty trait 为 PortablePty
@@ -521,10 +532,12 @@ impl AsyncPty for PortablePty {
     async fn resize(&mut self, cols: u16, rows: u16) -> Result<(), PtyError> {
         info!("PortablePty: Resizing PTY to {}x{}", cols, rows);
 
-        let master = self.master.clone();
-        let resize_result = spawn_blocking(move || Self::resize_pty(master, cols, rows)).await;
+        self.request("resize", |reply| PtyCommand::Resize { cols, rows, reply })
+            .await?;
 
-        Self::handle_resize_result(resize_result, self, cols, rows)
+        self.cols = cols;
+        self.rows = rows;
+        Ok(())
     }
 
     /// 获取进程ID（如果可用）
@@ -535,38 +548,21 @@ impl AsyncPty for PortablePty {
 
     /// 检查进程是否存活
     fn is_alive(&self) -> bool {
-        match self.child_exited.lock() {
-            Ok(exited) => !*exited,
-            Err(e) => {
-                error!(
-                    "Failed to acquire child_exited lock for is_alive check: {}",
-                    e
-                );
-                false
-            }
-        }
+        !self.child_exited.load(Ordering::Acquire)
     }
 
     /// 等待进程结束（非阻塞检查）
-    async fn try_wait(&mut self) -> Result<Option<StdExitStatus>, PtyError> {
-        let child = self.child.clone();
-        let child_exited = self.child_exited.clone();
-
-        let wait_result = spawn_blocking(move || Self::try_wait_process(child, child_exited)).await;
-
-        Self::handle_wait_result(wait_result)
+    async fn try_wait(&mut self) -> Result<Option<PtyExitStatus>, PtyError> {
+        self.request("try_wait", |reply| PtyCommand::TryWait { reply })
+            .await
     }
 
     /// 立即终止进程
     async fn kill(&mut self) -> Result<(), PtyError> {
         info!("PortablePty: Killing child process");
 
-        let child = self.child.clone();
-        let child_exited = self.child_exited.clone();
-
-        let kill_result = spawn_blocking(move || Self::kill_process(child, child_exited)).await;
-
-        Self::handle_kill_result(kill_result)
+        self.request("kill", |reply| PtyCommand::Kill { reply: Some(reply) })
+            .await
     }
 }
 
@@ -577,17 +573,13 @@ impl Drop for PortablePty {
     fn drop(&mut self) {
         info!("PortablePty: Dropping PTY instance");
 
-        // 尝试终止子进程
-        if self.is_alive() {
-            let child = self.child.clone();
-            let child_exited = self.child_exited.clone();
-
-            // 使用 spawn_blocking 避免阻塞异步运行时
-            let _ = spawn_blocking(move || {
-                if let Err(e) = Self::kill_process(child, child_exited) {
-                    error!("Failed to kill child process during drop: {}", e);
-                }
-            });
+        // 由维护线程负责终止并回收子进程，失败时由其记录日志
+        if self
+            .commands
+            .send(PtyCommand::Kill { reply: None })
+            .is_err()
+        {
+            warn!("PortablePty: maintenance worker already stopped during drop");
         }
 
         // 关闭数据通道
@@ -600,15 +592,42 @@ impl Drop for PortablePty {
 // ================ 工厂实现 ================
 
 /// 基于 portable-pty 的 PTY 工厂
-pub struct PortablePtyFactory;
+pub struct PortablePtyFactory {
+    /// 限制同时进行的阻塞式 PTY 创建数量
+    spawn_permits: Arc<Semaphore>,
+    /// 每个 PTY 保留复用的读取缓冲区数量
+    read_buffer_pool: usize,
+}
+
+impl PortablePtyFactory {
+    /// 创建工厂，最多允许 `max_concurrent_spawns` 个 PTY 同时创建
+    pub fn new(max_concurrent_spawns: usize, read_buffer_pool: usize) -> Self {
+        Self {
+            spawn_permits: Arc::new(Semaphore::new(max_concurrent_spawns.max(1))),
+            read_buffer_pool,
+        }
+    }
+}
 
 #[async_trait]
 impl PtyFactory for PortablePtyFactory {
     async fn create(&self, config: &PtyConfig) -> Result<Box<dyn AsyncPty>, PtyError> {
+        let _permit = self
+            .spawn_permits
+            .acquire()
+            .await
+            .map_err(|e| PtyError::Other(format!("PTY spawn limiter closed: {}", e)))?;
+
         // 创建 PTY 实例 - 这是阻塞操作，但只在初始化时执行一次
         // 使用 spawn_blocking 确保它不会阻塞异步运行时
+        // 在当前 span（pty_create 及会话）内创建，日志才带有会话信息
         let config_clone = config.clone();
-        let pty_result = spawn_blocking(move || PortablePty::new(&config_clone)).await;
+        let read_buffer_pool = self.read_buffer_pool;
+        let span = Span::current();
+        let pty_result = spawn_blocking(move || {
+            span.in_scope(|| PortablePty::new(&config_clone, read_buffer_pool))
+        })
+        .await;
 
         match pty_result {
             Ok(pty) => Ok(Box::new(pty?)),
@@ -14,12 +14,105 @@ pub struct PtyConfig {
     pub cwd: Option<std::path::PathBuf>,
 }
 
+/// What was being started when spawning the process of a PTY failed
+#[derive(Debug, Clone)]
+pub struct SpawnDiagnostic {
+    /// Shell type of the session (unset for PTYs created without a shell configuration)
+    pub shell_type: Option<String>,
+    /// Resolved command
+    pub command: String,
+    /// Arguments of the command
+    pub args: Vec<String>,
+    /// Working directory the process was started in
+    pub cwd: Option<std::path::PathBuf>,
+    /// Error code of the underlying OS error, if there was one
+    pub os_error: Option<i32>,
+    /// Why spawning failed
+    pub cause: String,
+}
+
+impl SpawnDiagnostic {
+    /// Describe a failure to spawn the process of `config`
+    pub fn new(config: &PtyConfig, error: &anyhow::Error) -> Self {
+        let os_error = error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<std::io::Error>())
+            .and_then(std::io::Error::raw_os_error);
+        Self {
+            shell_type: None,
+            command: config.command.clone(),
+            args: config.args.clone(),
+            cwd: config.cwd.clone(),
+            os_error,
+            // Kept on one line, it ends up in a single log line and error frame
+            cause: format!("{:#}", error).replace('\n', " "),
+        }
+    }
+
+    /// Describe a failure found before spawning the process of `config`
+    pub fn from_cause(config: &PtyConfig, cause: String, os_error: Option<i32>) -> Self {
+        Self {
+            shell_type: None,
+            command: config.command.clone(),
+            args: config.args.clone(),
+            cwd: config.cwd.clone(),
+            os_error,
+            cause,
+        }
+    }
+}
+
+impl std::fmt::Display for SpawnDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}`", self.command)?;
+        if let Some(shell_type) = &self.shell_type {
+            write!(f, " (shell {})", shell_type)?;
+        }
+        if let Some(cwd) = &self.cwd {
+            write!(f, " in {}", cwd.display())?;
+        }
+        write!(f, ": {}", self.cause)?;
+        if let Some(code) = self.os_error
+            && !self.cause.contains("os error")
+        {
+            write!(f, " (os error {})", code)?;
+        }
+        Ok(())
+    }
+}
+
+/// How the process of a PTY ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PtyExitStatus {
+    /// Exit code, `None` when the process was ended by a signal
+    pub code: Option<i32>,
+    /// Description of the signal that ended the process (e.g. `Killed`)
+    pub signal: Option<String>,
+}
+
+impl PtyExitStatus {
+    /// Whether the process exited with code 0
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+impl std::fmt::Display for PtyExitStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.signal, self.code) {
+            (Some(signal), _) => write!(f, "signal {}", signal),
+            (None, Some(code)) => write!(f, "exit code {}", code),
+            (None, None) => write!(f, "unknown exit status"),
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum PtyError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
     #[error("Process spawn failed: {0}")]
-    SpawnFailed(String),
+    SpawnFailed(Box<SpawnDiagnostic>),
     #[error("PTY not available")]
     NotAvailable,
     #[error("Process already terminated")]
@@ -62,7 +155,7 @@ pub trait AsyncPty: AsyncRead + AsyncWrite + Send + Sync + Unpin {
     fn is_alive(&self) -> bool;
 
     /// 等待进程结束（非阻塞检查）
-    async fn try_wait(&mut self) -> Result<Option<std::process::ExitStatus>, PtyError>;
+    async fn try_wait(&mut self) -> Result<Option<PtyExitStatus>, PtyError>;
 
     /// 立即终止进程
     async fn kill(&mut self) -> Result<(), PtyError>;
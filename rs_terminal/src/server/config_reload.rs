@@ -0,0 +1,99 @@
+/// Configuration reload on SIGHUP
+/// The reloaded configuration applies to requests and sessions started afterwards; running
+/// sessions keep the configuration they started with
+use std::path::PathBuf;
+
+use tracing::{error, info, warn};
+
+use crate::app_state::AppState;
+use crate::config::{ConfigLoader, TerminalConfig};
+
+/// Reload the configuration whenever the process receives SIGHUP
+/// `config_path` is the path the configuration was loaded from (`None` for the default path);
+/// a configuration read from stdin can't be reloaded
+#[cfg(unix)]
+pub fn spawn_config_reloader(state: AppState, config_path: Option<PathBuf>) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            warn!(
+                "Failed to install SIGHUP handler, configuration reload unavailable: {}",
+                e
+            );
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            info!("Received SIGHUP signal, reloading configuration...");
+            reload(&state, config_path.as_deref());
+        }
+    });
+}
+
+/// Configuration reload is only supported on Unix
+#[cfg(not(unix))]
+pub fn spawn_config_reloader(_state: AppState, _config_path: Option<PathBuf>) {}
+
+/// Load the configuration again and install it, keeping the current one if it is invalid
+#[cfg(unix)]
+fn reload(state: &AppState, config_path: Option<&std::path::Path>) {
+    if config_path.is_some_and(|path| path.as_os_str() == "-") {
+        warn!("Configuration was read from stdin and can't be reloaded");
+        return;
+    }
+    let config = match ConfigLoader::new().load_config(config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(
+                "Failed to reload configuration, keeping the current one: {}",
+                e
+            );
+            return;
+        }
+    };
+    // Compared with the configuration the server started with, which is still in effect for these
+    for setting in restart_required(&state.config, &config) {
+        warn!(
+            "Changed setting {} only takes effect after a restart",
+            setting
+        );
+    }
+    state.reload_config(config);
+    info!("Configuration reloaded, new sessions use it");
+}
+
+/// Settings read once at startup that differ between two configurations
+#[cfg(unix)]
+fn restart_required(current: &TerminalConfig, new: &TerminalConfig) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    if current.effective_http_port() != new.effective_http_port() {
+        changed.push(current.http_port_key());
+    }
+    if current.effective_webtransport_port() != new.effective_webtransport_port() {
+        changed.push(current.webtransport_port_key());
+    }
+    if current.enable_websocket != new.enable_websocket {
+        changed.push("enable_websocket");
+    }
+    if current.enable_webtransport != new.enable_webtransport {
+        changed.push("enable_webtransport");
+    }
+    if current.pty_implementation != new.pty_implementation
+        || current.max_concurrent_pty_spawns != new.max_concurrent_pty_spawns
+        || current.pty_read_buffer_pool != new.pty_read_buffer_pool
+    {
+        changed.push("pty_implementation/max_concurrent_pty_spawns/pty_read_buffer_pool");
+    }
+    if current.scrollback_limit_bytes != new.scrollback_limit_bytes
+        || current.scrollback_memory_budget_bytes != new.scrollback_memory_budget_bytes
+    {
+        changed.push("scrollback_limit_bytes/scrollback_memory_budget_bytes");
+    }
+    if current.auth != new.auth {
+        changed.push("auth");
+    }
+    changed
+}
@@ -0,0 +1,60 @@
+/// Error types for the server module
+use std::io;
+use std::net::SocketAddr;
+
+use thiserror::Error;
+
+/// Server startup and runtime error type
+#[derive(Error, Debug)]
+pub enum ServerError {
+    /// Failed to bind a listener, with a diagnosis of the likely cause
+    #[error(
+        "Failed to bind {transport} listener on {addr}: {cause}. Free the port or change `{config_key}` in the configuration"
+    )]
+    Bind {
+        transport: &'static str,
+        addr: SocketAddr,
+        config_key: &'static str,
+        cause: String,
+        #[source]
+        source: io::Error,
+    },
+
+    /// WebTransport setup error
+    #[error("WebTransport error: {0}")]
+    WebTransport(String),
+
+    /// IO error while serving
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl ServerError {
+    /// Build a bind error, translating the OS error into an actionable cause
+    pub fn bind(
+        transport: &'static str,
+        addr: SocketAddr,
+        config_key: &'static str,
+        source: io::Error,
+    ) -> Self {
+        let cause = match source.kind() {
+            io::ErrorKind::AddrInUse => {
+                "address already in use (another process is listening on this port)".to_string()
+            }
+            io::ErrorKind::PermissionDenied if addr.port() < 1024 => {
+                "permission denied (ports below 1024 require elevated privileges)".to_string()
+            }
+            io::ErrorKind::PermissionDenied => "permission denied".to_string(),
+            io::ErrorKind::AddrNotAvailable => "address not available on this host".to_string(),
+            _ => source.to_string(),
+        };
+
+        ServerError::Bind {
+            transport,
+            addr,
+            config_key,
+            cause,
+            source,
+        }
+    }
+}
@@ -1,6 +1,15 @@
 /// Server management for Waylon Terminal Rust backend
+mod config_reload;
+mod error;
 mod server;
+mod socket_activation;
+mod supervisor;
+
+pub use config_reload::spawn_config_reloader;
+pub use error::ServerError;
+pub use supervisor::{RestartPolicy, TaskError, supervise};
 
 pub use server::{
-    build_router, run_server, run_server_with_graceful_shutdown, start_webtransport_service,
+    build_router, run_server, run_server_with_graceful_shutdown, serve_with_graceful_shutdown,
+    start_webtransport_service,
 };
@@ -3,28 +3,105 @@ use std::net::SocketAddr;
 
 use axum::{
     Router,
-    http::Method,
-    routing::{delete, get, post},
+    http::{HeaderValue, Method, header},
+    middleware,
+    routing::{delete, get, patch, post},
 };
-use tokio::net::TcpListener;
-use tower_http::cors::{Any, CorsLayer};
-use tracing::info;
+use tokio::net::{TcpListener, TcpSocket};
+use tower_http::{
+    cors::{Any, CorsLayer},
+    set_header::SetResponseHeaderLayer,
+};
+use tracing::{error, info, warn};
 
-use crate::{app_state::AppState, handlers};
+use super::supervisor::{self, RestartPolicy};
+use super::{ServerError, socket_activation};
+use crate::{app_state::AppState, handlers, service};
 use std::time::Duration;
-use tokio::signal;
+use tokio::{signal, sync::oneshot};
+
+/// Lifetime of the HTTP/3 alternative service advertisement in seconds
+const ALT_SVC_MAX_AGE: u32 = 86400;
+
+/// Attempts to bind the HTTP port while it is in use (e.g. by a previous process still shutting down)
+const HTTP_BIND_ATTEMPTS: u32 = 5;
+
+/// Delay between attempts to bind the HTTP port
+const HTTP_BIND_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Pending connections queued by the HTTP listener (same as `TcpListener::bind`)
+const HTTP_LISTEN_BACKLOG: u32 = 1024;
 
 /// Start WebTransport server in a separate task
-pub fn start_webtransport_service(state: AppState) {
-    let webtransport_addr = SocketAddr::from(([0, 0, 0, 0], state.config.webtransport_port));
+/// The UDP port is bound before returning so bind failures reach the caller; with
+/// `enable_webtransport = false` nothing is started and no port is bound
+pub fn start_webtransport_service(state: AppState) -> Result<(), ServerError> {
+    if !state.config.enable_webtransport {
+        info!("WebTransport service disabled by configuration");
+        return Ok(());
+    }
+    let webtransport_addr =
+        SocketAddr::from(([0, 0, 0, 0], state.config.effective_webtransport_port()));
+    // Readiness reports WebTransport as down when it couldn't even start
+    let endpoint = handlers::webtransport::bind_webtransport_endpoint(
+        webtransport_addr,
+        state.config.webtransport_port_key(),
+    )
+    .inspect_err(|_| state.set_webtransport_degraded(true))?;
+
     let webtransport_state = state.clone();
-    tokio::spawn(async move {
-        crate::handlers::webtransport::start_webtransport_server(
-            webtransport_addr,
-            webtransport_state,
-        )
-        .await;
-    });
+    tokio::spawn(supervise_webtransport_server(
+        webtransport_addr,
+        endpoint,
+        webtransport_state,
+    ));
+    Ok(())
+}
+
+/// Keep the WebTransport server running, restarting it with backoff when it fails or panics.
+/// The HTTP/WebSocket server is never affected by WebTransport failures.
+async fn supervise_webtransport_server(
+    addr: SocketAddr,
+    endpoint: handlers::webtransport::WebTransportEndpoint,
+    state: AppState,
+) {
+    let mut endpoint = Some(endpoint);
+    let result = supervisor::supervise("WebTransport server", RestartPolicy::default(), || {
+        // The first run reuses the endpoint bound at startup, restarts bind a fresh one
+        let endpoint = endpoint.take();
+        let state = state.clone();
+        async move {
+            let endpoint = match endpoint {
+                Some(endpoint) => endpoint,
+                None => handlers::webtransport::bind_webtransport_endpoint(
+                    addr,
+                    state.config.webtransport_port_key(),
+                )?,
+            };
+            let result =
+                handlers::webtransport::start_webtransport_server(endpoint, state.clone()).await;
+            if result.is_err() {
+                // Readiness reports it until the server is listening again
+                state.set_webtransport_degraded(true);
+            }
+            result
+        }
+    })
+    .await;
+    if result.is_err() {
+        state.set_webtransport_degraded(true);
+        error!("WebTransport stays down; HTTP and WebSocket remain available");
+    }
+}
+
+/// `alt-svc` header value advertising the WebTransport (HTTP/3) endpoint
+fn alt_svc_header(config: &crate::config::TerminalConfig) -> HeaderValue {
+    HeaderValue::from_str(&format!(
+        "h3=\":{}\"; ma={}",
+        config.effective_webtransport_port(),
+        ALT_SVC_MAX_AGE
+    ))
+    .expect("alt-svc header value is valid ASCII")
 }
 
 /// Build the application router with routes
@@ -50,21 +127,44 @@ pub fn build_router(state: AppState) -> Router {
     // Removed allow_credentials(true) to comply with CORS spec
     // When allow_credentials is true, you can't use wildcard for origin or headers
 
+    // Only advertised when the WebTransport service runs
+    let alt_svc = state
+        .config
+        .enable_webtransport
+        .then(|| alt_svc_header(&state.config));
+
+    // REST API endpoints for session management
+    let mut authenticated = Router::new().nest("/api", api_routes());
+
+    // WebSocket endpoints for terminal communication (unless disabled)
+    if state.config.enable_websocket {
+        // Support both /ws and /ws/:session_id formats
+        authenticated = authenticated
+            .route("/ws", get(handlers::websocket::websocket_handler))
+            .route(
+                "/ws/:session_id",
+                get(handlers::websocket::websocket_handler_with_id),
+            );
+    }
+
     Router::new()
         // Health check endpoint
         .route("/", get(|| async { "Waylon Terminal - Rust Backend" }))
         .route("/health", get(handlers::rest::health_check))
-        // WebSocket endpoints for terminal communication
-        // Support both /ws and /ws/:session_id formats
-        .route("/ws", get(handlers::websocket::websocket_handler))
-        .route(
-            "/ws/:session_id",
-            get(handlers::websocket::websocket_handler_with_id),
-        )
-        // REST API endpoints for session management
-        .nest("/api", api_routes())
+        .route("/health/ready", get(handlers::rest::readiness_check))
+        .route("/metrics", get(handlers::rest::metrics))
+        // Sessions and terminals need an authenticated user, probes don't
+        .merge(authenticated.route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            handlers::auth::require_auth,
+        )))
         // Add CORS middleware layer
         .layer(cors)
+        // Let browsers discover the WebTransport endpoint
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::ALT_SVC,
+            move |_: &axum::response::Response| alt_svc.clone(),
+        ))
         .with_state(state)
 }
 
@@ -74,7 +174,29 @@ fn api_routes() -> Router<AppState> {
         // Session management endpoints
         .route("/sessions", post(handlers::rest::create_session))
         .route("/sessions", get(handlers::rest::get_all_sessions))
+        .route("/sessions/lookup", get(handlers::rest::lookup_sessions))
+        .route("/sessions/dry-run", post(handlers::rest::dry_run_session))
         .route("/sessions/:session_id", get(handlers::rest::get_session))
+        .route(
+            "/sessions/:session_id",
+            patch(handlers::rest::update_session),
+        )
+        .route(
+            "/sessions/:session_id/environment",
+            get(handlers::rest::get_session_environment),
+        )
+        .route(
+            "/sessions/:session_id/diagnostics",
+            get(handlers::rest::get_session_diagnostics),
+        )
+        .route(
+            "/sessions/:session_id/disconnect",
+            post(handlers::rest::disconnect_session),
+        )
+        .route(
+            "/sessions/:session_id/scrollback",
+            delete(handlers::rest::clear_session_scrollback),
+        )
         .route(
             "/sessions/:session_id/resize",
             post(handlers::rest::resize_session),
@@ -83,48 +205,163 @@ fn api_routes() -> Router<AppState> {
             "/sessions/:session_id",
             delete(handlers::rest::terminate_session),
         )
+        // Shells and session templates
+        .route("/shells", get(handlers::rest::get_shells))
+        .route("/templates", get(handlers::rest::get_templates))
+        // Headless command execution
+        .route("/exec", post(handlers::rest::exec_command))
+        // Administration endpoints
+        .nest("/admin", admin_routes())
+}
+
+/// Build the administration routes, only open to administrators
+fn admin_routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/sessions/terminate",
+            post(handlers::rest::terminate_all_sessions),
+        )
+        .route("/broadcast", post(handlers::rest::broadcast_notice))
+        .route("/drain", post(handlers::rest::start_draining))
+        .route("/undrain", post(handlers::rest::stop_draining))
+        .route("/config", get(handlers::rest::get_config))
+        .route("/pty-stats", get(handlers::rest::get_pty_stats))
+        .route_layer(middleware::from_fn(handlers::auth::require_admin))
+}
+
+/// Bind the HTTP listener, describing the likely cause on failure
+/// A listener passed by systemd socket activation is used instead of binding one
+async fn bind_http_listener(
+    addr: SocketAddr,
+    config: &crate::config::TerminalConfig,
+) -> Result<TcpListener, ServerError> {
+    if let Some(listener) = socket_activation::activated_listener()? {
+        info!(
+            "Using socket-activated HTTP listener on {}",
+            listener.local_addr()?
+        );
+        return Ok(listener);
+    }
+
+    let mut attempt = 1;
+    loop {
+        match bind_reusable(addr) {
+            Ok(listener) => return Ok(listener),
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse && attempt < HTTP_BIND_ATTEMPTS => {
+                warn!(
+                    "HTTP port {} is in use, retrying in {:?} (attempt {}/{})",
+                    addr.port(),
+                    HTTP_BIND_RETRY_DELAY,
+                    attempt,
+                    HTTP_BIND_ATTEMPTS
+                );
+                tokio::time::sleep(HTTP_BIND_RETRY_DELAY).await;
+                attempt += 1;
+            }
+            Err(e) => {
+                if e.kind() == std::io::ErrorKind::AddrInUse {
+                    error!(
+                        "HTTP port {} is still in use after {} attempts",
+                        addr.port(),
+                        HTTP_BIND_ATTEMPTS
+                    );
+                }
+                return Err(ServerError::bind(
+                    "HTTP (TCP)",
+                    addr,
+                    config.http_port_key(),
+                    e,
+                ));
+            }
+        }
+    }
+}
+
+/// Bind a TCP listener that can take over a port whose previous connections are in TIME_WAIT
+fn bind_reusable(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    // On Windows SO_REUSEADDR would also allow binding a port another process listens on
+    #[cfg(unix)]
+    socket.set_reuseaddr(true)?;
+    socket.bind(addr)?;
+    socket.listen(HTTP_LISTEN_BACKLOG)
 }
 
 /// Run the HTTP server
 pub async fn run_server(
     router: Router,
     config: &crate::config::TerminalConfig,
-) -> Result<(), std::io::Error> {
-    let addr = SocketAddr::from(([0, 0, 0, 0], config.http_port));
-    let webtransport_addr = SocketAddr::from(([0, 0, 0, 0], config.webtransport_port));
+) -> Result<(), ServerError> {
+    let addr = SocketAddr::from(([0, 0, 0, 0], config.effective_http_port()));
+    let webtransport_addr = SocketAddr::from(([0, 0, 0, 0], config.effective_webtransport_port()));
 
-    let listener = TcpListener::bind(addr).await?;
+    let listener = bind_http_listener(addr, config).await?;
+    // The address actually served (differs from the configured one with socket activation)
+    let addr = listener.local_addr()?;
 
     info!("Server running on http://{}", addr);
-    info!("WebSocket server available at ws://{}/ws", addr);
-    info!(
-        "WebTransport server available at https://{}",
-        webtransport_addr
-    );
+    if config.enable_websocket {
+        info!("WebSocket server available at ws://{}/ws", addr);
+    }
+    if config.enable_webtransport {
+        info!(
+            "WebTransport server available at https://{}",
+            webtransport_addr
+        );
+    }
 
     axum::serve(listener, router).await?;
     Ok(())
 }
 
 /// Run the HTTP server with graceful shutdown support
+/// On shutdown all sessions are terminated before the server exits
 pub async fn run_server_with_graceful_shutdown(
     router: Router,
-    config: &crate::config::TerminalConfig,
-) -> Result<(), std::io::Error> {
-    let addr = SocketAddr::from(([0, 0, 0, 0], config.http_port));
-    let webtransport_addr = SocketAddr::from(([0, 0, 0, 0], config.webtransport_port));
+    state: AppState,
+) -> Result<(), ServerError> {
+    let config = state.config.clone();
+    let addr = SocketAddr::from(([0, 0, 0, 0], config.effective_http_port()));
+    let webtransport_addr = SocketAddr::from(([0, 0, 0, 0], config.effective_webtransport_port()));
 
-    let listener = TcpListener::bind(addr).await?;
+    let listener = bind_http_listener(addr, &config).await?;
+    // The address actually served (differs from the configured one with socket activation)
+    let addr = listener.local_addr()?;
 
     info!("Server running on http://{}", addr);
-    info!("WebSocket server available at ws://{}/ws", addr);
-    info!(
-        "WebTransport server available at https://{}",
-        webtransport_addr
-    );
+    if config.enable_websocket {
+        info!("WebSocket server available at ws://{}/ws", addr);
+    }
+    if config.enable_webtransport {
+        info!(
+            "WebTransport server available at https://{}",
+            webtransport_addr
+        );
+    }
+
+    serve_with_graceful_shutdown(listener, router, state).await
+}
+
+/// Serve `router` on `listener` until Ctrl+C, SIGTERM or a drained server asks to stop
+/// All sessions are terminated, then connections still open after `max_shutdown_duration_ms` are
+/// dropped so a stuck connection can't keep the server from exiting
+pub async fn serve_with_graceful_shutdown(
+    listener: TcpListener,
+    router: Router,
+    state: AppState,
+) -> Result<(), ServerError> {
+    let config = state.config.clone();
+
+    // Signalled once shutdown has started, to bound how long it may take
+    let (shutdown_started_tx, shutdown_started_rx) = oneshot::channel();
+    let max_shutdown_duration = Duration::from_millis(config.max_shutdown_duration_ms);
 
     // Create graceful shutdown signal
-    let graceful_shutdown = async {
+    let graceful_shutdown = async move {
         let ctrl_c = async {
             signal::ctrl_c()
                 .await
@@ -144,16 +381,51 @@ pub async fn run_server_with_graceful_shutdown(
         #[cfg(not(unix))]
         let terminate = std::future::pending::<()>();
 
+        let drained = async {
+            state.shutdown.notified().await;
+            info!("Server drained, initiating graceful shutdown...");
+        };
+
         tokio::select! {
             _ = ctrl_c => {},
             _ = terminate => {},
+            _ = drained => {},
+        }
+        let _ = shutdown_started_tx.send(());
+
+        // Upgraded WebSocket connections outlive the HTTP server, stop their sessions explicitly
+        let timeout = Duration::from_millis(state.config.shutdown_timeout_ms);
+        let report = service::shutdown_all(&state, "server shutting down", timeout).await;
+        if report.killed > 0 {
+            warn!("{} sessions had to be killed on shutdown", report.killed);
+        }
+        if report.timed_out > 0 {
+            warn!(
+                "{} sessions did not finish before shutdown",
+                report.timed_out
+            );
         }
     };
 
-    // Run server with graceful shutdown
-    axum::serve(listener, router)
-        .with_graceful_shutdown(graceful_shutdown)
-        .await?;
+    // Fires once shutdown has taken longer than allowed
+    let shutdown_deadline = async move {
+        if shutdown_started_rx.await.is_err() {
+            return std::future::pending().await;
+        }
+        tokio::time::sleep(max_shutdown_duration).await;
+    };
+
+    // Run server with graceful shutdown, giving up on connections that don't close in time
+    let server = axum::serve(listener, router).with_graceful_shutdown(graceful_shutdown);
+    tokio::select! {
+        result = server => result?,
+        _ = shutdown_deadline => {
+            warn!(
+                "Graceful shutdown took longer than {:?}, dropping remaining connections",
+                max_shutdown_duration
+            );
+        }
+    }
 
     info!("Server shutdown complete");
     Ok(())
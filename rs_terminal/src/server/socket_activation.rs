@@ -0,0 +1,65 @@
+/// systemd socket activation: serving on a listener inherited from the service manager
+use std::io;
+
+use tokio::net::TcpListener;
+
+/// First file descriptor passed by the service manager (`SD_LISTEN_FDS_START`)
+#[cfg(unix)]
+const LISTEN_FDS_START: std::os::fd::RawFd = 3;
+
+/// Variables the service manager describes the passed descriptors with
+#[cfg(unix)]
+const ACTIVATION_VARIABLES: [&str; 3] = ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"];
+
+/// Take the listener passed by socket activation, if the process was started that way
+/// Activation is detected through `LISTEN_PID` (must be this process) and `LISTEN_FDS`;
+/// only the first descriptor is used. The variables are removed once read, so shells started
+/// later don't inherit them.
+/// Call it once at startup, before anything else reads the environment.
+#[cfg(unix)]
+pub fn activated_listener() -> io::Result<Option<TcpListener>> {
+    let listen_pid = std::env::var("LISTEN_PID").ok();
+    let listen_fds = std::env::var("LISTEN_FDS").ok();
+    let (Some(listen_pid), Some(listen_fds)) = (listen_pid, listen_fds) else {
+        return Ok(None);
+    };
+    for variable in ACTIVATION_VARIABLES {
+        // SAFETY: called once at startup, before sessions start shells or otherwise read the
+        // environment from other threads
+        unsafe { std::env::remove_var(variable) };
+    }
+
+    // The variables are inherited by child processes, only the addressed process may use them
+    if listen_pid.trim().parse::<u32>().ok() != Some(std::process::id()) {
+        return Ok(None);
+    }
+    match listen_fds.trim().parse::<u32>() {
+        Ok(0) => Ok(None),
+        Ok(_) => listener_from_fd(LISTEN_FDS_START).map(Some),
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid LISTEN_FDS value: {}", listen_fds),
+        )),
+    }
+}
+
+/// Socket activation is only supported on Unix
+#[cfg(not(unix))]
+pub fn activated_listener() -> io::Result<Option<TcpListener>> {
+    Ok(None)
+}
+
+/// Build a listener from an inherited file descriptor of a listening TCP socket
+/// The descriptor is owned by the returned listener from then on
+#[cfg(unix)]
+pub fn listener_from_fd(fd: std::os::fd::RawFd) -> io::Result<TcpListener> {
+    use std::os::fd::FromRawFd;
+
+    // SAFETY: the service manager passes the descriptor to this process for its exclusive use,
+    // and it is taken only once at startup
+    let listener = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+    // Fails for descriptors that aren't sockets
+    listener.local_addr()?;
+    listener.set_nonblocking(true)?;
+    TcpListener::from_std(listener)
+}
@@ -0,0 +1,81 @@
+/// Supervision of long-running server tasks
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use tracing::{error, warn};
+
+/// Error a supervised task ends with
+pub type TaskError = Box<dyn std::error::Error + Send + Sync>;
+
+/// When and how often a supervised task is restarted
+#[derive(Debug, Clone, Copy)]
+pub struct RestartPolicy {
+    /// Maximum number of consecutive restarts before giving up
+    pub max_restarts: u32,
+    /// Delay before the first restart, doubled on each consecutive failure
+    pub initial_backoff: Duration,
+    /// Upper bound for the restart delay
+    pub max_backoff: Duration,
+    /// A task that stayed up this long is considered healthy again
+    pub stable_uptime: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            stable_uptime: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Run the task `start` returns until it completes, restarting it with backoff when it fails or
+/// panics. Returns the last error once `policy.max_restarts` consecutive restarts failed too.
+pub async fn supervise<F, Fut>(
+    name: &str,
+    policy: RestartPolicy,
+    mut start: F,
+) -> Result<(), TaskError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(), TaskError>> + Send + 'static,
+{
+    let mut restarts = 0;
+    let mut backoff = policy.initial_backoff;
+
+    loop {
+        let started_at = Instant::now();
+        // Run on its own task, so a panic ends the task instead of the supervisor
+        let error = match tokio::spawn(start()).await {
+            Ok(Ok(())) => return Ok(()),
+            Ok(Err(e)) => e,
+            Err(e) => format!("task failed: {}", e).into(),
+        };
+
+        // A long-running task that fails later starts a fresh restart budget
+        if started_at.elapsed() >= policy.stable_uptime {
+            restarts = 0;
+            backoff = policy.initial_backoff;
+        }
+
+        if restarts >= policy.max_restarts {
+            error!(
+                "{} failed {} times in a row, giving up (last error: {})",
+                name,
+                restarts + 1,
+                error
+            );
+            return Err(error);
+        }
+
+        restarts += 1;
+        warn!(
+            "{} exited unexpectedly: {}; restarting in {:?} (attempt {}/{})",
+            name, error, backoff, restarts, policy.max_restarts
+        );
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(policy.max_backoff);
+    }
+}
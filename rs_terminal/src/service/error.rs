@@ -1,4 +1,6 @@
 /// Error types for the service layer
+use crate::protocol::ErrorCode;
+use crate::pty::PtyError;
 use thiserror::Error;
 
 /// Service layer error type
@@ -36,6 +38,10 @@ pub enum ServiceError {
     #[error("PTY creation error: {0}")]
     PtyCreation(String),
 
+    /// Writing client input to the PTY failed
+    #[error("PTY write error: {0}")]
+    PtyWrite(std::io::Error),
+
     /// Resource cleanup error
     #[error("Resource cleanup error: {0}")]
     ResourceCleanup(String),
@@ -48,3 +54,17 @@ pub enum ServiceError {
     #[error("Other error: {0}")]
     Other(String),
 }
+
+impl ServiceError {
+    /// Code of the `error` envelope telling the client why its session closes, for the failures
+    /// of terminal operations (other errors aren't the client's concern)
+    pub fn client_error_code(&self) -> Option<ErrorCode> {
+        match self {
+            ServiceError::PtyCreation(_) | ServiceError::Pty(PtyError::SpawnFailed(_)) => {
+                Some(ErrorCode::SpawnFailed)
+            }
+            ServiceError::PtyWrite(_) => Some(ErrorCode::WriteFailed),
+            _ => None,
+        }
+    }
+}
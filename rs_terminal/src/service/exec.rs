@@ -0,0 +1,161 @@
+/// Headless command execution in a short-lived PTY (`POST /api/exec`)
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use tokio::io::AsyncReadExt;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use super::{ServiceError, spawn_stats_shell_type};
+use crate::{api::dto::TerminalProfile, app_state::AppState, pty::PtyConfig};
+
+/// Time the exit status may take to become available after the output has ended
+const EXIT_STATUS_WAIT: Duration = Duration::from_millis(500);
+
+/// A command to run headlessly
+pub struct ExecCommand {
+    /// Shell type whose program runs the command
+    pub shell_type: String,
+    /// Command line passed to the shell
+    pub command: String,
+    /// Time after which the command is killed
+    pub timeout: Duration,
+    /// Environment variables set by the client (already filtered by the allowlist)
+    pub environment: HashMap<String, String>,
+}
+
+/// Outcome of a headless command
+pub struct ExecOutcome {
+    /// Terminal output, at most `exec_output_limit_bytes`
+    pub output: Vec<u8>,
+    /// Exit code, None when the command was killed or ended by a signal
+    pub exit_code: Option<i32>,
+    /// Signal that ended the command
+    pub signal: Option<String>,
+    pub duration: Duration,
+    /// Output beyond the limit was discarded
+    pub truncated: bool,
+    /// The command was killed after the timeout
+    pub timed_out: bool,
+}
+
+/// Run a command in a fresh PTY and collect its output until it exits or the timeout fires
+/// The shell program of the shell type runs the command non-interactively (its configured
+/// arguments are not used); the PTY is always killed or reaped before returning
+pub async fn run_command(state: &AppState, exec: ExecCommand) -> Result<ExecOutcome, ServiceError> {
+    let shell = state.config.get_shell_config(&exec.shell_type);
+    let program = shell.command.first().cloned().ok_or_else(|| {
+        ServiceError::PtyCreation(format!(
+            "No shell configuration found for shell: {}",
+            exec.shell_type
+        ))
+    })?;
+
+    let pty_config = PtyConfig {
+        args: vec![command_flag(&program).to_string(), exec.command],
+        command: program,
+        cols: shell.size.columns,
+        rows: shell.size.rows,
+        env: state.config.shell_environment(
+            &exec.shell_type,
+            None,
+            &exec.environment,
+            TerminalProfile::Full,
+        ),
+        cwd: shell.working_directory,
+    };
+
+    let started = Instant::now();
+    let deadline = started + exec.timeout;
+    let created = state.pty_factory.create(&pty_config).await;
+    state.pty_spawn_stats.record(
+        state.pty_factory.name(),
+        spawn_stats_shell_type(&state.config, Some(&exec.shell_type)),
+        started.elapsed(),
+        created.is_ok(),
+    );
+    let mut pty = created?;
+
+    let limit = state.config.exec_output_limit_bytes;
+    let mut output = Vec::new();
+    let mut truncated = false;
+    let mut buffer = [0u8; 4096];
+    let timed_out = loop {
+        match tokio::time::timeout_at(deadline, pty.read(&mut buffer)).await {
+            Err(_) => break true,
+            Ok(Ok(0)) => break false,
+            Ok(Ok(n)) => {
+                // Keep reading past the limit so the command isn't blocked on a full PTY
+                let room = limit.saturating_sub(output.len());
+                truncated |= n > room;
+                output.extend_from_slice(&buffer[..n.min(room)]);
+            }
+            Ok(Err(e)) => {
+                let _ = pty.kill().await;
+                return Err(e.into());
+            }
+        }
+    };
+
+    let status = if timed_out {
+        warn!(
+            "Exec command timed out after {:?}, killing it",
+            exec.timeout
+        );
+        let _ = pty.kill().await;
+        None
+    } else {
+        // The child may still be exiting when its output closes
+        let status_deadline = Instant::now() + EXIT_STATUS_WAIT;
+        loop {
+            match pty.try_wait().await {
+                Ok(Some(status)) => break Some(status),
+                Ok(None) if Instant::now() < status_deadline => {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+                _ => {
+                    let _ = pty.kill().await;
+                    break None;
+                }
+            }
+        }
+    };
+
+    let duration = started.elapsed();
+    info!(
+        "Exec command finished in {:?} ({}, {} bytes of output)",
+        duration,
+        status
+            .as_ref()
+            .map_or_else(|| "unknown exit status".to_string(), ToString::to_string),
+        output.len()
+    );
+
+    let (exit_code, signal) = match status {
+        Some(status) => (status.code, status.signal),
+        None => (None, None),
+    };
+    Ok(ExecOutcome {
+        output,
+        exit_code,
+        signal,
+        duration,
+        truncated,
+        timed_out,
+    })
+}
+
+/// Flag that makes a shell program run a command line and exit
+fn command_flag(program: &str) -> &'static str {
+    let name = Path::new(program)
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match name.as_str() {
+        "cmd" => "/C",
+        "powershell" | "pwsh" => "-Command",
+        _ => "-c",
+    }
+}
@@ -0,0 +1,122 @@
+/// Locking of sessions left without input (`lock_after_idle_secs`)
+/// A locked session holds back its output and drops its input until the client authenticates
+/// again as the same user; the shell keeps running
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use axum::http::{Request, header};
+
+use crate::app_state::AppState;
+use crate::auth::AuthError;
+use crate::config::TerminalConfig;
+
+/// Lock state of one connection
+#[derive(Debug)]
+pub struct IdleLock {
+    /// User the connection was authenticated as, only their credentials unlock it
+    user_id: String,
+    /// Time without input after which the session locks, `None` if it never does
+    after: Option<Duration>,
+    /// Latest client input
+    last_input: Instant,
+    locked: bool,
+    /// Output held back while locked, with its sequence numbers, oldest first
+    held_output: VecDeque<(u64, Vec<u8>)>,
+    held_bytes: usize,
+    max_held_bytes: usize,
+    /// Bytes dropped because the held back output exceeded `max_held_bytes`
+    dropped_bytes: usize,
+}
+
+impl IdleLock {
+    /// Create the lock state of a connection of `user_id` that starts at `now`
+    pub fn new(config: &TerminalConfig, user_id: String, now: Instant) -> Self {
+        Self {
+            user_id,
+            after: config.lock_after_idle_secs.map(Duration::from_secs),
+            last_input: now,
+            locked: false,
+            held_output: VecDeque::new(),
+            held_bytes: 0,
+            max_held_bytes: config.lock_buffer_bytes,
+            dropped_bytes: 0,
+        }
+    }
+
+    /// Whether sessions lock at all
+    pub fn is_enabled(&self) -> bool {
+        self.after.is_some()
+    }
+
+    /// User whose credentials unlock the session
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Seconds without input after which the session locks
+    pub fn idle_secs(&self) -> u64 {
+        self.after.map_or(0, |after| after.as_secs())
+    }
+
+    /// Record client input
+    pub fn input(&mut self, now: Instant) {
+        self.last_input = now;
+    }
+
+    /// Lock the session if it has been without input for too long, true if it was locked now
+    pub fn lock_if_idle(&mut self, now: Instant) -> bool {
+        let idle = self
+            .after
+            .is_some_and(|after| now.saturating_duration_since(self.last_input) >= after);
+        if self.locked || !idle {
+            return false;
+        }
+        self.locked = true;
+        true
+    }
+
+    /// Hold back output of a locked session, dropping the oldest output beyond the limit
+    pub fn hold(&mut self, seq: u64, data: &[u8]) {
+        self.held_output.push_back((seq, data.to_vec()));
+        self.held_bytes += data.len();
+        while self.held_bytes > self.max_held_bytes {
+            let Some((_, oldest)) = self.held_output.pop_front() else {
+                break;
+            };
+            self.held_bytes -= oldest.len();
+            self.dropped_bytes += oldest.len();
+        }
+    }
+
+    /// Unlock the session, returning the output held back and how many bytes were dropped
+    pub fn unlock(&mut self, now: Instant) -> (Vec<(u64, Vec<u8>)>, usize) {
+        self.locked = false;
+        self.last_input = now;
+        self.held_bytes = 0;
+        let dropped = std::mem::take(&mut self.dropped_bytes);
+        (self.held_output.drain(..).collect(), dropped)
+    }
+}
+
+/// Check the token of an `unlock` envelope: it has to authenticate the user the connection was
+/// authenticated as
+pub async fn verify_unlock(state: &AppState, token: &str, user_id: &str) -> Result<(), String> {
+    let (parts, ()) = Request::builder()
+        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+        .body(())
+        .map_err(|_| "malformed token".to_string())?
+        .into_parts();
+    let auth = state
+        .auth
+        .authenticate(&parts)
+        .await
+        .map_err(|e: AuthError| e.to_string())?;
+    if auth.user_id != user_id {
+        return Err(format!("the token is not for user {}", user_id));
+    }
+    Ok(())
+}
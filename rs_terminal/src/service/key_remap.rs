@@ -0,0 +1,57 @@
+/// Key remapping of client input, for clients that can't send certain keys
+/// A remap table replaces input sequences (e.g. a placeholder like `<C-c>`) with the sequence
+/// written to the PTY instead (e.g. `\u{3}`)
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Remap table of a session
+#[derive(Debug, Clone)]
+pub struct KeyRemap {
+    /// (sequence, replacement), longest sequences first so they win over their prefixes
+    rules: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl KeyRemap {
+    /// Build a remap table from a `key_remaps` entry (empty sequences are ignored)
+    pub fn new(table: &HashMap<String, String>) -> Self {
+        let mut rules: Vec<(Vec<u8>, Vec<u8>)> = table
+            .iter()
+            .filter(|(from, _)| !from.is_empty())
+            .map(|(from, to)| (from.as_bytes().to_vec(), to.as_bytes().to_vec()))
+            .collect();
+        rules.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.0.cmp(&b.0)));
+        Self { rules }
+    }
+
+    /// Replace the mapped sequences in a message of input
+    /// Replacements are not remapped again, and a sequence split across two messages is not
+    /// recognized (clients send a key press in one message)
+    pub fn apply<'a>(&self, input: &'a [u8]) -> Cow<'a, [u8]> {
+        let mut output: Option<Vec<u8>> = None;
+        let mut pos = 0;
+        let mut copied = 0;
+        while pos < input.len() {
+            let rule = self
+                .rules
+                .iter()
+                .find(|(from, _)| input[pos..].starts_with(from));
+            match rule {
+                Some((from, to)) => {
+                    let output = output.get_or_insert_with(|| Vec::with_capacity(input.len()));
+                    output.extend_from_slice(&input[copied..pos]);
+                    output.extend_from_slice(to);
+                    pos += from.len();
+                    copied = pos;
+                }
+                None => pos += 1,
+            }
+        }
+        match output {
+            Some(mut output) => {
+                output.extend_from_slice(&input[copied..]);
+                Cow::Owned(output)
+            }
+            None => Cow::Borrowed(input),
+        }
+    }
+}
@@ -0,0 +1,70 @@
+/// Line framing of terminal output (`frame_mode = "lines"`), for clients that handle one line per
+/// message: output is buffered until a newline and sent as one frame per line
+/// A line longer than the cap is sent in pieces of at most the cap's size, not splitting UTF-8
+/// characters
+use std::mem;
+
+/// Output of a session not yet ending in a newline
+#[derive(Debug)]
+pub struct LineFramer {
+    /// Start of the current line
+    partial: Vec<u8>,
+    /// Most bytes of a frame
+    max_line_bytes: usize,
+}
+
+impl LineFramer {
+    /// Create a framer sending lines of at most `max_line_bytes` (at least 4, the longest
+    /// character) per frame
+    pub fn new(max_line_bytes: usize) -> Self {
+        Self {
+            partial: Vec::new(),
+            max_line_bytes: max_line_bytes.max(4),
+        }
+    }
+
+    /// Add output, returning the frames completed by it (each line including its newline)
+    pub fn push(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        let mut frames = Vec::new();
+        for &byte in data {
+            self.partial.push(byte);
+            if byte == b'\n' {
+                frames.push(mem::take(&mut self.partial));
+            } else if self.partial.len() >= self.max_line_bytes {
+                // An incomplete character at the end moves on to the next piece
+                let cut = complete_utf8_len(&self.partial);
+                let rest = self.partial.split_off(cut);
+                frames.push(mem::replace(&mut self.partial, rest));
+            }
+        }
+        frames
+    }
+
+    /// Take the start of a line still waiting for its newline (e.g. when the shell exited)
+    pub fn flush(&mut self) -> Option<Vec<u8>> {
+        (!self.partial.is_empty()).then(|| mem::take(&mut self.partial))
+    }
+}
+
+/// Length of `data` without a UTF-8 character cut off at its end
+fn complete_utf8_len(data: &[u8]) -> usize {
+    // A character is at most 4 bytes, look for the start of the last one
+    for back in 1..=data.len().min(4) {
+        let byte = data[data.len() - back];
+        let width = match byte {
+            0x00..=0x7f => 1,
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            // Continuation byte, keep looking
+            0x80..=0xbf => continue,
+            _ => return data.len(),
+        };
+        return if back < width {
+            data.len() - back
+        } else {
+            data.len()
+        };
+    }
+    data.len()
+}
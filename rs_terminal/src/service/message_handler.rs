@@ -1,19 +1,386 @@
-use super::ServiceError;
+use super::{KeyRemap, LineFramer, OutputFilter, ServiceError};
 /// Message handler for processing terminal messages
 use crate::{
-    protocol::{TerminalConnection, TerminalMessage},
+    api::dto::TerminalProfile,
+    app_state::{AppState, Clock, ScrollbackStore, SystemClock},
+    config::{StalledInputPolicy, TerminalConfig},
+    protocol::{
+        ClientEnvelope, ErrorCode, NoticeLevel, ServerEnvelope, Subprotocol, TerminalConnection,
+        TerminalMessage,
+    },
     pty::AsyncPty,
 };
+use serde_json::error::Category;
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::io::AsyncWriteExt;
-use tracing::{debug, error, info};
+use tracing::{Instrument, debug, debug_span, error, info, warn};
+
+/// Control frames (ping/pong) a client may send in a burst
+const CONTROL_FRAME_BURST: f64 = 20.0;
+
+/// Sustained rate of control frames per second a client may send
+const CONTROL_FRAMES_PER_SECOND: f64 = 10.0;
+
+/// Consecutive control frames over the rate limit after which the session is closed
+const CONTROL_FRAME_FLOOD_LIMIT: u32 = 200;
+
+/// Token bucket for control frames of one connection
+///
+/// axum answers a ping with a pong on its own before the frame reaches the session, so the pongs
+/// a flooding client gets can't be throttled here; the limiter only drops the frames (and the
+/// work they would cause) and closes the session once the flood goes on
+struct ControlFrameLimiter {
+    /// Time source the bucket refills by
+    clock: Arc<dyn Clock>,
+    tokens: f64,
+    last_refill: Instant,
+    /// Control frames dropped since the last one that was within the limit
+    dropped: u32,
+}
+
+/// What to do with a control frame
+enum ControlFrameVerdict {
+    /// Within the rate limit
+    Allow,
+    /// Over the rate limit, drop it (the first drop of a streak is logged)
+    Drop { first: bool },
+    /// The flood went on for too long, close the session
+    Reject,
+}
+
+impl ControlFrameLimiter {
+    fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            tokens: CONTROL_FRAME_BURST,
+            last_refill: clock.now_instant(),
+            dropped: 0,
+            clock,
+        }
+    }
+
+    fn check(&mut self) -> ControlFrameVerdict {
+        let now = self.clock.now_instant();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * CONTROL_FRAMES_PER_SECOND).min(CONTROL_FRAME_BURST);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            self.dropped = 0;
+            return ControlFrameVerdict::Allow;
+        }
+
+        self.dropped += 1;
+        if self.dropped >= CONTROL_FRAME_FLOOD_LIMIT {
+            ControlFrameVerdict::Reject
+        } else {
+            ControlFrameVerdict::Drop {
+                first: self.dropped == 1,
+            }
+        }
+    }
+}
+
+/// Notice sent when the terminal stops accepting input
+const STALLED_INPUT_NOTICE: &str = "Terminal not accepting input (process stopped?)";
+
+/// Input the terminal didn't accept in time (a stopped process with a full input queue)
+pub struct StalledInput {
+    /// How long writing input to the PTY may take
+    timeout: Duration,
+    /// What happens to input the terminal didn't accept
+    policy: StalledInputPolicy,
+    /// Most bytes kept with `StalledInputPolicy::Buffer`
+    limit: usize,
+    /// Input kept for the terminal, written ahead of the next input
+    pending: Vec<u8>,
+    /// Whether the client was told the terminal isn't accepting input
+    reported: bool,
+}
+
+impl StalledInput {
+    /// Apply `pty_write_timeout_ms`, `stalled_input` and `stalled_input_buffer_bytes`
+    pub fn new(config: &TerminalConfig) -> Self {
+        Self {
+            timeout: Duration::from_millis(config.pty_write_timeout_ms),
+            policy: config.stalled_input,
+            limit: config.stalled_input_buffer_bytes,
+            pending: Vec::new(),
+            reported: false,
+        }
+    }
+
+    /// Keep or drop input the terminal didn't accept, according to the policy
+    fn keep(&mut self, mut input: Vec<u8>, session_id: &str) {
+        if self.policy == StalledInputPolicy::Drop {
+            debug!(
+                "Dropping {} bytes of input to stalled session {}",
+                input.len(),
+                session_id
+            );
+            return;
+        }
+        if input.len() > self.limit {
+            debug!(
+                "Dropping {} bytes of input to stalled session {} over the buffer limit",
+                input.len() - self.limit,
+                session_id
+            );
+            input.truncate(self.limit);
+        }
+        self.pending = input;
+    }
+}
+
+/// Describe why an envelope could not be parsed, without quoting the input
+fn envelope_error_summary(e: &serde_json::Error) -> String {
+    let kind = match e.classify() {
+        Category::Syntax => "malformed JSON",
+        Category::Eof => "truncated JSON",
+        Category::Data => "unknown message type or invalid fields",
+        Category::Io => "I/O error",
+    };
+    // Errors found after the envelope was buffered (e.g. by its type tag) carry no position
+    if e.line() == 0 {
+        kind.to_string()
+    } else {
+        format!("{} at line {} column {}", kind, e.line(), e.column())
+    }
+}
+
+/// Split text into pieces whose JSON string encoding takes at most `budget` bytes,
+/// without breaking UTF-8 sequences (a piece holds at least one character)
+fn split_for_envelope(text: &str, budget: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut size = 0;
+    for (index, c) in text.char_indices() {
+        // How serde_json escapes the character
+        let escaped = match c {
+            '"' | '\\' | '\n' | '\r' | '\t' | '\u{8}' | '\u{c}' => 2,
+            c if (c as u32) < 0x20 => 6,
+            c => c.len_utf8(),
+        };
+        if size + escaped > budget && index > start {
+            pieces.push(&text[start..index]);
+            start = index;
+            size = 0;
+        }
+        size += escaped;
+    }
+    pieces.push(&text[start..]);
+    pieces
+}
+
+/// Render a notice as a terminal line on its own: bold, colored by level
+/// Control characters are removed so the message can't carry escape sequences
+pub fn render_notice(level: NoticeLevel, message: &str) -> String {
+    let color = match level {
+        NoticeLevel::Info => "36",
+        NoticeLevel::Warning => "33",
+        _ => "31",
+    };
+    let message = strip_control_characters(message);
+    format!(
+        "\r\n\x1b[1;{}m[{}] {}\x1b[0m\r\n",
+        color,
+        level.as_str(),
+        message
+    )
+}
+
+/// Remove control characters (including ESC) from text shown in a terminal
+fn strip_control_characters(text: &str) -> String {
+    text.chars().filter(|c| !c.is_control()).collect()
+}
+
+/// Sessions a connection may list with `list_sessions` envelopes
+pub struct SessionListScope {
+    state: AppState,
+    /// Only the sessions of this user are listed
+    user_id: String,
+}
+
+impl SessionListScope {
+    /// Allow listing the sessions of `user_id`
+    pub fn new(state: AppState, user_id: String) -> Self {
+        Self { state, user_id }
+    }
+}
 
 /// Message handler responsible for processing terminal messages
-pub struct MessageHandler;
+pub struct MessageHandler {
+    control_frames: Mutex<ControlFrameLimiter>,
+    /// Drop all client input (view-only session), output still flows
+    read_only: bool,
+    /// Terminal profile of the client
+    terminal_profile: TerminalProfile,
+    /// Removes the escape sequences the client's terminal profile can't display
+    output_filter: Mutex<Option<OutputFilter>>,
+    /// Replaces input sequences before they are written to the PTY
+    key_remap: Option<KeyRemap>,
+    /// Sessions `list_sessions` answers with, `None` if listing is disabled
+    session_list: Option<SessionListScope>,
+    /// Splits the output into one frame per line (`frame_mode = "lines"`) and the sequence number
+    /// of the latest output
+    line_framer: Mutex<Option<(LineFramer, u64)>>,
+    /// Scrollback `clear_scrollback` empties
+    scrollback: Arc<ScrollbackStore>,
+    /// Input waiting for a terminal that stopped accepting it
+    stalled_input: Mutex<StalledInput>,
+    /// The client's hello asked for the input latency probe
+    latency_probe: AtomicBool,
+}
 
 impl MessageHandler {
-    /// Create a new message handler
-    pub fn new() -> Self {
-        Self
+    /// Create a new message handler (one per connection, it rate limits the connection's control
+    /// frames and filters the output for the connection's terminal profile)
+    pub fn new(
+        read_only: bool,
+        terminal_profile: TerminalProfile,
+        key_remap: Option<KeyRemap>,
+        session_list: Option<SessionListScope>,
+        line_framer: Option<LineFramer>,
+        scrollback: Arc<ScrollbackStore>,
+        stalled_input: StalledInput,
+    ) -> Self {
+        Self {
+            control_frames: Mutex::new(ControlFrameLimiter::new(Arc::new(SystemClock::new()))),
+            read_only,
+            terminal_profile,
+            output_filter: Mutex::new(OutputFilter::new(terminal_profile)),
+            key_remap,
+            session_list,
+            line_framer: Mutex::new(line_framer.map(|framer| (framer, 0))),
+            scrollback,
+            stalled_input: Mutex::new(stalled_input),
+            latency_probe: AtomicBool::new(false),
+        }
+    }
+
+    /// Rate limit control frames by `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.control_frames = Mutex::new(ControlFrameLimiter::new(clock));
+        self
+    }
+
+    /// Whether the client asked for its input latency to be measured
+    /// The server's `input_latency_probe` decides whether that is done
+    pub fn latency_probe_requested(&self) -> bool {
+        self.latency_probe.load(Ordering::Acquire)
+    }
+
+    /// Apply the session's key remap table to input
+    fn remap_input<'a>(&self, input: &'a [u8]) -> Cow<'a, [u8]> {
+        match &self.key_remap {
+            Some(key_remap) => key_remap.apply(input),
+            None => Cow::Borrowed(input),
+        }
+    }
+
+    /// Write all of the input to the PTY after applying the key remap table, behind the input
+    /// kept while the terminal wasn't accepting any
+    /// Returns false if the terminal didn't take the input within `pty_write_timeout_ms`: the
+    /// client is told once per stall and the input is handled by the `stalled_input` policy
+    async fn write_input(
+        &self,
+        pty: &mut Box<dyn AsyncPty>,
+        input: &[u8],
+        connection: &mut impl TerminalConnection,
+        session_id: &str,
+    ) -> Result<bool, ServiceError> {
+        let input = self.remap_input(input);
+        let (data, timeout, stalled) = {
+            let mut stalled = self.stalled_input.lock().unwrap_or_else(|e| e.into_inner());
+            let mut data = std::mem::take(&mut stalled.pending);
+            data.extend_from_slice(&input);
+            (data, stalled.timeout, stalled.reported)
+        };
+        // Nothing is written for empty input, backends differ in how they handle empty writes
+        if data.is_empty() {
+            return Ok(true);
+        }
+
+        // The PTY takes the data as a whole once its previous write is done, the flush waits for
+        // the data to be written; a write still running when the time is up goes on by itself.
+        // While stalled, the input is only checked against the PTY instead of waiting each time
+        let accept_timeout = if stalled { Duration::ZERO } else { timeout };
+        let (accepted, written) = async {
+            let accepted = Self::within(accept_timeout, pty.write_all(&data), session_id).await?;
+            let written = accepted && Self::within(timeout, pty.flush(), session_id).await?;
+            if written {
+                debug!("Wrote input to PTY for session {}", session_id);
+            }
+            Ok::<_, ServiceError>((accepted, written))
+        }
+        .instrument(debug_span!("pty_write", bytes = data.len()))
+        .await?;
+
+        let report = {
+            let mut stalled = self.stalled_input.lock().unwrap_or_else(|e| e.into_inner());
+            if written {
+                stalled.reported = false;
+                return Ok(true);
+            }
+            if !accepted {
+                stalled.keep(data, session_id);
+            }
+            !std::mem::replace(&mut stalled.reported, true)
+        };
+        if report {
+            warn!("Session {} is not accepting input", session_id);
+            self.send_notice(NoticeLevel::Warning, STALLED_INPUT_NOTICE, connection)
+                .await?;
+        }
+        Ok(false)
+    }
+
+    /// Write the newline making the shell print a prompt for a new client, given up on after
+    /// `pty_write_timeout_ms` like input
+    /// Returns false if the terminal didn't take it in time, it isn't kept for later
+    pub async fn nudge(
+        &self,
+        pty: &mut Box<dyn AsyncPty>,
+        session_id: &str,
+    ) -> Result<bool, ServiceError> {
+        let timeout = self
+            .stalled_input
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .timeout;
+        let written = Self::within(timeout, pty.write_all(b"\n"), session_id).await?
+            && Self::within(timeout, pty.flush(), session_id).await?;
+        Ok(written)
+    }
+
+    /// Run a PTY write, false if it didn't finish within `timeout`
+    async fn within(
+        timeout: Duration,
+        write: impl Future<Output = std::io::Result<()>>,
+        session_id: &str,
+    ) -> Result<bool, ServiceError> {
+        match tokio::time::timeout(timeout, write).await {
+            Ok(Ok(())) => Ok(true),
+            Ok(Err(e)) => {
+                error!(
+                    "Failed to write input to PTY for session {}: {}",
+                    session_id, e
+                );
+                Err(ServiceError::PtyWrite(e))
+            }
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Whether input must be dropped, logged per message
+    fn drops_input(&self, session_id: &str) -> bool {
+        if self.read_only {
+            debug!("Dropping input to read-only session {}", session_id);
+        }
+        self.read_only
     }
 
     /// Handle a terminal message
@@ -24,6 +391,35 @@ impl MessageHandler {
         pty: &mut Box<dyn AsyncPty>,
         session_id: &str,
     ) -> Result<bool, ServiceError> {
+        if matches!(message, TerminalMessage::Ping(_) | TerminalMessage::Pong(_)) {
+            match self.check_control_frame() {
+                ControlFrameVerdict::Allow => {}
+                ControlFrameVerdict::Drop { first } => {
+                    if first {
+                        warn!(
+                            "Session {} sends more than {} control frames per second, dropping them",
+                            session_id, CONTROL_FRAMES_PER_SECOND
+                        );
+                    }
+                    return Ok(false);
+                }
+                ControlFrameVerdict::Reject => {
+                    warn!(
+                        "Closing session {} after {} control frames over the rate limit",
+                        session_id, CONTROL_FRAME_FLOOD_LIMIT
+                    );
+                    let _ = self
+                        .send_error(
+                            ErrorCode::RateLimited,
+                            "Too many ping frames, closing the connection",
+                            connection,
+                        )
+                        .await;
+                    return Ok(true);
+                }
+            }
+        }
+
         match message {
             TerminalMessage::Text(text) => {
                 self.handle_text_message(text, connection, pty, session_id)
@@ -33,9 +429,25 @@ impl MessageHandler {
                 self.handle_binary_message(bin, connection, pty, session_id)
                     .await
             }
-            TerminalMessage::Ping(_) => self.handle_ping_message(connection, session_id).await,
+            TerminalMessage::Ping(_) => self.handle_ping_message(session_id).await,
             TerminalMessage::Pong(_) => self.handle_pong_message(session_id).await,
             TerminalMessage::Close => self.handle_close_message(connection, session_id).await,
+            other => {
+                debug!(
+                    "Ignoring unsupported message from session {}: {:?}",
+                    session_id, other
+                );
+                Ok(false)
+            }
+        }
+    }
+
+    /// Check a ping or pong frame against the connection's rate limit
+    fn check_control_frame(&self) -> ControlFrameVerdict {
+        match self.control_frames.lock() {
+            Ok(mut limiter) => limiter.check(),
+            // A panic while holding the lock can't leave the limiter in a harmful state
+            Err(poisoned) => poisoned.into_inner().check(),
         }
     }
 
@@ -43,36 +455,197 @@ impl MessageHandler {
     async fn handle_text_message(
         &self,
         text: String,
-        _connection: &mut impl TerminalConnection,
+        connection: &mut impl TerminalConnection,
         pty: &mut Box<dyn AsyncPty>,
         session_id: &str,
     ) -> Result<bool, ServiceError> {
+        // Input may contain passwords, only its size is logged
         debug!(
-            "Received text message from session {}: {}",
-            session_id, text
+            "Received text message from session {} of length {}",
+            session_id,
+            text.len()
         );
 
+        // Empty frames keep the connection alive, they aren't input (nor envelopes)
+        if text.is_empty() {
+            return Ok(false);
+        }
+
+        if connection.subprotocol() == Subprotocol::V1 {
+            return self
+                .handle_envelope_message(&text, connection, pty, session_id)
+                .await;
+        }
+
+        if self.drops_input(session_id) {
+            return Ok(false);
+        }
+
         // 处理转义的换行符 - 将字符串中的 "\n" 替换为实际的换行符字节
         let processed_text = text.replace("\\n", "\n");
 
         // Write the processed text to PTY (non-blocking async)
-        match pty.write(processed_text.as_bytes()).await {
-            Ok(_) => Ok(false),
+        self.write_input(pty, processed_text.as_bytes(), connection, session_id)
+            .await?;
+        Ok(false)
+    }
+
+    /// Handle a JSON envelope of the `waylon-terminal-v1` subprotocol
+    /// Malformed envelopes are reported to the client without closing the session
+    async fn handle_envelope_message(
+        &self,
+        text: &str,
+        connection: &mut impl TerminalConnection,
+        pty: &mut Box<dyn AsyncPty>,
+        session_id: &str,
+    ) -> Result<bool, ServiceError> {
+        let envelope = match serde_json::from_str::<ClientEnvelope>(text) {
+            Ok(envelope) => envelope,
             Err(e) => {
-                error!(
-                    "Failed to write text to PTY for session {}: {}",
-                    session_id, e
+                // serde errors quote parts of the input, only the client gets to see them
+                warn!(
+                    "Invalid message from session {}: {}",
+                    session_id,
+                    envelope_error_summary(&e)
+                );
+                self.send_error(
+                    ErrorCode::InvalidMessage,
+                    &format!("Invalid message: {}", e),
+                    connection,
+                )
+                .await?;
+                return Ok(false);
+            }
+        };
+
+        match envelope {
+            ClientEnvelope::Input { .. } if self.drops_input(session_id) => {}
+            ClientEnvelope::Input { data, id } => {
+                let written = self
+                    .write_input(pty, data.as_bytes(), connection, session_id)
+                    .await?;
+                // Input the terminal didn't take gets no ack
+                if let Some(id) = id.filter(|_| written) {
+                    let text = serde_json::to_string(&ServerEnvelope::Ack { id })
+                        .map_err(|e| ServiceError::MessageHandling(e.to_string()))?;
+                    connection.send_text(&text).await?;
+                }
+            }
+            ClientEnvelope::Resize { columns, rows } if columns == 0 || rows == 0 => {
+                warn!(
+                    "Invalid resize from session {}: {}x{}",
+                    session_id, columns, rows
+                );
+                self.send_error(
+                    ErrorCode::InvalidMessage,
+                    "Invalid message: terminal size must be at least 1x1",
+                    connection,
+                )
+                .await?;
+            }
+            ClientEnvelope::Resize { columns, rows } => {
+                info!(
+                    "Resizing PTY for session {} to {}x{}",
+                    session_id, columns, rows
+                );
+                if let Err(e) = pty.resize(columns, rows).await {
+                    // The terminal keeps its previous size, the session goes on
+                    warn!("Failed to resize PTY for session {}: {}", session_id, e);
+                    self.send_error(
+                        ErrorCode::ResizeFailed,
+                        &format!("Failed to resize the terminal: {}", e),
+                        connection,
+                    )
+                    .await?;
+                }
+            }
+            ClientEnvelope::ListSessions => {
+                self.handle_list_sessions(connection, session_id).await?;
+            }
+            ClientEnvelope::ClearScrollback if self.drops_input(session_id) => {}
+            ClientEnvelope::ClearScrollback => {
+                if matches!(self.check_control_frame(), ControlFrameVerdict::Allow) {
+                    self.scrollback.clear(session_id).await;
+                } else {
+                    debug!(
+                        "Dropping scrollback clear of session {} over the rate limit",
+                        session_id
+                    );
+                }
+            }
+            // Locked sessions handle unlocking before messages get here; the token isn't logged
+            ClientEnvelope::Unlock { .. } => {
+                debug!("Ignoring unlock of session {}, it isn't locked", session_id);
+            }
+            ClientEnvelope::Hello { latency_probe } => {
+                debug!(
+                    "Client of session {} sent hello (latency probe: {})",
+                    session_id, latency_probe
+                );
+                self.latency_probe.store(latency_probe, Ordering::Release);
+            }
+            other => {
+                debug!(
+                    "Ignoring unsupported message from session {}: {:?}",
+                    session_id, other
                 );
-                Err(ServiceError::Other(e.to_string()))
             }
         }
+        Ok(false)
+    }
+
+    /// Answer a `list_sessions` envelope with the sessions of the connection's user
+    /// Requests count against the control frame rate limit, requests over it are dropped
+    async fn handle_list_sessions(
+        &self,
+        connection: &mut impl TerminalConnection,
+        session_id: &str,
+    ) -> Result<(), ServiceError> {
+        let Some(scope) = &self.session_list else {
+            self.send_error(
+                ErrorCode::InvalidMessage,
+                "Invalid message: listing sessions is disabled",
+                connection,
+            )
+            .await?;
+            return Ok(());
+        };
+        if !matches!(self.check_control_frame(), ControlFrameVerdict::Allow) {
+            debug!(
+                "Dropping session list request of session {} over the rate limit",
+                session_id
+            );
+            return Ok(());
+        }
+
+        let sessions: Vec<_> = scope
+            .state
+            .get_all_sessions()
+            .await
+            .into_iter()
+            .filter(|session| session.user_id == scope.user_id)
+            .collect();
+        debug!(
+            "Listing {} sessions for session {}",
+            sessions.len(),
+            session_id
+        );
+        let envelope = ServerEnvelope::Sessions {
+            sessions: scope.state.session_responses(sessions).await,
+        };
+        let text = serde_json::to_string(&envelope)
+            .map_err(|e| ServiceError::MessageHandling(e.to_string()))?;
+        connection
+            .send_text(&text)
+            .await
+            .map_err(ServiceError::Connection)
     }
 
     /// Handle a binary message
     async fn handle_binary_message(
         &self,
         bin: Vec<u8>,
-        _connection: &mut impl TerminalConnection,
+        connection: &mut impl TerminalConnection,
         pty: &mut Box<dyn AsyncPty>,
         session_id: &str,
     ) -> Result<bool, ServiceError> {
@@ -82,38 +655,26 @@ impl MessageHandler {
             bin.len()
         );
 
-        // Write binary data to PTY directly (non-blocking async)
-        match pty.write(&bin).await {
-            Ok(_) => Ok(false),
-            Err(e) => {
-                error!(
-                    "Failed to write binary data to PTY for session {}: {}",
-                    session_id, e
-                );
-                Err(ServiceError::Other(e.to_string()))
-            }
+        // Empty frames keep the connection alive, they aren't input
+        if bin.is_empty() {
+            return Ok(false);
+        }
+
+        if self.drops_input(session_id) {
+            return Ok(false);
         }
+
+        // Write binary data to PTY directly (non-blocking async)
+        self.write_input(pty, &bin, connection, session_id).await?;
+        Ok(false)
     }
 
     /// Handle a ping message
-    async fn handle_ping_message(
-        &self,
-        connection: &mut impl TerminalConnection,
-        session_id: &str,
-    ) -> Result<bool, ServiceError> {
+    /// The WebSocket layer answers pings with a pong frame by itself; a text answer would end up
+    /// in the client's terminal output
+    async fn handle_ping_message(&self, session_id: &str) -> Result<bool, ServiceError> {
         debug!("Received ping from session {}", session_id);
-
-        // Respond with pong
-        match connection.send_text(&"Pong").await {
-            Ok(_) => Ok(false),
-            Err(e) => {
-                error!(
-                    "Failed to send pong response to session {}: {}",
-                    session_id, e
-                );
-                Err(ServiceError::Connection(e))
-            }
-        }
+        Ok(false)
     }
 
     /// Handle a pong message
@@ -134,10 +695,143 @@ impl MessageHandler {
         Ok(true)
     }
 
+    /// Send a server notice to the client
+    /// `waylon-terminal-v1` clients receive a notice envelope, raw clients a colored line;
+    /// control characters are removed from the message in both cases
+    pub async fn send_notice(
+        &self,
+        level: NoticeLevel,
+        message: &str,
+        connection: &mut impl TerminalConnection,
+    ) -> Result<(), ServiceError> {
+        let text = if connection.subprotocol() == Subprotocol::V1 {
+            let envelope = ServerEnvelope::Notice {
+                level,
+                message: strip_control_characters(message),
+            };
+            serde_json::to_string(&envelope)
+                .map_err(|e| ServiceError::MessageHandling(e.to_string()))?
+        } else {
+            let text = render_notice(level, message);
+            match OutputFilter::new(self.terminal_profile) {
+                Some(mut filter) => {
+                    String::from_utf8_lossy(&filter.filter(text.as_bytes())).into_owned()
+                }
+                None => text,
+            }
+        };
+        connection
+            .send_text(&text)
+            .await
+            .map_err(ServiceError::Connection)
+    }
+
+    /// Tell the client that a terminal operation failed: `waylon-terminal-v1` clients get an
+    /// `error` envelope with the code, other clients an `Error:` text frame
+    pub async fn send_error(
+        &self,
+        code: ErrorCode,
+        message: &str,
+        connection: &mut impl TerminalConnection,
+    ) -> Result<(), ServiceError> {
+        let text = if connection.subprotocol() == Subprotocol::V1 {
+            let envelope = ServerEnvelope::Error {
+                code,
+                message: message.to_string(),
+            };
+            serde_json::to_string(&envelope)
+                .map_err(|e| ServiceError::MessageHandling(e.to_string()))?
+        } else {
+            format!("Error: {}", message)
+        };
+        connection
+            .send_text(&text)
+            .await
+            .map_err(ServiceError::Connection)
+    }
+
+    /// Send the hello envelope to `waylon-terminal-v1` clients (other clients get nothing)
+    pub async fn send_hello(
+        &self,
+        connection: &mut impl TerminalConnection,
+    ) -> Result<(), ServiceError> {
+        if connection.subprotocol() != Subprotocol::V1 {
+            return Ok(());
+        }
+        let envelope = ServerEnvelope::Hello {
+            max_message_bytes: connection.max_message_bytes().map(|limit| limit as u64),
+        };
+        let text = serde_json::to_string(&envelope)
+            .map_err(|e| ServiceError::MessageHandling(e.to_string()))?;
+        connection
+            .send_text(&text)
+            .await
+            .map_err(ServiceError::Connection)
+    }
+
+    /// Tell the client its session was locked after `idle_secs` without input, or unlocked (`None`)
+    /// `waylon-terminal-v1` clients get a `locked` or `unlocked` envelope, raw clients a notice
+    pub async fn send_lock_state(
+        &self,
+        idle_secs: Option<u64>,
+        connection: &mut impl TerminalConnection,
+    ) -> Result<(), ServiceError> {
+        if connection.subprotocol() != Subprotocol::V1 {
+            // Raw clients can't send an unlock envelope, they reconnect with their credentials
+            let message = match idle_secs {
+                Some(idle_secs) => format!(
+                    "Session locked after {} seconds without input, reconnect to unlock it",
+                    idle_secs
+                ),
+                None => "Session unlocked".to_string(),
+            };
+            return self
+                .send_notice(NoticeLevel::Warning, &message, connection)
+                .await;
+        }
+        let envelope = match idle_secs {
+            Some(idle_secs) => ServerEnvelope::Locked { idle_secs },
+            None => ServerEnvelope::Unlocked,
+        };
+        let text = serde_json::to_string(&envelope)
+            .map_err(|e| ServiceError::MessageHandling(e.to_string()))?;
+        connection
+            .send_text(&text)
+            .await
+            .map_err(ServiceError::Connection)
+    }
+
+    /// Serialize output envelopes, splitting the output so each fits the connection's limit
+    fn output_envelopes(
+        data: &str,
+        seq: u64,
+        max_message_bytes: Option<usize>,
+    ) -> Result<Vec<String>, ServiceError> {
+        let encode = |data: &str| {
+            serde_json::to_string(&ServerEnvelope::Output {
+                seq,
+                data: data.to_string(),
+            })
+            .map_err(|e| ServiceError::MessageHandling(e.to_string()))
+        };
+        let text = encode(data)?;
+        let Some(limit) = max_message_bytes.filter(|limit| text.len() > *limit) else {
+            return Ok(vec![text]);
+        };
+        // A piece must be able to hold the longest escaped character
+        let budget = limit.saturating_sub(encode("")?.len()).max(6);
+        split_for_envelope(data, budget)
+            .into_iter()
+            .map(encode)
+            .collect()
+    }
+
     /// Handle PTY output
+    /// `waylon-terminal-v1` clients receive it in an envelope with its sequence number
     pub async fn handle_pty_output(
         &self,
         data: &[u8],
+        seq: u64,
         connection: &mut impl TerminalConnection,
         session_id: &str,
     ) -> Result<(), ServiceError> {
@@ -147,8 +841,86 @@ impl MessageHandler {
             String::from_utf8_lossy(data)
         );
 
+        let filtered = self
+            .output_filter
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_mut()
+            .map(|filter| filter.filter(data));
+        let data = filtered.as_deref().unwrap_or(data);
+
+        let lines = self
+            .line_framer
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_mut()
+            .map(|(framer, latest_seq)| {
+                *latest_seq = seq;
+                framer.push(data)
+            });
+        match lines {
+            // Nothing is sent until a line is complete
+            Some(lines) => {
+                for line in lines {
+                    self.send_output(&line, seq, connection, session_id).await?;
+                }
+                Ok(())
+            }
+            None => self.send_output(data, seq, connection, session_id).await,
+        }
+    }
+
+    /// Send the start of a line still waiting for its newline (line framing only)
+    /// Called when the output ends, so the last line isn't lost
+    pub async fn flush_pty_output(
+        &self,
+        connection: &mut impl TerminalConnection,
+        session_id: &str,
+    ) -> Result<(), ServiceError> {
+        let partial = self
+            .line_framer
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_mut()
+            .and_then(|(framer, latest_seq)| Some((framer.flush()?, *latest_seq)));
+        match partial {
+            Some((partial, seq)) => {
+                self.send_output(&partial, seq, connection, session_id)
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Send one frame of (filtered) output
+    async fn send_output(
+        &self,
+        data: &[u8],
+        seq: u64,
+        connection: &mut impl TerminalConnection,
+        session_id: &str,
+    ) -> Result<(), ServiceError> {
+        if connection.subprotocol() == Subprotocol::V1 {
+            let envelopes = Self::output_envelopes(
+                &String::from_utf8_lossy(data),
+                seq,
+                connection.max_message_bytes(),
+            )?;
+            for text in envelopes {
+                connection.send_text(&text).await.map_err(|e| {
+                    error!("Failed to send PTY output to session {}: {}", session_id, e);
+                    ServiceError::Connection(e)
+                })?;
+            }
+            return Ok(());
+        }
+
+        // Nothing left after filtering (v1 clients still get the sequence number)
+        if data.is_empty() {
+            return Ok(());
+        }
+
         // Try to convert data to string for text-based protocols
-        use std::borrow::Cow;
         match String::from_utf8_lossy(data) {
             Cow::Borrowed(text) => {
                 // Send text to client
@@ -2,13 +2,28 @@
 /// This module provides a structured approach to handling terminal sessions
 /// with clear separation of concerns following SOLID principles
 mod error;
+mod exec;
+mod idle_lock;
+mod key_remap;
+mod line_framer;
 mod message_handler;
+mod output_filter;
+mod output_log;
 mod pty_manager;
 mod session_handler;
 mod session_manager;
+mod session_tmpdir;
+mod shutdown;
 
 // Re-export public types and functions
 pub use error::ServiceError;
-pub use message_handler::MessageHandler;
-pub use pty_manager::PtyManager;
-pub use session_handler::handle_terminal_session;
+pub use exec::{ExecCommand, run_command};
+pub use key_remap::KeyRemap;
+pub use line_framer::LineFramer;
+pub use message_handler::{MessageHandler, SessionListScope, StalledInput, render_notice};
+pub use output_filter::OutputFilter;
+pub use output_log::SessionOutputLog;
+pub use pty_manager::{PtyManager, spawn_stats_shell_type};
+pub use session_handler::{end_detached_session, handle_terminal_session, resolve_session_pty};
+pub use session_tmpdir::{SESSION_TMPDIR_VAR, spawn_tmpdir_sweeper, sweep_orphaned_tmpdirs};
+pub use shutdown::shutdown_all;
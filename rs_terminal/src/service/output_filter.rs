@@ -0,0 +1,188 @@
+/// Escape sequence filter for clients with a limited terminal profile
+/// `no-color` removes SGR sequences (colors and text attributes), `dumb` removes all escape
+/// sequences and control characters except line breaks, tabs and backspace.
+/// PTY output arrives in arbitrary chunks, so a sequence split across chunks is completed with
+/// the next chunk instead of leaking out half-filtered.
+use crate::api::dto::TerminalProfile;
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+
+/// Longest control sequence held back while deciding whether it is SGR; longer ones are
+/// passed through as they are
+const MAX_HELD_SEQUENCE: usize = 256;
+
+/// Where the filter is within the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Plain text
+    Ground,
+    /// After ESC
+    Escape,
+    /// After ESC and intermediate bytes (e.g. `ESC ( B`)
+    EscapeIntermediate,
+    /// Control sequence (`ESC [`), until its final byte
+    Csi,
+    /// Control string (OSC, DCS, SOS, PM, APC), until BEL or ST
+    ControlString,
+    /// ESC inside a control string, either ST (`ESC \`) or the start of a new sequence
+    ControlStringEscape,
+}
+
+/// Stateful output filter of one connection
+#[derive(Debug)]
+pub struct OutputFilter {
+    profile: TerminalProfile,
+    state: State,
+    /// Bytes of the current sequence held back until it is known whether it is removed
+    /// (only used with `no-color`, `dumb` drops sequences right away)
+    held: Vec<u8>,
+}
+
+impl OutputFilter {
+    /// Create a filter for a terminal profile, `None` if the profile sends output as-is
+    pub fn new(profile: TerminalProfile) -> Option<Self> {
+        match profile {
+            TerminalProfile::NoColor | TerminalProfile::Dumb => Some(Self {
+                profile,
+                state: State::Ground,
+                held: Vec::new(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Filter a chunk of output
+    /// The end of a sequence that continues in the next chunk is held back
+    pub fn filter(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len());
+        for &byte in data {
+            self.push(byte, &mut out);
+        }
+        out
+    }
+
+    fn push(&mut self, byte: u8, out: &mut Vec<u8>) {
+        match self.state {
+            State::Ground => {
+                if byte == ESC {
+                    self.state = State::Escape;
+                    self.hold(byte);
+                } else if !self.strips_control(byte) {
+                    out.push(byte);
+                }
+            }
+            State::Escape => match byte {
+                b'[' => {
+                    self.state = State::Csi;
+                    self.hold(byte);
+                }
+                b']' | b'P' | b'X' | b'^' | b'_' => {
+                    self.state = State::ControlString;
+                    self.release(out);
+                    self.pass(byte, out);
+                }
+                0x20..=0x2f => {
+                    self.state = State::EscapeIntermediate;
+                    self.release(out);
+                    self.pass(byte, out);
+                }
+                0x30..=0x7e => {
+                    self.state = State::Ground;
+                    self.release(out);
+                    self.pass(byte, out);
+                }
+                // A control character cancels the sequence and is processed on its own
+                _ => {
+                    self.state = State::Ground;
+                    self.release(out);
+                    self.push(byte, out);
+                }
+            },
+            State::EscapeIntermediate => match byte {
+                0x20..=0x2f => self.pass(byte, out),
+                0x30..=0x7e => {
+                    self.state = State::Ground;
+                    self.pass(byte, out);
+                }
+                _ => {
+                    self.state = State::Ground;
+                    self.push(byte, out);
+                }
+            },
+            State::Csi => match byte {
+                // Parameter and intermediate bytes
+                0x20..=0x3f => {
+                    self.hold(byte);
+                    if self.held.len() > MAX_HELD_SEQUENCE {
+                        self.state = State::Ground;
+                        self.release(out);
+                    }
+                }
+                // Final byte: `m` selects graphic rendition (colors and attributes)
+                0x40..=0x7e => {
+                    self.state = State::Ground;
+                    if byte == b'm' {
+                        self.held.clear();
+                    } else {
+                        self.hold(byte);
+                        self.release(out);
+                    }
+                }
+                _ => {
+                    self.state = State::Ground;
+                    self.release(out);
+                    self.push(byte, out);
+                }
+            },
+            State::ControlString => match byte {
+                BEL => {
+                    self.state = State::Ground;
+                    self.pass(byte, out);
+                }
+                ESC => {
+                    self.state = State::ControlStringEscape;
+                    self.hold(byte);
+                }
+                _ => self.pass(byte, out),
+            },
+            State::ControlStringEscape => {
+                if byte == b'\\' {
+                    self.state = State::Ground;
+                    self.release(out);
+                    self.pass(byte, out);
+                } else {
+                    // The held ESC starts a new sequence
+                    self.state = State::Escape;
+                    self.push(byte, out);
+                }
+            }
+        }
+    }
+
+    /// Whether a plain text byte is removed
+    fn strips_control(&self, byte: u8) -> bool {
+        self.profile == TerminalProfile::Dumb
+            && (byte < 0x20 || byte == 0x7f)
+            && !matches!(byte, b'\n' | b'\r' | b'\t' | 0x08)
+    }
+
+    /// Hold back a byte of the current sequence
+    fn hold(&mut self, byte: u8) {
+        if self.profile != TerminalProfile::Dumb {
+            self.held.push(byte);
+        }
+    }
+
+    /// Pass the held bytes through, the sequence is kept
+    fn release(&mut self, out: &mut Vec<u8>) {
+        out.append(&mut self.held);
+    }
+
+    /// Pass a byte of a sequence that is kept
+    fn pass(&mut self, byte: u8, out: &mut Vec<u8>) {
+        if self.profile != TerminalProfile::Dumb {
+            out.push(byte);
+        }
+    }
+}
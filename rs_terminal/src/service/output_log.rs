@@ -0,0 +1,107 @@
+/// Per-session output log for auditing
+/// Only PTY output is written here; client input never reaches this file
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::AsyncWriteExt,
+};
+use tracing::info;
+
+use crate::config::TerminalConfig;
+
+/// Placeholder in the path template replaced by the session ID
+const SESSION_ID_PLACEHOLDER: &str = "{session_id}";
+
+/// Append-only log of the output of one session, rotated to `<path>.1` when it grows too large
+pub struct SessionOutputLog {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    max_bytes: u64,
+}
+
+impl SessionOutputLog {
+    /// Open the output log of a session if output logs are enabled in the config
+    pub async fn open(
+        config: &TerminalConfig,
+        session_id: &str,
+    ) -> Option<Result<Self, std::io::Error>> {
+        let template = config.session_output_log.as_ref()?;
+        let path =
+            PathBuf::from(template.replace(SESSION_ID_PLACEHOLDER, &encode_session_id(session_id)));
+        Some(Self::open_path(path, config.session_output_log_max_bytes).await)
+    }
+
+    async fn open_path(path: PathBuf, max_bytes: u64) -> Result<Self, std::io::Error> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).await?;
+        }
+        let file = open_append(&path).await?;
+        let written = file.metadata().await?.len();
+        info!("Writing session output log to {}", path.display());
+
+        Ok(Self {
+            path,
+            file,
+            written,
+            max_bytes,
+        })
+    }
+
+    /// Path of the log file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append PTY output, rotating the file first if it would exceed the size limit
+    pub async fn write(&mut self, data: &[u8]) -> Result<(), std::io::Error> {
+        if self.written > 0 && self.written + data.len() as u64 > self.max_bytes {
+            self.rotate().await?;
+        }
+        self.file.write_all(data).await?;
+        self.written += data.len() as u64;
+        Ok(())
+    }
+
+    /// Move the current file to `<path>.1` (replacing an older one) and start a new file
+    async fn rotate(&mut self) -> Result<(), std::io::Error> {
+        self.file.flush().await?;
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        fs::rename(&self.path, &rotated).await?;
+        self.file = open_append(&self.path).await?;
+        self.written = 0;
+        Ok(())
+    }
+
+    /// Flush buffered output when the session ends
+    pub async fn close(mut self) -> Result<(), std::io::Error> {
+        self.file.flush().await
+    }
+}
+
+async fn open_append(path: &Path) -> Result<File, std::io::Error> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+}
+
+/// Session IDs come from the request URL: encode them into a file name that stays inside the
+/// log directory and that no other session ID maps to
+/// Lowercase ASCII letters, digits, `-` and `_` are kept and every other byte becomes `%XX`,
+/// so IDs only differing in case stay apart on case-insensitive file systems too
+pub(super) fn encode_session_id(session_id: &str) -> String {
+    let mut encoded = String::with_capacity(session_id.len());
+    for byte in session_id.bytes() {
+        if byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-' || byte == b'_' {
+            encoded.push(char::from(byte));
+        } else {
+            let _ = write!(encoded, "%{:02X}", byte);
+        }
+    }
+    encoded
+}
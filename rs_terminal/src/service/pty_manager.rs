@@ -1,23 +1,42 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::app_state::PtySpawnStatsStore;
 use crate::config::TerminalConfig;
 /// PTY manager for managing PTY instances
-use crate::pty::{self, AsyncPty, PtyError};
+use crate::pty::{self, AsyncPty, PtyError, PtyFactory, PtyOverrides};
 use tracing::{error, info};
 
 /// PTY manager responsible for managing PTY instances
-pub struct PtyManager;
+pub struct PtyManager {
+    factory: Arc<dyn PtyFactory>,
+    spawn_stats: Arc<PtySpawnStatsStore>,
+}
 
 impl PtyManager {
-    /// Create a new PTY manager
-    pub fn new() -> Self {
-        Self
+    /// Create a new PTY manager backed by the given factory, recording its spawns in `spawn_stats`
+    pub fn new(factory: Arc<dyn PtyFactory>, spawn_stats: Arc<PtySpawnStatsStore>) -> Self {
+        Self {
+            factory,
+            spawn_stats,
+        }
     }
 
-    /// Create a new PTY instance using application configuration
+    /// Create a new PTY instance using application configuration and the session's overrides
     pub async fn create_pty_from_config(
         &self,
         config: &TerminalConfig,
+        overrides: &PtyOverrides<'_>,
     ) -> Result<Box<dyn AsyncPty>, PtyError> {
-        match pty::create_pty_from_config(config).await {
+        let started = Instant::now();
+        let result = pty::create_pty_from_config(config, self.factory.as_ref(), overrides).await;
+        self.spawn_stats.record(
+            self.factory.name(),
+            spawn_stats_shell_type(config, overrides.shell_type),
+            started.elapsed(),
+            result.is_ok(),
+        );
+        match result {
             Ok(pty) => {
                 info!("Created new PTY instance from configuration");
                 Ok(pty)
@@ -48,3 +67,16 @@ impl PtyManager {
         pty.is_alive()
     }
 }
+
+/// Shell type a spawn is counted under; requested types that aren't configured share one label,
+/// so clients can't create metrics series at will
+pub fn spawn_stats_shell_type<'a>(
+    config: &'a TerminalConfig,
+    shell_type: Option<&'a str>,
+) -> &'a str {
+    match shell_type {
+        None => &config.default_shell_type,
+        Some(shell_type) if config.shells.contains_key(shell_type) => shell_type,
+        Some(_) => "unknown",
+    }
+}
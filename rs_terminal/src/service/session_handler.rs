@@ -1,18 +1,49 @@
+use futures_util::future::{Abortable, Aborted};
+use std::path::Path;
+use std::time::{Duration, Instant};
 use tokio::io::AsyncReadExt;
 /// Terminal session handler for processing terminal connections
 use tokio::select;
-use tracing::{error, info};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{Instrument, debug, debug_span, error, info, info_span, warn};
 
-use super::{MessageHandler, PtyManager};
+use super::idle_lock::{IdleLock, verify_unlock};
+use super::session_tmpdir::{
+    apply_session_tmpdir, create_session_tmpdir, remove_session_tmpdir, session_tmpdir_path,
+};
+use super::{
+    KeyRemap, LineFramer, MessageHandler, PtyManager, SessionListScope, SessionOutputLog,
+    StalledInput, render_notice,
+};
 use crate::{
-    app_state::{AppState, ConnectionType, Session, SessionStatus},
-    protocol::{ConnectionResult, TerminalConnection, TerminalMessage},
-    pty::AsyncPty,
+    api::dto::{DiagnosticLogEvent, EnvironmentSource, TerminalCapabilities, TerminalProfile},
+    app_state::{
+        AppState, ConnectionType, SESSION_SPAN, Session, SessionCommand, SessionParams,
+        SessionStatus,
+    },
+    auth::ANONYMOUS_USER,
+    config::{FrameMode, TerminalConfig},
+    protocol::{
+        ClientEnvelope, ConnectionError, ConnectionResult, ErrorCode, NoticeLevel, Subprotocol,
+        TerminalConnection, TerminalMessage,
+    },
+    pty::{
+        AsyncPty, PtyError, PtyExitStatus, PtyOverrides, ResolvedPtyConfig, apply_session_identity,
+        resolve_pty_config,
+    },
     service::ServiceError,
 };
 
 /// Handle a terminal session using the TerminalConnection trait
-pub async fn handle_terminal_session(mut connection: impl TerminalConnection, state: AppState) {
+/// Everything the session logs carries its ID in a span, so its warnings show up in its diagnostics
+pub async fn handle_terminal_session(connection: impl TerminalConnection, state: AppState) {
+    let span = info_span!(SESSION_SPAN, session_id = %connection.id());
+    run_terminal_session(connection, state)
+        .instrument(span)
+        .await
+}
+
+async fn run_terminal_session(mut connection: impl TerminalConnection, state: AppState) {
     let conn_id = connection.id().to_string();
     let conn_type = connection.connection_type();
 
@@ -20,48 +51,317 @@ pub async fn handle_terminal_session(mut connection: impl TerminalConnection, st
         "New terminal connection: {} (Type: {:?})",
         conn_id, conn_type
     );
+    state
+        .diagnostics
+        .register(&conn_id, state.config.diagnostics_log_events);
+    state
+        .diagnostics
+        .set_subprotocol(&conn_id, connection.subprotocol().name());
 
     // Initialize managers
-    let pty_manager = PtyManager::new();
-    let message_handler = MessageHandler::new();
+    let pty_manager = PtyManager::new(state.pty_factory.clone(), state.pty_spawn_stats.clone());
 
     // Initialize session
-    if let Err(e) = SessionHandlerHelper::initialize_session(&conn_id, conn_type, &state).await {
+    let user_id = connection
+        .auth_context()
+        .map_or(ANONYMOUS_USER, |auth| auth.user_id.as_str())
+        .to_string();
+    if let Err(e) =
+        SessionHandlerHelper::initialize_session(&conn_id, conn_type, user_id, &state).await
+    {
         SessionHandlerHelper::handle_session_initialization_error(e, connection, &conn_id, &state)
             .await;
         return;
     }
 
-    // Create PTY for this session
-    let mut pty = match SessionHandlerHelper::create_session_pty(&pty_manager, &state, &conn_id)
-        .await
-    {
-        Ok(pty) => pty,
-        Err(e) => {
-            SessionHandlerHelper::handle_pty_creation_error(e, connection, &conn_id, &state).await;
-            return;
+    // Read-only sessions drop client input
+    let session = state.get_session(&conn_id).await;
+    let read_only = session.as_ref().is_some_and(|session| session.read_only);
+    let nudge_on_connect = session
+        .as_ref()
+        .and_then(|session| session.nudge_on_connect)
+        .unwrap_or(state.config.nudge_on_connect);
+    // The attaching client's terminal profile replaces the one the session was created with
+    let terminal_profile = connection
+        .terminal_profile()
+        .or_else(|| session.as_ref().map(|session| session.terminal_profile))
+        .unwrap_or_default();
+    let key_remap = session
+        .as_ref()
+        .and_then(|session| session.key_remap.as_ref())
+        .and_then(|name| state.config.key_remaps.get(name))
+        .map(KeyRemap::new);
+    let session_list = session
+        .as_ref()
+        .filter(|_| state.config.enable_session_list_frame)
+        .map(|session| SessionListScope::new(state.clone(), session.user_id.clone()));
+    let line_framer = (state.config.frame_mode == FrameMode::Lines)
+        .then(|| LineFramer::new(state.config.frame_max_line_bytes));
+    let message_handler = MessageHandler::new(
+        read_only,
+        terminal_profile,
+        key_remap,
+        session_list,
+        line_framer,
+        state.scrollback.clone(),
+        StalledInput::new(&state.config),
+    )
+    .with_clock(state.clock.clone());
+
+    // Attach to the PTY a disconnected client left running, or create one for this session
+    let mut pty = match state.detached_ptys.take(&conn_id).await {
+        Some(pty) => {
+            info!("Reattached to the running PTY of session {}", conn_id);
+            pty
+        }
+        None => {
+            let environment_profile =
+                SessionHandlerHelper::resolve_environment_profile(&connection, &conn_id, &state)
+                    .await;
+            let locale = connection.locale().to_vec();
+            if !locale.is_empty() {
+                info!("Using the client's locale for session {}", conn_id);
+            }
+            let pty = match SessionHandlerHelper::create_session_pty(
+                &pty_manager,
+                &state,
+                &conn_id,
+                environment_profile.as_deref(),
+                &locale,
+                terminal_profile,
+            )
+            .await
+            {
+                Ok(pty) => pty,
+                Err(e) => {
+                    SessionHandlerHelper::handle_pty_creation_error(
+                        e,
+                        connection,
+                        &message_handler,
+                        &conn_id,
+                        &state,
+                    )
+                    .await;
+                    return;
+                }
+            };
+
+            info!("PTY created for session {}", conn_id);
+            SessionHandlerHelper::update_session_environment(
+                &conn_id,
+                environment_profile.as_deref(),
+                &locale,
+                terminal_profile,
+                &state,
+            )
+            .await;
+            pty
         }
     };
 
-    info!("PTY created for session {}", conn_id);
+    // Let the application terminate this session (server shutdown, bulk termination)
+    let mut task_link = state.register_session_task(&conn_id).await;
+
+    // Tell the client the limits of the connection before anything else is sent
+    if let Err(e) = message_handler.send_hello(&mut connection).await {
+        warn!("Failed to send hello to session {}: {}", conn_id, e);
+    }
+
+    // Make the shell print a prompt for the new client
+    if nudge_on_connect {
+        match message_handler.nudge(&mut pty, &conn_id).await {
+            Ok(true) => {}
+            Ok(false) => warn!("PTY of session {} didn't take the nudge in time", conn_id),
+            Err(e) => warn!("Failed to nudge PTY for session {}: {}", conn_id, e),
+        }
+    }
 
-    // Run main session loop
-    SessionHandlerHelper::run_session_loop(&mut connection, &mut pty, &message_handler, &conn_id)
+    // Replay output the client missed before streaming live output
+    if let Some(seq) = connection.resume_from() {
+        SessionHandlerHelper::replay_scrollback(
+            &mut connection,
+            &message_handler,
+            seq,
+            &conn_id,
+            &state,
+        )
         .await;
+    }
 
-    // Clean up session resources
-    SessionHandlerHelper::cleanup_session_resources(
-        connection,
-        pty,
-        &pty_manager,
+    // Run main session loop; a shutdown cancels it when the session doesn't end on request
+    let abort_registration = task_link.take_abort_registration();
+    let session_loop = SessionHandlerHelper::run_session_loop(
+        &mut connection,
+        &mut pty,
+        &message_handler,
+        &mut task_link.commands,
         &conn_id,
         &state,
-    )
-    .await;
+    );
+    let detach = match Abortable::new(session_loop, abort_registration).await {
+        Ok(detach) => detach,
+        Err(Aborted) => {
+            warn!("Session {} didn't end when asked to, killing it", conn_id);
+            state
+                .diagnostics
+                .set_close_reason(&conn_id, "killed: did not end in time".to_string());
+            // The cleanup kills the shell
+            false
+        }
+    };
+
+    if detach {
+        // The shell keeps running for the next client
+        SessionHandlerHelper::detach_session(connection, pty, &conn_id, &state).await;
+    } else {
+        // Clean up session resources
+        SessionHandlerHelper::cleanup_session_resources(
+            connection,
+            pty,
+            &pty_manager,
+            &conn_id,
+            &state,
+        )
+        .await;
+    }
+
+    // Dropping the task link tells a pending shutdown that this session has finished
+    state.take_session_handle(&conn_id).await;
+    drop(task_link);
+    state.shutdown_if_drained().await;
 
     info!("Terminal session {} closed", conn_id);
 }
 
+/// A shell ending its output within this window after start, before the client sent any input,
+/// is reported as a failed start unless it exited with code 0
+const IMMEDIATE_EXIT_WINDOW: Duration = Duration::from_secs(2);
+
+/// How long to wait for the exit status of a shell that closed its output
+const EXIT_STATUS_WAIT: Duration = Duration::from_millis(500);
+
+/// How long closing the connection of an ending session may take
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Output arriving later than this after input is not counted as its echo
+const ECHO_WINDOW: Duration = Duration::from_secs(1);
+
+/// How often a session is checked for having been idle long enough to lock
+const LOCK_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Close reason of a client that didn't take its output within `connection_send_timeout_ms`,
+/// its session is disconnected and the shell keeps running
+const CLIENT_NOT_READING: &str = "client not reading its output";
+
+/// Resolve how the PTY of a session would be started, without creating anything
+/// Runs the same resolution as a WebSocket client attaching to the session (the client's own
+/// environment and terminal profiles aside); the session gets the scratch directory, capabilities
+/// and environment snapshot it would have
+pub fn resolve_session_pty(
+    state: &AppState,
+    session: &mut Session,
+) -> Result<ResolvedPtyConfig, PtyError> {
+    let environment_profile = effective_environment_profile(
+        &state.config,
+        None,
+        session.environment_profile.as_deref(),
+        "websocket",
+    );
+    if let Some(path) = session_tmpdir_path(&state.config, &session.id) {
+        apply_session_tmpdir(&state.config, session, &path);
+    }
+    let resolved = resolve_pty_config(
+        &state.config,
+        &session_pty_overrides(
+            session,
+            environment_profile.as_deref(),
+            &[],
+            session.terminal_profile,
+        ),
+    )?;
+    record_session_environment(session, resolved.environment.clone(), &state.config);
+    Ok(resolved)
+}
+
+/// End a disconnected session whose shell is still running: kill it and remove its scratch
+/// directory (the session record is left to the caller)
+/// Returns false if the session isn't detached
+pub async fn end_detached_session(state: &AppState, session_id: &str) -> bool {
+    let Some(mut pty) = state.detached_ptys.take(session_id).await else {
+        return false;
+    };
+    info!("Ending detached session {}", session_id);
+    if let Err(e) = pty.kill().await {
+        error!(
+            "Failed to kill PTY process for session {}: {}",
+            session_id, e
+        );
+    }
+    remove_session_tmpdir(&state.config, session_id).await;
+    true
+}
+
+/// Environment profile of a session: the connection's, the session's, or the transport's default
+fn effective_environment_profile(
+    config: &TerminalConfig,
+    connection_profile: Option<&str>,
+    session_profile: Option<&str>,
+    transport: &str,
+) -> Option<String> {
+    connection_profile
+        .or(session_profile)
+        .map(str::to_string)
+        .or_else(|| {
+            config
+                .transport_environment_profiles
+                .get(transport)
+                .cloned()
+        })
+}
+
+/// PTY overrides from the settings of a session
+fn session_pty_overrides<'a>(
+    session: &'a Session,
+    environment_profile: Option<&'a str>,
+    locale: &'a [(String, String)],
+    terminal_profile: TerminalProfile,
+) -> PtyOverrides<'a> {
+    PtyOverrides {
+        shell_type: Some(&session.shell_type),
+        command: session.command.as_deref(),
+        working_directory: session.working_directory.as_deref().map(Path::new),
+        size: Some((session.columns, session.rows)),
+        environment_profile,
+        environment: Some(&session.environment),
+        locale,
+        terminal_profile,
+        identity: Some((&session.id, &session.user_id)),
+    }
+}
+
+/// Record the capabilities and environment snapshot of a session whose shell gets `environment`
+fn record_session_environment(
+    session: &mut Session,
+    environment: Vec<(String, String, EnvironmentSource)>,
+    config: &TerminalConfig,
+) {
+    session.capabilities = TerminalCapabilities::from_environment(
+        environment
+            .iter()
+            .map(|(key, value, _)| (key.as_str(), value.as_str())),
+    );
+
+    // The shell also inherits the server's variables that the configuration doesn't set
+    let inherited: Vec<(String, String, EnvironmentSource)> = std::env::vars()
+        .filter(|(key, _)| !environment.iter().any(|(k, _, _)| k == key))
+        .map(|(key, value)| (key, value, EnvironmentSource::Inherited))
+        .collect();
+    session.record_environment(
+        inherited.into_iter().chain(environment),
+        &config.environment_reveal_allowlist,
+    );
+}
+
 /// 会话处理器辅助方法
 struct SessionHandlerHelper;
 
@@ -70,12 +370,13 @@ impl SessionHandlerHelper {
     async fn initialize_session(
         conn_id: &str,
         conn_type: crate::protocol::ConnectionType,
+        user_id: String,
         state: &AppState,
     ) -> Result<(), ServiceError> {
         match state.get_session(conn_id).await {
             Some(mut session) => {
                 // Update session status to active
-                session.set_status(SessionStatus::Active);
+                session.set_status(SessionStatus::Active, state.clock.now_unix());
                 state.update_session(session).await;
             }
             None => {
@@ -84,19 +385,23 @@ impl SessionHandlerHelper {
 
                 // Create a new session if it doesn't exist
                 let session = Session::new(
-                    conn_id.to_string(),
-                    "anonymous".to_string(), // Default to anonymous user
-                    None,
-                    None,
-                    shell_type,
-                    state.config.default_shell_config.size.columns,
-                    state.config.default_shell_config.size.rows,
-                    match conn_type {
-                        crate::protocol::ConnectionType::WebSocket => ConnectionType::WebSocket,
-                        crate::protocol::ConnectionType::WebTransport => {
-                            ConnectionType::WebTransport
-                        }
+                    SessionParams {
+                        id: conn_id.to_string(),
+                        user_id,
+                        title: None,
+                        working_directory: None,
+                        shell_type,
+                        columns: state.config.default_shell_config.size.columns,
+                        rows: state.config.default_shell_config.size.rows,
+                        connection_type: match conn_type {
+                            crate::protocol::ConnectionType::WebSocket => ConnectionType::WebSocket,
+                            crate::protocol::ConnectionType::WebTransport => {
+                                ConnectionType::WebTransport
+                            }
+                            crate::protocol::ConnectionType::Embedded => ConnectionType::Embedded,
+                        },
                     },
+                    state.clock.now_unix(),
                 );
                 state.add_session(session).await;
             }
@@ -106,13 +411,115 @@ impl SessionHandlerHelper {
         Ok(())
     }
 
+    /// 选择会话的环境配置
+    /// Priority: requested by the connection > requested when the session was created > transport default
+    async fn resolve_environment_profile(
+        connection: &impl TerminalConnection,
+        conn_id: &str,
+        state: &AppState,
+    ) -> Option<String> {
+        let session_profile = state
+            .get_session(conn_id)
+            .await
+            .and_then(|session| session.environment_profile);
+        let transport = match connection.connection_type() {
+            crate::protocol::ConnectionType::WebSocket => "websocket",
+            crate::protocol::ConnectionType::WebTransport => "webtransport",
+            crate::protocol::ConnectionType::Embedded => "embedded",
+        };
+        effective_environment_profile(
+            &state.config,
+            connection.environment_profile(),
+            session_profile.as_deref(),
+            transport,
+        )
+    }
+
+    /// 更新会话终端能力和环境快照
+    /// Derived from the environment the PTY was started with
+    async fn update_session_environment(
+        conn_id: &str,
+        environment_profile: Option<&str>,
+        locale: &[(String, String)],
+        terminal_profile: TerminalProfile,
+        state: &AppState,
+    ) {
+        let Some(mut session) = state.get_session(conn_id).await else {
+            return;
+        };
+        session.terminal_profile = terminal_profile;
+        let mut environment = state.config.shell_environment_sources(
+            &session.shell_type,
+            environment_profile,
+            &session.environment,
+            locale,
+            terminal_profile,
+        );
+        apply_session_identity(&mut environment, &session.id, &session.user_id);
+        record_session_environment(&mut session, environment, &state.config);
+        state.update_session(session).await;
+    }
+
+    /// 创建会话临时目录
+    /// Exported as `SESSION_TMPDIR`, and used as working directory if configured and none is set
+    async fn prepare_session_tmpdir(session: &mut Session, state: &AppState) {
+        let path = match create_session_tmpdir(&state.config, &session.id).await {
+            Some(Ok(path)) => path,
+            Some(Err(e)) => {
+                error!(
+                    "Failed to create scratch directory for session {}: {}",
+                    session.id, e
+                );
+                return;
+            }
+            None => return,
+        };
+        apply_session_tmpdir(&state.config, session, &path);
+        state.update_session(session.clone()).await;
+    }
+
     /// 创建会话 PTY
     async fn create_session_pty(
         pty_manager: &PtyManager,
         state: &AppState,
         conn_id: &str,
+        environment_profile: Option<&str>,
+        locale: &[(String, String)],
+        terminal_profile: TerminalProfile,
     ) -> Result<Box<dyn AsyncPty>, ServiceError> {
-        match pty_manager.create_pty_from_config(&state.config).await {
+        if let Some(profile) = environment_profile {
+            info!(
+                "Using environment profile {} for session {}",
+                profile, conn_id
+            );
+        }
+        if terminal_profile != TerminalProfile::Full {
+            info!(
+                "Using terminal profile {} for session {}",
+                terminal_profile.as_str(),
+                conn_id
+            );
+        }
+        // Start the shell the session was created with (over REST or by the connection)
+        let mut session = state.get_session(conn_id).await;
+        if let Some(session) = session.as_mut() {
+            Self::prepare_session_tmpdir(session, state).await;
+        }
+        let overrides = match &session {
+            Some(session) => {
+                session_pty_overrides(session, environment_profile, locale, terminal_profile)
+            }
+            None => PtyOverrides {
+                environment_profile,
+                locale,
+                terminal_profile,
+                ..Default::default()
+            },
+        };
+        match pty_manager
+            .create_pty_from_config(&state.config, &overrides)
+            .await
+        {
             Ok(pty) => {
                 info!("PTY created for session {}", conn_id);
                 Ok(pty)
@@ -148,13 +555,17 @@ impl SessionHandlerHelper {
     async fn handle_pty_creation_error(
         e: ServiceError,
         mut connection: impl TerminalConnection,
+        message_handler: &MessageHandler,
         conn_id: &str,
         state: &AppState,
     ) {
         error!("Failed to create PTY for session {}: {}", conn_id, e);
 
-        let error_msg = format!("Error: Failed to create terminal session: {}", e);
-        let _ = connection.send_text(&error_msg).await;
+        let code = e.client_error_code().unwrap_or(ErrorCode::SpawnFailed);
+        let message = format!("Failed to create terminal session: {}", e);
+        let _ = message_handler
+            .send_error(code, &message, &mut connection)
+            .await;
         let _ = connection.close().await;
 
         // Clean up session if it was added
@@ -162,94 +573,565 @@ impl SessionHandlerHelper {
     }
 
     /// 运行会话主循环
+    /// Returns true if the client was disconnected and the PTY should keep running
     async fn run_session_loop(
         connection: &mut impl TerminalConnection,
         pty: &mut Box<dyn AsyncPty>,
         message_handler: &MessageHandler,
+        commands: &mut mpsc::Receiver<SessionCommand>,
         conn_id: &str,
-    ) {
+        state: &AppState,
+    ) -> bool {
         let mut pty_buffer = [0u8; 4096];
+        let mut detach = false;
+        let started_at = state.clock.now_instant();
+        let mut received_input = false;
+        let mut output_log = Self::open_output_log(conn_id, state).await;
+        // Arrival time of the input whose echo is awaited (input latency probe)
+        let mut pending_input: Option<Instant> = None;
+        // Keepalive pings are protocol frames only, the PTY never sees them
+        let keepalive_interval = Duration::from_secs(state.config.keepalive_interval_secs);
+        let mut last_traffic = tokio::time::Instant::now();
+        // Idle time is measured on the state's clock, checked periodically
+        // Unlocking takes credentials of the user the connection was authenticated as
+        let user_id = connection
+            .auth_context()
+            .map_or(ANONYMOUS_USER, |auth| auth.user_id.as_str())
+            .to_string();
+        let mut idle_lock = IdleLock::new(&state.config, user_id, state.clock.now_instant());
+        let mut lock_check = tokio::time::interval(LOCK_CHECK_INTERVAL);
 
-        loop {
+        let close_reason = loop {
             select! {
                 // Handle incoming messages from the connection
                 msg_result = connection.receive() => {
-                    if Self::handle_connection_message(msg_result, connection, pty, message_handler, conn_id).await {
-                        break;
+                    last_traffic = tokio::time::Instant::now();
+                    if idle_lock.is_locked() {
+                        if let Some(reason) = Self::handle_locked_message(msg_result, connection, pty, message_handler, &mut idle_lock, &mut output_log, state).await {
+                            break reason;
+                        }
+                        continue;
+                    }
+                    if matches!(&msg_result, Some(Ok(message)) if message.carries_data()) {
+                        idle_lock.input(state.clock.now_instant());
+                        received_input = true;
+                        state.diagnostics.count_input(conn_id);
+                        if state.config.input_latency_probe && message_handler.latency_probe_requested() && pending_input.is_none() {
+                            pending_input = Some(state.clock.now_instant());
+                        }
+                    }
+                    let span = debug_span!("client_message");
+                    if let Some(reason) = Self::handle_connection_message(msg_result, connection, pty, message_handler, conn_id).instrument(span).await {
+                        break reason;
                     }
                 },
                 // Handle PTY output directly (non-blocking async)
                 read_result = pty.read(&mut pty_buffer) => {
-                    if Self::handle_pty_output(read_result, &pty_buffer, connection, message_handler, conn_id).await {
-                        break;
+                    last_traffic = tokio::time::Instant::now();
+                    // A clean exit 0 is a shell that ran its command, it ends like any other
+                    if matches!(read_result, Ok(0)) && !received_input && state.clock.now_instant() - started_at < IMMEDIATE_EXIT_WINDOW {
+                        let status = Self::wait_exit_status(pty).await;
+                        if !status.as_ref().is_some_and(PtyExitStatus::success) {
+                            Self::report_immediate_exit(connection, status, message_handler, conn_id, state).await;
+                            break "shell exited right after starting".to_string();
+                        }
+                    }
+                    // Measured before forwarding, recorded after so the probe never delays output
+                    let echo_latency = match read_result {
+                        Ok(n) if n > 0 => pending_input.take().map(|at| state.clock.now_instant() - at),
+                        _ => None,
+                    };
+                    let span = debug_span!("pty_read", bytes = read_result.as_ref().ok());
+                    if let Some(reason) = Self::handle_pty_output(read_result, &pty_buffer, connection, message_handler, &mut output_log, &mut idle_lock, state).instrument(span).await {
+                        detach = reason == CLIENT_NOT_READING;
+                        break reason;
+                    }
+                    if let Some(latency) = echo_latency.filter(|latency| *latency <= ECHO_WINDOW) {
+                        state.input_latency.record(conn_id, latency).await;
                     }
                 },
+                // Lock a session left without input
+                _ = lock_check.tick(), if idle_lock.is_enabled() && !idle_lock.is_locked() => {
+                    if idle_lock.lock_if_idle(state.clock.now_instant()) {
+                        let message = format!("Session locked after {} seconds without input", idle_lock.idle_secs());
+                        Self::record_lock_event(&mut output_log, &message, conn_id, state).await;
+                        if let Err(e) = message_handler.send_lock_state(Some(idle_lock.idle_secs()), connection).await {
+                            error!("Failed to tell session {} it is locked: {}", conn_id, e);
+                            break format!("failed to send lock state: {}", e);
+                        }
+                    }
+                },
+                // Keep an idle connection open
+                _ = tokio::time::sleep_until(last_traffic + keepalive_interval), if !keepalive_interval.is_zero() => {
+                    debug!("Sending keepalive to idle session {}", conn_id);
+                    if let Err(e) = connection.send_keepalive().await {
+                        error!("Failed to send keepalive to session {}: {}", conn_id, e);
+                        break format!("failed to send keepalive: {}", e);
+                    }
+                    last_traffic = tokio::time::Instant::now();
+                },
+                // Handle commands from the application
+                Some(command) = commands.recv() => {
+                    match command {
+                        SessionCommand::Terminate { reason } => {
+                            info!("Terminating session {}: {}", conn_id, reason);
+                            let notice = format!("Session terminated: {}", reason);
+                            let _ = message_handler.send_error(ErrorCode::SessionTerminated, &notice, connection).await;
+                            break format!("terminated: {}", reason);
+                        }
+                        SessionCommand::Disconnect { reason } => {
+                            info!("Disconnecting the client of session {}: {}", conn_id, reason);
+                            let notice = format!("Session disconnected: {}", reason);
+                            let _ = message_handler.send_error(ErrorCode::SessionDisconnected, &notice, connection).await;
+                            detach = true;
+                            break format!("disconnected: {}", reason);
+                        }
+                        SessionCommand::Notice { level, message } => {
+                            info!("Sending {} notice to session {}", level.as_str(), conn_id);
+                            // The audit log records what the user saw
+                            Self::write_output_log(&mut output_log, render_notice(level, &message).as_bytes(), conn_id).await;
+                            if let Err(e) = message_handler.send_notice(level, &message, connection).await {
+                                error!("Failed to send notice to session {}: {}", conn_id, e);
+                                break format!("failed to send notice: {}", e);
+                            }
+                        }
+                    }
+                },
+            }
+        };
+        state.diagnostics.set_close_reason(conn_id, close_reason);
+
+        if let Some(log) = output_log
+            && let Err(e) = log.close().await
+        {
+            error!("Failed to flush output log for session {}: {}", conn_id, e);
+        }
+        detach
+    }
+
+    /// 打开会话输出日志
+    /// A log that can't be opened is reported but doesn't prevent the session from running
+    async fn open_output_log(conn_id: &str, state: &AppState) -> Option<SessionOutputLog> {
+        match SessionOutputLog::open(&state.config, conn_id).await? {
+            Ok(log) => Some(log),
+            Err(e) => {
+                error!("Failed to open output log for session {}: {}", conn_id, e);
+                None
             }
         }
     }
 
     /// 处理连接消息
+    /// Returns why the session must close, if it must
     async fn handle_connection_message(
         msg_result: Option<ConnectionResult<TerminalMessage>>,
         connection: &mut impl TerminalConnection,
         pty: &mut Box<dyn AsyncPty>,
         message_handler: &MessageHandler,
         conn_id: &str,
-    ) -> bool {
+    ) -> Option<String> {
         match msg_result {
             Some(Ok(msg)) => {
                 match message_handler
                     .handle_message(msg, connection, pty, conn_id)
                     .await
                 {
-                    Ok(close) => close,
+                    Ok(true) => Some("closed by client".to_string()),
+                    Ok(false) => None,
                     Err(e) => {
                         error!("Failed to handle message for session {}: {}", conn_id, e);
-                        true
+                        if let Some(code) = e.client_error_code() {
+                            let _ = message_handler
+                                .send_error(code, &e.to_string(), connection)
+                                .await;
+                        }
+                        Some(format!("failed to handle message: {}", e))
                     }
                 }
             }
+            Some(Err(ConnectionError::ConnectionClosed)) => {
+                info!("Connection closed for session {}", conn_id);
+                Some("connection closed".to_string())
+            }
+            Some(Err(e)) if e.is_recoverable() => {
+                warn!(
+                    "Ignoring recoverable connection error for session {}: {}",
+                    conn_id, e
+                );
+                None
+            }
             Some(Err(e)) => {
                 error!("Connection error for session {}: {}", conn_id, e);
-                true
+                Some(format!("connection error: {}", e))
             }
             None => {
                 info!("Connection closed by client for session {}", conn_id);
-                true
+                Some("connection closed by client".to_string())
+            }
+        }
+    }
+
+    /// 处理锁定会话的消息
+    /// Only `unlock` envelopes and control frames are handled, input is discarded
+    async fn handle_locked_message(
+        msg_result: Option<ConnectionResult<TerminalMessage>>,
+        connection: &mut impl TerminalConnection,
+        pty: &mut Box<dyn AsyncPty>,
+        message_handler: &MessageHandler,
+        idle_lock: &mut IdleLock,
+        output_log: &mut Option<SessionOutputLog>,
+        state: &AppState,
+    ) -> Option<String> {
+        let conn_id = connection.id().to_string();
+        let unlock_token = match &msg_result {
+            Some(Ok(TerminalMessage::Text(text)))
+                if connection.subprotocol() == Subprotocol::V1 =>
+            {
+                match serde_json::from_str(text) {
+                    Ok(ClientEnvelope::Unlock { token }) => Some(token),
+                    _ => None,
+                }
             }
+            _ => None,
+        };
+        if let Some(token) = unlock_token {
+            return Self::unlock_session(
+                &token,
+                connection,
+                message_handler,
+                idle_lock,
+                output_log,
+                state,
+            )
+            .await;
+        }
+        if matches!(&msg_result, Some(Ok(message)) if message.carries_data()) {
+            debug!("Discarding input to locked session {}", conn_id);
+            return None;
+        }
+        Self::handle_connection_message(msg_result, connection, pty, message_handler, &conn_id)
+            .await
+    }
+
+    /// 解锁会话
+    /// The token has to authenticate the connection's user; the output held back is sent after
+    /// the `unlocked` envelope
+    async fn unlock_session(
+        token: &str,
+        connection: &mut impl TerminalConnection,
+        message_handler: &MessageHandler,
+        idle_lock: &mut IdleLock,
+        output_log: &mut Option<SessionOutputLog>,
+        state: &AppState,
+    ) -> Option<String> {
+        let conn_id = connection.id().to_string();
+        let conn_id = conn_id.as_str();
+        if let Err(cause) = verify_unlock(state, token, idle_lock.user_id()).await {
+            warn!("Refusing to unlock session {}: {}", conn_id, cause);
+            let message = format!("The session stays locked: {}", cause);
+            return message_handler
+                .send_error(ErrorCode::UnlockFailed, &message, connection)
+                .await
+                .err()
+                .map(|e| format!("failed to send error: {}", e));
+        }
+
+        let (held, dropped) = idle_lock.unlock(state.clock.now_instant());
+        Self::record_lock_event(output_log, "Session unlocked", conn_id, state).await;
+        let sent = async {
+            message_handler.send_lock_state(None, connection).await?;
+            if dropped > 0 {
+                let notice = format!(
+                    "{} bytes of output were dropped while the session was locked",
+                    dropped
+                );
+                message_handler
+                    .send_notice(NoticeLevel::Warning, &notice, connection)
+                    .await?;
+            }
+            for (seq, data) in held {
+                message_handler
+                    .handle_pty_output(&data, seq, connection, conn_id)
+                    .await?;
+            }
+            Ok::<_, ServiceError>(())
+        }
+        .await;
+        sent.err().map(|e| {
+            error!("Failed to unlock session {}: {}", conn_id, e);
+            format!("failed to send output held back while locked: {}", e)
+        })
+    }
+
+    /// 记录会话锁定状态变化
+    /// Locking and unlocking go to the output log (the audit trail of what the user saw) and the
+    /// session's diagnostics events
+    async fn record_lock_event(
+        output_log: &mut Option<SessionOutputLog>,
+        message: &str,
+        conn_id: &str,
+        state: &AppState,
+    ) {
+        info!("{}: {}", message, conn_id);
+        Self::write_output_log(
+            output_log,
+            render_notice(NoticeLevel::Info, message).as_bytes(),
+            conn_id,
+        )
+        .await;
+        state.diagnostics.record_event(
+            conn_id,
+            DiagnosticLogEvent {
+                timestamp: state.clock.now_unix(),
+                level: "info".to_string(),
+                target: module_path!().to_string(),
+                message: message.to_string(),
+            },
+        );
+    }
+
+    /// 等待 shell 的退出状态
+    /// The child may still be exiting when its output closes, give it a moment
+    async fn wait_exit_status(pty: &mut Box<dyn AsyncPty>) -> Option<PtyExitStatus> {
+        let deadline = Instant::now() + EXIT_STATUS_WAIT;
+        loop {
+            match pty.try_wait().await {
+                Ok(Some(status)) => return Some(status),
+                Ok(None) if Instant::now() < deadline => {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    /// 报告启动后立即退出的 shell
+    /// Tell the client why the session is closing instead of just dropping the connection
+    async fn report_immediate_exit(
+        connection: &mut impl TerminalConnection,
+        status: Option<PtyExitStatus>,
+        message_handler: &MessageHandler,
+        conn_id: &str,
+        state: &AppState,
+    ) {
+        // The shell the session was started with, and the command of its template if it has one
+        let session = state.get_session(conn_id).await;
+        let shell_type = session
+            .as_ref()
+            .map_or(state.config.default_shell_type.as_str(), |session| {
+                session.shell_type.as_str()
+            });
+        let command = match session
+            .as_ref()
+            .and_then(|session| session.command.as_ref())
+        {
+            Some(command) => command.join(" "),
+            None => state.config.get_shell_config(shell_type).command.join(" "),
+        };
+        let exit = match status {
+            Some(status) => status.to_string(),
+            None => "unknown exit status".to_string(),
+        };
+
+        error!(
+            "Shell {} for session {} exited immediately after start ({}): {}",
+            shell_type, conn_id, exit, command
+        );
+        let message = format!(
+            "Shell {} exited immediately after start ({}), check its configuration: {}",
+            shell_type, exit, command
+        );
+        // What the shell printed last (often why it failed) goes before the error
+        if let Err(e) = message_handler.flush_pty_output(connection, conn_id).await {
+            debug!(
+                "Failed to send the last output line to session {}: {}",
+                conn_id, e
+            );
+        }
+        let _ = message_handler
+            .send_error(ErrorCode::SpawnFailed, &message, connection)
+            .await;
+    }
+
+    /// 重放会话输出
+    /// Sends the retained scrollback starting at `seq`, with the original sequence numbers
+    async fn replay_scrollback(
+        connection: &mut impl TerminalConnection,
+        message_handler: &MessageHandler,
+        seq: u64,
+        conn_id: &str,
+        state: &AppState,
+    ) {
+        let chunks = state.scrollback.replay_from(conn_id, seq).await;
+        info!(
+            "Replaying {} output chunks from sequence {} for session {}",
+            chunks.len(),
+            seq,
+            conn_id
+        );
+        for (chunk_seq, data) in chunks {
+            if let Err(e) = message_handler
+                .handle_pty_output(&data, chunk_seq, connection, conn_id)
+                .await
+            {
+                error!("Failed to replay output for session {}: {}", conn_id, e);
+                break;
+            }
+        }
+    }
+
+    /// 写入会话输出日志
+    /// A log that fails is disabled for the rest of the session
+    async fn write_output_log(
+        output_log: &mut Option<SessionOutputLog>,
+        data: &[u8],
+        conn_id: &str,
+    ) {
+        if let Some(log) = output_log
+            && let Err(e) = log.write(data).await
+        {
+            error!(
+                "Failed to write output log {} for session {}, disabling it: {}",
+                log.path().display(),
+                conn_id,
+                e
+            );
+            *output_log = None;
         }
     }
 
     /// 处理 PTY 输出
+    /// Returns why the session must close, if it must
     async fn handle_pty_output(
         read_result: Result<usize, std::io::Error>,
         pty_buffer: &[u8],
         connection: &mut impl TerminalConnection,
         message_handler: &MessageHandler,
-        conn_id: &str,
-    ) -> bool {
+        output_log: &mut Option<SessionOutputLog>,
+        idle_lock: &mut IdleLock,
+        state: &AppState,
+    ) -> Option<String> {
+        let conn_id = connection.id().to_string();
+        let conn_id = conn_id.as_str();
         match read_result {
             Ok(0) => {
                 info!("PTY closed for session {}", conn_id);
-                true
+                if idle_lock.is_locked() {
+                    return Some("shell exited".to_string());
+                }
+                if let Err(e) = message_handler.flush_pty_output(connection, conn_id).await {
+                    debug!(
+                        "Failed to send the last output line to session {}: {}",
+                        conn_id, e
+                    );
+                }
+                Some("shell exited".to_string())
             }
             Ok(n) => {
                 let data = &pty_buffer[..n];
+                let seq = state.scrollback.append(conn_id, data).await;
+                state.diagnostics.count_output(conn_id, n);
+
+                Self::write_output_log(output_log, data, conn_id).await;
+
+                // A locked session keeps reading its shell, the client gets the output on unlock
+                if idle_lock.is_locked() {
+                    idle_lock.hold(seq, data);
+                    return None;
+                }
                 if let Err(e) = message_handler
-                    .handle_pty_output(data, connection, conn_id)
+                    .handle_pty_output(data, seq, connection, conn_id)
                     .await
                 {
+                    if let ServiceError::Connection(ConnectionError::Timeout) = e {
+                        warn!("Client of session {} isn't reading its output", conn_id);
+                        return Some(CLIENT_NOT_READING.to_string());
+                    }
                     error!("Failed to handle PTY output for session {}: {}", conn_id, e);
-                    true
+                    Some(format!("failed to send output: {}", e))
                 } else {
-                    false
+                    None
                 }
             }
             Err(e) => {
                 error!("Error reading from PTY for session {}: {}", conn_id, e);
-                true
+                Some(format!("failed to read from the PTY: {}", e))
+            }
+        }
+    }
+
+    /// Close the connection of a disconnected session and keep its PTY running
+    /// Until a client attaches again, the output goes to the scrollback and the output log
+    async fn detach_session(
+        mut connection: impl TerminalConnection,
+        pty: Box<dyn AsyncPty>,
+        conn_id: &str,
+        state: &AppState,
+    ) {
+        if let Err(e) = connection.close().await {
+            error!("Failed to close connection for session {}: {}", conn_id, e);
+        }
+
+        if let Some(mut session) = state.get_session(conn_id).await {
+            session.set_status(SessionStatus::Disconnected, state.clock.now_unix());
+            state.update_session(session).await;
+        }
+
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let task = tokio::spawn(
+            Self::drain_detached_pty(pty, stop_rx, conn_id.to_string(), state.clone())
+                .instrument(info_span!(SESSION_SPAN, session_id = %conn_id)),
+        );
+        state.detached_ptys.insert(conn_id, stop_tx, task).await;
+        info!("Session {} detached, its shell keeps running", conn_id);
+    }
+
+    /// Keep reading the output of a detached PTY until it is taken back (returning it) or its
+    /// shell exits (ending the session)
+    async fn drain_detached_pty(
+        mut pty: Box<dyn AsyncPty>,
+        mut stop: oneshot::Receiver<()>,
+        conn_id: String,
+        state: AppState,
+    ) -> Option<Box<dyn AsyncPty>> {
+        let mut pty_buffer = [0u8; 4096];
+        let mut output_log = Self::open_output_log(&conn_id, &state).await;
+        let attached = loop {
+            select! {
+                _ = &mut stop => break true,
+                read_result = pty.read(&mut pty_buffer) => match read_result {
+                    Ok(n) if n > 0 => {
+                        let data = &pty_buffer[..n];
+                        state.scrollback.append(&conn_id, data).await;
+                        state.diagnostics.count_output(&conn_id, n);
+                        Self::write_output_log(&mut output_log, data, &conn_id).await;
+                    }
+                    Ok(_) => break false,
+                    Err(e) => {
+                        error!("Error reading from PTY for session {}: {}", conn_id, e);
+                        break false;
+                    }
+                },
             }
+        };
+
+        if let Some(log) = output_log
+            && let Err(e) = log.close().await
+        {
+            error!("Failed to flush output log for session {}: {}", conn_id, e);
         }
+        if attached {
+            return Some(pty);
+        }
+
+        // Nobody is attaching right now: the session ends with its shell
+        if state.detached_ptys.remove(&conn_id).await {
+            info!("Shell of detached session {} exited", conn_id);
+            let _ = pty.kill().await;
+            remove_session_tmpdir(&state.config, &conn_id).await;
+            state.remove_session(&conn_id).await;
+        }
+        None
     }
 
     /// 清理会话资源
@@ -262,9 +1144,11 @@ impl SessionHandlerHelper {
     ) {
         info!("Cleaning up session {}", conn_id);
 
-        // Close the connection
-        if let Err(e) = connection.close().await {
-            error!("Failed to close connection for session {}: {}", conn_id, e);
+        // Close the connection; a client that stopped reading doesn't hold up the cleanup
+        match tokio::time::timeout(CLOSE_TIMEOUT, connection.close()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => error!("Failed to close connection for session {}: {}", conn_id, e),
+            Err(_) => warn!("Closing the connection of session {} timed out", conn_id),
         }
 
         // Kill the PTY process
@@ -274,10 +1158,13 @@ impl SessionHandlerHelper {
 
         // Update session status to terminated
         if let Some(mut session) = state.get_session(conn_id).await {
-            session.set_status(SessionStatus::Terminated);
+            session.set_status(SessionStatus::Terminated, state.clock.now_unix());
             state.update_session(session.clone()).await;
         }
 
+        // The shell is gone, so is its scratch directory
+        remove_session_tmpdir(&state.config, conn_id).await;
+
         // Remove session from state after a short delay (allowing time for cleanup)
         tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
         state.remove_session(conn_id).await;
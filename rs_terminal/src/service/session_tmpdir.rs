@@ -0,0 +1,191 @@
+/// Per-session scratch directories under `session_tmpdir_root`
+/// Each session gets `<root>/<session_id>` (mode 0700, the ID percent-encoded like output log
+/// names), exported to the shell as `SESSION_TMPDIR` and removed when the session ends;
+/// directories of crashed sessions are swept by age
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use tracing::{error, info, warn};
+
+use super::output_log::encode_session_id;
+use crate::app_state::{AppState, Session};
+use crate::config::TerminalConfig;
+
+/// Environment variable holding the session's scratch directory
+pub const SESSION_TMPDIR_VAR: &str = "SESSION_TMPDIR";
+
+/// How often leftover scratch directories are swept
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Path of a session's scratch directory, if scratch directories are enabled
+pub fn session_tmpdir_path(config: &TerminalConfig, session_id: &str) -> Option<PathBuf> {
+    let root = config.session_tmpdir_root.as_ref()?;
+    Some(root.join(encode_session_id(session_id)))
+}
+
+/// Point a session at its scratch directory
+/// Exported as `SESSION_TMPDIR`, and used as working directory if configured and none is set
+pub fn apply_session_tmpdir(config: &TerminalConfig, session: &mut Session, path: &Path) {
+    let path = path.to_string_lossy().into_owned();
+    session
+        .environment
+        .insert(SESSION_TMPDIR_VAR.to_string(), path.clone());
+    if config.session_tmpdir_as_cwd && session.working_directory.is_none() {
+        session.working_directory = Some(path);
+    }
+}
+
+/// Create the scratch directory of a session if scratch directories are enabled
+pub async fn create_session_tmpdir(
+    config: &TerminalConfig,
+    session_id: &str,
+) -> Option<io::Result<PathBuf>> {
+    let path = session_tmpdir_path(config, session_id)?;
+    let result = tokio::task::spawn_blocking(move || {
+        let mut builder = std::fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&path).map(|()| path)
+    })
+    .await
+    .unwrap_or_else(|e| Err(io::Error::other(e)));
+    Some(result)
+}
+
+/// Remove the scratch directory of a session, logging failures
+pub async fn remove_session_tmpdir(config: &TerminalConfig, session_id: &str) {
+    let Some(path) = session_tmpdir_path(config, session_id) else {
+        return;
+    };
+    remove_tmpdir(path, config.session_tmpdir_max_bytes).await;
+}
+
+/// Remove a scratch directory by path, logging failures
+async fn remove_tmpdir(path: PathBuf, max_bytes: u64) {
+    let shown = path.display().to_string();
+    match tokio::task::spawn_blocking(move || remove_guarded(&path, max_bytes)).await {
+        Ok(Ok(())) => info!("Removed scratch directory {}", shown),
+        Ok(Err(e)) => error!("Failed to remove scratch directory {}: {}", shown, e),
+        Err(e) => error!("Failed to remove scratch directory {}: {}", shown, e),
+    }
+}
+
+/// Remove a scratch directory recursively unless it is a symlink or larger than `max_bytes`
+/// (a tree that big points at a misconfigured root rather than scratch files)
+fn remove_guarded(path: &Path, max_bytes: u64) -> io::Result<()> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    if !metadata.is_dir() {
+        return Err(io::Error::other("not a directory"));
+    }
+    if tree_size(path, max_bytes)? > max_bytes {
+        return Err(io::Error::other(format!(
+            "larger than session_tmpdir_max_bytes ({} bytes), left in place",
+            max_bytes
+        )));
+    }
+    std::fs::remove_dir_all(path)
+}
+
+/// Total size of the files in a directory tree (symlinks are not followed),
+/// counting stops once `limit` is exceeded
+fn tree_size(path: &Path, limit: u64) -> io::Result<u64> {
+    let mut total = 0;
+    let mut pending = vec![path.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else {
+                total += metadata.len();
+            }
+            if total > limit {
+                return Ok(total);
+            }
+        }
+    }
+    Ok(total)
+}
+
+/// Remove scratch directories that belong to no session and are older than
+/// `session_tmpdir_max_age_secs`, returning how many were removed
+pub async fn sweep_orphaned_tmpdirs(state: &AppState) -> usize {
+    let Some(root) = state.config.session_tmpdir_root.clone() else {
+        return 0;
+    };
+    let max_age = Duration::from_secs(state.config.session_tmpdir_max_age_secs);
+    let listed = root.clone();
+    let candidates = match tokio::task::spawn_blocking(move || old_directories(&listed, max_age))
+        .await
+        .unwrap_or_else(|e| Err(io::Error::other(e)))
+    {
+        Ok(candidates) => candidates,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return 0,
+        Err(e) => {
+            warn!("Failed to sweep scratch directories: {}", e);
+            return 0;
+        }
+    };
+
+    // Directory names are encoded session IDs, compare them with the live sessions' names
+    let live: HashSet<String> = state
+        .get_all_sessions()
+        .await
+        .iter()
+        .map(|session| encode_session_id(&session.id))
+        .collect();
+
+    let mut removed = 0;
+    for name in candidates {
+        if live.contains(&name) {
+            continue;
+        }
+        warn!("Removing scratch directory {} of an ended session", name);
+        remove_tmpdir(root.join(&name), state.config.session_tmpdir_max_bytes).await;
+        removed += 1;
+    }
+    removed
+}
+
+/// Names of the directories under `root` last modified more than `max_age` ago
+fn old_directories(root: &Path, max_age: Duration) -> io::Result<Vec<String>> {
+    let now = SystemTime::now();
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(root)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let age = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok());
+        if metadata.is_dir() && age.is_some_and(|age| age > max_age) {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    Ok(names)
+}
+
+/// Sweep leftover scratch directories now and then periodically
+pub fn spawn_tmpdir_sweeper(state: AppState) {
+    if state.config.session_tmpdir_root.is_none() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            let removed = sweep_orphaned_tmpdirs(&state).await;
+            if removed > 0 {
+                info!("Swept {} leftover scratch directories", removed);
+            }
+        }
+    });
+}
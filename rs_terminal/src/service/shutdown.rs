@@ -0,0 +1,90 @@
+/// Terminate all running sessions, used on server shutdown and for bulk termination
+use std::time::Duration;
+
+use futures_util::future::join_all;
+use tracing::{info, warn};
+
+use super::end_detached_session;
+use crate::app_state::AppState;
+
+/// How long a session gets to end once its task was cancelled
+const KILL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Outcome of terminating all sessions
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShutdownReport {
+    /// Sessions whose task ended within the timeout
+    pub terminated: usize,
+    /// Sessions whose task was still running when the timeout expired and was cancelled, killing
+    /// their PTYs
+    pub killed: usize,
+    /// Sessions whose task didn't end even after being cancelled
+    pub timed_out: usize,
+}
+
+/// Notify every connected client, end the session tasks (which kill their PTYs) and wait up to
+/// `timeout` for them. Sessions still running afterwards are cancelled, which kills their PTYs
+/// without waiting for the client, then dropped from the application state.
+pub async fn shutdown_all(state: &AppState, reason: &str, timeout: Duration) -> ShutdownReport {
+    let handles = state.take_all_session_handles().await;
+    info!(
+        "Terminating {} running sessions ({}), waiting up to {:?}",
+        handles.len(),
+        reason,
+        timeout
+    );
+
+    for (session_id, handle) in &handles {
+        if !handle.request_terminate(reason) {
+            info!("Session {} had already ended", session_id);
+        }
+    }
+
+    let results = join_all(
+        handles
+            .into_iter()
+            .map(|(session_id, mut handle)| async move {
+                if handle.wait_finished(timeout).await {
+                    return (session_id, Some(true));
+                }
+                warn!(
+                    "Session {} did not finish within {:?}, killing it",
+                    session_id, timeout
+                );
+                handle.force_terminate();
+                if handle.wait_finished(KILL_TIMEOUT).await {
+                    (session_id, Some(false))
+                } else {
+                    (session_id, None)
+                }
+            }),
+    )
+    .await;
+
+    let mut report = ShutdownReport::default();
+    for (session_id, outcome) in results {
+        match outcome {
+            Some(true) => report.terminated += 1,
+            Some(false) => report.killed += 1,
+            None => {
+                warn!("Session {} could not be killed, dropping it", session_id);
+                report.timed_out += 1;
+            }
+        }
+    }
+
+    // Disconnected sessions have no task either, only a running shell
+    for session_id in state.detached_ptys.session_ids().await {
+        if end_detached_session(state, &session_id).await {
+            report.terminated += 1;
+        }
+    }
+
+    // Sessions created through the API but never connected have no task to stop
+    let removed = state.cleanup_all_sessions().await;
+    info!(
+        "Terminated {} sessions, killed {}, {} timed out, {} session records removed",
+        report.terminated, report.killed, report.timed_out, removed
+    );
+    report
+}
@@ -0,0 +1,658 @@
+/// Data Transfer Objects (DTOs) for REST API endpoints
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::NoticeLevel;
+
+/// Request DTO for creating a new terminal session
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateSessionRequest {
+    /// User ID associated with this session (the authenticated user when empty)
+    #[serde(default)]
+    pub user_id: String,
+
+    /// Optional title for the session
+    pub title: Option<String>,
+
+    /// Optional working directory for the terminal
+    pub working_directory: Option<String>,
+
+    /// Optional shell type to use
+    pub shell_type: Option<String>,
+
+    /// Optional terminal columns
+    pub columns: Option<u16>,
+
+    /// Optional terminal rows
+    pub rows: Option<u16>,
+
+    /// Optional environment profile (from the server's `environment_profiles`)
+    #[serde(default)]
+    pub environment_profile: Option<String>,
+
+    /// Extra environment variables for the shell; names outside the server's
+    /// `client_environment_allowlist` are dropped
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub environment: HashMap<String, String>,
+
+    /// View-only session: client input is dropped, output is still sent
+    #[serde(default)]
+    pub read_only: bool,
+
+    /// Optional session template (from the server's `templates`); the request's
+    /// size and title override the template's
+    #[serde(default)]
+    pub template: Option<String>,
+
+    /// Write a newline to the shell when a client connects, so the prompt shows up right away
+    /// (defaults to the server's `nudge_on_connect`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nudge_on_connect: Option<bool>,
+
+    /// Terminal features the client can handle (a connection may pick another profile)
+    #[serde(default)]
+    pub terminal_profile: Option<TerminalProfile>,
+
+    /// Optional key remap table (from the server's `key_remaps`), replacing the template's
+    #[serde(default)]
+    pub key_remap: Option<String>,
+}
+
+/// Terminal features a client can handle
+/// Limited profiles give the shell a matching `TERM` and filter escape sequences out of the output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum TerminalProfile {
+    /// Full terminal emulation, output is sent as-is
+    #[default]
+    Full,
+    /// Cursor control but no colors: `TERM=vt100`, `NO_COLOR=1`, SGR sequences are removed
+    NoColor,
+    /// Plain text only: `TERM=dumb`, `NO_COLOR=1`, all escape sequences are removed
+    Dumb,
+}
+
+impl TerminalProfile {
+    /// Name of the profile
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TerminalProfile::Full => "full",
+            TerminalProfile::NoColor => "no-color",
+            TerminalProfile::Dumb => "dumb",
+        }
+    }
+
+    /// Environment variables the shell gets with this profile, overriding all others
+    /// `COLORTERM` is cleared so programs don't assume 24-bit colors
+    pub fn environment(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            TerminalProfile::Full => &[],
+            TerminalProfile::NoColor => &[("TERM", "vt100"), ("COLORTERM", ""), ("NO_COLOR", "1")],
+            TerminalProfile::Dumb => &[("TERM", "dumb"), ("COLORTERM", ""), ("NO_COLOR", "1")],
+        }
+    }
+}
+
+/// Request DTO for running a command headlessly
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecRequest {
+    /// Command line to run
+    pub command: String,
+
+    /// Optional shell type whose program runs the command
+    #[serde(default)]
+    pub shell_type: Option<String>,
+
+    /// Optional timeout in seconds (the server's default applies otherwise)
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+
+    /// Extra environment variables; names outside the server's
+    /// `client_environment_allowlist` are dropped
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub environment: HashMap<String, String>,
+}
+
+/// Response DTO for a headless command
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecResponse {
+    /// Terminal output of the command (stdout and stderr, invalid UTF-8 replaced)
+    pub output: String,
+
+    /// Exit code, absent when the command was killed
+    pub exit_code: Option<i32>,
+
+    /// Signal that ended the command (e.g. `Killed`), absent when it exited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<String>,
+
+    /// Run time in milliseconds
+    pub duration_ms: u64,
+
+    /// Output beyond the server's limit was discarded
+    pub truncated: bool,
+
+    /// The command was killed because it hit the timeout
+    pub timed_out: bool,
+}
+
+/// Request DTO for updating a terminal session
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateSessionRequest {
+    /// New session title
+    pub title: String,
+}
+
+/// Request DTO for resizing a terminal session
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResizeTerminalRequest {
+    /// New terminal columns
+    pub columns: u16,
+
+    /// New terminal rows
+    pub rows: u16,
+}
+
+/// Response DTO for a terminal session
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalSession {
+    /// Unique session ID (renamed to 'id' to match frontend expectations)
+    pub id: String,
+
+    /// User ID associated with this session
+    pub user_id: String,
+
+    /// Session title
+    pub title: Option<String>,
+
+    /// Session status
+    pub status: String,
+
+    /// Terminal columns
+    pub columns: u16,
+
+    /// Terminal rows
+    pub rows: u16,
+
+    /// Working directory (use empty string instead of null if not set)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub working_directory: Option<String>,
+
+    /// Shell type
+    pub shell_type: String,
+
+    /// Connection type (WebSocket/WebTransport)
+    pub connection_type: String,
+
+    /// Session creation timestamp
+    pub created_at: u64,
+
+    /// Terminal capabilities (`colorDepth`, `unicode`)
+    #[serde(flatten, default)]
+    pub capabilities: TerminalCapabilities,
+
+    /// Whether client input is dropped (view-only session)
+    #[serde(default)]
+    pub read_only: bool,
+
+    /// Template the session was created from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+
+    /// Labels of the session's template
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
+
+    /// Terminal profile of the session's most recent connection
+    #[serde(default)]
+    pub terminal_profile: TerminalProfile,
+
+    /// p95 of the recent input echo latency in milliseconds (only with the latency probe enabled)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_latency_p95_ms: Option<f64>,
+}
+
+/// Color depth a terminal supports
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum ColorDepth {
+    /// No colors (`TERM=dumb` or unset)
+    Monochrome,
+    /// The 16 ANSI colors
+    #[default]
+    Ansi16,
+    /// The 256 color palette (`TERM=*-256color`)
+    Ansi256,
+    /// 24-bit colors (`COLORTERM=truecolor` or `24bit`, `TERM=*-direct`)
+    Truecolor,
+}
+
+/// Terminal capabilities assumed for a session, derived from the shell environment
+/// Missing fields take their defaults, so sessions of servers that don't report them still parse
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TerminalCapabilities {
+    /// Supported color depth
+    pub color_depth: ColorDepth,
+
+    /// Whether the locale uses UTF-8
+    pub unicode: bool,
+}
+
+impl TerminalCapabilities {
+    /// Derive the capabilities from `TERM`, `COLORTERM`, `NO_COLOR` and the locale variables
+    pub fn from_environment<'a>(vars: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        let mut term = None;
+        let mut colorterm = None;
+        let mut no_color = false;
+        let mut lc_all = None;
+        let mut lc_ctype = None;
+        let mut lang = None;
+        for (key, value) in vars {
+            match key {
+                "TERM" => term = Some(value),
+                "COLORTERM" => colorterm = Some(value),
+                "NO_COLOR" => no_color = !value.is_empty(),
+                "LC_ALL" => lc_all = Some(value),
+                "LC_CTYPE" => lc_ctype = Some(value),
+                "LANG" => lang = Some(value),
+                _ => {}
+            }
+        }
+
+        let color_depth = match (term.unwrap_or(""), colorterm.unwrap_or("")) {
+            _ if no_color => ColorDepth::Monochrome,
+            (_, "truecolor" | "24bit") => ColorDepth::Truecolor,
+            (term, _) if term.ends_with("-direct") => ColorDepth::Truecolor,
+            (term, _) if term.contains("256color") => ColorDepth::Ansi256,
+            ("" | "dumb", _) => ColorDepth::Monochrome,
+            _ => ColorDepth::Ansi16,
+        };
+
+        // The first non-empty locale variable decides, as in the C library
+        let locale = [lc_all, lc_ctype, lang]
+            .into_iter()
+            .flatten()
+            .find(|value| !value.is_empty())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        let unicode = locale.contains("utf-8") || locale.contains("utf8");
+
+        Self {
+            color_depth,
+            unicode,
+        }
+    }
+}
+
+/// Where a session environment variable came from, later sources override earlier ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum EnvironmentSource {
+    /// Inherited from the server process
+    Inherited,
+    /// `default_shell_config.environment`
+    DefaultConfig,
+    /// The shell's `environment`
+    ShellConfig,
+    /// The session's environment profile
+    Profile,
+    /// Set when creating the session (template and client variables)
+    Request,
+    /// Locale forwarded by the connecting client
+    ClientLocale,
+    /// The connection's terminal profile
+    TerminalProfile,
+    /// Identity of the session (`WAYLON_SESSION_ID`, `WAYLON_USER_ID`), set by the server
+    Session,
+}
+
+/// Environment variable a session's shell was started with
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvironmentVariableInfo {
+    /// Variable name
+    pub name: String,
+
+    /// Where the value came from
+    pub source: EnvironmentSource,
+
+    /// Hash of the value, comparable between sessions of the same server run
+    pub value_hash: String,
+
+    /// The value, only when explicitly revealed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+/// Response DTO for `GET /api/sessions/:session_id/environment`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionEnvironmentResponse {
+    /// Session ID
+    pub session_id: String,
+
+    /// Variables sorted by name
+    pub variables: Vec<EnvironmentVariableInfo>,
+}
+
+/// Response DTO for `POST /api/sessions/dry-run`: what creating a session would resolve to
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionDryRunResponse {
+    /// Shell type that would be started
+    pub shell_type: String,
+
+    /// Command of the shell
+    pub command: String,
+
+    /// Arguments of the command
+    pub args: Vec<String>,
+
+    /// Terminal columns
+    pub columns: u16,
+
+    /// Terminal rows
+    pub rows: u16,
+
+    /// Working directory of the shell, absolute if it exists
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_directory: Option<String>,
+
+    /// Session title
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    /// Environment profile applied for a WebSocket client that doesn't choose one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment_profile: Option<String>,
+
+    /// Terminal profile of the session
+    pub terminal_profile: TerminalProfile,
+
+    /// Terminal capabilities derived from the environment
+    pub capabilities: TerminalCapabilities,
+
+    /// Environment variables sorted by name, without values
+    pub environment: Vec<EnvironmentVariableInfo>,
+
+    /// PTY implementation that would create the terminal
+    pub pty_backend: String,
+
+    /// Problems that wouldn't prevent the session from being created
+    pub warnings: Vec<String>,
+}
+
+/// Warning or error logged by the server within a session
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticLogEvent {
+    /// When it was logged (UNIX epoch in seconds)
+    pub timestamp: u64,
+
+    /// `warn` or `error` (`info` for events like locking the session)
+    pub level: String,
+
+    /// Module that logged it
+    pub target: String,
+
+    /// Log message
+    pub message: String,
+}
+
+/// Traffic of a session since it was created
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionCounters {
+    /// Messages received from clients
+    pub input_messages: u64,
+
+    /// Chunks of terminal output
+    pub output_chunks: u64,
+
+    /// Bytes of terminal output
+    pub output_bytes: u64,
+}
+
+/// Response DTO for `GET /api/sessions/:session_id/diagnostics`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionDiagnosticsResponse {
+    /// Current state of the session
+    pub session: TerminalSession,
+
+    /// Subprotocol negotiated by the session's connection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subprotocol: Option<String>,
+
+    /// Environment profile of the session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment_profile: Option<String>,
+
+    /// Traffic counters
+    pub counters: SessionCounters,
+
+    /// Most recent warnings and errors logged within the session, oldest first
+    pub events: Vec<DiagnosticLogEvent>,
+
+    /// Why the session ended, if it has
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub close_reason: Option<String>,
+}
+
+/// PTY spawn statistics of one factory and shell type, since the server started
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PtySpawnStats {
+    /// PTY factory that spawned the processes
+    pub factory: String,
+
+    /// Shell type of the sessions
+    pub shell_type: String,
+
+    /// Spawn attempts
+    pub count: u64,
+
+    /// Attempts that failed
+    pub failures: u64,
+
+    /// Share of failed attempts (0 to 1)
+    pub error_rate: f64,
+
+    /// Median spawn time in milliseconds (the upper bound of its histogram bucket)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p50_ms: Option<f64>,
+
+    /// 99th percentile spawn time in milliseconds (the upper bound of its histogram bucket)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p99_ms: Option<f64>,
+}
+
+/// Response DTO for `GET /api/admin/pty-stats`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PtyStatsResponse {
+    /// Statistics per factory and shell type
+    pub spawns: Vec<PtySpawnStats>,
+}
+
+/// Response DTO for terminal resize operation
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalResizeResponse {
+    /// Session ID
+    pub session_id: String,
+
+    /// New terminal columns
+    pub columns: u16,
+
+    /// New terminal rows
+    pub rows: u16,
+
+    /// Success flag
+    pub success: bool,
+}
+
+/// Response DTO for terminal termination operation
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalTerminateResponse {
+    /// Session ID
+    pub session_id: String,
+
+    /// Success flag
+    pub success: bool,
+
+    /// Termination reason
+    pub reason: String,
+}
+
+/// Request DTO for broadcasting a notice to all connected sessions
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BroadcastRequest {
+    /// Message shown to every connected client
+    pub message: String,
+
+    /// Severity, `info` unless given
+    #[serde(default)]
+    pub level: NoticeLevel,
+}
+
+/// Response DTO for a broadcast
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BroadcastResponse {
+    /// Sessions the notice was queued for
+    pub delivered: usize,
+
+    /// Sessions that couldn't take the notice (ended or not keeping up)
+    pub failed: usize,
+}
+
+/// Response DTO for terminating all sessions
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkTerminateResponse {
+    /// Sessions that ended within the timeout
+    pub terminated: usize,
+
+    /// Sessions that were still running when the timeout expired and were killed
+    #[serde(default)]
+    pub killed: usize,
+
+    /// Sessions that didn't end even after being killed
+    pub timed_out: usize,
+}
+
+/// Session template as listed by `GET /api/templates`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionTemplateInfo {
+    /// Template name, used as `template` when creating a session
+    pub name: String,
+
+    /// Shell type the template starts
+    pub shell_type: String,
+
+    /// Session title pattern
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    /// Terminal columns, when the template sets a size
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub columns: Option<u16>,
+
+    /// Terminal rows, when the template sets a size
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rows: Option<u16>,
+
+    /// Free-form labels
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
+}
+
+/// Response DTO for the health endpoints and toggling draining mode
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthResponse {
+    /// Success flag (false when the server is not ready)
+    pub success: bool,
+
+    /// Response message
+    pub message: String,
+
+    /// Whether the server is draining (refusing new sessions)
+    pub draining: bool,
+
+    /// Whether a transport is unavailable (WebTransport stopped after repeated failures)
+    #[serde(default)]
+    pub degraded: bool,
+
+    /// Number of sessions that are still running
+    pub sessions: usize,
+
+    /// Server version (unset for servers that don't report it)
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+/// Shell as listed by `GET /api/shells`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShellInfo {
+    /// Shell type, used as `shellType` when creating a session
+    pub name: String,
+
+    /// Terminal columns a new session of this shell starts with
+    pub columns: u16,
+
+    /// Terminal rows a new session of this shell starts with
+    pub rows: u16,
+}
+
+/// Response DTO for `GET /api/shells`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShellsResponse {
+    /// Shell type used when a session doesn't name one
+    pub default_shell: String,
+
+    /// Configured shells, sorted by name
+    pub shells: Vec<ShellInfo>,
+}
+
+/// Generic success response
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SuccessResponse {
+    /// Success flag
+    pub success: bool,
+
+    /// Response message
+    pub message: String,
+}
+
+/// Generic error response
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorResponse {
+    /// Error flag
+    pub error: bool,
+
+    /// Error message
+    pub message: String,
+
+    /// Optional error code
+    pub code: Option<u16>,
+}
@@ -0,0 +1,3 @@
+/// Wire types shared by the rs_terminal server and its clients
+pub mod dto;
+pub mod protocol;
@@ -0,0 +1,267 @@
+/// Messages exchanged over a terminal connection (WebSocket or WebTransport)
+use serde::{Deserialize, Serialize};
+
+use crate::dto::TerminalSession;
+
+/// Define `TYPES` and `type_name` of an envelope enum from one list of its variants and their
+/// `type` tags; the match is exhaustive, so a new variant can't be left out of `TYPES`
+macro_rules! envelope_types {
+    ($envelope:ident { $($variant:ident => $tag:literal,)* }) => {
+        impl $envelope {
+            /// `type` tags of all envelopes, each needs a case in the conformance fixtures
+            /// (`conformance/` in this crate)
+            pub const TYPES: &'static [&'static str] = &[$($tag),*];
+
+            /// `type` tag of the envelope
+            pub fn type_name(&self) -> &'static str {
+                match self {
+                    $($envelope::$variant { .. } => $tag,)*
+                }
+            }
+        }
+    };
+}
+
+/// Terminal message types
+/// New message kinds may be added, so matches outside this crate need a fallback arm
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub enum TerminalMessage {
+    /// Text message
+    Text(String),
+    /// Binary message
+    Binary(Vec<u8>),
+    /// Ping message
+    Ping(Vec<u8>),
+    /// Pong message
+    Pong(()),
+    /// Close message
+    Close,
+}
+
+impl TerminalMessage {
+    /// Whether the message carries data for the terminal
+    /// Empty text and binary frames are keepalives, not input
+    pub fn carries_data(&self) -> bool {
+        match self {
+            TerminalMessage::Text(text) => !text.is_empty(),
+            TerminalMessage::Binary(data) => !data.is_empty(),
+            _ => false,
+        }
+    }
+}
+
+/// WebSocket subprotocols (`Sec-WebSocket-Protocol`) understood by the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Subprotocol {
+    /// Frames carry terminal input and output as-is (also used when no subprotocol is offered)
+    Raw,
+    /// Text frames are JSON envelopes: [`ClientEnvelope`] from the client, [`ServerEnvelope`] from the server
+    V1,
+}
+
+impl Subprotocol {
+    /// All subprotocols in the server's order of preference
+    pub const ALL: [Subprotocol; 2] = [Subprotocol::V1, Subprotocol::Raw];
+
+    /// Name used in the `Sec-WebSocket-Protocol` header
+    pub fn name(self) -> &'static str {
+        match self {
+            Subprotocol::Raw => "waylon-terminal-raw",
+            Subprotocol::V1 => "waylon-terminal-v1",
+        }
+    }
+
+    /// Look up a subprotocol by its header name
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name() == name)
+    }
+}
+
+/// Client message of the `waylon-terminal-v1` subprotocol, sent as a JSON text frame
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+#[non_exhaustive]
+pub enum ClientEnvelope {
+    /// Input written to the terminal
+    Input {
+        /// Input data
+        data: String,
+        /// Set to have the server answer with an `ack` envelope once the input is written
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<u64>,
+    },
+    /// Resize the terminal
+    Resize {
+        /// New terminal columns
+        columns: u16,
+        /// New terminal rows
+        rows: u16,
+    },
+    /// Request the sessions of the connection's user, answered with a `sessions` envelope
+    #[serde(rename = "list_sessions")]
+    ListSessions,
+    /// Drop the session's scrollback (e.g. after `clear`), so reconnecting clients don't replay it
+    #[serde(rename = "clear_scrollback")]
+    ClearScrollback,
+    /// Authenticate again to unlock a session locked after inactivity
+    Unlock {
+        /// Bearer token of the user the connection was authenticated as
+        token: String,
+    },
+    /// Options of the client, usually its first message
+    Hello {
+        /// Measure the time from input to its echo (only if the server's `input_latency_probe`
+        /// permits it)
+        #[serde(rename = "latencyProbe", default)]
+        latency_probe: bool,
+    },
+}
+
+envelope_types!(ClientEnvelope {
+    Input => "input",
+    Resize => "resize",
+    ListSessions => "list_sessions",
+    ClearScrollback => "clear_scrollback",
+    Unlock => "unlock",
+    Hello => "hello",
+});
+
+/// Server message of the `waylon-terminal-v1` subprotocol, sent as a JSON text frame
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+#[non_exhaustive]
+pub enum ServerEnvelope {
+    /// First message of a connection, describing the limits the server applies
+    Hello {
+        /// Largest message the server sends or accepts, in bytes (unset if unlimited)
+        #[serde(
+            rename = "maxMessageBytes",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        max_message_bytes: Option<u64>,
+    },
+    /// Terminal output
+    /// Output too large for one message is sent as several envelopes with the same `seq`
+    Output {
+        /// Sequence number of this output in the session, increasing by one per frame
+        seq: u64,
+        /// Output data (invalid UTF-8 replaced)
+        data: String,
+    },
+    /// Notice from the server operators (e.g. upcoming maintenance), not part of the terminal output
+    Notice {
+        /// Severity
+        level: NoticeLevel,
+        /// Message text
+        message: String,
+    },
+    /// Answer to `list_sessions`
+    Sessions {
+        /// Sessions of the connection's user, as returned by `GET /api/sessions`
+        sessions: Vec<TerminalSession>,
+    },
+    /// Answer to an `input` envelope with an `id`, sent once its data was written to the terminal
+    Ack {
+        /// The input's `id`
+        id: u64,
+    },
+    /// A terminal operation failed
+    Error {
+        /// What failed, for clients to react on
+        code: ErrorCode,
+        /// Description of the failure, for people
+        message: String,
+    },
+    /// The session was locked after inactivity: output is held back and input is discarded until
+    /// the client sends an `unlock` envelope; the shell keeps running
+    Locked {
+        /// Seconds without input after which the session was locked
+        #[serde(rename = "idleSecs")]
+        idle_secs: u64,
+    },
+    /// The session was unlocked, the output held back follows
+    Unlocked,
+}
+
+envelope_types!(ServerEnvelope {
+    Hello => "hello",
+    Output => "output",
+    Notice => "notice",
+    Sessions => "sessions",
+    Ack => "ack",
+    Error => "error",
+    Locked => "locked",
+    Unlocked => "unlocked",
+});
+
+/// What failed, in an `error` envelope
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum ErrorCode {
+    /// The shell could not be started, the session closes
+    SpawnFailed,
+    /// Input could not be written to the terminal, the session closes
+    WriteFailed,
+    /// The terminal could not be resized, the session goes on at its previous size
+    ResizeFailed,
+    /// The server ended the session (shutdown, termination through the API), the session closes
+    SessionTerminated,
+    /// The server closed the connection, the shell keeps running for the next client to attach
+    SessionDisconnected,
+    /// An `unlock` envelope was refused, the session stays locked
+    UnlockFailed,
+    /// The client kept sending control frames over the rate limit, the session closes
+    RateLimited,
+    /// A client message was malformed or not allowed, it is ignored and the session goes on
+    InvalidMessage,
+}
+
+impl ErrorCode {
+    /// All error codes
+    pub const ALL: [ErrorCode; 8] = [
+        ErrorCode::SpawnFailed,
+        ErrorCode::WriteFailed,
+        ErrorCode::ResizeFailed,
+        ErrorCode::SessionTerminated,
+        ErrorCode::SessionDisconnected,
+        ErrorCode::UnlockFailed,
+        ErrorCode::RateLimited,
+        ErrorCode::InvalidMessage,
+    ];
+}
+
+/// Severity of a server notice
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum NoticeLevel {
+    /// Informational
+    #[default]
+    Info,
+    /// Something the user should act on, e.g. save work before maintenance
+    Warning,
+    /// Imminent disruption
+    Critical,
+}
+
+impl NoticeLevel {
+    /// All levels, least severe first
+    pub const ALL: [NoticeLevel; 3] = [
+        NoticeLevel::Info,
+        NoticeLevel::Warning,
+        NoticeLevel::Critical,
+    ];
+
+    /// Label of the level
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NoticeLevel::Info => "info",
+            NoticeLevel::Warning => "warning",
+            NoticeLevel::Critical => "critical",
+        }
+    }
+}
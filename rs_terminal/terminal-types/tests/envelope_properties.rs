@@ -0,0 +1,184 @@
+//! Property tests of the `waylon-terminal-v1` envelopes: every variant survives encoding and
+//! decoding unchanged, and decoding arbitrary text returns an error instead of panicking
+//! The same inputs are fuzzed without a time limit by the targets in `rs_terminal/fuzz`
+
+use std::collections::BTreeSet;
+
+use proptest::prelude::*;
+use proptest::strategy::ValueTree;
+use proptest::test_runner::TestRunner;
+use terminal_types::dto::{ColorDepth, TerminalCapabilities, TerminalProfile, TerminalSession};
+use terminal_types::protocol::{ClientEnvelope, ErrorCode, NoticeLevel, ServerEnvelope};
+
+/// Text including control characters, quotes, backslashes and characters outside the BMP
+fn text() -> impl Strategy<Value = String> {
+    prop_oneof![
+        any::<String>(),
+        "[\\x00-\\x1f\"\\\\a-z]{0,16}",
+        "[\u{80}-\u{10ffff}]{0,8}",
+    ]
+}
+
+fn client_envelope() -> impl Strategy<Value = ClientEnvelope> {
+    prop_oneof![
+        (text(), any::<Option<u64>>()).prop_map(|(data, id)| ClientEnvelope::Input { data, id }),
+        (any::<u16>(), any::<u16>())
+            .prop_map(|(columns, rows)| ClientEnvelope::Resize { columns, rows }),
+        Just(ClientEnvelope::ListSessions),
+        Just(ClientEnvelope::ClearScrollback),
+        text().prop_map(|token| ClientEnvelope::Unlock { token }),
+        any::<bool>().prop_map(|latency_probe| ClientEnvelope::Hello { latency_probe }),
+    ]
+}
+
+fn terminal_session() -> impl Strategy<Value = TerminalSession> {
+    (
+        (text(), text(), proptest::option::of(text()), text()),
+        (any::<u16>(), any::<u16>(), proptest::option::of(text())),
+        (any::<u64>(), any::<bool>(), any::<bool>()),
+        proptest::option::of(text()),
+        proptest::collection::hash_map(text(), text(), 0..3),
+        proptest::option::of(0u32..1_000_000),
+    )
+        .prop_map(
+            |(
+                (id, user_id, title, shell_type),
+                (columns, rows, working_directory),
+                (created_at, unicode, read_only),
+                template,
+                labels,
+                latency,
+            )| TerminalSession {
+                id,
+                user_id,
+                title,
+                status: "active".to_string(),
+                columns,
+                rows,
+                working_directory,
+                shell_type,
+                connection_type: "WebSocket".to_string(),
+                created_at,
+                capabilities: TerminalCapabilities {
+                    color_depth: ColorDepth::Ansi256,
+                    unicode,
+                },
+                read_only,
+                template,
+                labels,
+                terminal_profile: TerminalProfile::default(),
+                // Quarters are exact in binary, so they survive the decimal encoding
+                input_latency_p95_ms: latency.map(|quarters| f64::from(quarters) / 4.0),
+            },
+        )
+}
+
+fn server_envelope() -> impl Strategy<Value = ServerEnvelope> {
+    prop_oneof![
+        any::<Option<u64>>()
+            .prop_map(|max_message_bytes| ServerEnvelope::Hello { max_message_bytes }),
+        (any::<u64>(), text()).prop_map(|(seq, data)| ServerEnvelope::Output { seq, data }),
+        (proptest::sample::select(NoticeLevel::ALL.to_vec()), text())
+            .prop_map(|(level, message)| ServerEnvelope::Notice { level, message }),
+        proptest::collection::vec(terminal_session(), 0..3)
+            .prop_map(|sessions| ServerEnvelope::Sessions { sessions }),
+        any::<u64>().prop_map(|id| ServerEnvelope::Ack { id }),
+        (proptest::sample::select(ErrorCode::ALL.to_vec()), text())
+            .prop_map(|(code, message)| ServerEnvelope::Error { code, message }),
+        any::<u64>().prop_map(|idle_secs| ServerEnvelope::Locked { idle_secs }),
+        Just(ServerEnvelope::Unlocked),
+    ]
+}
+
+/// Text shaped like an envelope: a known or unknown `type` with fields of any JSON type
+fn envelope_like_text() -> impl Strategy<Value = String> {
+    let tag = prop_oneof![
+        proptest::sample::select(ClientEnvelope::TYPES.to_vec()).prop_map(str::to_string),
+        text(),
+    ];
+    let value = prop_oneof![
+        Just("null".to_string()),
+        any::<i64>().prop_map(|n| n.to_string()),
+        any::<f64>().prop_map(|n| n.to_string()),
+        text().prop_map(|text| format!("{:?}", text)),
+        Just("[]".to_string()),
+        Just("{}".to_string()),
+    ];
+    let field = proptest::sample::select(vec![
+        "data",
+        "id",
+        "columns",
+        "rows",
+        "token",
+        "latencyProbe",
+    ]);
+    (tag, proptest::collection::vec((field, value), 0..4)).prop_map(|(tag, fields)| {
+        let fields: String = fields
+            .iter()
+            .map(|(name, value)| format!(",\"{}\":{}", name, value))
+            .collect();
+        format!("{{\"type\":{:?}{}}}", tag, fields)
+    })
+}
+
+/// `type` tags produced by `strategy` over many samples
+fn sampled_types<T>(
+    strategy: impl Strategy<Value = T>,
+    type_name: fn(&T) -> &'static str,
+) -> BTreeSet<&'static str> {
+    let mut runner = TestRunner::deterministic();
+    (0..1000)
+        .map(|_| type_name(&strategy.new_tree(&mut runner).unwrap().current()))
+        .collect()
+}
+
+#[test]
+fn strategies_cover_every_envelope() {
+    assert_eq!(
+        sampled_types(client_envelope(), ClientEnvelope::type_name),
+        ClientEnvelope::TYPES.iter().copied().collect()
+    );
+    assert_eq!(
+        sampled_types(server_envelope(), ServerEnvelope::type_name),
+        ServerEnvelope::TYPES.iter().copied().collect()
+    );
+}
+
+proptest! {
+    #[test]
+    fn client_envelopes_round_trip(envelope in client_envelope()) {
+        let json = serde_json::to_string(&envelope).unwrap();
+        let decoded: ClientEnvelope = serde_json::from_str(&json).unwrap();
+        prop_assert_eq!(decoded, envelope);
+    }
+
+    #[test]
+    fn server_envelopes_round_trip(envelope in server_envelope()) {
+        let json = serde_json::to_string(&envelope).unwrap();
+        let decoded: ServerEnvelope = serde_json::from_str(&json).unwrap();
+        prop_assert_eq!(decoded, envelope);
+    }
+
+    #[test]
+    fn arbitrary_text_is_rejected_without_panicking(text in any::<String>()) {
+        let _ = serde_json::from_str::<ClientEnvelope>(&text);
+        let _ = serde_json::from_str::<ServerEnvelope>(&text);
+    }
+
+    #[test]
+    fn envelope_like_text_decodes_consistently(text in envelope_like_text()) {
+        // Whatever decodes must encode to an envelope that decodes the same way
+        if let Ok(envelope) = serde_json::from_str::<ClientEnvelope>(&text) {
+            let json = serde_json::to_string(&envelope).unwrap();
+            prop_assert_eq!(serde_json::from_str::<ClientEnvelope>(&json).unwrap(), envelope);
+        }
+    }
+
+    #[test]
+    fn truncated_envelopes_are_errors(envelope in client_envelope(), cut in any::<prop::sample::Index>()) {
+        let json = serde_json::to_string(&envelope).unwrap();
+        let boundaries: Vec<usize> = (0..json.len()).filter(|&i| json.is_char_boundary(i)).collect();
+        let truncated = &json[..boundaries[cut.index(boundaries.len())]];
+        prop_assert!(serde_json::from_str::<ClientEnvelope>(truncated).is_err());
+    }
+}
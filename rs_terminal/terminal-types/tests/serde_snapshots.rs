@@ -0,0 +1,266 @@
+//! JSON snapshots of the wire types: every field name and enum value the server, the Rust client
+//! and the frontend agree on is spelled out here, and each value survives a round trip
+//! The envelopes of `waylon-terminal-v1` are locked by the fixtures in `conformance/`
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use terminal_types::dto::{
+    ColorDepth, CreateSessionRequest, ErrorResponse, ResizeTerminalRequest, TerminalCapabilities,
+    TerminalProfile, TerminalSession,
+};
+use terminal_types::protocol::{ErrorCode, NoticeLevel, TerminalMessage};
+
+/// Check that `value` serializes to `expected` and deserializes from it to the same JSON again
+fn assert_snapshot<T: Serialize + DeserializeOwned>(value: &T, expected: Value) {
+    let serialized = serde_json::to_value(value).unwrap();
+    assert_eq!(serialized, expected);
+    let decoded: T = serde_json::from_value(serialized).unwrap();
+    assert_eq!(serde_json::to_value(&decoded).unwrap(), expected);
+}
+
+#[test]
+fn create_session_request() {
+    let request = CreateSessionRequest {
+        user_id: "alice".to_string(),
+        title: Some("build".to_string()),
+        working_directory: Some("/srv".to_string()),
+        shell_type: Some("bash".to_string()),
+        columns: Some(120),
+        rows: Some(40),
+        environment_profile: Some("ci".to_string()),
+        environment: HashMap::from([("LANG".to_string(), "C.UTF-8".to_string())]),
+        read_only: true,
+        template: Some("deploy".to_string()),
+        nudge_on_connect: Some(false),
+        terminal_profile: Some(TerminalProfile::NoColor),
+        key_remap: Some("mac".to_string()),
+    };
+    assert_snapshot(
+        &request,
+        json!({
+            "userId": "alice",
+            "title": "build",
+            "workingDirectory": "/srv",
+            "shellType": "bash",
+            "columns": 120,
+            "rows": 40,
+            "environmentProfile": "ci",
+            "environment": { "LANG": "C.UTF-8" },
+            "readOnly": true,
+            "template": "deploy",
+            "nudgeOnConnect": false,
+            "terminalProfile": "no-color",
+            "keyRemap": "mac",
+        }),
+    );
+
+    assert_snapshot(
+        &CreateSessionRequest::default(),
+        json!({
+            "userId": "",
+            "title": null,
+            "workingDirectory": null,
+            "shellType": null,
+            "columns": null,
+            "rows": null,
+            "environmentProfile": null,
+            "readOnly": false,
+            "template": null,
+            "terminalProfile": null,
+            "keyRemap": null,
+        }),
+    );
+}
+
+#[test]
+fn create_session_request_fields_are_optional() {
+    let request: CreateSessionRequest = serde_json::from_str("{}").unwrap();
+    assert_eq!(
+        serde_json::to_value(&request).unwrap(),
+        serde_json::to_value(CreateSessionRequest::default()).unwrap()
+    );
+}
+
+#[test]
+fn resize_terminal_request() {
+    assert_snapshot(
+        &ResizeTerminalRequest {
+            columns: 132,
+            rows: 43,
+        },
+        json!({ "columns": 132, "rows": 43 }),
+    );
+}
+
+#[test]
+fn terminal_session() {
+    let session = TerminalSession {
+        id: "session-1".to_string(),
+        user_id: "alice".to_string(),
+        title: Some("build".to_string()),
+        status: "active".to_string(),
+        columns: 80,
+        rows: 24,
+        working_directory: Some("/srv".to_string()),
+        shell_type: "bash".to_string(),
+        connection_type: "WebSocket".to_string(),
+        created_at: 1_700_000_000,
+        capabilities: TerminalCapabilities {
+            color_depth: ColorDepth::Truecolor,
+            unicode: true,
+        },
+        read_only: false,
+        template: Some("deploy".to_string()),
+        labels: HashMap::from([("team".to_string(), "ops".to_string())]),
+        terminal_profile: TerminalProfile::Full,
+        input_latency_p95_ms: Some(12.5),
+    };
+    // The capabilities are flattened into the session
+    assert_snapshot(
+        &session,
+        json!({
+            "id": "session-1",
+            "userId": "alice",
+            "title": "build",
+            "status": "active",
+            "columns": 80,
+            "rows": 24,
+            "workingDirectory": "/srv",
+            "shellType": "bash",
+            "connectionType": "WebSocket",
+            "createdAt": 1_700_000_000,
+            "colorDepth": "truecolor",
+            "unicode": true,
+            "readOnly": false,
+            "template": "deploy",
+            "labels": { "team": "ops" },
+            "terminalProfile": "full",
+            "inputLatencyP95Ms": 12.5,
+        }),
+    );
+
+    // Optional fields are left out, and older servers that don't send them are still read
+    let minimal = json!({
+        "id": "session-2",
+        "userId": "bob",
+        "title": null,
+        "status": "disconnected",
+        "columns": 100,
+        "rows": 30,
+        "shellType": "sh",
+        "connectionType": "WebTransport",
+        "createdAt": 0,
+    });
+    let decoded: TerminalSession = serde_json::from_value(minimal).unwrap();
+    assert_eq!(decoded.capabilities, TerminalCapabilities::default());
+    assert_eq!(decoded.terminal_profile, TerminalProfile::Full);
+    assert_snapshot(
+        &decoded,
+        json!({
+            "id": "session-2",
+            "userId": "bob",
+            "title": null,
+            "status": "disconnected",
+            "columns": 100,
+            "rows": 30,
+            "shellType": "sh",
+            "connectionType": "WebTransport",
+            "createdAt": 0,
+            "colorDepth": "ansi16",
+            "unicode": false,
+            "readOnly": false,
+            "terminalProfile": "full",
+        }),
+    );
+}
+
+#[test]
+fn error_response() {
+    assert_snapshot(
+        &ErrorResponse {
+            error: true,
+            message: "Session not found".to_string(),
+            code: Some(404),
+        },
+        json!({ "error": true, "message": "Session not found", "code": 404 }),
+    );
+    assert_snapshot(
+        &ErrorResponse {
+            error: true,
+            message: "Internal server error".to_string(),
+            code: None,
+        },
+        json!({ "error": true, "message": "Internal server error", "code": null }),
+    );
+}
+
+#[test]
+fn terminal_message() {
+    for (message, expected) in [
+        (
+            TerminalMessage::Text("ls\r".to_string()),
+            json!({ "Text": "ls\r" }),
+        ),
+        (
+            TerminalMessage::Binary(vec![0, 255]),
+            json!({ "Binary": [0, 255] }),
+        ),
+        (TerminalMessage::Ping(vec![1]), json!({ "Ping": [1] })),
+        (TerminalMessage::Pong(()), json!({ "Pong": null })),
+        (TerminalMessage::Close, json!("Close")),
+    ] {
+        assert_snapshot(&message, expected.clone());
+        let decoded: TerminalMessage = serde_json::from_value(expected).unwrap();
+        assert_eq!(decoded, message);
+    }
+}
+
+#[test]
+fn enum_values() {
+    for (profile, expected) in [
+        (TerminalProfile::Full, "full"),
+        (TerminalProfile::NoColor, "no-color"),
+        (TerminalProfile::Dumb, "dumb"),
+    ] {
+        assert_snapshot(&profile, json!(expected));
+        assert_eq!(profile.as_str(), expected);
+    }
+
+    for (depth, expected) in [
+        (ColorDepth::Monochrome, "monochrome"),
+        (ColorDepth::Ansi16, "ansi16"),
+        (ColorDepth::Ansi256, "ansi256"),
+        (ColorDepth::Truecolor, "truecolor"),
+    ] {
+        assert_snapshot(&depth, json!(expected));
+    }
+
+    let codes: Vec<Value> = ErrorCode::ALL
+        .iter()
+        .map(|code| serde_json::to_value(code).unwrap())
+        .collect();
+    assert_eq!(
+        codes,
+        [
+            "spawn_failed",
+            "write_failed",
+            "resize_failed",
+            "session_terminated",
+            "session_disconnected",
+            "unlock_failed",
+            "rate_limited",
+            "invalid_message",
+        ]
+    );
+    for code in ErrorCode::ALL {
+        let json = serde_json::to_value(code).unwrap();
+        assert_eq!(serde_json::from_value::<ErrorCode>(json).unwrap(), code);
+    }
+
+    for level in NoticeLevel::ALL {
+        assert_snapshot(&level, json!(level.as_str()));
+    }
+}
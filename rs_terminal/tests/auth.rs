@@ -0,0 +1,261 @@
+//! Authentication and session ownership of the REST API, with a custom provider
+
+mod common;
+
+use std::sync::Arc;
+
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::http::{Request, StatusCode};
+use rs_terminal::config::ConfigLoader;
+use rs_terminal::server::build_router;
+use serde_json::{Value, json};
+use tower::ServiceExt;
+
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "bash"
+session_timeout = 1800000
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+
+[shells.bash]
+command = ["bash"]
+"#;
+
+fn router() -> Router {
+    let state = common::state(CONFIG).with_auth_provider(Arc::new(common::HeaderUser));
+    build_router(state)
+}
+
+/// Create a session as `user`, returning its ID
+async fn create_session(router: &Router, user: &str) -> String {
+    let (status, session) =
+        common::call_as(router, "POST", "/api/sessions", user, Some(json!({}))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(session["userId"], user);
+    session["id"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn provider_rejects_a_user() {
+    let router = router();
+
+    let (status, body) = common::call_as(&router, "GET", "/api/sessions", "mallory", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["message"], "Invalid credentials: user is banned");
+
+    let (status, _) = common::call_as(&router, "GET", "/api/sessions", "alice", None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Probes stay open
+    let (status, _) = common::call_as(&router, "GET", "/health", "mallory", None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn sessions_of_other_users_are_forbidden() {
+    let router = router();
+    let session_id = create_session(&router, "alice").await;
+    let uri = format!("/api/sessions/{}", session_id);
+
+    let (status, _) = common::call_as(&router, "GET", &uri, "alice", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = common::call_as(&router, "GET", &uri, "root", None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = common::call_as(&router, "GET", &uri, "bob", None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let resize = json!({"columns": 100, "rows": 30});
+    let (status, _) = common::call_as(
+        &router,
+        "POST",
+        &format!("{}/resize", uri),
+        "bob",
+        Some(resize),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) =
+        common::call_as(&router, "GET", &format!("{}/environment", uri), "bob", None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = common::call_as(&router, "DELETE", &uri, "bob", None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Bob's list doesn't show it, the administrator's does
+    let (_, sessions) = common::call_as(&router, "GET", "/api/sessions", "bob", None).await;
+    assert_eq!(sessions, json!([]));
+    let (_, sessions) = common::call_as(&router, "GET", "/api/sessions", "root", None).await;
+    assert_eq!(sessions.as_array().unwrap().len(), 1);
+
+    let (status, _) = common::call_as(&router, "DELETE", &uri, "alice", None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn sessions_are_created_for_the_caller() {
+    let router = router();
+
+    let request = json!({"userId": "alice"});
+    let (status, _) = common::call_as(
+        &router,
+        "POST",
+        "/api/sessions",
+        "bob",
+        Some(request.clone()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = common::call_as(
+        &router,
+        "POST",
+        "/api/sessions/dry-run",
+        "bob",
+        Some(request.clone()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, session) =
+        common::call_as(&router, "POST", "/api/sessions", "root", Some(request)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(session["userId"], "alice");
+}
+
+#[tokio::test]
+async fn lookup_is_scoped_to_the_caller() {
+    let router = router();
+    for user in ["alice", "bob"] {
+        let request = json!({"title": "INC-1"});
+        let (status, _) =
+            common::call_as(&router, "POST", "/api/sessions", user, Some(request)).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    let (status, sessions) = common::call_as(
+        &router,
+        "GET",
+        "/api/sessions/lookup?title=INC-1",
+        "bob",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let sessions = sessions.as_array().unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0]["userId"], "bob");
+
+    let uri = "/api/sessions/lookup?title=INC-1&userId=alice";
+    let (status, _) = common::call_as(&router, "GET", uri, "bob", None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, sessions) = common::call_as(&router, "GET", uri, "root", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(sessions.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn admin_endpoints_need_the_admin_role() {
+    let router = router();
+
+    let (status, _) = common::call_as(&router, "GET", "/api/admin/config", "alice", None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = common::call_as(&router, "POST", "/api/admin/drain", "alice", None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = common::call_as(&router, "GET", "/api/admin/config", "root", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = common::call_as(&router, "GET", "/api/admin/pty-stats", "root", None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn token_shells_limit_the_shell_type() {
+    let config = format!(
+        r#"{}
+[shells.zsh]
+command = ["zsh"]
+
+[auth]
+provider = "static_token"
+tokens = [{{ token = "bash-only", user_id = "alice", shells = ["bash"] }}]
+"#,
+        CONFIG
+    );
+    let state = common::state(&config);
+    let router = build_router(state);
+
+    let create = |shell_type: &str| {
+        Request::builder()
+            .method("POST")
+            .uri("/api/sessions")
+            .header("authorization", "Bearer bash-only")
+            .header("content-type", "application/json")
+            .body(Body::from(json!({ "shellType": shell_type }).to_string()))
+            .unwrap()
+    };
+    let response = router.clone().oneshot(create("zsh")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = router.clone().oneshot(create("bash")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn admin_config_redacts_the_secrets() {
+    let config = format!(
+        r#"environment_reveal_allowlist = ["EDITOR"]
+{}
+[shells.bash.environment]
+EDITOR = "vim"
+API_KEY = "environment-secret"
+
+[auth]
+provider = "static_token"
+tokens = [{{ token = "admin-token-secret", user_id = "root", roles = ["admin"] }}]
+jwt_secret = "jwt-signing-secret"
+"#,
+        CONFIG
+    );
+    let router = build_router(common::state(&config));
+
+    let request = Request::builder()
+        .uri("/api/admin/config")
+        .header("authorization", "Bearer admin-token-secret")
+        .body(Body::empty())
+        .unwrap();
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let text = String::from_utf8(bytes.to_vec()).unwrap();
+    for secret in [
+        "admin-token-secret",
+        "jwt-signing-secret",
+        "environment-secret",
+    ] {
+        assert!(!text.contains(secret), "{} in {}", secret, text);
+    }
+
+    let config: Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(config["auth"]["tokens"], "<redacted>");
+    assert_eq!(config["auth"]["jwt_secret"], "<redacted>");
+    assert_eq!(config["auth"]["provider"], "static_token");
+    // Unset secrets stay unset, allowlisted variables are shown
+    assert_eq!(config["auth"]["jwt_issuer"], Value::Null);
+    let environment = &config["shells"]["bash"]["environment"];
+    assert_eq!(environment["API_KEY"], "<redacted>");
+    assert_eq!(environment["EDITOR"], "vim");
+}
+
+#[test]
+fn token_shells_must_exist() {
+    let config = format!(
+        r#"{}
+[auth]
+provider = "static_token"
+tokens = [{{ token = "fish-only", user_id = "alice", shells = ["fish"] }}]
+"#,
+        CONFIG
+    );
+    let error = ConfigLoader::new().parse_config(&config).unwrap_err();
+    assert!(error.to_string().contains("unknown shell type: fish"));
+}
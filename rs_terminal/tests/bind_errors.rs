@@ -0,0 +1,147 @@
+//! Ports that can't be bound: the server names the port, the likely cause and the config key to
+//! change; an HTTP bind failure is fatal, a WebTransport one only with `webtransport_required`
+//! (otherwise readiness reports the server as `degraded`)
+#![cfg(unix)]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Longest wait for the server to exit or answer (binding HTTP retries for a few seconds)
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+fn config(http_port: u16, webtransport_port: u16, webtransport_required: bool) -> String {
+    format!(
+        r#"
+http_port = {}
+webtransport_port = {}
+webtransport_required = {}
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+
+[default_shell_config]
+size = {{ columns = 80, rows = 24 }}
+working_directory = "."
+
+[shells.sh]
+command = ["sh"]
+"#,
+        http_port, webtransport_port, webtransport_required
+    )
+}
+
+/// A TCP port nothing listens on
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn start_server(config: &str) -> Child {
+    let mut server = Command::new(env!("CARGO_BIN_EXE_rs_terminal"))
+        .args(["--config", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    server
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(config.as_bytes())
+        .unwrap();
+    server
+}
+
+/// Wait for the server to exit and return whether it succeeded and what it printed to stderr
+fn wait_for_exit(mut server: Child) -> (bool, String) {
+    let deadline = Instant::now() + TIMEOUT;
+    let status = loop {
+        if let Some(status) = server.try_wait().unwrap() {
+            break status;
+        }
+        if Instant::now() > deadline {
+            server.kill().unwrap();
+            panic!("the server didn't exit");
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    let mut stderr = String::new();
+    server
+        .stderr
+        .take()
+        .unwrap()
+        .read_to_string(&mut stderr)
+        .unwrap();
+    (status.success(), stderr)
+}
+
+#[test]
+fn http_port_in_use_is_fatal() {
+    let occupied = TcpListener::bind("0.0.0.0:0").unwrap();
+    let port = occupied.local_addr().unwrap().port();
+
+    let server = start_server(&config(port, free_port(), false));
+    let (success, stderr) = wait_for_exit(server);
+    assert!(!success);
+    assert!(stderr.contains("HTTP (TCP)"), "{}", stderr);
+    assert!(stderr.contains(&format!(":{}", port)), "{}", stderr);
+    assert!(stderr.contains("address already in use"), "{}", stderr);
+    assert!(stderr.contains("`http_port`"), "{}", stderr);
+}
+
+#[test]
+fn required_webtransport_port_in_use_is_fatal() {
+    let occupied = UdpSocket::bind("0.0.0.0:0").unwrap();
+    let port = occupied.local_addr().unwrap().port();
+
+    let server = start_server(&config(free_port(), port, true));
+    let (success, stderr) = wait_for_exit(server);
+    assert!(!success);
+    assert!(stderr.contains("WebTransport (UDP)"), "{}", stderr);
+    assert!(stderr.contains(&format!(":{}", port)), "{}", stderr);
+    assert!(stderr.contains("address already in use"), "{}", stderr);
+    assert!(stderr.contains("`webtransport_port`"), "{}", stderr);
+}
+
+#[test]
+fn optional_webtransport_port_in_use_keeps_http_serving() {
+    let occupied = UdpSocket::bind("0.0.0.0:0").unwrap();
+    let webtransport_port = occupied.local_addr().unwrap().port();
+    let http_port = free_port();
+
+    let mut server = start_server(&config(http_port, webtransport_port, false));
+    let deadline = Instant::now() + TIMEOUT;
+    let mut stream = loop {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", http_port)) {
+            break stream;
+        }
+        if let Some(status) = server.try_wait().unwrap() {
+            panic!("the server exited with {}", status);
+        }
+        assert!(Instant::now() < deadline, "the server isn't listening");
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    stream
+        .write_all(b"GET /health/ready HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    server.kill().unwrap();
+    server.wait().unwrap();
+
+    // Ready, with WebTransport reported as down
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.contains("\"degraded\":true"), "{}", response);
+    assert!(
+        response.contains("Server is ready, WebTransport is unavailable"),
+        "{}",
+        response
+    );
+}
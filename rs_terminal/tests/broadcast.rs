@@ -0,0 +1,142 @@
+//! `POST /api/admin/broadcast` queues a notice for every connected session: `waylon-terminal-v1`
+//! clients get a notice envelope, raw clients a colored line, and the session output log records
+//! it. Sessions that fall behind count as failed instead of holding up the broadcast
+
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use rs_terminal::app_state::AppState;
+use rs_terminal::protocol::{ChannelClient, NoticeLevel, ServerEnvelope, channel_connection};
+use rs_terminal::pty::MockPtyFactory;
+use rs_terminal::server::build_router;
+use rs_terminal::service::handle_terminal_session;
+use serde_json::{Value, json};
+
+/// Longest wait for a message of a session
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Line a raw client shows for a warning about maintenance
+const RAW_NOTICE: &str = "\r\n\x1b[1;33m[warning] Maintenance at 18:00\x1b[0m\r\n";
+
+fn log_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("broadcast-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&directory);
+    directory
+}
+
+fn state(log_directory: &Path) -> AppState {
+    let config = common::config(&format!(
+        "session_output_log = \"{}/{{session_id}}.log\"",
+        log_directory.display()
+    ));
+    common::state(&config).with_pty_factory(Arc::new(MockPtyFactory))
+}
+
+/// Broadcast a notice as the (unauthenticated, thus administrator) caller
+async fn broadcast(state: &AppState, body: Value) -> (StatusCode, Value) {
+    let router = build_router(state.clone());
+    common::call(&router, "POST", "/api/admin/broadcast", Some(body)).await
+}
+
+/// Start an in-process session and wait for its prompt
+async fn start_session(
+    state: &AppState,
+    session_id: &str,
+) -> (ChannelClient, tokio::task::JoinHandle<()>) {
+    let (connection, mut client) = channel_connection(session_id);
+    let task = tokio::spawn(handle_terminal_session(connection, state.clone()));
+    let mut output = String::new();
+    while !output.ends_with("mock$ ") {
+        match common::receive(&mut client).await {
+            ServerEnvelope::Output { data, .. } => output.push_str(&data),
+            ServerEnvelope::Hello { .. } => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+    (client, task)
+}
+
+#[tokio::test]
+async fn notice_reaches_enveloped_and_raw_clients() {
+    let directory = log_directory("delivery");
+    let state = state(&directory);
+    let (mut first, first_task) = start_session(&state, "enveloped-1").await;
+    let (mut second, _) = start_session(&state, "enveloped-2").await;
+
+    let (address, _) = common::start_server(state.clone()).await;
+    let mut raw = common::connect(&format!("ws://{}/ws", address), None).await;
+    common::text_until(&mut raw, "mock$ ").await;
+
+    let body = json!({ "message": "Maintenance at 18:00", "level": "warning" });
+    let (status, response) = broadcast(&state, body).await;
+    assert_eq!(status, StatusCode::OK, "{}", response);
+    assert_eq!(response, json!({ "delivered": 3, "failed": 0 }));
+
+    for client in [&mut first, &mut second] {
+        assert_eq!(
+            common::receive(client).await,
+            ServerEnvelope::Notice {
+                level: NoticeLevel::Warning,
+                message: "Maintenance at 18:00".to_string(),
+            }
+        );
+    }
+    assert_eq!(common::next_text(&mut raw).await, RAW_NOTICE);
+
+    // The output log records what the user saw
+    first.close().await;
+    tokio::time::timeout(TIMEOUT, first_task)
+        .await
+        .expect("session didn't end")
+        .unwrap();
+    let log = std::fs::read_to_string(directory.join("enveloped-1.log")).unwrap();
+    assert!(log.ends_with(RAW_NOTICE), "{:?}", log);
+
+    let _ = std::fs::remove_dir_all(&directory);
+}
+
+#[tokio::test]
+async fn sessions_that_fall_behind_count_as_failed() {
+    let directory = log_directory("failed");
+    let state = state(&directory);
+    let (mut reading, _) = start_session(&state, "reading").await;
+    let (stalled, _) = start_session(&state, "stalled").await;
+
+    // Echoes the stalled client doesn't read fill its connection, until the session stops
+    // taking input
+    while tokio::time::timeout(Duration::from_millis(200), stalled.input("x"))
+        .await
+        .is_ok()
+    {}
+
+    // The stalled session queues a few notices, then refuses them without delaying the others
+    let body = json!({ "message": "Maintenance at 18:00" });
+    let mut results = Vec::new();
+    for _ in 0..5 {
+        let (status, response) = broadcast(&state, body.clone()).await;
+        assert_eq!(status, StatusCode::OK, "{}", response);
+        results.push(response);
+        assert!(matches!(
+            common::receive(&mut reading).await,
+            ServerEnvelope::Notice {
+                level: NoticeLevel::Info,
+                ..
+            }
+        ));
+    }
+    assert_eq!(results[0], json!({ "delivered": 2, "failed": 0 }));
+    assert_eq!(results[4], json!({ "delivered": 1, "failed": 1 }));
+
+    let _ = std::fs::remove_dir_all(&directory);
+}
+
+#[tokio::test]
+async fn empty_notice_is_refused() {
+    let state = state(&log_directory("empty"));
+    let (status, _) = broadcast(&state, json!({ "message": "  " })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,56 @@
+//! Variables clients pass with `"environment"` reach the shell only when their names are in
+//! `client_environment_allowlist`; others, such as `LD_PRELOAD`, are stripped
+
+mod common;
+
+use axum::Router;
+use serde_json::json;
+
+/// The shell prints the variables between markers, then waits to be closed
+fn config(settings: &str) -> String {
+    common::shell_config(
+        settings,
+        r#"["sh", "-c", "stty -echo; printf '[GREETING=%s LD_PRELOAD=%s]' \"$GREETING\" \"$LD_PRELOAD\"; sleep 5"]"#,
+    )
+}
+
+/// Start a server, returning its address and a router on the same state
+async fn start_server(settings: &str) -> (String, Router) {
+    common::start_server(common::state(&config(settings))).await
+}
+
+/// Create a session setting `GREETING` and `LD_PRELOAD`, returning its ID
+async fn create_session(router: &Router) -> String {
+    let body = json!({
+        "environment": { "GREETING": "hello", "LD_PRELOAD": "/tmp/injected.so" },
+    });
+    common::create_session(router, body).await
+}
+
+/// Attach to the session and return the variables the shell printed
+async fn shell_variables(address: &str, session_id: &str) -> String {
+    let mut socket = common::connect(&format!("ws://{}/ws/{}", address, session_id), None).await;
+    let output = common::text_until(&mut socket, "]").await;
+    let start = output.find('[').unwrap();
+    output[start..].trim().to_string()
+}
+
+#[tokio::test]
+async fn disallowed_variable_is_stripped() {
+    let (address, router) = start_server(r#"client_environment_allowlist = ["GREETING"]"#).await;
+    let session_id = create_session(&router).await;
+    assert_eq!(
+        shell_variables(&address, &session_id).await,
+        "[GREETING=hello LD_PRELOAD=]"
+    );
+}
+
+#[tokio::test]
+async fn nothing_is_allowed_by_default() {
+    let (address, router) = start_server("").await;
+    let session_id = create_session(&router).await;
+    assert_eq!(
+        shell_variables(&address, &session_id).await,
+        "[GREETING= LD_PRELOAD=]"
+    );
+}
@@ -0,0 +1,170 @@
+//! `forward_client_locale`: the locale a WebSocket client sends with `?locale=` reaches the shell
+//! it starts, replacing the configured values; names other than `LANG`, `LANGUAGE` and `LC_*` and
+//! values that don't look like locale names are rejected with 400 before the upgrade. Without the
+//! setting the parameter is ignored
+
+mod common;
+
+use std::sync::Arc;
+
+use axum::Router;
+use axum::http::StatusCode;
+use serde_json::Value;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Error as WsError;
+
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+nudge_on_connect = false
+forward_client_locale = {forward}
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+environment = { LANG = "en_US.UTF-8", LC_ALL = "en_US.UTF-8" }
+
+[shells.sh]
+command = ["sh"]
+"#;
+
+struct Server {
+    address: String,
+    router: Router,
+    factory: Arc<common::RecordingPtyFactory>,
+}
+
+impl Server {
+    async fn start(forward: bool) -> Self {
+        let factory = Arc::new(common::RecordingPtyFactory::default());
+        let state = common::state(&CONFIG.replace("{forward}", &forward.to_string()))
+            .with_pty_factory(factory.clone());
+        let (address, router) = common::start_server(state).await;
+        Self {
+            address,
+            router,
+            factory,
+        }
+    }
+
+    /// Start a shell at `path` and return the values of the locale variables it got
+    async fn start_shell(&self, path: &str) -> Vec<(String, String)> {
+        let url = format!("ws://{}{}", self.address, path);
+        let mut socket = common::connect(&url, None).await;
+        // The first output means the shell was started
+        common::next_text(&mut socket).await;
+
+        let configs = self.factory.configs.lock().unwrap();
+        let mut locale: Vec<_> = configs
+            .last()
+            .expect("no PTY was created")
+            .env
+            .iter()
+            .filter(|(name, _)| name.starts_with("LANG") || name.starts_with("LC_"))
+            .cloned()
+            .collect();
+        locale.sort();
+        locale
+    }
+
+    /// Expect the upgrade at `path` to be rejected with 400, returning the message
+    async fn rejection(&self, path: &str) -> String {
+        let url = format!("ws://{}{}", self.address, path);
+        match connect_async(url).await {
+            Err(WsError::Http(response)) => {
+                assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+                let body: Value =
+                    serde_json::from_slice(response.body().as_deref().unwrap()).unwrap();
+                body["message"].as_str().unwrap().to_string()
+            }
+            other => panic!("the upgrade wasn't rejected: {:?}", other.map(|_| ())),
+        }
+    }
+}
+
+fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+#[tokio::test]
+async fn forwarded_locale_replaces_the_configured_one() {
+    let server = Server::start(true).await;
+
+    let locale = server
+        .start_shell("/ws?locale=LANG=de_DE.UTF-8,LC_TIME=C,LC_ALL=de_DE.UTF-8@euro")
+        .await;
+
+    assert_eq!(
+        locale,
+        pairs(&[
+            ("LANG", "de_DE.UTF-8"),
+            ("LC_ALL", "de_DE.UTF-8@euro"),
+            ("LC_TIME", "C"),
+        ])
+    );
+}
+
+#[tokio::test]
+async fn forwarded_locale_is_attributed_to_the_client() {
+    let server = Server::start(true).await;
+    server
+        .start_shell("/ws/localized?locale=LANG=de_DE.UTF-8")
+        .await;
+
+    let uri = "/api/sessions/localized/environment";
+    let (status, body) = common::call(&server.router, "GET", uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let source = |name: &str| {
+        body["variables"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|variable| variable["name"] == name)
+            .map(|variable| variable["source"].clone())
+    };
+    assert_eq!(source("LANG"), Some(Value::from("client_locale")));
+    assert_eq!(source("LC_ALL"), Some(Value::from("default_config")));
+}
+
+#[tokio::test]
+async fn locale_that_cannot_be_applied_is_rejected() {
+    let server = Server::start(true).await;
+
+    assert_eq!(
+        server.rejection("/ws?locale=LD_PRELOAD=/tmp/evil.so").await,
+        "Locale variable LD_PRELOAD can't be forwarded"
+    );
+    assert_eq!(
+        server.rejection("/ws/attached?locale=LANG=$(id)").await,
+        "Invalid value for locale variable LANG"
+    );
+    let long = format!("/ws?locale=LC_TIME={}", "a".repeat(65));
+    assert_eq!(
+        server.rejection(&long).await,
+        "Invalid value for locale variable LC_TIME"
+    );
+    assert_eq!(
+        server.rejection("/ws?locale=LANG").await,
+        "Invalid locale setting LANG, expected NAME=value"
+    );
+    // No shell was started for any of them
+    assert!(server.factory.configs.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn locale_is_ignored_unless_forwarding_is_enabled() {
+    let server = Server::start(false).await;
+
+    let locale = server
+        .start_shell("/ws?locale=LANG=de_DE.UTF-8,LD_PRELOAD=/tmp/evil.so")
+        .await;
+
+    assert_eq!(
+        locale,
+        pairs(&[("LANG", "en_US.UTF-8"), ("LC_ALL", "en_US.UTF-8")])
+    );
+}
@@ -0,0 +1,78 @@
+//! Session timestamps and elapsed time come from the clock in `AppState`
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use rs_terminal::app_state::{Clock, MockClock, SystemClock};
+use rs_terminal::server::build_router;
+use serde_json::json;
+
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "bash"
+session_timeout = 1800000
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+
+[shells.bash]
+command = ["bash"]
+"#;
+
+/// 2023-11-14T22:13:20Z
+const START: u64 = 1_700_000_000;
+
+#[tokio::test]
+async fn session_timestamps_follow_the_clock() {
+    let clock = Arc::new(MockClock::new(START));
+    let state = common::state(CONFIG).with_clock(clock.clone());
+    let router = build_router(state.clone());
+
+    let (status, session) = common::call(&router, "POST", "/api/sessions", Some(json!({}))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(session["createdAt"], START);
+    let session_id = session["id"].as_str().unwrap();
+
+    clock.advance(Duration::from_secs(90));
+    let uri = format!("/api/sessions/{}/resize", session_id);
+    let body = json!({"columns": 100, "rows": 30});
+    let (status, _) = common::call(&router, "POST", &uri, Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let session = state.get_session(session_id).await.unwrap();
+    assert_eq!(session.created_at, START);
+    assert_eq!(session.updated_at, START + 90);
+
+    // A clock stepped backwards doesn't break anything
+    clock.set_unix(START - 3600);
+    let body = json!({"columns": 80, "rows": 24});
+    let (status, _) = common::call(&router, "POST", &uri, Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[test]
+fn mock_clock_only_moves_when_advanced() {
+    let clock = MockClock::new(START);
+    let instant = clock.now_instant();
+    std::thread::sleep(Duration::from_millis(5));
+    assert_eq!(clock.now_instant(), instant);
+    assert_eq!(clock.now_unix(), START);
+
+    clock.advance(Duration::from_secs(30));
+    assert_eq!(clock.now_instant() - instant, Duration::from_secs(30));
+    assert_eq!(clock.now_unix(), START + 30);
+}
+
+#[test]
+fn system_clock_timestamps_never_go_backwards() {
+    let clock = SystemClock::new();
+    let mut previous = clock.now_unix();
+    for _ in 0..1000 {
+        let now = clock.now_unix();
+        assert!(now >= previous);
+        previous = now;
+    }
+}
@@ -0,0 +1,91 @@
+//! `resolve_command_path`: bare command names are looked up in the shell's `PATH` before the
+//! spawn, a missing one fails early with a message that doesn't reveal the searched `PATH`
+
+mod common;
+
+use std::time::Duration;
+
+use rs_terminal::protocol::{ErrorCode, ServerEnvelope};
+use rs_terminal::pty::find_in_path;
+
+/// Directory only the shell's `PATH` mentions, it must not reach the client
+const SECRET_DIRECTORY: &str = "/opt/internal-tools/bin";
+
+/// Longest wait for the error of the session
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn path_with(directory: &str) -> Vec<(String, String)> {
+    vec![("PATH".to_string(), directory.to_string())]
+}
+
+#[tokio::test]
+async fn bare_command_is_resolved() {
+    let directory = std::env::temp_dir().join(format!("command-path-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let command = directory.join("present-shell");
+    std::fs::write(&command, "#!/bin/sh\n").unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&command, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    let env = path_with(&format!("{}:{}", SECRET_DIRECTORY, directory.display()));
+    let found = find_in_path("present-shell", &env).await;
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(found, Ok(Some(command)));
+
+    // Commands with a path are spawned as given
+    assert_eq!(find_in_path("./present-shell", &env).await, Ok(None));
+}
+
+#[tokio::test]
+async fn missing_command_is_reported_without_the_path() {
+    let error = find_in_path("absent-shell", &path_with(SECRET_DIRECTORY)).await;
+    assert_eq!(
+        error,
+        Err("command absent-shell was not found in PATH".to_string())
+    );
+}
+
+#[tokio::test]
+async fn missing_shell_fails_the_session_early() {
+    let config = format!(
+        r#"
+pty_implementation = "portable_pty"
+default_shell_type = "missing"
+session_timeout = 1800000
+nudge_on_connect = false
+resolve_command_path = true
+
+[default_shell_config]
+size = {{ columns = 80, rows = 24 }}
+working_directory = "."
+
+[shells.missing]
+command = ["absent-shell"]
+environment = {{ PATH = "{}" }}
+"#,
+        SECRET_DIRECTORY
+    );
+    let mut client = common::attach(&common::state(&config), "missing-shell");
+
+    let (code, message) = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match client.receive().await {
+                Some(Ok(ServerEnvelope::Error { code, message })) => return (code, message),
+                Some(Ok(_)) => continue,
+                other => panic!("no error before {:?}", other),
+            }
+        }
+    })
+    .await
+    .expect("no error from the session");
+    assert_eq!(code, ErrorCode::SpawnFailed);
+    assert!(
+        message.contains("command absent-shell was not found in PATH"),
+        "{}",
+        message
+    );
+    assert!(!message.contains(SECRET_DIRECTORY), "{}", message);
+}
@@ -0,0 +1,343 @@
+//! Fixtures shared by the integration tests: the configuration and state of a test server, the
+//! server itself on a local port, calls to its REST API and clients reading a session's output
+//! Every test crate uses part of them only
+
+#![allow(dead_code)]
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::http::request::Parts;
+use axum::http::{Request, Response, StatusCode};
+use futures_util::{SinkExt, StreamExt};
+use rs_terminal::app_state::{AppState, DiagnosticsStore};
+use rs_terminal::auth::{ADMIN_ROLE, AuthContext, AuthError, AuthProvider};
+use rs_terminal::config::ConfigLoader;
+use rs_terminal::protocol::{
+    ChannelClient, ClientEnvelope, ServerEnvelope, Subprotocol, channel_connection,
+};
+use rs_terminal::pty::{AsyncPty, MockPtyFactory, PtyConfig, PtyError, PtyFactory};
+use rs_terminal::server::build_router;
+use rs_terminal::service::handle_terminal_session;
+use serde_json::Value;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::Request as WsRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
+use tower::ServiceExt;
+
+/// Longest wait for a message of the server
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Header naming the user of a request to a server authenticating with [`HeaderUser`]
+pub const USER_HEADER: &str = "x-user";
+
+pub type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Configuration whose default shell `sh` runs `["sh"]`, with extra top-level `settings`
+pub fn config(settings: &str) -> String {
+    shell_config(settings, r#"["sh"]"#)
+}
+
+/// Configuration whose default shell `sh` runs `command`, with extra top-level `settings`
+pub fn shell_config(settings: &str, command: &str) -> String {
+    format!(
+        r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+nudge_on_connect = false
+{}
+
+[default_shell_config]
+size = {{ columns = 80, rows = 24 }}
+working_directory = "."
+
+[shells.sh]
+command = {}
+"#,
+        settings, command
+    )
+}
+
+/// Application state of `config`
+pub fn state(config: &str) -> AppState {
+    let config = ConfigLoader::new().parse_config(config).unwrap();
+    AppState::new(config, Arc::new(DiagnosticsStore::new()))
+}
+
+/// Serve `state` on a local port, returning its address and a router on the same state
+pub async fn start_server(state: AppState) -> (String, Router) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let router = build_router(state);
+    let server = router.clone();
+    tokio::spawn(async move { axum::serve(listener, server).await });
+    (address, router)
+}
+
+/// Mock PTYs that remember the configuration they were created with
+#[derive(Default)]
+pub struct RecordingPtyFactory {
+    pub configs: Mutex<Vec<PtyConfig>>,
+}
+
+#[async_trait]
+impl PtyFactory for RecordingPtyFactory {
+    async fn create(&self, config: &PtyConfig) -> Result<Box<dyn AsyncPty>, PtyError> {
+        self.configs.lock().unwrap().push(config.clone());
+        MockPtyFactory.create(config).await
+    }
+
+    fn name(&self) -> &'static str {
+        "recording"
+    }
+}
+
+/// Authenticates the user named by [`USER_HEADER`]: `root` is an admin, `mallory` is banned
+pub struct HeaderUser;
+
+#[async_trait]
+impl AuthProvider for HeaderUser {
+    async fn authenticate(&self, parts: &Parts) -> Result<AuthContext, AuthError> {
+        let user = parts
+            .headers
+            .get(USER_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .ok_or(AuthError::MissingCredentials)?;
+        if user == "mallory" {
+            return Err(AuthError::InvalidCredentials("user is banned".to_string()));
+        }
+        let roles = if user == "root" {
+            vec![ADMIN_ROLE.to_string()]
+        } else {
+            Vec::new()
+        };
+        Ok(AuthContext {
+            user_id: user.to_string(),
+            roles,
+            expires_at: None,
+            shells: None,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "header"
+    }
+}
+
+/// Send a request to the REST API with the given headers and JSON body
+pub async fn request(
+    router: &Router,
+    method: &str,
+    uri: &str,
+    headers: &[(&str, &str)],
+    body: Option<Value>,
+) -> Response<Body> {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json");
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let body = body.map_or_else(Body::empty, |body| Body::from(body.to_string()));
+    router
+        .clone()
+        .oneshot(request.body(body).unwrap())
+        .await
+        .unwrap()
+}
+
+/// Body of a response, `null` when it isn't JSON
+pub async fn json_body(response: Response<Body>) -> Value {
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap_or(Value::Null)
+}
+
+/// Call the REST API, returning the status and the JSON body
+pub async fn call(
+    router: &Router,
+    method: &str,
+    uri: &str,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let response = request(router, method, uri, &[], body).await;
+    (response.status(), json_body(response).await)
+}
+
+/// Call the REST API as `user` of [`HeaderUser`]
+pub async fn call_as(
+    router: &Router,
+    method: &str,
+    uri: &str,
+    user: &str,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let response = request(router, method, uri, &[(USER_HEADER, user)], body).await;
+    (response.status(), json_body(response).await)
+}
+
+/// Create a session through the REST API, returning its ID
+pub async fn create_session(router: &Router, body: Value) -> String {
+    let (status, session) = call(router, "POST", "/api/sessions", Some(body)).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", session);
+    session["id"].as_str().unwrap().to_string()
+}
+
+/// Attach a client to the session, creating it under `session_id` if it doesn't exist
+pub fn attach(state: &AppState, session_id: &str) -> ChannelClient {
+    let (connection, client) = channel_connection(session_id);
+    tokio::spawn(handle_terminal_session(connection, state.clone()));
+    client
+}
+
+/// Next message of the session
+pub async fn receive(client: &mut ChannelClient) -> ServerEnvelope {
+    tokio::time::timeout(TIMEOUT, client.receive())
+        .await
+        .expect("no message from the session")
+        .expect("session closed")
+        .expect("malformed message")
+}
+
+/// Wait for the first output of the session, which means its shell was started
+pub async fn started(client: &mut ChannelClient) {
+    while !matches!(receive(client).await, ServerEnvelope::Output { .. }) {}
+}
+
+/// Receive output until `done` accepts it, returning all of it
+pub async fn output_matching(client: &mut ChannelClient, done: impl Fn(&str) -> bool) -> String {
+    let mut output = String::new();
+    while !done(&output) {
+        let message = tokio::time::timeout(TIMEOUT, client.receive())
+            .await
+            .unwrap_or_else(|_| panic!("output stopped at {:?}", output))
+            .expect("session closed")
+            .expect("malformed message");
+        if let ServerEnvelope::Output { data, .. } = message {
+            output.push_str(&data);
+        }
+    }
+    output
+}
+
+/// Receive output until it ends with `expected`, returning all of it
+pub async fn output_until(client: &mut ChannelClient, expected: &str) -> String {
+    output_matching(client, |output| output.ends_with(expected)).await
+}
+
+/// WebSocket request to `url` offering `subprotocol`
+pub fn ws_request(url: &str, subprotocol: Option<Subprotocol>) -> WsRequest {
+    let mut request = url.into_client_request().unwrap();
+    if let Some(subprotocol) = subprotocol {
+        request.headers_mut().insert(
+            "sec-websocket-protocol",
+            HeaderValue::from_static(subprotocol.name()),
+        );
+    }
+    request
+}
+
+/// Connect to `url` offering `subprotocol`
+pub async fn connect(url: &str, subprotocol: Option<Subprotocol>) -> Socket {
+    let (socket, _) = connect_async(ws_request(url, subprotocol)).await.unwrap();
+    socket
+}
+
+/// Connect to `url` with `waylon-terminal-v1`, skipping the hello
+pub async fn connect_v1(url: &str) -> Socket {
+    let mut socket = connect(url, Some(Subprotocol::V1)).await;
+    let hello = next_envelope(&mut socket).await;
+    assert!(matches!(hello, ServerEnvelope::Hello { .. }), "{:?}", hello);
+    socket
+}
+
+/// Send an envelope to a `waylon-terminal-v1` server
+pub async fn send(socket: &mut Socket, envelope: &ClientEnvelope) {
+    let text = serde_json::to_string(envelope).unwrap();
+    socket.send(Message::Text(text)).await.unwrap();
+}
+
+/// Send input to a `waylon-terminal-v1` server, acknowledged if it has an `id`
+pub async fn input(socket: &mut Socket, data: &str, id: Option<u64>) {
+    let envelope = ClientEnvelope::Input {
+        data: data.to_string(),
+        id,
+    };
+    send(socket, &envelope).await;
+}
+
+/// Next frame of the server
+pub async fn next_frame(socket: &mut Socket) -> Message {
+    tokio::time::timeout(TIMEOUT, socket.next())
+        .await
+        .expect("no frame from the server")
+        .expect("connection closed")
+        .unwrap()
+}
+
+/// Next text frame of the server
+pub async fn next_text(socket: &mut Socket) -> String {
+    loop {
+        if let Message::Text(text) = next_frame(socket).await {
+            return text;
+        }
+    }
+}
+
+/// Next envelope of a `waylon-terminal-v1` server
+pub async fn next_envelope(socket: &mut Socket) -> ServerEnvelope {
+    serde_json::from_str(&next_text(socket).await).unwrap()
+}
+
+/// Receive text frames until `found` picks something out of one, returning it
+pub async fn text_matching<T>(socket: &mut Socket, found: impl Fn(&str) -> Option<T>) -> T {
+    loop {
+        if let Some(found) = found(&next_text(socket).await) {
+            return found;
+        }
+    }
+}
+
+/// Receive text frames until they end with `expected`, returning all of them
+pub async fn text_until(socket: &mut Socket, expected: &str) -> String {
+    let mut output = String::new();
+    while !output.ends_with(expected) {
+        output.push_str(&next_text(socket).await);
+    }
+    output
+}
+
+/// Receive output envelopes until they end with `expected`, returning all of the output
+pub async fn envelope_output_until(socket: &mut Socket, expected: &str) -> String {
+    let mut output = String::new();
+    while !output.ends_with(expected) {
+        if let ServerEnvelope::Output { data, .. } = next_envelope(socket).await {
+            output.push_str(&data);
+        }
+    }
+    output
+}
+
+/// Receive output envelopes only until their data ends with `expected`, returning them numbered
+pub async fn numbered_output_until(socket: &mut Socket, expected: &str) -> Vec<(u64, String)> {
+    let mut chunks: Vec<(u64, String)> = Vec::new();
+    while !chunks
+        .iter()
+        .map(|(_, data)| data.as_str())
+        .collect::<String>()
+        .ends_with(expected)
+    {
+        match next_envelope(socket).await {
+            ServerEnvelope::Output { seq, data } => chunks.push((seq, data)),
+            other => panic!("unexpected envelope {:?}", other),
+        }
+    }
+    chunks
+}
@@ -0,0 +1,60 @@
+//! Loading the configuration from any reader (`--config -` reads stdin this way)
+
+use std::io::{self, Read};
+
+use rs_terminal::config::{ConfigError, ConfigLoader};
+
+/// Reader failing like a closed pipe
+struct BrokenReader;
+
+impl Read for BrokenReader {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::BrokenPipe.into())
+    }
+}
+
+#[test]
+fn shipped_config_loads_from_a_reader() {
+    let config = ConfigLoader::new()
+        .load_config_from_reader(include_str!("../config.toml").as_bytes())
+        .unwrap();
+    assert_eq!(config.default_shell_type, "bash");
+    assert_eq!(config.http_port, 8080);
+    assert!(config.shells.contains_key("bash"));
+}
+
+#[test]
+fn reader_config_is_parsed_like_a_file() {
+    let toml = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+http_port = 9000
+session_timeout = 1800000
+
+[default_shell_config]
+size = { columns = 100, rows = 30 }
+working_directory = "."
+
+[shells.sh]
+command = ["sh"]
+"#;
+    let config = ConfigLoader::new()
+        .load_config_from_reader(io::Cursor::new(toml))
+        .unwrap();
+    assert_eq!(config.default_shell_type, "sh");
+    assert_eq!(config.http_port, 9000);
+    assert_eq!(config.shells["sh"].command, vec!["sh".to_string()]);
+}
+
+#[test]
+fn reader_errors_are_reported() {
+    let loader = ConfigLoader::new();
+    assert!(matches!(
+        loader.load_config_from_reader("http_port = [".as_bytes()),
+        Err(ConfigError::ParseError(_))
+    ));
+    assert!(matches!(
+        loader.load_config_from_reader(BrokenReader),
+        Err(ConfigError::FileOpenError(_))
+    ));
+}
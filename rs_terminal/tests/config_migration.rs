@@ -0,0 +1,142 @@
+//! Configuration files of an older layout are refused with a pointer to `migrate-config`, which
+//! converts them key by key, reports each change and checks the result
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use rs_terminal::config::{ConfigError, ConfigLoader, migrate_config};
+
+/// Every kind of legacy key: top-level defaults, `cols`, `env` and a command line string
+const LEGACY: &str = r#"
+pty_implementation = "portable_pty"
+shell = "sh"
+session_timeout = 1800000
+default_size = { cols = 100, rows = 30 }
+default_working_directory = "."
+
+[shells.sh]
+command = "sh -l"
+env = { EDITOR = "vi" }
+"#;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("config-migration-{}-{}", std::process::id(), name))
+}
+
+fn migrate(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rs_terminal"))
+        .arg("migrate-config")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn legacy_layout_is_refused_with_its_keys() {
+    let error = ConfigLoader::new().parse_config(LEGACY).unwrap_err();
+
+    assert!(matches!(error, ConfigError::LegacyLayout(_)), "{:?}", error);
+    assert_eq!(
+        error.to_string(),
+        "Configuration uses keys of an older layout (shell, default_size, \
+         default_working_directory, shells.sh.env, shells.sh.command); convert it with \
+         `rs_terminal migrate-config <file> --write <file>`"
+    );
+}
+
+#[test]
+fn legacy_layout_is_converted_key_by_key() {
+    let migration = migrate_config(LEGACY).unwrap();
+
+    assert_eq!(
+        migration.changes,
+        [
+            "moved `shell` to `default_shell_type`",
+            "moved `default_size` to `default_shell_config.size`",
+            "moved `default_working_directory` to `default_shell_config.working_directory`",
+            "moved `default_shell_config.size.cols` to `default_shell_config.size.columns`",
+            "moved `shells.sh.env` to `shells.sh.environment`",
+            "split `shells.sh.command` into an array (check arguments that contained quoted spaces)",
+        ]
+    );
+    let config = ConfigLoader::new().parse_config(&migration.config).unwrap();
+    assert_eq!(config.default_shell_type, "sh");
+    assert_eq!(config.default_shell_config.size.columns, 100);
+    assert_eq!(config.default_shell_config.size.rows, 30);
+    let shell = &config.shells["sh"];
+    assert_eq!(shell.command, ["sh", "-l"]);
+    assert_eq!(shell.environment.as_ref().unwrap()["EDITOR"], "vi");
+}
+
+#[test]
+fn legacy_key_does_not_replace_a_current_one() {
+    let content = LEGACY.replace(
+        "shell = \"sh\"",
+        "shell = \"bash\"\ndefault_shell_type = \"sh\"",
+    );
+
+    let migration = migrate_config(&content).unwrap();
+
+    assert_eq!(
+        migration.changes[0],
+        "removed `shell`: `default_shell_type` is already set"
+    );
+    let config = ConfigLoader::new().parse_config(&migration.config).unwrap();
+    assert_eq!(config.default_shell_type, "sh");
+}
+
+#[test]
+fn current_layout_needs_no_conversion() {
+    let migration = migrate_config(include_str!("../config.toml")).unwrap();
+
+    assert!(migration.changes.is_empty(), "{:?}", migration.changes);
+    ConfigLoader::new().parse_config(&migration.config).unwrap();
+}
+
+#[test]
+fn migrate_config_writes_the_converted_file() {
+    let (input, converted) = (temp_path("old.toml"), temp_path("new.toml"));
+    std::fs::write(&input, LEGACY).unwrap();
+
+    let output = migrate(&[
+        input.to_str().unwrap(),
+        "--write",
+        converted.to_str().unwrap(),
+    ]);
+    let written = std::fs::read_to_string(&converted);
+    let _ = std::fs::remove_file(&input);
+    let _ = std::fs::remove_file(&converted);
+
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("moved `shell` to `default_shell_type`\n"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.ends_with(&format!(
+        "Converted configuration written to {}\n",
+        converted.display()
+    )));
+    assert!(output.stdout.is_empty());
+    ConfigLoader::new().parse_config(&written.unwrap()).unwrap();
+}
+
+#[test]
+fn migrate_config_refuses_a_result_that_is_still_invalid() {
+    let input = temp_path("incomplete.toml");
+    // `session_timeout` didn't exist in the old layout
+    std::fs::write(&input, LEGACY.replace("session_timeout = 1800000\n", "")).unwrap();
+
+    let output = migrate(&[input.to_str().unwrap()]);
+    let _ = std::fs::remove_file(&input);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("The converted configuration is not valid yet: "),
+        "{}",
+        stderr
+    );
+    assert!(output.stdout.is_empty());
+}
@@ -0,0 +1,116 @@
+//! Configuration reload on SIGHUP: sessions created afterwards use the reloaded configuration,
+//! sessions created before keep theirs, and an invalid file leaves the configuration as it was
+
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rs_terminal::app_state::{AppState, DiagnosticsStore};
+use rs_terminal::config::ConfigLoader;
+use rs_terminal::server::{build_router, spawn_config_reloader};
+use serde_json::{Value, json};
+
+/// Longest wait for a reload or for a session to start
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Write a configuration with `default_shell_type` set to `default_shell`
+fn write_config(path: &Path, default_shell: &str) {
+    let config = format!(
+        r#"
+pty_implementation = "portable_pty"
+default_shell_type = "{}"
+session_timeout = 1800000
+nudge_on_connect = false
+
+[default_shell_config]
+size = {{ columns = 80, rows = 24 }}
+working_directory = "."
+
+[shells.first]
+command = ["first-shell"]
+
+[shells.second]
+command = ["second-shell"]
+"#,
+        default_shell
+    );
+    std::fs::write(path, config).unwrap();
+}
+
+fn config_path() -> PathBuf {
+    std::env::temp_dir().join(format!("config-reload-{}.toml", std::process::id()))
+}
+
+async fn request(state: &AppState, method: &str, uri: &str) -> Value {
+    let router = build_router(state.clone());
+    let (status, body) = common::call(&router, method, uri, Some(json!({}))).await;
+    assert!(status.is_success(), "{}", status);
+    body
+}
+
+/// Create a session with the default shell and start it, returning the session and the command
+/// its PTY runs
+async fn start_session(state: &AppState, factory: &common::RecordingPtyFactory) -> (Value, String) {
+    let session = request(state, "POST", "/api/sessions").await;
+    let mut client = common::attach(state, session["id"].as_str().unwrap());
+    common::started(&mut client).await;
+    tokio::spawn(async move { while client.receive().await.is_some() {} });
+    let command = factory.configs.lock().unwrap().pop().unwrap().command;
+    (session, command)
+}
+
+/// Send SIGHUP to the test process, which the reloader handles
+fn hangup() {
+    // SAFETY: signalling our own process has no memory safety requirements
+    let result = unsafe { libc::kill(libc::getpid(), libc::SIGHUP) };
+    assert_eq!(result, 0);
+}
+
+/// Wait until the configuration in effect uses `default_shell`
+async fn reloaded(state: &AppState, default_shell: &str) {
+    tokio::time::timeout(TIMEOUT, async {
+        while state.with_current_config().config.default_shell_type != default_shell {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("the configuration wasn't reloaded to {}", default_shell));
+}
+
+// One test only: the signal reaches every reloader in the process
+#[tokio::test]
+async fn sessions_after_a_reload_use_the_new_default_shell() {
+    let path = config_path();
+    write_config(&path, "first");
+    let config = ConfigLoader::new().load_config(Some(&path)).unwrap();
+    let factory = Arc::new(common::RecordingPtyFactory::default());
+    let state =
+        AppState::new(config, Arc::new(DiagnosticsStore::new())).with_pty_factory(factory.clone());
+    spawn_config_reloader(state.clone(), Some(path.clone()));
+
+    let (before, command) = start_session(&state, &factory).await;
+    assert_eq!(before["shellType"], "first");
+    assert_eq!(command, "first-shell");
+
+    write_config(&path, "second");
+    hangup();
+    reloaded(&state, "second").await;
+
+    let (after, command) = start_session(&state, &factory).await;
+    assert_eq!(after["shellType"], "second");
+    assert_eq!(command, "second-shell");
+    // The earlier session keeps its shell
+    let uri = format!("/api/sessions/{}", before["id"].as_str().unwrap());
+    assert_eq!(request(&state, "GET", &uri).await["shellType"], "first");
+
+    // An invalid file is not installed
+    std::fs::write(&path, "default_shell_type = ").unwrap();
+    hangup();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let (_, command) = start_session(&state, &factory).await;
+    assert_eq!(command, "second-shell");
+
+    let _ = std::fs::remove_file(&path);
+}
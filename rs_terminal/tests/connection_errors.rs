@@ -0,0 +1,106 @@
+//! The session loop tells connection errors apart: a timeout only affects one receive, a closed
+//! connection ends the session
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use rs_terminal::protocol::{
+    ChannelClient, ChannelConnection, ConnectionError, ConnectionResult, ConnectionType,
+    Subprotocol, TerminalConnection, TerminalMessage, channel_connection,
+};
+use rs_terminal::pty::MockPtyFactory;
+use rs_terminal::service::handle_terminal_session;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Longest wait for output or the end of the session
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// In-process connection whose receive can also fail with an injected error
+#[derive(Debug)]
+struct FaultyConnection {
+    inner: ChannelConnection,
+    errors: mpsc::UnboundedReceiver<ConnectionError>,
+}
+
+#[async_trait]
+impl TerminalConnection for FaultyConnection {
+    async fn send_text(&mut self, message: &str) -> ConnectionResult<()> {
+        self.inner.send_text(message).await
+    }
+
+    async fn send_binary(&mut self, data: &[u8]) -> ConnectionResult<()> {
+        self.inner.send_binary(data).await
+    }
+
+    async fn receive(&mut self) -> Option<ConnectionResult<TerminalMessage>> {
+        tokio::select! {
+            biased;
+
+            Some(error) = self.errors.recv() => Some(Err(error)),
+            message = self.inner.receive() => message,
+        }
+    }
+
+    async fn close(&mut self) -> ConnectionResult<()> {
+        self.inner.close().await
+    }
+
+    fn id(&self) -> &str {
+        self.inner.id()
+    }
+
+    fn connection_type(&self) -> ConnectionType {
+        self.inner.connection_type()
+    }
+
+    fn is_alive(&self) -> bool {
+        self.inner.is_alive()
+    }
+
+    fn subprotocol(&self) -> Subprotocol {
+        self.inner.subprotocol()
+    }
+}
+
+/// Start a session on a mock PTY, returning its client, the sender of injected errors and the
+/// session task
+async fn start_session() -> (
+    ChannelClient,
+    mpsc::UnboundedSender<ConnectionError>,
+    JoinHandle<()>,
+) {
+    let state = common::state(&common::config("")).with_pty_factory(Arc::new(MockPtyFactory));
+    let (inner, mut client) = channel_connection("faulty");
+    let (errors_tx, errors) = mpsc::unbounded_channel();
+    let session = tokio::spawn(handle_terminal_session(
+        FaultyConnection { inner, errors },
+        state,
+    ));
+    common::output_until(&mut client, "mock$ ").await;
+    (client, errors_tx, session)
+}
+
+#[tokio::test]
+async fn timeout_keeps_the_session() {
+    let (mut client, errors, session) = start_session().await;
+
+    errors.send(ConnectionError::Timeout).unwrap();
+    client.input("still here").await.unwrap();
+    common::output_until(&mut client, "still here").await;
+    assert!(!session.is_finished());
+}
+
+#[tokio::test]
+async fn closed_connection_ends_the_session() {
+    let (_client, errors, session) = start_session().await;
+
+    errors.send(ConnectionError::ConnectionClosed).unwrap();
+    tokio::time::timeout(TIMEOUT, session)
+        .await
+        .expect("the session kept running")
+        .unwrap();
+}
@@ -0,0 +1,128 @@
+//! Ping frames of a client are rate limited by the session's clock: frames within the limit keep
+//! the session going, a flood closes it with a `rate_limited` error. The WebSocket layer answers
+//! every ping with a pong before the session sees it, so the pongs themselves aren't throttled
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use rs_terminal::app_state::MockClock;
+use rs_terminal::protocol::{ClientEnvelope, ErrorCode, ServerEnvelope, Subprotocol};
+use rs_terminal::pty::MockPtyFactory;
+use tokio_tungstenite::tungstenite::Message;
+
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "bash"
+session_timeout = 1800000
+nudge_on_connect = false
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+
+[shells.bash]
+command = ["bash"]
+"#;
+
+/// Ping frames a client may send at once plus the frames over the limit that close the session
+const FLOOD: usize = 20 + 200;
+
+/// Longest wait for a message of the session
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Start a server on a mock PTY and clock and connect to it as a `waylon-terminal-v1` client
+async fn connect(clock: Arc<MockClock>) -> common::Socket {
+    let state = common::state(CONFIG)
+        .with_pty_factory(Arc::new(MockPtyFactory))
+        .with_clock(clock);
+    let (address, _) = common::start_server(state).await;
+    common::connect(&format!("ws://{}/ws", address), Some(Subprotocol::V1)).await
+}
+
+/// Receive until the session sends a pong
+async fn pong(socket: &mut common::Socket) {
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match socket.next().await {
+                Some(Ok(Message::Pong(_))) => return,
+                Some(Ok(_)) => continue,
+                other => panic!("no pong before {:?}", other),
+            }
+        }
+    })
+    .await
+    .expect("no pong from the session");
+}
+
+/// Receive until the output ends with `expected`, failing on errors
+async fn output_until(socket: &mut common::Socket, expected: &str) {
+    let mut output = String::new();
+    tokio::time::timeout(TIMEOUT, async {
+        while !output.ends_with(expected) {
+            match socket.next().await {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text).unwrap() {
+                    ServerEnvelope::Output { data, .. } => output.push_str(&data),
+                    ServerEnvelope::Error { code, message } => {
+                        panic!("error {:?}: {}", code, message)
+                    }
+                    _ => {}
+                },
+                Some(Ok(_)) => continue,
+                other => panic!(
+                    "no {:?} in the output {:?} before {:?}",
+                    expected, output, other
+                ),
+            }
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("no {:?} in the output", expected));
+}
+
+#[tokio::test]
+async fn ping_flood_closes_the_session() {
+    // The clock stands still, so the bucket never refills
+    let mut socket = connect(Arc::new(MockClock::new(0))).await;
+    for _ in 0..FLOOD {
+        socket.send(Message::Ping(Vec::new())).await.unwrap();
+    }
+
+    let mut errors = Vec::new();
+    tokio::time::timeout(TIMEOUT, async {
+        while let Some(Ok(message)) = socket.next().await {
+            if let Message::Text(text) = message
+                && let Ok(ServerEnvelope::Error { code, .. }) = serde_json::from_str(&text)
+            {
+                errors.push(code);
+            }
+        }
+    })
+    .await
+    .expect("the connection wasn't closed");
+    assert_eq!(errors, vec![ErrorCode::RateLimited]);
+}
+
+#[tokio::test]
+async fn pings_within_the_limit_keep_the_session() {
+    let clock = Arc::new(MockClock::new(0));
+    let mut socket = connect(clock.clone()).await;
+    output_until(&mut socket, "mock$ ").await;
+
+    // More pings than a flood, but no faster than 10 per second of the session's clock
+    for _ in 0..FLOOD {
+        socket.send(Message::Ping(Vec::new())).await.unwrap();
+        pong(&mut socket).await;
+        clock.advance(Duration::from_millis(100));
+    }
+
+    let input = serde_json::to_string(&ClientEnvelope::Input {
+        data: "echo alive\r".to_string(),
+        id: None,
+    })
+    .unwrap();
+    socket.send(Message::Text(input)).await.unwrap();
+    output_until(&mut socket, "echo alive\r\nmock$ ").await;
+}
@@ -0,0 +1,393 @@
+//! Property tests of the decoders that see untrusted input: permessage-deflate frames, bearer
+//! tokens (base64url JWT segments), the escape sequence scanner of the output filter and
+//! `waylon-terminal-v1` envelopes. Malformed input must be refused with an error, never a panic
+//! The same decoders are fuzzed without a time limit by the targets in `fuzz/`
+
+mod common;
+
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::Request;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use flate2::{Compress, Compression, FlushCompress};
+use futures_util::{SinkExt, StreamExt};
+use proptest::prelude::*;
+use proptest::strategy::ValueTree;
+use proptest::test_runner::TestRunner;
+use ring::hmac;
+use rs_terminal::api::dto::TerminalProfile;
+use rs_terminal::auth::{AuthContext, AuthError, AuthProvider, Jwt};
+use rs_terminal::protocol::{ClientEnvelope, DeflateParams, DeflateStream, Subprotocol};
+use rs_terminal::pty::MockPtyFactory;
+use rs_terminal::service::OutputFilter;
+use tokio::io::AsyncReadExt;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+
+/// Largest message the deflate decoder accepts in these tests
+const LIMIT: usize = 64 * 1024;
+
+/// Trailer of a deflate block, removed from compressed messages (RFC 7692)
+const MESSAGE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+const JWT_SECRET: &str = "property-test-secret";
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+/// Run client bytes through the permessage-deflate decoder, returning the plain frames
+fn inflate(bytes: Vec<u8>) -> std::io::Result<Vec<u8>> {
+    block_on(async {
+        let params = DeflateParams {
+            server_no_context_takeover: false,
+        };
+        let mut stream = DeflateStream::new(Cursor::new(bytes), params, LIMIT);
+        let mut frames = Vec::new();
+        stream.read_to_end(&mut frames).await?;
+        Ok(frames)
+    })
+}
+
+/// Append a frame header with any declared length, which need not match the payload that follows
+fn write_header(out: &mut Vec<u8>, first: u8, mask: Option<[u8; 4]>, len: u64) {
+    out.push(first);
+    let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+    match len {
+        0..=125 => out.push(mask_bit | len as u8),
+        126..=0xffff => {
+            out.push(mask_bit | 126);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        _ => {
+            out.push(mask_bit | 127);
+            out.extend_from_slice(&len.to_be_bytes());
+        }
+    }
+    if let Some(mask) = mask {
+        out.extend_from_slice(&mask);
+    }
+}
+
+/// Frames with arbitrary flags, opcodes and declared lengths, with or without their payload
+fn frames() -> impl Strategy<Value = Vec<u8>> {
+    let declared = prop_oneof![
+        0u64..126,
+        126u64..0x1_0000,
+        any::<u64>(),
+        (u64::MAX - 0x1_0000)..=u64::MAX,
+    ];
+    // FIN, RSV1 and opcode: data frames, compressed or not, continuations and control frames
+    let first = prop_oneof![
+        proptest::sample::select(vec![
+            0x01, 0x41, 0x81, 0xc1, 0x02, 0x42, 0x00, 0x80, 0xc0, 0x88, 0x89, 0x8a
+        ]),
+        any::<u8>(),
+    ];
+    let frame = (
+        first,
+        proptest::option::of(any::<[u8; 4]>()),
+        proptest::option::of(declared),
+        proptest::collection::vec(any::<u8>(), 0..64),
+    )
+        .prop_map(|(first, mask, declared, payload)| {
+            let mut frame = Vec::new();
+            let len = declared.unwrap_or(payload.len() as u64);
+            write_header(&mut frame, first, mask, len);
+            frame.extend_from_slice(&payload);
+            frame
+        });
+    proptest::collection::vec(frame, 0..6).prop_map(|frames| frames.concat())
+}
+
+/// Compress messages like a client with context takeover, fragmenting each into frames of at
+/// most `fragment` payload bytes
+fn compressed_frames(messages: &[(u8, Vec<u8>)], fragment: usize, mask: [u8; 4]) -> Vec<u8> {
+    let mut compress = Compress::new(Compression::default(), false);
+    let mut out = Vec::new();
+    for (opcode, message) in messages {
+        let mut payload = Vec::with_capacity(message.len() + 64);
+        compress
+            .compress_vec(message, &mut payload, FlushCompress::Sync)
+            .unwrap();
+        payload.truncate(payload.len() - MESSAGE_TAIL.len());
+
+        let pieces: Vec<&[u8]> = if payload.is_empty() {
+            vec![&payload]
+        } else {
+            payload.chunks(fragment).collect()
+        };
+        for (index, piece) in pieces.iter().enumerate() {
+            let fin = if index + 1 == pieces.len() { 0x80 } else { 0 };
+            // The first frame carries the opcode and RSV1, continuations neither
+            let first = match index {
+                0 => fin | 0x40 | opcode,
+                _ => fin,
+            };
+            write_header(&mut out, first, Some(mask), piece.len() as u64);
+            out.extend(piece.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+        }
+    }
+    out
+}
+
+/// Split decoded frames into their opcodes and unmasked payloads
+fn parse_frames(mut bytes: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    let mut frames = Vec::new();
+    while !bytes.is_empty() {
+        let opcode = bytes[0] & 0x0f;
+        let (len, mut header_len) = match bytes[1] & 0x7f {
+            126 => (u16::from_be_bytes([bytes[2], bytes[3]]) as usize, 4),
+            127 => (
+                u64::from_be_bytes(bytes[2..10].try_into().unwrap()) as usize,
+                10,
+            ),
+            len => (len as usize, 2),
+        };
+        let mut mask = [0; 4];
+        if bytes[1] & 0x80 != 0 {
+            mask.copy_from_slice(&bytes[header_len..header_len + 4]);
+            header_len += 4;
+        }
+        let payload = bytes[header_len..header_len + len]
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ mask[i % 4])
+            .collect();
+        frames.push((opcode, payload));
+        bytes = &bytes[header_len + len..];
+    }
+    frames
+}
+
+/// Authenticate a request carrying `token` as its bearer token
+fn authenticate(token: &str) -> Result<AuthContext, AuthError> {
+    let (parts, ()) = Request::builder()
+        .header("authorization", format!("Bearer {}", token))
+        .body(())
+        .unwrap()
+        .into_parts();
+    block_on(Jwt::new(JWT_SECRET, None).authenticate(&parts))
+}
+
+/// Sign `claims` with the test secret
+fn sign(header: &[u8], claims: &[u8]) -> String {
+    let signed = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(header),
+        URL_SAFE_NO_PAD.encode(claims)
+    );
+    let key = hmac::Key::new(hmac::HMAC_SHA256, JWT_SECRET.as_bytes());
+    let signature = hmac::sign(&key, signed.as_bytes());
+    format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(signature.as_ref()))
+}
+
+/// Characters a bearer token can hold in an `Authorization` header
+fn token_text() -> impl Strategy<Value = String> {
+    "[ -~]{0,200}".prop_filter("not a header value", |token| {
+        HeaderValue::from_str(&format!("Bearer {}", token)).is_ok()
+    })
+}
+
+/// Run `data` through a filter for `profile` in the given chunks
+fn filter_chunked(profile: TerminalProfile, data: &[u8], cuts: &[prop::sample::Index]) -> Vec<u8> {
+    let mut filter = OutputFilter::new(profile).unwrap();
+    let mut positions: Vec<usize> = cuts.iter().map(|cut| cut.index(data.len() + 1)).collect();
+    positions.push(0);
+    positions.push(data.len());
+    positions.sort();
+    positions
+        .windows(2)
+        .flat_map(|pair| filter.filter(&data[pair[0]..pair[1]]))
+        .collect()
+}
+
+/// Terminal output mixing text, control characters and (partial) escape sequences
+fn terminal_output() -> impl Strategy<Value = Vec<u8>> {
+    let piece = prop_oneof![
+        proptest::collection::vec(any::<u8>(), 0..16),
+        "[ -~]{0,16}".prop_map(String::into_bytes),
+        "\x1b\\[[0-9;]{0,8}[ -~]?".prop_map(String::into_bytes),
+        "\x1b\\][0-9];[ -~]{0,16}(\x07|\x1b\\\\)?".prop_map(String::into_bytes),
+        "\x1b[P_^X ]?[ -~]{0,4}".prop_map(String::into_bytes),
+    ];
+    proptest::collection::vec(piece, 0..8).prop_map(|pieces| pieces.concat())
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_bytes_never_panic_the_deflate_decoder(
+        bytes in proptest::collection::vec(any::<u8>(), 0..512),
+    ) {
+        let _ = inflate(bytes);
+    }
+
+    #[test]
+    fn malformed_frames_never_panic_the_deflate_decoder(bytes in frames()) {
+        if let Err(e) = inflate(bytes) {
+            prop_assert_eq!(e.kind(), std::io::ErrorKind::InvalidData, "{}", e);
+        }
+    }
+
+    #[test]
+    fn compressed_messages_inflate_to_what_was_sent(
+        messages in proptest::collection::vec(
+            (prop_oneof![Just(0x1u8), Just(0x2u8)], proptest::collection::vec(any::<u8>(), 0..2048)),
+            1..4,
+        ),
+        fragment in 1usize..300,
+        mask in any::<[u8; 4]>(),
+    ) {
+        let frames = inflate(compressed_frames(&messages, fragment, mask)).unwrap();
+        prop_assert_eq!(parse_frames(&frames), messages);
+    }
+
+    #[test]
+    fn arbitrary_tokens_are_rejected(token in token_text()) {
+        prop_assert!(authenticate(&token).is_err());
+    }
+
+    #[test]
+    fn arbitrary_segments_are_rejected(
+        segments in proptest::collection::vec(proptest::collection::vec(any::<u8>(), 0..64), 0..5),
+    ) {
+        let token: Vec<String> = segments.iter().map(|s| URL_SAFE_NO_PAD.encode(s)).collect();
+        prop_assert!(authenticate(&token.join(".")).is_err());
+    }
+
+    #[test]
+    fn signed_tokens_round_trip(subject in any::<String>(), roles in proptest::collection::vec("[a-z]{1,8}", 0..3)) {
+        let claims = serde_json::json!({ "sub": subject, "roles": roles });
+        let token = sign(br#"{"alg":"HS256","typ":"JWT"}"#, claims.to_string().as_bytes());
+        let context = authenticate(&token).unwrap();
+        prop_assert_eq!(context.user_id, subject);
+        prop_assert_eq!(context.roles, roles);
+
+        // Any change to the signed part breaks the signature
+        let tampered = format!("{}x", &token[..token.find('.').unwrap()]) + &token[token.find('.').unwrap()..];
+        prop_assert!(authenticate(&tampered).is_err());
+    }
+
+    #[test]
+    fn signed_tokens_with_malformed_segments_are_rejected(
+        header in proptest::collection::vec(any::<u8>(), 0..64),
+        claims in proptest::collection::vec(any::<u8>(), 0..64),
+    ) {
+        // Correctly signed, but the header or the claims aren't what the server expects
+        prop_assume!(serde_json::from_slice::<serde_json::Value>(&claims).is_err());
+        prop_assert!(authenticate(&sign(&header, &claims)).is_err());
+        let hs256 = br#"{"alg":"HS256"}"#;
+        prop_assert!(authenticate(&sign(hs256, &claims)).is_err());
+    }
+
+    #[test]
+    fn output_filter_ignores_chunk_boundaries(
+        output in terminal_output(),
+        cuts in proptest::collection::vec(any::<prop::sample::Index>(), 0..8),
+    ) {
+        for profile in [TerminalProfile::NoColor, TerminalProfile::Dumb] {
+            prop_assert_eq!(
+                filter_chunked(profile, &output, &cuts),
+                filter_chunked(profile, &output, &[]),
+                "{:?}",
+                profile
+            );
+        }
+    }
+
+    #[test]
+    fn dumb_output_holds_no_escape_or_control_bytes(output in terminal_output()) {
+        let filtered = OutputFilter::new(TerminalProfile::Dumb).unwrap().filter(&output);
+        prop_assert!(
+            filtered
+                .iter()
+                .all(|&byte| byte >= 0x20 && byte != 0x7f || matches!(byte, b'\n' | b'\r' | b'\t' | 0x08)),
+            "{:?}",
+            filtered
+        );
+    }
+
+    #[test]
+    fn no_color_keeps_control_strings_and_drops_colors(title in "[ -~]{0,32}", text in "[ -~]{0,32}") {
+        let output = format!("\x1b]0;{}\x07\x1b[1;31m{}\x1b[0m\x1b]2;{}\x1b\\", title, text, title);
+        let filtered = OutputFilter::new(TerminalProfile::NoColor).unwrap().filter(output.as_bytes());
+        prop_assert_eq!(
+            String::from_utf8(filtered).unwrap(),
+            format!("\x1b]0;{}\x07{}\x1b]2;{}\x1b\\", title, text, title)
+        );
+    }
+}
+
+/// Text frames that aren't valid envelopes: arbitrary text and JSON of the wrong shape
+/// Empty frames are keepalives, not envelopes, and get no answer
+fn malformed_envelope() -> impl Strategy<Value = String> {
+    prop_oneof![
+        any::<String>(),
+        "\\{\"type\":\"(input|resize|unlock|hello|nope)\"(,\"[a-z]{1,8}\":(null|-?[0-9]{1,20}|\"[^\"]{0,8}\"|\\[\\]))*\\}?",
+    ]
+    .prop_filter("a keepalive or valid envelope", |text| {
+        !text.is_empty() && serde_json::from_str::<ClientEnvelope>(text).is_err()
+    })
+}
+
+#[tokio::test]
+async fn session_survives_malformed_envelopes() {
+    let state = common::state(&common::config("")).with_pty_factory(Arc::new(MockPtyFactory));
+    let (address, _) = common::start_server(state).await;
+    let url = format!("ws://{}/ws", address);
+    let mut socket = common::connect(&url, Some(Subprotocol::V1)).await;
+
+    let mut runner = TestRunner::deterministic();
+    let strategy = malformed_envelope();
+    for _ in 0..200 {
+        let text = strategy.new_tree(&mut runner).unwrap().current();
+        socket.send(Message::Text(text)).await.unwrap();
+    }
+
+    // Each was answered with an error; the session still takes input
+    common::input(&mut socket, "alive\r", None).await;
+    let mut output = String::new();
+    let mut errors = 0;
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !output.contains("alive\r\nmock$ ") {
+            match socket.next().await {
+                Some(Ok(Message::Text(text))) => {
+                    let envelope: serde_json::Value = serde_json::from_str(&text).unwrap();
+                    match envelope["type"].as_str() {
+                        Some("output") => output.push_str(envelope["data"].as_str().unwrap()),
+                        Some("error") => {
+                            assert_eq!(envelope["code"], "invalid_message", "{}", envelope);
+                            errors += 1;
+                        }
+                        _ => {}
+                    }
+                }
+                Some(Ok(_)) => {}
+                other => panic!("connection ended: {:?}", other),
+            }
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("no echo in the output {:?}", output));
+    assert_eq!(errors, 200);
+}
+
+#[test]
+fn continuation_declaring_the_largest_length_is_refused() {
+    let mut bytes = Vec::new();
+    write_header(&mut bytes, 0x41, Some([0; 4]), 3);
+    bytes.extend_from_slice(b"abc");
+    write_header(&mut bytes, 0x80, Some([0; 4]), u64::MAX);
+
+    // Only the header of an oversized frame is handed on, for tungstenite to refuse
+    let mut oversized = Vec::new();
+    write_header(&mut oversized, 0x82, Some([0; 4]), LIMIT as u64 + 1);
+    assert_eq!(inflate(bytes).unwrap(), oversized);
+}
@@ -0,0 +1,111 @@
+//! Draining: `POST /api/admin/drain` makes the server refuse new sessions with 503 and report
+//! not-ready, while a session that is already running keeps streaming until it ends
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::Router;
+use axum::http::StatusCode;
+use futures_util::SinkExt;
+use rs_terminal::app_state::AppState;
+use rs_terminal::pty::MockPtyFactory;
+use serde_json::{Value, json};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::{Error, Message};
+
+/// Longest wait for output of the session
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Start a server on mock PTYs, returning its state, address and a router on the same state
+async fn start_server(settings: &str) -> (AppState, String, Router) {
+    let state = common::state(&common::config(settings)).with_pty_factory(Arc::new(MockPtyFactory));
+    let (address, router) = common::start_server(state.clone()).await;
+    (state, address, router)
+}
+
+/// Call the REST API, returning the status, the `Retry-After` header and the JSON body
+async fn call(
+    router: &Router,
+    method: &str,
+    uri: &str,
+    body: Option<Value>,
+) -> (StatusCode, Option<String>, Value) {
+    let response = common::request(router, method, uri, &[], body).await;
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get("retry-after")
+        .map(|value| value.to_str().unwrap().to_string());
+    (status, retry_after, common::json_body(response).await)
+}
+
+#[tokio::test]
+async fn new_sessions_are_refused_while_existing_ones_stream() {
+    let (_state, address, router) = start_server("").await;
+    let mut socket = common::connect(&format!("ws://{}/ws", address), None).await;
+    common::text_until(&mut socket, "mock$ ").await;
+
+    let (status, _, _) = call(&router, "POST", "/api/admin/drain", None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // New sessions are refused, through the REST API and by connecting
+    let (status, retry_after, error) =
+        call(&router, "POST", "/api/sessions", Some(json!({}))).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(retry_after.as_deref(), Some("30"));
+    assert_eq!(
+        error["message"],
+        "Server is draining, not accepting new sessions"
+    );
+    match connect_async(format!("ws://{}/ws", address)).await {
+        Err(Error::Http(response)) => {
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert!(response.headers().contains_key("retry-after"));
+        }
+        Ok(_) => panic!("a new connection was accepted while draining"),
+        Err(e) => panic!("unexpected error: {}", e),
+    }
+
+    // The load balancer is told to stop routing here
+    let (status, _, ready) = call(&router, "GET", "/health/ready", None).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(ready["draining"], true);
+    assert_eq!(ready["degraded"], false);
+    assert_eq!(ready["sessions"], 1);
+    assert_eq!(ready["version"], env!("CARGO_PKG_VERSION"));
+    let (status, _, health) = call(&router, "GET", "/health", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(health["draining"], true);
+    assert_eq!(health["version"], env!("CARGO_PKG_VERSION"));
+
+    // The running session is untouched
+    socket
+        .send(Message::Text("still here".into()))
+        .await
+        .unwrap();
+    common::text_until(&mut socket, "still here").await;
+
+    // Undraining accepts new sessions again
+    let (status, _, _) = call(&router, "POST", "/api/admin/undrain", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = call(&router, "POST", "/api/sessions", Some(json!({}))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, _, _) = call(&router, "GET", "/health/ready", None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn drained_server_shuts_down_when_configured() {
+    let (state, address, router) = start_server("shutdown_when_drained = true").await;
+    let mut socket = common::connect(&format!("ws://{}/ws", address), None).await;
+    common::text_until(&mut socket, "mock$ ").await;
+
+    call(&router, "POST", "/api/admin/drain", None).await;
+    socket.send(Message::Text("exit\r".into())).await.unwrap();
+
+    tokio::time::timeout(TIMEOUT, state.shutdown.notified())
+        .await
+        .expect("no shutdown once the last session ended");
+}
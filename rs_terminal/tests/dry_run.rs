@@ -0,0 +1,127 @@
+//! `POST /api/sessions/dry-run` reports what creating the same session actually starts: the
+//! command, size, working directory and environment the PTY gets
+
+mod common;
+
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use rs_terminal::api::dto::{SessionDryRunResponse, SessionEnvironmentResponse};
+use rs_terminal::app_state::AppState;
+use rs_terminal::server::build_router;
+use serde_json::{Value, json};
+
+/// Layers the request resolves through: default shell config, shell, template and the request
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "bash"
+session_timeout = 1800000
+nudge_on_connect = false
+client_environment_allowlist = ["FROM_CLIENT"]
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+environment = { TERM = "xterm-256color", FROM_DEFAULTS = "1" }
+
+[shells.bash]
+command = ["bash"]
+
+[shells.sh]
+command = ["sh"]
+environment = { FROM_SHELL = "1" }
+
+[templates.build]
+shell_type = "sh"
+command = ["sh", "-c", "make"]
+size = { columns = 100, rows = 30 }
+working_directory = "/"
+environment = { FROM_TEMPLATE = "1" }
+"#;
+
+async fn request(state: &AppState, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+    common::call(&build_router(state.clone()), method, uri, Some(body)).await
+}
+
+#[tokio::test]
+async fn dry_run_matches_the_created_session() {
+    let factory = Arc::new(common::RecordingPtyFactory::default());
+    let state = common::state(CONFIG).with_pty_factory(factory.clone());
+    let body = json!({
+        "template": "build",
+        "rows": 40,
+        "environment": { "FROM_CLIENT": "1", "NOT_ALLOWED": "1" },
+    });
+
+    let (status, dry_run) = request(&state, "POST", "/api/sessions/dry-run", body.clone()).await;
+    assert_eq!(status, StatusCode::OK, "{}", dry_run);
+    let dry_run: SessionDryRunResponse = serde_json::from_value(dry_run).unwrap();
+    assert!(state.get_all_sessions().await.is_empty());
+    assert!(factory.configs.lock().unwrap().is_empty());
+
+    let (status, session) = request(&state, "POST", "/api/sessions", body).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", session);
+    let session_id = session["id"].as_str().unwrap().to_string();
+    let mut client = common::attach(&state, &session_id);
+    common::started(&mut client).await;
+
+    let started = factory.configs.lock().unwrap()[0].clone();
+    assert_eq!(dry_run.shell_type, "sh");
+    assert_eq!(dry_run.pty_backend, "recording");
+    assert_eq!(
+        (dry_run.command.as_str(), &dry_run.args),
+        (started.command.as_str(), &started.args)
+    );
+    assert_eq!(
+        (dry_run.columns, dry_run.rows),
+        (started.cols, started.rows)
+    );
+    assert_eq!((dry_run.columns, dry_run.rows), (100, 40));
+    let cwd = std::fs::canonicalize(started.cwd.unwrap()).unwrap();
+    assert_eq!(
+        dry_run.working_directory.as_deref(),
+        Some(cwd.to_string_lossy().as_ref())
+    );
+
+    // The PTY gets the configured variables on top of the inherited ones the dry run also lists
+    let dry_run_names: Vec<&str> = dry_run
+        .environment
+        .iter()
+        .map(|variable| variable.name.as_str())
+        .collect();
+    for (name, _) in &started.env {
+        assert!(dry_run_names.contains(&name.as_str()), "{} missing", name);
+    }
+    for name in [
+        "FROM_DEFAULTS",
+        "FROM_SHELL",
+        "FROM_TEMPLATE",
+        "FROM_CLIENT",
+    ] {
+        assert!(dry_run_names.contains(&name), "{} missing", name);
+    }
+    assert!(!dry_run_names.contains(&"NOT_ALLOWED"));
+    assert!(dry_run.warnings.iter().any(|w| w.contains("NOT_ALLOWED")));
+
+    // Same sources and values as the running session reports, only the session ID differs
+    let uri = format!("/api/sessions/{}/environment", session_id);
+    let (status, environment) = request(&state, "GET", &uri, Value::Null).await;
+    assert_eq!(status, StatusCode::OK, "{}", environment);
+    let environment: SessionEnvironmentResponse = serde_json::from_value(environment).unwrap();
+    let describe = |variables: &[rs_terminal::api::dto::EnvironmentVariableInfo]| {
+        variables
+            .iter()
+            .map(|variable| match variable.name.as_str() {
+                "WAYLON_SESSION_ID" => format!("{} {:?}", variable.name, variable.source),
+                _ => format!(
+                    "{} {:?} {}",
+                    variable.name, variable.source, variable.value_hash
+                ),
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        describe(&dry_run.environment),
+        describe(&environment.variables)
+    );
+}
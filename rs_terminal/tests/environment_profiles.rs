@@ -0,0 +1,87 @@
+//! Environment profiles: the variables of the profile selected for a connection, a session or
+//! the transport reach the shell, in that order of priority
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use serde_json::json;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Error;
+use tower::ServiceExt;
+
+/// The shell prints the profile variables between markers, then waits to be closed
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+nudge_on_connect = false
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+
+[shells.sh]
+command = ["sh", "-c", "stty -echo; printf '[CI=%s EDITOR=%s]' \"$CI\" \"$EDITOR\"; sleep 5"]
+
+[transport_environment_profiles]
+websocket = "interactive"
+
+[environment_profiles.interactive]
+EDITOR = "vim"
+
+[environment_profiles.ci]
+CI = "true"
+"#;
+
+/// Connect to `path` and return the variables the shell printed
+async fn shell_variables(address: &str, path: &str) -> String {
+    let mut socket = common::connect(&format!("ws://{}{}", address, path), None).await;
+    let output = common::text_until(&mut socket, "]").await;
+    let start = output.find('[').unwrap();
+    output[start..].trim().to_string()
+}
+
+#[tokio::test]
+async fn transport_profile_is_the_default() {
+    let (address, _) = common::start_server(common::state(CONFIG)).await;
+    assert_eq!(shell_variables(&address, "/ws").await, "[CI= EDITOR=vim]");
+}
+
+#[tokio::test]
+async fn connection_profile_replaces_the_transport_profile() {
+    let (address, _) = common::start_server(common::state(CONFIG)).await;
+    assert_eq!(
+        shell_variables(&address, "/ws?environment_profile=ci").await,
+        "[CI=true EDITOR=]"
+    );
+}
+
+#[tokio::test]
+async fn session_profile_reaches_the_shell() {
+    let (address, router) = common::start_server(common::state(CONFIG)).await;
+    let session_id = common::create_session(&router, json!({ "environmentProfile": "ci" })).await;
+    let path = format!("/ws/{}", session_id);
+    assert_eq!(shell_variables(&address, &path).await, "[CI=true EDITOR=]");
+}
+
+#[tokio::test]
+async fn unknown_profile_is_rejected() {
+    let (address, router) = common::start_server(common::state(CONFIG)).await;
+    match connect_async(format!("ws://{}/ws?environment_profile=missing", address)).await {
+        Err(Error::Http(response)) => assert_eq!(response.status(), 400),
+        Ok(_) => panic!("connection with an unknown profile was accepted"),
+        Err(e) => panic!("unexpected error: {}", e),
+    }
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/sessions")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({ "environmentProfile": "missing" }).to_string(),
+        ))
+        .unwrap();
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
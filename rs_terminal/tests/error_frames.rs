@@ -0,0 +1,229 @@
+//! Failed terminal operations reach the client as structured errors over a real WebSocket
+//! connection: `waylon-terminal-v1` clients get an `error` envelope with `spawn_failed`,
+//! `write_failed` or `resize_failed`, raw clients an `Error:` text frame. A failed write or spawn
+//! ends the session, a failed resize doesn't
+
+mod common;
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures_util::{SinkExt, StreamExt};
+use rs_terminal::protocol::{ClientEnvelope, ErrorCode, ServerEnvelope, Subprotocol};
+use rs_terminal::pty::{AsyncPty, MockPty, PtyConfig, PtyError, PtyExitStatus, PtyFactory};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::tungstenite::Message;
+
+/// Longest wait for a frame of the server
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Which operations of the PTY fail
+#[derive(Clone, Copy, Default)]
+struct Failures {
+    spawn: bool,
+    write: bool,
+    resize: bool,
+}
+
+/// Mock PTY whose writes or resizes fail
+struct FailingPty {
+    inner: MockPty,
+    failures: Failures,
+}
+
+impl AsyncRead for FailingPty {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for FailingPty {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        if self.failures.write {
+            return Poll::Ready(Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe)));
+        }
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl AsyncPty for FailingPty {
+    async fn resize(&mut self, cols: u16, rows: u16) -> Result<(), PtyError> {
+        if self.failures.resize {
+            return Err(PtyError::NotAvailable);
+        }
+        self.inner.resize(cols, rows).await
+    }
+
+    fn pid(&self) -> Option<u32> {
+        self.inner.pid()
+    }
+
+    fn is_alive(&self) -> bool {
+        self.inner.is_alive()
+    }
+
+    async fn try_wait(&mut self) -> Result<Option<PtyExitStatus>, PtyError> {
+        self.inner.try_wait().await
+    }
+
+    async fn kill(&mut self) -> Result<(), PtyError> {
+        self.inner.kill().await
+    }
+}
+
+struct FailingPtyFactory {
+    failures: Failures,
+}
+
+#[async_trait]
+impl PtyFactory for FailingPtyFactory {
+    async fn create(&self, config: &PtyConfig) -> Result<Box<dyn AsyncPty>, PtyError> {
+        if self.failures.spawn {
+            return Err(PtyError::NotAvailable);
+        }
+        Ok(Box::new(FailingPty {
+            inner: MockPty::new(config),
+            failures: self.failures,
+        }))
+    }
+
+    fn name(&self) -> &'static str {
+        "failing"
+    }
+}
+
+/// Start a server whose PTYs fail as given and connect to it offering `subprotocol`
+async fn connect(failures: Failures, subprotocol: Option<Subprotocol>) -> common::Socket {
+    let state = common::state(&common::config(""))
+        .with_pty_factory(Arc::new(FailingPtyFactory { failures }));
+    let (address, _) = common::start_server(state).await;
+    common::connect(&format!("ws://{}/ws", address), subprotocol).await
+}
+
+async fn error_envelope(socket: &mut common::Socket) -> (ErrorCode, String) {
+    common::text_matching(socket, |text| match serde_json::from_str(text) {
+        Ok(ServerEnvelope::Error { code, message }) => Some((code, message)),
+        _ => None,
+    })
+    .await
+}
+
+async fn error_text(socket: &mut common::Socket) -> String {
+    common::text_matching(socket, |text| {
+        text.starts_with("Error:").then(|| text.to_string())
+    })
+    .await
+}
+
+/// Expect the server to close the connection, skipping other frames
+async fn closed(socket: &mut common::Socket) {
+    tokio::time::timeout(TIMEOUT, async {
+        while let Some(Ok(message)) = socket.next().await {
+            if let Message::Close(_) = message {
+                return;
+            }
+        }
+    })
+    .await
+    .expect("the connection wasn't closed");
+}
+
+#[tokio::test]
+async fn failed_spawn_is_reported() {
+    let failures = Failures {
+        spawn: true,
+        ..Failures::default()
+    };
+
+    let mut socket = connect(failures, Some(Subprotocol::V1)).await;
+    let (code, message) = error_envelope(&mut socket).await;
+    assert_eq!(code, ErrorCode::SpawnFailed);
+    assert!(
+        message.starts_with("Failed to create terminal session: "),
+        "{}",
+        message
+    );
+    closed(&mut socket).await;
+
+    let mut socket = connect(failures, None).await;
+    let text = error_text(&mut socket).await;
+    assert!(
+        text.starts_with("Error: Failed to create terminal session: "),
+        "{}",
+        text
+    );
+    closed(&mut socket).await;
+}
+
+#[tokio::test]
+async fn failed_write_ends_the_session() {
+    let failures = Failures {
+        write: true,
+        ..Failures::default()
+    };
+
+    let mut socket = connect(failures, Some(Subprotocol::V1)).await;
+    common::input(&mut socket, "ls\r", None).await;
+    let (code, message) = error_envelope(&mut socket).await;
+    assert_eq!(code, ErrorCode::WriteFailed);
+    assert!(message.starts_with("PTY write error: "), "{}", message);
+    closed(&mut socket).await;
+
+    let mut socket = connect(failures, None).await;
+    socket
+        .send(Message::Text("ls\r".to_string()))
+        .await
+        .unwrap();
+    let text = error_text(&mut socket).await;
+    assert!(text.starts_with("Error: PTY write error: "), "{}", text);
+    closed(&mut socket).await;
+}
+
+#[tokio::test]
+async fn failed_resize_keeps_the_session() {
+    let failures = Failures {
+        resize: true,
+        ..Failures::default()
+    };
+
+    let mut socket = connect(failures, Some(Subprotocol::V1)).await;
+    common::send(
+        &mut socket,
+        &ClientEnvelope::Resize {
+            columns: 100,
+            rows: 30,
+        },
+    )
+    .await;
+    let (code, message) = error_envelope(&mut socket).await;
+    assert_eq!(code, ErrorCode::ResizeFailed);
+    assert_eq!(message, "Failed to resize the terminal: PTY not available");
+
+    // The session goes on
+    common::input(&mut socket, "still here\r", None).await;
+    common::text_matching(&mut socket, |text| match serde_json::from_str(text) {
+        Ok(ServerEnvelope::Output { data, .. }) => data.contains("still here").then_some(()),
+        _ => None,
+    })
+    .await;
+}
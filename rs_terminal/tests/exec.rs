@@ -0,0 +1,141 @@
+//! `POST /api/exec` runs a command headlessly in a shell and reports its output and exit
+
+mod common;
+
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use rs_terminal::api::dto::ExecResponse;
+use rs_terminal::server::build_router;
+use serde_json::{Value, json};
+
+/// Commands without a timeout of their own get 300 ms, output beyond 256 bytes is discarded
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+default_exec_timeout_ms = 300
+exec_output_limit_bytes = 256
+client_environment_allowlist = ["GREETING"]
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+
+[shells.sh]
+command = ["sh"]
+"#;
+
+async fn exec(body: Value) -> ExecResponse {
+    let router = build_router(common::state(CONFIG));
+    let (status, response) = common::call(&router, "POST", "/api/exec", Some(body)).await;
+    assert_eq!(status, StatusCode::OK, "{}", response);
+    serde_json::from_value(response).unwrap()
+}
+
+#[tokio::test]
+async fn output_and_exit_code_are_returned() {
+    let response = exec(json!({ "command": "echo hi", "timeoutSecs": 10 })).await;
+    assert_eq!(response.output.trim_end(), "hi");
+    assert_eq!(response.exit_code, Some(0));
+    assert_eq!(response.signal, None);
+    assert!(!response.timed_out);
+    assert!(!response.truncated);
+
+    let response = exec(json!({ "command": "echo failing; exit 3", "timeoutSecs": 10 })).await;
+    assert_eq!(response.output.trim_end(), "failing");
+    assert_eq!(response.exit_code, Some(3));
+}
+
+#[tokio::test]
+async fn command_ended_by_a_signal_reports_it() {
+    let response = exec(json!({ "command": "echo dying; kill -9 $$", "timeoutSecs": 10 })).await;
+    assert_eq!(response.output.trim_end(), "dying");
+    // No exit code made up for it
+    assert_eq!(response.exit_code, None);
+    assert_eq!(response.signal.as_deref(), Some("Killed"));
+    assert!(!response.timed_out);
+}
+
+#[tokio::test]
+async fn output_beyond_the_limit_is_discarded() {
+    let response = exec(json!({
+        "command": "i=0; while [ $i -lt 100 ]; do echo line $i; i=$((i+1)); done",
+        "timeoutSecs": 10,
+    }))
+    .await;
+    assert!(response.truncated);
+    assert_eq!(response.output.len(), 256);
+    assert!(
+        response.output.starts_with("line 0"),
+        "{:?}",
+        response.output
+    );
+    assert_eq!(response.exit_code, Some(0));
+}
+
+#[tokio::test]
+async fn environment_is_filtered_by_the_allowlist() {
+    let response = exec(json!({
+        "command": "printf '[%s %s]' \"$GREETING\" \"$LD_PRELOAD\"",
+        "timeoutSecs": 10,
+        "environment": { "GREETING": "hello", "LD_PRELOAD": "/tmp/injected.so" },
+    }))
+    .await;
+    assert_eq!(response.output, "[hello ]");
+}
+
+#[tokio::test]
+async fn command_hitting_its_timeout_is_killed() {
+    let response = tokio::time::timeout(
+        Duration::from_secs(10),
+        exec(json!({ "command": "sleep 31337", "timeoutSecs": 1 })),
+    )
+    .await
+    .expect("the command ran past its timeout");
+    assert!(response.timed_out);
+    assert_eq!(response.exit_code, None);
+    assert!(
+        (1000..5000).contains(&response.duration_ms),
+        "{} ms",
+        response.duration_ms
+    );
+
+    // Nothing of the command is left running once the killed process was reaped
+    #[cfg(target_os = "linux")]
+    {
+        let running = || {
+            std::fs::read_dir("/proc")
+                .unwrap()
+                .filter_map(|entry| std::fs::read(entry.ok()?.path().join("cmdline")).ok())
+                .any(|cmdline| {
+                    cmdline == b"sh\0-c\0sleep 31337\0" || cmdline == b"sleep\x0031337\0"
+                })
+        };
+        let started = std::time::Instant::now();
+        while running() {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "the timed out command is still running"
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+}
+
+#[tokio::test]
+async fn default_timeout_bounds_a_command_without_one() {
+    let response = tokio::time::timeout(
+        Duration::from_secs(10),
+        exec(json!({ "command": "sleep 30" })),
+    )
+    .await
+    .expect("the command ran unbounded");
+    assert!(response.timed_out);
+    assert_eq!(response.exit_code, None);
+    assert!(
+        (300..5000).contains(&response.duration_ms),
+        "{} ms",
+        response.duration_ms
+    );
+}
@@ -0,0 +1,73 @@
+//! A shell that ends right after starting is reported with how it ended: its exit code, or the
+//! signal that ended it instead of a made-up exit code
+
+mod common;
+
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use rs_terminal::protocol::{ErrorCode, ServerEnvelope, Subprotocol};
+use tokio_tungstenite::tungstenite::Message;
+
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+nudge_on_connect = false
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+
+[shells.sh]
+command = ["sh", "-c", "{script}"]
+"#;
+
+/// Longest wait for the report
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Start a server whose shell runs `script` and return the error its session reports
+async fn report(script: &str) -> (ErrorCode, String) {
+    let state = common::state(&CONFIG.replace("{script}", script));
+    let (address, _) = common::start_server(state).await;
+    let url = format!("ws://{}/ws", address);
+    let mut socket = common::connect(&url, Some(Subprotocol::V1)).await;
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match socket.next().await {
+                Some(Ok(Message::Text(text))) => {
+                    if let Ok(ServerEnvelope::Error { code, message }) = serde_json::from_str(&text)
+                    {
+                        return (code, message);
+                    }
+                }
+                Some(Ok(_)) => continue,
+                other => panic!("connection ended with {:?}", other),
+            }
+        }
+    })
+    .await
+    .expect("the exit wasn't reported")
+}
+
+#[tokio::test]
+async fn exit_code_is_reported() {
+    let (code, message) = report("exit 3").await;
+    assert_eq!(code, ErrorCode::SpawnFailed);
+    assert_eq!(
+        message,
+        "Shell sh exited immediately after start (exit code 3), check its configuration: \
+         sh -c exit 3"
+    );
+}
+
+#[tokio::test]
+async fn signal_is_reported_instead_of_an_exit_code() {
+    let (code, message) = report("kill -9 $$").await;
+    assert_eq!(code, ErrorCode::SpawnFailed);
+    assert_eq!(
+        message,
+        "Shell sh exited immediately after start (signal Killed), check its configuration: \
+         sh -c kill -9 $$"
+    );
+}
@@ -0,0 +1,149 @@
+//! A client flooded with output can still close its session: its messages are read while a send
+//! waits for it, so the close frame ends the session right away instead of after
+//! `connection_send_timeout_ms`
+
+mod common;
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use rs_terminal::pty::{AsyncPty, MockPty, PtyConfig, PtyError, PtyExitStatus, PtyFactory};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::connect_async;
+
+/// Sends to a client that doesn't read only give up after a minute
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+nudge_on_connect = false
+connection_send_timeout_ms = 60000
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+
+[shells.sh]
+command = ["sh"]
+"#;
+
+/// Longest wait for the session to end, far below the send timeout
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the output keeps the client's socket full before it closes
+const FLOOD_TIME: Duration = Duration::from_millis(500);
+
+/// Mock PTY producing output as fast as it is read, counting how often it was killed
+struct FloodingPty {
+    inner: MockPty,
+    killed: Arc<AtomicUsize>,
+}
+
+impl AsyncRead for FloodingPty {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        buf.put_slice(&vec![b'x'; buf.remaining()]);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for FloodingPty {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl AsyncPty for FloodingPty {
+    async fn resize(&mut self, cols: u16, rows: u16) -> Result<(), PtyError> {
+        self.inner.resize(cols, rows).await
+    }
+
+    fn pid(&self) -> Option<u32> {
+        self.inner.pid()
+    }
+
+    fn is_alive(&self) -> bool {
+        self.inner.is_alive()
+    }
+
+    async fn try_wait(&mut self) -> Result<Option<PtyExitStatus>, PtyError> {
+        self.inner.try_wait().await
+    }
+
+    async fn kill(&mut self) -> Result<(), PtyError> {
+        self.killed.fetch_add(1, Ordering::SeqCst);
+        self.inner.kill().await
+    }
+}
+
+struct FloodingPtyFactory {
+    killed: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl PtyFactory for FloodingPtyFactory {
+    async fn create(&self, config: &PtyConfig) -> Result<Box<dyn AsyncPty>, PtyError> {
+        Ok(Box::new(FloodingPty {
+            inner: MockPty::new(config),
+            killed: self.killed.clone(),
+        }))
+    }
+
+    fn name(&self) -> &'static str {
+        "flooding"
+    }
+}
+
+#[tokio::test]
+async fn flooded_client_closes_its_session() {
+    let killed = Arc::new(AtomicUsize::new(0));
+    let state = common::state(CONFIG).with_pty_factory(Arc::new(FloodingPtyFactory {
+        killed: killed.clone(),
+    }));
+    let (address, router) = common::start_server(state).await;
+    let url = format!("ws://{}/ws/flooded", address);
+
+    // Never reads, so the server's sends back up
+    let (mut socket, _) = connect_async(url).await.unwrap();
+    tokio::time::sleep(FLOOD_TIME).await;
+    let closed_at = Instant::now();
+    socket.close(None).await.unwrap();
+
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            let (status, _) = common::call(&router, "GET", "/api/sessions/flooded", None).await;
+            if status == StatusCode::NOT_FOUND && killed.load(Ordering::SeqCst) == 1 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| {
+        panic!(
+            "the session was still running {:?} after the close",
+            closed_at.elapsed()
+        )
+    });
+}
@@ -0,0 +1,68 @@
+//! Graceful shutdown waits for open connections at most `max_shutdown_duration_ms`, so a request
+//! that never completes can't keep the server from exiting
+
+mod common;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::Router;
+use axum::routing::get;
+use rs_terminal::pty::MockPtyFactory;
+use rs_terminal::server::serve_with_graceful_shutdown;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+max_shutdown_duration_ms = 500
+shutdown_timeout_ms = 100
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+
+[shells.sh]
+command = ["sh"]
+"#;
+
+/// Longest the server may take to exit once the shutdown deadline passed
+const GRACE: Duration = Duration::from_secs(3);
+
+#[tokio::test]
+async fn stuck_connection_does_not_block_shutdown_beyond_the_timeout() {
+    let state = common::state(CONFIG).with_pty_factory(Arc::new(MockPtyFactory));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    // The response never comes, keeping the connection busy
+    let router = Router::new().route("/stuck", get(std::future::pending::<&'static str>));
+    let server = tokio::spawn(serve_with_graceful_shutdown(
+        listener,
+        router,
+        state.clone(),
+    ));
+
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    stream
+        .write_all(b"GET /stuck HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    // Give the server time to start handling the request
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let started = Instant::now();
+    state.shutdown.notify_one();
+    tokio::time::timeout(Duration::from_millis(500) + GRACE, server)
+        .await
+        .expect("the server kept waiting for the stuck connection")
+        .unwrap()
+        .unwrap();
+    assert!(
+        started.elapsed() >= Duration::from_millis(500),
+        "the server exited before the deadline: {:?}",
+        started.elapsed()
+    );
+    drop(stream);
+}
@@ -0,0 +1,72 @@
+//! The mock path of `examples/headless_session.rs`: the library API an application embeds a
+//! terminal with (`AppState`, `channel_connection`, `SessionHandle`) works outside the crate
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use rs_terminal::protocol::{ServerEnvelope, channel_connection};
+use rs_terminal::pty::MockPtyFactory;
+use rs_terminal::service::handle_terminal_session;
+
+/// The example's configuration; the mock PTY replaces bash
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "bash"
+session_timeout = 1800000
+nudge_on_connect = false
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+environment = { TERM = "xterm-256color", PS1 = "$ " }
+
+[shells.bash]
+command = ["bash", "--norc", "--noprofile", "-i"]
+"#;
+
+const SESSION_ID: &str = "headless-test";
+
+/// Longest wait for a message of the session
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[tokio::test]
+async fn embedded_session_runs_and_terminates() {
+    let state = common::state(CONFIG).with_pty_factory(Arc::new(MockPtyFactory));
+
+    let (connection, mut client) = channel_connection(SESSION_ID);
+    let session = tokio::spawn(handle_terminal_session(connection, state.clone()));
+    let hello = tokio::time::timeout(TIMEOUT, client.receive())
+        .await
+        .unwrap();
+    assert!(
+        matches!(hello, Some(Ok(ServerEnvelope::Hello { .. }))),
+        "{:?}",
+        hello
+    );
+    let banner = common::output_until(&mut client, "mock$ ").await;
+    assert!(banner.contains("(80x24)"), "{:?}", banner);
+
+    client.input("echo hello\r").await.unwrap();
+    common::output_until(&mut client, "echo hello\r\nmock$ ").await;
+
+    client.resize(120, 40).await.unwrap();
+    common::output_until(&mut client, "[resized to 120x40]\r\nmock$ ").await;
+
+    // Terminating through the handle closes the connection and ends the session task
+    let mut handle = state.take_session_handle(SESSION_ID).await.unwrap();
+    assert!(handle.request_terminate("test finished"));
+    tokio::time::timeout(TIMEOUT, async {
+        while let Some(message) = client.receive().await {
+            message.unwrap();
+        }
+    })
+    .await
+    .expect("the connection wasn't closed");
+    assert!(handle.wait_finished(TIMEOUT).await);
+    tokio::time::timeout(TIMEOUT, session)
+        .await
+        .unwrap()
+        .unwrap();
+}
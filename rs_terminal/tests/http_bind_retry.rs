@@ -0,0 +1,115 @@
+//! Binding the HTTP port: a port that is in use is retried for a few seconds, so a server
+//! restarted while the previous one still shuts down comes up; connections of the previous one
+//! left in TIME_WAIT don't keep the port from being bound (SO_REUSEADDR)
+#![cfg(unix)]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Longest wait for the server to answer
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+fn config(http_port: u16) -> String {
+    format!(
+        r#"
+http_port = {}
+enable_webtransport = false
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+
+[default_shell_config]
+size = {{ columns = 80, rows = 24 }}
+working_directory = "."
+
+[shells.sh]
+command = ["sh"]
+"#,
+        http_port
+    )
+}
+
+/// A TCP port nothing listens on
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn start_server(http_port: u16) -> Child {
+    let mut server = Command::new(env!("CARGO_BIN_EXE_rs_terminal"))
+        .args(["--config", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    server
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(config(http_port).as_bytes())
+        .unwrap();
+    server
+}
+
+/// Ask the server for `/health` once it listens, with the server closing the connection first
+fn health(server: &mut Child, http_port: u16) -> String {
+    let deadline = Instant::now() + TIMEOUT;
+    let mut stream = loop {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", http_port)) {
+            break stream;
+        }
+        if let Some(status) = server.try_wait().unwrap() {
+            panic!("the server exited with {}", status);
+        }
+        assert!(Instant::now() < deadline, "the server isn't listening");
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    stream
+        .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+fn stop(mut server: Child) {
+    server.kill().unwrap();
+    server.wait().unwrap();
+}
+
+#[test]
+fn http_port_freed_while_retrying_is_bound() {
+    let occupied = TcpListener::bind("0.0.0.0:0").unwrap();
+    let port = occupied.local_addr().unwrap().port();
+
+    let mut server = start_server(port);
+    // Within the retries of the server
+    std::thread::sleep(Duration::from_millis(1000));
+    drop(occupied);
+
+    let response = health(&mut server, port);
+    stop(server);
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+}
+
+#[test]
+fn http_port_with_connections_in_time_wait_is_bound() {
+    let port = free_port();
+    let mut server = start_server(port);
+    // The server closes first, its end of the connection stays in TIME_WAIT for a minute
+    let response = health(&mut server, port);
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    stop(server);
+
+    let mut server = start_server(port);
+    let response = health(&mut server, port);
+    stop(server);
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+}
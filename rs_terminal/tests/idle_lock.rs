@@ -0,0 +1,257 @@
+//! Locking sessions left without input (`lock_after_idle_secs`), driven by a `MockClock`
+
+mod common;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rs_terminal::app_state::MockClock;
+use rs_terminal::auth::AuthContext;
+use rs_terminal::protocol::{
+    ChannelClient, ClientEnvelope, ErrorCode, ServerEnvelope, channel_connection,
+};
+use rs_terminal::service::handle_terminal_session;
+
+/// bash without startup files, locking after a minute; `alice-token` authenticates alice and
+/// `bob-token` bob
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "bash"
+session_timeout = 1800000
+nudge_on_connect = false
+lock_after_idle_secs = 60
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+environment = { TERM = "dumb", PS1 = "$ " }
+
+[shells.bash]
+command = ["bash", "--norc", "--noprofile", "-i"]
+
+[auth]
+provider = "static_token"
+tokens = [
+    { token = "alice-token", user_id = "alice" },
+    { token = "bob-token", user_id = "bob" },
+]
+"#;
+
+/// Longest wait for an expected message
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn alice() -> AuthContext {
+    AuthContext {
+        user_id: "alice".to_string(),
+        roles: Vec::new(),
+        expires_at: None,
+        shells: None,
+    }
+}
+
+/// Start a session of alice with extra configuration appended
+async fn start_session(extra_config: &str) -> (ChannelClient, Arc<MockClock>) {
+    let clock = Arc::new(MockClock::new(1_700_000_000));
+    let state = common::state(&format!("{}{}", extra_config, CONFIG)).with_clock(clock.clone());
+    let (connection, mut client) = channel_connection("locked-session");
+    tokio::spawn(handle_terminal_session(
+        connection.with_auth_context(alice()),
+        state,
+    ));
+    let hello = common::receive(&mut client).await;
+    assert!(matches!(hello, ServerEnvelope::Hello { .. }), "{:?}", hello);
+    (client, clock)
+}
+
+/// Receive messages until one matches, returning the output seen before it
+async fn receive_until(
+    client: &mut ChannelClient,
+    wanted: impl Fn(&ServerEnvelope) -> bool,
+) -> (String, ServerEnvelope) {
+    let mut output = String::new();
+    loop {
+        let envelope = common::receive(client).await;
+        if wanted(&envelope) {
+            return (output, envelope);
+        }
+        if let ServerEnvelope::Output { data, .. } = &envelope {
+            output.push_str(data);
+        }
+    }
+}
+
+/// Receive output until it contains `text`, returning everything received
+async fn output_containing(client: &mut ChannelClient, text: &str) -> String {
+    common::output_matching(client, |output| output.contains(text)).await
+}
+
+/// Files a shell command waits for (`go`) and creates once it printed (`printed`), so the test
+/// decides when it prints
+struct Handshake {
+    go: PathBuf,
+    printed: PathBuf,
+}
+
+impl Handshake {
+    fn new(name: &str) -> Self {
+        let path = |step: &str| {
+            std::env::temp_dir().join(format!(
+                "idle-lock-{}-{}-{}",
+                std::process::id(),
+                name,
+                step
+            ))
+        };
+        let handshake = Self {
+            go: path("go"),
+            printed: path("printed"),
+        };
+        let _ = std::fs::remove_file(&handshake.go);
+        let _ = std::fs::remove_file(&handshake.printed);
+        handshake
+    }
+
+    /// Run `command` in the shell once `release` was called
+    fn wrap(&self, command: &str) -> String {
+        format!(
+            "while [ ! -e {} ]; do sleep 0.1; done; {}; touch {}\r",
+            self.go.display(),
+            command,
+            self.printed.display()
+        )
+    }
+
+    /// Let the command run and wait until it printed
+    async fn release(&self) {
+        std::fs::write(&self.go, "").unwrap();
+        tokio::time::timeout(TIMEOUT, async {
+            while !self.printed.exists() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("the shell didn't print");
+        // Let the session read the output
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+}
+
+impl Drop for Handshake {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.go);
+        let _ = std::fs::remove_file(&self.printed);
+    }
+}
+
+async fn unlock(client: &ChannelClient, token: &str) {
+    client
+        .send(&ClientEnvelope::Unlock {
+            token: token.to_string(),
+        })
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn idle_session_locks_and_unlocks() {
+    let (mut client, clock) = start_session("").await;
+
+    // The shell prints later, while the session is locked
+    let handshake = Handshake::new("unlock");
+    client
+        .input(&handshake.wrap("echo after-$((40 + 2))"))
+        .await
+        .unwrap();
+    output_containing(&mut client, "touch").await;
+
+    // Without input for a minute the session locks
+    clock.advance(Duration::from_secs(61));
+    let (_, locked) = receive_until(&mut client, |envelope| {
+        matches!(envelope, ServerEnvelope::Locked { .. })
+    })
+    .await;
+    assert_eq!(locked, ServerEnvelope::Locked { idle_secs: 60 });
+
+    // Input is discarded and output held back
+    client.input("echo typed-while-locked\r").await.unwrap();
+    handshake.release().await;
+    let quiet = tokio::time::timeout(Duration::from_secs(3), client.receive()).await;
+    assert!(quiet.is_err(), "message while locked: {:?}", quiet);
+
+    // Only the same user's credentials unlock it
+    unlock(&client, "bob-token").await;
+    match common::receive(&mut client).await {
+        ServerEnvelope::Error { code, .. } => assert_eq!(code, ErrorCode::UnlockFailed),
+        other => panic!("expected an unlock_failed error, got {:?}", other),
+    }
+    unlock(&client, "wrong").await;
+    match common::receive(&mut client).await {
+        ServerEnvelope::Error { code, .. } => assert_eq!(code, ErrorCode::UnlockFailed),
+        other => panic!("expected an unlock_failed error, got {:?}", other),
+    }
+
+    unlock(&client, "alice-token").await;
+    assert_eq!(common::receive(&mut client).await, ServerEnvelope::Unlocked);
+    let output = output_containing(&mut client, "after-42").await;
+    assert!(!output.contains("typed-while-locked"), "{:?}", output);
+
+    // The shell kept running
+    client.input("echo still-$((1 + 1))\r").await.unwrap();
+    output_containing(&mut client, "still-2").await;
+}
+
+#[tokio::test]
+async fn output_held_back_while_locked_is_bounded() {
+    let (mut client, clock) = start_session("lock_buffer_bytes = 64\n").await;
+
+    // Held output is dropped a whole chunk at a time, the pause keeps the last line apart
+    let handshake = Handshake::new("bounded");
+    client
+        .input(&handshake.wrap("seq 1000 1100; sleep 0.5; echo done-$((1 + 1))"))
+        .await
+        .unwrap();
+    output_containing(&mut client, "touch").await;
+    clock.advance(Duration::from_secs(61));
+    receive_until(&mut client, |envelope| {
+        matches!(envelope, ServerEnvelope::Locked { .. })
+    })
+    .await;
+    handshake.release().await;
+
+    unlock(&client, "alice-token").await;
+    assert_eq!(common::receive(&mut client).await, ServerEnvelope::Unlocked);
+    match common::receive(&mut client).await {
+        ServerEnvelope::Notice { message, .. } => {
+            assert!(message.contains("dropped while the session was locked"))
+        }
+        other => panic!("expected a notice about dropped output, got {:?}", other),
+    }
+    // Only the latest output is kept
+    let output = output_containing(&mut client, "done-2").await;
+    assert!(!output.contains("1000"), "{:?}", output);
+}
+
+#[tokio::test]
+async fn input_keeps_the_session_unlocked() {
+    let (mut client, clock) = start_session("").await;
+
+    for step in 0..3 {
+        clock.advance(Duration::from_secs(45));
+        client
+            .input(&format!("echo step-{}\r", step))
+            .await
+            .unwrap();
+        output_containing(&mut client, &format!("step-{}\r\n", step)).await;
+    }
+    // Two checks of the lock happen meanwhile
+    let quiet = tokio::time::timeout(Duration::from_secs(1), async {
+        loop {
+            if let ServerEnvelope::Locked { .. } = common::receive(&mut client).await {
+                break;
+            }
+        }
+    })
+    .await;
+    assert!(quiet.is_err(), "locked although there was input");
+}
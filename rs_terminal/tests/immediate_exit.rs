@@ -0,0 +1,125 @@
+//! A shell that exits right after starting is reported to the client with its exit status and
+//! the command that was started, before the connection closes; one that exits with code 0 just
+//! ends its session
+
+mod common;
+
+use std::time::Duration;
+
+use rs_terminal::app_state::AppState;
+use rs_terminal::protocol::{ChannelClient, ErrorCode, ServerEnvelope};
+use rs_terminal::server::build_router;
+use serde_json::json;
+
+/// `broken` exits right away; the `failing` template starts a working shell with a command
+/// that exits right away, the `done` template one that prints a line and exits cleanly
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "broken"
+session_timeout = 1800000
+nudge_on_connect = false
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+
+[shells.broken]
+command = ["sh", "-c", "exit 3"]
+
+[shells.sh]
+command = ["sh"]
+
+[templates.failing]
+shell_type = "sh"
+command = ["sh", "-c", "exit 4"]
+
+[templates.done]
+shell_type = "sh"
+command = ["sh", "-c", "echo done"]
+"#;
+
+/// Longest wait for a message of the session
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn start_state() -> AppState {
+    common::state(CONFIG)
+}
+
+/// Create a session from `template`, returning its ID
+async fn create_session(state: &AppState, template: &str) -> String {
+    let router = build_router(state.clone());
+    common::create_session(&router, json!({ "template": template })).await
+}
+
+/// Receive until the error, then expect the connection to close
+async fn exit_error(client: &mut ChannelClient) -> (ErrorCode, String) {
+    let error = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match client.receive().await {
+                Some(Ok(ServerEnvelope::Error { code, message })) => return (code, message),
+                Some(Ok(_)) => continue,
+                other => panic!("no error before {:?}", other),
+            }
+        }
+    })
+    .await
+    .expect("no error from the session");
+    let closed = tokio::time::timeout(TIMEOUT, async {
+        while let Some(message) = client.receive().await {
+            message.unwrap();
+        }
+    })
+    .await;
+    assert!(closed.is_ok(), "the connection wasn't closed");
+    error
+}
+
+#[tokio::test]
+async fn default_shell_exiting_is_reported() {
+    let state = start_state();
+    let mut client = common::attach(&state, "exits-at-once");
+
+    let (code, message) = exit_error(&mut client).await;
+    assert_eq!(code, ErrorCode::SpawnFailed);
+    assert!(message.contains("Shell broken"), "{}", message);
+    assert!(message.contains("exit code 3"), "{}", message);
+    assert!(message.contains("sh -c exit 3"), "{}", message);
+}
+
+#[tokio::test]
+async fn template_command_exiting_is_reported() {
+    let state = start_state();
+    let session_id = create_session(&state, "failing").await;
+
+    let mut client = common::attach(&state, &session_id);
+
+    // The session's shell and the template's command, not the default shell
+    let (code, message) = exit_error(&mut client).await;
+    assert_eq!(code, ErrorCode::SpawnFailed);
+    assert!(message.contains("Shell sh"), "{}", message);
+    assert!(message.contains("exit code 4"), "{}", message);
+    assert!(message.contains("sh -c exit 4"), "{}", message);
+}
+
+#[tokio::test]
+async fn command_exiting_cleanly_is_not_a_failure() {
+    let state = start_state();
+    let session_id = create_session(&state, "done").await;
+
+    let mut client = common::attach(&state, &session_id);
+
+    // Its output, then the connection closes without an error
+    let mut output = String::new();
+    let closed = tokio::time::timeout(TIMEOUT, async {
+        while let Some(message) = client.receive().await {
+            match message.unwrap() {
+                ServerEnvelope::Output { data, .. } => output.push_str(&data),
+                ServerEnvelope::Error { code, message } => panic!("{:?}: {}", code, message),
+                _ => {}
+            }
+        }
+    })
+    .await;
+    assert!(closed.is_ok(), "the connection wasn't closed");
+    assert!(output.contains("done"), "{:?}", output);
+}
@@ -0,0 +1,182 @@
+//! Input envelopes with an `id` are answered with an `ack` once all of their data was written to
+//! the terminal, also when the PTY takes it a few bytes at a time; input without an `id` and input
+//! dropped by read-only sessions get no `ack`
+
+mod common;
+
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use futures_util::SinkExt;
+use rs_terminal::protocol::{ClientEnvelope, ServerEnvelope, Subprotocol};
+use rs_terminal::pty::{AsyncPty, MockPty, PtyConfig, PtyError, PtyExitStatus, PtyFactory};
+use serde_json::json;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::tungstenite::Message;
+
+/// Most bytes the PTY takes per write
+const WRITE_CHUNK: usize = 2;
+
+/// Mock PTY taking at most `WRITE_CHUNK` bytes per write and keeping what was written
+struct SlowWriter {
+    inner: MockPty,
+    written: Arc<Mutex<Vec<u8>>>,
+}
+
+impl AsyncRead for SlowWriter {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for SlowWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = &mut *self;
+        let buf = &buf[..buf.len().min(WRITE_CHUNK)];
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            this.written.lock().unwrap().extend_from_slice(&buf[..n]);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl AsyncPty for SlowWriter {
+    async fn resize(&mut self, cols: u16, rows: u16) -> Result<(), PtyError> {
+        self.inner.resize(cols, rows).await
+    }
+
+    fn pid(&self) -> Option<u32> {
+        self.inner.pid()
+    }
+
+    fn is_alive(&self) -> bool {
+        self.inner.is_alive()
+    }
+
+    async fn try_wait(&mut self) -> Result<Option<PtyExitStatus>, PtyError> {
+        self.inner.try_wait().await
+    }
+
+    async fn kill(&mut self) -> Result<(), PtyError> {
+        self.inner.kill().await
+    }
+}
+
+struct SlowWriterFactory {
+    written: Arc<Mutex<Vec<u8>>>,
+}
+
+#[async_trait]
+impl PtyFactory for SlowWriterFactory {
+    async fn create(&self, config: &PtyConfig) -> Result<Box<dyn AsyncPty>, PtyError> {
+        Ok(Box::new(SlowWriter {
+            inner: MockPty::new(config),
+            written: self.written.clone(),
+        }))
+    }
+
+    fn name(&self) -> &'static str {
+        "slow-writer"
+    }
+}
+
+/// Start a server, create a session through the REST API and attach to it with
+/// `waylon-terminal-v1`, returning the socket and the input that reached the PTY
+async fn attach(read_only: bool) -> (common::Socket, Arc<Mutex<Vec<u8>>>) {
+    let written = Arc::new(Mutex::new(Vec::new()));
+    let state = common::state(&common::config("")).with_pty_factory(Arc::new(SlowWriterFactory {
+        written: written.clone(),
+    }));
+    let (address, router) = common::start_server(state).await;
+
+    let session_id = common::create_session(&router, json!({ "readOnly": read_only })).await;
+    let url = format!("ws://{}/ws/{}", address, session_id);
+    let mut socket = common::connect(&url, Some(Subprotocol::V1)).await;
+    output_until(&mut socket, "mock$ ").await;
+    (socket, written)
+}
+
+/// Receive until the output ends with `expected`, returning the IDs acknowledged meanwhile
+async fn output_until(socket: &mut common::Socket, expected: &str) -> Vec<u64> {
+    let mut output = String::new();
+    let mut acks = Vec::new();
+    while !output.ends_with(expected) {
+        match common::next_envelope(socket).await {
+            ServerEnvelope::Output { data, .. } => output.push_str(&data),
+            ServerEnvelope::Ack { id } => acks.push(id),
+            _ => {}
+        }
+    }
+    acks
+}
+
+/// Receive until an ack arrives, returning its ID
+async fn ack(socket: &mut common::Socket) -> u64 {
+    loop {
+        if let ServerEnvelope::Ack { id } = common::next_envelope(socket).await {
+            return id;
+        }
+    }
+}
+
+#[tokio::test]
+async fn input_is_acknowledged_once_written() {
+    let (mut socket, written) = attach(false).await;
+
+    common::input(&mut socket, "echo written in pieces\r", Some(7)).await;
+    assert_eq!(ack(&mut socket).await, 7);
+    // All of it, even though the PTY took two bytes per write
+    assert_eq!(
+        written.lock().unwrap().as_slice(),
+        b"echo written in pieces\r"
+    );
+}
+
+#[tokio::test]
+async fn only_input_with_an_id_is_acknowledged() {
+    let (mut socket, _) = attach(false).await;
+
+    common::input(&mut socket, "first\r", None).await;
+    common::input(&mut socket, "second\r", Some(8)).await;
+    common::input(&mut socket, "third\r", None).await;
+    let acks = output_until(&mut socket, "third\r\nmock$ ").await;
+    assert_eq!(acks, [8]);
+}
+
+#[tokio::test]
+async fn dropped_input_is_not_acknowledged() {
+    let (mut socket, written) = attach(true).await;
+
+    common::input(&mut socket, "rm -rf ~\r", Some(9)).await;
+    // Answered by the terminal once the input before it was handled
+    let resize = ClientEnvelope::Resize {
+        columns: 100,
+        rows: 30,
+    };
+    let text = serde_json::to_string(&resize).unwrap();
+    socket.send(Message::Text(text)).await.unwrap();
+    let acks = output_until(&mut socket, "[resized to 100x30]\r\nmock$ ").await;
+
+    assert!(acks.is_empty(), "{:?}", acks);
+    assert!(written.lock().unwrap().is_empty());
+}
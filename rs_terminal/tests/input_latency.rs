@@ -0,0 +1,155 @@
+//! Input latency probe: the client's hello turns it on where `input_latency_probe` permits it,
+//! and the measured latency is the time the terminal took to echo the input
+
+mod common;
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use rs_terminal::app_state::MockClock;
+use rs_terminal::protocol::ClientEnvelope;
+use rs_terminal::pty::{AsyncPty, MockPty, PtyConfig, PtyError, PtyExitStatus, PtyFactory};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Mock PTY whose echo takes `delay` on the session's clock
+struct DelayedEcho {
+    inner: MockPty,
+    clock: Arc<MockClock>,
+    delay: Duration,
+}
+
+impl AsyncRead for DelayedEcho {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for DelayedEcho {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = &mut *self;
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        // The echo is queued by the write, time passes before the session can read it
+        this.clock.advance(this.delay);
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl AsyncPty for DelayedEcho {
+    async fn resize(&mut self, cols: u16, rows: u16) -> Result<(), PtyError> {
+        self.inner.resize(cols, rows).await
+    }
+
+    fn pid(&self) -> Option<u32> {
+        self.inner.pid()
+    }
+
+    fn is_alive(&self) -> bool {
+        self.inner.is_alive()
+    }
+
+    async fn try_wait(&mut self) -> Result<Option<PtyExitStatus>, PtyError> {
+        self.inner.try_wait().await
+    }
+
+    async fn kill(&mut self) -> Result<(), PtyError> {
+        self.inner.kill().await
+    }
+}
+
+struct DelayedEchoFactory {
+    clock: Arc<MockClock>,
+    delay: Duration,
+}
+
+#[async_trait]
+impl PtyFactory for DelayedEchoFactory {
+    async fn create(&self, config: &PtyConfig) -> Result<Box<dyn AsyncPty>, PtyError> {
+        Ok(Box::new(DelayedEcho {
+            inner: MockPty::new(config),
+            clock: self.clock.clone(),
+            delay: self.delay,
+        }))
+    }
+
+    fn name(&self) -> &'static str {
+        "delayed-echo"
+    }
+}
+
+/// Run a session on a mock PTY echoing after `delay`, type a line and return the p95 latency
+/// measured once the echo arrived (`None` if nothing was measured)
+async fn measured_p95(permitted: bool, hello: bool, delay: Duration) -> Option<f64> {
+    let clock = Arc::new(MockClock::new(0));
+    let settings = format!("input_latency_probe = {}", permitted);
+    let state = common::state(&common::config(&settings))
+        .with_clock(clock.clone())
+        .with_pty_factory(Arc::new(DelayedEchoFactory { clock, delay }));
+
+    let session_id = "latency-probe";
+    let mut client = common::attach(&state, session_id);
+    common::output_until(&mut client, "mock$ ").await;
+    if hello {
+        client
+            .send(&ClientEnvelope::Hello {
+                latency_probe: true,
+            })
+            .await
+            .unwrap();
+    }
+
+    client.input("a").await.unwrap();
+    common::output_until(&mut client, "a").await;
+    // Recorded right after the echo was forwarded
+    let started = tokio::time::Instant::now();
+    loop {
+        let p95 = state.input_latency.p95_ms(session_id).await;
+        if p95.is_some() || started.elapsed() > Duration::from_millis(200) {
+            return p95;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test]
+async fn instant_echo_measures_no_latency() {
+    let p95 = measured_p95(true, true, Duration::ZERO).await;
+    assert_eq!(p95, Some(0.0));
+}
+
+#[tokio::test]
+async fn delayed_echo_measures_the_delay() {
+    let p95 = measured_p95(true, true, Duration::from_millis(40)).await;
+    assert_eq!(p95, Some(40.0));
+}
+
+#[tokio::test]
+async fn probe_needs_the_clients_hello() {
+    let p95 = measured_p95(true, false, Duration::from_millis(40)).await;
+    assert_eq!(p95, None);
+}
+
+#[tokio::test]
+async fn probe_needs_the_servers_permission() {
+    let p95 = measured_p95(false, true, Duration::from_millis(40)).await;
+    assert_eq!(p95, None);
+}
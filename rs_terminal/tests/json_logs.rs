@@ -0,0 +1,129 @@
+//! `--log-format json` logs one JSON object per line; session activity carries the `session` span
+//! with its `session_id`, inside the `pty_create`, `pty_read`, `pty_write` and `client_message`
+//! spans of what the session was doing
+#![cfg(unix)]
+
+use std::collections::BTreeSet;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::Value;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+/// Longest wait for the server to start or answer
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+fn config(http_port: u16) -> String {
+    format!(
+        r#"
+http_port = {}
+enable_webtransport = false
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+
+[default_shell_config]
+size = {{ columns = 80, rows = 24 }}
+working_directory = "."
+
+[shells.sh]
+command = ["sh"]
+"#,
+        http_port
+    )
+}
+
+/// A TCP port nothing listens on
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[tokio::test]
+async fn session_activity_is_logged_as_json_with_its_spans() {
+    let port = free_port();
+    let mut server = Command::new(env!("CARGO_BIN_EXE_rs_terminal"))
+        .args(["--log-format", "json", "--config", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    server
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(config(port).as_bytes())
+        .unwrap();
+    let stdout = server.stdout.take().unwrap();
+    let lines = std::thread::spawn(move || {
+        BufReader::new(stdout)
+            .lines()
+            .map_while(Result::ok)
+            .collect::<Vec<_>>()
+    });
+
+    let url = format!("ws://127.0.0.1:{}/ws/traced", port);
+    let session = tokio::time::timeout(TIMEOUT, async {
+        let mut socket = loop {
+            match connect_async(url.as_str()).await {
+                Ok((socket, _)) => break socket,
+                Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+            }
+        };
+        socket
+            .send(Message::Text("echo traced\r".to_string()))
+            .await
+            .unwrap();
+        let mut output = String::new();
+        while output.matches("traced").count() < 2 {
+            match socket.next().await {
+                Some(Ok(Message::Text(text))) => output.push_str(&text),
+                Some(Ok(_)) => continue,
+                other => panic!("connection ended with {:?}", other),
+            }
+        }
+        socket.close(None).await.unwrap();
+        // Lets the session log its end
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    })
+    .await;
+    server.kill().unwrap();
+    server.wait().unwrap();
+    session.expect("the session didn't run");
+
+    let lines = lines.join().unwrap();
+    let mut activities = BTreeSet::new();
+    for line in &lines {
+        let entry: Value = serde_json::from_str(line)
+            .unwrap_or_else(|e| panic!("not a JSON log line ({}): {}", e, line));
+        let Some(spans) = entry["spans"].as_array() else {
+            continue;
+        };
+        let in_session = spans
+            .iter()
+            .any(|span| span["name"] == "session" && span["session_id"] == "traced");
+        if in_session {
+            activities.extend(
+                spans
+                    .iter()
+                    .filter_map(|span| Some(span["name"].as_str()?.to_string())),
+            );
+        }
+    }
+    for activity in ["pty_create", "pty_read", "pty_write", "client_message"] {
+        assert!(
+            activities.contains(activity),
+            "no {} span in the session's log lines: {:?}",
+            activity,
+            activities
+        );
+    }
+}
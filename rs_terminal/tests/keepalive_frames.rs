@@ -0,0 +1,201 @@
+//! Empty text and binary frames keep a WebSocket connection alive: they are neither input nor
+//! envelopes, so nothing is written to the PTY and the session's input counter stays put. Input
+//! envelopes with empty data don't reach the PTY either
+
+mod common;
+
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use futures_util::{SinkExt, StreamExt};
+use rs_terminal::protocol::{ServerEnvelope, Subprotocol};
+use rs_terminal::pty::{AsyncPty, MockPty, PtyConfig, PtyError, PtyExitStatus, PtyFactory};
+use serde_json::json;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::tungstenite::Message;
+
+/// Longest wait for output of the session
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Every write to the PTY, one entry per call
+type Writes = Arc<Mutex<Vec<Vec<u8>>>>;
+
+/// Mock PTY recording each write call
+struct WriteRecorder {
+    inner: MockPty,
+    writes: Writes,
+}
+
+impl AsyncRead for WriteRecorder {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for WriteRecorder {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = &mut *self;
+        this.writes.lock().unwrap().push(buf.to_vec());
+        Pin::new(&mut this.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl AsyncPty for WriteRecorder {
+    async fn resize(&mut self, cols: u16, rows: u16) -> Result<(), PtyError> {
+        self.inner.resize(cols, rows).await
+    }
+
+    fn pid(&self) -> Option<u32> {
+        self.inner.pid()
+    }
+
+    fn is_alive(&self) -> bool {
+        self.inner.is_alive()
+    }
+
+    async fn try_wait(&mut self) -> Result<Option<PtyExitStatus>, PtyError> {
+        self.inner.try_wait().await
+    }
+
+    async fn kill(&mut self) -> Result<(), PtyError> {
+        self.inner.kill().await
+    }
+}
+
+struct WriteRecorderFactory {
+    writes: Writes,
+}
+
+#[async_trait]
+impl PtyFactory for WriteRecorderFactory {
+    async fn create(&self, config: &PtyConfig) -> Result<Box<dyn AsyncPty>, PtyError> {
+        Ok(Box::new(WriteRecorder {
+            inner: MockPty::new(config),
+            writes: self.writes.clone(),
+        }))
+    }
+
+    fn name(&self) -> &'static str {
+        "write-recorder"
+    }
+}
+
+/// Server with a session created through the REST API, attached to over a WebSocket
+struct Attached {
+    router: axum::Router,
+    session_id: String,
+    socket: common::Socket,
+    writes: Writes,
+}
+
+impl Attached {
+    /// Start a server, create a session and attach to it offering `subprotocol`
+    async fn new(subprotocol: Option<Subprotocol>) -> Self {
+        let writes = Writes::default();
+        let state =
+            common::state(&common::config("")).with_pty_factory(Arc::new(WriteRecorderFactory {
+                writes: writes.clone(),
+            }));
+        let (address, router) = common::start_server(state).await;
+        let session_id = common::create_session(&router, json!({})).await;
+        let url = format!("ws://{}/ws/{}", address, session_id);
+        let socket = common::connect(&url, subprotocol).await;
+        let mut attached = Self {
+            router,
+            session_id,
+            socket,
+            writes,
+        };
+        attached.output_until("mock$ ").await;
+        attached
+    }
+
+    /// Receive output until it ends with `expected`, failing on errors
+    async fn output_until(&mut self, expected: &str) {
+        let mut output = String::new();
+        tokio::time::timeout(TIMEOUT, async {
+            while !output.ends_with(expected) {
+                let message = self.socket.next().await.expect("connection closed");
+                let Message::Text(text) = message.unwrap() else {
+                    continue;
+                };
+                match serde_json::from_str::<ServerEnvelope>(&text) {
+                    Ok(ServerEnvelope::Output { data, .. }) => output.push_str(&data),
+                    Ok(ServerEnvelope::Error { code, message }) => {
+                        panic!("error {:?}: {}", code, message)
+                    }
+                    Ok(_) => {}
+                    Err(_) => output.push_str(&text),
+                }
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("no {:?} in the output {:?}", expected, output));
+    }
+
+    async fn send(&mut self, message: Message) {
+        self.socket.send(message).await.unwrap();
+    }
+
+    /// Messages the session counted as input
+    async fn input_messages(&self) -> u64 {
+        let uri = format!("/api/sessions/{}/diagnostics", self.session_id);
+        let (status, diagnostics) = common::call(&self.router, "GET", &uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        diagnostics["counters"]["inputMessages"].as_u64().unwrap()
+    }
+
+    fn writes(&self) -> Vec<Vec<u8>> {
+        self.writes.lock().unwrap().clone()
+    }
+}
+
+#[tokio::test]
+async fn empty_raw_frames_are_keepalives() {
+    let mut attached = Attached::new(None).await;
+
+    attached.send(Message::Text(String::new())).await;
+    attached.send(Message::Binary(Vec::new())).await;
+    attached.send(Message::Text("ls\r".to_string())).await;
+    attached.output_until("ls\r\nmock$ ").await;
+
+    // Frames are handled in order, so the keepalives were seen before the input
+    assert_eq!(attached.writes(), [b"ls\r".to_vec()]);
+    assert_eq!(attached.input_messages().await, 1);
+}
+
+#[tokio::test]
+async fn empty_frames_and_input_are_not_written_on_v1() {
+    let mut attached = Attached::new(Some(Subprotocol::V1)).await;
+
+    // An empty text frame isn't a malformed envelope
+    attached.send(Message::Text(String::new())).await;
+    common::input(&mut attached.socket, "", None).await;
+    common::input(&mut attached.socket, "ls\r", None).await;
+    attached.output_until("ls\r\nmock$ ").await;
+
+    assert_eq!(attached.writes(), [b"ls\r".to_vec()]);
+    // The empty input envelope is a message with data, just none to write
+    assert_eq!(attached.input_messages().await, 2);
+}
@@ -0,0 +1,72 @@
+//! Connections without traffic for `keepalive_interval_secs` get WebSocket pings; the client's
+//! pongs never reach the shell nor count as input, and an interval of 0 sends none
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::Router;
+use axum::http::StatusCode;
+use futures_util::{SinkExt, StreamExt};
+use rs_terminal::pty::MockPtyFactory;
+use tokio_tungstenite::tungstenite::Message;
+
+/// Start a server pinging idle connections every `interval` seconds and attach a raw client to
+/// `/ws/:session_id`, waiting for the prompt
+async fn attach(interval: u64, session_id: &str) -> (Router, common::Socket) {
+    let config = common::config(&format!("keepalive_interval_secs = {}", interval));
+    let state = common::state(&config).with_pty_factory(Arc::new(MockPtyFactory));
+    let (address, router) = common::start_server(state).await;
+
+    let url = format!("ws://{}/ws/{}", address, session_id);
+    let mut socket = common::connect(&url, None).await;
+    output_until(&mut socket, "mock$ ").await;
+    (router, socket)
+}
+
+/// Receive until the output ends with `expected`, failing on pings
+async fn output_until(socket: &mut common::Socket, expected: &str) {
+    let mut output = String::new();
+    while !output.ends_with(expected) {
+        match common::next_frame(socket).await {
+            Message::Text(text) => output.push_str(&text),
+            other => panic!("unexpected {:?} in the output {:?}", other, output),
+        }
+    }
+}
+
+/// Messages the session counted as input
+async fn input_messages(router: &Router, session_id: &str) -> u64 {
+    let uri = format!("/api/sessions/{}/diagnostics", session_id);
+    let (status, diagnostics) = common::call(router, "GET", &uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+    diagnostics["counters"]["inputMessages"].as_u64().unwrap()
+}
+
+#[tokio::test]
+async fn idle_connection_is_pinged() {
+    let (router, mut socket) = attach(1, "idle").await;
+
+    // Two intervals without traffic, each ends with a ping (reading it queues the pong)
+    for _ in 0..2 {
+        let frame = common::next_frame(&mut socket).await;
+        assert!(matches!(frame, Message::Ping(_)), "{:?}", frame);
+    }
+
+    // The pongs sent meanwhile didn't reach the shell: the next output is only the echo
+    socket
+        .send(Message::Text("ls\r".to_string()))
+        .await
+        .unwrap();
+    output_until(&mut socket, "ls\r\nmock$ ").await;
+    assert_eq!(input_messages(&router, "idle").await, 1);
+}
+
+#[tokio::test]
+async fn zero_interval_sends_no_pings() {
+    let (_router, mut socket) = attach(0, "unpinged").await;
+
+    let frame = tokio::time::timeout(Duration::from_millis(2500), socket.next()).await;
+    assert!(frame.is_err(), "{:?}", frame);
+}
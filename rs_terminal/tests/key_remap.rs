@@ -0,0 +1,70 @@
+//! Key remapping of client input: the longest matching sequence wins over its prefixes,
+//! replacements aren't remapped again, and input without a mapped sequence is passed through
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use rs_terminal::service::KeyRemap;
+
+fn remap(rules: &[(&str, &str)]) -> KeyRemap {
+    let table: HashMap<String, String> = rules
+        .iter()
+        .map(|(from, to)| (from.to_string(), to.to_string()))
+        .collect();
+    KeyRemap::new(&table)
+}
+
+#[test]
+fn longest_sequence_wins_over_its_prefixes() {
+    let remap = remap(&[
+        ("<C", "prefix"),
+        ("<C-c>", "\u{3}"),
+        ("<C-c><C-c>", "twice"),
+    ]);
+
+    assert_eq!(remap.apply(b"<C-c>").as_ref(), b"\x03");
+    assert_eq!(remap.apply(b"<C-c><C-c>").as_ref(), b"twice");
+    assert_eq!(remap.apply(b"<C-c><C-c><C-c>").as_ref(), b"twice\x03");
+    // Only the prefix matches when the longer sequence is incomplete
+    assert_eq!(remap.apply(b"<C-x>").as_ref(), b"prefix-x>");
+}
+
+#[test]
+fn sequences_are_replaced_inside_other_input() {
+    let remap = remap(&[("<C-c>", "\u{3}"), ("<Esc>", "\u{1b}")]);
+
+    assert_eq!(
+        remap.apply(b"sleep 10<C-c>ls<Esc>:q").as_ref(),
+        b"sleep 10\x03ls\x1b:q"
+    );
+}
+
+#[test]
+fn replacements_are_not_remapped_again() {
+    let remap = remap(&[("a", "b"), ("b", "c")]);
+
+    assert_eq!(remap.apply(b"ab").as_ref(), b"bc");
+}
+
+#[test]
+fn sequence_split_across_two_messages_is_passed_through() {
+    let remap = remap(&[("<C-c>", "\u{3}")]);
+
+    // Each message is remapped on its own, so the halves reach the PTY unchanged
+    assert_eq!(remap.apply(b"echo <C-").as_ref(), b"echo <C-");
+    assert_eq!(remap.apply(b"c>").as_ref(), b"c>");
+}
+
+#[test]
+fn input_without_a_sequence_is_borrowed() {
+    let remap = remap(&[("<C-c>", "\u{3}")]);
+
+    assert!(matches!(
+        remap.apply(b"plain input"),
+        Cow::Borrowed(b"plain input")
+    ));
+    assert!(matches!(remap.apply(b""), Cow::Borrowed(b"")));
+    // Empty sequences are left out of the table
+    let empty = self::remap(&[("", "ignored")]);
+    assert!(matches!(empty.apply(b"<C-c>"), Cow::Borrowed(b"<C-c>")));
+}
@@ -0,0 +1,213 @@
+//! `frame_mode = "lines"`: output is sent one frame per line whatever pieces the shell printed it
+//! in; lines longer than `frame_max_line_bytes` go in pieces split between characters, and a line
+//! without its newline is sent once the shell exits, also before the error of a shell that exited
+//! right after starting
+
+mod common;
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+use async_trait::async_trait;
+use futures_util::SinkExt;
+use rs_terminal::protocol::{ErrorCode, ServerEnvelope, Subprotocol};
+use rs_terminal::pty::{AsyncPty, MockPty, PtyConfig, PtyError, PtyExitStatus, PtyFactory};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::tungstenite::Message;
+
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+nudge_on_connect = false
+frame_mode = "lines"
+frame_max_line_bytes = 12
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+
+[shells.sh]
+command = ["sh"]
+"#;
+
+/// What the shell prints, one read each, before it exits: lines cut anywhere, a line longer than
+/// the 12 bytes of a frame with `ü` across the cut, and a prompt without a newline
+const READS: [&str; 4] = [
+    "first li",
+    "ne\r\nsecond\r\n",
+    "abcdefghijkü\r\n",
+    "prompt$ ",
+];
+
+/// Frames the reads are sent in
+const FRAMES: [&str; 5] = [
+    "first line\r\n",
+    "second\r\n",
+    "abcdefghijk",
+    "ü\r\n",
+    "prompt$ ",
+];
+
+/// Mock PTY printing `READS`, then exiting once it gets input (or right away)
+struct ScriptedPty {
+    inner: MockPty,
+    reads: VecDeque<&'static str>,
+    exiting: bool,
+    reader: Option<Waker>,
+}
+
+impl AsyncRead for ScriptedPty {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        if let Some(read) = self.reads.pop_front() {
+            buf.put_slice(read.as_bytes());
+        } else if !self.exiting {
+            self.reader = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        // Nothing put into the buffer once exiting: EOF
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for ScriptedPty {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.exiting = true;
+        if let Some(reader) = self.reader.take() {
+            reader.wake();
+        }
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl AsyncPty for ScriptedPty {
+    async fn resize(&mut self, cols: u16, rows: u16) -> Result<(), PtyError> {
+        self.inner.resize(cols, rows).await
+    }
+
+    fn pid(&self) -> Option<u32> {
+        self.inner.pid()
+    }
+
+    fn is_alive(&self) -> bool {
+        self.inner.is_alive()
+    }
+
+    async fn try_wait(&mut self) -> Result<Option<PtyExitStatus>, PtyError> {
+        self.inner.try_wait().await
+    }
+
+    async fn kill(&mut self) -> Result<(), PtyError> {
+        self.inner.kill().await
+    }
+}
+
+struct ScriptedPtyFactory {
+    /// Exit right after printing, before any input
+    exit_at_once: bool,
+}
+
+#[async_trait]
+impl PtyFactory for ScriptedPtyFactory {
+    async fn create(&self, config: &PtyConfig) -> Result<Box<dyn AsyncPty>, PtyError> {
+        Ok(Box::new(ScriptedPty {
+            inner: MockPty::new(config),
+            reads: READS.into(),
+            exiting: self.exit_at_once,
+            reader: None,
+        }))
+    }
+
+    fn name(&self) -> &'static str {
+        "scripted"
+    }
+}
+
+/// Start a server and connect to it offering `subprotocol`
+async fn connect(subprotocol: Option<Subprotocol>, exit_at_once: bool) -> common::Socket {
+    let state =
+        common::state(CONFIG).with_pty_factory(Arc::new(ScriptedPtyFactory { exit_at_once }));
+    let (address, _) = common::start_server(state).await;
+    common::connect(&format!("ws://{}/ws", address), subprotocol).await
+}
+
+#[tokio::test]
+async fn output_is_sent_one_line_per_frame() {
+    let mut socket = connect(None, false).await;
+
+    let mut frames = Vec::new();
+    for _ in &FRAMES[..FRAMES.len() - 1] {
+        frames.push(common::next_text(&mut socket).await);
+    }
+    // The prompt waits for its newline until the shell exits
+    socket
+        .send(Message::Text("exit\r".to_string()))
+        .await
+        .unwrap();
+    frames.push(common::next_text(&mut socket).await);
+    assert_eq!(frames, FRAMES);
+}
+
+#[tokio::test]
+async fn v1_lines_carry_the_seq_of_the_output_completing_them() {
+    let mut socket = connect(Some(Subprotocol::V1), false).await;
+
+    let mut lines = Vec::new();
+    while lines.len() < FRAMES.len() {
+        if lines.len() == FRAMES.len() - 1 {
+            let input = r#"{"type":"input","data":"exit\r"}"#;
+            socket.send(Message::Text(input.to_string())).await.unwrap();
+        }
+        let envelope = serde_json::from_str(&common::next_text(&mut socket).await).unwrap();
+        if let ServerEnvelope::Output { seq, data } = envelope {
+            lines.push((seq, data));
+        }
+    }
+
+    let data: Vec<_> = lines.iter().map(|(_, data)| data.as_str()).collect();
+    assert_eq!(data, FRAMES);
+    let seqs: Vec<_> = lines.iter().map(|(seq, _)| *seq).collect();
+    // Both lines completed by the second read, the long line's pieces by the third, the prompt
+    // by the last one
+    assert_eq!(seqs[0], seqs[1]);
+    assert!(seqs[1] < seqs[2], "{:?}", seqs);
+    assert_eq!(seqs[2], seqs[3]);
+    assert!(seqs[3] < seqs[4], "{:?}", seqs);
+}
+
+#[tokio::test]
+async fn last_line_of_a_shell_exiting_at_once_goes_before_its_error() {
+    let mut socket = connect(Some(Subprotocol::V1), true).await;
+
+    let mut data = Vec::new();
+    let code = loop {
+        let envelope = serde_json::from_str(&common::next_text(&mut socket).await).unwrap();
+        match envelope {
+            ServerEnvelope::Output { data: line, .. } => data.push(line),
+            ServerEnvelope::Error { code, .. } => break code,
+            _ => {}
+        }
+    };
+
+    assert_eq!(data, FRAMES);
+    assert_eq!(code, ErrorCode::SpawnFailed);
+}
@@ -0,0 +1,97 @@
+//! SGR mouse reports, as the Rust client sends them with `--mouse`, reach the PTY byte for byte
+//! over a real WebSocket connection, in raw text and binary frames and in v1 input envelopes
+
+mod common;
+
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use rs_terminal::protocol::{ServerEnvelope, Subprotocol};
+use tokio_tungstenite::tungstenite::Message;
+
+/// `cat -v` shows the escape sequences it reads, e.g. `^[[<0;10;5M`; the terminal doesn't echo
+/// the input, so only its output arrives
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "cat"
+session_timeout = 1800000
+nudge_on_connect = false
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+environment = { TERM = "xterm" }
+
+[shells.cat]
+command = ["sh", "-c", "stty -echo; echo ready; exec cat -v"]
+"#;
+
+/// Longest wait for the expected output
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+async fn start_server() -> String {
+    let (address, _) = common::start_server(common::state(CONFIG)).await;
+    format!("ws://{}/ws", address)
+}
+
+/// Connect, offering `subprotocol`, and wait until `cat` runs with echo off
+async fn connect(url: &str, subprotocol: Option<Subprotocol>) -> common::Socket {
+    let mut socket = common::connect(url, subprotocol).await;
+    output_until(&mut socket, "ready").await;
+    socket
+}
+
+/// Terminal output of a frame, unwrapping output envelopes
+fn output_of(message: Message) -> String {
+    let Message::Text(text) = message else {
+        return String::new();
+    };
+    match serde_json::from_str::<ServerEnvelope>(&text) {
+        Ok(ServerEnvelope::Output { data, .. }) => data,
+        Ok(_) => String::new(),
+        Err(_) => text,
+    }
+}
+
+/// Receive output until it contains `expected`, returning all of it
+async fn output_until(socket: &mut common::Socket, expected: &str) -> String {
+    let mut output = String::new();
+    tokio::time::timeout(TIMEOUT, async {
+        while !output.contains(expected) {
+            let message = socket.next().await.expect("connection closed").unwrap();
+            output.push_str(&output_of(message));
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("no {:?} in the output {:?}", expected, output));
+    output
+}
+
+#[tokio::test]
+async fn mouse_reports_reach_the_pty() {
+    let url = start_server().await;
+
+    // Press and release in a raw text frame
+    let mut socket = connect(&url, None).await;
+    socket
+        .send(Message::Text("\x1b[<0;10;5M\x1b[<0;10;5m\r".to_string()))
+        .await
+        .unwrap();
+    output_until(&mut socket, "^[[<0;10;5M^[[<0;10;5m\r\n").await;
+
+    // Drag and scroll in a binary frame
+    socket
+        .send(Message::Binary(b"\x1b[<32;21;4M\x1b[<64;5;8M\r".to_vec()))
+        .await
+        .unwrap();
+    output_until(&mut socket, "^[[<32;21;4M^[[<64;5;8M\r\n").await;
+
+    // Release with modifiers and a far position in an input envelope
+    let mut socket = connect(&url, Some(Subprotocol::V1)).await;
+    let envelope = r#"{"type":"input","data":"\u001b[<50;300;100m\u001b[<69;5;8M\r"}"#;
+    socket
+        .send(Message::Text(envelope.to_string()))
+        .await
+        .unwrap();
+    output_until(&mut socket, "^[[<50;300;100m^[[<69;5;8M\r\n").await;
+}
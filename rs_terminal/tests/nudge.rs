@@ -0,0 +1,79 @@
+//! With `nudge_on_connect` a newline is written to the shell when a client connects, so a prompt
+//! line shows up without waiting for input; sessions can turn it off with `nudgeOnConnect`
+
+mod common;
+
+use std::sync::Arc;
+
+use rs_terminal::app_state::AppState;
+use rs_terminal::protocol::ChannelClient;
+use rs_terminal::pty::MockPtyFactory;
+use rs_terminal::server::build_router;
+use serde_json::{Value, json};
+
+/// `sh` prints its prompt for every line it reads, including the nudge
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+nudge_on_connect = true
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+
+[shells.sh]
+command = ["sh", "-i"]
+environment = { PS1 = "nudged> " }
+"#;
+
+/// Banner of the mock shell, ending in its first prompt
+const MOCK_BANNER: &str = "mock shell for `sh` (80x24), type exit to end\r\nmock$ ";
+
+fn state(mock: bool) -> AppState {
+    let state = common::state(CONFIG);
+    if mock {
+        state.with_pty_factory(Arc::new(MockPtyFactory))
+    } else {
+        state
+    }
+}
+
+/// Create a session with the given request and attach to it
+async fn attach(state: &AppState, body: Value) -> ChannelClient {
+    let session_id = common::create_session(&build_router(state.clone()), body).await;
+    common::attach(state, &session_id)
+}
+
+#[tokio::test]
+async fn nudge_makes_the_shell_print_another_prompt() {
+    let state = state(false);
+    let mut client = attach(&state, json!({})).await;
+
+    // The prompt of the shell starting, and the one of the empty line it was sent
+    let output = common::output_matching(&mut client, |output| {
+        output.matches("nudged> ").count() >= 2
+    })
+    .await;
+    assert!(output.contains('\n'), "{:?}", output);
+}
+
+#[tokio::test]
+async fn nudge_prints_a_prompt_line_on_connect() {
+    let state = state(true);
+    let mut client = attach(&state, json!({})).await;
+
+    let output = common::output_until(&mut client, "mock$ \r\nmock$ ").await;
+    assert_eq!(output, format!("{}\r\nmock$ ", MOCK_BANNER));
+}
+
+#[tokio::test]
+async fn session_can_turn_the_nudge_off() {
+    let state = state(true);
+    let mut client = attach(&state, json!({ "nudgeOnConnect": false })).await;
+
+    // The first thing after the banner is the echo of the input, not another prompt
+    client.input("x").await.unwrap();
+    let output = common::output_until(&mut client, "x").await;
+    assert_eq!(output, format!("{}x", MOCK_BANNER));
+}
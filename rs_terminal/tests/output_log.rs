@@ -0,0 +1,128 @@
+//! `session_output_log` records what the PTY printed for a session, never the input the client
+//! sent, rotates the file once it reaches `session_output_log_max_bytes`, and gives every session
+//! ID a file of its own
+
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rs_terminal::protocol::channel_connection;
+use rs_terminal::pty::MockPtyFactory;
+use rs_terminal::service::handle_terminal_session;
+
+/// Longest wait for a message of the session
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn config(directory: &Path, max_bytes: u64) -> String {
+    common::config(&format!(
+        "session_output_log = \"{}/{{session_id}}.log\"\nsession_output_log_max_bytes = {}",
+        directory.display(),
+        max_bytes
+    ))
+}
+
+fn log_directory(name: &str) -> PathBuf {
+    let directory =
+        std::env::temp_dir().join(format!("output-log-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&directory);
+    directory
+}
+
+/// Run a session on a mock PTY, typing each of `inputs` after the prompt, then `exit`
+/// Returns the output the client received once the session ended
+async fn run_session(
+    directory: &Path,
+    max_bytes: u64,
+    session_id: &str,
+    inputs: &[&str],
+) -> String {
+    let state =
+        common::state(&config(directory, max_bytes)).with_pty_factory(Arc::new(MockPtyFactory));
+
+    let (connection, mut client) = channel_connection(session_id);
+    let session = tokio::spawn(handle_terminal_session(connection, state));
+    let mut output = common::output_until(&mut client, "mock$ ").await;
+    for input in inputs {
+        client.input(input).await.unwrap();
+        output.push_str(&common::output_until(&mut client, "mock$ ").await);
+    }
+    client.input("exit\r").await.unwrap();
+    output.push_str(&common::output_until(&mut client, "exit\r\n").await);
+
+    // The log is flushed when the session ends
+    tokio::time::timeout(TIMEOUT, session)
+        .await
+        .expect("session didn't end")
+        .unwrap();
+    output
+}
+
+#[tokio::test]
+async fn output_is_logged_and_input_is_not() {
+    let directory = log_directory("output");
+    // Backspaces and Ctrl+C are input the mock shell doesn't echo
+    let input = "typo\x7f\x7fpe\x03";
+    let output = run_session(&directory, 1024 * 1024, "audited", &[input]).await;
+
+    let log = std::fs::read_to_string(directory.join("audited.log")).unwrap();
+    assert_eq!(log, output);
+    assert!(log.contains("mock shell for `sh`"), "{:?}", log);
+    assert!(log.contains("typo\x08 \x08\x08 \x08pe^C"), "{:?}", log);
+    assert!(!log.contains(input), "{:?}", log);
+    assert!(!log.contains(['\x7f', '\x03']), "{:?}", log);
+
+    let _ = std::fs::remove_dir_all(&directory);
+}
+
+#[tokio::test]
+async fn log_is_rotated_at_the_size_limit() {
+    let directory = log_directory("rotation");
+    let inputs = ["first line\r", "second line\r", "third line\r"];
+    run_session(&directory, 64, "rotated", &inputs).await;
+
+    let current = std::fs::read(directory.join("rotated.log")).unwrap();
+    let rotated = std::fs::read(directory.join("rotated.log.1")).unwrap();
+    assert!(!current.is_empty());
+    assert!(!rotated.is_empty());
+    assert!(current.len() <= 64, "{}", current.len());
+    assert!(rotated.len() <= 64, "{}", rotated.len());
+
+    let _ = std::fs::remove_dir_all(&directory);
+}
+
+#[tokio::test]
+async fn session_id_stays_inside_the_log_directory() {
+    let directory = log_directory("sanitized");
+    run_session(&directory, 1024 * 1024, "../escaped", &[]).await;
+
+    assert!(directory.join("%2E%2E%2Fescaped.log").is_file());
+    assert!(!directory.with_file_name("escaped.log").exists());
+
+    let _ = std::fs::remove_dir_all(&directory);
+}
+
+#[tokio::test]
+async fn similar_session_ids_get_their_own_logs() {
+    let directory = log_directory("distinct");
+    let dotted = run_session(&directory, 1024 * 1024, "a.b", &["dotted\r"]).await;
+    let underscored = run_session(&directory, 1024 * 1024, "a_b", &["underscored\r"]).await;
+    let upper = run_session(&directory, 1024 * 1024, "A_b", &["upper\r"]).await;
+
+    assert_eq!(
+        std::fs::read_to_string(directory.join("a%2Eb.log")).unwrap(),
+        dotted
+    );
+    assert_eq!(
+        std::fs::read_to_string(directory.join("a_b.log")).unwrap(),
+        underscored
+    );
+    assert_eq!(
+        std::fs::read_to_string(directory.join("%41_b.log")).unwrap(),
+        upper
+    );
+    assert!(!underscored.contains("dotted"), "{:?}", underscored);
+
+    let _ = std::fs::remove_dir_all(&directory);
+}
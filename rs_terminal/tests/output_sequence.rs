@@ -0,0 +1,68 @@
+//! Output envelopes of `waylon-terminal-v1` are numbered per session, and a client reattaching
+//! with `resume_from` gets the output from that number on replayed from the scrollback before the
+//! live output continues
+
+mod common;
+
+use std::sync::Arc;
+
+use axum::Router;
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use rs_terminal::pty::MockPtyFactory;
+use tower::ServiceExt;
+
+/// Start a server on mock PTYs, returning its address and a router on the same state
+async fn start_server() -> (String, Router) {
+    let state = common::state(&common::config("")).with_pty_factory(Arc::new(MockPtyFactory));
+    common::start_server(state).await
+}
+
+/// Attach to `path` with `waylon-terminal-v1`, skipping the hello
+async fn attach(address: &str, path: &str) -> common::Socket {
+    common::connect_v1(&format!("ws://{}{}", address, path)).await
+}
+
+#[tokio::test]
+async fn sequence_numbers_increase_and_replay_starts_at_the_requested_one() {
+    let (address, router) = start_server().await;
+
+    let mut socket = attach(&address, "/ws/numbered").await;
+    let mut chunks = common::numbered_output_until(&mut socket, "mock$ ").await;
+    for line in ["first\r", "second\r", "third\r"] {
+        common::input(&mut socket, line, None).await;
+        chunks
+            .extend(common::numbered_output_until(&mut socket, &format!("{}\nmock$ ", line)).await);
+    }
+    assert!(chunks.len() >= 4, "{:?}", chunks);
+    assert!(
+        chunks.windows(2).all(|pair| pair[0].0 < pair[1].0),
+        "{:?}",
+        chunks
+    );
+
+    // Leave the shell running and come back asking for the output from the third chunk on
+    let request = Request::post("/api/sessions/numbered/disconnect")
+        .body(Body::empty())
+        .unwrap();
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    drop(socket);
+
+    let resume_from = chunks[2].0;
+    let mut socket = attach(
+        &address,
+        &format!("/ws/numbered?resume_from={}", resume_from),
+    )
+    .await;
+    let missed: String = chunks[2..].iter().map(|(_, data)| data.as_str()).collect();
+    let replayed = common::numbered_output_until(&mut socket, &missed).await;
+    assert_eq!(replayed.first().map(|(seq, _)| *seq), Some(resume_from));
+    assert_eq!(replayed, chunks[2..]);
+
+    // Live output continues after the replayed numbers
+    common::input(&mut socket, "fourth\r", None).await;
+    let live = common::numbered_output_until(&mut socket, "fourth\r\nmock$ ").await;
+    let last_seq = chunks.last().unwrap().0;
+    assert!(live.iter().all(|(seq, _)| *seq > last_seq), "{:?}", live);
+}
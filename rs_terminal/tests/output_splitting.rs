@@ -0,0 +1,176 @@
+//! `waylon-terminal-v1` connections start with a `hello` envelope carrying `max_message_bytes`;
+//! output whose envelope would be larger is split into several envelopes of the same `seq`, each
+//! within the limit and split between characters. Raw connections get no `hello`
+
+mod common;
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use rs_terminal::protocol::{ServerEnvelope, Subprotocol};
+use rs_terminal::pty::{AsyncPty, MockPty, PtyConfig, PtyError, PtyExitStatus, PtyFactory};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+nudge_on_connect = false
+max_message_bytes = 200
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+
+[shells.sh]
+command = ["sh"]
+"#;
+
+/// Configured `max_message_bytes`
+const MAX_MESSAGE_BYTES: usize = 200;
+
+/// Output of a single read, many times the limit: multi-byte characters, characters JSON escapes
+/// and control characters escaped as `\u001b`
+fn banner() -> String {
+    "Grüße \"aus\" 漢字\t\u{1b}[1mbold\u{1b}[0m\r\n".repeat(40)
+}
+
+/// Mock PTY printing the banner before anything else
+struct BannerPty {
+    inner: MockPty,
+    banner: Option<Vec<u8>>,
+}
+
+impl AsyncRead for BannerPty {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        if let Some(banner) = self.banner.take() {
+            buf.put_slice(&banner);
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for BannerPty {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl AsyncPty for BannerPty {
+    async fn resize(&mut self, cols: u16, rows: u16) -> Result<(), PtyError> {
+        self.inner.resize(cols, rows).await
+    }
+
+    fn pid(&self) -> Option<u32> {
+        self.inner.pid()
+    }
+
+    fn is_alive(&self) -> bool {
+        self.inner.is_alive()
+    }
+
+    async fn try_wait(&mut self) -> Result<Option<PtyExitStatus>, PtyError> {
+        self.inner.try_wait().await
+    }
+
+    async fn kill(&mut self) -> Result<(), PtyError> {
+        self.inner.kill().await
+    }
+}
+
+struct BannerPtyFactory;
+
+#[async_trait]
+impl PtyFactory for BannerPtyFactory {
+    async fn create(&self, config: &PtyConfig) -> Result<Box<dyn AsyncPty>, PtyError> {
+        Ok(Box::new(BannerPty {
+            inner: MockPty::new(config),
+            banner: Some(banner().into_bytes()),
+        }))
+    }
+
+    fn name(&self) -> &'static str {
+        "banner"
+    }
+}
+
+/// Start a server and connect to it offering `subprotocol`
+async fn connect(subprotocol: Option<Subprotocol>) -> common::Socket {
+    let state = common::state(CONFIG).with_pty_factory(Arc::new(BannerPtyFactory));
+    let (address, _) = common::start_server(state).await;
+    common::connect(&format!("ws://{}/ws", address), subprotocol).await
+}
+
+#[tokio::test]
+async fn v1_connection_starts_with_hello() {
+    let mut socket = connect(Some(Subprotocol::V1)).await;
+
+    let hello: ServerEnvelope =
+        serde_json::from_str(&common::next_text(&mut socket).await).unwrap();
+    assert!(
+        matches!(
+            hello,
+            ServerEnvelope::Hello {
+                max_message_bytes: Some(limit)
+            } if limit == MAX_MESSAGE_BYTES as u64
+        ),
+        "{:?}",
+        hello
+    );
+}
+
+#[tokio::test]
+async fn large_output_is_split_within_the_limit() {
+    let mut socket = connect(Some(Subprotocol::V1)).await;
+    common::next_text(&mut socket).await;
+
+    let expected = banner();
+    let mut output = String::new();
+    let mut seqs = Vec::new();
+    while output.len() < expected.len() {
+        let text = common::next_text(&mut socket).await;
+        assert!(text.len() <= MAX_MESSAGE_BYTES, "{} bytes", text.len());
+        if let ServerEnvelope::Output { seq, data } = serde_json::from_str(&text).unwrap() {
+            seqs.push(seq);
+            output.push_str(&data);
+        }
+    }
+
+    // Nothing replaced or lost at the splits
+    assert_eq!(output, expected);
+    assert!(seqs.len() > 1, "{:?}", seqs);
+    assert!(seqs.iter().all(|seq| *seq == seqs[0]), "{:?}", seqs);
+}
+
+#[tokio::test]
+async fn raw_connection_gets_no_hello() {
+    let mut socket = connect(None).await;
+
+    // The output right away, as it is
+    let expected = banner();
+    let mut output = String::new();
+    while output.len() < expected.len() {
+        output.push_str(&common::next_text(&mut socket).await);
+    }
+    assert_eq!(output, expected);
+}
@@ -0,0 +1,94 @@
+//! Ports the servers listen on, derived from `port`, `http_port`, `webtransport_port` and
+//! `single_port`, and the config key bind errors point at
+//! Precedence: `port` wins over everything, then `single_port` puts WebTransport on `http_port`,
+//! otherwise the two ports are separate
+
+use rs_terminal::config::{ConfigLoader, TerminalConfig};
+
+fn config(ports: &str) -> TerminalConfig {
+    let config = format!(
+        r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+{}
+
+[default_shell_config]
+size = {{ columns = 80, rows = 24 }}
+working_directory = "."
+
+[shells.sh]
+command = ["sh"]
+"#,
+        ports
+    );
+    ConfigLoader::new().parse_config(&config).unwrap()
+}
+
+/// HTTP port, WebTransport port and the keys naming them
+fn derived(ports: &str) -> (u16, u16, &'static str, &'static str) {
+    let config = config(ports);
+    (
+        config.effective_http_port(),
+        config.effective_webtransport_port(),
+        config.http_port_key(),
+        config.webtransport_port_key(),
+    )
+}
+
+#[test]
+fn separate_ports_by_default() {
+    assert_eq!(derived(""), (8080, 8082, "http_port", "webtransport_port"));
+    assert_eq!(
+        derived("http_port = 9000\nwebtransport_port = 9001"),
+        (9000, 9001, "http_port", "webtransport_port")
+    );
+}
+
+#[test]
+fn single_port_serves_webtransport_on_the_http_port() {
+    assert_eq!(
+        derived("http_port = 9000\nwebtransport_port = 9001\nsingle_port = true"),
+        (9000, 9000, "http_port", "http_port")
+    );
+}
+
+#[test]
+fn port_takes_precedence() {
+    let expected = (7000, 7000, "port", "port");
+    assert_eq!(derived("port = 7000"), expected);
+    assert_eq!(
+        derived("port = 7000\nhttp_port = 9000\nwebtransport_port = 9001"),
+        expected
+    );
+    assert_eq!(
+        derived("port = 7000\nhttp_port = 9000\nsingle_port = true"),
+        expected
+    );
+}
+
+#[test]
+fn single_port_doesnt_matter_once_port_is_set() {
+    let expected = (7000, 7000, "port", "port");
+    // An explicit false doesn't split the shared port again
+    assert_eq!(
+        derived("port = 7000\nhttp_port = 9000\nwebtransport_port = 9001\nsingle_port = false"),
+        expected
+    );
+    assert_eq!(
+        derived("port = 7000\nhttp_port = 9000\nwebtransport_port = 9001\nsingle_port = true"),
+        expected
+    );
+}
+
+#[test]
+fn single_port_ignores_webtransport_port_only() {
+    assert_eq!(
+        derived("single_port = true"),
+        (8080, 8080, "http_port", "http_port")
+    );
+    assert_eq!(
+        derived("webtransport_port = 9001\nsingle_port = false"),
+        (8080, 9001, "http_port", "webtransport_port")
+    );
+}
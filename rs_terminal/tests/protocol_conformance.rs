@@ -0,0 +1,245 @@
+//! Protocol conformance: the server decodes every case of the shared fixtures in
+//! `terminal-types/conformance` and encodes it again byte for byte
+//!
+//! The Rust client runs the same fixtures (`clients/terminal-client/tests`), so a change to the
+//! protocol has to update the fixtures, which both sides are then checked against.
+
+mod common;
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use rs_terminal::protocol::{
+    ChannelClient, ClientEnvelope, ErrorCode, NoticeLevel, ServerEnvelope, channel_connection,
+};
+use rs_terminal::service::handle_terminal_session;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/terminal-types/conformance");
+
+/// A message and its encodings
+#[derive(Debug, Deserialize)]
+struct Case {
+    name: String,
+    /// Text frame of the `waylon-terminal-v1` subprotocol
+    json: String,
+    /// Hex of the frame on a `waylon-terminal-raw` connection, for the messages it carries
+    #[serde(default)]
+    raw: Option<String>,
+}
+
+/// A frame that must not decode
+#[derive(Debug, Deserialize)]
+struct InvalidCase {
+    name: String,
+    /// `client` or `server`, who sends the frame
+    direction: String,
+    json: String,
+}
+
+/// Output split to fit a connection's message limit
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChunkedCase {
+    name: String,
+    max_message_bytes: usize,
+    /// Input echoed by the mock shell as one piece of output
+    input: String,
+    /// Text frames the echo is sent in
+    frames: Vec<String>,
+}
+
+fn load<T: DeserializeOwned>(file: &str) -> Vec<T> {
+    let path = format!("{}/{}", FIXTURES, file);
+    let text = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    serde_json::from_str(&text).unwrap_or_else(|e| panic!("{}: {}", path, e))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// `type` field of a case
+fn type_tag(case: &Case) -> String {
+    let value: Value = serde_json::from_str(&case.json).unwrap();
+    value["type"].as_str().unwrap().to_string()
+}
+
+#[test]
+fn client_messages_round_trip() {
+    for case in load::<Case>("client.json") {
+        let envelope: ClientEnvelope = serde_json::from_str(&case.json)
+            .unwrap_or_else(|e| panic!("{}: doesn't decode: {}", case.name, e));
+        assert_eq!(
+            serde_json::to_string(&envelope).unwrap(),
+            case.json,
+            "{}",
+            case.name
+        );
+        assert_eq!(envelope.type_name(), type_tag(&case), "{}", case.name);
+
+        // Raw connections carry input as a binary frame of its bytes
+        let Some(raw) = case.raw else { continue };
+        let ClientEnvelope::Input { data, id: None } = &envelope else {
+            panic!("{}: raw frames only carry input without an id", case.name);
+        };
+        assert_eq!(to_hex(data.as_bytes()), raw, "{}", case.name);
+        let decoded = ClientEnvelope::Input {
+            data: String::from_utf8(from_hex(&raw)).unwrap(),
+            id: None,
+        };
+        assert_eq!(decoded, envelope, "{}", case.name);
+    }
+}
+
+#[test]
+fn server_messages_round_trip() {
+    for case in load::<Case>("server.json") {
+        let envelope: ServerEnvelope = serde_json::from_str(&case.json)
+            .unwrap_or_else(|e| panic!("{}: doesn't decode: {}", case.name, e));
+        assert_eq!(
+            serde_json::to_string(&envelope).unwrap(),
+            case.json,
+            "{}",
+            case.name
+        );
+        assert_eq!(envelope.type_name(), type_tag(&case), "{}", case.name);
+
+        // Raw connections carry output as a text frame, without its sequence number
+        let Some(raw) = case.raw else { continue };
+        let ServerEnvelope::Output { seq, data } = &envelope else {
+            panic!("{}: raw frames only carry output", case.name);
+        };
+        assert_eq!(to_hex(data.as_bytes()), raw, "{}", case.name);
+        let decoded = ServerEnvelope::Output {
+            seq: *seq,
+            data: String::from_utf8(from_hex(&raw)).unwrap(),
+        };
+        assert_eq!(decoded, envelope, "{}", case.name);
+    }
+}
+
+#[test]
+fn invalid_messages_are_rejected() {
+    for case in load::<InvalidCase>("invalid.json") {
+        let decoded = match case.direction.as_str() {
+            "client" => {
+                serde_json::from_str::<ClientEnvelope>(&case.json).map(|e| format!("{:?}", e))
+            }
+            "server" => {
+                serde_json::from_str::<ServerEnvelope>(&case.json).map(|e| format!("{:?}", e))
+            }
+            other => panic!("{}: unknown direction {}", case.name, other),
+        };
+        assert!(decoded.is_err(), "{}: decoded as {:?}", case.name, decoded);
+    }
+}
+
+#[test]
+fn fixtures_cover_every_message() {
+    let client: HashSet<String> = load::<Case>("client.json").iter().map(type_tag).collect();
+    for tag in ClientEnvelope::TYPES {
+        assert!(client.contains(*tag), "client.json has no {} message", tag);
+    }
+    let server: Vec<ServerEnvelope> = load::<Case>("server.json")
+        .iter()
+        .map(|case| serde_json::from_str(&case.json).unwrap())
+        .collect();
+    let tags: HashSet<&str> = server.iter().map(ServerEnvelope::type_name).collect();
+    for tag in ServerEnvelope::TYPES {
+        assert!(tags.contains(tag), "server.json has no {} message", tag);
+    }
+
+    for code in ErrorCode::ALL {
+        let covered = server.iter().any(
+            |envelope| matches!(envelope, ServerEnvelope::Error { code: c, .. } if *c == code),
+        );
+        assert!(covered, "server.json has no error with code {:?}", code);
+    }
+    for level in NoticeLevel::ALL {
+        let covered = server.iter().any(
+            |envelope| matches!(envelope, ServerEnvelope::Notice { level: l, .. } if *l == level),
+        );
+        assert!(covered, "server.json has no notice of level {:?}", level);
+    }
+}
+
+/// Mock shell sessions, whose echo of the input is a single piece of output
+const CONFIG: &str = r#"
+pty_implementation = "mock"
+default_shell_type = "bash"
+session_timeout = 1800000
+nudge_on_connect = false
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+
+[shells.bash]
+command = ["bash"]
+"#;
+
+/// Longest wait for a message of the session
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn receive(client: &mut ChannelClient) -> ServerEnvelope {
+    tokio::time::timeout(TIMEOUT, client.receive())
+        .await
+        .expect("no message from the session")
+        .expect("session closed")
+        .expect("malformed message")
+}
+
+#[tokio::test]
+async fn output_is_split_at_the_message_limit() {
+    let state = common::state(CONFIG);
+    for case in load::<ChunkedCase>("chunked.json") {
+        let (connection, mut client) = channel_connection(case.name.as_str());
+        let connection = connection.with_max_message_bytes(case.max_message_bytes);
+        tokio::spawn(handle_terminal_session(connection, state.clone()));
+        let hello = receive(&mut client).await;
+        assert_eq!(
+            hello,
+            ServerEnvelope::Hello {
+                max_message_bytes: Some(case.max_message_bytes as u64)
+            }
+        );
+        // The mock shell's banner ends with its prompt
+        let mut banner = String::new();
+        while !banner.ends_with("mock$ ") {
+            if let ServerEnvelope::Output { data, .. } = receive(&mut client).await {
+                banner.push_str(&data);
+            }
+        }
+
+        client.input(&case.input).await.unwrap();
+        let mut frames = Vec::new();
+        let mut echo = String::new();
+        while echo.len() < case.input.len() {
+            let envelope = receive(&mut client).await;
+            if let ServerEnvelope::Output { data, .. } = &envelope {
+                echo.push_str(data);
+                frames.push(serde_json::to_string(&envelope).unwrap());
+            }
+        }
+        assert_eq!(echo, case.input, "{}", case.name);
+        for frame in &frames {
+            assert!(
+                frame.len() <= case.max_message_bytes,
+                "{}: {} bytes in {}",
+                case.name,
+                frame.len(),
+                frame
+            );
+        }
+        assert_eq!(frames, case.frames, "{}", case.name);
+    }
+}
@@ -0,0 +1,105 @@
+//! Dropping or killing a PTY always ends and reaps its process, also when it ignores SIGHUP or
+//! closed the terminal while still running
+#![cfg(target_os = "linux")]
+
+use std::time::{Duration, Instant};
+
+use futures_util::future::join_all;
+use rs_terminal::pty::{AsyncPty, PortablePtyFactory, PtyConfig, PtyFactory};
+
+/// PTYs created and destroyed at once
+const PTYS: usize = 100;
+
+/// Longest wait for the processes to be gone
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+fn config(script: &str) -> PtyConfig {
+    PtyConfig {
+        command: "sh".to_string(),
+        args: vec!["-c".to_string(), script.to_string()],
+        cols: 80,
+        rows: 24,
+        env: Vec::new(),
+        cwd: None,
+    }
+}
+
+/// Processes whose parent is the test process, zombies included
+fn children() -> usize {
+    let pid = std::process::id().to_string();
+    std::fs::read_dir("/proc")
+        .unwrap()
+        .filter_map(|entry| std::fs::read_to_string(entry.ok()?.path().join("stat")).ok())
+        .filter(|stat| {
+            // pid (comm) state ppid ..., the command may contain spaces and parentheses
+            let fields = &stat[stat.rfind(')').unwrap_or(0)..];
+            fields.split_whitespace().nth(2) == Some(pid.as_str())
+        })
+        .count()
+}
+
+async fn wait_for_children(expected: usize) {
+    let started = Instant::now();
+    while children() != expected {
+        assert!(
+            started.elapsed() < TIMEOUT,
+            "{} child processes left, expected {}",
+            children(),
+            expected
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn no_children_are_leaked() {
+    let before = children();
+    let factory = PortablePtyFactory::new(16, 0);
+    let scripts = [
+        // Ends on SIGHUP
+        "exec sleep 600",
+        // Ignores SIGHUP, only the kill ends it
+        "trap '' HUP; exec sleep 600",
+        // Closes the terminal, so its output ends while it keeps running
+        "exec sleep 600 </dev/null >/dev/null 2>&1",
+    ];
+    let configs: Vec<PtyConfig> = (0..PTYS)
+        .map(|i| config(scripts[i % scripts.len()]))
+        .collect();
+
+    let ptys: Vec<Box<dyn AsyncPty>> =
+        join_all(configs.iter().map(|config| factory.create(config)))
+            .await
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .unwrap();
+    wait_for_children(before + PTYS).await;
+
+    // Let the terminals that were closed report the end of their output
+    let started = Instant::now();
+    while ptys
+        .iter()
+        .skip(2)
+        .step_by(scripts.len())
+        .any(|pty| pty.is_alive())
+    {
+        assert!(started.elapsed() < TIMEOUT, "output didn't end");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    // Kill half of them explicitly, drop the others
+    let (killed, dropped): (Vec<_>, Vec<_>) =
+        ptys.into_iter().enumerate().partition(|(i, _)| i % 2 == 0);
+    drop(dropped);
+    let results = join_all(killed.into_iter().map(|(_, mut pty)| async move {
+        let result = pty.kill().await;
+        drop(pty);
+        result
+    }))
+    .await;
+    for result in results {
+        result.unwrap();
+    }
+
+    wait_for_children(before).await;
+}
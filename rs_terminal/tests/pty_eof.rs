@@ -0,0 +1,66 @@
+//! Once the process of a PTY has exited and its output was read, every further read reports EOF
+//! right away instead of waiting or returning data again
+#![cfg(unix)]
+
+use std::time::Duration;
+
+use rs_terminal::pty::{PortablePtyFactory, PtyConfig, PtyFactory};
+use tokio::io::AsyncReadExt;
+
+/// Longest wait for a read
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[tokio::test]
+async fn reads_report_eof_after_the_child_exited() {
+    let factory = PortablePtyFactory::new(1, 0);
+    let mut pty = factory
+        .create(&PtyConfig {
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), "printf done".to_string()],
+            cols: 80,
+            rows: 24,
+            env: Vec::new(),
+            cwd: None,
+        })
+        .await
+        .unwrap();
+
+    // The output still buffered is read before the end
+    let mut output = Vec::new();
+    let mut buffer = [0u8; 256];
+    loop {
+        let n = tokio::time::timeout(TIMEOUT, pty.read(&mut buffer))
+            .await
+            .expect("the output didn't end")
+            .unwrap();
+        if n == 0 {
+            break;
+        }
+        output.extend_from_slice(&buffer[..n]);
+    }
+    assert!(
+        String::from_utf8_lossy(&output).contains("done"),
+        "{:?}",
+        output
+    );
+
+    for _ in 0..100 {
+        let n = tokio::time::timeout(Duration::from_millis(100), pty.read(&mut buffer))
+            .await
+            .expect("read after EOF didn't return at once")
+            .unwrap();
+        assert_eq!(n, 0);
+    }
+
+    let status = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            if let Some(status) = pty.try_wait().await.unwrap() {
+                return status;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("no exit status");
+    assert_eq!(status.code, Some(0));
+}
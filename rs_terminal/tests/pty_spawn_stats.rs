@@ -0,0 +1,142 @@
+//! Every PTY spawn is counted per factory and shell type: `GET /api/admin/pty-stats` reports the
+//! attempts, failures and spawn time percentiles, `/metrics` the histogram and failure counter
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::Router;
+use axum::body::to_bytes;
+use axum::http::StatusCode;
+use futures_util::StreamExt;
+use rs_terminal::api::dto::PtyStatsResponse;
+use rs_terminal::pty::{AsyncPty, MockPty, PtyConfig, PtyError, PtyFactory};
+use serde_json::{Value, json};
+use tokio_tungstenite::tungstenite::Message;
+
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+nudge_on_connect = false
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+
+[shells.sh]
+command = ["sh"]
+
+[shells.broken]
+command = ["broken"]
+"#;
+
+/// Longest wait for a session to start or fail
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Mock PTY factory failing to spawn the `broken` command
+struct FlakyPtyFactory;
+
+#[async_trait]
+impl PtyFactory for FlakyPtyFactory {
+    async fn create(&self, config: &PtyConfig) -> Result<Box<dyn AsyncPty>, PtyError> {
+        if config.command == "broken" {
+            return Err(PtyError::NotAvailable);
+        }
+        Ok(Box::new(MockPty::new(config)))
+    }
+
+    fn name(&self) -> &'static str {
+        "flaky"
+    }
+}
+
+async fn call(
+    router: &Router,
+    method: &str,
+    uri: &str,
+    body: Option<Value>,
+) -> (StatusCode, String) {
+    let response = common::request(router, method, uri, &[], body).await;
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8(bytes.to_vec()).unwrap())
+}
+
+/// Create a session of `shell_type` through the REST API and attach to it until its shell
+/// prompts or the session ends
+async fn run_session(router: &Router, address: &str, shell_type: &str) {
+    let (status, body) = call(
+        router,
+        "POST",
+        "/api/sessions",
+        Some(json!({ "shellType": shell_type })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", body);
+    let session: Value = serde_json::from_str(&body).unwrap();
+    let url = format!("ws://{}/ws/{}", address, session["id"].as_str().unwrap());
+    let mut socket = common::connect(&url, None).await;
+    tokio::time::timeout(TIMEOUT, async {
+        while let Some(Ok(message)) = socket.next().await {
+            match message {
+                Message::Text(text) if text.ends_with("mock$ ") => return,
+                Message::Close(_) => return,
+                _ => {}
+            }
+        }
+    })
+    .await
+    .expect("the session neither started nor ended");
+}
+
+#[tokio::test]
+async fn spawns_are_counted_per_factory_and_shell_type() {
+    let state = common::state(CONFIG).with_pty_factory(Arc::new(FlakyPtyFactory));
+    let (address, router) = common::start_server(state).await;
+
+    run_session(&router, &address, "sh").await;
+    run_session(&router, &address, "sh").await;
+    run_session(&router, &address, "broken").await;
+
+    let (status, body) = call(&router, "GET", "/api/admin/pty-stats", None).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let stats: PtyStatsResponse = serde_json::from_str(&body).unwrap();
+    let summary: Vec<_> = stats
+        .spawns
+        .iter()
+        .map(|spawns| {
+            (
+                spawns.factory.as_str(),
+                spawns.shell_type.as_str(),
+                spawns.count,
+                spawns.failures,
+                spawns.error_rate,
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [("flaky", "broken", 1, 1, 1.0), ("flaky", "sh", 2, 0, 0.0)]
+    );
+    let sh = &stats.spawns[1];
+    assert!(sh.p50_ms.is_some() && sh.p99_ms >= sh.p50_ms, "{:?}", sh);
+
+    let (status, metrics) = call(&router, "GET", "/metrics", None).await;
+    assert_eq!(status, StatusCode::OK);
+    for line in [
+        "terminal_pty_spawn_seconds_count{factory=\"flaky\",shell_type=\"sh\"} 2",
+        "terminal_pty_spawn_seconds_count{factory=\"flaky\",shell_type=\"broken\"} 1",
+        "terminal_pty_spawn_failures_total{factory=\"flaky\",shell_type=\"sh\"} 0",
+        "terminal_pty_spawn_failures_total{factory=\"flaky\",shell_type=\"broken\"} 1",
+    ] {
+        assert!(
+            metrics.lines().any(|l| l == line),
+            "no {} in\n{}",
+            line,
+            metrics
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! Read-only sessions (`"readOnly": true` when created) drop all input before it reaches the PTY,
+//! while the terminal's output keeps flowing to the client
+
+mod common;
+
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use rs_terminal::protocol::ChannelClient;
+use rs_terminal::pty::{AsyncPty, MockPty, PtyConfig, PtyError, PtyExitStatus, PtyFactory};
+use rs_terminal::server::build_router;
+use serde_json::json;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Mock PTY keeping everything written to it
+struct InputRecorder {
+    inner: MockPty,
+    written: Arc<Mutex<Vec<u8>>>,
+}
+
+impl AsyncRead for InputRecorder {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for InputRecorder {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = &mut *self;
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            this.written.lock().unwrap().extend_from_slice(&buf[..n]);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl AsyncPty for InputRecorder {
+    async fn resize(&mut self, cols: u16, rows: u16) -> Result<(), PtyError> {
+        self.inner.resize(cols, rows).await
+    }
+
+    fn pid(&self) -> Option<u32> {
+        self.inner.pid()
+    }
+
+    fn is_alive(&self) -> bool {
+        self.inner.is_alive()
+    }
+
+    async fn try_wait(&mut self) -> Result<Option<PtyExitStatus>, PtyError> {
+        self.inner.try_wait().await
+    }
+
+    async fn kill(&mut self) -> Result<(), PtyError> {
+        self.inner.kill().await
+    }
+}
+
+struct InputRecorderFactory {
+    written: Arc<Mutex<Vec<u8>>>,
+}
+
+#[async_trait]
+impl PtyFactory for InputRecorderFactory {
+    async fn create(&self, config: &PtyConfig) -> Result<Box<dyn AsyncPty>, PtyError> {
+        Ok(Box::new(InputRecorder {
+            inner: MockPty::new(config),
+            written: self.written.clone(),
+        }))
+    }
+
+    fn name(&self) -> &'static str {
+        "input-recorder"
+    }
+}
+
+/// Create a session through the REST API and attach to it, returning the client and the input
+/// that reached the PTY
+async fn attach(read_only: bool) -> (ChannelClient, Arc<Mutex<Vec<u8>>>) {
+    let written = Arc::new(Mutex::new(Vec::new()));
+    let state =
+        common::state(&common::config("")).with_pty_factory(Arc::new(InputRecorderFactory {
+            written: written.clone(),
+        }));
+
+    let body = json!({ "readOnly": read_only });
+    let router = build_router(state.clone());
+    let (status, session) = common::call(&router, "POST", "/api/sessions", Some(body)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(session["readOnly"], read_only);
+
+    let mut client = common::attach(&state, session["id"].as_str().unwrap());
+    common::output_until(&mut client, "mock$ ").await;
+    (client, written)
+}
+
+#[tokio::test]
+async fn input_to_a_read_only_session_does_not_reach_the_pty() {
+    let (mut client, written) = attach(true).await;
+
+    client.input("rm -rf ~\r").await.unwrap();
+    // Output still flows: the resize is answered by the terminal
+    client.resize(100, 30).await.unwrap();
+    common::output_until(&mut client, "[resized to 100x30]\r\nmock$ ").await;
+
+    assert!(written.lock().unwrap().is_empty(), "{:?}", written);
+}
+
+#[tokio::test]
+async fn input_to_a_writable_session_reaches_the_pty() {
+    let (mut client, written) = attach(false).await;
+
+    client.input("ls\r").await.unwrap();
+    common::output_until(&mut client, "ls\r\nmock$ ").await;
+
+    assert_eq!(written.lock().unwrap().as_slice(), b"ls\r");
+}
@@ -0,0 +1,84 @@
+//! `scrollback_memory_budget_bytes` bounds the scrollback of all sessions together, evicting the
+//! oldest output across sessions first
+
+mod common;
+
+use std::sync::Arc;
+
+use rs_terminal::app_state::ScrollbackStore;
+use rs_terminal::pty::MockPtyFactory;
+
+/// Per session 4 KiB, 6 KiB across all sessions
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+nudge_on_connect = false
+scrollback_limit_bytes = 4096
+scrollback_memory_budget_bytes = 6144
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+
+[shells.sh]
+command = ["sh"]
+"#;
+
+const SESSIONS: usize = 10;
+
+/// Retained output of a session as text
+async fn retained(store: &ScrollbackStore, session_id: &str) -> String {
+    store
+        .replay_from(session_id, 0)
+        .await
+        .into_iter()
+        .map(|(_, data)| String::from_utf8(data).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn many_sessions_stay_within_the_budget() {
+    let state = common::state(CONFIG).with_pty_factory(Arc::new(MockPtyFactory));
+
+    // Every session echoes 2 KiB, five times the budget in total
+    let mut clients = Vec::new();
+    for index in 0..SESSIONS {
+        let session_id = format!("session-{}", index);
+        let mut client = common::attach(&state, session_id.as_str());
+        common::output_until(&mut client, "mock$ ").await;
+        let line = format!("{}{}", index, "x".repeat(2047));
+        client.input(&line).await.unwrap();
+        common::output_until(&mut client, &line).await;
+        assert!(state.scrollback.total_bytes().await <= 6144);
+        clients.push((session_id, client));
+    }
+
+    let mut total = 0;
+    for (session_id, _) in &clients {
+        total += retained(&state.scrollback, session_id).await.len();
+    }
+    assert_eq!(total, state.scrollback.total_bytes().await);
+    assert!(total > 4096 && total <= 6144, "{} bytes kept", total);
+
+    // The output of the first sessions was evicted, the last session keeps its own
+    assert!(retained(&state.scrollback, "session-0").await.is_empty());
+    let last = retained(&state.scrollback, &clients[SESSIONS - 1].0).await;
+    assert!(last.ends_with(&format!("{}{}", SESSIONS - 1, "x".repeat(2047))));
+}
+
+#[tokio::test]
+async fn oldest_output_is_evicted_first() {
+    let store = ScrollbackStore::new(1000, 1000);
+    store.append("a", &[b'a'; 400]).await;
+    store.append("b", &[b'b'; 400]).await;
+    store.append("a", &[b'c'; 400]).await;
+
+    // The first chunk of "a" is the oldest output, the newer one of "a" stays
+    assert_eq!(store.total_bytes().await, 800);
+    assert_eq!(retained(&store, "a").await, "c".repeat(400));
+    assert_eq!(retained(&store, "b").await, "b".repeat(400));
+
+    store.remove("a").await;
+    assert_eq!(store.total_bytes().await, 400);
+}
@@ -0,0 +1,136 @@
+//! Clearing a session's scrollback, with a `clear_scrollback` envelope or
+//! `DELETE /api/sessions/:session_id/scrollback`: a reattaching client only gets the output
+//! produced afterwards replayed, numbered where the sequence left off; read-only sessions ignore
+//! the envelope
+
+mod common;
+
+use std::sync::Arc;
+
+use axum::Router;
+use axum::http::StatusCode;
+use rs_terminal::protocol::ClientEnvelope;
+use rs_terminal::pty::MockPtyFactory;
+use serde_json::json;
+
+/// Start a server on mock PTYs, returning its address and a router on the same state
+async fn start_server() -> (String, Router) {
+    let state = common::state(&common::config("")).with_pty_factory(Arc::new(MockPtyFactory));
+    common::start_server(state).await
+}
+
+/// Attach to `path` with `waylon-terminal-v1`, skipping the hello
+async fn attach(address: &str, path: &str) -> common::Socket {
+    common::connect_v1(&format!("ws://{}{}", address, path)).await
+}
+
+/// Leave the shell of `session_id` running and attach again, asking for all retained output
+async fn reattach(
+    address: &str,
+    router: &Router,
+    session_id: &str,
+    socket: common::Socket,
+) -> common::Socket {
+    let uri = format!("/api/sessions/{}/disconnect", session_id);
+    let (status, _) = common::call(router, "POST", &uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+    drop(socket);
+    attach(address, &format!("/ws/{}?resume_from=0", session_id)).await
+}
+
+fn text(chunks: &[(u64, String)]) -> String {
+    chunks.iter().map(|(_, data)| data.as_str()).collect()
+}
+
+#[tokio::test]
+async fn cleared_output_is_not_replayed() {
+    let (address, router) = start_server().await;
+
+    let mut socket = attach(&address, "/ws/cleared").await;
+    common::numbered_output_until(&mut socket, "mock$ ").await;
+    common::input(&mut socket, "before\r", None).await;
+    let before = common::numbered_output_until(&mut socket, "before\r\nmock$ ").await;
+
+    common::send(&mut socket, &ClientEnvelope::ClearScrollback).await;
+    common::input(&mut socket, "after\r", None).await;
+    let after = common::numbered_output_until(&mut socket, "after\r\nmock$ ").await;
+
+    let mut socket = reattach(&address, &router, "cleared", socket).await;
+    let replayed = common::numbered_output_until(&mut socket, "after\r\nmock$ ").await;
+    assert_eq!(replayed, after);
+    // Numbers continue where they were instead of starting over
+    assert!(replayed[0].0 > before.last().unwrap().0, "{:?}", replayed);
+}
+
+#[tokio::test]
+async fn rest_api_clears_the_scrollback() {
+    let (address, router) = start_server().await;
+
+    let mut socket = attach(&address, "/ws/cleared-by-api").await;
+    common::numbered_output_until(&mut socket, "mock$ ").await;
+    common::input(&mut socket, "before\r", None).await;
+    common::numbered_output_until(&mut socket, "before\r\nmock$ ").await;
+
+    let uri = "/api/sessions/cleared-by-api/scrollback";
+    let (status, body) = common::call(&router, "DELETE", uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["success"], true);
+    let message = body["message"].as_str().unwrap();
+    assert!(
+        message.starts_with("Cleared ") && !message.starts_with("Cleared 0 "),
+        "{}",
+        message
+    );
+    // Nothing is left to clear
+    let (_, body) = common::call(&router, "DELETE", uri, None).await;
+    assert_eq!(body["message"], "Cleared 0 bytes of scrollback");
+
+    // Nothing is replayed, the first output after reattaching is live
+    let mut socket = reattach(&address, &router, "cleared-by-api", socket).await;
+    common::input(&mut socket, "after\r", None).await;
+    let live = common::numbered_output_until(&mut socket, "after\r\nmock$ ").await;
+    assert_eq!(text(&live), "after\r\nmock$ ");
+}
+
+#[tokio::test]
+async fn clearing_an_unknown_session_is_not_found() {
+    let (_, router) = start_server().await;
+
+    let (status, body) =
+        common::call(&router, "DELETE", "/api/sessions/missing/scrollback", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["message"], "Session not found: missing");
+}
+
+#[tokio::test]
+async fn read_only_session_keeps_its_scrollback() {
+    let (address, router) = start_server().await;
+    let (status, session) = common::call(
+        &router,
+        "POST",
+        "/api/sessions",
+        Some(json!({ "readOnly": true })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let session_id = session["id"].as_str().unwrap();
+
+    let mut socket = attach(&address, &format!("/ws/{}", session_id)).await;
+    let banner = common::numbered_output_until(&mut socket, "mock$ ").await;
+    common::send(&mut socket, &ClientEnvelope::ClearScrollback).await;
+    // Answered by the terminal once the envelope before it was handled
+    common::send(
+        &mut socket,
+        &ClientEnvelope::Resize {
+            columns: 100,
+            rows: 30,
+        },
+    )
+    .await;
+    let resized = common::numbered_output_until(&mut socket, "[resized to 100x30]\r\nmock$ ").await;
+
+    let mut socket = reattach(&address, &router, session_id, socket).await;
+    let expected = format!("{}{}", text(&banner), text(&resized));
+    let replayed = common::numbered_output_until(&mut socket, &expected).await;
+    assert_eq!(text(&replayed), expected);
+}
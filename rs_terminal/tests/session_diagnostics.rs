@@ -0,0 +1,181 @@
+//! `GET /api/sessions/:id/diagnostics`: the subprotocol a session's client negotiated, its traffic
+//! counters, the warnings logged within the session (at most `diagnostics_log_events`, the most
+//! recent ones) and why its connection ended
+
+mod common;
+
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use axum::Router;
+use axum::http::StatusCode;
+use rs_terminal::app_state::{AppState, DiagnosticsLayer, DiagnosticsStore};
+use rs_terminal::config::ConfigLoader;
+use rs_terminal::protocol::{ClientEnvelope, ServerEnvelope, Subprotocol};
+use rs_terminal::pty::MockPtyFactory;
+use serde_json::Value;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+nudge_on_connect = false
+diagnostics_log_events = {events}
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+
+[shells.sh]
+command = ["sh"]
+"#;
+
+/// Longest wait for a frame or a state change of the server
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Store the log events of all tests go to, as `main` sets it up (the subscriber is global)
+fn diagnostics() -> Arc<DiagnosticsStore> {
+    static DIAGNOSTICS: OnceLock<Arc<DiagnosticsStore>> = OnceLock::new();
+    DIAGNOSTICS
+        .get_or_init(|| {
+            let diagnostics = Arc::new(DiagnosticsStore::new());
+            tracing_subscriber::registry()
+                .with(DiagnosticsLayer::new(diagnostics.clone()))
+                .init();
+            diagnostics
+        })
+        .clone()
+}
+
+struct Server {
+    address: String,
+    router: Router,
+}
+
+impl Server {
+    /// Start a server keeping `events` log events per session
+    async fn start(events: usize) -> Self {
+        let config = ConfigLoader::new()
+            .parse_config(&CONFIG.replace("{events}", &events.to_string()))
+            .unwrap();
+        let state = AppState::new(config, diagnostics()).with_pty_factory(Arc::new(MockPtyFactory));
+        let (address, router) = common::start_server(state).await;
+        Self { address, router }
+    }
+
+    /// Attach to `/ws/:session_id` with `waylon-terminal-v1` and wait for the prompt
+    async fn attach(&self, session_id: &str) -> common::Socket {
+        let url = format!("ws://{}/ws/{}", self.address, session_id);
+        let mut socket = common::connect(&url, Some(Subprotocol::V1)).await;
+        common::envelope_output_until(&mut socket, "mock$ ").await;
+        socket
+    }
+
+    async fn request(&self, method: &str, uri: &str) -> (StatusCode, Value) {
+        common::call(&self.router, method, uri, None).await
+    }
+
+    /// Diagnostics of a session once its connection has ended
+    async fn diagnostics_once_closed(&self, session_id: &str) -> Value {
+        let uri = format!("/api/sessions/{}/diagnostics", session_id);
+        tokio::time::timeout(TIMEOUT, async {
+            loop {
+                let (status, body) = self.request("GET", &uri).await;
+                assert_eq!(status, StatusCode::OK, "{}", body);
+                if body.get("closeReason").is_some() {
+                    return body;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("the connection didn't end")
+    }
+}
+
+/// Send a resize the server refuses with a warning, and wait for the refusal
+async fn invalid_resize(socket: &mut common::Socket, columns: u16) {
+    common::send(socket, &ClientEnvelope::Resize { columns, rows: 0 }).await;
+    while !matches!(
+        common::next_envelope(socket).await,
+        ServerEnvelope::Error { .. }
+    ) {}
+}
+
+fn messages(diagnostics: &Value) -> Vec<&str> {
+    diagnostics["events"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|event| event["message"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn diagnostics_show_the_connection_its_warnings_and_why_it_ended() {
+    let server = Server::start(50).await;
+    let mut socket = server.attach("diagnosed").await;
+
+    common::input(&mut socket, "ls\r", None).await;
+    common::envelope_output_until(&mut socket, "ls\r\nmock$ ").await;
+    invalid_resize(&mut socket, 100).await;
+
+    let (status, _) = server
+        .request("POST", "/api/sessions/diagnosed/disconnect")
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let diagnostics = server.diagnostics_once_closed("diagnosed").await;
+
+    assert_eq!(diagnostics["session"]["id"], "diagnosed");
+    assert_eq!(diagnostics["subprotocol"], Subprotocol::V1.name());
+    assert_eq!(diagnostics["counters"]["inputMessages"], 2);
+    assert!(diagnostics["counters"]["outputChunks"].as_u64().unwrap() > 0);
+    // The prompt, the echo and the prompt after it
+    assert!(diagnostics["counters"]["outputBytes"].as_u64().unwrap() >= 18);
+    let event = &diagnostics["events"][0];
+    assert_eq!(event["level"], "warn");
+    assert_eq!(event["target"], "rs_terminal::service::message_handler");
+    assert_eq!(
+        messages(&diagnostics),
+        ["Invalid resize from session diagnosed: 100x0"]
+    );
+    assert_eq!(
+        diagnostics["closeReason"],
+        "disconnected: disconnected by API request"
+    );
+}
+
+#[tokio::test]
+async fn diagnostics_keep_the_most_recent_events() {
+    let server = Server::start(2).await;
+    let mut socket = server.attach("bounded").await;
+
+    for columns in [1, 2, 3] {
+        invalid_resize(&mut socket, columns).await;
+    }
+
+    let (status, diagnostics) = server
+        .request("GET", "/api/sessions/bounded/diagnostics")
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        messages(&diagnostics),
+        [
+            "Invalid resize from session bounded: 2x0",
+            "Invalid resize from session bounded: 3x0",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn diagnostics_of_an_unknown_session_are_not_found() {
+    let server = Server::start(50).await;
+
+    let (status, body) = server
+        .request("GET", "/api/sessions/missing/diagnostics")
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["message"], "Session not found: missing");
+}
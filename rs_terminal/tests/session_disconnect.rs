@@ -0,0 +1,250 @@
+//! `POST /api/sessions/:session_id/disconnect` closes the client's connection but keeps the shell
+//! running: the session is `disconnected` until a client attaches again and takes over the same
+//! shell, and terminating the session or shutting down kills it
+
+mod common;
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::Router;
+use axum::http::StatusCode;
+use rs_terminal::app_state::AppState;
+use rs_terminal::protocol::{ChannelClient, ErrorCode, ServerEnvelope};
+use rs_terminal::pty::{AsyncPty, MockPty, PtyConfig, PtyError, PtyExitStatus, PtyFactory};
+use rs_terminal::server::build_router;
+use rs_terminal::service::shutdown_all;
+use serde_json::{Value, json};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Longest wait for a message of the session
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often PTYs were created and killed
+#[derive(Default)]
+struct PtyCounts {
+    created: AtomicUsize,
+    killed: AtomicUsize,
+}
+
+/// Mock PTY counting how often it was killed
+struct CountingPty {
+    inner: MockPty,
+    counts: Arc<PtyCounts>,
+}
+
+impl AsyncRead for CountingPty {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for CountingPty {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl AsyncPty for CountingPty {
+    async fn resize(&mut self, cols: u16, rows: u16) -> Result<(), PtyError> {
+        self.inner.resize(cols, rows).await
+    }
+
+    fn pid(&self) -> Option<u32> {
+        self.inner.pid()
+    }
+
+    fn is_alive(&self) -> bool {
+        self.inner.is_alive()
+    }
+
+    async fn try_wait(&mut self) -> Result<Option<PtyExitStatus>, PtyError> {
+        self.inner.try_wait().await
+    }
+
+    async fn kill(&mut self) -> Result<(), PtyError> {
+        self.counts.killed.fetch_add(1, Ordering::SeqCst);
+        self.inner.kill().await
+    }
+}
+
+struct CountingPtyFactory {
+    counts: Arc<PtyCounts>,
+}
+
+#[async_trait]
+impl PtyFactory for CountingPtyFactory {
+    async fn create(&self, config: &PtyConfig) -> Result<Box<dyn AsyncPty>, PtyError> {
+        self.counts.created.fetch_add(1, Ordering::SeqCst);
+        Ok(Box::new(CountingPty {
+            inner: MockPty::new(config),
+            counts: self.counts.clone(),
+        }))
+    }
+
+    fn name(&self) -> &'static str {
+        "counting"
+    }
+}
+
+fn start_state() -> (AppState, Arc<PtyCounts>) {
+    let counts = Arc::new(PtyCounts::default());
+    let state = common::state(&common::config("")).with_pty_factory(Arc::new(CountingPtyFactory {
+        counts: counts.clone(),
+    }));
+    (state, counts)
+}
+
+/// Disconnect the session's client through the REST API, expecting it to be told why
+async fn disconnect(router: &Router, session_id: &str, client: &mut ChannelClient) {
+    let uri = format!("/api/sessions/{}/disconnect", session_id);
+    let (status, body) = common::call(router, "POST", &uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body["message"],
+        "Client disconnected, the shell keeps running"
+    );
+
+    let error = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match client.receive().await {
+                Some(Ok(ServerEnvelope::Error { code, message })) => return (code, message),
+                Some(Ok(_)) => continue,
+                other => panic!("no error before {:?}", other),
+            }
+        }
+    })
+    .await
+    .expect("the client wasn't told");
+    assert_eq!(
+        error,
+        (
+            ErrorCode::SessionDisconnected,
+            "Session disconnected: disconnected by API request".to_string()
+        )
+    );
+    let end = tokio::time::timeout(TIMEOUT, client.receive())
+        .await
+        .expect("the connection wasn't closed");
+    assert!(end.is_none(), "{:?}", end);
+}
+
+async fn status_of(router: &Router, session_id: &str) -> Value {
+    let (status, session) = common::call(
+        router,
+        "GET",
+        &format!("/api/sessions/{}", session_id),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    session["status"].clone()
+}
+
+#[tokio::test]
+async fn next_client_takes_over_the_shell() {
+    let (state, counts) = start_state();
+    let router = build_router(state.clone());
+    let session_id = common::create_session(&router, json!({})).await;
+    let mut client = common::attach(&state, &session_id);
+    common::output_until(&mut client, "mock$ ").await;
+
+    disconnect(&router, &session_id, &mut client).await;
+    assert_eq!(status_of(&router, &session_id).await, "disconnected");
+    assert_eq!(counts.killed.load(Ordering::SeqCst), 0);
+
+    let mut client = common::attach(&state, &session_id);
+    client.input("ls\r").await.unwrap();
+    common::output_until(&mut client, "ls\r\nmock$ ").await;
+    assert_ne!(status_of(&router, &session_id).await, "disconnected");
+    // Still the first shell
+    assert_eq!(counts.created.load(Ordering::SeqCst), 1);
+    assert_eq!(counts.killed.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn only_connected_sessions_can_be_disconnected() {
+    let (state, _) = start_state();
+    let router = build_router(state.clone());
+
+    let (status, body) =
+        common::call(&router, "POST", "/api/sessions/missing/disconnect", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["message"], "Session not found: missing");
+
+    // Created, but no client attached yet
+    let session_id = common::create_session(&router, json!({})).await;
+    let uri = format!("/api/sessions/{}/disconnect", session_id);
+    let (status, body) = common::call(&router, "POST", &uri, None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(
+        body["message"],
+        format!("Session has no connected client: {}", session_id)
+    );
+}
+
+#[tokio::test]
+async fn terminating_a_disconnected_session_kills_its_shell() {
+    let (state, counts) = start_state();
+    let router = build_router(state.clone());
+    let session_id = common::create_session(&router, json!({})).await;
+    let mut client = common::attach(&state, &session_id);
+    common::output_until(&mut client, "mock$ ").await;
+    disconnect(&router, &session_id, &mut client).await;
+
+    let (status, _) = common::call(
+        &router,
+        "DELETE",
+        &format!("/api/sessions/{}", session_id),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    assert_eq!(counts.killed.load(Ordering::SeqCst), 1);
+    let (status, _) = common::call(
+        &router,
+        "GET",
+        &format!("/api/sessions/{}", session_id),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn shutdown_kills_the_shells_of_disconnected_sessions() {
+    let (state, counts) = start_state();
+    let router = build_router(state.clone());
+    let session_id = common::create_session(&router, json!({})).await;
+    let mut client = common::attach(&state, &session_id);
+    common::output_until(&mut client, "mock$ ").await;
+    disconnect(&router, &session_id, &mut client).await;
+
+    let report = shutdown_all(&state, "maintenance", TIMEOUT).await;
+
+    assert_eq!(report.terminated, 1);
+    assert_eq!(counts.killed.load(Ordering::SeqCst), 1);
+    assert!(state.get_all_sessions().await.is_empty());
+}
@@ -0,0 +1,142 @@
+//! `GET /api/sessions/:id/environment` attributes every variable to the layer that set it last,
+//! reports values as hashes, and reveals allowlisted values to administrators only
+
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use rs_terminal::api::dto::{
+    EnvironmentSource, EnvironmentVariableInfo, SessionEnvironmentResponse,
+};
+use rs_terminal::app_state::AppState;
+use rs_terminal::pty::MockPtyFactory;
+use rs_terminal::server::build_router;
+use serde_json::{Value, json};
+
+/// `LAYERED` is set by every layer, each of the others by one
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+nudge_on_connect = false
+client_environment_allowlist = ["LAYERED", "FROM_CLIENT"]
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+environment = { LAYERED = "default", FROM_DEFAULTS = "1", HOME = "/srv/terminal" }
+
+[shells.sh]
+command = ["sh"]
+environment = { LAYERED = "shell", FROM_SHELL = "1" }
+"#;
+
+fn state() -> AppState {
+    common::state(CONFIG)
+        .with_auth_provider(Arc::new(common::HeaderUser))
+        .with_pty_factory(Arc::new(MockPtyFactory))
+}
+
+/// Create a session of alice with the client `environment` and start its shell, returning its ID
+async fn start_session(state: &AppState, environment: Value) -> String {
+    let router = build_router(state.clone());
+    let body = json!({ "environment": environment });
+    let (status, session) =
+        common::call_as(&router, "POST", "/api/sessions", "alice", Some(body)).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", session);
+    let session_id = session["id"].as_str().unwrap().to_string();
+
+    let mut client = common::attach(state, &session_id);
+    common::started(&mut client).await;
+    // Keep the client connected while the test runs
+    tokio::spawn(async move { while client.receive().await.is_some() {} });
+    session_id
+}
+
+/// The session's variables by name, as seen by `user`
+async fn environment(
+    state: &AppState,
+    session_id: &str,
+    user: &str,
+) -> HashMap<String, EnvironmentVariableInfo> {
+    let router = build_router(state.clone());
+    let uri = format!("/api/sessions/{}/environment", session_id);
+    let (status, body) = common::call_as(&router, "GET", &uri, user, None).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let response: SessionEnvironmentResponse = serde_json::from_value(body).unwrap();
+    response
+        .variables
+        .into_iter()
+        .map(|variable| (variable.name.clone(), variable))
+        .collect()
+}
+
+#[tokio::test]
+async fn sources_follow_the_merge_order() {
+    let state = state();
+    let requested =
+        start_session(&state, json!({ "LAYERED": "request", "FROM_CLIENT": "1" })).await;
+    let configured = start_session(&state, json!({})).await;
+
+    let variables = environment(&state, &requested, "alice").await;
+    let source = |name: &str| variables.get(name).map(|variable| variable.source);
+    assert_eq!(source("LAYERED"), Some(EnvironmentSource::Request));
+    assert_eq!(source("FROM_CLIENT"), Some(EnvironmentSource::Request));
+    assert_eq!(source("FROM_SHELL"), Some(EnvironmentSource::ShellConfig));
+    assert_eq!(
+        source("FROM_DEFAULTS"),
+        Some(EnvironmentSource::DefaultConfig)
+    );
+    // The configuration overrides what the server inherited
+    assert_eq!(source("HOME"), Some(EnvironmentSource::DefaultConfig));
+    assert_eq!(source("PATH"), Some(EnvironmentSource::Inherited));
+    assert_eq!(
+        source("WAYLON_SESSION_ID"),
+        Some(EnvironmentSource::Session)
+    );
+    assert!(variables.values().all(|variable| variable.value.is_none()));
+
+    // Without the client's value, the shell's wins over the default
+    let other = environment(&state, &configured, "alice").await;
+    assert_eq!(other["LAYERED"].source, EnvironmentSource::ShellConfig);
+    assert!(!other.contains_key("FROM_CLIENT"));
+
+    // Equal values hash equally across sessions, different ones don't
+    assert_eq!(
+        variables["FROM_DEFAULTS"].value_hash,
+        other["FROM_DEFAULTS"].value_hash
+    );
+    assert_ne!(variables["LAYERED"].value_hash, other["LAYERED"].value_hash);
+    assert_ne!(
+        variables["WAYLON_SESSION_ID"].value_hash,
+        other["WAYLON_SESSION_ID"].value_hash
+    );
+}
+
+#[tokio::test]
+async fn only_administrators_reveal_allowlisted_values() {
+    let state = state();
+    let session_id = start_session(&state, json!({})).await;
+    let router = build_router(state.clone());
+    let uri = |reveal: &str| format!("/api/sessions/{}/environment?reveal={}", session_id, reveal);
+
+    // The owner sees the hashes, but no values
+    let (status, _) = common::call_as(&router, "GET", &uri("PATH"), "alice", None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, body) = common::call_as(&router, "GET", &uri("PATH"), "root", None).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let response: SessionEnvironmentResponse = serde_json::from_value(body).unwrap();
+    for variable in response.variables {
+        match variable.name.as_str() {
+            "PATH" => assert_eq!(variable.value, std::env::var("PATH").ok()),
+            _ => assert_eq!(variable.value, None, "{}", variable.name),
+        }
+    }
+
+    // Variables outside the allowlist stay hidden, even from administrators
+    let (status, _) = common::call_as(&router, "GET", &uri("PATH,HOME"), "root", None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
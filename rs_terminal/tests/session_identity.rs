@@ -0,0 +1,118 @@
+//! Every session's shell gets `WAYLON_SESSION_ID` and `WAYLON_USER_ID`, which neither the
+//! configuration nor the client can override
+
+mod common;
+
+use std::sync::Arc;
+
+use axum::Router;
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+
+/// The configuration tries to set the user ID of every shell
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+nudge_on_connect = false
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+environment = { WAYLON_USER_ID = "configured" }
+
+[shells.sh]
+command = ["sh"]
+"#;
+
+struct Server {
+    address: String,
+    router: Router,
+    factory: Arc<common::RecordingPtyFactory>,
+}
+
+impl Server {
+    async fn start() -> Self {
+        let factory = Arc::new(common::RecordingPtyFactory::default());
+        let state = common::state(CONFIG).with_pty_factory(factory.clone());
+        let (address, router) = common::start_server(state).await;
+        Self {
+            address,
+            router,
+            factory,
+        }
+    }
+
+    async fn request(&self, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+        common::call(&self.router, method, uri, Some(body)).await
+    }
+
+    /// Attach to `/ws/:session_id`, returning the identity variables its shell got, sorted
+    async fn identity(&self, session_id: &str) -> Vec<(String, String)> {
+        let url = format!("ws://{}/ws/{}", self.address, session_id);
+        let mut socket = common::connect(&url, None).await;
+        // The first output means the shell was started
+        common::next_text(&mut socket).await;
+
+        let configs = self.factory.configs.lock().unwrap();
+        let mut identity: Vec<_> = configs
+            .last()
+            .expect("no PTY was created")
+            .env
+            .iter()
+            .filter(|(name, _)| name.starts_with("WAYLON_"))
+            .cloned()
+            .collect();
+        identity.sort();
+        identity
+    }
+}
+
+fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+#[tokio::test]
+async fn shell_gets_the_session_and_user_id() {
+    let server = Server::start().await;
+    let body = json!({
+        "userId": "alice",
+        "environment": { "WAYLON_SESSION_ID": "forged", "WAYLON_USER_ID": "mallory" },
+    });
+    let (status, session) = server.request("POST", "/api/sessions", body).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", session);
+    let session_id = session["id"].as_str().unwrap();
+
+    // Once each, over the client's and the configuration's values
+    assert_eq!(
+        server.identity(session_id).await,
+        pairs(&[
+            ("WAYLON_SESSION_ID", session_id),
+            ("WAYLON_USER_ID", "alice")
+        ])
+    );
+}
+
+#[tokio::test]
+async fn shell_of_a_websocket_session_gets_its_identity() {
+    let server = Server::start().await;
+
+    let identity = server.identity("attached").await;
+
+    let (status, session) = server
+        .request("GET", "/api/sessions/attached", Value::Null)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", session);
+    let user_id = session["userId"].as_str().unwrap();
+    assert_ne!(user_id, "configured");
+    assert_eq!(
+        identity,
+        pairs(&[
+            ("WAYLON_SESSION_ID", "attached"),
+            ("WAYLON_USER_ID", user_id)
+        ])
+    );
+}
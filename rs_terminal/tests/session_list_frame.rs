@@ -0,0 +1,89 @@
+//! `list_sessions` envelopes of `waylon-terminal-v1` connections are answered with the sessions
+//! of the connection's user, as `GET /api/sessions` lists them, when `enable_session_list_frame`
+//! is set; otherwise they are answered with an `invalid_message` error
+
+mod common;
+
+use std::sync::Arc;
+
+use axum::Router;
+use axum::http::StatusCode;
+use rs_terminal::protocol::{ClientEnvelope, ErrorCode, ServerEnvelope, Subprotocol};
+use rs_terminal::pty::MockPtyFactory;
+use serde_json::json;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+
+/// Start a server on mock PTYs authenticating with [`common::HeaderUser`], returning its address
+/// and a router on the same state
+async fn start_server(enabled: bool) -> (String, Router) {
+    let config = common::config(&format!("enable_session_list_frame = {}", enabled));
+    let state = common::state(&config)
+        .with_pty_factory(Arc::new(MockPtyFactory))
+        .with_auth_provider(Arc::new(common::HeaderUser));
+    common::start_server(state).await
+}
+
+/// Create a session as `user` through the REST API, returning its ID
+async fn create_session(router: &Router, user: &str) -> String {
+    let (status, session) =
+        common::call_as(router, "POST", "/api/sessions", user, Some(json!({}))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    session["id"].as_str().unwrap().to_string()
+}
+
+/// Attach to `session_id` as `user` with the `waylon-terminal-v1` subprotocol
+async fn attach(address: &str, session_id: &str, user: &str) -> common::Socket {
+    let url = format!("ws://{}/ws/{}", address, session_id);
+    let mut request = common::ws_request(&url, Some(Subprotocol::V1));
+    request
+        .headers_mut()
+        .insert(common::USER_HEADER, HeaderValue::from_str(user).unwrap());
+    let (socket, _) = connect_async(request).await.unwrap();
+    socket
+}
+
+#[tokio::test]
+async fn sessions_of_the_connection_user_are_listed() {
+    let (address, router) = start_server(true).await;
+    let attached = create_session(&router, "alice").await;
+    let other = create_session(&router, "alice").await;
+    create_session(&router, "bob").await;
+
+    let mut socket = attach(&address, &attached, "alice").await;
+    common::send(&mut socket, &ClientEnvelope::ListSessions).await;
+    let sessions = common::text_matching(&mut socket, |text| match serde_json::from_str(text) {
+        Ok(ServerEnvelope::Sessions { sessions }) => Some(sessions),
+        _ => None,
+    })
+    .await;
+
+    let mut listed: Vec<_> = sessions
+        .iter()
+        .map(|session| (session.id.clone(), session.user_id.clone()))
+        .collect();
+    listed.sort();
+    let mut expected = vec![
+        (attached, "alice".to_string()),
+        (other, "alice".to_string()),
+    ];
+    expected.sort();
+    assert_eq!(listed, expected);
+}
+
+#[tokio::test]
+async fn listing_is_refused_when_disabled() {
+    let (address, router) = start_server(false).await;
+    let attached = create_session(&router, "alice").await;
+
+    let mut socket = attach(&address, &attached, "alice").await;
+    common::send(&mut socket, &ClientEnvelope::ListSessions).await;
+    let (code, message) =
+        common::text_matching(&mut socket, |text| match serde_json::from_str(text) {
+            Ok(ServerEnvelope::Error { code, message }) => Some((code, message)),
+            _ => None,
+        })
+        .await;
+    assert_eq!(code, ErrorCode::InvalidMessage);
+    assert_eq!(message, "Invalid message: listing sessions is disabled");
+}
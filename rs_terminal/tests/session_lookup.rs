@@ -0,0 +1,165 @@
+//! Titles of sessions: `unique_titles_per_user` suffixes colliding titles of the same user, and
+//! `GET /api/sessions/lookup` finds sessions by exact or prefix title within the caller's sessions
+
+mod common;
+
+use std::sync::Arc;
+
+use axum::Router;
+use axum::http::StatusCode;
+use rs_terminal::server::build_router;
+use serde_json::json;
+
+fn router(unique_titles: bool) -> Router {
+    let config = common::config(&format!("unique_titles_per_user = {}", unique_titles));
+    let state = common::state(&config).with_auth_provider(Arc::new(common::HeaderUser));
+    build_router(state)
+}
+
+/// Create a session titled `title` as `user`, returning the title it got
+async fn create_session(router: &Router, user: &str, title: &str) -> String {
+    let body = json!({ "title": title });
+    let (status, session) =
+        common::call_as(router, "POST", "/api/sessions", user, Some(body)).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", session);
+    session["title"].as_str().unwrap().to_string()
+}
+
+/// Look sessions up as `user`, returning the owner and title of each
+async fn lookup(router: &Router, user: &str, query: &str) -> Vec<(String, String)> {
+    let uri = format!("/api/sessions/lookup?{}", query);
+    let (status, sessions) = common::call_as(router, "GET", &uri, user, None).await;
+    assert_eq!(status, StatusCode::OK, "{}", sessions);
+    sessions
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|session| {
+            (
+                session["userId"].as_str().unwrap().to_string(),
+                session["title"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect()
+}
+
+fn owned(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(user, title)| (user.to_string(), title.to_string()))
+        .collect()
+}
+
+#[tokio::test]
+async fn colliding_titles_of_the_same_user_are_suffixed() {
+    let router = router(true);
+
+    assert_eq!(
+        create_session(&router, "alice", "INC-1234").await,
+        "INC-1234"
+    );
+    assert_eq!(
+        create_session(&router, "alice", "INC-1234").await,
+        "INC-1234 (2)"
+    );
+    // Case doesn't make a title different
+    assert_eq!(
+        create_session(&router, "alice", "inc-1234").await,
+        "inc-1234 (3)"
+    );
+    // A suffix taken by hand is skipped
+    assert_eq!(
+        create_session(&router, "alice", "Deploy (2)").await,
+        "Deploy (2)"
+    );
+    assert_eq!(create_session(&router, "alice", "Deploy").await, "Deploy");
+    assert_eq!(
+        create_session(&router, "alice", "Deploy").await,
+        "Deploy (3)"
+    );
+    // Full case folding: "ß" equals "SS"
+    assert_eq!(create_session(&router, "alice", "Straße").await, "Straße");
+    assert_eq!(
+        create_session(&router, "alice", "STRASSE").await,
+        "STRASSE (2)"
+    );
+
+    // Other users' titles don't collide
+    assert_eq!(create_session(&router, "bob", "INC-1234").await, "INC-1234");
+}
+
+#[tokio::test]
+async fn colliding_titles_are_kept_by_default() {
+    let router = router(false);
+
+    assert_eq!(
+        create_session(&router, "alice", "INC-1234").await,
+        "INC-1234"
+    );
+    assert_eq!(
+        create_session(&router, "alice", "INC-1234").await,
+        "INC-1234"
+    );
+}
+
+#[tokio::test]
+async fn lookup_matches_titles_within_the_callers_sessions() {
+    let router = router(true);
+    create_session(&router, "alice", "INC-1234").await;
+    create_session(&router, "alice", "INC-1234").await;
+    create_session(&router, "alice", "inc-12345 database").await;
+    create_session(&router, "alice", "Straße").await;
+    create_session(&router, "bob", "INC-1234").await;
+
+    // Exact, case-insensitive and only alice's own sessions
+    assert_eq!(
+        lookup(&router, "alice", "title=inc-1234").await,
+        owned(&[("alice", "INC-1234")])
+    );
+    assert_eq!(
+        lookup(&router, "alice", "title=INC-1234&match=prefix").await,
+        owned(&[
+            ("alice", "INC-1234"),
+            ("alice", "INC-1234 (2)"),
+            ("alice", "inc-12345 database"),
+        ])
+    );
+    assert_eq!(
+        lookup(&router, "alice", "title=STRASSE").await,
+        owned(&[("alice", "Straße")])
+    );
+    assert!(lookup(&router, "alice", "title=INC-99").await.is_empty());
+
+    // Naming the caller is fine, naming another user is not
+    assert_eq!(
+        lookup(&router, "alice", "title=INC-1234&userId=alice").await,
+        owned(&[("alice", "INC-1234")])
+    );
+    let (status, _) = common::call_as(
+        &router,
+        "GET",
+        "/api/sessions/lookup?title=INC-1234&userId=bob",
+        "alice",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Another user only finds their own sessions
+    assert_eq!(
+        lookup(&router, "bob", "title=INC-1234&match=prefix").await,
+        owned(&[("bob", "INC-1234")])
+    );
+
+    // Administrators search everyone's sessions, or one user's
+    let mut everyone = lookup(&router, "root", "title=INC-1234").await;
+    everyone.sort();
+    assert_eq!(
+        everyone,
+        owned(&[("alice", "INC-1234"), ("bob", "INC-1234")])
+    );
+    assert_eq!(
+        lookup(&router, "root", "title=inc-1234&match=prefix&userId=bob").await,
+        owned(&[("bob", "INC-1234")])
+    );
+}
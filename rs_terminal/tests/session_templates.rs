@@ -0,0 +1,182 @@
+//! Session templates: a session created from a template gets its shell, command, size, working
+//! directory, environment, labels and title, and the request still overrides size and title;
+//! `GET /api/shells` lists the shells sessions can name with the size they start with
+
+mod common;
+
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use rs_terminal::app_state::AppState;
+use rs_terminal::config::{ConfigError, ConfigLoader};
+use rs_terminal::pty::PtyConfig;
+use rs_terminal::server::build_router;
+use serde_json::{Value, json};
+
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "bash"
+session_timeout = 1800000
+nudge_on_connect = false
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+
+[shells.bash]
+command = ["bash"]
+
+[shells.sh]
+command = ["sh"]
+size = { columns = 100, rows = 30 }
+
+[templates.rails]
+shell_type = "sh"
+command = ["bin/rails", "console"]
+size = { columns = 120, rows = 40 }
+working_directory = "/"
+environment = { RAILS_ENV = "development" }
+labels = { team = "web" }
+title = "Rails console of {user_id}"
+"#;
+
+fn state() -> (AppState, Arc<common::RecordingPtyFactory>) {
+    let factory = Arc::new(common::RecordingPtyFactory::default());
+    let state = common::state(CONFIG).with_pty_factory(factory.clone());
+    (state, factory)
+}
+
+async fn request(state: &AppState, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+    common::call(&build_router(state.clone()), method, uri, Some(body)).await
+}
+
+/// Attach to the session and return the configuration its PTY was created with
+async fn started_pty(
+    state: &AppState,
+    factory: &common::RecordingPtyFactory,
+    session_id: &str,
+) -> PtyConfig {
+    let mut client = common::attach(state, session_id);
+    common::started(&mut client).await;
+    factory.configs.lock().unwrap().pop().unwrap()
+}
+
+#[tokio::test]
+async fn template_fields_land_on_the_session_and_the_pty() {
+    let (state, factory) = state();
+    let body = json!({ "template": "rails", "userId": "alice" });
+    let (status, session) = request(&state, "POST", "/api/sessions", body).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", session);
+
+    assert_eq!(session["template"], "rails");
+    assert_eq!(session["shellType"], "sh");
+    assert_eq!(session["title"], "Rails console of alice");
+    assert_eq!(
+        (&session["columns"], &session["rows"]),
+        (&json!(120), &json!(40))
+    );
+    assert_eq!(session["workingDirectory"], "/");
+    assert_eq!(session["labels"], json!({ "team": "web" }));
+
+    let session_id = session["id"].as_str().unwrap();
+    let pty = started_pty(&state, &factory, session_id).await;
+    assert_eq!(pty.command, "bin/rails");
+    assert_eq!(pty.args, ["console"]);
+    assert_eq!((pty.cols, pty.rows), (120, 40));
+    assert_eq!(pty.cwd.as_deref(), Some(std::path::Path::new("/")));
+    assert!(
+        pty.env
+            .contains(&("RAILS_ENV".to_string(), "development".to_string())),
+        "{:?}",
+        pty.env
+    );
+
+    // The session reports the template after attaching, too
+    let (_, fetched) = request(
+        &state,
+        "GET",
+        &format!("/api/sessions/{}", session_id),
+        Value::Null,
+    )
+    .await;
+    assert_eq!(fetched["template"], "rails");
+    assert_eq!(fetched["labels"], json!({ "team": "web" }));
+}
+
+#[tokio::test]
+async fn request_overrides_size_and_title() {
+    let (state, factory) = state();
+    let body = json!({ "template": "rails", "columns": 90, "title": "mine" });
+    let (status, session) = request(&state, "POST", "/api/sessions", body).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", session);
+    assert_eq!(session["title"], "mine");
+    assert_eq!(
+        (&session["columns"], &session["rows"]),
+        (&json!(90), &json!(40))
+    );
+
+    let pty = started_pty(&state, &factory, session["id"].as_str().unwrap()).await;
+    assert_eq!((pty.cols, pty.rows), (90, 40));
+    assert_eq!(pty.command, "bin/rails");
+}
+
+#[tokio::test]
+async fn templates_are_listed() {
+    let (state, _) = state();
+    let (status, templates) = request(&state, "GET", "/api/templates", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        templates,
+        json!([{
+            "name": "rails",
+            "shellType": "sh",
+            "title": "Rails console of {user_id}",
+            "columns": 120,
+            "rows": 40,
+            "labels": { "team": "web" },
+        }])
+    );
+}
+
+#[tokio::test]
+async fn shells_are_listed_with_their_size() {
+    let (state, _) = state();
+    let (status, shells) = request(&state, "GET", "/api/shells", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        shells,
+        json!({
+            "defaultShell": "bash",
+            "shells": [
+                { "name": "bash", "columns": 80, "rows": 24 },
+                { "name": "sh", "columns": 100, "rows": 30 },
+            ],
+        })
+    );
+}
+
+#[tokio::test]
+async fn unknown_template_is_rejected() {
+    let (state, factory) = state();
+    let (status, _) = request(
+        &state,
+        "POST",
+        "/api/sessions",
+        json!({ "template": "psql" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(state.get_all_sessions().await.is_empty());
+    assert!(factory.configs.lock().unwrap().is_empty());
+}
+
+#[test]
+fn template_with_an_unknown_shell_fails_to_load() {
+    let config = CONFIG.replace("shell_type = \"sh\"", "shell_type = \"fish\"");
+    match ConfigLoader::new().parse_config(&config) {
+        Err(ConfigError::ShellConfigNotFound(message)) => {
+            assert!(message.contains("template rails"), "{}", message);
+        }
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+}
@@ -0,0 +1,82 @@
+//! `PATCH /api/sessions/:session_id` renames a session, and the new title is what the other
+//! endpoints report afterwards
+
+mod common;
+
+use std::sync::Arc;
+
+use axum::Router;
+use axum::http::StatusCode;
+use rs_terminal::pty::MockPtyFactory;
+use rs_terminal::server::build_router;
+use serde_json::json;
+
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+
+[shells.sh]
+command = ["sh"]
+"#;
+
+fn router() -> Router {
+    build_router(common::state(CONFIG).with_pty_factory(Arc::new(MockPtyFactory)))
+}
+
+#[tokio::test]
+async fn updated_title_is_read_back() {
+    let router = router();
+    let (status, session) = common::call(
+        &router,
+        "POST",
+        "/api/sessions",
+        Some(json!({ "title": "build" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let uri = format!("/api/sessions/{}", session["id"].as_str().unwrap());
+
+    let (status, updated) =
+        common::call(&router, "PATCH", &uri, Some(json!({ "title": "deploy" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(updated["title"], "deploy");
+    assert_eq!(updated["id"], session["id"]);
+
+    let (status, fetched) = common::call(&router, "GET", &uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(fetched["title"], "deploy");
+    // Only the title changed
+    for field in ["userId", "columns", "rows", "shellType", "createdAt"] {
+        assert_eq!(fetched[field], session[field], "{}", field);
+    }
+
+    let (_, listed) = common::call(&router, "GET", "/api/sessions", None).await;
+    assert_eq!(listed[0]["title"], "deploy");
+}
+
+#[tokio::test]
+async fn unknown_session_is_not_found() {
+    let (status, error) = common::call(
+        &router(),
+        "PATCH",
+        "/api/sessions/missing",
+        Some(json!({ "title": "deploy" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(error["message"], "Session not found: missing");
+}
+
+#[tokio::test]
+async fn title_is_required() {
+    let router = router();
+    let (_, session) = common::call(&router, "POST", "/api/sessions", Some(json!({}))).await;
+    let uri = format!("/api/sessions/{}", session["id"].as_str().unwrap());
+
+    let (status, _) = common::call(&router, "PATCH", &uri, Some(json!({}))).await;
+    assert!(status.is_client_error(), "{}", status);
+}
@@ -0,0 +1,222 @@
+//! Scratch directories under `session_tmpdir_root`: created (mode 0700) before the shell starts,
+//! exported as `SESSION_TMPDIR`, removed when the session is terminated, and swept by age once
+//! their session is gone; session IDs are encoded into distinct directory names
+
+mod common;
+
+use std::fs::{File, FileTimes};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use axum::http::StatusCode;
+use rs_terminal::app_state::AppState;
+use rs_terminal::pty::PtyConfig;
+use rs_terminal::server::build_router;
+use rs_terminal::service::{SESSION_TMPDIR_VAR, sweep_orphaned_tmpdirs};
+use serde_json::{Value, json};
+
+/// Longest wait for the session to start or end
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An empty root for the scratch directories of a test
+fn tmpdir_root(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("session-tmpdir-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&root);
+    root
+}
+
+fn state(root: &Path, max_bytes: u64) -> (AppState, Arc<common::RecordingPtyFactory>) {
+    let config = format!(
+        r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+nudge_on_connect = false
+session_tmpdir_root = {:?}
+session_tmpdir_as_cwd = true
+session_tmpdir_max_bytes = {}
+session_tmpdir_max_age_secs = 3600
+
+[default_shell_config]
+size = {{ columns = 80, rows = 24 }}
+
+[shells.sh]
+command = ["sh"]
+"#,
+        root.to_str().unwrap(),
+        max_bytes
+    );
+    let factory = Arc::new(common::RecordingPtyFactory::default());
+    let state = common::state(&config).with_pty_factory(factory.clone());
+    (state, factory)
+}
+
+async fn request(state: &AppState, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+    common::call(&build_router(state.clone()), method, uri, Some(body)).await
+}
+
+/// Create a session and start its shell, returning its ID and the configuration of its PTY
+async fn start_session(
+    state: &AppState,
+    factory: &common::RecordingPtyFactory,
+) -> (String, PtyConfig) {
+    let (status, session) = request(state, "POST", "/api/sessions", json!({})).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", session);
+    let session_id = session["id"].as_str().unwrap().to_string();
+    let pty = connect(state, factory, &session_id).await;
+    (session_id, pty)
+}
+
+/// Connect to a session, creating it under `session_id` if it doesn't exist, and start its shell,
+/// returning the configuration of its PTY
+async fn connect(
+    state: &AppState,
+    factory: &common::RecordingPtyFactory,
+    session_id: &str,
+) -> PtyConfig {
+    let mut client = common::attach(state, session_id);
+    common::started(&mut client).await;
+    // Keep the client connected until the session ends
+    tokio::spawn(async move { while client.receive().await.is_some() {} });
+    factory.configs.lock().unwrap().pop().unwrap()
+}
+
+/// Terminate a session through the API
+async fn terminate(state: &AppState, session_id: &str) {
+    let uri = format!("/api/sessions/{}", session_id);
+    let (status, body) = request(state, "DELETE", &uri, Value::Null).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+}
+
+/// Wait until `path` no longer exists
+async fn removed(path: &Path) -> bool {
+    tokio::time::timeout(TIMEOUT, async {
+        while path.exists() {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .is_ok()
+}
+
+/// Make a directory look like it was last modified `age` ago
+fn age(path: &Path, age: Duration) {
+    let modified = SystemTime::now() - age;
+    File::open(path)
+        .unwrap()
+        .set_times(FileTimes::new().set_modified(modified))
+        .unwrap();
+}
+
+#[tokio::test]
+async fn scratch_directory_is_created_exported_and_removed_on_terminate() {
+    let root = tmpdir_root("terminate");
+    let (state, factory) = state(&root, 1024 * 1024);
+    let (session_id, pty) = start_session(&state, &factory).await;
+
+    let tmpdir = root.join(&session_id);
+    let metadata = std::fs::metadata(&tmpdir).expect("no scratch directory");
+    assert!(metadata.is_dir());
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o700);
+    assert!(
+        pty.env.contains(&(
+            SESSION_TMPDIR_VAR.to_string(),
+            tmpdir.to_string_lossy().into_owned()
+        )),
+        "{:?}",
+        pty.env
+    );
+    // The session has no working directory of its own, so it starts in the scratch directory
+    assert_eq!(pty.cwd.as_deref(), Some(tmpdir.as_path()));
+
+    std::fs::create_dir(tmpdir.join("nested")).unwrap();
+    std::fs::write(tmpdir.join("nested/notes.txt"), "scratch").unwrap();
+    terminate(&state, &session_id).await;
+    assert!(removed(&tmpdir).await, "{} is left", tmpdir.display());
+    assert!(root.exists());
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn scratch_directory_over_the_size_guard_is_left_in_place() {
+    let root = tmpdir_root("size-guard");
+    let (state, factory) = state(&root, 16);
+    let (session_id, _) = start_session(&state, &factory).await;
+
+    let tmpdir = root.join(&session_id);
+    std::fs::write(tmpdir.join("large"), [0u8; 64]).unwrap();
+    terminate(&state, &session_id).await;
+    // Removal is attempted when the session task cleans up, give it the chance
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(tmpdir.join("large").exists());
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn sweep_removes_old_directories_without_a_session() {
+    let root = tmpdir_root("sweep");
+    let (state, factory) = state(&root, 1024 * 1024);
+    let (session_id, _) = start_session(&state, &factory).await;
+
+    let orphan = root.join("crashed-session");
+    std::fs::create_dir(&orphan).unwrap();
+    std::fs::write(orphan.join("notes.txt"), "left behind").unwrap();
+    let recent = root.join("recent-session");
+    std::fs::create_dir(&recent).unwrap();
+    let live = root.join(&session_id);
+    age(&orphan, Duration::from_secs(2 * 3600));
+    age(&live, Duration::from_secs(2 * 3600));
+
+    // Only the old directory without a session goes
+    assert_eq!(sweep_orphaned_tmpdirs(&state).await, 1);
+    assert!(!orphan.exists());
+    assert!(recent.exists());
+    assert!(live.exists());
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn sweep_of_a_missing_root_does_nothing() {
+    let root = tmpdir_root("missing");
+    let (state, _) = state(&root, 1024 * 1024);
+    assert_eq!(sweep_orphaned_tmpdirs(&state).await, 0);
+}
+
+#[tokio::test]
+async fn ids_that_need_encoding_get_distinct_directories_the_sweep_keeps() {
+    let root = tmpdir_root("encoded");
+    let (state, factory) = state(&root, 1024 * 1024);
+    // Both IDs can be chosen by a client, e.g. through /wt/<session_id>
+    let dotted_pty = connect(&state, &factory, "a.b").await;
+    connect(&state, &factory, "a_b").await;
+
+    let dotted = root.join("a%2Eb");
+    let underscored = root.join("a_b");
+    assert!(dotted.is_dir());
+    assert!(underscored.is_dir());
+    assert_eq!(dotted_pty.cwd.as_deref(), Some(dotted.as_path()));
+
+    // Ending one session leaves the other's directory alone
+    std::fs::write(dotted.join("notes.txt"), "still needed").unwrap();
+    terminate(&state, "a_b").await;
+    assert!(
+        removed(&underscored).await,
+        "{} is left",
+        underscored.display()
+    );
+    assert!(dotted.join("notes.txt").exists());
+
+    // The sweep recognizes the live session behind the encoded name
+    let orphan = root.join("%41_b");
+    std::fs::create_dir(&orphan).unwrap();
+    age(&dotted, Duration::from_secs(2 * 3600));
+    age(&orphan, Duration::from_secs(2 * 3600));
+    assert_eq!(sweep_orphaned_tmpdirs(&state).await, 1);
+    assert!(dotted.join("notes.txt").exists());
+    assert!(!orphan.exists());
+
+    let _ = std::fs::remove_dir_all(&root);
+}
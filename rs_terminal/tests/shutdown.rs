@@ -0,0 +1,177 @@
+//! `shutdown_all` tells every connected client why its session ends and kills the shells; a
+//! session stuck on a client that stopped reading is killed once the timeout expires
+
+mod common;
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use rs_terminal::app_state::AppState;
+use rs_terminal::protocol::{ChannelClient, ErrorCode, ServerEnvelope};
+use rs_terminal::pty::{AsyncPty, MockPty, PtyConfig, PtyError, PtyExitStatus, PtyFactory};
+use rs_terminal::service::shutdown_all;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Longest wait for a message of the session
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Mock PTY counting how often it was killed
+struct KillRecorder {
+    inner: MockPty,
+    kills: Arc<AtomicUsize>,
+}
+
+impl AsyncRead for KillRecorder {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for KillRecorder {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl AsyncPty for KillRecorder {
+    async fn resize(&mut self, cols: u16, rows: u16) -> Result<(), PtyError> {
+        self.inner.resize(cols, rows).await
+    }
+
+    fn pid(&self) -> Option<u32> {
+        self.inner.pid()
+    }
+
+    fn is_alive(&self) -> bool {
+        self.inner.is_alive()
+    }
+
+    async fn try_wait(&mut self) -> Result<Option<PtyExitStatus>, PtyError> {
+        self.inner.try_wait().await
+    }
+
+    async fn kill(&mut self) -> Result<(), PtyError> {
+        self.kills.fetch_add(1, Ordering::SeqCst);
+        self.inner.kill().await
+    }
+}
+
+struct KillRecorderFactory {
+    kills: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl PtyFactory for KillRecorderFactory {
+    async fn create(&self, config: &PtyConfig) -> Result<Box<dyn AsyncPty>, PtyError> {
+        Ok(Box::new(KillRecorder {
+            inner: MockPty::new(config),
+            kills: self.kills.clone(),
+        }))
+    }
+
+    fn name(&self) -> &'static str {
+        "kill-recorder"
+    }
+}
+
+/// Application state on mock PTYs, with the counter of killed PTYs
+fn state() -> (AppState, Arc<AtomicUsize>) {
+    let kills = Arc::new(AtomicUsize::new(0));
+    let state =
+        common::state(&common::config("")).with_pty_factory(Arc::new(KillRecorderFactory {
+            kills: kills.clone(),
+        }));
+    (state, kills)
+}
+
+/// Start a session and wait for its prompt, so it is running when the test goes on
+async fn start_session(state: &AppState, session_id: &str) -> ChannelClient {
+    let mut client = common::attach(state, session_id);
+    common::output_until(&mut client, "mock$ ").await;
+    client
+}
+
+/// Wait for the session to report why it ended, then for its connection to close
+async fn termination_message(client: &mut ChannelClient) -> String {
+    let mut message = None;
+    loop {
+        match tokio::time::timeout(TIMEOUT, client.receive())
+            .await
+            .expect("connection not closed")
+        {
+            Some(Ok(ServerEnvelope::Error {
+                code: ErrorCode::SessionTerminated,
+                message: text,
+            })) => message = Some(text),
+            Some(Ok(_)) => {}
+            Some(Err(e)) => panic!("malformed message: {}", e),
+            None => break,
+        }
+    }
+    message.expect("no session_terminated error")
+}
+
+#[tokio::test]
+async fn clients_are_notified_and_shells_killed() {
+    let (state, kills) = state();
+    let mut first = start_session(&state, "first").await;
+    let mut second = start_session(&state, "second").await;
+
+    let report = shutdown_all(&state, "maintenance", TIMEOUT).await;
+    assert_eq!(report.terminated, 2);
+    assert_eq!(report.killed, 0);
+    assert_eq!(report.timed_out, 0);
+
+    for client in [&mut first, &mut second] {
+        let message = termination_message(client).await;
+        assert!(message.contains("maintenance"), "{:?}", message);
+    }
+    assert_eq!(kills.load(Ordering::SeqCst), 2);
+    assert!(state.detached_ptys.session_ids().await.is_empty());
+}
+
+#[tokio::test]
+async fn stuck_session_is_killed_after_the_timeout() {
+    let (state, kills) = state();
+    let client = start_session(&state, "stuck").await;
+
+    // Type without reading the echo until the session blocks on sending it
+    let mut blocked = false;
+    for _ in 0..1000 {
+        let input = tokio::time::timeout(Duration::from_millis(200), client.input("x")).await;
+        if input.is_err() {
+            blocked = true;
+            break;
+        }
+    }
+    assert!(blocked, "the session kept up with the input");
+
+    let started = Instant::now();
+    let report = shutdown_all(&state, "maintenance", Duration::from_millis(300)).await;
+    assert!(started.elapsed() < TIMEOUT, "{:?}", started.elapsed());
+    assert_eq!(report.terminated, 0);
+    assert_eq!(report.killed, 1);
+    assert_eq!(report.timed_out, 0);
+    assert_eq!(kills.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,163 @@
+//! Single-port operation: with `port` set, WebSocket (TCP) and WebTransport (UDP) are served on
+//! the same port number, and HTTP responses advertise the WebTransport endpoint with `alt-svc`
+//! With `enable_webtransport = false` no UDP port is bound at all, with `enable_websocket = false`
+//! there are no `/ws` routes
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use rs_terminal::app_state::AppState;
+use rs_terminal::pty::MockPtyFactory;
+use rs_terminal::server::{build_router, start_webtransport_service};
+use tokio::net::{TcpListener, UdpSocket};
+use tokio_tungstenite::connect_async;
+use tower::ServiceExt;
+use wtransport::{ClientConfig, Endpoint};
+
+/// Longest wait for a step of the exchange
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn state(ports: &str) -> AppState {
+    common::state(&common::config(ports)).with_pty_factory(Arc::new(MockPtyFactory))
+}
+
+/// `alt-svc` header of a health check response
+async fn alt_svc(ports: &str) -> Option<String> {
+    let request = Request::get("/health").body(Body::empty()).unwrap();
+    let response = build_router(state(ports)).oneshot(request).await.unwrap();
+    response
+        .headers()
+        .get("alt-svc")
+        .map(|value| value.to_str().unwrap().to_string())
+}
+
+#[tokio::test]
+async fn alt_svc_points_at_the_webtransport_port() {
+    assert_eq!(
+        alt_svc("port = 9443").await.as_deref(),
+        Some("h3=\":9443\"; ma=86400")
+    );
+    assert_eq!(
+        alt_svc("http_port = 9000\nsingle_port = true")
+            .await
+            .as_deref(),
+        Some("h3=\":9000\"; ma=86400")
+    );
+    // The two-port scheme is still accepted
+    assert_eq!(
+        alt_svc("http_port = 9000\nwebtransport_port = 9001")
+            .await
+            .as_deref(),
+        Some("h3=\":9001\"; ma=86400")
+    );
+    assert_eq!(
+        alt_svc("port = 9443\nenable_webtransport = false").await,
+        None
+    );
+}
+
+#[tokio::test]
+async fn disabled_webtransport_binds_no_port() {
+    let socket = UdpSocket::bind("0.0.0.0:0").await.unwrap();
+    let port = socket.local_addr().unwrap().port();
+    drop(socket);
+
+    let state = state(&format!("port = {}\nenable_webtransport = false", port));
+    start_webtransport_service(state).unwrap();
+    // The port is still free
+    UdpSocket::bind(("0.0.0.0", port))
+        .await
+        .expect("the WebTransport port was bound");
+}
+
+/// Status of a WebSocket upgrade request to `uri`
+async fn upgrade_status(ports: &str, uri: &str) -> StatusCode {
+    let request = Request::get(uri)
+        .header("connection", "upgrade")
+        .header("upgrade", "websocket")
+        .header("sec-websocket-version", "13")
+        .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+        .body(Body::empty())
+        .unwrap();
+    build_router(state(ports))
+        .oneshot(request)
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn disabled_websocket_has_no_routes() {
+    assert_eq!(
+        upgrade_status("enable_websocket = false", "/ws").await,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        upgrade_status("enable_websocket = false", "/ws/some-session").await,
+        StatusCode::NOT_FOUND
+    );
+    // The rest of the server is still there
+    assert_eq!(
+        upgrade_status("enable_websocket = false", "/health").await,
+        StatusCode::OK
+    );
+    assert_ne!(upgrade_status("", "/ws").await, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn both_transports_connect_on_the_shared_port() {
+    let listener = TcpListener::bind("0.0.0.0:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let state = state(&format!("port = {}", port));
+    start_webtransport_service(state.clone()).unwrap();
+    tokio::spawn(async move { axum::serve(listener, build_router(state)).await });
+
+    // WebSocket over TCP, advertising WebTransport on the same number
+    let (mut socket, response) = connect_async(format!("ws://127.0.0.1:{}/ws", port))
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers()["alt-svc"],
+        format!("h3=\":{}\"; ma=86400", port)
+    );
+    common::text_until(&mut socket, "mock$ ").await;
+
+    // WebTransport over UDP; the server's certificate is self-signed
+    let client = Endpoint::client(
+        ClientConfig::builder()
+            .with_bind_default()
+            .with_no_cert_validation()
+            .build(),
+    )
+    .unwrap();
+    let connection = tokio::time::timeout(
+        TIMEOUT,
+        client.connect(format!("https://127.0.0.1:{}/wt", port)),
+    )
+    .await
+    .expect("WebTransport connection timed out")
+    .unwrap();
+    let (_send, mut recv) = tokio::time::timeout(TIMEOUT, connection.accept_bi())
+        .await
+        .expect("no stream from the server")
+        .unwrap();
+    let mut output = Vec::new();
+    tokio::time::timeout(TIMEOUT, async {
+        while !output.ends_with(b"mock$ ") {
+            let mut buffer = [0u8; 256];
+            let n = recv
+                .read(&mut buffer)
+                .await
+                .unwrap()
+                .expect("stream finished");
+            output.extend_from_slice(&buffer[..n]);
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("no prompt over WebTransport: {:?}", output));
+    assert!(output.starts_with(b"mock shell"), "{:?}", output);
+}
@@ -0,0 +1,119 @@
+//! Socket activation: the server serves on a listener passed as fd 3 with `LISTEN_PID` and
+//! `LISTEN_FDS` set, and removes those variables so its shells don't inherit them
+#![cfg(target_os = "linux")]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::fd::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use serde_json::Value;
+
+const CONFIG: &str = r#"
+http_port = 1
+enable_webtransport = false
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+
+[shells.sh]
+command = ["sh"]
+"#;
+
+/// Longest wait for the server's answer
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Send a request over a new connection and return the status line and the body
+fn request(port: u16, method: &str, path: &str, body: &str) -> (String, String) {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        method,
+        path,
+        body.len(),
+        body
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    let status = head.lines().next().unwrap_or_default().to_string();
+    (status, body.to_string())
+}
+
+#[test]
+fn serves_on_the_passed_listener() {
+    // The port the configuration names isn't bound, the passed listener is used instead
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let fd = listener.as_raw_fd();
+
+    // Like the service manager: fd 3 is the socket and LISTEN_PID names the server's process
+    // (the shell's PID, kept by exec)
+    let mut command = Command::new("sh");
+    command
+        .args([
+            "-c",
+            "LISTEN_PID=$$ exec \"$0\" --config -",
+            env!("CARGO_BIN_EXE_rs_terminal"),
+        ])
+        .env("LISTEN_FDS", "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // SAFETY: only async-signal-safe calls between fork and exec
+    unsafe {
+        command.pre_exec(move || {
+            let result = if fd == 3 {
+                libc::fcntl(3, libc::F_SETFD, 0)
+            } else {
+                libc::dup2(fd, 3)
+            };
+            if result == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut server = command.spawn().unwrap();
+    server
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(CONFIG.as_bytes())
+        .unwrap();
+    drop(listener);
+
+    let (status, _) = request(port, "GET", "/health", "");
+    let (dry_run_status, dry_run) = request(port, "POST", "/api/sessions/dry-run", "{}");
+    server.kill().unwrap();
+    server.wait().unwrap();
+
+    assert!(status.contains("200"), "{}", status);
+    assert!(
+        dry_run_status.contains("200"),
+        "{}: {}",
+        dry_run_status,
+        dry_run
+    );
+    let dry_run: Value = serde_json::from_str(&dry_run).unwrap();
+    let names: Vec<&str> = dry_run["environment"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|variable| variable["name"].as_str())
+        .collect();
+    assert!(names.contains(&"PATH"), "{:?}", names);
+    for variable in ["LISTEN_PID", "LISTEN_FDS"] {
+        assert!(!names.contains(&variable), "{} is inherited", variable);
+    }
+}
@@ -0,0 +1,148 @@
+//! Shells that can't be started are reported to the client with the shell, the command, the
+//! working directory and the OS error, before the connection closes; a working directory that
+//! can't be used is reported as such instead of as an opaque error of the spawn
+
+mod common;
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use rs_terminal::protocol::{ChannelClient, ErrorCode, ServerEnvelope};
+
+/// Longest wait for a message of the session
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Receive until the error, then expect the connection to close
+async fn spawn_error(client: &mut ChannelClient) -> (ErrorCode, String) {
+    let error = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match client.receive().await {
+                Some(Ok(ServerEnvelope::Error { code, message })) => return (code, message),
+                Some(Ok(_)) => continue,
+                other => panic!("no error before {:?}", other),
+            }
+        }
+    })
+    .await
+    .expect("no error from the session");
+    let end = tokio::time::timeout(TIMEOUT, client.receive())
+        .await
+        .expect("the connection wasn't closed");
+    assert!(end.is_none(), "{:?}", end);
+    error
+}
+
+/// Start a session of a shell running `command` in `working_directory`
+async fn start(command: &str, working_directory: &str) -> (ErrorCode, String) {
+    let config = format!(
+        r#"
+pty_implementation = "portable_pty"
+default_shell_type = "broken"
+session_timeout = 1800000
+nudge_on_connect = false
+
+[default_shell_config]
+size = {{ columns = 80, rows = 24 }}
+working_directory = "{}"
+
+[shells.broken]
+command = ["{}"]
+"#,
+        working_directory, command
+    );
+    let mut client = common::attach(&common::state(&config), "spawn-failure");
+    spawn_error(&mut client).await
+}
+
+/// Scratch directory of a test, removed when dropped
+struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("rs-terminal-spawn-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    fn path(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[tokio::test]
+async fn missing_command_is_described() {
+    let dir = ScratchDir::new("missing");
+
+    let (code, message) = start("/nonexistent/waylon-shell", dir.path()).await;
+
+    assert_eq!(code, ErrorCode::SpawnFailed);
+    let expected = format!(
+        "Process spawn failed: `/nonexistent/waylon-shell` (shell broken) in {}: ",
+        dir.path()
+    );
+    assert!(message.contains(&expected), "{}", message);
+    assert!(message.contains("doesn't exist"), "{}", message);
+    assert!(message.contains("ENOENT"), "{}", message);
+}
+
+#[tokio::test]
+async fn command_that_is_not_executable_is_described() {
+    let dir = ScratchDir::new("not-executable");
+    let script = dir.0.join("shell");
+    std::fs::write(&script, "#!/bin/sh\n").unwrap();
+    let script = script.to_str().unwrap();
+
+    let (code, message) = start(script, dir.path()).await;
+
+    assert_eq!(code, ErrorCode::SpawnFailed);
+    let expected = format!(
+        "Process spawn failed: `{}` (shell broken) in {}: ",
+        script,
+        dir.path()
+    );
+    assert!(message.contains(&expected), "{}", message);
+    assert!(message.contains("not executable"), "{}", message);
+    assert!(message.contains("EACCES"), "{}", message);
+    // The cause stays on one line
+    assert!(!message.contains('\n'), "{}", message);
+}
+
+#[tokio::test]
+async fn missing_working_directory_is_described() {
+    let dir = ScratchDir::new("missing-cwd");
+    let missing = dir.0.join("missing");
+    let missing = missing.to_str().unwrap();
+
+    let (code, message) = start("sh", missing).await;
+
+    assert_eq!(code, ErrorCode::SpawnFailed);
+    let expected = format!(
+        "Process spawn failed: `sh` (shell broken) in {}: working directory {} does not exist (os error 2)",
+        missing, missing
+    );
+    assert!(message.ends_with(&expected), "{}", message);
+}
+
+#[tokio::test]
+async fn working_directory_that_is_a_file_is_described() {
+    let dir = ScratchDir::new("file-cwd");
+    let file = dir.0.join("file");
+    std::fs::write(&file, "").unwrap();
+    let file = file.to_str().unwrap();
+
+    let (code, message) = start("sh", file).await;
+
+    assert_eq!(code, ErrorCode::SpawnFailed);
+    let expected = format!(
+        "Process spawn failed: `sh` (shell broken) in {}: working directory {} is not a directory",
+        file, file
+    );
+    assert!(message.ends_with(&expected), "{}", message);
+}
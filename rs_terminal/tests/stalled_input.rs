@@ -0,0 +1,334 @@
+//! A terminal that stops taking input (a stopped process with a full input queue) doesn't hold up
+//! its session: after `pty_write_timeout_ms` the client is warned once, the input is dropped or
+//! kept per `stalled_input` and the session keeps answering; the nudge of a new client is given up
+//! on the same way. A client that doesn't read its output
+//! within `connection_send_timeout_ms` is disconnected, its shell keeps running
+
+mod common;
+
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::http::{Request, StatusCode};
+use futures_util::StreamExt;
+use rs_terminal::app_state::{AppState, DiagnosticsStore};
+use rs_terminal::config::ConfigLoader;
+use rs_terminal::protocol::{NoticeLevel, ServerEnvelope, Subprotocol};
+use rs_terminal::pty::{AsyncPty, MockPty, PtyConfig, PtyError, PtyExitStatus, PtyFactory};
+use serde_json::{Value, json};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::tungstenite::Message;
+use tower::ServiceExt;
+
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+nudge_on_connect = false
+pty_write_timeout_ms = 100
+stalled_input = "{policy}"
+stalled_input_buffer_bytes = 8
+connection_send_timeout_ms = 200
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+
+[shells.sh]
+command = ["sh"]
+"#;
+
+/// Longest wait for a frame of the server
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Output produced by a flooding shell, more than the socket buffers of both ends take
+const FLOOD_BYTES: usize = 64 * 1024 * 1024;
+
+/// Whether the PTY takes input, with the write waiting for it to take input again
+#[derive(Default)]
+struct Intake {
+    stalled: bool,
+    waiting: Option<Waker>,
+    written: Vec<u8>,
+}
+
+impl Intake {
+    fn set_stalled(&mut self, stalled: bool) {
+        self.stalled = stalled;
+        if let Some(waker) = self.waiting.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Mock PTY that takes no input while stalled and, when flooding, produces output right away
+struct StallingPty {
+    inner: MockPty,
+    intake: Arc<Mutex<Intake>>,
+    flood: usize,
+}
+
+impl AsyncRead for StallingPty {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        if self.flood > 0 {
+            let n = self.flood.min(buf.remaining());
+            buf.put_slice(&vec![b'x'; n]);
+            self.flood -= n;
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for StallingPty {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = &mut *self;
+        let mut intake = this.intake.lock().unwrap();
+        if intake.stalled {
+            intake.waiting = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            intake.written.extend_from_slice(&buf[..n]);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl AsyncPty for StallingPty {
+    async fn resize(&mut self, cols: u16, rows: u16) -> Result<(), PtyError> {
+        self.inner.resize(cols, rows).await
+    }
+
+    fn pid(&self) -> Option<u32> {
+        self.inner.pid()
+    }
+
+    fn is_alive(&self) -> bool {
+        self.inner.is_alive()
+    }
+
+    async fn try_wait(&mut self) -> Result<Option<PtyExitStatus>, PtyError> {
+        self.inner.try_wait().await
+    }
+
+    async fn kill(&mut self) -> Result<(), PtyError> {
+        self.inner.kill().await
+    }
+}
+
+struct StallingPtyFactory {
+    intake: Arc<Mutex<Intake>>,
+    flood: usize,
+}
+
+#[async_trait]
+impl PtyFactory for StallingPtyFactory {
+    async fn create(&self, config: &PtyConfig) -> Result<Box<dyn AsyncPty>, PtyError> {
+        Ok(Box::new(StallingPty {
+            inner: MockPty::new(config),
+            intake: self.intake.clone(),
+            flood: self.flood,
+        }))
+    }
+
+    fn name(&self) -> &'static str {
+        "stalling"
+    }
+}
+
+struct Server {
+    address: String,
+    router: Router,
+    intake: Arc<Mutex<Intake>>,
+}
+
+impl Server {
+    /// Start a server with the `stalled_input` policy whose shells produce `flood` bytes first
+    async fn start(policy: &str, flood: usize) -> Self {
+        let config = ConfigLoader::new()
+            .parse_config(&CONFIG.replace("{policy}", policy))
+            .unwrap();
+        let intake = Arc::new(Mutex::new(Intake::default()));
+        let state = AppState::new(config, Arc::new(DiagnosticsStore::new())).with_pty_factory(
+            Arc::new(StallingPtyFactory {
+                intake: intake.clone(),
+                flood,
+            }),
+        );
+        let (address, router) = common::start_server(state).await;
+        Self {
+            address,
+            router,
+            intake,
+        }
+    }
+
+    /// Attach to `/ws/:session_id` with `waylon-terminal-v1`
+    async fn attach(&self, session_id: &str) -> common::Socket {
+        let url = format!("ws://{}/ws/{}", self.address, session_id);
+        common::connect(&url, Some(Subprotocol::V1)).await
+    }
+
+    fn set_stalled(&self, stalled: bool) {
+        self.intake.lock().unwrap().set_stalled(stalled);
+    }
+
+    fn written(&self) -> Vec<u8> {
+        self.intake.lock().unwrap().written.clone()
+    }
+}
+
+/// Receive until the ack of `id`, returning the warnings received meanwhile
+async fn warnings_until_ack(socket: &mut common::Socket, id: u64) -> Vec<String> {
+    let mut warnings = Vec::new();
+    loop {
+        match common::next_envelope(socket).await {
+            ServerEnvelope::Ack { id: acked } if acked == id => return warnings,
+            ServerEnvelope::Ack { id: acked } => panic!("unexpected ack of {}", acked),
+            ServerEnvelope::Notice {
+                level: NoticeLevel::Warning,
+                message,
+            } => warnings.push(message),
+            _ => {}
+        }
+    }
+}
+
+#[tokio::test]
+async fn input_to_a_stalled_terminal_is_dropped() {
+    let server = Server::start("drop", 0).await;
+    let mut socket = server.attach("dropped").await;
+    common::envelope_output_until(&mut socket, "mock$ ").await;
+
+    server.set_stalled(true);
+    common::input(&mut socket, "lost\r", Some(1)).await;
+    common::input(&mut socket, "also lost\r", Some(2)).await;
+    // Lets the server give up on both before the terminal reads again
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    server.set_stalled(false);
+    common::input(&mut socket, "ls\r", Some(3)).await;
+
+    // Warned once, and only the input written is acknowledged
+    let warnings = warnings_until_ack(&mut socket, 3).await;
+    assert_eq!(
+        warnings,
+        ["Terminal not accepting input (process stopped?)"]
+    );
+    assert_eq!(server.written(), b"ls\r");
+}
+
+#[tokio::test]
+async fn input_to_a_stalled_terminal_is_buffered() {
+    let server = Server::start("buffer", 0).await;
+    let mut socket = server.attach("buffered").await;
+    common::envelope_output_until(&mut socket, "mock$ ").await;
+
+    server.set_stalled(true);
+    common::input(&mut socket, "one\r", None).await;
+    common::input(&mut socket, "two\r", None).await;
+    // Over `stalled_input_buffer_bytes`
+    common::input(&mut socket, "three\r", None).await;
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    server.set_stalled(false);
+    common::input(&mut socket, "ls\r", Some(4)).await;
+
+    let warnings = warnings_until_ack(&mut socket, 4).await;
+    assert_eq!(warnings.len(), 1, "{:?}", warnings);
+    assert_eq!(server.written(), b"one\rtwo\rls\r");
+}
+
+#[tokio::test]
+async fn nudge_to_a_stalled_terminal_is_given_up() {
+    let server = Server::start("buffer", 0).await;
+    let body = json!({ "nudgeOnConnect": true });
+    let session_id = common::create_session(&server.router, body).await;
+
+    server.set_stalled(true);
+    let mut socket = server.attach(&session_id).await;
+    // The session streams output instead of waiting for the terminal to take the nudge
+    common::envelope_output_until(&mut socket, "mock$ ").await;
+
+    // Not kept for the terminal like input
+    server.set_stalled(false);
+    common::input(&mut socket, "ls\r", Some(1)).await;
+    let warnings = warnings_until_ack(&mut socket, 1).await;
+    assert!(warnings.is_empty(), "{:?}", warnings);
+    assert_eq!(server.written(), b"ls\r");
+}
+
+#[tokio::test]
+async fn stalled_session_still_closes() {
+    let server = Server::start("drop", 0).await;
+    let mut socket = server.attach("closed").await;
+    common::envelope_output_until(&mut socket, "mock$ ").await;
+
+    server.set_stalled(true);
+    common::input(&mut socket, "lost\r", None).await;
+    socket.close(None).await.unwrap();
+
+    tokio::time::timeout(TIMEOUT, async {
+        while let Some(Ok(message)) = socket.next().await {
+            if let Message::Close(_) = message {
+                return;
+            }
+        }
+    })
+    .await
+    .expect("the close wasn't answered");
+}
+
+#[tokio::test]
+async fn client_not_reading_is_disconnected() {
+    let server = Server::start("drop", FLOOD_BYTES).await;
+    // Never reads the output
+    let _socket = server.attach("flooded").await;
+
+    let status = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            let request = Request::get("/api/sessions/flooded")
+                .body(Body::empty())
+                .unwrap();
+            let response = server.router.clone().oneshot(request).await.unwrap();
+            if response.status() == StatusCode::OK {
+                let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                let session: Value = serde_json::from_slice(&bytes).unwrap();
+                if session["status"] == "disconnected" {
+                    return session["status"].clone();
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("the client wasn't disconnected");
+    assert_eq!(status, "disconnected");
+
+    // The shell kept running and takes the next client
+    let mut socket = server.attach("flooded").await;
+    common::input(&mut socket, "ls\r", None).await;
+    common::envelope_output_until(&mut socket, "ls\r\nmock$ ").await;
+}
@@ -0,0 +1,143 @@
+//! `Sec-WebSocket-Protocol` negotiation: tungstenite clients offer each combination of the known
+//! subprotocols and unknown ones, and the connection behaves in the mode the server selected;
+//! offers of only unknown subprotocols are accepted in raw mode or refused with 426, depending on
+//! `reject_unknown_subprotocols`
+
+mod common;
+
+use std::sync::Arc;
+
+use futures_util::SinkExt;
+use rs_terminal::protocol::ServerEnvelope;
+use rs_terminal::pty::MockPtyFactory;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::{Error, Message};
+
+const RAW: &str = "waylon-terminal-raw";
+const V1: &str = "waylon-terminal-v1";
+
+/// Start a server on mock PTYs, returning the URL of its WebSocket endpoint
+async fn start_server(reject_unknown: bool) -> String {
+    let config = common::config(&format!("reject_unknown_subprotocols = {}", reject_unknown));
+    let state = common::state(&config).with_pty_factory(Arc::new(MockPtyFactory));
+    let (address, _) = common::start_server(state).await;
+    format!("ws://{}/ws", address)
+}
+
+/// Connect offering `offer` (no header when empty), returning the socket and the selected
+/// subprotocol
+/// The names are joined without spaces: tungstenite checks the selection against the offer split
+/// at the commas only
+async fn connect(url: &str, offer: &[&str]) -> Result<(common::Socket, Option<String>), Error> {
+    let mut request = url.into_client_request().unwrap();
+    if !offer.is_empty() {
+        request.headers_mut().insert(
+            "sec-websocket-protocol",
+            HeaderValue::from_str(&offer.join(",")).unwrap(),
+        );
+    }
+    let (socket, response) = connect_async(request).await?;
+    let selected = response
+        .headers()
+        .get("sec-websocket-protocol")
+        .map(|value| value.to_str().unwrap().to_string());
+    Ok((socket, selected))
+}
+
+/// Check that the connection speaks raw mode: output as-is, text frames written to the terminal
+async fn assert_raw(socket: &mut common::Socket) {
+    let output = common::text_until(socket, "mock$ ").await;
+    assert!(output.starts_with("mock shell"), "{:?}", output);
+
+    socket
+        .send(Message::Text("raw input".into()))
+        .await
+        .unwrap();
+    common::text_until(socket, "raw input").await;
+}
+
+/// Check that the connection speaks `waylon-terminal-v1`: a hello first, then envelopes both ways
+async fn assert_v1(socket: &mut common::Socket) {
+    let hello = common::next_envelope(socket).await;
+    assert!(matches!(hello, ServerEnvelope::Hello { .. }), "{:?}", hello);
+
+    common::input(socket, "v1 input", None).await;
+    common::numbered_output_until(socket, "v1 input").await;
+}
+
+#[tokio::test]
+async fn known_subprotocols_select_the_mode() {
+    let url = start_server(false).await;
+
+    let (mut socket, selected) = connect(&url, &[]).await.unwrap();
+    assert_eq!(selected, None);
+    assert_raw(&mut socket).await;
+
+    let (mut socket, selected) = connect(&url, &[RAW]).await.unwrap();
+    assert_eq!(selected.as_deref(), Some(RAW));
+    assert_raw(&mut socket).await;
+
+    let (mut socket, selected) = connect(&url, &[V1]).await.unwrap();
+    assert_eq!(selected.as_deref(), Some(V1));
+    assert_v1(&mut socket).await;
+}
+
+#[tokio::test]
+async fn server_preference_decides_between_offers() {
+    let url = start_server(false).await;
+
+    for offer in [[RAW, V1], [V1, RAW]] {
+        let (mut socket, selected) = connect(&url, &offer).await.unwrap();
+        assert_eq!(selected.as_deref(), Some(V1), "{:?}", offer);
+        assert_v1(&mut socket).await;
+    }
+}
+
+#[tokio::test]
+async fn unknown_subprotocols_next_to_known_ones_are_ignored() {
+    for reject_unknown in [false, true] {
+        let url = start_server(reject_unknown).await;
+
+        let (mut socket, selected) = connect(&url, &["chat", V1]).await.unwrap();
+        assert_eq!(selected.as_deref(), Some(V1));
+        assert_v1(&mut socket).await;
+
+        let (mut socket, selected) = connect(&url, &[RAW, "chat"]).await.unwrap();
+        assert_eq!(selected.as_deref(), Some(RAW));
+        assert_raw(&mut socket).await;
+    }
+}
+
+#[tokio::test]
+async fn only_unknown_subprotocols_use_raw_mode_by_default() {
+    let url = start_server(false).await;
+
+    // The first offer is echoed back, clients fail the handshake without a selection
+    let (mut socket, selected) = connect(&url, &["chat", "superchat"]).await.unwrap();
+    assert_eq!(selected.as_deref(), Some("chat"));
+    assert_raw(&mut socket).await;
+}
+
+#[tokio::test]
+async fn only_unknown_subprotocols_are_rejected_when_configured() {
+    let url = start_server(true).await;
+
+    match connect(&url, &["chat"]).await {
+        Err(Error::Http(response)) => {
+            assert_eq!(response.status(), 426);
+            assert_eq!(
+                response.headers()["sec-websocket-protocol"],
+                format!("{}, {}", V1, RAW)
+            );
+        }
+        Ok(_) => panic!("client offering only unknown subprotocols was accepted"),
+        Err(e) => panic!("unexpected error: {}", e),
+    }
+
+    // Clients offering nothing still get raw mode
+    let (mut socket, selected) = connect(&url, &[]).await.unwrap();
+    assert_eq!(selected, None);
+    assert_raw(&mut socket).await;
+}
@@ -0,0 +1,60 @@
+//! Supervised tasks (the WebTransport server runs as one) are restarted when they fail or panic
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use rs_terminal::server::{RestartPolicy, TaskError, supervise};
+
+/// Restarts quickly, so the tests don't wait out the production backoff
+fn policy(max_restarts: u32) -> RestartPolicy {
+    RestartPolicy {
+        max_restarts,
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(20),
+        stable_uptime: Duration::from_secs(60),
+    }
+}
+
+/// Supervise a task that fails the way `fail` does on its first `failures` runs, then completes.
+/// Returns the supervisor's result and how often the task was started.
+async fn run(
+    max_restarts: u32,
+    failures: u32,
+    fail: fn() -> Result<(), TaskError>,
+) -> (Result<(), TaskError>, u32) {
+    let starts = Arc::new(AtomicU32::new(0));
+    let result = supervise("test task", policy(max_restarts), || {
+        let starts = starts.clone();
+        async move {
+            if starts.fetch_add(1, Ordering::SeqCst) < failures {
+                fail()
+            } else {
+                Ok(())
+            }
+        }
+    })
+    .await;
+    (result, starts.load(Ordering::SeqCst))
+}
+
+#[tokio::test]
+async fn failed_task_is_restarted() {
+    let (result, starts) = run(5, 1, || Err("listener closed".into())).await;
+    assert!(result.is_ok());
+    assert_eq!(starts, 2);
+}
+
+#[tokio::test]
+async fn panicked_task_is_restarted() {
+    let (result, starts) = run(5, 1, || panic!("server task panicked")).await;
+    assert!(result.is_ok());
+    assert_eq!(starts, 2);
+}
+
+#[tokio::test]
+async fn supervisor_gives_up_after_the_restart_limit() {
+    let (result, starts) = run(2, u32::MAX, || Err("listener closed".into())).await;
+    assert_eq!(result.unwrap_err().to_string(), "listener closed");
+    assert_eq!(starts, 3);
+}
@@ -0,0 +1,99 @@
+//! Sessions report the color depth and unicode support derived from their shell's `TERM`,
+//! `COLORTERM`, `NO_COLOR` and locale variables, both when created through the REST API and once
+//! a client attached
+
+mod common;
+
+use std::sync::Arc;
+
+use axum::Router;
+use axum::http::StatusCode;
+use rs_terminal::app_state::AppState;
+use rs_terminal::pty::MockPtyFactory;
+use rs_terminal::server::build_router;
+use serde_json::{Value, json};
+
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "truecolor"
+session_timeout = 1800000
+nudge_on_connect = false
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+
+[shells.truecolor]
+command = ["sh"]
+environment = { TERM = "xterm-256color", COLORTERM = "truecolor", LANG = "C.UTF-8" }
+
+[shells.palette]
+command = ["sh"]
+environment = { TERM = "xterm-256color", LANG = "en_US.ISO-8859-1" }
+
+[shells.basic]
+command = ["sh"]
+environment = { TERM = "vt100", LC_ALL = "en_US.utf8" }
+
+[shells.dumb]
+command = ["sh"]
+environment = { TERM = "dumb" }
+"#;
+
+fn state() -> AppState {
+    common::state(CONFIG).with_pty_factory(Arc::new(MockPtyFactory))
+}
+
+/// Create a session with `request` and return its `colorDepth` and `unicode`
+async fn capabilities(router: &Router, request: Value) -> (String, bool) {
+    let (status, session) = common::call(router, "POST", "/api/sessions", Some(request)).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", session);
+    (
+        session["colorDepth"].as_str().unwrap().to_string(),
+        session["unicode"].as_bool().unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn capabilities_follow_the_shell_environment() {
+    let router = build_router(state());
+
+    for (shell_type, color_depth, unicode) in [
+        ("truecolor", "truecolor", true),
+        ("palette", "ansi256", false),
+        ("basic", "ansi16", true),
+        ("dumb", "monochrome", false),
+    ] {
+        assert_eq!(
+            capabilities(&router, json!({ "shellType": shell_type })).await,
+            (color_depth.to_string(), unicode),
+            "{}",
+            shell_type
+        );
+    }
+}
+
+#[tokio::test]
+async fn terminal_profile_overrides_the_color_depth() {
+    let router = build_router(state());
+    let request = json!({ "shellType": "truecolor", "terminalProfile": "no-color" });
+    assert_eq!(
+        capabilities(&router, request).await,
+        ("monochrome".to_string(), true)
+    );
+}
+
+#[tokio::test]
+async fn attached_session_reports_its_capabilities() {
+    let state = state();
+    let router = build_router(state.clone());
+
+    let mut client = common::attach(&state, "attached");
+    // The first output means the shell was started
+    common::started(&mut client).await;
+
+    let (status, session) = common::call(&router, "GET", "/api/sessions/attached", None).await;
+    assert_eq!(status, StatusCode::OK, "{}", session);
+    assert_eq!(session["colorDepth"], "truecolor");
+    assert_eq!(session["unicode"], true);
+}
@@ -0,0 +1,117 @@
+//! Terminal profiles: `no-color` and `dumb` sessions get a matching `TERM`, and escape sequences
+//! are filtered out of their output (colors only, or all of them); the profile is chosen when the
+//! session is created or when a client attaches
+
+mod common;
+
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use futures_util::StreamExt;
+use rs_terminal::app_state::AppState;
+use rs_terminal::protocol::ServerEnvelope;
+use rs_terminal::server::build_router;
+use serde_json::{Value, json};
+use tokio_tungstenite::tungstenite::Message;
+
+/// The shell prints its terminal variables, then colored output with a title and a line erase
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "colors"
+session_timeout = 1800000
+nudge_on_connect = false
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+
+[shells.colors]
+command = ["sh", "-c", '''printf '%s|%s|%s\n' "$TERM" "$COLORTERM" "$NO_COLOR"; printf '\033[1;31mred\033[0m \033]0;title\007\033[2Kplain\n' ''']
+environment = { TERM = "xterm-256color", COLORTERM = "truecolor" }
+"#;
+
+/// Longest wait for the shell to finish
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What the shell prints with the `full` profile
+const FULL: &str =
+    "xterm-256color|truecolor|\r\n\x1b[1;31mred\x1b[0m \x1b]0;title\x07\x1b[2Kplain\r\n";
+
+/// What the shell prints with the `no-color` profile: the title and the erase are kept
+const NO_COLOR: &str = "vt100||1\r\nred \x1b]0;title\x07\x1b[2Kplain\r\n";
+
+/// What the shell prints with the `dumb` profile: plain text only
+const DUMB: &str = "dumb||1\r\nred plain\r\n";
+
+fn state() -> AppState {
+    common::state(CONFIG)
+}
+
+/// Create a session with the terminal profile `profile`, returning the session
+async fn create_session(state: &AppState, profile: Option<&str>) -> Value {
+    let body = match profile {
+        Some(profile) => json!({ "terminalProfile": profile }),
+        None => json!({}),
+    };
+    let router = build_router(state.clone());
+    let (status, session) = common::call(&router, "POST", "/api/sessions", Some(body)).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", session);
+    session
+}
+
+/// Attach to the session and return all output until the shell exits
+async fn session_output(state: &AppState, session_id: &str) -> String {
+    let mut client = common::attach(state, session_id);
+    let mut output = String::new();
+    tokio::time::timeout(TIMEOUT, async {
+        while let Some(message) = client.receive().await {
+            if let ServerEnvelope::Output { data, .. } = message.unwrap() {
+                output.push_str(&data);
+            }
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("the shell didn't exit: {:?}", output));
+    output
+}
+
+#[tokio::test]
+async fn output_is_filtered_by_the_session_profile() {
+    let state = state();
+    for (profile, expected) in [
+        (None, FULL),
+        (Some("full"), FULL),
+        (Some("no-color"), NO_COLOR),
+        (Some("dumb"), DUMB),
+    ] {
+        let session = create_session(&state, profile).await;
+        assert_eq!(session["terminalProfile"], profile.unwrap_or("full"));
+        let output = session_output(&state, session["id"].as_str().unwrap()).await;
+        assert_eq!(output, expected, "{:?}", profile);
+    }
+}
+
+#[tokio::test]
+async fn attaching_client_chooses_its_profile() {
+    let state = state();
+    let session = create_session(&state, None).await;
+    let (address, _) = common::start_server(state).await;
+
+    let url = format!(
+        "ws://{}/ws/{}?terminal_profile=dumb",
+        address,
+        session["id"].as_str().unwrap()
+    );
+    let mut socket = common::connect(&url, None).await;
+    let mut output = String::new();
+    tokio::time::timeout(TIMEOUT, async {
+        while let Some(Ok(message)) = socket.next().await {
+            if let Message::Text(text) = message {
+                output.push_str(&text);
+            }
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("the shell didn't exit: {:?}", output));
+    assert!(output.starts_with(DUMB), "{:?}", output);
+}
@@ -0,0 +1,177 @@
+//! Sending on a WebSocket connection: frames of concurrent senders never interleave, terminal
+//! output above `max_message_bytes` is split into several frames and other `waylon-terminal-v1`
+//! envelopes are never split into invalid JSON; a client message above the limit closes the
+//! connection with 1009
+
+mod common;
+
+use std::time::Duration;
+
+use axum::Router;
+use axum::extract::WebSocketUpgrade;
+use axum::extract::ws::Message as AxumMessage;
+use axum::routing::get;
+use futures_util::{SinkExt, StreamExt};
+use rs_terminal::protocol::{ServerEnvelope, Subprotocol, WebSocketConnection};
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+
+const LIMIT: usize = 256;
+
+/// Longest wait for the connection to end
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Start a server whose default shell runs `command`
+async fn start_server(command: &str) -> String {
+    let config = common::shell_config(&format!("max_message_bytes = {}", LIMIT), command);
+    let (address, _) = common::start_server(common::state(&config)).await;
+    format!("ws://{}/ws", address)
+}
+
+/// Connect, offering `subprotocol`, and collect the text frames until the connection ends
+async fn text_frames(url: &str, subprotocol: Option<Subprotocol>) -> Vec<String> {
+    let mut socket = common::connect(url, subprotocol).await;
+    let mut frames = Vec::new();
+    tokio::time::timeout(TIMEOUT, async {
+        while let Some(Ok(message)) = socket.next().await {
+            if let Message::Text(text) = message {
+                frames.push(text);
+            }
+        }
+    })
+    .await
+    .expect("the connection wasn't closed");
+    frames
+}
+
+#[tokio::test]
+async fn raw_output_is_split_into_frames() {
+    let url = start_server(r#"["sh", "-c", "stty -echo; printf '%0600d' 0; sleep 1"]"#).await;
+    let frames = text_frames(&url, Some(Subprotocol::Raw)).await;
+    assert!(
+        frames.iter().all(|frame| frame.len() <= LIMIT),
+        "{:?}",
+        frames
+    );
+    assert!(frames.concat().contains(&"0".repeat(600)), "{:?}", frames);
+}
+
+#[tokio::test]
+async fn oversized_envelopes_are_not_split() {
+    // The shell exits right away; the error naming its long command exceeds the limit
+    let command = format!(r#"["sh", "-c", "exit 3", "{}"]"#, "x".repeat(LIMIT));
+    let url = start_server(&command).await;
+    let frames = text_frames(&url, Some(Subprotocol::V1)).await;
+    for frame in &frames {
+        assert!(frame.len() <= LIMIT, "{:?}", frame);
+        let envelope: Result<ServerEnvelope, _> = serde_json::from_str(frame);
+        assert!(envelope.is_ok(), "not an envelope: {:?}", frame);
+        assert!(
+            !matches!(envelope, Ok(ServerEnvelope::Error { .. })),
+            "{:?}",
+            frame
+        );
+    }
+}
+
+#[tokio::test]
+async fn oversized_input_closes_with_1009() {
+    let url = start_server(r#"["sh", "-c", "sleep 5"]"#).await;
+    let mut socket = common::connect(&url, None).await;
+    socket
+        .send(Message::Text("x".repeat(LIMIT * 4)))
+        .await
+        .unwrap();
+
+    let mut error = None;
+    let close = tokio::time::timeout(TIMEOUT, async {
+        while let Some(message) = socket.next().await {
+            match message.unwrap() {
+                Message::Text(text) if text.starts_with("Error:") => error = Some(text),
+                Message::Close(frame) => return frame,
+                _ => {}
+            }
+        }
+        None
+    })
+    .await
+    .expect("the connection wasn't closed")
+    .expect("no close frame");
+    assert_eq!(close.code, CloseCode::Size);
+    assert_eq!(
+        error.as_deref(),
+        Some("Error: Message exceeds the size limit of 256 bytes")
+    );
+}
+
+/// Batches each of the two senders sends
+const BATCHES: usize = 200;
+
+/// Frames per batch
+const BATCH_FRAMES: usize = 5;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_senders_do_not_interleave() {
+    // Two tasks share the connection's sender, like the session loop and a ping task
+    let app = Router::new().route(
+        "/ws",
+        get(|upgrade: WebSocketUpgrade| async move {
+            upgrade.on_upgrade(|socket| async move {
+                let connection = WebSocketConnection::new(
+                    socket,
+                    "concurrent".to_string(),
+                    1024,
+                    Duration::from_secs(5),
+                );
+                let senders = ["a", "b"].map(|name| {
+                    let sender = connection.sender.clone();
+                    tokio::spawn(async move {
+                        for batch in 0..BATCHES {
+                            let frames = (0..BATCH_FRAMES)
+                                .map(|frame| {
+                                    AxumMessage::Text(format!("{}-{}-{}", name, batch, frame))
+                                })
+                                .collect();
+                            sender.send_all(frames).await.unwrap();
+                        }
+                    })
+                });
+                for sender in senders {
+                    sender.await.unwrap();
+                }
+                connection
+                    .sender
+                    .send(AxumMessage::Close(None))
+                    .await
+                    .unwrap();
+            })
+        }),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let frames = text_frames(&url, None).await;
+    assert_eq!(frames.len(), 2 * BATCHES * BATCH_FRAMES);
+    // Every batch arrives whole, in order, and each sender's batches in the order sent
+    let mut next_batch = [0, 0];
+    for batch in frames.chunks(BATCH_FRAMES) {
+        let (name, number) = {
+            let mut parts = batch[0].split('-');
+            (parts.next().unwrap(), parts.next().unwrap().to_string())
+        };
+        let sender = usize::from(name == "b");
+        assert_eq!(number, next_batch[sender].to_string(), "{:?}", batch);
+        next_batch[sender] += 1;
+        for (frame, text) in batch.iter().enumerate() {
+            assert_eq!(
+                *text,
+                format!("{}-{}-{}", name, number, frame),
+                "{:?}",
+                batch
+            );
+        }
+    }
+    assert_eq!(next_batch, [BATCHES, BATCHES]);
+}
@@ -0,0 +1,633 @@
+//! `websocket_permessage_deflate` accepts a client's permessage-deflate offer in the upgrade and
+//! compresses the messages both ways; without the flag, or for offers the server can't honour, the
+//! frames stay uncompressed
+//! `DeflateParams` and `DeflateStream` are also checked on their own: offer parsing, fragmented
+//! compressed messages with control frames in between, and messages above the size limit
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::{HeaderMap, HeaderValue, header};
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress};
+use futures_util::{SinkExt, StreamExt};
+use rs_terminal::protocol::{DeflateParams, DeflateStream};
+use rs_terminal::pty::MockPtyFactory;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio::net::TcpStream;
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::protocol::{Role, WebSocketConfig};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+
+/// Empty stored block ending every compressed message, left out on the wire
+const MESSAGE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Longest wait for a frame of the server
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn config(deflate: bool) -> String {
+    format!(
+        r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+nudge_on_connect = false
+max_message_bytes = 1024
+websocket_permessage_deflate = {}
+
+[default_shell_config]
+size = {{ columns = 80, rows = 24 }}
+working_directory = "."
+
+[shells.sh]
+command = ["sh"]
+"#,
+        deflate
+    )
+}
+
+/// Frame received from the server
+#[derive(Debug)]
+struct Frame {
+    compressed: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+/// Raw WebSocket client, speaking permessage-deflate itself
+struct Client {
+    stream: TcpStream,
+    /// Response head of the upgrade, lowercase
+    response: String,
+    compress: Compress,
+    decompress: Decompress,
+    /// Inflate every message on its own, as agreed with `server_no_context_takeover`
+    no_context_takeover: bool,
+}
+
+impl Client {
+    /// Start a server on a mock PTY and upgrade `/ws` offering `extensions`
+    async fn connect(deflate: bool, extensions: Option<&str>) -> Self {
+        let state = common::state(&config(deflate)).with_pty_factory(Arc::new(MockPtyFactory));
+        let (address, _) = common::start_server(state).await;
+
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        let mut request = format!(
+            "GET /ws HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n",
+            address
+        );
+        if let Some(extensions) = extensions {
+            request.push_str(&format!("Sec-WebSocket-Extensions: {}\r\n", extensions));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        // Read the head byte by byte, the frames after it stay in the stream
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(stream.read_u8().await.unwrap());
+        }
+        let response = String::from_utf8(head).unwrap().to_lowercase();
+        assert!(response.starts_with("http/1.1 101"), "{}", response);
+        Self {
+            stream,
+            response,
+            compress: Compress::new(Compression::default(), false),
+            decompress: Decompress::new(false),
+            no_context_takeover: false,
+        }
+    }
+
+    /// `Sec-WebSocket-Extensions` of the upgrade response
+    fn extensions(&self) -> Option<&str> {
+        self.response
+            .lines()
+            .find_map(|line| line.strip_prefix("sec-websocket-extensions:"))
+            .map(str::trim)
+    }
+
+    /// Send a masked text frame, compressed or not
+    async fn send_text(&mut self, text: &str, compressed: bool) {
+        let mut payload = text.as_bytes().to_vec();
+        if compressed {
+            let mut output = Vec::with_capacity(payload.len() + 64);
+            self.compress
+                .compress_vec(&payload, &mut output, FlushCompress::Sync)
+                .unwrap();
+            assert!(output.ends_with(&MESSAGE_TAIL));
+            output.truncate(output.len() - MESSAGE_TAIL.len());
+            payload = output;
+        }
+
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        let mut frame = vec![if compressed { 0xc1 } else { 0x81 }];
+        match payload.len() {
+            len @ 0..=125 => frame.push(0x80 | len as u8),
+            len => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(&mask);
+        frame.extend(
+            payload
+                .iter()
+                .enumerate()
+                .map(|(index, byte)| byte ^ mask[index % 4]),
+        );
+        self.stream.write_all(&frame).await.unwrap();
+    }
+
+    /// Receive the next frame, with the payload of a compressed one inflated
+    async fn receive(&mut self) -> Frame {
+        tokio::time::timeout(TIMEOUT, async {
+            let first = self.stream.read_u8().await.unwrap();
+            let second = self.stream.read_u8().await.unwrap();
+            assert_eq!(second & 0x80, 0, "server frames are unmasked");
+            let len = match second & 0x7f {
+                126 => self.stream.read_u16().await.unwrap() as usize,
+                127 => self.stream.read_u64().await.unwrap() as usize,
+                len => len as usize,
+            };
+            let mut payload = vec![0; len];
+            self.stream.read_exact(&mut payload).await.unwrap();
+
+            let compressed = first & 0x40 != 0;
+            if compressed {
+                payload.extend_from_slice(&MESSAGE_TAIL);
+                let mut inflated = Vec::with_capacity(64 * 1024);
+                self.decompress
+                    .decompress_vec(&payload, &mut inflated, FlushDecompress::Sync)
+                    .unwrap();
+                payload = inflated;
+                if self.no_context_takeover {
+                    self.decompress.reset(false);
+                }
+            }
+            Frame {
+                compressed,
+                opcode: first & 0x0f,
+                payload,
+            }
+        })
+        .await
+        .expect("no frame from the server")
+    }
+
+    /// Receive text frames until their text ends with `expected`, returning them
+    async fn text_until(&mut self, expected: &str) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        let mut text = String::new();
+        while !text.ends_with(expected) {
+            let frame = self.receive().await;
+            assert_eq!(frame.opcode, 0x1, "{:?} before {:?}", frame, expected);
+            text.push_str(std::str::from_utf8(&frame.payload).unwrap());
+            frames.push(frame);
+        }
+        frames
+    }
+}
+
+#[tokio::test]
+async fn negotiated_extension_compresses_both_ways() {
+    let mut client =
+        Client::connect(true, Some("permessage-deflate; client_max_window_bits")).await;
+    assert_eq!(client.extensions(), Some("permessage-deflate"));
+
+    let prompt = client.text_until("mock$ ").await;
+    assert!(prompt.iter().all(|frame| frame.compressed));
+
+    // The mock PTY echoes the inflated input, compressed again on the way back
+    client.send_text("hello deflate", true).await;
+    let echo = client.text_until("hello deflate").await;
+    assert!(echo.iter().all(|frame| frame.compressed));
+
+    // Messages above 125 bytes use the extended lengths on both sides
+    let long = "0123456789".repeat(90);
+    client.send_text(&long, true).await;
+    let echo = client.text_until(&long).await;
+    assert!(echo.iter().all(|frame| frame.compressed));
+
+    // Compression is per message, an uncompressed one is read as well
+    client.send_text(" plain", false).await;
+    client.text_until(" plain").await;
+}
+
+#[tokio::test]
+async fn server_no_context_takeover_is_honoured() {
+    let mut client =
+        Client::connect(true, Some("permessage-deflate; server_no_context_takeover")).await;
+    assert_eq!(
+        client.extensions(),
+        Some("permessage-deflate; server_no_context_takeover")
+    );
+
+    // Every message can be inflated on its own, also when it repeats an earlier one
+    client.no_context_takeover = true;
+    client.text_until("mock$ ").await;
+    for _ in 0..2 {
+        client.send_text("again", false).await;
+        let frames = client.text_until("again").await;
+        assert!(frames.iter().all(|frame| frame.compressed));
+    }
+}
+
+#[tokio::test]
+async fn disabled_flag_ignores_the_offer() {
+    let mut client = Client::connect(false, Some("permessage-deflate")).await;
+    assert_eq!(client.extensions(), None);
+
+    let prompt = client.text_until("mock$ ").await;
+    assert!(prompt.iter().all(|frame| !frame.compressed));
+}
+
+#[tokio::test]
+async fn unsupported_offers_are_declined() {
+    for offer in [
+        "permessage-deflate; server_max_window_bits=10",
+        "permessage-deflate; client_no_context_takeover; client_no_context_takeover",
+        "permessage-deflate; unknown_parameter",
+        "x-webkit-deflate-frame",
+    ] {
+        let mut client = Client::connect(true, Some(offer)).await;
+        assert_eq!(client.extensions(), None, "{}", offer);
+        let prompt = client.text_until("mock$ ").await;
+        assert!(prompt.iter().all(|frame| !frame.compressed));
+    }
+
+    // The first offer the server can honour is accepted
+    let client = Client::connect(
+        true,
+        Some("permessage-deflate; server_max_window_bits=10, permessage-deflate"),
+    )
+    .await;
+    assert_eq!(client.extensions(), Some("permessage-deflate"));
+}
+
+#[tokio::test]
+async fn inflated_message_above_the_limit_closes_with_1009() {
+    let mut client = Client::connect(true, Some("permessage-deflate")).await;
+    client.text_until("mock$ ").await;
+
+    // 64 KiB of input compress to a few hundred bytes, far below the 1 KiB limit
+    client.send_text(&"a".repeat(64 * 1024), true).await;
+    loop {
+        let frame = client.receive().await;
+        if frame.opcode == 0x8 {
+            assert_eq!(
+                u16::from_be_bytes([frame.payload[0], frame.payload[1]]),
+                1009
+            );
+            break;
+        }
+    }
+}
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_PING: u8 = 0x9;
+
+/// Parameters the server agrees to for `Sec-WebSocket-Extensions` headers with these values
+fn negotiate(values: &[&str]) -> Option<DeflateParams> {
+    let mut headers = HeaderMap::new();
+    for value in values {
+        headers.append(
+            header::SEC_WEBSOCKET_EXTENSIONS,
+            HeaderValue::from_str(value).unwrap(),
+        );
+    }
+    DeflateParams::negotiate(&headers)
+}
+
+const CONTEXT_TAKEOVER: Option<DeflateParams> = Some(DeflateParams {
+    server_no_context_takeover: false,
+});
+const NO_CONTEXT_TAKEOVER: Option<DeflateParams> = Some(DeflateParams {
+    server_no_context_takeover: true,
+});
+
+#[test]
+fn offers_naming_a_parameter_twice_are_declined() {
+    for offer in [
+        "permessage-deflate; server_no_context_takeover; server_no_context_takeover",
+        "permessage-deflate; client_max_window_bits; client_max_window_bits=10",
+        "permessage-deflate; server_max_window_bits=15; server_max_window_bits=15",
+    ] {
+        assert_eq!(negotiate(&[offer]), None, "{}", offer);
+    }
+}
+
+#[test]
+fn server_window_bits_other_than_15_are_declined() {
+    assert_eq!(
+        negotiate(&["permessage-deflate; server_max_window_bits=15"]),
+        CONTEXT_TAKEOVER
+    );
+    for bits in ["8", "10", "14", "16", ""] {
+        let offer = format!("permessage-deflate; server_max_window_bits={}", bits);
+        assert_eq!(negotiate(&[&offer]), None, "{}", offer);
+    }
+    // The parameter needs a value
+    assert_eq!(
+        negotiate(&["permessage-deflate; server_max_window_bits"]),
+        None
+    );
+}
+
+#[test]
+fn quoted_values_are_unquoted() {
+    assert_eq!(
+        negotiate(&["permessage-deflate; server_max_window_bits=\"15\""]),
+        CONTEXT_TAKEOVER
+    );
+    assert_eq!(
+        negotiate(&["permessage-deflate; client_max_window_bits = \"10\""]),
+        CONTEXT_TAKEOVER
+    );
+    for bits in ["\"7\"", "\"16\"", "\"ten\""] {
+        let offer = format!("permessage-deflate; client_max_window_bits={}", bits);
+        assert_eq!(negotiate(&[&offer]), None, "{}", offer);
+    }
+    // Flags don't take values, quoted or not
+    assert_eq!(
+        negotiate(&["permessage-deflate; server_no_context_takeover=\"true\""]),
+        None
+    );
+}
+
+#[test]
+fn first_acceptable_offer_of_any_header_wins() {
+    assert_eq!(
+        negotiate(&["PerMessage-Deflate; server_no_context_takeover"]),
+        NO_CONTEXT_TAKEOVER
+    );
+    assert_eq!(
+        negotiate(&[
+            "x-webkit-deflate-frame",
+            "permessage-deflate; server_max_window_bits=9, permessage-deflate; server_no_context_takeover",
+            "permessage-deflate",
+        ]),
+        NO_CONTEXT_TAKEOVER
+    );
+    assert_eq!(negotiate(&[]), None);
+}
+
+/// A masked client frame
+fn client_frame(fin: bool, compressed: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mask = [0x37, 0xfa, 0x21, 0x3d];
+    let mut frame = vec![if fin { 0x80 } else { 0 } | if compressed { 0x40 } else { 0 } | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(0x80 | len as u8),
+        len => {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(&mask);
+    frame.extend(
+        payload
+            .iter()
+            .enumerate()
+            .map(|(index, byte)| byte ^ mask[index % 4]),
+    );
+    frame
+}
+
+/// Compress a message as a client does, continuing the context of `compress`
+fn compress_message(compress: &mut Compress, data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len() + 64);
+    compress
+        .compress_vec(data, &mut output, FlushCompress::Sync)
+        .unwrap();
+    assert!(output.ends_with(&MESSAGE_TAIL));
+    output.truncate(output.len() - MESSAGE_TAIL.len());
+    output
+}
+
+/// Server side WebSocket over a `DeflateStream` and the raw client end of the connection
+async fn deflate_pair(
+    params: DeflateParams,
+    limit: usize,
+) -> (WebSocketStream<DeflateStream<DuplexStream>>, DuplexStream) {
+    let (client, server) = tokio::io::duplex(256 * 1024);
+    let config = WebSocketConfig {
+        max_message_size: Some(limit),
+        max_frame_size: Some(limit),
+        ..Default::default()
+    };
+    let server = WebSocketStream::from_raw_socket(
+        DeflateStream::new(server, params, limit),
+        Role::Server,
+        Some(config),
+    )
+    .await;
+    (server, client)
+}
+
+/// Next message the server reads
+async fn next_message(
+    server: &mut WebSocketStream<DeflateStream<DuplexStream>>,
+) -> Result<Message, WsError> {
+    tokio::time::timeout(TIMEOUT, server.next())
+        .await
+        .expect("no message")
+        .expect("connection ended")
+}
+
+/// Read an unmasked server frame from the raw client end: (RSV1, opcode, payload)
+async fn read_server_frame(client: &mut DuplexStream) -> (bool, u8, Vec<u8>) {
+    let first = client.read_u8().await.unwrap();
+    let len = match client.read_u8().await.unwrap() {
+        126 => client.read_u16().await.unwrap() as usize,
+        127 => client.read_u64().await.unwrap() as usize,
+        len => len as usize,
+    };
+    let mut payload = vec![0; len];
+    client.read_exact(&mut payload).await.unwrap();
+    (first & 0x40 != 0, first & 0x0f, payload)
+}
+
+/// Inflate a server message on its own, with no earlier context
+fn inflate_alone(payload: &[u8]) -> String {
+    let mut payload = payload.to_vec();
+    payload.extend_from_slice(&MESSAGE_TAIL);
+    let mut inflated = Vec::with_capacity(64 * 1024);
+    Decompress::new(false)
+        .decompress_vec(&payload, &mut inflated, FlushDecompress::Sync)
+        .unwrap();
+    String::from_utf8(inflated).unwrap()
+}
+
+#[tokio::test]
+async fn fragmented_compressed_message_with_control_frames_in_between() {
+    let (mut server, mut client) = deflate_pair(CONTEXT_TAKEOVER.unwrap(), 1024).await;
+    let mut compress = Compress::new(Compression::default(), false);
+
+    let message = compress_message(&mut compress, b"fragmented message, compressed once");
+    // The second message is compressed with the context of the first
+    let second = compress_message(&mut compress, b"fragmented message, again");
+    let quarter = message.len() / 4;
+    let fragments: Vec<&[u8]> = vec![
+        &message[..quarter],
+        &message[quarter..2 * quarter],
+        &message[2 * quarter..3 * quarter],
+        &message[3 * quarter..],
+    ];
+
+    let mut frames = client_frame(false, true, OPCODE_TEXT, fragments[0]);
+    frames.extend(client_frame(true, false, OPCODE_PING, b"mid-message"));
+    frames.extend(client_frame(
+        false,
+        false,
+        OPCODE_CONTINUATION,
+        fragments[1],
+    ));
+    frames.extend(client_frame(true, false, OPCODE_PING, b"again"));
+    frames.extend(client_frame(
+        false,
+        false,
+        OPCODE_CONTINUATION,
+        fragments[2],
+    ));
+    frames.extend(client_frame(true, false, OPCODE_CONTINUATION, fragments[3]));
+    frames.extend(client_frame(true, true, OPCODE_TEXT, &second));
+    client.write_all(&frames).await.unwrap();
+
+    // Control frames pass through as soon as they arrive, the message follows once complete
+    let mut messages = Vec::new();
+    for _ in 0..4 {
+        messages.push(next_message(&mut server).await.unwrap());
+    }
+    assert_eq!(
+        messages,
+        [
+            Message::Ping(b"mid-message".to_vec()),
+            Message::Ping(b"again".to_vec()),
+            Message::Text("fragmented message, compressed once".to_string()),
+            Message::Text("fragmented message, again".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn data_frame_inside_a_fragmented_message_is_an_error() {
+    let (mut server, mut client) = deflate_pair(CONTEXT_TAKEOVER.unwrap(), 1024).await;
+    let mut compress = Compress::new(Compression::default(), false);
+    let payload = compress_message(&mut compress, b"interrupted");
+
+    let mut frames = client_frame(false, true, OPCODE_TEXT, &payload);
+    frames.extend(client_frame(true, false, OPCODE_TEXT, b"new message"));
+    client.write_all(&frames).await.unwrap();
+
+    assert!(matches!(
+        next_message(&mut server).await,
+        Err(WsError::Io(_))
+    ));
+}
+
+#[tokio::test]
+async fn fragments_adding_up_above_the_limit_are_refused() {
+    let (mut server, mut client) = deflate_pair(CONTEXT_TAKEOVER.unwrap(), 64).await;
+
+    // Each fragment fits, together they don't; nothing is inflated before the last one
+    let mut frames = client_frame(false, true, OPCODE_TEXT, &[0x55; 40]);
+    frames.extend(client_frame(false, false, OPCODE_CONTINUATION, &[0x55; 40]));
+    client.write_all(&frames).await.unwrap();
+
+    assert!(matches!(
+        next_message(&mut server).await,
+        Err(WsError::Capacity(_))
+    ));
+}
+
+#[tokio::test]
+async fn declared_length_above_the_limit_is_refused_before_it_arrives() {
+    let (mut server, mut client) = deflate_pair(CONTEXT_TAKEOVER.unwrap(), 64).await;
+
+    // A header announcing u64::MAX bytes, without any of them
+    let mut header = vec![0x80 | 0x40 | OPCODE_TEXT, 0x80 | 127];
+    header.extend_from_slice(&u64::MAX.to_be_bytes());
+    header.extend_from_slice(&[0; 4]);
+    client.write_all(&header).await.unwrap();
+
+    assert!(matches!(
+        next_message(&mut server).await,
+        Err(WsError::Capacity(_))
+    ));
+}
+
+#[tokio::test]
+async fn message_inflating_above_the_limit_is_refused() {
+    let (mut server, mut client) = deflate_pair(CONTEXT_TAKEOVER.unwrap(), 64).await;
+    let mut compress = Compress::new(Compression::default(), false);
+    let payload = compress_message(&mut compress, &[b'a'; 4096]);
+    assert!(payload.len() < 64);
+
+    client
+        .write_all(&client_frame(true, true, OPCODE_TEXT, &payload))
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        next_message(&mut server).await,
+        Err(WsError::Capacity(_))
+    ));
+}
+
+#[tokio::test]
+async fn round_trip_without_server_context_takeover() {
+    let (mut server, mut client) = deflate_pair(NO_CONTEXT_TAKEOVER.unwrap(), 1024).await;
+    let mut compress = Compress::new(Compression::default(), false);
+
+    // The client keeps its own context, only the server's is reset
+    for text in ["repeated message", "repeated message"] {
+        let payload = compress_message(&mut compress, text.as_bytes());
+        client
+            .write_all(&client_frame(true, true, OPCODE_TEXT, &payload))
+            .await
+            .unwrap();
+        assert_eq!(
+            next_message(&mut server).await.unwrap(),
+            Message::Text(text.to_string())
+        );
+    }
+
+    // Equal messages compress to equal frames, each inflating without the ones before it
+    server
+        .send(Message::Text("server says hi".into()))
+        .await
+        .unwrap();
+    server
+        .send(Message::Text("server says hi".into()))
+        .await
+        .unwrap();
+    let first = read_server_frame(&mut client).await;
+    let second = read_server_frame(&mut client).await;
+    assert_eq!(first, second);
+    let (compressed, opcode, payload) = first;
+    assert!(compressed);
+    assert_eq!(opcode, OPCODE_TEXT);
+    assert_eq!(inflate_alone(&payload), "server says hi");
+}
+
+#[tokio::test]
+async fn server_context_is_kept_by_default() {
+    let (mut server, mut client) = deflate_pair(CONTEXT_TAKEOVER.unwrap(), 1024).await;
+
+    server
+        .send(Message::Text("server says hi".into()))
+        .await
+        .unwrap();
+    server
+        .send(Message::Text("server says hi".into()))
+        .await
+        .unwrap();
+    let (_, _, first) = read_server_frame(&mut client).await;
+    let (_, _, second) = read_server_frame(&mut client).await;
+
+    // The repeat refers back to the first message and comes out shorter
+    assert!(second.len() < first.len(), "{:?} {:?}", first, second);
+    assert_eq!(inflate_alone(&first), "server says hi");
+}
@@ -0,0 +1,128 @@
+//! WebTransport sessions are routed by the requested path like `/ws/:session_id`: `/wt` starts a
+//! new session, `/wt/:session_id` attaches to the session created through the REST API with its
+//! settings, and other paths are refused
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use rs_terminal::app_state::AppState;
+use rs_terminal::handlers::webtransport::{bind_webtransport_endpoint, start_webtransport_server};
+use rs_terminal::pty::MockPtyFactory;
+use rs_terminal::server::build_router;
+use serde_json::json;
+use wtransport::endpoint::endpoint_side::Client;
+use wtransport::error::ConnectingError;
+use wtransport::{ClientConfig, Connection, Endpoint};
+
+const CONFIG: &str = r#"
+pty_implementation = "portable_pty"
+default_shell_type = "sh"
+session_timeout = 1800000
+nudge_on_connect = false
+
+[default_shell_config]
+size = { columns = 80, rows = 24 }
+working_directory = "."
+
+[shells.sh]
+command = ["sh"]
+
+[shells.zsh]
+command = ["zsh"]
+"#;
+
+/// Longest wait for a step of the exchange
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Start the WebTransport server, returning the state it serves and its URL
+async fn start_server() -> (AppState, String) {
+    let state = common::state(CONFIG).with_pty_factory(Arc::new(MockPtyFactory));
+    let endpoint =
+        bind_webtransport_endpoint("127.0.0.1:0".parse().unwrap(), "webtransport_port").unwrap();
+    let url = format!(
+        "https://127.0.0.1:{}",
+        endpoint.local_addr().unwrap().port()
+    );
+    tokio::spawn(start_webtransport_server(endpoint, state.clone()));
+    (state, url)
+}
+
+/// Open a WebTransport session at `url`; the server's certificate is self-signed
+async fn connect(url: &str) -> Result<(Endpoint<Client>, Connection), ConnectingError> {
+    let client = Endpoint::client(
+        ClientConfig::builder()
+            .with_bind_default()
+            .with_no_cert_validation()
+            .build(),
+    )
+    .unwrap();
+    let connection = tokio::time::timeout(TIMEOUT, client.connect(url))
+        .await
+        .expect("WebTransport connection timed out")?;
+    Ok((client, connection))
+}
+
+/// Output of the session until the mock shell's prompt
+async fn banner(connection: &Connection) -> String {
+    let (_send, mut recv) = tokio::time::timeout(TIMEOUT, connection.accept_bi())
+        .await
+        .expect("no stream from the server")
+        .unwrap();
+    let mut output = Vec::new();
+    tokio::time::timeout(TIMEOUT, async {
+        while !output.ends_with(b"mock$ ") {
+            let mut buffer = [0u8; 256];
+            let n = recv
+                .read(&mut buffer)
+                .await
+                .unwrap()
+                .expect("stream finished");
+            output.extend_from_slice(&buffer[..n]);
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("no prompt: {:?}", output));
+    String::from_utf8(output).unwrap()
+}
+
+#[tokio::test]
+async fn known_session_path_attaches_to_the_session() {
+    let (state, url) = start_server().await;
+
+    // The session is created with a shell other than the default
+    let router = build_router(state.clone());
+    let session_id = common::create_session(&router, json!({ "shellType": "zsh" })).await;
+
+    let (_client, connection) = connect(&format!("{}/wt/{}", url, session_id))
+        .await
+        .unwrap();
+    let output = banner(&connection).await;
+    assert!(output.starts_with("mock shell for `zsh`"), "{:?}", output);
+    assert_eq!(state.get_all_sessions().await.len(), 1);
+}
+
+#[tokio::test]
+async fn new_session_path_starts_the_default_shell() {
+    let (state, url) = start_server().await;
+
+    let (_client, connection) = connect(&format!("{}/wt", url)).await.unwrap();
+    let output = banner(&connection).await;
+    assert!(output.starts_with("mock shell for `sh`"), "{:?}", output);
+    assert_eq!(state.get_all_sessions().await.len(), 1);
+}
+
+#[tokio::test]
+async fn unknown_path_is_refused() {
+    let (state, url) = start_server().await;
+
+    for path in ["/", "/ws", "/wt/a/b", "/other/session"] {
+        match connect(&format!("{}{}", url, path)).await {
+            Err(ConnectingError::SessionRejected) => {}
+            Ok(_) => panic!("session for {} was accepted", path),
+            Err(e) => panic!("unexpected error for {}: {}", path, e),
+        }
+    }
+    assert!(state.get_all_sessions().await.is_empty());
+}
@@ -0,0 +1,204 @@
+//! A WebTransport stream the client resets is replaced by a new one on the same QUIC connection;
+//! the session's data keeps flowing and the connection stays alive. A client that finished sending
+//! still gets output until it closes the QUIC connection. Stream data is handed on in messages
+//! within the connection's size limit
+
+use std::time::Duration;
+
+use rs_terminal::protocol::{
+    ConnectionError, TerminalConnection, TerminalMessage, WebTransportConnection,
+};
+use wtransport::endpoint::endpoint_side::{Client, Server};
+use wtransport::stream::RecvStream;
+use wtransport::{ClientConfig, Connection, Endpoint, Identity, ServerConfig};
+
+/// Longest wait for a step of the exchange
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Read until `expected` arrived on the stream
+async fn read_exactly(recv: &mut RecvStream, expected: &str) {
+    let mut received = Vec::new();
+    while received.len() < expected.len() {
+        let mut buffer = [0u8; 64];
+        let n = tokio::time::timeout(TIMEOUT, recv.read(&mut buffer))
+            .await
+            .expect("no data on the stream")
+            .unwrap()
+            .expect("stream finished");
+        received.extend_from_slice(&buffer[..n]);
+    }
+    assert_eq!(String::from_utf8(received).unwrap(), expected);
+}
+
+/// QUIC connection between a client and a server endpoint on localhost
+struct Connected {
+    client: Connection,
+    server: Connection,
+    _endpoints: (Endpoint<Client>, Endpoint<Server>),
+}
+
+async fn connect() -> Connected {
+    let identity = Identity::self_signed(["localhost"]).unwrap();
+    let certificate_hash = identity.certificate_chain().as_slice()[0].hash();
+    let server = Endpoint::server(
+        ServerConfig::builder()
+            .with_bind_address("127.0.0.1:0".parse().unwrap())
+            .with_identity(identity)
+            .build(),
+    )
+    .unwrap();
+    let port = server.local_addr().unwrap().port();
+
+    let client = Endpoint::client(
+        ClientConfig::builder()
+            .with_bind_default()
+            .with_server_certificate_hashes([certificate_hash])
+            .build(),
+    )
+    .unwrap();
+    let (client_connection, server_connection) = tokio::join!(
+        async {
+            client
+                .connect(format!("https://localhost:{}/", port))
+                .await
+                .unwrap()
+        },
+        async { server.accept().await.await.unwrap().accept().await.unwrap() }
+    );
+    Connected {
+        client: client_connection,
+        server: server_connection,
+        _endpoints: (client, server),
+    }
+}
+
+#[tokio::test]
+async fn reset_stream_is_replaced() {
+    let Connected {
+        client: client_connection,
+        server: server_connection,
+        _endpoints,
+    } = connect().await;
+
+    let mut connection = WebTransportConnection::new("reset-stream".to_string(), TIMEOUT);
+    connection.set_connection(server_connection).await.unwrap();
+
+    // The client only sees the stream once the server wrote to it
+    connection.send_text("one").await.unwrap();
+    let (mut send, mut recv) = client_connection.accept_bi().await.unwrap();
+    read_exactly(&mut recv, "one").await;
+
+    send.reset(7u32.into()).unwrap();
+    let received = tokio::time::timeout(TIMEOUT, connection.receive())
+        .await
+        .expect("the reset wasn't noticed");
+    assert!(
+        matches!(received, Some(Err(ConnectionError::StreamReset(_)))),
+        "{:?}",
+        received
+    );
+    assert!(connection.is_alive());
+
+    // Output and input continue on the stream that replaced it
+    connection.send_text("two").await.unwrap();
+    let (mut send, mut recv) = tokio::time::timeout(TIMEOUT, client_connection.accept_bi())
+        .await
+        .expect("no new stream")
+        .unwrap();
+    read_exactly(&mut recv, "two").await;
+    send.write_all(b"three").await.unwrap();
+    let received = tokio::time::timeout(TIMEOUT, connection.receive())
+        .await
+        .expect("no input on the new stream");
+    assert!(
+        matches!(&received, Some(Ok(TerminalMessage::Binary(data))) if data == b"three"),
+        "{:?}",
+        received
+    );
+    assert!(connection.is_alive());
+
+    // Closing the QUIC connection is what ends it
+    client_connection.close(0u32.into(), b"done");
+    let received = tokio::time::timeout(TIMEOUT, connection.receive())
+        .await
+        .expect("the closed connection wasn't noticed");
+    assert!(received.is_none(), "{:?}", received);
+    tokio::time::timeout(TIMEOUT, async {
+        while connection.is_alive() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the connection still looks alive");
+}
+
+#[tokio::test]
+async fn finished_stream_lasts_until_the_connection_closes() {
+    let Connected {
+        client,
+        server,
+        _endpoints,
+    } = connect().await;
+    let mut connection = WebTransportConnection::new("finished".to_string(), TIMEOUT);
+    connection.set_connection(server).await.unwrap();
+
+    connection.send_text("ready").await.unwrap();
+    let (mut send, mut recv) = client.accept_bi().await.unwrap();
+    read_exactly(&mut recv, "ready").await;
+    send.write_all(b"exit").await.unwrap();
+    send.finish().await.unwrap();
+    let received = tokio::time::timeout(TIMEOUT, connection.receive())
+        .await
+        .expect("no input on the stream");
+    assert!(
+        matches!(&received, Some(Ok(TerminalMessage::Binary(data))) if data == b"exit"),
+        "{:?}",
+        received
+    );
+
+    // The end of the client's input isn't the end of the connection
+    let pending = tokio::time::timeout(Duration::from_millis(300), connection.receive()).await;
+    assert!(pending.is_err(), "{:?}", pending);
+    assert!(connection.is_alive());
+    connection.send_text("logout").await.unwrap();
+    read_exactly(&mut recv, "logout").await;
+
+    client.close(0u32.into(), b"done");
+    let received = tokio::time::timeout(TIMEOUT, connection.receive())
+        .await
+        .expect("the closed connection wasn't noticed");
+    assert!(received.is_none(), "{:?}", received);
+}
+
+#[tokio::test]
+async fn input_is_split_at_the_message_limit() {
+    let Connected {
+        client,
+        server,
+        _endpoints,
+    } = connect().await;
+    let mut connection =
+        WebTransportConnection::new("limited".to_string(), TIMEOUT).with_max_message_bytes(16);
+    connection.set_connection(server).await.unwrap();
+
+    connection.send_text("ready").await.unwrap();
+    let (mut send, mut recv) = client.accept_bi().await.unwrap();
+    read_exactly(&mut recv, "ready").await;
+
+    let input = "0123456789".repeat(10);
+    send.write_all(input.as_bytes()).await.unwrap();
+    let mut received = Vec::new();
+    while received.len() < input.len() {
+        let message = tokio::time::timeout(TIMEOUT, connection.receive())
+            .await
+            .expect("no input on the stream");
+        match message {
+            Some(Ok(TerminalMessage::Binary(data))) => {
+                assert!(data.len() <= 16, "{} bytes in one message", data.len());
+                received.extend_from_slice(&data);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+    assert_eq!(String::from_utf8(received).unwrap(), input);
+}